@@ -0,0 +1,73 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use project_neon::protocol::{self, ConnectRequest, NeonPacket, PacketPayload, PacketType, PeerInfo, PeerList, Ping};
+
+fn packet(packet_type: PacketType, payload: PacketPayload) -> NeonPacket {
+    NeonPacket { packet_type: packet_type as u8, sequence: 1234, client_id: 2, destination_id: 1, payload }
+}
+
+/// A representative packet of each shape the hot paths see
+fn samples() -> Vec<(&'static str, NeonPacket)> {
+    let game = |size: usize| packet(PacketType::GamePacket, PacketPayload::GamePacket(vec![0x5A; size]));
+    vec![
+        ("ping", packet(PacketType::Ping, PacketPayload::Ping(Ping { timestamp: 1_700_000_000_000 }))),
+        ("game_64", game(64)),
+        ("game_1200", game(1200)),
+        (
+            "connect_request",
+            packet(
+                PacketType::ConnectRequest,
+                PacketPayload::ConnectRequest(ConnectRequest {
+                    client_version: protocol::PROTOCOL_VERSION,
+                    desired_name: "PlayerOne".to_string(),
+                    target_session_id: 42,
+                    game_identifier: 7,
+                    nonce: 99,
+                    resume_id: 0,
+                    resume_token: 0,
+                    auth_token: vec![1; 32],
+                    role: Default::default(),
+                }),
+            ),
+        ),
+        (
+            "peer_list_32",
+            packet(
+                PacketType::PeerList,
+                PacketPayload::PeerList(PeerList {
+                    peers: (2..34).map(|client_id| PeerInfo { client_id, name: format!("player-{}", client_id), role: Default::default() }).collect(),
+                }),
+            ),
+        ),
+        ("batch_8", packet(PacketType::Batch, PacketPayload::Batch((0..8).map(|_| game(48)).collect()))),
+    ]
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for (name, packet) in samples() {
+        group.throughput(Throughput::Bytes(protocol::encode(&packet).len() as u64));
+        group.bench_function(name, |b| b.iter(|| protocol::encode(black_box(&packet))));
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, packet) in samples() {
+        let data = protocol::encode(&packet);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_function(name, |b| b.iter(|| protocol::decode(black_box(&data)).unwrap()));
+    }
+    group.finish();
+}
+
+fn header(c: &mut Criterion) {
+    let data = protocol::encode(&samples()[1].1);
+    let header = protocol::decode_header(&data).unwrap();
+    c.bench_function("header/decode", |b| b.iter(|| protocol::decode_header(black_box(&data)).unwrap()));
+    c.bench_function("header/encode", |b| b.iter(|| black_box(&header).to_bytes()));
+}
+
+criterion_group!(benches, encode, decode, header);
+criterion_main!(benches);
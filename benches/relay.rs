@@ -0,0 +1,111 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use project_neon::client::NeonClient;
+use project_neon::host::NeonHost;
+use project_neon::protocol::{self, NeonPacket, PacketPayload, PacketType};
+use project_neon::relay::{LogFormat, Logger, NeonRelay, RelayConfig, SessionKey, SessionManager};
+use project_neon::transport::{MemoryNetwork, Transport};
+
+const SESSIONS: u32 = 64;
+
+fn addr(session_id: u32, client_id: u16) -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::from(0x0A00_0000 | session_id << 8 | client_id as u32), 40_000))
+}
+
+/// `SESSIONS` sessions of `peers` peers each, the host included
+fn populated(peers: u16) -> SessionManager {
+    // JSON logs skip the session tables text mode prints on every registration
+    let mut sessions = SessionManager::new(Logger::new(LogFormat::Json));
+    for session_id in 0..SESSIONS {
+        sessions.register_host(session_id.into(), addr(session_id, 1));
+        for client_id in 2..=peers {
+            sessions.register_client(session_id.into(), client_id, addr(session_id, client_id));
+        }
+    }
+    sessions
+}
+
+fn session_lookups(c: &mut Criterion) {
+    let mut group = c.benchmark_group("session_manager");
+    for peers in [8u16, 64, 250] {
+        let mut sessions = populated(peers);
+        let (session_id, client_id) = (SESSIONS / 2, peers);
+        let client_addr = addr(session_id, client_id);
+        let session = SessionKey::from(session_id);
+
+        group.bench_with_input(BenchmarkId::new("lookup_peer", peers), &client_addr, |b, addr| {
+            b.iter(|| sessions.lookup_peer(black_box(*addr), black_box(client_id)))
+        });
+        group.bench_with_input(BenchmarkId::new("peer_addr", peers), &client_id, |b, &client_id| {
+            b.iter(|| sessions.peer_addr(black_box(session), black_box(client_id)))
+        });
+        group.bench_with_input(BenchmarkId::new("other_peers", peers), &client_id, |b, &client_id| {
+            b.iter(|| sessions.other_peers(black_box(session), black_box(client_id)))
+        });
+        // Runs for every forwarded packet, so a scan here is paid per datagram
+        group.bench_with_input(BenchmarkId::new("update_client_activity", peers), &client_id, |b, &client_id| {
+            b.iter(|| sessions.update_client_activity(black_box(client_id), black_box(session)))
+        });
+    }
+    group.finish();
+}
+
+/// One game packet from a client, through a relay on an in-memory network, to the host
+fn relay_forward(c: &mut Criterion) {
+    let network = MemoryNetwork::new();
+    let relay_transport = network.bind("127.0.0.1:7777").unwrap();
+    let relay_addr = relay_transport.local_addr().unwrap();
+    let mut relay = NeonRelay::with_transport(relay_transport, RelayConfig { worker_threads: 1, ..RelayConfig::default() }).unwrap();
+    relay.set_log_format(LogFormat::Json);
+    thread::spawn(move || relay.start());
+
+    // Connect a client through a real host, then take over both endpoints' transports
+    let host_transport = network.bind("127.0.0.1:0").unwrap();
+    let host_inbox = host_transport.try_clone().unwrap();
+    let mut host = NeonHost::with_transport(7, relay_addr, host_transport).unwrap();
+    host.register().unwrap();
+    let host = Arc::new(Mutex::new(host));
+    let accepting = {
+        let host = host.clone();
+        thread::spawn(move || {
+            for _ in 0..200 {
+                host.lock().unwrap().process_packets().unwrap();
+                thread::sleep(Duration::from_millis(5));
+            }
+        })
+    };
+    let client_transport = network.bind("127.0.0.1:0").unwrap();
+    let client_outbox = client_transport.try_clone().unwrap();
+    let mut client = NeonClient::with_transport("bench".to_string(), client_transport).unwrap();
+    client.connect(7, relay_addr).unwrap();
+    accepting.join().unwrap();
+
+    let datagram = protocol::encode(&NeonPacket {
+        packet_type: PacketType::GamePacket as u8,
+        sequence: 0,
+        client_id: client.client_id().unwrap(),
+        destination_id: 1,
+        payload: PacketPayload::GamePacket(vec![0x5A; 64]),
+    });
+    // Drain whatever the handshake left for the host
+    host_inbox.set_nonblocking(true).unwrap();
+    let mut buf = [0u8; protocol::MAX_DATAGRAM_SIZE];
+    while host_inbox.recv_from(&mut buf).is_ok() {}
+    host_inbox.set_nonblocking(false).unwrap();
+    host_inbox.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+
+    c.bench_function("relay/forward_game_packet", |b| {
+        b.iter(|| {
+            client_outbox.send_to(&datagram, relay_addr).unwrap();
+            host_inbox.recv_from(&mut buf).expect("relay didn't forward the packet")
+        })
+    });
+}
+
+criterion_group!(benches, session_lookups, relay_forward);
+criterion_main!(benches);
@@ -0,0 +1,179 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use godot::prelude::*;
+use project_neon::client::{ConnectionState, NeonClient};
+
+/// Callback events, queued by the client and emitted as signals from `process`
+enum ClientEvent {
+    Connected(u16),
+    ConnectFailed(String),
+    GamePacket(u8, u16, Vec<u8>),
+    PeerJoined(u16, String),
+    PeerLeft(u16, String),
+}
+
+/// A Neon client as a node. Set the exported properties, call `join_session`, and the node
+/// processes packets every frame, emitting signals for what arrives.
+#[derive(GodotClass)]
+#[class(base=Node)]
+pub struct NeonClientNode {
+    #[export]
+    player_name: GString,
+    #[export]
+    relay_address: GString,
+    #[export]
+    session_id: u32,
+    client: Option<NeonClient>,
+    events: Receiver<ClientEvent>,
+    sender: Sender<ClientEvent>,
+    base: Base<Node>,
+}
+
+#[godot_api]
+impl INode for NeonClientNode {
+    fn init(base: Base<Node>) -> Self {
+        let (sender, events) = mpsc::channel();
+        Self {
+            player_name: "Player".into(),
+            relay_address: "127.0.0.1:7777".into(),
+            session_id: 1,
+            client: None,
+            events,
+            sender,
+            base,
+        }
+    }
+
+    fn process(&mut self, _delta: f64) {
+        let Some(client) = &mut self.client else { return };
+        let connecting = client.connection_state() == ConnectionState::Connecting;
+        if let Err(e) = client.process_packets() {
+            self.client = None;
+            if connecting {
+                // on_connect_failed queued the reason for connection_denied
+                self.emit_events();
+                return;
+            }
+            godot_error!("[Client] Failed to process packets: {}", e);
+            self.base_mut().emit_signal("disconnected", &[e.to_string().to_variant()]);
+            return;
+        }
+        self.emit_events();
+    }
+}
+
+impl NeonClientNode {
+    fn emit_events(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            let (signal, args) = match event {
+                ClientEvent::Connected(client_id) => ("connected", vec![client_id.to_variant()]),
+                ClientEvent::ConnectFailed(reason) => ("connection_denied", vec![reason.to_variant()]),
+                ClientEvent::GamePacket(packet_type, from, data) => (
+                    "game_packet_received",
+                    vec![packet_type.to_variant(), from.to_variant(), PackedByteArray::from(data.as_slice()).to_variant()],
+                ),
+                ClientEvent::PeerJoined(client_id, name) => ("peer_joined", vec![client_id.to_variant(), name.to_variant()]),
+                ClientEvent::PeerLeft(client_id, name) => ("peer_left", vec![client_id.to_variant(), name.to_variant()]),
+            };
+            self.base_mut().emit_signal(signal, &args);
+        }
+    }
+}
+
+#[godot_api]
+impl NeonClientNode {
+    #[signal]
+    fn connected(client_id: u16);
+
+    #[signal]
+    fn connection_denied(reason: GString);
+
+    #[signal]
+    fn disconnected(reason: GString);
+
+    #[signal]
+    fn game_packet_received(packet_type: u8, from_client_id: u16, data: PackedByteArray);
+
+    #[signal]
+    fn peer_joined(client_id: u16, name: GString);
+
+    #[signal]
+    fn peer_left(client_id: u16, name: GString);
+
+    /// Start joining `session_id` through `relay_address` without blocking. Emits `connected` or
+    /// `connection_denied` from a later frame; returns whether the request went out.
+    #[func]
+    fn join_session(&mut self) -> bool {
+        let mut client = match NeonClient::new(self.player_name.to_string()) {
+            Ok(client) => client,
+            Err(e) => {
+                self.base_mut().emit_signal("connection_denied", &[e.to_string().to_variant()]);
+                return false;
+            }
+        };
+        let events = self.sender.clone();
+        client.on_connected(move |client_id, _session_id| {
+            let _ = events.send(ClientEvent::Connected(client_id));
+        });
+        let events = self.sender.clone();
+        client.on_connect_failed(move |e| {
+            let _ = events.send(ClientEvent::ConnectFailed(e.to_string()));
+        });
+        let events = self.sender.clone();
+        client.on_game_packet(move |packet_type, from, data| {
+            let _ = events.send(ClientEvent::GamePacket(packet_type, from, data.to_vec()));
+        });
+        let events = self.sender.clone();
+        client.on_peer_joined(move |client_id, name| {
+            let _ = events.send(ClientEvent::PeerJoined(client_id, name));
+        });
+        let events = self.sender.clone();
+        client.on_peer_left(move |client_id, name| {
+            let _ = events.send(ClientEvent::PeerLeft(client_id, name));
+        });
+
+        match client.begin_connect(self.session_id, self.relay_address.to_string().as_str()) {
+            Ok(()) => {
+                self.client = Some(client);
+                true
+            }
+            Err(_) => {
+                // on_connect_failed queued the reason for connection_denied
+                self.emit_events();
+                false
+            }
+        }
+    }
+
+    /// Send a game packet (0x10-0xEF) to the host (1) or another client. Returns whether it was sent.
+    #[func]
+    fn send_game_packet(&mut self, packet_type: u8, data: PackedByteArray, destination_id: u16) -> bool {
+        let Some(client) = &mut self.client else {
+            godot_error!("[Client] Not connected");
+            return false;
+        };
+        match client.send_game_packet_to(destination_id, packet_type, data.as_slice()) {
+            Ok(()) => true,
+            Err(e) => {
+                godot_error!("[Client] Failed to send game packet: {}", e);
+                false
+            }
+        }
+    }
+
+    #[func]
+    fn get_client_id(&self) -> u16 {
+        self.client.as_ref().and_then(|client| client.client_id()).unwrap_or(0)
+    }
+
+    #[func]
+    fn is_joined(&self) -> bool {
+        self.client.as_ref().is_some_and(|client| client.state() == ConnectionState::Connected)
+    }
+
+    /// Drop the client; the host sees it time out
+    #[func]
+    fn leave_session(&mut self) {
+        self.client = None;
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use godot::prelude::*;
+use project_neon::host::NeonHost;
+
+/// Callback events, queued by the host and emitted as signals from `process`
+enum HostEvent {
+    ClientConnected(u16, String),
+    ClientDenied(String, String),
+    GamePacket(u8, u16, Vec<u8>),
+}
+
+/// A Neon host as a node. Set the exported properties, call `start_hosting`, and the node
+/// processes packets every frame, emitting signals for what arrives.
+#[derive(GodotClass)]
+#[class(base=Node)]
+pub struct NeonHostNode {
+    #[export]
+    relay_address: GString,
+    #[export]
+    session_id: u32,
+    host: Option<NeonHost>,
+    events: Receiver<HostEvent>,
+    sender: Sender<HostEvent>,
+    base: Base<Node>,
+}
+
+#[godot_api]
+impl INode for NeonHostNode {
+    fn init(base: Base<Node>) -> Self {
+        let (sender, events) = mpsc::channel();
+        Self {
+            relay_address: "127.0.0.1:7777".into(),
+            session_id: 1,
+            host: None,
+            events,
+            sender,
+            base,
+        }
+    }
+
+    fn process(&mut self, _delta: f64) {
+        let Some(host) = &mut self.host else { return };
+        if let Err(e) = host.process_packets() {
+            godot_error!("[Host] Failed to process packets: {}", e);
+            self.host = None;
+            self.base_mut().emit_signal("stopped", &[e.to_string().to_variant()]);
+            return;
+        }
+
+        while let Ok(event) = self.events.try_recv() {
+            let (signal, args) = match event {
+                HostEvent::ClientConnected(client_id, name) => ("client_connected", vec![client_id.to_variant(), name.to_variant()]),
+                HostEvent::ClientDenied(name, reason) => ("client_denied", vec![name.to_variant(), reason.to_variant()]),
+                HostEvent::GamePacket(packet_type, from, data) => (
+                    "game_packet_received",
+                    vec![packet_type.to_variant(), from.to_variant(), PackedByteArray::from(data.as_slice()).to_variant()],
+                ),
+            };
+            self.base_mut().emit_signal(signal, &args);
+        }
+    }
+}
+
+#[godot_api]
+impl NeonHostNode {
+    #[signal]
+    fn client_connected(client_id: u16, name: GString);
+
+    #[signal]
+    fn client_denied(name: GString, reason: GString);
+
+    #[signal]
+    fn game_packet_received(packet_type: u8, from_client_id: u16, data: PackedByteArray);
+
+    #[signal]
+    fn stopped(reason: GString);
+
+    /// Register `session_id` with the relay at `relay_address`. Blocks until the relay answers;
+    /// returns whether the session is up.
+    #[func]
+    fn start_hosting(&mut self) -> bool {
+        let mut host = match NeonHost::new(self.session_id, self.relay_address.to_string().as_str()) {
+            Ok(host) => host,
+            Err(e) => {
+                godot_error!("[Host] Failed to create host: {}", e);
+                return false;
+            }
+        };
+        let events = self.sender.clone();
+        host.on_client_connect(move |client_id, name, _session_id| {
+            let _ = events.send(HostEvent::ClientConnected(client_id, name));
+        });
+        let events = self.sender.clone();
+        host.on_client_deny(move |name, reason| {
+            let _ = events.send(HostEvent::ClientDenied(name, reason));
+        });
+        let events = self.sender.clone();
+        host.on_game_packet(move |packet_type, from, data| {
+            let _ = events.send(HostEvent::GamePacket(packet_type, from, data.to_vec()));
+        });
+
+        if let Err(e) = host.register() {
+            godot_error!("[Host] Failed to register session {}: {}", self.session_id, e);
+            return false;
+        }
+        self.host = Some(host);
+        true
+    }
+
+    /// Send a game packet (0x10-0xEF) to one client. Returns whether it was sent.
+    #[func]
+    fn send_to_client(&mut self, client_id: u16, packet_type: u8, data: PackedByteArray) -> bool {
+        self.with_host(|host| host.send_game_packet(client_id, packet_type, data.as_slice()))
+    }
+
+    /// Send a game packet (0x10-0xEF) to every client. Returns whether it was sent.
+    #[func]
+    fn broadcast(&mut self, packet_type: u8, data: PackedByteArray) -> bool {
+        self.with_host(|host| host.broadcast_game_packet(packet_type, data.as_slice()))
+    }
+
+    #[func]
+    fn get_client_count(&self) -> u32 {
+        self.host.as_ref().map_or(0, |host| host.client_count() as u32)
+    }
+
+    /// Drop the host; the relay ends the session once its clients time out
+    #[func]
+    fn stop_hosting(&mut self) {
+        self.host = None;
+    }
+
+    fn with_host(&mut self, send: impl FnOnce(&mut NeonHost) -> Result<(), std::io::Error>) -> bool {
+        let Some(host) = &mut self.host else {
+            godot_error!("[Host] Not hosting");
+            return false;
+        };
+        match send(host) {
+            Ok(()) => true,
+            Err(e) => {
+                godot_error!("[Host] Failed to send game packet: {}", e);
+                false
+            }
+        }
+    }
+}
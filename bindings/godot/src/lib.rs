@@ -0,0 +1,12 @@
+use godot::prelude::*;
+
+mod client;
+mod host;
+
+pub use client::NeonClientNode;
+pub use host::NeonHostNode;
+
+struct NeonExtension;
+
+#[gdextension]
+unsafe impl ExtensionLibrary for NeonExtension {}
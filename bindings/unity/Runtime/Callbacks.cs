@@ -0,0 +1,76 @@
+using System;
+using System.Runtime.ExceptionServices;
+using System.Runtime.InteropServices;
+using System.Threading;
+
+#if !UNITY_5_3_OR_NEWER
+namespace AOT
+{
+    /// <summary>Outside Unity, stands in for UnityEngine's attribute marking methods native code calls back into.</summary>
+    [AttributeUsage(AttributeTargets.Method)]
+    public sealed class MonoPInvokeCallbackAttribute : Attribute
+    {
+        public MonoPInvokeCallbackAttribute(Type type) { }
+    }
+}
+#endif
+
+namespace ProjectNeon
+{
+    /// <summary>
+    /// The managed side of a handle's callbacks. Native code gets one static delegate per callback
+    /// type, which IL2CPP requires and which is never collected, plus a weak GCHandle to the owner as
+    /// user_data. Exceptions thrown by event handlers must not unwind into native code, so they are
+    /// held and rethrown from the next call on the owner.
+    /// </summary>
+    internal sealed class Callbacks : IDisposable
+    {
+        GCHandle owner;
+        Exception pending;
+
+        public Callbacks(object owner)
+        {
+            this.owner = GCHandle.Alloc(owner, GCHandleType.Weak);
+        }
+
+        /// <summary>The user_data pointer to register with every callback.</summary>
+        public IntPtr UserData => GCHandle.ToIntPtr(owner);
+
+        /// <summary>The owner a callback's user_data points to, or null once it has been collected.</summary>
+        public static T Owner<T>(IntPtr userData) where T : class
+        {
+            return userData == IntPtr.Zero ? null : GCHandle.FromIntPtr(userData).Target as T;
+        }
+
+        /// <summary>Run an event handler, holding on to the first exception it throws.</summary>
+        public void Raise(Action handler)
+        {
+            try
+            {
+                handler();
+            }
+            catch (Exception e)
+            {
+                Interlocked.CompareExchange(ref pending, e, null);
+            }
+        }
+
+        /// <summary>Rethrow an exception an event handler threw since the last call, if one did.</summary>
+        public void RethrowPending()
+        {
+            var e = Interlocked.Exchange(ref pending, null);
+            if (e != null)
+            {
+                ExceptionDispatchInfo.Capture(e).Throw();
+            }
+        }
+
+        public void Dispose()
+        {
+            if (owner.IsAllocated)
+            {
+                owner.Free();
+            }
+        }
+    }
+}
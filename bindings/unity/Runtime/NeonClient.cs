@@ -0,0 +1,229 @@
+using System;
+using System.Runtime.InteropServices;
+using AOT;
+
+namespace ProjectNeon
+{
+    /// <summary>A game packet type described by the host's registry.</summary>
+    public struct PacketTypeInfo
+    {
+        public byte Id;
+        public string Name;
+        public string Description;
+    }
+
+    /// <summary>
+    /// A Neon client. Events fire during <see cref="ProcessPackets"/>, on the thread that calls it.
+    /// Dispose it when done; the finalizer frees it otherwise.
+    /// </summary>
+    public sealed class NeonClient : IDisposable
+    {
+        static readonly PongCallback PongNative = OnPong;
+        static readonly SessionConfigCallback SessionConfigNative = OnSessionConfig;
+        static readonly PacketTypeRegistryCallback PacketTypeRegistryNative = OnPacketTypeRegistry;
+        static readonly UnhandledPacketCallback UnhandledPacketNative = OnUnhandledPacket;
+        static readonly WrongDestinationCallback WrongDestinationNative = OnWrongDestination;
+        static readonly GamePacketCallback GamePacketNative = OnGamePacket;
+
+        IntPtr handle;
+        readonly Callbacks callbacks;
+
+        /// <summary>(responseTimeMs, timestamp)</summary>
+        public event Action<ulong, ulong> PongReceived;
+        /// <summary>(version, tickRate, maxPacketSize)</summary>
+        public event Action<byte, ushort, ushort> SessionConfigReceived;
+        public event Action<PacketTypeInfo[]> PacketTypesReceived;
+        /// <summary>(packetType, fromClientId)</summary>
+        public event Action<byte, ushort> UnhandledPacket;
+        /// <summary>(myId, packetDestinationId)</summary>
+        public event Action<ushort, ushort> WrongDestination;
+        /// <summary>(packetType, fromClientId, data); data is a copy the handler may keep.</summary>
+        public event Action<byte, ushort, byte[]> GamePacketReceived;
+
+        public NeonClient(string name)
+        {
+            handle = NeonNative.neon_client_new(name);
+            if (handle == IntPtr.Zero)
+            {
+                throw new NeonException(NeonErrorCode.InvalidArgument);
+            }
+            callbacks = new Callbacks(this);
+            var userData = callbacks.UserData;
+            NeonNative.neon_client_set_pong_callback(handle, PongNative, userData);
+            NeonNative.neon_client_set_session_config_callback(handle, SessionConfigNative, userData);
+            NeonNative.neon_client_set_packet_type_registry_callback(handle, PacketTypeRegistryNative, userData);
+            NeonNative.neon_client_set_unhandled_packet_callback(handle, UnhandledPacketNative, userData);
+            NeonNative.neon_client_set_wrong_destination_callback(handle, WrongDestinationNative, userData);
+            NeonNative.neon_client_set_game_packet_callback(handle, GamePacketNative, userData);
+        }
+
+        ~NeonClient()
+        {
+            Free();
+        }
+
+        /// <summary>The native handle, for calling NeonNative directly.</summary>
+        public IntPtr Handle => Live();
+
+        public ushort ClientId => NeonNative.neon_client_get_id(Live());
+
+        public uint SessionId => NeonNative.neon_client_get_session_id(Live());
+
+        public bool IsConnected => NeonNative.neon_client_is_connected(Live());
+
+        public NeonConnectionState ConnectionState => NeonNative.neon_client_get_connection_state(Live());
+
+        /// <summary>Like <see cref="ConnectionState"/>, also reporting AwaitingConfig and Disconnecting.</summary>
+        public NeonConnectionState State => NeonNative.neon_client_get_state(Live());
+
+        /// <summary>Ping the host automatically; set after connecting.</summary>
+        public bool AutoPing
+        {
+            set => NeonNative.neon_client_set_auto_ping(Live(), value);
+        }
+
+        /// <summary>Set the token sent with connection requests; null sends none.</summary>
+        public void SetAuthToken(byte[] token)
+        {
+            NeonNative.neon_client_set_auth_token(Live(), token, (UIntPtr)(token?.Length ?? 0));
+        }
+
+        /// <summary>Join a session through a relay, e.g. "127.0.0.1:7777". Blocks until accepted or denied.</summary>
+        public void Connect(uint sessionId, string relayAddr)
+        {
+            NeonException.Check(NeonNative.neon_client_connect(Live(), sessionId, relayAddr));
+        }
+
+        /// <summary>Process packets until the host's session config arrives, throwing if it doesn't in time.</summary>
+        public void WaitUntilConfigured(ulong timeoutMs)
+        {
+            NeonException.Check(NeonNative.neon_client_wait_until_configured(Live(), timeoutMs));
+        }
+
+        /// <summary>Leave the session, sending whatever is queued first.</summary>
+        public void Disconnect()
+        {
+            NeonException.Check(NeonNative.neon_client_disconnect(Live()));
+        }
+
+        /// <summary>Start joining a session without blocking. <see cref="ProcessPackets"/> advances the attempt,
+        /// throwing if it fails, until <see cref="ConnectionState"/> is Connected.</summary>
+        public void BeginConnect(uint sessionId, string relayAddr)
+        {
+            NeonException.Check(NeonNative.neon_client_begin_connect(Live(), sessionId, relayAddr));
+        }
+
+        /// <summary>Join a session through whichever relay the directory relay picks. Blocks like Connect.</summary>
+        public void ConnectViaDirectory(string directoryAddr, uint sessionId)
+        {
+            NeonException.Check(NeonNative.neon_client_connect_via_directory(Live(), directoryAddr, sessionId));
+        }
+
+        /// <summary>Send what's due and handle every packet waiting, firing events. Call once per frame.</summary>
+        public void ProcessPackets()
+        {
+            var code = NeonNative.neon_client_process_packets(Live());
+            callbacks.RethrowPending();
+            NeonException.Check(code);
+        }
+
+        public void SendPing()
+        {
+            NeonException.Check(NeonNative.neon_client_send_ping(Live()));
+        }
+
+        /// <summary>Send a game packet (0x10-0xEF) to the host (1) or another client in the session.</summary>
+        public void SendGamePacket(byte packetType, byte[] data, ushort destinationId = 1)
+        {
+            var len = (UIntPtr)(data?.Length ?? 0);
+            NeonException.Check(NeonNative.neon_client_send_game_packet(Live(), packetType, destinationId, data, len));
+        }
+
+        public void Dispose()
+        {
+            Free();
+            GC.SuppressFinalize(this);
+        }
+
+        IntPtr Live()
+        {
+            if (handle == IntPtr.Zero)
+            {
+                throw new ObjectDisposedException(nameof(NeonClient));
+            }
+            return handle;
+        }
+
+        void Free()
+        {
+            if (handle != IntPtr.Zero)
+            {
+                NeonNative.neon_client_free(handle);
+                handle = IntPtr.Zero;
+            }
+            callbacks?.Dispose();
+        }
+
+        [MonoPInvokeCallback(typeof(PongCallback))]
+        static void OnPong(ulong responseTimeMs, ulong timestamp, IntPtr userData)
+        {
+            var client = Callbacks.Owner<NeonClient>(userData);
+            client?.callbacks.Raise(() => client.PongReceived?.Invoke(responseTimeMs, timestamp));
+        }
+
+        [MonoPInvokeCallback(typeof(SessionConfigCallback))]
+        static void OnSessionConfig(byte version, ushort tickRate, ushort maxPacketSize, IntPtr userData)
+        {
+            var client = Callbacks.Owner<NeonClient>(userData);
+            client?.callbacks.Raise(() => client.SessionConfigReceived?.Invoke(version, tickRate, maxPacketSize));
+        }
+
+        [MonoPInvokeCallback(typeof(PacketTypeRegistryCallback))]
+        static void OnPacketTypeRegistry(UIntPtr count, IntPtr ids, IntPtr names, IntPtr descriptions, IntPtr userData)
+        {
+            var client = Callbacks.Owner<NeonClient>(userData);
+            if (client == null)
+            {
+                return;
+            }
+            // Copied now: the arrays and strings are only valid during the call
+            var types = new PacketTypeInfo[(int)count];
+            for (var i = 0; i < types.Length; i++)
+            {
+                types[i] = new PacketTypeInfo
+                {
+                    Id = Marshal.ReadByte(ids, i),
+                    Name = NeonNative.PtrToString(Marshal.ReadIntPtr(names, i * IntPtr.Size)),
+                    Description = NeonNative.PtrToString(Marshal.ReadIntPtr(descriptions, i * IntPtr.Size)),
+                };
+            }
+            client.callbacks.Raise(() => client.PacketTypesReceived?.Invoke(types));
+        }
+
+        [MonoPInvokeCallback(typeof(UnhandledPacketCallback))]
+        static void OnUnhandledPacket(byte packetType, ushort fromClientId, IntPtr userData)
+        {
+            var client = Callbacks.Owner<NeonClient>(userData);
+            client?.callbacks.Raise(() => client.UnhandledPacket?.Invoke(packetType, fromClientId));
+        }
+
+        [MonoPInvokeCallback(typeof(WrongDestinationCallback))]
+        static void OnWrongDestination(ushort myId, ushort packetDestinationId, IntPtr userData)
+        {
+            var client = Callbacks.Owner<NeonClient>(userData);
+            client?.callbacks.Raise(() => client.WrongDestination?.Invoke(myId, packetDestinationId));
+        }
+
+        [MonoPInvokeCallback(typeof(GamePacketCallback))]
+        static void OnGamePacket(byte packetType, ushort fromClientId, IntPtr data, UIntPtr len, IntPtr userData)
+        {
+            var client = Callbacks.Owner<NeonClient>(userData);
+            if (client == null)
+            {
+                return;
+            }
+            var bytes = NeonNative.CopyBytes(data, len);
+            client.callbacks.Raise(() => client.GamePacketReceived?.Invoke(packetType, fromClientId, bytes));
+        }
+    }
+}
@@ -0,0 +1,88 @@
+#if UNITY_5_3_OR_NEWER
+using System;
+using UnityEngine;
+using UnityEngine.Events;
+
+namespace ProjectNeon
+{
+    /// <summary>(packetType, fromClientId, data)</summary>
+    [Serializable]
+    public class GamePacketEvent : UnityEvent<int, int, byte[]> { }
+
+    /// <summary>
+    /// Drop on a GameObject to join a session: connects on Start (if set), processes packets every
+    /// Update, and frees the client when destroyed. Connecting doesn't block; onConnected fires from
+    /// the Update in which the host accepts.
+    /// </summary>
+    [AddComponentMenu("Project Neon/Neon Client")]
+    public class NeonClientComponent : MonoBehaviour
+    {
+        public string playerName = "Player";
+        public string relayAddress = "127.0.0.1:7777";
+        public uint sessionId = 1;
+        public bool connectOnStart = true;
+        public bool autoPing = true;
+
+        public UnityEvent onConnected = new UnityEvent();
+        public GamePacketEvent onGamePacket = new GamePacketEvent();
+
+        bool connecting;
+
+        /// <summary>The client, for its other events and sends. Created in Awake.</summary>
+        public NeonClient Client { get; private set; }
+
+        public bool IsConnected => Client != null && Client.IsConnected;
+
+        void Awake()
+        {
+            Client = new NeonClient(playerName);
+            Client.GamePacketReceived += (packetType, from, data) => onGamePacket.Invoke(packetType, from, data);
+        }
+
+        void Start()
+        {
+            if (connectOnStart)
+            {
+                Connect();
+            }
+        }
+
+        /// <summary>Start joining <see cref="sessionId"/> through <see cref="relayAddress"/>.</summary>
+        public void Connect()
+        {
+            Client.BeginConnect(sessionId, relayAddress);
+            connecting = true;
+        }
+
+        /// <summary>Send a game packet (0x10-0xEF) to the host (1) or another client.</summary>
+        public void Send(byte packetType, byte[] data, ushort destinationId = 1)
+        {
+            Client.SendGamePacket(packetType, data, destinationId);
+        }
+
+        void Update()
+        {
+            if (!connecting && !IsConnected)
+            {
+                return;
+            }
+            // Cleared first so a failed attempt, which throws, isn't retried every frame
+            var wasConnecting = connecting;
+            connecting = false;
+            Client.ProcessPackets();
+            connecting = Client.ConnectionState == NeonConnectionState.Connecting;
+            if (wasConnecting && IsConnected)
+            {
+                Client.AutoPing = autoPing;
+                onConnected.Invoke();
+            }
+        }
+
+        void OnDestroy()
+        {
+            Client?.Dispose();
+            Client = null;
+        }
+    }
+}
+#endif
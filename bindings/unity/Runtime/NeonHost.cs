@@ -0,0 +1,172 @@
+using System;
+using AOT;
+
+namespace ProjectNeon
+{
+    /// <summary>
+    /// A Neon host. Either call <see cref="Update"/> from your own loop, where events fire, or
+    /// <see cref="StartThreaded"/>, after which events fire on the library's thread.
+    /// Dispose it when done; the finalizer frees it otherwise.
+    /// </summary>
+    public sealed class NeonHost : IDisposable
+    {
+        static readonly ClientConnectCallback ClientConnectNative = OnClientConnect;
+        static readonly ClientDenyCallback ClientDenyNative = OnClientDeny;
+        static readonly PingReceivedCallback PingReceivedNative = OnPingReceived;
+        static readonly HostUnhandledPacketCallback UnhandledPacketNative = OnUnhandledPacket;
+        static readonly GamePacketCallback GamePacketNative = OnGamePacket;
+
+        IntPtr handle;
+        readonly Callbacks callbacks;
+
+        /// <summary>(clientId, name, sessionId)</summary>
+        public event Action<ushort, string, uint> ClientConnected;
+        /// <summary>(name, reason)</summary>
+        public event Action<string, string> ClientDenied;
+        /// <summary>(fromClientId)</summary>
+        public event Action<ushort> PingReceived;
+        /// <summary>(packetType, fromClientId)</summary>
+        public event Action<byte, ushort> UnhandledPacket;
+        /// <summary>(packetType, fromClientId, data); data is a copy the handler may keep.</summary>
+        public event Action<byte, ushort, byte[]> GamePacketReceived;
+
+        public NeonHost(uint sessionId, string relayAddr)
+        {
+            handle = NeonNative.neon_host_new(sessionId, relayAddr);
+            if (handle == IntPtr.Zero)
+            {
+                throw new NeonException(NeonErrorCode.InvalidArgument);
+            }
+            callbacks = new Callbacks(this);
+            var userData = callbacks.UserData;
+            NeonNative.neon_host_set_client_connect_callback(handle, ClientConnectNative, userData);
+            NeonNative.neon_host_set_client_deny_callback(handle, ClientDenyNative, userData);
+            NeonNative.neon_host_set_ping_received_callback(handle, PingReceivedNative, userData);
+            NeonNative.neon_host_set_unhandled_packet_callback(handle, UnhandledPacketNative, userData);
+            NeonNative.neon_host_set_game_packet_callback(handle, GamePacketNative, userData);
+        }
+
+        ~NeonHost()
+        {
+            Free();
+        }
+
+        /// <summary>The native handle, for calling NeonNative directly.</summary>
+        public IntPtr Handle => Live();
+
+        public uint SessionId => NeonNative.neon_host_get_session_id(Live());
+
+        public int ClientCount => (int)NeonNative.neon_host_get_client_count(Live());
+
+        /// <summary>Describe a game packet type (0x10+) in the registry sent to connecting clients.</summary>
+        public void RegisterPacketType(byte packetId, string name, string description)
+        {
+            NeonException.Check(NeonNative.neon_host_register_packet_type(Live(), packetId, name, description));
+        }
+
+        /// <summary>Run one pass: send what's due and handle every packet waiting, firing events.
+        /// The first call registers with the relay, waiting for its reply.</summary>
+        public void Update()
+        {
+            var code = NeonNative.neon_host_update(Live());
+            callbacks.RethrowPending();
+            NeonException.Check(code);
+        }
+
+        /// <summary>Register, then run the host on the library's own thread until <see cref="Stop"/>.</summary>
+        public void StartThreaded()
+        {
+            NeonException.Check(NeonNative.neon_host_start_threaded(Live()));
+        }
+
+        /// <summary>Stop the thread from <see cref="StartThreaded"/>. Throws what stopped it early, if anything did.</summary>
+        public void Stop()
+        {
+            var code = NeonNative.neon_host_stop(Live());
+            callbacks.RethrowPending();
+            NeonException.Check(code);
+        }
+
+        /// <summary>Send a game packet (0x10-0xEF) to one connected client.</summary>
+        public void SendToClient(ushort clientId, byte packetType, byte[] data)
+        {
+            var len = (UIntPtr)(data?.Length ?? 0);
+            NeonException.Check(NeonNative.neon_host_send_to_client(Live(), clientId, packetType, data, len));
+        }
+
+        /// <summary>Send a game packet (0x10-0xEF) to every connected client.</summary>
+        public void Broadcast(byte packetType, byte[] data)
+        {
+            var len = (UIntPtr)(data?.Length ?? 0);
+            NeonException.Check(NeonNative.neon_host_broadcast(Live(), packetType, data, len));
+        }
+
+        public void Dispose()
+        {
+            Free();
+            GC.SuppressFinalize(this);
+        }
+
+        IntPtr Live()
+        {
+            if (handle == IntPtr.Zero)
+            {
+                throw new ObjectDisposedException(nameof(NeonHost));
+            }
+            return handle;
+        }
+
+        void Free()
+        {
+            if (handle != IntPtr.Zero)
+            {
+                // Stops the host's thread, if it has one, before freeing
+                NeonNative.neon_host_free(handle);
+                handle = IntPtr.Zero;
+            }
+            callbacks?.Dispose();
+        }
+
+        [MonoPInvokeCallback(typeof(ClientConnectCallback))]
+        static void OnClientConnect(ushort clientId, IntPtr name, uint sessionId, IntPtr userData)
+        {
+            var host = Callbacks.Owner<NeonHost>(userData);
+            var clientName = NeonNative.PtrToString(name);
+            host?.callbacks.Raise(() => host.ClientConnected?.Invoke(clientId, clientName, sessionId));
+        }
+
+        [MonoPInvokeCallback(typeof(ClientDenyCallback))]
+        static void OnClientDeny(IntPtr name, IntPtr reason, IntPtr userData)
+        {
+            var host = Callbacks.Owner<NeonHost>(userData);
+            var (clientName, why) = (NeonNative.PtrToString(name), NeonNative.PtrToString(reason));
+            host?.callbacks.Raise(() => host.ClientDenied?.Invoke(clientName, why));
+        }
+
+        [MonoPInvokeCallback(typeof(PingReceivedCallback))]
+        static void OnPingReceived(ushort fromClientId, IntPtr userData)
+        {
+            var host = Callbacks.Owner<NeonHost>(userData);
+            host?.callbacks.Raise(() => host.PingReceived?.Invoke(fromClientId));
+        }
+
+        [MonoPInvokeCallback(typeof(HostUnhandledPacketCallback))]
+        static void OnUnhandledPacket(byte packetType, ushort fromClientId, IntPtr userData)
+        {
+            var host = Callbacks.Owner<NeonHost>(userData);
+            host?.callbacks.Raise(() => host.UnhandledPacket?.Invoke(packetType, fromClientId));
+        }
+
+        [MonoPInvokeCallback(typeof(GamePacketCallback))]
+        static void OnGamePacket(byte packetType, ushort fromClientId, IntPtr data, UIntPtr len, IntPtr userData)
+        {
+            var host = Callbacks.Owner<NeonHost>(userData);
+            if (host == null)
+            {
+                return;
+            }
+            var bytes = NeonNative.CopyBytes(data, len);
+            host.callbacks.Raise(() => host.GamePacketReceived?.Invoke(packetType, fromClientId, bytes));
+        }
+    }
+}
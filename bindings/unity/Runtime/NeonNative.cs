@@ -0,0 +1,273 @@
+// P/Invoke declarations for every function in include/neon.h. Keep in step with src/ffi.rs;
+// tests/unity_bindings.rs fails when a function is missing here.
+using System;
+using System.Runtime.InteropServices;
+
+namespace ProjectNeon
+{
+    /// <summary>Result of a fallible call; mirrors NeonErrorCode in neon.h.</summary>
+    public enum NeonErrorCode
+    {
+        Ok = 0,
+        NullPointer = 1,
+        InvalidArgument = 2,
+        NotConnected = 3,
+        NotFound = 4,
+        TimedOut = 5,
+        ConnectionDenied = 6,
+        WouldBlock = 7,
+        Io = 8,
+    }
+
+    /// <summary>Where a client is in joining a session; mirrors NeonConnectionState in neon.h.</summary>
+    public enum NeonConnectionState
+    {
+        Disconnected = 0,
+        Connecting = 1,
+        Connected = 2,
+        AwaitingConfig = 3,
+        Disconnecting = 4,
+    }
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void PongCallback(ulong responseTimeMs, ulong timestamp, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void SessionConfigCallback(byte version, ushort tickRate, ushort maxPacketSize, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void PacketTypeRegistryCallback(UIntPtr count, IntPtr ids, IntPtr names, IntPtr descriptions, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void UnhandledPacketCallback(byte packetType, ushort fromClientId, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void WrongDestinationCallback(ushort myId, ushort packetDestinationId, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void GamePacketCallback(byte packetType, ushort fromClientId, IntPtr data, UIntPtr len, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void ClientConnectCallback(ushort clientId, IntPtr name, uint sessionId, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void ClientDenyCallback(IntPtr name, IntPtr reason, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void PingReceivedCallback(ushort fromClientId, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void HostUnhandledPacketCallback(byte packetType, ushort fromClientId, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void RelaySessionCallback(uint sessionId, IntPtr userData);
+
+    /// <summary>
+    /// The raw C API. Handles are IntPtr; strings passed in are marshalled as UTF-8, and strings
+    /// coming back are const char* to read with <see cref="NeonNative.PtrToString"/>.
+    /// Prefer NeonClient, NeonHost and NeonRelay, which manage handles and callbacks.
+    /// </summary>
+    public static class NeonNative
+    {
+#if UNITY_IOS && !UNITY_EDITOR
+        // iOS links the static library into the player
+        const string Lib = "__Internal";
+#else
+        const string Lib = "project_neon";
+#endif
+
+        // Client
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern IntPtr neon_client_new([MarshalAs(UnmanagedType.LPUTF8Str)] string name);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_set_pong_callback(IntPtr client, PongCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_set_session_config_callback(IntPtr client, SessionConfigCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_set_packet_type_registry_callback(IntPtr client, PacketTypeRegistryCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_set_unhandled_packet_callback(IntPtr client, UnhandledPacketCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_set_wrong_destination_callback(IntPtr client, WrongDestinationCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_set_game_packet_callback(IntPtr client, GamePacketCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_client_connect(IntPtr client, uint sessionId, [MarshalAs(UnmanagedType.LPUTF8Str)] string relayAddr);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_client_begin_connect(IntPtr client, uint sessionId, [MarshalAs(UnmanagedType.LPUTF8Str)] string relayAddr);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_client_connect_via_directory(IntPtr client, [MarshalAs(UnmanagedType.LPUTF8Str)] string directoryAddr, uint sessionId);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_client_process_packets(IntPtr client);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern ushort neon_client_get_id(IntPtr client);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern uint neon_client_get_session_id(IntPtr client);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern bool neon_client_is_connected(IntPtr client);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonConnectionState neon_client_get_connection_state(IntPtr client);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonConnectionState neon_client_get_state(IntPtr client);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_client_disconnect(IntPtr client);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_client_wait_until_configured(IntPtr client, ulong timeoutMs);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_client_send_ping(IntPtr client);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_client_send_game_packet(IntPtr client, byte packetType, ushort destinationId, byte[] data, UIntPtr len);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_set_auto_ping(IntPtr client, [MarshalAs(UnmanagedType.U1)] bool enabled);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_set_auth_token(IntPtr client, byte[] token, UIntPtr len);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_client_free(IntPtr client);
+
+        // Host
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern IntPtr neon_host_new(uint sessionId, [MarshalAs(UnmanagedType.LPUTF8Str)] string relayAddr);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_host_set_client_connect_callback(IntPtr host, ClientConnectCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_host_set_client_deny_callback(IntPtr host, ClientDenyCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_host_set_ping_received_callback(IntPtr host, PingReceivedCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_host_set_unhandled_packet_callback(IntPtr host, HostUnhandledPacketCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_host_set_game_packet_callback(IntPtr host, GamePacketCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_host_register_packet_type(IntPtr host, byte packetId, [MarshalAs(UnmanagedType.LPUTF8Str)] string name, [MarshalAs(UnmanagedType.LPUTF8Str)] string description);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern uint neon_host_get_session_id(IntPtr host);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern UIntPtr neon_host_get_client_count(IntPtr host);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_host_start(IntPtr host);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_host_update(IntPtr host);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_host_start_threaded(IntPtr host);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_host_stop(IntPtr host);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_host_send_to_client(IntPtr host, ushort clientId, byte packetType, byte[] data, UIntPtr len);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_host_broadcast(IntPtr host, byte packetType, byte[] data, UIntPtr len);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_host_free(IntPtr host);
+
+        // Relay
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern IntPtr neon_relay_new([MarshalAs(UnmanagedType.LPUTF8Str)] string bindAddr);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_relay_set_session_created_callback(IntPtr relay, RelaySessionCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_relay_set_session_destroyed_callback(IntPtr relay, RelaySessionCallback callback, IntPtr userData);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern NeonErrorCode neon_relay_start(IntPtr relay);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_relay_stop(IntPtr relay);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern UIntPtr neon_relay_session_count(IntPtr relay);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern UIntPtr neon_relay_total_client_count(IntPtr relay);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void neon_relay_free(IntPtr relay);
+
+        // Errors
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern IntPtr neon_get_last_error();
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern IntPtr neon_error_message(int code);
+
+        /// <summary>Read a UTF-8 const char* from the library; null stays null.</summary>
+        public static string PtrToString(IntPtr ptr)
+        {
+            return ptr == IntPtr.Zero ? null : Marshal.PtrToStringUTF8(ptr);
+        }
+
+        /// <summary>Copy a payload the library lends a callback; it is only valid during the call.</summary>
+        public static byte[] CopyBytes(IntPtr data, UIntPtr len)
+        {
+            var bytes = new byte[(int)len];
+            if (bytes.Length > 0)
+            {
+                Marshal.Copy(data, bytes, 0, bytes.Length);
+            }
+            return bytes;
+        }
+    }
+
+    /// <summary>A failed call: the code, and neon_get_last_error's message at the time.</summary>
+    public class NeonException : Exception
+    {
+        public NeonErrorCode Code { get; }
+
+        public NeonException(NeonErrorCode code)
+            : base(NeonNative.PtrToString(NeonNative.neon_get_last_error()) ?? NeonNative.PtrToString(NeonNative.neon_error_message((int)code)))
+        {
+            Code = code;
+        }
+
+        /// <summary>Throw unless the call succeeded.</summary>
+        public static void Check(NeonErrorCode code)
+        {
+            if (code != NeonErrorCode.Ok)
+            {
+                throw new NeonException(code);
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+using System;
+using AOT;
+
+namespace ProjectNeon
+{
+    /// <summary>
+    /// A relay embedded in a dedicated server. <see cref="Start"/> blocks until <see cref="Stop"/>,
+    /// so run it on a thread of its own; session events fire on that thread.
+    /// </summary>
+    public sealed class NeonRelay : IDisposable
+    {
+        static readonly RelaySessionCallback SessionCreatedNative = OnSessionCreated;
+        static readonly RelaySessionCallback SessionDestroyedNative = OnSessionDestroyed;
+
+        IntPtr handle;
+        readonly Callbacks callbacks;
+
+        /// <summary>(sessionId)</summary>
+        public event Action<uint> SessionCreated;
+        /// <summary>(sessionId)</summary>
+        public event Action<uint> SessionDestroyed;
+
+        /// <summary>Bind a relay to an address such as "0.0.0.0:7777".</summary>
+        public NeonRelay(string bindAddr)
+        {
+            handle = NeonNative.neon_relay_new(bindAddr);
+            if (handle == IntPtr.Zero)
+            {
+                throw new NeonException(NeonErrorCode.Io);
+            }
+            callbacks = new Callbacks(this);
+            NeonNative.neon_relay_set_session_created_callback(handle, SessionCreatedNative, callbacks.UserData);
+            NeonNative.neon_relay_set_session_destroyed_callback(handle, SessionDestroyedNative, callbacks.UserData);
+        }
+
+        ~NeonRelay()
+        {
+            Free();
+        }
+
+        /// <summary>Safe from any thread while the relay runs.</summary>
+        public int SessionCount => (int)NeonNative.neon_relay_session_count(Live());
+
+        /// <summary>Safe from any thread while the relay runs.</summary>
+        public int TotalClientCount => (int)NeonNative.neon_relay_total_client_count(Live());
+
+        /// <summary>Run the relay on this thread until <see cref="Stop"/> is called.</summary>
+        public void Start()
+        {
+            var code = NeonNative.neon_relay_start(Live());
+            callbacks.RethrowPending();
+            NeonException.Check(code);
+        }
+
+        /// <summary>Ask the relay to stop; Start returns within a second. Safe from any thread.</summary>
+        public void Stop()
+        {
+            NeonNative.neon_relay_stop(Live());
+        }
+
+        /// <summary>Free the relay, after Start has returned.</summary>
+        public void Dispose()
+        {
+            Free();
+            GC.SuppressFinalize(this);
+        }
+
+        IntPtr Live()
+        {
+            if (handle == IntPtr.Zero)
+            {
+                throw new ObjectDisposedException(nameof(NeonRelay));
+            }
+            return handle;
+        }
+
+        void Free()
+        {
+            if (handle != IntPtr.Zero)
+            {
+                NeonNative.neon_relay_free(handle);
+                handle = IntPtr.Zero;
+            }
+            callbacks?.Dispose();
+        }
+
+        [MonoPInvokeCallback(typeof(RelaySessionCallback))]
+        static void OnSessionCreated(uint sessionId, IntPtr userData)
+        {
+            var relay = Callbacks.Owner<NeonRelay>(userData);
+            relay?.callbacks.Raise(() => relay.SessionCreated?.Invoke(sessionId));
+        }
+
+        [MonoPInvokeCallback(typeof(RelaySessionCallback))]
+        static void OnSessionDestroyed(uint sessionId, IntPtr userData)
+        {
+            var relay = Callbacks.Owner<NeonRelay>(userData);
+            relay?.callbacks.Raise(() => relay.SessionDestroyed?.Invoke(sessionId));
+        }
+    }
+}
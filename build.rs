@@ -0,0 +1,15 @@
+fn main() {
+    // Regenerate include/neon.h from ffi.rs when the `header` feature is on
+    #[cfg(feature = "header")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_crate(&crate_dir)
+            .with_config(cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).unwrap())
+            .generate()
+            .expect("Unable to generate neon.h")
+            .write_to_file(format!("{}/include/neon.h", crate_dir));
+    }
+}
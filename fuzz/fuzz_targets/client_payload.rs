@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use project_neon::client::types::PacketPayload;
+use project_neon::protocol::{PROTOCOL_V1, PROTOCOL_VERSION};
+
+// First byte picks the packet type so every payload parser is reachable.
+// The host shares the client's wire format, so this covers both.
+fuzz_target!(|data: &[u8]| {
+    if let Some((&packet_type, payload)) = data.split_first() {
+        for version in [PROTOCOL_V1, PROTOCOL_VERSION] {
+            let _ = PacketPayload::from_bytes(packet_type, payload, version);
+        }
+    }
+});
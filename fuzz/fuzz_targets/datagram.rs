@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use project_neon::protocol;
+
+// Whole datagrams as they arrive off the wire, including batches and compressed payloads
+fuzz_target!(|data: &[u8]| {
+    let _ = protocol::decode(data);
+});
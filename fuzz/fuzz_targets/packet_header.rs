@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use project_neon::client::types::PacketHeader as ClientHeader;
+use project_neon::relay::types::PacketHeader as RelayHeader;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(header) = ClientHeader::from_bytes(data) {
+        assert_eq!(header.to_bytes(), data[..header.size()]);
+    }
+    if let Ok(header) = RelayHeader::from_bytes(data) {
+        assert_eq!(header.to_bytes(), data[..header.size()]);
+    }
+});
@@ -0,0 +1,290 @@
+/* Generated by cbindgen from src/ffi.rs; do not edit. Rebuild with --features header. */
+
+#ifndef NEON_H
+#define NEON_H
+
+#include <stdint.h>
+#include <stdbool.h>
+#include <stddef.h>
+typedef struct NeonClientHandle NeonClientHandle;
+typedef struct NeonHostHandle NeonHostHandle;
+typedef struct NeonRelayHandle NeonRelayHandle;
+
+// Result of a fallible call. Ok is 0; neon_get_last_error has the details of anything else.
+typedef enum NeonErrorCode {
+  NEON_ERROR_CODE_OK = 0,
+  NEON_ERROR_CODE_NULL_POINTER = 1,
+  NEON_ERROR_CODE_INVALID_ARGUMENT = 2,
+  NEON_ERROR_CODE_NOT_CONNECTED = 3,
+  NEON_ERROR_CODE_NOT_FOUND = 4,
+  NEON_ERROR_CODE_TIMED_OUT = 5,
+  NEON_ERROR_CODE_CONNECTION_DENIED = 6,
+  NEON_ERROR_CODE_WOULD_BLOCK = 7,
+  NEON_ERROR_CODE_IO = 8,
+} NeonErrorCode;
+
+// Where a client is in joining a session
+typedef enum NeonConnectionState {
+  NEON_CONNECTION_STATE_DISCONNECTED = 0,
+  NEON_CONNECTION_STATE_CONNECTING = 1,
+  NEON_CONNECTION_STATE_CONNECTED = 2,
+  NEON_CONNECTION_STATE_AWAITING_CONFIG = 3,
+  NEON_CONNECTION_STATE_DISCONNECTING = 4,
+} NeonConnectionState;
+
+typedef void (*PongCallback)(uint64_t response_time_ms, uint64_t timestamp, void *user_data);
+
+typedef void (*SessionConfigCallback)(uint8_t version,
+                                      uint16_t tick_rate,
+                                      uint16_t max_packet_size,
+                                      void *user_data);
+
+typedef void (*PacketTypeRegistryCallback)(size_t count,
+                                           const uint8_t *ids,
+                                           const char *const *names,
+                                           const char *const *descriptions,
+                                           void *user_data);
+
+typedef void (*UnhandledPacketCallback)(uint8_t packet_type,
+                                        uint16_t from_client_id,
+                                        void *user_data);
+
+typedef void (*WrongDestinationCallback)(uint16_t my_id,
+                                         uint16_t packet_destination_id,
+                                         void *user_data);
+
+typedef void (*GamePacketCallback)(uint8_t packet_type,
+                                   uint16_t from_client_id,
+                                   const uint8_t *data,
+                                   size_t len,
+                                   void *user_data);
+
+typedef void (*ClientConnectCallback)(uint16_t client_id,
+                                      const char *name,
+                                      uint32_t session_id,
+                                      void *user_data);
+
+typedef void (*ClientDenyCallback)(const char *name, const char *reason, void *user_data);
+
+typedef void (*PingReceivedCallback)(uint16_t from_client_id, void *user_data);
+
+typedef void (*HostUnhandledPacketCallback)(uint8_t packet_type,
+                                            uint16_t from_client_id,
+                                            void *user_data);
+
+typedef void (*RelaySessionCallback)(uint32_t session_id, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create a new Neon client
+// Returns null on failure (see neon_get_last_error)
+NeonClientHandle *neon_client_new(const char *name);
+
+// Set callback for pong events
+void neon_client_set_pong_callback(NeonClientHandle *client,
+                                   PongCallback callback,
+                                   void *user_data);
+
+// Set callback for session config events
+void neon_client_set_session_config_callback(NeonClientHandle *client,
+                                             SessionConfigCallback callback,
+                                             void *user_data);
+
+// Set callback for packet type registry events
+void neon_client_set_packet_type_registry_callback(NeonClientHandle *client,
+                                                   PacketTypeRegistryCallback callback,
+                                                   void *user_data);
+
+// Set callback for unhandled packet events
+void neon_client_set_unhandled_packet_callback(NeonClientHandle *client,
+                                               UnhandledPacketCallback callback,
+                                               void *user_data);
+
+// Set callback for wrong destination events
+void neon_client_set_wrong_destination_callback(NeonClientHandle *client,
+                                                WrongDestinationCallback callback,
+                                                void *user_data);
+
+// Set callback for game packets (0x10+). `data` is only valid during the call.
+void neon_client_set_game_packet_callback(NeonClientHandle *client,
+                                          GamePacketCallback callback,
+                                          void *user_data);
+
+// Connect the client to a session
+enum NeonErrorCode neon_client_connect(NeonClientHandle *client,
+                                       uint32_t session_id,
+                                       const char *relay_addr);
+
+// Start connecting the client to a session without waiting; neon_client_process_packets advances the attempt
+enum NeonErrorCode neon_client_begin_connect(NeonClientHandle *client,
+                                             uint32_t session_id,
+                                             const char *relay_addr);
+
+// Process packets until the host's session config arrives, for up to `timeout_ms`
+enum NeonErrorCode neon_client_wait_until_configured(NeonClientHandle *client, uint64_t timeout_ms);
+
+// Connect the client to a session through whichever relay the directory relay picks
+enum NeonErrorCode neon_client_connect_via_directory(NeonClientHandle *client,
+                                                     const char *directory_addr,
+                                                     uint32_t session_id);
+
+// Process incoming packets (call this regularly, e.g. in your game tick)
+enum NeonErrorCode neon_client_process_packets(NeonClientHandle *client);
+
+// Get the client's assigned ID (returns 0 if not connected)
+uint16_t neon_client_get_id(NeonClientHandle *client);
+
+// Get the session ID (returns 0 if not connected)
+uint32_t neon_client_get_session_id(NeonClientHandle *client);
+
+// Get whether the client is connected, connecting, or neither
+enum NeonConnectionState neon_client_get_connection_state(NeonClientHandle *client);
+
+// Get the client's state in full, including waiting on SessionConfig and disconnecting
+enum NeonConnectionState neon_client_get_state(NeonClientHandle *client);
+
+// Leave the session, sending whatever is queued first
+enum NeonErrorCode neon_client_disconnect(NeonClientHandle *client);
+
+// Check if the client is connected
+bool neon_client_is_connected(NeonClientHandle *client);
+
+// Manually send a ping
+enum NeonErrorCode neon_client_send_ping(NeonClientHandle *client);
+
+// Send a game packet to another client in the session, or the host (1)
+enum NeonErrorCode neon_client_send_game_packet(NeonClientHandle *client,
+                                                uint8_t packet_type,
+                                                uint16_t destination_id,
+                                                const uint8_t *data,
+                                                size_t len);
+
+// Set auto-ping enabled/disabled
+void neon_client_set_auto_ping(NeonClientHandle *client, bool enabled);
+
+// Set the token sent with connection requests (null or zero length sends none)
+void neon_client_set_auth_token(NeonClientHandle *client, const uint8_t *token, size_t len);
+
+// Free the client (call when done)
+void neon_client_free(NeonClientHandle *client);
+
+// Create a new Neon host
+// Returns null on failure (see neon_get_last_error)
+NeonHostHandle *neon_host_new(uint32_t session_id, const char *relay_addr);
+
+// Set callback for client connect events
+void neon_host_set_client_connect_callback(NeonHostHandle *host,
+                                           ClientConnectCallback callback,
+                                           void *user_data);
+
+// Set callback for client deny events
+void neon_host_set_client_deny_callback(NeonHostHandle *host,
+                                        ClientDenyCallback callback,
+                                        void *user_data);
+
+// Set callback for ping received events
+void neon_host_set_ping_received_callback(NeonHostHandle *host,
+                                          PingReceivedCallback callback,
+                                          void *user_data);
+
+// Set callback for unhandled packet events
+void neon_host_set_unhandled_packet_callback(NeonHostHandle *host,
+                                             HostUnhandledPacketCallback callback,
+                                             void *user_data);
+
+// Set callback for game packets (0x10+). `data` is only valid during the call.
+void neon_host_set_game_packet_callback(NeonHostHandle *host,
+                                        GamePacketCallback callback,
+                                        void *user_data);
+
+// Describe a game packet type (0x10+) in the registry sent to connecting clients
+enum NeonErrorCode neon_host_register_packet_type(NeonHostHandle *host,
+                                                  uint8_t packet_id,
+                                                  const char *name,
+                                                  const char *description);
+
+// Get the host's session ID
+uint32_t neon_host_get_session_id(NeonHostHandle *host);
+
+// Get the number of connected clients
+size_t neon_host_get_client_count(NeonHostHandle *host);
+
+// Start the host (this blocks! Run in a separate thread)
+// Only returns on failure
+enum NeonErrorCode neon_host_start(NeonHostHandle *host);
+
+// Run one pass of the host: send what's due and handle every packet waiting, then return.
+// Call it from your own loop, e.g. once per frame. The first call registers with the relay,
+// waiting for its reply.
+enum NeonErrorCode neon_host_update(NeonHostHandle *host);
+
+// Register with the relay, then run the host on a thread the library owns until neon_host_stop.
+// Callbacks run on that thread. Returns once registered, so clients can connect straight away.
+enum NeonErrorCode neon_host_start_threaded(NeonHostHandle *host);
+
+// Stop the thread from neon_host_start_threaded and wait for it to finish. Returns the error that
+// stopped it early, if one did.
+enum NeonErrorCode neon_host_stop(NeonHostHandle *host);
+
+// Send a game packet to a connected client
+enum NeonErrorCode neon_host_send_to_client(NeonHostHandle *host,
+                                            uint16_t client_id,
+                                            uint8_t packet_type,
+                                            const uint8_t *data,
+                                            size_t len);
+
+// Send a game packet to every connected client
+enum NeonErrorCode neon_host_broadcast(NeonHostHandle *host,
+                                       uint8_t packet_type,
+                                       const uint8_t *data,
+                                       size_t len);
+
+// Free the host (call when done), stopping its thread if neon_host_start_threaded started one
+void neon_host_free(NeonHostHandle *host);
+
+// Create a new relay bound to `bind_addr`, e.g. "0.0.0.0:7777"
+// Returns null on failure (see neon_get_last_error)
+NeonRelayHandle *neon_relay_new(const char *bind_addr);
+
+// Set callback for when a host registers a new session. Runs on the thread inside neon_relay_start.
+// Must be set before neon_relay_start.
+void neon_relay_set_session_created_callback(NeonRelayHandle *relay,
+                                             RelaySessionCallback callback,
+                                             void *user_data);
+
+// Set callback for when a session ends, including when the relay stops. Runs on the thread inside
+// neon_relay_start. Must be set before neon_relay_start.
+void neon_relay_set_session_destroyed_callback(NeonRelayHandle *relay,
+                                               RelaySessionCallback callback,
+                                               void *user_data);
+
+// Start the relay (this blocks! Run in a separate thread)
+// Returns NEON_ERROR_CODE_OK once neon_relay_stop is called, or the error that stopped it
+enum NeonErrorCode neon_relay_start(NeonRelayHandle *relay);
+
+// Ask a running relay to stop; neon_relay_start returns within a second. Safe from any thread.
+void neon_relay_stop(NeonRelayHandle *relay);
+
+// Get the number of active sessions. Safe from any thread, while the relay runs.
+size_t neon_relay_session_count(NeonRelayHandle *relay);
+
+// Get the number of connected clients across all sessions. Safe from any thread, while the relay runs.
+size_t neon_relay_total_client_count(NeonRelayHandle *relay);
+
+// Free the relay (call when done, after neon_relay_start has returned)
+void neon_relay_free(NeonRelayHandle *relay);
+
+// Get the last error message (or null if no error)
+// The returned string is valid until the next error or until this thread exits
+const char *neon_get_last_error(void);
+
+// Get a static description of an error code, for when the last error message isn't at hand
+const char *neon_error_message(int code);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NEON_H */
@@ -0,0 +1,84 @@
+// Header-only C++ convenience layer over neon.h: owning handle pointers and exceptions for error codes
+#ifndef NEON_HPP
+#define NEON_HPP
+
+#include <cstdint>
+#include <memory>
+#include <stdexcept>
+#include <string>
+
+#include "neon.h"
+
+namespace neon {
+
+struct ClientDeleter {
+    void operator()(NeonClientHandle* client) const noexcept { neon_client_free(client); }
+};
+
+struct HostDeleter {
+    void operator()(NeonHostHandle* host) const noexcept { neon_host_free(host); }
+};
+
+struct RelayDeleter {
+    void operator()(NeonRelayHandle* relay) const noexcept { neon_relay_free(relay); }
+};
+
+// Frees the client when it goes out of scope
+using NeonClientPtr = std::unique_ptr<NeonClientHandle, ClientDeleter>;
+
+// Frees the host when it goes out of scope; stop any thread inside neon_host_start first
+using NeonHostPtr = std::unique_ptr<NeonHostHandle, HostDeleter>;
+
+// Frees the relay when it goes out of scope; call neon_relay_stop and join the thread inside neon_relay_start first
+using NeonRelayPtr = std::unique_ptr<NeonRelayHandle, RelayDeleter>;
+
+// A failed call: the code, and neon_get_last_error's message at the time
+class Error : public std::runtime_error {
+public:
+    explicit Error(NeonErrorCode code) : std::runtime_error(last_error_or(code)), code_(code) {}
+
+    NeonErrorCode code() const noexcept { return code_; }
+
+private:
+    static std::string last_error_or(NeonErrorCode code) {
+        const char* message = neon_get_last_error();
+        return message ? message : neon_error_message(code);
+    }
+
+    NeonErrorCode code_;
+};
+
+// Throw Error unless the call succeeded
+inline void check(NeonErrorCode code) {
+    if (code != NEON_ERROR_CODE_OK) {
+        throw Error(code);
+    }
+}
+
+inline NeonClientPtr make_client(const std::string& name) {
+    NeonClientPtr client(neon_client_new(name.c_str()));
+    if (!client) {
+        throw Error(NEON_ERROR_CODE_INVALID_ARGUMENT);
+    }
+    return client;
+}
+
+inline NeonHostPtr make_host(uint32_t session_id, const std::string& relay_addr) {
+    NeonHostPtr host(neon_host_new(session_id, relay_addr.c_str()));
+    if (!host) {
+        throw Error(NEON_ERROR_CODE_INVALID_ARGUMENT);
+    }
+    return host;
+}
+
+inline NeonRelayPtr make_relay(const std::string& bind_addr) {
+    NeonRelayPtr relay(neon_relay_new(bind_addr.c_str()));
+    if (!relay) {
+        throw Error(NEON_ERROR_CODE_IO);
+    }
+    return relay;
+}
+
+}  // namespace neon
+
+#endif  // NEON_HPP
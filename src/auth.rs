@@ -0,0 +1,51 @@
+/// Deny reason for a request that carried no token
+pub const AUTH_REQUIRED: &str = "Authentication required";
+
+/// Deny reason for a request whose token was rejected
+pub const AUTH_INVALID: &str = "Invalid authentication token";
+
+/// Checks the token a client sends with its ConnectRequest before it's admitted.
+/// Set one on a host with `NeonHost::set_authenticator`, on a relay with `NeonRelay::set_authenticator`, or both.
+pub trait Authenticator: Send + Sync {
+    /// Ok admits the client; Err denies it and is sent back as the reason
+    fn authenticate(&self, name: &str, session_id: u32, token: &[u8]) -> Result<(), String>;
+}
+
+impl<F> Authenticator for F
+where
+    F: Fn(&str, u32, &[u8]) -> Result<(), String> + Send + Sync,
+{
+    fn authenticate(&self, name: &str, session_id: u32, token: &[u8]) -> Result<(), String> {
+        self(name, session_id, token)
+    }
+}
+
+/// Admits clients presenting any of a fixed set of tokens, e.g. invite codes
+#[derive(Debug, Clone, Default)]
+pub struct TokenList {
+    tokens: Vec<Vec<u8>>,
+}
+
+impl TokenList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `token` from now on
+    pub fn allow(mut self, token: impl Into<Vec<u8>>) -> Self {
+        self.tokens.push(token.into());
+        self
+    }
+}
+
+impl Authenticator for TokenList {
+    fn authenticate(&self, _name: &str, _session_id: u32, token: &[u8]) -> Result<(), String> {
+        if self.tokens.iter().any(|allowed| allowed == token) {
+            Ok(())
+        } else if token.is_empty() {
+            Err(AUTH_REQUIRED.to_string())
+        } else {
+            Err(AUTH_INVALID.to_string())
+        }
+    }
+}
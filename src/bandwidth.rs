@@ -0,0 +1,171 @@
+use std::time::{Duration, Instant};
+
+/// Smallest burst allowed, so a full-size datagram can always go out once the bucket refills
+const MIN_BURST_BYTES: f64 = 1024.0;
+
+/// Fraction of a second's budget that may be sent back to back
+const BURST_SECONDS: f64 = 0.1;
+
+/// Leaky-bucket cap on outgoing bytes per second. The bucket refills continuously and holds
+/// up to a tenth of a second of traffic (at least one full datagram), so bursts are smoothed
+/// into a steady rate instead of overrunning a slow link.
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+    bytes_per_second: u32,
+    capacity: f64,
+    available: f64,
+    last_refill: Instant,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_second: u32) -> Self {
+        let capacity = (bytes_per_second as f64 * BURST_SECONDS).max(MIN_BURST_BYTES);
+        Self {
+            bytes_per_second,
+            capacity,
+            available: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    pub fn bytes_per_second(&self) -> u32 {
+        self.bytes_per_second
+    }
+
+    /// Change the rate, keeping whatever budget is currently available
+    pub fn set_bytes_per_second(&mut self, bytes_per_second: u32) {
+        self.refill();
+        self.bytes_per_second = bytes_per_second;
+        self.capacity = (bytes_per_second as f64 * BURST_SECONDS).max(MIN_BURST_BYTES);
+        self.available = self.available.min(self.capacity);
+    }
+
+    /// Budget that could be spent right now, in bytes
+    pub fn available(&mut self) -> usize {
+        self.refill();
+        self.available.max(0.0) as usize
+    }
+
+    /// Spend `bytes` of budget if there is enough. A packet larger than the whole bucket is
+    /// let through once the bucket is full, leaving the budget in debt.
+    pub fn try_consume(&mut self, bytes: usize) -> bool {
+        self.refill();
+        let bytes = bytes as f64;
+        if self.available < bytes.min(self.capacity) {
+            return false;
+        }
+        self.available -= bytes;
+        true
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.available = (self.available + elapsed * self.bytes_per_second as f64).min(self.capacity);
+    }
+}
+
+/// Ceiling for congestion control when no bandwidth limit has been set
+pub const DEFAULT_MAX_RATE: u32 = 1_000_000;
+
+/// Congestion control never slows below this, so pings and input still get through
+const MIN_RATE: u32 = 8_000;
+
+/// An RTT sample more than this factor above the lowest seen means packets are queueing
+const RTT_INFLATION: f64 = 1.5;
+
+/// Slack added to the inflation threshold so jitter on very short paths isn't mistaken for queueing
+const RTT_SLACK: Duration = Duration::from_millis(5);
+
+/// Multiplier applied to the rate on each congestion signal
+const DECREASE_FACTOR: f64 = 0.7;
+
+/// Fraction of the ceiling added to the rate each clear interval
+const INCREASE_FRACTION: f64 = 0.02;
+
+/// How often the rate grows while the link looks clear, and the shortest gap between decreases
+const ADJUST_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Additive-increase/multiplicative-decrease rate controller. Congestion is signalled by round
+/// trips inflating above the lowest seen, or by reliable packets needing a retransmit; each
+/// signal cuts the rate by 30% (at most once per round trip), and every 100ms without one it
+/// grows by 2% of the ceiling.
+#[derive(Debug, Clone)]
+pub struct CongestionController {
+    rate: f64,
+    min_rate: f64,
+    max_rate: f64,
+    base_rtt: Option<Duration>,
+    last_decrease: Option<Instant>,
+    last_increase: Instant,
+}
+
+impl CongestionController {
+    /// Start at the ceiling and back off from there
+    pub fn new(max_rate: u32) -> Self {
+        let max_rate = max_rate as f64;
+        Self {
+            rate: max_rate,
+            min_rate: (MIN_RATE as f64).min(max_rate),
+            max_rate,
+            base_rtt: None,
+            last_decrease: None,
+            last_increase: Instant::now(),
+        }
+    }
+
+    /// Current allowed rate in bytes per second
+    pub fn rate(&self) -> u32 {
+        self.rate as u32
+    }
+
+    pub fn max_rate(&self) -> u32 {
+        self.max_rate as u32
+    }
+
+    pub fn set_max_rate(&mut self, max_rate: u32) {
+        self.max_rate = max_rate as f64;
+        self.min_rate = (MIN_RATE as f64).min(self.max_rate);
+        self.rate = self.rate.clamp(self.min_rate, self.max_rate);
+    }
+
+    /// Lowest round trip seen, taken as the uncongested path delay
+    pub fn base_rtt(&self) -> Option<Duration> {
+        self.base_rtt
+    }
+
+    /// Feed a measured round trip
+    pub fn on_rtt_sample(&mut self, rtt: Duration) {
+        let base = *self.base_rtt.get_or_insert(rtt);
+        if rtt < base {
+            self.base_rtt = Some(rtt);
+        } else if rtt > base.mul_f64(RTT_INFLATION) + RTT_SLACK {
+            self.on_congestion();
+        }
+    }
+
+    /// Feed a retransmit (a reliable packet that went unacknowledged)
+    pub fn on_loss(&mut self) {
+        self.on_congestion();
+    }
+
+    /// Grow the rate for any clear intervals that have passed
+    pub fn update(&mut self) {
+        let intervals = (self.last_increase.elapsed().as_secs_f64() / ADJUST_INTERVAL.as_secs_f64()).floor();
+        if intervals >= 1.0 {
+            self.rate = (self.rate + intervals * self.max_rate * INCREASE_FRACTION).min(self.max_rate);
+            self.last_increase += ADJUST_INTERVAL.mul_f64(intervals);
+        }
+    }
+
+    fn on_congestion(&mut self) {
+        let hold = self.base_rtt.unwrap_or_default().max(ADJUST_INTERVAL);
+        if self.last_decrease.is_some_and(|at| at.elapsed() < hold) {
+            return;
+        }
+        self.rate = (self.rate * DECREASE_FACTOR).max(self.min_rate);
+        self.last_decrease = Some(Instant::now());
+        self.last_increase = Instant::now();
+    }
+}
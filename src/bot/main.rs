@@ -0,0 +1,348 @@
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use project_neon::client::NeonClient;
+use project_neon::host::NeonHost;
+
+const USAGE: &str = "Usage: neon-bot --session <id> [--relay <addr>] [--host] [--clients <n>] [--rate <packets/s>] \
+                     [--size <bytes>] [--duration <secs>] [--pattern steady|burst|mesh]";
+
+/// Game packet type the bots send
+const BOT_PACKET: u8 = 0x10;
+
+/// Bot index, sequence and send time lead every payload
+const TAG_SIZE: usize = 16;
+
+const PING_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long to keep listening after the last packet is sent, for stragglers
+const DRAIN_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pattern {
+    /// Every bot sends to the host at an even rate
+    Steady,
+    /// Every bot sends a second's worth of packets to the host at once, once a second
+    Burst,
+    /// Every bot sends to the other bots in turn, so delivery can be measured without a host of ours
+    Mesh,
+}
+
+struct Args {
+    relay: String,
+    session_id: Option<u32>,
+    host: bool,
+    clients: usize,
+    rate: u32,
+    size: usize,
+    duration: Duration,
+    pattern: Pattern,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        relay: "127.0.0.1:7777".to_string(),
+        session_id: None,
+        host: false,
+        clients: 10,
+        rate: 20,
+        size: 64,
+        duration: Duration::from_secs(10),
+        pattern: Pattern::Steady,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--relay" => args.relay = value()?,
+            "--session" => args.session_id = Some(number(&value()?)?),
+            "--host" => args.host = true,
+            "--clients" => args.clients = number(&value()?)?,
+            "--rate" => args.rate = number(&value()?)?,
+            "--size" => args.size = number(&value()?)?,
+            "--duration" => args.duration = Duration::from_secs_f64(number(&value()?)?),
+            "--pattern" => {
+                args.pattern = match value()?.as_str() {
+                    "steady" => Pattern::Steady,
+                    "burst" => Pattern::Burst,
+                    "mesh" => Pattern::Mesh,
+                    other => return Err(format!("Unknown pattern '{}'", other)),
+                }
+            }
+            _ => return Err(format!("Unknown argument '{}'", arg)),
+        }
+    }
+    if args.session_id.is_none() && !args.host {
+        return Err("--session is required unless --host starts the session".to_string());
+    }
+    if args.clients == 0 || args.rate == 0 {
+        return Err("--clients and --rate must be at least 1".to_string());
+    }
+    if args.size < TAG_SIZE {
+        return Err(format!("--size must be at least {} bytes", TAG_SIZE));
+    }
+    Ok(args)
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid number '{}'", value))
+}
+
+/// Counters shared by every bot and the host
+#[derive(Default)]
+struct Stats {
+    sent: u64,
+    sent_bytes: u64,
+    received: u64,
+    /// Packets sent to a receiver that counts what it gets: our host, or another bot
+    tracked: u64,
+    /// One-way delivery times in microseconds
+    latencies: Vec<u64>,
+    pings: u64,
+    /// Ping round trips in milliseconds
+    rtts: Vec<u64>,
+}
+
+impl Stats {
+    /// Count a bot packet arriving, timing it by the send time in its tag
+    fn record(&mut self, start: Instant, data: &[u8]) {
+        if data.len() < TAG_SIZE {
+            return;
+        }
+        let sent_at = Duration::from_nanos(u64::from_le_bytes(data[8..16].try_into().unwrap()));
+        self.received += 1;
+        self.latencies.push(start.elapsed().saturating_sub(sent_at).as_micros() as u64);
+    }
+}
+
+struct Bot {
+    index: u32,
+    client: NeonClient,
+    sequence: u32,
+    next_send: Instant,
+    next_ping: Instant,
+    next_peer: usize,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("neon-bot failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), std::io::Error> {
+    let session_id = args.session_id.unwrap_or_else(rand::random);
+    let start = Instant::now();
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    let running = Arc::new(AtomicBool::new(true));
+
+    let host_thread = if args.host {
+        let mut host = NeonHost::new(session_id, args.relay.as_str())?;
+        let host_stats = stats.clone();
+        host.on_game_packet(move |_, _, data| host_stats.lock().unwrap().record(start, data));
+        host.register()?;
+        let running = running.clone();
+        Some(thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                host.process_packets()?;
+                sleep(Duration::from_millis(1));
+            }
+            Ok::<(), std::io::Error>(())
+        }))
+    } else {
+        None
+    };
+
+    println!(
+        "neon-bot: {} clients, {:?} pattern, {} packets/s each, {}-byte payloads, {:.1}s against session {} via {}",
+        args.clients,
+        args.pattern,
+        args.rate,
+        args.size,
+        args.duration.as_secs_f64(),
+        session_id,
+        args.relay
+    );
+
+    let mut bots = Vec::with_capacity(args.clients);
+    for index in 0..args.clients as u32 {
+        let mut client = NeonClient::new(format!("bot-{}", index))?;
+        client.set_auto_ping(false);
+        let bot_stats = stats.clone();
+        client.on_game_packet(move |_, _, data| bot_stats.lock().unwrap().record(start, data));
+        let rtt_stats = stats.clone();
+        client.on_pong(move |response_time, _| rtt_stats.lock().unwrap().rtts.push(response_time));
+        match client.connect(session_id, args.relay.as_str()) {
+            Ok(()) => bots.push(Bot {
+                index,
+                client,
+                sequence: 0,
+                next_send: Instant::now(),
+                next_ping: Instant::now(),
+                next_peer: 0,
+            }),
+            Err(e) => eprintln!("bot-{} failed to connect: {}", index, e),
+        }
+    }
+    println!("Connected {}/{} clients in {:.2}s", bots.len(), args.clients, start.elapsed().as_secs_f64());
+    if bots.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "No bot could connect"));
+    }
+
+    let threads = thread::available_parallelism().map_or(4, |n| n.get()).min(bots.len());
+    let mut groups: Vec<Vec<Bot>> = (0..threads).map(|_| Vec::new()).collect();
+    for (i, bot) in bots.into_iter().enumerate() {
+        groups[i % threads].push(bot);
+    }
+
+    let traffic_start = Instant::now();
+    let send_until = traffic_start + args.duration;
+    let handles: Vec<_> = groups
+        .into_iter()
+        .map(|mut group| {
+            let stats = stats.clone();
+            let (rate, size, pattern, track_host) = (args.rate, args.size, args.pattern, args.host);
+            thread::spawn(move || {
+                while Instant::now() < send_until + DRAIN_TIME {
+                    for bot in &mut group {
+                        if Instant::now() < send_until {
+                            drive(bot, &stats, start, rate, size, pattern, track_host)?;
+                        }
+                        bot.client.process_packets()?;
+                    }
+                    sleep(Duration::from_millis(1));
+                }
+                Ok::<(), std::io::Error>(())
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("bot thread panicked")?;
+    }
+
+    running.store(false, Ordering::Relaxed);
+    if let Some(host_thread) = host_thread {
+        host_thread.join().expect("host thread panicked")?;
+    }
+
+    report(&mut stats.lock().unwrap(), args.duration);
+    Ok(())
+}
+
+/// Send whatever this bot owes by now: game packets by the pattern, and a ping every PING_INTERVAL
+fn drive(
+    bot: &mut Bot,
+    stats: &Mutex<Stats>,
+    start: Instant,
+    rate: u32,
+    size: usize,
+    pattern: Pattern,
+    track_host: bool,
+) -> Result<(), std::io::Error> {
+    let now = Instant::now();
+    if now >= bot.next_ping {
+        bot.client.send_ping()?;
+        stats.lock().unwrap().pings += 1;
+        bot.next_ping = now + PING_INTERVAL;
+    }
+    if now < bot.next_send {
+        return Ok(());
+    }
+
+    let (count, interval) = match pattern {
+        Pattern::Burst => (rate, Duration::from_secs(1)),
+        Pattern::Steady | Pattern::Mesh => (1, Duration::from_secs(1) / rate),
+    };
+    let own_id = bot.client.client_id();
+    for _ in 0..count {
+        let mut payload = vec![0u8; size];
+        payload[0..4].copy_from_slice(&bot.index.to_le_bytes());
+        payload[4..8].copy_from_slice(&bot.sequence.to_le_bytes());
+        payload[8..16].copy_from_slice(&(start.elapsed().as_nanos() as u64).to_le_bytes());
+        bot.sequence += 1;
+
+        let target = match pattern {
+            Pattern::Mesh => {
+                let peers: Vec<u16> =
+                    bot.client.peers().iter().map(|peer| peer.client_id).filter(|&id| Some(id) != own_id).collect();
+                if peers.is_empty() {
+                    None
+                } else {
+                    bot.next_peer = (bot.next_peer + 1) % peers.len();
+                    Some(peers[bot.next_peer])
+                }
+            }
+            _ => None,
+        };
+        match target {
+            Some(peer_id) => bot.client.send_game_packet_to(peer_id, BOT_PACKET, &payload)?,
+            None => bot.client.send_game_packet(BOT_PACKET, &payload)?,
+        }
+
+        let mut stats = stats.lock().unwrap();
+        stats.sent += 1;
+        stats.sent_bytes += size as u64;
+        if target.is_some() || track_host {
+            stats.tracked += 1;
+        }
+    }
+    bot.next_send += interval;
+    if bot.next_send < now {
+        // Fell behind; carry on from now rather than bursting to catch up
+        bot.next_send = now + interval;
+    }
+    Ok(())
+}
+
+fn report(stats: &mut Stats, duration: Duration) {
+    let secs = duration.as_secs_f64();
+    println!(
+        "Sent     {:>9} packets ({:.1}/s, {:.1} KB/s)",
+        stats.sent,
+        stats.sent as f64 / secs,
+        stats.sent_bytes as f64 / secs / 1024.0
+    );
+    if stats.tracked == 0 {
+        println!("Received         - (run with --host or --pattern mesh to measure delivery)");
+    } else {
+        let lost = stats.tracked.saturating_sub(stats.received);
+        println!(
+            "Received {:>9} packets ({:.1}/s), loss {:.2}%",
+            stats.received,
+            stats.received as f64 / secs,
+            lost as f64 * 100.0 / stats.tracked as f64
+        );
+        println!("Latency  {} (one-way, us)", distribution(&mut stats.latencies));
+    }
+    let unanswered = stats.pings.saturating_sub(stats.rtts.len() as u64);
+    println!(
+        "RTT      {} (ms), {} pings, {:.2}% unanswered",
+        distribution(&mut stats.rtts),
+        stats.pings,
+        unanswered as f64 * 100.0 / stats.pings.max(1) as f64
+    );
+}
+
+/// Percentiles of `samples` as "p50 .. p90 .. p99 .. max .."
+fn distribution(samples: &mut [u64]) -> String {
+    if samples.is_empty() {
+        return "no samples".to_string();
+    }
+    samples.sort_unstable();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+    format!("p50 {} p90 {} p99 {} max {}", percentile(50), percentile(90), percentile(99), samples[samples.len() - 1])
+}
@@ -0,0 +1,135 @@
+use std::io::Error;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use super::NeonClient;
+use super::outgoing::{DEFAULT_CONNECT_ATTEMPTS, DEFAULT_CONNECT_RETRY_INTERVAL, DEFAULT_PING_INTERVAL};
+use crate::protocol::FeatureSet;
+use crate::rpc::DEFAULT_RPC_TIMEOUT;
+use crate::socket_options::{LocalPort, SocketOptions};
+use crate::transport::Transport;
+
+/// Collects a client's socket and timing settings, then creates it with `build` (or
+/// `build_with_transport`). Anything not set keeps the default `NeonClient::new` uses.
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    name: String,
+    socket_options: SocketOptions,
+    connect_retry_interval: Duration,
+    connect_attempts: u32,
+    auto_ping: bool,
+    ping_interval: Duration,
+    rpc_timeout: Duration,
+    game_identifier: u32,
+    auth_token: Vec<u8>,
+    features: FeatureSet,
+}
+
+impl ClientBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            socket_options: SocketOptions::default(),
+            connect_retry_interval: DEFAULT_CONNECT_RETRY_INTERVAL,
+            connect_attempts: DEFAULT_CONNECT_ATTEMPTS,
+            auto_ping: true,
+            ping_interval: DEFAULT_PING_INTERVAL,
+            rpc_timeout: DEFAULT_RPC_TIMEOUT,
+            game_identifier: 0,
+            auth_token: Vec::new(),
+            features: FeatureSet::empty(),
+        }
+    }
+
+    /// Tune the socket. Replaces any bind address or port set before.
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket_options = options;
+        self
+    }
+
+    /// Bind this local address, e.g. one interface of a multi-homed machine
+    pub fn bind_ip(mut self, ip: IpAddr) -> Self {
+        self.socket_options.local_ip = Some(ip);
+        self
+    }
+
+    /// Bind this local port
+    pub fn port(mut self, port: u16) -> Self {
+        self.socket_options.local_port = LocalPort::Fixed(port);
+        self
+    }
+
+    /// Bind the first free local port in `ports`
+    pub fn port_range(mut self, ports: RangeInclusive<u16>) -> Self {
+        self.socket_options.local_port = LocalPort::Range(*ports.start(), *ports.end());
+        self
+    }
+
+    /// See `NeonClient::set_connect_retry`
+    pub fn connect_retry(mut self, interval: Duration, attempts: u32) -> Self {
+        self.connect_retry_interval = interval;
+        self.connect_attempts = attempts;
+        self
+    }
+
+    /// See `NeonClient::set_auto_ping`
+    pub fn auto_ping(mut self, enabled: bool) -> Self {
+        self.auto_ping = enabled;
+        self
+    }
+
+    /// See `NeonClient::set_ping_interval`
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// See `NeonClient::set_rpc_timeout`
+    pub fn rpc_timeout(mut self, timeout: Duration) -> Self {
+        self.rpc_timeout = timeout;
+        self
+    }
+
+    /// See `NeonClient::set_game_identifier`
+    pub fn game_identifier(mut self, game_identifier: u32) -> Self {
+        self.game_identifier = game_identifier;
+        self
+    }
+
+    /// See `NeonClient::set_auth_token`
+    pub fn auth_token(mut self, token: impl Into<Vec<u8>>) -> Self {
+        self.auth_token = token.into();
+        self
+    }
+
+    /// See `NeonClient::set_supported_features`
+    pub fn features(mut self, features: FeatureSet) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Bind a UDP socket and create the client
+    pub fn build(self) -> Result<NeonClient, Error> {
+        // The relay isn't known yet, so take the family of the chosen local address, else IPv4
+        let family = SocketAddr::new(self.socket_options.local_ip.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)), 0);
+        let socket = self.socket_options.bind_local(&family)?;
+        let options = self.socket_options;
+        let mut client = self.build_with_transport(socket)?;
+        client.socket_options = options;
+        Ok(client)
+    }
+
+    /// Create the client over `transport`. The socket options and bind address aren't used.
+    pub fn build_with_transport<T: Transport>(self, transport: T) -> Result<NeonClient<T>, Error> {
+        let mut client = NeonClient::with_transport(self.name, transport)?;
+        client.set_connect_retry(self.connect_retry_interval, self.connect_attempts);
+        client.set_auto_ping(self.auto_ping);
+        client.set_ping_interval(self.ping_interval);
+        client.set_rpc_timeout(self.rpc_timeout);
+        client.set_game_identifier(self.game_identifier);
+        client.set_auth_token(self.auth_token);
+        client.set_supported_features(self.features);
+        Ok(client)
+    }
+}
@@ -1,101 +1,363 @@
-use std::net::SocketAddr;
+use std::cell::RefCell;
+use std::net::{SocketAddr, UdpSocket};
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
 use super::types::*;
+use crate::name;
+use crate::protocol::{self, ANNOUNCEMENT, Capabilities, CAPABILITIES, CONFIG_VALUE, MAX_DATAGRAM_SIZE, NAME_CHANGE, PROTOCOL_V1, REPLICATION_ACK, REPLICATION_SNAPSHOT, RESERVED_GAME_TYPES, RPC_REQUEST, RPC_RESPONSE, VOICE_FRAME};
+use crate::replication::encode_ack;
+use crate::scheduler::Priority;
+use crate::timesync::elapsed_between;
+use crate::transport::{self, Transport};
+use super::{ConnectionState, NeonClient};
+use super::outgoing::{DEFAULT_MAX_PACKET_SIZE, PIGGYBACK_ACK_DELAY, send_pong};
 
-pub struct NeonSocket {
-    pub socket: std::net::UdpSocket,
+pub struct NeonSocket<T: Transport = UdpSocket> {
+    pub socket: T,
+    pub compression_threshold: Option<usize>,
+    /// Protocol version packets are sent at; v1 until the host accepts a higher one
+    pub version: u8,
+    /// Protocol version of the last datagram received
+    pub last_version: u8,
+    /// Whether the sender of the last packet received wants it acknowledged
+    pub last_reliable: bool,
+    /// Acks waiting to ride on the next game packet to each peer, with when the first was queued.
+    /// Packets go out through shared references, hence the RefCell.
+    queued_acks: RefCell<HashMap<u16, (Instant, Vec<u16>)>>,
+    unbatched: VecDeque<(NeonPacket, SocketAddr)>,
+    /// Datagrams skipped since the last take_malformed because they couldn't be decoded
+    malformed: Vec<(SocketAddr, Error)>,
 }
 
-impl NeonSocket {
-    pub fn new(bind_addr: &str) -> Result<Self, Error> {
-        let socket = std::net::UdpSocket::bind(bind_addr)?;
+impl<T: Transport> NeonSocket<T> {
+    pub fn from_transport(socket: T) -> Result<Self, Error> {
         socket.set_nonblocking(true)?;
-        Ok(Self { socket })
+        socket.disable_connection_reset()?;
+        Ok(Self {
+            socket,
+            compression_threshold: None,
+            version: PROTOCOL_V1,
+            last_version: PROTOCOL_V1,
+            last_reliable: false,
+            queued_acks: RefCell::new(HashMap::new()),
+            unbatched: VecDeque::new(),
+            malformed: Vec::new(),
+        })
     }
 
+    /// Send a packet, carrying any acks queued for its destination if it can
     pub fn send_packet(&self, packet: &NeonPacket, addr: SocketAddr) -> Result<(), Error> {
-    let header = PacketHeader {
-            magic: 0x4E45,
-            version: 1,
-            packet_type: packet.packet_type,
-            sequence: packet.sequence,
-            client_id: packet.client_id,
-            destination_id: packet.destination_id,
+        let acks = if protocol::can_carry_acks(packet, self.version) {
+            self.queued_acks.borrow_mut().remove(&packet.destination_id).map(|(_, acks)| acks).unwrap_or_default()
+        } else {
+            Vec::new()
         };
-        let mut bytes = header.to_bytes();
-        bytes.extend(packet.payload.to_bytes());
+        let bytes = protocol::encode_with_acks(packet, self.version, self.compression_threshold, &acks);
         self.socket.send_to(&bytes, addr)?;
+        // Past what one packet carries; the rest wait for the next
+        if acks.len() > protocol::MAX_PIGGYBACKED_ACKS {
+            self.queued_acks.borrow_mut().insert(packet.destination_id, (Instant::now(), acks[protocol::MAX_PIGGYBACKED_ACKS..].to_vec()));
+        }
         Ok(())
     }
 
-    pub fn receive_packet(&self) -> Result<(NeonPacket, SocketAddr), Error> {
-        let mut buf = [0; 1024];
-    let (size, addr) = self.socket.recv_from(&mut buf)?;
-    let header = PacketHeader::from_bytes(&buf[..8])?;
-    let payload = PacketPayload::from_bytes(header.packet_type, &buf[8..size])?;
-        Ok((NeonPacket {
-            packet_type: header.packet_type,
-            sequence: header.sequence,
-            client_id: header.client_id,
-            destination_id: header.destination_id,
-            payload,
-        }, addr))
+    /// Hold an ack for `destination_id` until a game packet to it can carry it
+    pub fn queue_ack(&self, destination_id: u16, sequence: u16) {
+        self.queued_acks.borrow_mut().entry(destination_id).or_insert_with(|| (Instant::now(), Vec::new())).1.push(sequence);
+    }
+
+    /// Take the acks that have waited at least `delay` for a packet to ride on
+    pub fn take_stale_acks(&self, delay: Duration) -> Vec<(u16, Vec<u16>)> {
+        let mut queued = self.queued_acks.borrow_mut();
+        let stale: Vec<u16> = queued.iter().filter(|(_, (since, _))| since.elapsed() >= delay).map(|(&id, _)| id).collect();
+        stale.into_iter().filter_map(|id| queued.remove(&id).map(|(_, acks)| (id, acks))).collect()
+    }
+
+    /// Receive the next packet, transparently unpacking batches
+    pub fn receive_packet(&mut self) -> Result<(NeonPacket, SocketAddr), Error> {
+        if let Some(queued) = self.unbatched.pop_front() {
+            self.last_reliable = false;
+            return Ok(queued);
+        }
+
+        let mut buf = [0; MAX_DATAGRAM_SIZE + 1];
+        loop {
+            let (size, addr) = transport::recv_skipping_resets(&self.socket, &mut buf)?;
+            match self.decode_datagram(&buf[..size], addr) {
+                // One bad datagram mustn't stop the ones behind it
+                Err(e) if protocol::is_malformed(&e) => self.malformed.push((addr, e)),
+                result => return result,
+            }
+        }
+    }
+
+    /// Take the senders of datagrams receive_packet skipped as malformed, with why
+    pub fn take_malformed(&mut self) -> Vec<(SocketAddr, Error)> {
+        std::mem::take(&mut self.malformed)
+    }
+
+    fn decode_datagram(&mut self, buf: &[u8], addr: SocketAddr) -> Result<(NeonPacket, SocketAddr), Error> {
+        if buf.len() > MAX_DATAGRAM_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Datagram from {} exceeds {} bytes", addr, MAX_DATAGRAM_SIZE)));
+        }
+        let header = protocol::decode_header(buf)?;
+        let packet = protocol::decode(buf)?;
+        packet.check_batch()?;
+        self.last_version = header.version;
+        self.last_reliable = false;
+
+        if let PacketPayload::Batch(packets) = packet.payload {
+            self.unbatched.extend(packets.into_iter().map(|p| (p, addr)));
+            return match self.unbatched.pop_front() {
+                Some(queued) => Ok(queued),
+                None => self.receive_packet(),
+            };
+        }
+        self.last_reliable = header.flags & protocol::FLAG_RELIABLE != 0;
+
+        Ok((packet, addr))
     }
 }
 
-pub fn process_incoming_packets(
-    socket: &NeonSocket,
-    relay_addr: SocketAddr,
-    client_id: u8,
-    on_pong: &mut Option<Box<dyn FnMut(u64, u64) + Send>>,
-    on_session_config: &mut Option<Box<dyn FnMut(u8, u16, u16) + Send>>,
-    on_packet_type_registry: &mut Option<Box<dyn FnMut(Vec<(u8, String, String)>) + Send>>,
-    on_unhandled_packet: &mut Option<Box<dyn FnMut(u8, u8) + Send>>,
-    on_wrong_destination: &mut Option<Box<dyn FnMut(u8, u8) + Send>>,
-) -> Result<(), Error> {
+pub fn process_incoming_packets(client: &mut NeonClient<impl Transport>) -> Result<(), Error> {
+    let (Some(relay_addr), Some(client_id)) = (client.relay_addr, client.client_id) else {
+        return Err(Error::new(ErrorKind::NotConnected, "Client not connected"));
+    };
+
+    // Acks that found no game packet to ride on in time go alone
+    for (destination_id, acks) in client.socket.take_stale_acks(PIGGYBACK_ACK_DELAY) {
+        let sequence = client.outgoing_sequence.advance();
+        send_ack(&client.socket, relay_addr, client_id, destination_id, acks, sequence)?;
+    }
+
     loop {
-        match socket.receive_packet() {
-            Ok((packet, _)) => {
+        let received = client.socket.receive_packet();
+        report_malformed(client);
+        match received {
+            Ok((packet, addr)) => {
+                // Relay notices and resume answers aren't part of any peer's sequence stream
+                match packet.payload {
+                    PacketPayload::Challenge(_) if packet.destination_id == client_id => {
+                        client.request_resume()?;
+                        continue;
+                    }
+                    PacketPayload::ConnectAccept(accept) if packet.destination_id == client_id => {
+                        client.finish_resume(accept)?;
+                        continue;
+                    }
+                    // Only the relay sends these (client 0), naming the host when the whole session closed
+                    PacketPayload::DisconnectNotice(notice) if packet.client_id == 0 && notice.client_id == 1 => {
+                        println!("[Client] Session {} {}", client.session_id.unwrap_or_default(), notice.reason);
+                        client.client_id = None;
+                        client.session_id = None;
+                        client.session_config = None;
+                        client.config_values.clear();
+                        client.resume_requested = None;
+                        client.peers.clear();
+                        client.set_state(ConnectionState::Disconnected);
+                        if let Some(callback) = &mut client.on_session_closed {
+                            callback(notice.reason);
+                        }
+                        return Ok(());
+                    }
+                    PacketPayload::ConnectDeny(deny) if client.resume_requested.is_some() => {
+                        println!("[Client] Host refused to resume the session: {}", deny.reason);
+                        client.client_id = None;
+                        client.session_id = None;
+                        client.session_config = None;
+                        client.resume_requested = None;
+                        client.set_state(ConnectionState::Disconnected);
+                        return Err(Error::new(ErrorKind::ConnectionRefused, deny.reason));
+                    }
+                    _ => {}
+                }
+
                 if packet.destination_id == client_id {
+                    // Acked before the duplicate check: a resend usually means the first Ack was lost.
+                    // Version 1 hosts can't flag packets, so their handshake packets are always acked.
+                    if client.socket.last_reliable
+                        || matches!(packet.payload, PacketPayload::SessionConfig(_) | PacketPayload::PacketTypeRegistry(_))
+                    {
+                        if client.piggyback && protocol::has_flags_byte(client.socket.version) {
+                            client.socket.queue_ack(packet.client_id, packet.sequence);
+                        } else {
+                            let sequence = client.outgoing_sequence.advance();
+                            send_ack(&client.socket, relay_addr, client_id, packet.client_id, vec![packet.sequence], sequence)?;
+                        }
+                    }
+                    if !client.incoming_sequences.record(packet.client_id, packet.sequence).should_accept() {
+                        continue;
+                    }
+
                     match packet.payload {
+                        // The host pings to measure round trips and check the client is still there
+                        PacketPayload::Ping(ping) => {
+                            let sequence = client.outgoing_sequence.advance();
+                            send_pong(&client.socket, relay_addr, client_id, packet.client_id, ping.timestamp, sequence)?;
+                        }
                         PacketPayload::Pong(pong) => {
-                            let pong_time = std::time::SystemTime::now()
-                                .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                                .unwrap()
-                                .as_millis() as u64;
-                            let response_time = pong_time - pong.original_timestamp;
-                            
-                            if let Some(callback) = on_pong {
-                                callback(response_time, pong_time);
+                            // Pongs echo the ping's id; anything not awaited is a duplicate or came too late
+                            let Some(index) = client.pings_in_flight.iter().position(|&(id, _)| id == pong.original_timestamp) else {
+                                continue;
+                            };
+                            // Pings sent before this one and still unanswered are taken as lost
+                            let (_, sent_at) = client.pings_in_flight.drain(..=index).next_back().unwrap();
+                            let rtt = sent_at.elapsed();
+                            client.rtt.add_sample(rtt);
+                            if let Some(controller) = &mut client.congestion {
+                                controller.on_rtt_sample(rtt);
+                            }
+
+                            if let Some(callback) = &mut client.on_pong {
+                                callback(rtt.as_millis() as u64, client.local_clock.now_millis());
                             }
                         }
                         PacketPayload::SessionConfig(config) => {
-                            send_ack(socket, relay_addr, client_id, packet.sequence)?;
+                            // Start conservatively and probe the host path up to the advertised limit
+                            client.advertised_packet_size = (config.max_packet_size as usize).min(MAX_DATAGRAM_SIZE);
+                            client.max_packet_size = client.advertised_packet_size.min(DEFAULT_MAX_PACKET_SIZE);
+                            client.host_packet_size = client.max_packet_size;
+                            client.session_config = Some(config.clone());
+                            if let Some(batcher) = &mut client.batcher {
+                                batcher.set_max_packet_size(client.max_packet_size);
+                            }
+
+                            client.probe_mtu()?;
+                            let configured = client.state == ConnectionState::AwaitingConfig;
+                            if configured {
+                                client.set_state(ConnectionState::Connected);
+                            }
 
-                            if let Some(callback) = on_session_config {
+                            if let Some(callback) = &mut client.on_session_config {
                                 callback(config.version, config.tick_rate, config.max_packet_size);
                             }
+                            if configured
+                                && let Some(callback) = &mut client.on_connected
+                            {
+                                callback(client_id, client.session_id.unwrap_or_default());
+                            }
+                            // What follows waits for the next call, so connect returns before any of it
+                            // fires callbacks the caller hasn't set yet
+                            if configured {
+                                return Ok(());
+                            }
                         }
                         PacketPayload::PacketTypeRegistry(registry) => {
+                            check_schema(client, &registry);
+
                             let entries: Vec<(u8, String, String)> = registry.entries
                                 .into_iter()
                                 .map(|e| (e.packet_id, e.name, e.description))
                                 .collect();
                             
-                            if let Some(callback) = on_packet_type_registry {
+                            if let Some(callback) = &mut client.on_packet_type_registry {
                                 callback(entries);
                             }
                         }
+                        PacketPayload::MtuProbe(probe) => {
+                            let size = probe.probe_size as usize;
+                            if size > client.host_packet_size && size <= client.advertised_packet_size {
+                                client.host_packet_size = size;
+                            }
+                        }
+                        PacketPayload::TimeSync(sync) => {
+                            let received = client.local_clock.now();
+                            client.time_sync.add_sample(sync.client_time, sync.host_time, received);
+                            if let Some(controller) = &mut client.congestion {
+                                controller.on_rtt_sample(Duration::from_micros(elapsed_between(sync.client_time, received)));
+                            }
+                        }
+                        PacketPayload::PeerList(list) => {
+                            update_peers(client, list.peers, client_id);
+                        }
+                        PacketPayload::Chat(chat) if client.on_chat_message.is_some() => {
+                            if let Some(callback) = &mut client.on_chat_message {
+                                callback(packet.client_id, name::strip(&chat.sender_name), chat.text);
+                            }
+                        }
+                        PacketPayload::GamePacket(data) if packet.packet_type == CAPABILITIES && packet.client_id == 1 => {
+                            let Some(&bits) = data.first() else { continue };
+                            client.capabilities = Capabilities::from_bits_truncate(bits);
+                            if let Some(callback) = &mut client.on_capabilities_changed {
+                                callback(client.capabilities);
+                            }
+                        }
+                        PacketPayload::GamePacket(data) if packet.packet_type == ANNOUNCEMENT && packet.client_id == 1 => {
+                            let text = String::from_utf8_lossy(&data).into_owned();
+                            println!("[Client] Announcement: {}", text);
+                            if let Some(callback) = &mut client.on_announcement {
+                                callback(text);
+                            }
+                        }
+                        PacketPayload::GamePacket(data) if packet.packet_type == CONFIG_VALUE && packet.client_id == 1 => {
+                            let Some((key, value)) = skip_malformed(client, addr, protocol::decode_config_value(&data))? else { continue };
+                            match &value {
+                                Some(value) => client.config_values.insert(key.clone(), value.clone()),
+                                None => client.config_values.remove(&key),
+                            };
+                            if let Some(callback) = &mut client.on_config_changed {
+                                callback(&key, value.as_deref());
+                            }
+                        }
+                        PacketPayload::GamePacket(data) if packet.packet_type == NAME_CHANGE && packet.client_id == 1 => {
+                            let Some(answer) = skip_malformed(client, addr, protocol::decode_name_answer(&data))? else { continue };
+                            match answer {
+                                Ok(granted) => {
+                                    let name = name::strip(&granted);
+                                    client.name = name.clone();
+                                    if let Some(callback) = &mut client.on_name_changed {
+                                        callback(name);
+                                    }
+                                }
+                                Err(reason) => {
+                                    println!("[Client] Host refused name change: {}", reason);
+                                    if let Some(callback) = &mut client.on_name_change_denied {
+                                        callback(reason);
+                                    }
+                                }
+                            }
+                        }
+                        PacketPayload::GamePacket(data) if packet.packet_type == REPLICATION_SNAPSHOT => {
+                            let received = receive_snapshot(client, &data);
+                            skip_malformed(client, addr, received)?;
+                        }
+                        PacketPayload::GamePacket(data) if packet.packet_type == RPC_REQUEST => {
+                            let response = client.rpc.handle_request(packet.client_id, &data);
+                            if let Some(Some(response)) = skip_malformed(client, addr, response)? {
+                                client.send_game_data(packet.client_id, RPC_RESPONSE, &response, Priority::Normal)?;
+                            }
+                        }
+                        PacketPayload::GamePacket(data) if packet.packet_type == RPC_RESPONSE => {
+                            let handled = client.rpc.handle_response(packet.client_id, &data);
+                            skip_malformed(client, addr, handled)?;
+                        }
+                        PacketPayload::GamePacket(data) if packet.packet_type == VOICE_FRAME => {
+                            let frame = client.voice.receive(packet.client_id, &data);
+                            if let Some(frame) = skip_malformed(client, addr, frame)?
+                                && let Some(callback) = &mut client.on_voice_frame
+                            {
+                                callback(packet.client_id, frame.sequence, &frame.data);
+                            }
+                        }
+                        PacketPayload::GamePacket(data) if client.messages.handles(packet.packet_type) => {
+                            let dispatched = client.messages.dispatch(packet.packet_type, packet.client_id, &data);
+                            skip_malformed(client, addr, dispatched)?;
+                        }
+                        // Reserved types this end has no use for never reach the game
+                        PacketPayload::GamePacket(data) if client.on_game_packet.is_some() && !RESERVED_GAME_TYPES.contains(&packet.packet_type) => {
+                            if let Some(callback) = &mut client.on_game_packet {
+                                callback(packet.packet_type, packet.client_id, &data);
+                            }
+                        }
                         _ => {
-                            if let Some(callback) = on_unhandled_packet {
+                            if let Some(callback) = &mut client.on_unhandled_packet {
                                 callback(packet.packet_type, packet.client_id);
                             }
                         }
                     }
-                } else {
-                    if let Some(callback) = on_wrong_destination {
-                        callback(client_id, packet.destination_id);
-                    }
+                } else if let Some(callback) = &mut client.on_wrong_destination {
+                    callback(client_id, packet.destination_id);
                 }
             }
             Err(e) if e.kind() == ErrorKind::WouldBlock => {
@@ -107,20 +369,113 @@ pub fn process_incoming_packets(
     Ok(())
 }
 
+/// Log, count and report the datagrams the socket skipped as malformed
+pub fn report_malformed(client: &mut NeonClient<impl Transport>) {
+    for (addr, e) in client.socket.take_malformed() {
+        drop_malformed(client, addr, e);
+    }
+}
+
+/// Pass on the result of handling a packet, unless its payload didn't decode: that packet is
+/// dropped and reported like a malformed datagram, and None returned
+fn skip_malformed<T>(client: &mut NeonClient<impl Transport>, addr: SocketAddr, result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if protocol::is_malformed(&e) => {
+            drop_malformed(client, addr, e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn drop_malformed(client: &mut NeonClient<impl Transport>, addr: SocketAddr, e: Error) {
+    println!("[Client] Dropped malformed datagram from {}: {}", addr, e);
+    client.malformed_packets += 1;
+    if let Some(callback) = &mut client.on_malformed_packet {
+        callback(addr, &e);
+    }
+}
+
 fn send_ack(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    client_id: u8,
+    client_id: u16,
+    destination_id: u16,
+    acknowledged_sequences: Vec<u16>,
     sequence: u16,
 ) -> Result<(), Error> {
     let ack_packet = NeonPacket {
         packet_type: PacketType::Ack as u8,
-        sequence: 0,
+        sequence,
         client_id,
-        destination_id: 1,
-        payload: PacketPayload::Ack(Ack {
-            acknowledged_sequences: vec![sequence],
-        }),
+        destination_id,
+        payload: PacketPayload::Ack(Ack { acknowledged_sequences }),
     };
     socket.send_packet(&ack_packet, relay_addr)
-}
\ No newline at end of file
+}
+
+/// Apply a snapshot part; once a whole snapshot lands, acknowledge it to the host and report it
+fn receive_snapshot(client: &mut NeonClient<impl Transport>, data: &[u8]) -> Result<(), Error> {
+    let Some(tick) = client.replication.receive(data)? else {
+        return Ok(());
+    };
+    client.send_game_data(1, REPLICATION_ACK, &encode_ack(tick), Priority::Normal)?;
+
+    if let Some(callback) = &mut client.on_snapshot {
+        callback(tick, client.replication.entities());
+    }
+    Ok(())
+}
+
+/// Replace the roster and report who joined, left and was renamed since the last one
+fn update_peers(client: &mut NeonClient<impl Transport>, mut peers: Vec<PeerInfo>, own_id: u16) {
+    peers.retain(|peer| peer.client_id != own_id);
+    for peer in &mut peers {
+        peer.name = name::strip(&peer.name);
+    }
+    let previous = std::mem::replace(&mut client.peers, peers);
+    let find = |peers: &[PeerInfo], client_id: u16| peers.iter().position(|peer| peer.client_id == client_id);
+
+    for peer in previous.iter().filter(|old| find(&client.peers, old.client_id).is_none()) {
+        client.voice.forget(peer.client_id);
+        if let Some(callback) = &mut client.on_peer_left {
+            callback(peer.client_id, peer.name.clone());
+        }
+    }
+    for peer in &client.peers {
+        match find(&previous, peer.client_id) {
+            None => {
+                // The ID may have been someone else's, whose numbering the newcomer doesn't continue
+                client.incoming_sequences.reset(peer.client_id);
+                if let Some(callback) = &mut client.on_peer_joined {
+                    callback(peer.client_id, peer.name.clone());
+                }
+            }
+            Some(old) if previous[old].name != peer.name => {
+                if let Some(callback) = &mut client.on_peer_renamed {
+                    callback(peer.client_id, previous[old].name.clone(), peer.name.clone());
+                }
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Compare the host's registry with the types the client declared and report any difference
+fn check_schema(client: &mut NeonClient<impl Transport>, registry: &PacketTypeRegistry) {
+    if client.expected_packet_types.is_empty() {
+        return;
+    }
+
+    let registered: BTreeSet<u8> = registry.entries.iter().map(|e| e.packet_id).collect();
+    let unknown: Vec<u8> = registered.difference(&client.expected_packet_types).copied().collect();
+    let missing: Vec<u8> = client.expected_packet_types.difference(&registered).copied().collect();
+    if unknown.is_empty() && missing.is_empty() {
+        return;
+    }
+
+    if let Some(callback) = &mut client.on_schema_mismatch {
+        callback(unknown, missing);
+    }
+}
@@ -1,60 +1,300 @@
 pub mod types;
+mod builder;
 mod incoming;
 mod outgoing;
+mod sans_io;
+mod sender;
+mod worker;
 
-use std::net::SocketAddr;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
 use std::io::{Error, ErrorKind};
 use std::time::{Instant, Duration};
 use std::thread::sleep;
 
-pub use types::{PacketPayload, NeonPacket};
-use incoming::{NeonSocket, process_incoming_packets};
+pub use types::{PacketPayload, NeonPacket, PeerInfo};
+pub use sans_io::{ClientEvent, ClientProtocol};
+pub use sender::ClientSender;
+pub use builder::ClientBuilder;
+use sender::QueuedSend;
+pub use worker::ClientWorker;
+use types::{ConnectAccept, ConnectRequest, SessionConfig, SessionRedirect};
+use incoming::{NeonSocket, process_incoming_packets, report_malformed};
 use outgoing::*;
+use crate::bandwidth::{BandwidthLimiter, CongestionController, DEFAULT_MAX_RATE};
+use crate::directory;
+use crate::join_code;
+use crate::name;
+use crate::message::MessageHandlers;
+#[cfg(feature = "serde")]
+use crate::message::{Message, encode_message};
+use crate::protocol::{self, Capabilities, DisconnectReason, FeatureSet, NAME_CHANGE, PeerRole, PROTOCOL_V1, PROTOCOL_VERSION, RESERVED_GAME_TYPES, is_v1, RPC_REQUEST, VOICE_FRAME};
+use crate::replication::ReplicationClient;
+use crate::resolve::resolve_addrs;
+use crate::rpc::RpcEndpoint;
+use crate::scheduler::{OutgoingScheduler, Priority};
+use crate::timesync::{ClockEstimator, LocalClock, RttEstimator};
+use crate::sequence::{PeerSequences, SequenceCounter, SequenceStats};
+use crate::socket_options::SocketOptions;
+use crate::transport::Transport;
+use crate::voice::{JitterBuffer, VoiceChannel, VoiceFrame};
 
-pub type PongCallback = Box<dyn FnMut(u64, u64) + Send>; // (response_time_ms, timestamp)
+pub type ConnectedCallback = Box<dyn FnMut(u16, u32) + Send>; // (client_id, session_id)
+pub type ConnectFailedCallback = Box<dyn FnMut(&Error) + Send>; // (error)
+pub type PongCallback = Box<dyn FnMut(u64, u64) + Send>; // (response_time_ms, connection_time_ms)
 pub type SessionConfigCallback = Box<dyn FnMut(u8, u16, u16) + Send>; // (version, tick_rate, max_packet_size)
 pub type PacketTypeRegistryCallback = Box<dyn FnMut(Vec<(u8, String, String)>) + Send>; // Vec<(id, name, description)>
-pub type UnhandledPacketCallback = Box<dyn FnMut(u8, u8) + Send>; // (packet_type, from_client_id)
-pub type WrongDestinationCallback = Box<dyn FnMut(u8, u8) + Send>; // (my_id, packet_destination_id)
+pub type UnhandledPacketCallback = Box<dyn FnMut(u8, u16) + Send>; // (packet_type, from_client_id)
+pub type MalformedPacketCallback = Box<dyn FnMut(SocketAddr, &Error) + Send>; // (from, error)
+pub type WrongDestinationCallback = Box<dyn FnMut(u16, u16) + Send>; // (my_id, packet_destination_id)
+pub type GamePacketCallback = Box<dyn FnMut(u8, u16, &[u8]) + Send>; // (packet_type, from_client_id, data)
+pub type ChatMessageCallback = Box<dyn FnMut(u16, String, String) + Send>; // (from_client_id, name, text)
+pub type PeerJoinedCallback = Box<dyn FnMut(u16, String) + Send>; // (client_id, name)
+pub type PeerLeftCallback = Box<dyn FnMut(u16, String) + Send>; // (client_id, name)
+pub type PeerRenamedCallback = Box<dyn FnMut(u16, String, String) + Send>; // (client_id, old_name, new_name)
+pub type NameChangedCallback = Box<dyn FnMut(String) + Send>; // (name)
+pub type NameChangeDeniedCallback = Box<dyn FnMut(String) + Send>; // (reason)
+pub type AnnouncementCallback = Box<dyn FnMut(String) + Send>; // (text)
+pub type SessionClosedCallback = Box<dyn FnMut(DisconnectReason) + Send>; // (reason)
+pub type CapabilitiesChangedCallback = Box<dyn FnMut(Capabilities) + Send>; // (capabilities)
+pub type SchemaMismatchCallback = Box<dyn FnMut(Vec<u8>, Vec<u8>) + Send>; // (unknown_ids, missing_ids)
+pub type SnapshotCallback = Box<dyn FnMut(u64, &HashMap<u32, Vec<u8>>) + Send>; // (tick, entities)
+pub type VoiceFrameCallback = Box<dyn FnMut(u16, u32, &[u8]) + Send>; // (from_client_id, sequence, frame)
+pub type BudgetExceededCallback = Box<dyn FnMut(usize, u32) + Send>; // (queued_packets, bytes_per_second)
+pub type StateChangedCallback = Box<dyn FnMut(ConnectionState, ConnectionState) + Send>; // (old, new)
+pub type ConfigChangedCallback = Box<dyn FnMut(&str, Option<&[u8]>) + Send>; // (key, value, None if removed)
 
-pub struct NeonClient {
-    socket: NeonSocket,
+/// Where a client is in joining a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    /// Waiting on the relay and host to answer a connection request
+    Connecting,
+    /// Accepted by the host, waiting on its SessionConfig
+    AwaitingConfig,
+    Connected,
+    /// Flushing what is queued before leaving the session (see disconnect)
+    Disconnecting,
+}
+
+/// A connection attempt in progress, advanced by process_packets
+struct PendingConnect {
+    session_id: u32,
+    /// Resolved relay addresses not tried yet
+    candidates: VecDeque<SocketAddr>,
+    /// The resolved address being tried, before any redirects
+    candidate: SocketAddr,
+    /// Whether more than one address resolved, in which case failures are collected
+    several: bool,
+    failures: Vec<String>,
+    redirects: usize,
+    /// Requests sent to the current relay address, and their sequence numbers
+    attempts: u32,
+    requests: Vec<u16>,
+    /// Whether the relay acknowledged a request, i.e. it is up and has passed the request to the host
+    relay_answered: bool,
+    sent_at: Instant,
+}
+
+pub struct NeonClient<T: Transport = UdpSocket> {
+    socket: NeonSocket<T>,
+    /// Reapplied when the socket is reopened for a relay of the other address family
+    socket_options: SocketOptions,
     relay_addr: Option<SocketAddr>,
-    client_id: Option<u8>,
+    client_id: Option<u16>,
     session_id: Option<u32>,
     name: String,
+    auth_token: Vec<u8>,
+    game_identifier: u32,
+    supported_features: FeatureSet,
+    role: PeerRole,
+    /// What the host lets this client do
+    capabilities: Capabilities,
+    resume_token: u64,
+    resume_requested: Option<Instant>,
+    connecting: Option<PendingConnect>,
+    state: ConnectionState,
+    connect_retry_interval: Duration,
+    connect_attempts: u32,
     auto_ping: bool,
     ping_interval: Duration,
     last_ping: Option<Instant>,
+    next_ping_id: u64,
+    /// Id and send time of each ping awaiting a pong
+    pings_in_flight: VecDeque<(u64, Instant)>,
+    rtt: RttEstimator,
+    piggyback: bool,
+    /// When a game packet last went to the host, which with piggybacking on stands in for a ping
+    last_host_send: Option<Instant>,
+    outgoing_sequence: SequenceCounter,
+    incoming_sequences: PeerSequences,
+    malformed_packets: u64,
+    batcher: Option<PacketBatcher>,
+    max_packet_size: usize,
+    advertised_packet_size: usize,
+    host_packet_size: usize,
+    session_config: Option<SessionConfig>,
+    /// The session's key/value config, as the host last sent it
+    config_values: BTreeMap<String, Vec<u8>>,
+    expected_packet_types: BTreeSet<u8>,
+    peers: Vec<PeerInfo>,
+    local_clock: LocalClock,
+    time_sync: ClockEstimator,
+    replication: ReplicationClient,
+    rpc: RpcEndpoint,
+    voice: VoiceChannel,
+    scheduler: OutgoingScheduler<NeonPacket>,
+    bandwidth: Option<BandwidthLimiter>,
+    bandwidth_limit: Option<u32>,
+    congestion: Option<CongestionController>,
+    throttled: bool,
+    sender: ClientSender,
+    queued_sends: Receiver<QueuedSend>,
     
+    on_connected: Option<ConnectedCallback>,
+    on_connect_failed: Option<ConnectFailedCallback>,
     on_pong: Option<PongCallback>,
     on_session_config: Option<SessionConfigCallback>,
     on_packet_type_registry: Option<PacketTypeRegistryCallback>,
     on_unhandled_packet: Option<UnhandledPacketCallback>,
+    on_malformed_packet: Option<MalformedPacketCallback>,
     on_wrong_destination: Option<WrongDestinationCallback>,
+    on_game_packet: Option<GamePacketCallback>,
+    on_schema_mismatch: Option<SchemaMismatchCallback>,
+    on_chat_message: Option<ChatMessageCallback>,
+    on_peer_joined: Option<PeerJoinedCallback>,
+    on_peer_left: Option<PeerLeftCallback>,
+    on_peer_renamed: Option<PeerRenamedCallback>,
+    on_name_changed: Option<NameChangedCallback>,
+    on_name_change_denied: Option<NameChangeDeniedCallback>,
+    on_announcement: Option<AnnouncementCallback>,
+    on_session_closed: Option<SessionClosedCallback>,
+    on_capabilities_changed: Option<CapabilitiesChangedCallback>,
+    on_snapshot: Option<SnapshotCallback>,
+    on_voice_frame: Option<VoiceFrameCallback>,
+    on_budget_exceeded: Option<BudgetExceededCallback>,
+    on_state_changed: Option<StateChangedCallback>,
+    on_config_changed: Option<ConfigChangedCallback>,
+    messages: MessageHandlers,
 }
 
 impl NeonClient {
     /// Create a new client with a name
     pub fn new(name: String) -> Result<Self, Error> {
+        Self::builder(name).build()
+    }
+
+    /// Create a new client whose socket is tuned with `options`
+    pub fn with_socket_options(name: String, options: SocketOptions) -> Result<Self, Error> {
+        Self::builder(name).socket_options(options).build()
+    }
+
+    /// Start building a client with a name, to set its socket and timings before it is created
+    pub fn builder(name: impl Into<String>) -> ClientBuilder {
+        ClientBuilder::new(name)
+    }
+}
+
+impl<T: Transport> NeonClient<T> {
+    /// Create a new client that talks over the given transport instead of a UDP socket
+    pub fn with_transport(name: String, transport: T) -> Result<Self, Error> {
+        let (queue, queued_sends) = mpsc::channel();
         Ok(Self {
-            socket: NeonSocket::new("0.0.0.0:0")?,
+            socket: NeonSocket::from_transport(transport)?,
+            socket_options: SocketOptions::default(),
             relay_addr: None,
             client_id: None,
             session_id: None,
             name,
+            auth_token: Vec::new(),
+            game_identifier: 0,
+            supported_features: FeatureSet::empty(),
+            role: PeerRole::Player,
+            capabilities: Capabilities::for_role(PeerRole::Player),
+            resume_token: 0,
+            resume_requested: None,
+            connecting: None,
+            state: ConnectionState::Disconnected,
+            connect_retry_interval: DEFAULT_CONNECT_RETRY_INTERVAL,
+            connect_attempts: DEFAULT_CONNECT_ATTEMPTS,
             auto_ping: true,
-            ping_interval: Duration::from_secs(5),
+            ping_interval: DEFAULT_PING_INTERVAL,
             last_ping: None,
+            next_ping_id: 0,
+            pings_in_flight: VecDeque::new(),
+            rtt: RttEstimator::new(),
+            piggyback: false,
+            last_host_send: None,
+            outgoing_sequence: SequenceCounter::new(),
+            incoming_sequences: PeerSequences::new(),
+            malformed_packets: 0,
+            batcher: None,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            advertised_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            host_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            session_config: None,
+            config_values: BTreeMap::new(),
+            expected_packet_types: BTreeSet::new(),
+            peers: Vec::new(),
+            local_clock: LocalClock::new(),
+            time_sync: ClockEstimator::new(),
+            replication: ReplicationClient::new(),
+            rpc: RpcEndpoint::new(),
+            voice: VoiceChannel::new(),
+            scheduler: OutgoingScheduler::new(),
+            bandwidth: None,
+            bandwidth_limit: None,
+            congestion: None,
+            throttled: false,
+            sender: ClientSender::new(queue),
+            queued_sends,
+            on_connected: None,
+            on_connect_failed: None,
             on_pong: None,
             on_session_config: None,
             on_packet_type_registry: None,
             on_unhandled_packet: None,
+            on_malformed_packet: None,
             on_wrong_destination: None,
+            on_game_packet: None,
+            on_schema_mismatch: None,
+            on_chat_message: None,
+            on_peer_joined: None,
+            on_peer_left: None,
+            on_peer_renamed: None,
+            on_name_changed: None,
+            on_name_change_denied: None,
+            on_announcement: None,
+            on_session_closed: None,
+            on_capabilities_changed: None,
+            on_snapshot: None,
+            on_voice_frame: None,
+            on_budget_exceeded: None,
+            on_state_changed: None,
+            on_config_changed: None,
+            messages: MessageHandlers::new(),
         })
     }
 
-    /// Set callback for when a pong is received
+    /// Set callback for when a connection attempt succeeds: the host accepted the client and its
+    /// SessionConfig has arrived, so game packets can be sent from the callback
+    pub fn on_connected<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, u32) + Send + 'static,
+    {
+        self.on_connected = Some(Box::new(callback));
+    }
+
+    /// Set callback for when a connection attempt fails, e.g. because the host denied it or no relay answered
+    pub fn on_connect_failed<F>(&mut self, callback: F)
+    where
+        F: FnMut(&Error) + Send + 'static,
+    {
+        self.on_connect_failed = Some(Box::new(callback));
+    }
+
+    /// Set callback for when a pong is received, with the round trip and the time since connecting, in milliseconds
     pub fn on_pong<F>(&mut self, callback: F)
     where
         F: FnMut(u64, u64) + Send + 'static,
@@ -81,19 +321,242 @@ impl NeonClient {
     /// Set callback for unhandled packets
     pub fn on_unhandled_packet<F>(&mut self, callback: F)
     where
-        F: FnMut(u8, u8) + Send + 'static,
+        F: FnMut(u8, u16) + Send + 'static,
     {
         self.on_unhandled_packet = Some(Box::new(callback));
     }
 
+    /// Set callback for datagrams dropped because they couldn't be decoded, with why
+    pub fn on_malformed_packet<F>(&mut self, callback: F)
+    where
+        F: FnMut(SocketAddr, &Error) + Send + 'static,
+    {
+        self.on_malformed_packet = Some(Box::new(callback));
+    }
+
     /// Set callback for packets sent to wrong destination
     pub fn on_wrong_destination<F>(&mut self, callback: F)
     where
-        F: FnMut(u8, u8) + Send + 'static,
+        F: FnMut(u16, u16) + Send + 'static,
     {
         self.on_wrong_destination = Some(Box::new(callback));
     }
 
+    /// Set callback for game packets (types 0x10+)
+    pub fn on_game_packet<F>(&mut self, callback: F)
+    where
+        F: FnMut(u8, u16, &[u8]) + Send + 'static,
+    {
+        self.on_game_packet = Some(Box::new(callback));
+    }
+
+    /// Set callback for when the host's packet type registry disagrees with the expected types
+    /// (see expect_packet_types). Receives the IDs the host sent that this client doesn't know,
+    /// then the expected IDs the host didn't send.
+    pub fn on_schema_mismatch<F>(&mut self, callback: F)
+    where
+        F: FnMut(Vec<u8>, Vec<u8>) + Send + 'static,
+    {
+        self.on_schema_mismatch = Some(Box::new(callback));
+    }
+
+    /// Set callback for chat messages from the host or other clients
+    pub fn on_chat_message<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, String, String) + Send + 'static,
+    {
+        self.on_chat_message = Some(Box::new(callback));
+    }
+
+    /// Set callback for when another client joins the session.
+    /// Also fires for everyone already there when the first peer list arrives.
+    pub fn on_peer_joined<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, String) + Send + 'static,
+    {
+        self.on_peer_joined = Some(Box::new(callback));
+    }
+
+    /// Set callback for when another client leaves the session
+    pub fn on_peer_left<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, String) + Send + 'static,
+    {
+        self.on_peer_left = Some(Box::new(callback));
+    }
+
+    /// Set callback for when another client in the session changes its name
+    pub fn on_peer_renamed<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, String, String) + Send + 'static,
+    {
+        self.on_peer_renamed = Some(Box::new(callback));
+    }
+
+    /// Set callback for when the host gives this client a name: one it asked for with request_name_change,
+    /// or a numbered one in place of a taken name it joined with. name() returns it from then on.
+    pub fn on_name_changed<F>(&mut self, callback: F)
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        self.on_name_changed = Some(Box::new(callback));
+    }
+
+    /// Set callback for when the host refuses a request_name_change
+    pub fn on_name_change_denied<F>(&mut self, callback: F)
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        self.on_name_change_denied = Some(Box::new(callback));
+    }
+
+    /// Set callback for each entry of the session's key/value config the host sends: every entry on
+    /// joining, then each change. The value is None when the host removed the key.
+    pub fn on_config_changed<F>(&mut self, callback: F)
+    where
+        F: FnMut(&str, Option<&[u8]>) + Send + 'static,
+    {
+        self.on_config_changed = Some(Box::new(callback));
+    }
+
+    /// Set callback for the host's announcements, including its message of the day on joining
+    pub fn on_announcement<F>(&mut self, callback: F)
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        self.on_announcement = Some(Box::new(callback));
+    }
+
+    /// Set callback for each change of state(), with the state left and the one entered
+    pub fn on_state_changed<F>(&mut self, callback: F)
+    where
+        F: FnMut(ConnectionState, ConnectionState) + Send + 'static,
+    {
+        self.on_state_changed = Some(Box::new(callback));
+    }
+
+    /// Set callback for when the host or relay closes the session, e.g. with close_session or for reaching
+    /// a relay time limit. The client is disconnected by the time it runs.
+    pub fn on_session_closed<F>(&mut self, callback: F)
+    where
+        F: FnMut(DisconnectReason) + Send + 'static,
+    {
+        self.on_session_closed = Some(Box::new(callback));
+    }
+
+    /// Set callback for when the host changes what this client may do, e.g. to grey out chat
+    pub fn on_capabilities_changed<F>(&mut self, callback: F)
+    where
+        F: FnMut(Capabilities) + Send + 'static,
+    {
+        self.on_capabilities_changed = Some(Box::new(callback));
+    }
+
+    /// Set callback for each replicated snapshot applied, with the full entity state as of that tick.
+    /// The first one after joining carries every entity.
+    pub fn on_snapshot<F>(&mut self, callback: F)
+    where
+        F: FnMut(u64, &HashMap<u32, Vec<u8>>) + Send + 'static,
+    {
+        self.on_snapshot = Some(Box::new(callback));
+    }
+
+    /// Set callback for each voice frame as it arrives, before any jitter buffering
+    pub fn on_voice_frame<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, u32, &[u8]) + Send + 'static,
+    {
+        self.on_voice_frame = Some(Box::new(callback));
+    }
+
+    /// Queue incoming voice in a jitter buffer per peer, created by `factory`; read it back
+    /// with next_voice_frame. e.g. `set_jitter_buffer(|_| Box::new(FixedJitterBuffer::new(3)))`
+    pub fn set_jitter_buffer<F>(&mut self, factory: F)
+    where
+        F: FnMut(u16) -> Box<dyn JitterBuffer> + Send + 'static,
+    {
+        self.voice.set_jitter_buffer(Box::new(factory));
+    }
+
+    /// Next buffered voice frame to play for a peer, called once per frame period.
+    /// None means silence or a lost frame to conceal.
+    pub fn next_voice_frame(&mut self, peer_id: u16) -> Option<VoiceFrame> {
+        self.voice.pop(peer_id)
+    }
+
+    /// Declare the game packet types (0x10+) this client understands, to be checked against
+    /// the registry the host sends on connect
+    pub fn expect_packet_types(&mut self, packet_ids: &[u8]) -> Result<(), Error> {
+        if let Some(id) = packet_ids.iter().find(|&&id| id < 0x10) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Packet type 0x{:02X} is in the reserved core range (0x00-0x0F)", id),
+            ));
+        }
+        if let Some(id) = packet_ids.iter().find(|id| RESERVED_GAME_TYPES.contains(id)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Packet type 0x{:02X} is reserved for built-in subsystems (0xF0-0xFF)", id),
+            ));
+        }
+        self.expected_packet_types.extend(packet_ids);
+        Ok(())
+    }
+
+    /// Set a typed callback for one message type (requires the `serde` feature).
+    /// Takes priority over on_game_packet for that packet type.
+    #[cfg(feature = "serde")]
+    pub fn on_message<M, F>(&mut self, callback: F) -> Result<(), Error>
+    where
+        M: Message,
+        F: FnMut(u16, M) + Send + 'static,
+    {
+        self.messages.register(callback)
+    }
+
+    /// Handle calls to an RPC method. The handler gets the caller's ID and argument bytes and
+    /// returns reply bytes, or an error message passed to the caller.
+    pub fn register_rpc<F>(&mut self, method: &str, handler: F) -> Result<(), Error>
+    where
+        F: FnMut(u16, &[u8]) -> Result<Vec<u8>, String> + Send + 'static,
+    {
+        self.rpc.register(method, Box::new(handler))
+    }
+
+    /// Set how long rpc_with_reply waits before failing with TimedOut (default: 5 seconds)
+    pub fn set_rpc_timeout(&mut self, timeout: Duration) {
+        self.rpc.set_timeout(timeout);
+    }
+
+    /// Set the token sent with connection requests, checked by the host's or relay's Authenticator (empty sends none)
+    pub fn set_auth_token(&mut self, token: impl Into<Vec<u8>>) {
+        self.auth_token = token.into();
+    }
+
+    /// Set the game sent with connection requests, for relays that only serve particular games and
+    /// hosts that check it (default: 0, unset)
+    pub fn set_game_identifier(&mut self, game_identifier: u32) {
+        self.game_identifier = game_identifier;
+    }
+
+    /// Set the features this client supports, declared to the host when joining. The session
+    /// then uses those the host also does (see features). Default: none.
+    pub fn set_supported_features(&mut self, features: FeatureSet) {
+        self.supported_features = features;
+    }
+
+    /// Get the features this client declares when joining
+    pub fn supported_features(&self) -> FeatureSet {
+        self.supported_features
+    }
+
+    /// Resend an unanswered connection request every `interval`, up to `attempts` requests per relay
+    /// address, before giving up on it (default: every second, 10 attempts)
+    pub fn set_connect_retry(&mut self, interval: Duration, attempts: u32) {
+        self.connect_retry_interval = interval;
+        self.connect_attempts = attempts.max(1);
+    }
+
     /// Set whether to automatically send pings (default: true)
     pub fn set_auto_ping(&mut self, enabled: bool) {
         self.auto_ping = enabled;
@@ -104,8 +567,120 @@ impl NeonClient {
         self.ping_interval = interval;
     }
 
+    /// Carry acks on outgoing game packets instead of sending them alone, and only auto-ping once
+    /// nothing has gone to the host for a ping interval (default: false). Needs protocol version 3;
+    /// acks with nothing to ride on are sent alone after 100ms.
+    pub fn set_piggyback(&mut self, enabled: bool) {
+        self.piggyback = enabled;
+    }
+
+    /// Compress game payloads at or above this size (default: None, off). Needs the `lz4` feature at
+    /// both ends: peers built without it drop compressed packets, so only turn this on when every peer has it.
+    pub fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.socket.compression_threshold = threshold;
+    }
+
+    /// Coalesce outgoing game packets into shared datagrams, flushed every process_packets() call (default: false)
+    pub fn set_batching(&mut self, enabled: bool) {
+        self.batcher = enabled.then(|| PacketBatcher::new(self.max_packet_size));
+    }
+
+    /// Largest datagram, header included, that may be sent to a peer. Traffic to the host (1)
+    /// grows past the session default as MTU probes succeed; other peers stay at the default.
+    pub fn packet_size_limit(&self, peer_id: u16) -> usize {
+        if peer_id == 1 { self.host_packet_size } else { self.max_packet_size }
+    }
+
+    /// Probe the path to the host with padded datagrams at each size in MTU_PROBE_SIZES
+    /// above the current limit, up to the host's advertised maximum. Echoed probes raise
+    /// packet_size_limit(1). Sent automatically when the session config arrives.
+    pub fn probe_mtu(&mut self) -> Result<(), Error> {
+        let (Some(relay_addr), Some(client_id)) = (self.relay_addr, self.client_id) else {
+            return Err(Error::new(ErrorKind::NotConnected, "Client not connected"));
+        };
+        for probe_size in MTU_PROBE_SIZES {
+            let size = probe_size as usize;
+            if size > self.host_packet_size && size <= self.advertised_packet_size {
+                send_mtu_probe(&self.socket, relay_addr, client_id, probe_size, self.outgoing_sequence.advance())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop low-priority packets that have waited this long to be sent (None keeps them; default: 200ms)
+    pub fn set_stale_packet_age(&mut self, age: Option<Duration>) {
+        self.scheduler.set_stale_age(age);
+    }
+
+    /// Cap outgoing game traffic at this many bytes per second (None removes the cap).
+    /// Packets over budget wait in the priority queues and go out as budget refills.
+    pub fn set_bandwidth_limit(&mut self, bytes_per_second: Option<u32>) {
+        self.bandwidth_limit = bytes_per_second;
+        self.apply_bandwidth_settings();
+    }
+
+    /// Get the outgoing bandwidth cap in bytes per second
+    pub fn bandwidth_limit(&self) -> Option<u32> {
+        self.bandwidth_limit
+    }
+
+    /// Adapt the send rate to the link (default: false). The rate backs off when round trips
+    /// inflate or reliable packets need retransmitting and creeps back up while the link is clear,
+    /// never exceeding the bandwidth limit (1 MB/s if none is set).
+    pub fn set_congestion_control(&mut self, enabled: bool) {
+        if enabled != self.congestion.is_some() {
+            self.congestion = enabled.then(|| CongestionController::new(self.bandwidth_limit.unwrap_or(DEFAULT_MAX_RATE)));
+            self.apply_bandwidth_settings();
+        }
+    }
+
+    /// Get the rate outgoing game traffic is currently paced at (None when unpaced)
+    pub fn send_rate(&self) -> Option<u32> {
+        self.bandwidth.as_ref().map(BandwidthLimiter::bytes_per_second)
+    }
+
+    /// Set callback for when the bandwidth cap starts holding packets back. Fires again only
+    /// after the queues have emptied.
+    pub fn on_budget_exceeded<F>(&mut self, callback: F)
+    where
+        F: FnMut(usize, u32) + Send + 'static,
+    {
+        self.on_budget_exceeded = Some(Box::new(callback));
+    }
+
+    /// Get the number of packets waiting in the outgoing priority queues
+    pub fn queued_packets(&self) -> usize {
+        self.scheduler.len()
+    }
+
+    /// Get the number of low-priority packets dropped for going stale
+    pub fn dropped_packets(&self) -> u64 {
+        self.scheduler.dropped_packets()
+    }
+
+    /// Get a handle other threads can send through; see ClientSender
+    pub fn sender(&self) -> ClientSender {
+        self.sender.clone()
+    }
+
+    /// Process packets on a background thread from now on, so callbacks fire without a loop of
+    /// your own. Connect first; a client that isn't connected stops its worker at once.
+    pub fn spawn(self) -> Result<ClientWorker<T>, Error> {
+        ClientWorker::spawn(self)
+    }
+
+    /// Send queued packets, then any batched packets, immediately
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.send_queued();
+        self.drain_outgoing()?;
+        match (&mut self.batcher, self.relay_addr) {
+            (Some(batcher), Some(relay_addr)) => batcher.flush(&self.socket, relay_addr),
+            _ => Ok(()),
+        }
+    }
+
     /// Get the client's assigned ID (None if not connected)
-    pub fn client_id(&self) -> Option<u8> {
+    pub fn client_id(&self) -> Option<u16> {
         self.client_id
     }
 
@@ -114,75 +689,743 @@ impl NeonClient {
         self.session_id
     }
 
+    /// Get the address of the relay this client joined through, which may differ from the one
+    /// passed to `connect` if that relay redirected it
+    pub fn relay_addr(&self) -> Option<SocketAddr> {
+        self.relay_addr
+    }
+
+    /// Get the header version negotiated with the host
+    pub fn protocol_version(&self) -> u8 {
+        self.socket.version
+    }
+
+    /// Get the role this client joined, or is joining, as
+    pub fn role(&self) -> PeerRole {
+        self.role
+    }
+
+    /// Get what the host lets this client do; its role's defaults until the host says otherwise
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
     /// Get the client name
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    /// Connect to a session
-    pub fn connect(&mut self, session_id: u32, relay_addr: &str) -> Result<(), Error> {
-        let relay_addr = relay_addr.parse()
-            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid relay address"))?;
-        
-        self.relay_addr = Some(relay_addr);
-        self.socket.socket.set_nonblocking(false)?;
+    /// Get the session config the host sent (None until it arrives)
+    pub fn session_config(&self) -> Option<&SessionConfig> {
+        self.session_config.as_ref()
+    }
+
+    /// Get the host's tick rate from the session config (None until it arrives)
+    pub fn tick_rate(&self) -> Option<u16> {
+        self.session_config.as_ref().map(|config| config.tick_rate).filter(|&tick_rate| tick_rate > 0)
+    }
+
+    /// Get the features agreed with the host, from the session config (None until it arrives)
+    pub fn features(&self) -> Option<FeatureSet> {
+        self.session_config.as_ref().map(|config| config.features)
+    }
+
+    /// Get an entry of the session's key/value config (see NeonHost::set_config_value)
+    pub fn config_value(&self, key: &str) -> Option<&[u8]> {
+        self.config_values.get(key).map(Vec::as_slice)
+    }
+
+    /// Get an entry of the session's key/value config as text, if it is valid UTF-8
+    pub fn config_str(&self, key: &str) -> Option<&str> {
+        self.config_value(key).and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Get the session's key/value config as received so far
+    pub fn config_values(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.config_values
+    }
+
+    /// Get the largest datagram the host advertised in its session config (None until it arrives).
+    /// packet_size_limit is what may be sent right now.
+    pub fn max_packet_size(&self) -> Option<usize> {
+        self.session_config.as_ref().map(|_| self.advertised_packet_size)
+    }
+
+    /// Get the other clients in the session, as last reported by the host
+    pub fn peers(&self) -> &[PeerInfo] {
+        &self.peers
+    }
 
-        send_connect_request(&self.socket, relay_addr, &self.name, session_id)?;
+    /// Get the replicated entities as of the last applied snapshot
+    pub fn entities(&self) -> &HashMap<u32, Vec<u8>> {
+        self.replication.entities()
+    }
+
+    /// Get the tick of the last applied snapshot (None until the first arrives)
+    pub fn snapshot_tick(&self) -> Option<u64> {
+        self.replication.tick()
+    }
+
+    /// Get the number of datagrams dropped because they couldn't be decoded
+    pub fn malformed_packet_count(&self) -> u64 {
+        self.malformed_packets
+    }
+
+    /// Get duplicate/out-of-order statistics for packets received from a peer
+    pub fn sequence_stats(&self, peer_id: u16) -> Option<SequenceStats> {
+        self.incoming_sequences.stats(peer_id)
+    }
+
+    /// Duplicate/out-of-order statistics summed over every peer heard from, including earlier sessions.
+    /// `rejected()` counts the packets dropped as replays.
+    pub fn sequence_totals(&self) -> SequenceStats {
+        self.incoming_sequences.totals()
+    }
+
+    /// Connect to a session, blocking until the host answers and sends its session config, or every
+    /// relay address fails.
+    /// The relay address may be an IP literal or a hostname; each resolved address is tried in order
+    /// until one answers. Use begin_connect instead to keep the calling thread free.
+    pub fn connect<A: ToSocketAddrs>(&mut self, session_id: u32, relay_addr: A) -> Result<(), Error> {
+        self.role = PeerRole::Player;
+        self.connect_blocking(session_id, relay_addr)
+    }
+
+    /// Connect to a session as a spectator, which receives the host's game traffic but can't send
+    /// its own: the relay and host drop its game packets. Blocks like connect.
+    pub fn connect_as_spectator<A: ToSocketAddrs>(&mut self, session_id: u32, relay_addr: A) -> Result<(), Error> {
+        self.role = PeerRole::Spectator;
+        self.connect_blocking(session_id, relay_addr)
+    }
+
+    fn connect_blocking<A: ToSocketAddrs>(&mut self, session_id: u32, relay_addr: A) -> Result<(), Error> {
+        self.start_connect(session_id, relay_addr)?;
+        while self.connecting.is_some() {
+            self.socket.socket.wait_readable(CONNECT_POLL_INTERVAL)?;
+            self.poll_connect()?;
+        }
+        self.wait_until_configured(CONFIG_TIMEOUT)
+    }
+
+    /// Process packets until the host's SessionConfig arrives and state() is Connected, so tick_rate
+    /// and the packet size limit are the session's. Waits through a connect begun with begin_connect.
+    /// TimedOut if the config doesn't come within `timeout`; NotConnected if the client isn't joining
+    /// or in a session.
+    pub fn wait_until_configured(&mut self, timeout: Duration) -> Result<(), Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.state {
+                ConnectionState::Connected => return Ok(()),
+                ConnectionState::Connecting | ConnectionState::AwaitingConfig => {}
+                _ => return Err(Error::new(ErrorKind::NotConnected, "Client not connected")),
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::new(ErrorKind::TimedOut, "Host never sent its session config"));
+            }
+            self.socket.socket.wait_readable(remaining.min(CONNECT_POLL_INTERVAL))?;
+            self.process_packets()?;
+        }
+    }
+
+    /// Start connecting to a session without waiting. Each process_packets call advances the attempt
+    /// until on_connected or on_connect_failed fires; connection_state reports where it is.
+    pub fn begin_connect<A: ToSocketAddrs>(&mut self, session_id: u32, relay_addr: A) -> Result<(), Error> {
+        self.role = PeerRole::Player;
+        self.start_connect(session_id, relay_addr)
+    }
+
+    /// Like begin_connect, joining as a spectator (see connect_as_spectator)
+    pub fn begin_connect_as_spectator<A: ToSocketAddrs>(&mut self, session_id: u32, relay_addr: A) -> Result<(), Error> {
+        self.role = PeerRole::Spectator;
+        self.start_connect(session_id, relay_addr)
+    }
+
+    fn start_connect<A: ToSocketAddrs>(&mut self, session_id: u32, relay_addr: A) -> Result<(), Error> {
+        // Stripping control characters also drops any NUL, which would be read as the start of the auth token
+        self.name = name::normalize(&self.name)?;
+
+        let mut candidates: VecDeque<SocketAddr> = resolve_addrs(relay_addr)?.into();
+        let first = candidates.pop_front().expect("resolve_addrs returns at least one address");
+        self.client_id = None;
+        self.session_id = None;
+        self.session_config = None;
+        self.config_values.clear();
+        self.capabilities = Capabilities::for_role(self.role);
+        self.pings_in_flight.clear();
+        self.incoming_sequences.reset_all();
+        // Timestamps count from the start of each connection, and the host's clock may differ from the last one
+        self.local_clock = LocalClock::new();
+        self.time_sync = ClockEstimator::new();
+        self.connecting = Some(PendingConnect {
+            session_id,
+            several: !candidates.is_empty(),
+            candidates,
+            candidate: first,
+            failures: Vec::new(),
+            redirects: 0,
+            attempts: 0,
+            requests: Vec::new(),
+            relay_answered: false,
+            sent_at: Instant::now(),
+        });
+        self.set_state(ConnectionState::Connecting);
+        let result = match self.request_connection(first) {
+            Ok(()) => Ok(()),
+            Err(e) => self.try_next_relay(e),
+        };
+        self.settle_connect(result)
+    }
+
+    /// Where the client is in joining or leaving a session. Connected only once the host's
+    /// SessionConfig has arrived; on_state_changed reports each move.
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Whether the client is connected, connecting, or neither. Unlike state, a client still waiting
+    /// on SessionConfig counts as connecting, and one disconnecting as connected.
+    pub fn connection_state(&self) -> ConnectionState {
+        match self.state {
+            ConnectionState::AwaitingConfig => ConnectionState::Connecting,
+            ConnectionState::Disconnecting => ConnectionState::Connected,
+            state => state,
+        }
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        let old = std::mem::replace(&mut self.state, state);
+        if old != state
+            && let Some(callback) = &mut self.on_state_changed
+        {
+            callback(old, state);
+        }
+    }
+
+    /// Leave the session: send whatever is queued, then forget it. The host isn't told and drops
+    /// the client when it stops hearing from it. Does nothing if the client isn't in a session.
+    pub fn disconnect(&mut self) -> Result<(), Error> {
+        if self.client_id.is_none() && self.connecting.is_none() {
+            return Ok(());
+        }
+        let flushed = if self.client_id.is_some() {
+            self.set_state(ConnectionState::Disconnecting);
+            self.flush()
+        } else {
+            Ok(())
+        };
+        println!("[Client] Leaving session {}", self.session_id.or(self.connecting.as_ref().map(|p| p.session_id)).unwrap_or_default());
+        self.connecting = None;
+        self.client_id = None;
+        self.session_id = None;
+        self.session_config = None;
+        self.config_values.clear();
+        self.resume_requested = None;
+        self.peers.clear();
+        self.set_state(ConnectionState::Disconnected);
+        flushed
+    }
+
+    /// Join the session a host gave `code` (see `NeonHost::set_join_code`), asking the relay which
+    /// session that is. A client without a game identifier takes the session's. NotFound if no
+    /// session on the relay has the code, or it's another game's.
+    pub fn connect_with_code<A: ToSocketAddrs>(&mut self, code: &str, relay_addr: A) -> Result<(), Error> {
+        let relay = resolve_addrs(relay_addr)?[0];
+        self.socket.socket.set_nonblocking(false)?;
+        let session = join_code::lookup_with(&self.socket.socket, relay, code);
+        self.socket.socket.set_nonblocking(true)?;
+        let session = session?;
+        if self.game_identifier != 0 && session.game_identifier != 0 && session.game_identifier != self.game_identifier {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("Join code {} is for game 0x{:08X}", code.trim().to_ascii_uppercase(), session.game_identifier),
+            ));
+        }
+        if self.game_identifier == 0 {
+            self.game_identifier = session.game_identifier;
+        }
+        self.connect(session.session_id, relay)
+    }
 
-        let (assigned_client_id, received_session_id) = wait_for_connect_response(&self.socket, Duration::from_secs(10))?;
-        
+    /// Ask `directory_addr`, any relay in a peered group, which relay has `session_id` and connect
+    /// through that one. Where the session doesn't exist yet, the relay with the best mix of load
+    /// and measured latency is chosen (see `directory::find_relay`).
+    pub fn connect_via_directory<A: ToSocketAddrs>(&mut self, directory_addr: A, session_id: u32) -> Result<(), Error> {
+        let directory = resolve_addrs(directory_addr)?[0];
+        self.socket.socket.set_nonblocking(false)?;
+        let relay = directory::find_relay_with(&self.socket.socket, directory, session_id);
         self.socket.socket.set_nonblocking(true)?;
-        
-        if received_session_id != session_id {
-            return Err(Error::new(ErrorKind::ConnectionRefused, 
-                format!("Session ID mismatch: requested {}, got {}", session_id, received_session_id)));
+        self.connect(session_id, relay?)
+    }
+
+    /// Start asking `relay_addr` for the session, from the first attempt
+    fn request_connection(&mut self, relay_addr: SocketAddr) -> Result<(), Error> {
+        // A socket pinned to a chosen address or port is kept, even if it can't reach the relay
+        if !self.socket_options.pins_local_addr()
+            && let Some(transport) = self.socket.socket.reopen_for(&relay_addr)?
+        {
+            transport.apply_socket_options(&self.socket_options)?;
+            let mut socket = NeonSocket::from_transport(transport)?;
+            socket.compression_threshold = self.socket.compression_threshold;
+            self.socket = socket;
+        }
+
+        let Some(pending) = &mut self.connecting else { return Ok(()) };
+        pending.attempts = 0;
+        pending.requests.clear();
+        pending.relay_answered = false;
+        self.relay_addr = Some(relay_addr);
+        // The request goes out at v1 so any host can read it; the accept comes back at the agreed version
+        self.socket.version = PROTOCOL_V1;
+        self.send_connection_request()
+    }
+
+    /// Send, or resend, the connection request to the relay being tried
+    fn send_connection_request(&mut self) -> Result<(), Error> {
+        let (Some(pending), Some(relay_addr)) = (&mut self.connecting, self.relay_addr) else { return Ok(()) };
+        let sequence = self.outgoing_sequence.advance();
+        pending.attempts += 1;
+        pending.requests.push(sequence);
+        pending.sent_at = Instant::now();
+
+        let session_id = pending.session_id;
+        send_connect_request(&self.socket, relay_addr, self.connect_request(session_id, None), sequence)
+    }
+
+    /// A request to join `session_id`, or to resume a client ID with its token
+    fn connect_request(&self, session_id: u32, resume: Option<(u16, u64)>) -> ConnectRequest {
+        let (resume_id, resume_token) = resume.unwrap_or_default();
+        ConnectRequest {
+            client_version: PROTOCOL_VERSION,
+            desired_name: self.name.clone(),
+            target_session_id: session_id,
+            game_identifier: self.game_identifier,
+            nonce: 0,
+            resume_id,
+            resume_token,
+            auth_token: self.auth_token.clone(),
+            role: self.role,
+        }
+    }
+
+    /// Handle the relay's answers to the connection request, moving on to the next address if it's overdue
+    fn poll_connect(&mut self) -> Result<(), Error> {
+        let result = self.advance_connect();
+        self.settle_connect(result)
+    }
+
+    fn advance_connect(&mut self) -> Result<(), Error> {
+        loop {
+            let received = self.socket.receive_packet();
+            report_malformed(self);
+            let outcome = match received {
+                // Only the relay being tried answers; anything else is stray or forged
+                Ok((_, addr)) if Some(addr) != self.relay_addr => continue,
+                Ok((packet, _)) => match packet.payload {
+                    PacketPayload::ConnectAccept(accept) => return self.finish_connect(accept),
+                    PacketPayload::SessionRedirect(redirect) => self.follow_redirect(redirect),
+                    PacketPayload::ConnectDeny(deny) => Err(Error::new(ErrorKind::ConnectionRefused, deny.reason)),
+                    PacketPayload::Ack(ack) if packet.client_id == 0 => {
+                        if let Some(pending) = &mut self.connecting
+                            && ack.acknowledged_sequences.iter().any(|sequence| pending.requests.contains(sequence))
+                        {
+                            pending.relay_answered = true;
+                        }
+                        continue;
+                    }
+                    // Left over from an earlier session
+                    _ => continue,
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = outcome {
+                self.try_next_relay(e)?;
+            }
         }
-        
+
+        let (Some(pending), Some(relay_addr)) = (&self.connecting, self.relay_addr) else { return Ok(()) };
+        if pending.sent_at.elapsed() < self.connect_retry_interval {
+            return Ok(());
+        }
+        if pending.attempts < self.connect_attempts {
+            if let Err(e) = self.send_connection_request() {
+                self.try_next_relay(e)?;
+            }
+            return Ok(());
+        }
+
+        let stage = if pending.relay_answered {
+            format!("Relay {} passed the request on, but the host of session {} never answered", relay_addr, pending.session_id)
+        } else {
+            format!("Relay {} never answered", relay_addr)
+        };
+        let attempts = pending.attempts;
+        self.try_next_relay(Error::new(ErrorKind::TimedOut, format!("{} ({} connection requests sent)", stage, attempts)))
+    }
+
+    fn follow_redirect(&mut self, redirect: SessionRedirect) -> Result<(), Error> {
+        let (Some(pending), Some(relay_addr)) = (&mut self.connecting, self.relay_addr) else { return Ok(()) };
+        if pending.redirects == MAX_REDIRECTS {
+            return Err(Error::new(ErrorKind::NotConnected, format!("Gave up after {} relay redirects", MAX_REDIRECTS)));
+        }
+        let owner = redirect.relays.first().and_then(|relay| relay.addr.parse().ok());
+        let owner = match owner {
+            Some(owner) if redirect.session_id == pending.session_id => owner,
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("Relay {} sent an invalid redirect", relay_addr))),
+        };
+        println!("[Client] Session {} is on relay {}, following redirect", pending.session_id, owner);
+        pending.redirects += 1;
+        self.request_connection(owner)
+    }
+
+    /// Give up on the address being tried and move on to the next, or fail the attempt if there is none
+    fn try_next_relay(&mut self, error: Error) -> Result<(), Error> {
+        let Some(pending) = &mut self.connecting else { return Err(error) };
+        // The relay answered and the host said no; another address won't change that
+        if !pending.several || error.kind() == ErrorKind::ConnectionRefused {
+            return Err(error);
+        }
+        pending.failures.push(format!("{}: {}", pending.candidate, error));
+        let Some(next) = pending.candidates.pop_front() else {
+            return Err(Error::new(
+                ErrorKind::NotConnected,
+                format!("Could not reach relay at any resolved address ({})", pending.failures.join("; ")),
+            ));
+        };
+        pending.candidate = next;
+        pending.redirects = 0;
+        match self.request_connection(next) {
+            Ok(()) => Ok(()),
+            Err(e) => self.try_next_relay(e),
+        }
+    }
+
+    /// End the attempt if it failed, reporting the failure through on_connect_failed as well
+    fn settle_connect(&mut self, result: Result<(), Error>) -> Result<(), Error> {
+        if let Err(e) = &result {
+            self.connecting = None;
+            self.set_state(ConnectionState::Disconnected);
+            if let Some(callback) = &mut self.on_connect_failed {
+                callback(e);
+            }
+        }
+        result
+    }
+
+    fn finish_connect(&mut self, mut accept: ConnectAccept) -> Result<(), Error> {
+        let (Some(pending), Some(relay_addr)) = (self.connecting.take(), self.relay_addr) else { return Ok(()) };
+        let (assigned_client_id, received_session_id) = (accept.assigned_client_id, accept.session_id);
+
+        if received_session_id != pending.session_id {
+            return Err(Error::new(ErrorKind::ConnectionRefused,
+                format!("Session ID mismatch: requested {}, got {}", pending.session_id, received_session_id)));
+        }
+
         self.client_id = Some(assigned_client_id);
         self.session_id = Some(received_session_id);
+        self.socket.version = self.socket.last_version;
+        self.resume_token = accept.resume_token;
+        self.resume_requested = None;
+        self.set_state(ConnectionState::AwaitingConfig);
 
-        let accept = types::ConnectAccept {
-            assigned_client_id,
-            session_id: received_session_id,
+        // Echoing the accept, cookie included, registers this address with the relay. The host
+        // reads which features the client supports from it. on_connected waits for its SessionConfig.
+        accept.features = self.supported_features;
+        send_connect_accept_confirmation(&self.socket, relay_addr, assigned_client_id, accept, self.outgoing_sequence.advance())
+    }
+
+    /// Ask the host, through the relay, to re-admit this client under its current ID.
+    /// Sent when the relay says it doesn't know this address, e.g. after it restarted.
+    fn request_resume(&mut self) -> Result<(), Error> {
+        let (Some(relay_addr), Some(client_id), Some(session_id)) = (self.relay_addr, self.client_id, self.session_id) else {
+            return Ok(());
         };
-        send_connect_accept_confirmation(&self.socket, relay_addr, assigned_client_id, accept)?;
+        if self.resume_requested.is_some_and(|at| at.elapsed() < RESUME_RETRY_INTERVAL) {
+            return Ok(());
+        }
+        if self.resume_token == 0 || is_v1(self.socket.version) {
+            println!("[Client] Relay lost this connection and the host gave no way to resume it");
+            return Ok(());
+        }
 
-        Ok(())
+        println!("[Client] Relay lost this connection, asking to resume as client {}", client_id);
+        self.resume_requested = Some(Instant::now());
+        let request = self.connect_request(session_id, Some((client_id, self.resume_token)));
+        send_connect_request(&self.socket, relay_addr, request, self.outgoing_sequence.advance())
     }
 
-    /// Manually send a ping
-    pub fn send_ping(&self) -> Result<(), Error> {
+    /// Register again with the relay using an accept from the host: its answer to a resume request,
+    /// or a resend of the original accept because the first confirmation was lost
+    fn finish_resume(&mut self, accept: ConnectAccept) -> Result<(), Error> {
+        let (Some(relay_addr), Some(client_id)) = (self.relay_addr, self.client_id) else {
+            return Ok(());
+        };
+        if accept.assigned_client_id != client_id || Some(accept.session_id) != self.session_id {
+            return Ok(());
+        }
+
+        // Without a resume pending, this is the host resending an accept whose confirmation it never heard
+        if self.resume_requested.take().is_some() {
+            println!("[Client] Resumed session as client {}", client_id);
+        }
+        send_connect_accept_confirmation(&self.socket, relay_addr, client_id, accept, self.outgoing_sequence.advance())
+    }
+
+    /// Manually send a ping. Its round trip is timed on the monotonic clock, so wall clock changes don't skew it.
+    pub fn send_ping(&mut self) -> Result<(), Error> {
         if let (Some(relay_addr), Some(client_id)) = (self.relay_addr, self.client_id) {
-            send_ping(&self.socket, relay_addr, client_id)
+            self.next_ping_id = self.next_ping_id.wrapping_add(1);
+            send_ping(&self.socket, relay_addr, client_id, self.next_ping_id, self.outgoing_sequence.advance())?;
+            if self.pings_in_flight.len() == MAX_PINGS_IN_FLIGHT {
+                self.pings_in_flight.pop_front();
+            }
+            self.pings_in_flight.push_back((self.next_ping_id, Instant::now()));
+            Ok(())
         } else {
             Err(Error::new(ErrorKind::NotConnected, "Client not connected"))
         }
     }
 
-    /// Process incoming packets once
+    /// Ask the host for its clock. Sent automatically with each auto ping.
+    pub fn sync_time(&mut self) -> Result<(), Error> {
+        if let (Some(relay_addr), Some(client_id)) = (self.relay_addr, self.client_id) {
+            let sequence = self.outgoing_sequence.advance();
+            send_time_sync(&self.socket, relay_addr, client_id, self.local_clock.now(), sequence)
+        } else {
+            Err(Error::new(ErrorKind::NotConnected, "Client not connected"))
+        }
+    }
+
+    /// Estimated current reading of the host's clock in microseconds (None until the first sync completes)
+    pub fn server_time(&self) -> Option<u64> {
+        self.time_sync.remote_time_at(self.local_clock.now())
+    }
+
+    /// Offset, drift, and round trip behind server_time()
+    pub fn time_sync(&self) -> &ClockEstimator {
+        &self.time_sync
+    }
+
+    /// Latest, smoothed, and jitter of the round trips to the host measured by pings
+    pub fn rtt_stats(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// Ask the host to rename this client, to `new_name` as name::normalize leaves it. The host's answer
+    /// fires on_name_changed or on_name_change_denied; the request isn't resent, so ask again if neither fires.
+    pub fn request_name_change(&mut self, new_name: &str) -> Result<(), Error> {
+        let new_name = name::normalize(new_name)?;
+        self.send_game_data(1, NAME_CHANGE, new_name.as_bytes(), Priority::Normal)
+    }
+
+    /// Send a chat message to one peer (1 is the host) or to everyone in the session (0)
+    pub fn send_chat(&mut self, text: &str, destination_id: u16) -> Result<(), Error> {
+        protocol::check_chat_length(text)?;
+
+        let (Some(relay_addr), Some(client_id)) = (self.relay_addr, self.client_id) else {
+            return Err(Error::new(ErrorKind::NotConnected, "Client not connected"));
+        };
+        send_chat(&self.socket, relay_addr, client_id, &self.name, text, destination_id, self.outgoing_sequence.advance())
+    }
+
+    /// Send a game packet to the host
+    pub fn send_game_packet(&mut self, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.send_game_packet_to(1, packet_type, data)
+    }
+
+    /// Send a game packet to another client in the session (or the host, 1), routed through the relay
+    pub fn send_game_packet_to(&mut self, peer_id: u16, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.send_game_packet_with_priority(peer_id, packet_type, data, Priority::Normal)
+    }
+
+    /// Send a game packet to a peer, ahead of or behind other queued traffic when the link is backed up
+    pub fn send_game_packet_with_priority(&mut self, peer_id: u16, packet_type: u8, data: &[u8], priority: Priority) -> Result<(), Error> {
+        protocol::check_game_packet_type(packet_type)?;
+        self.send_game_data(peer_id, packet_type, data, priority)
+    }
+
+    /// Call an RPC method on the host without waiting for a reply
+    pub fn rpc(&mut self, method: &str, args: &[u8]) -> Result<(), Error> {
+        let request = self.rpc.request(1, method, args, None)?;
+        self.send_game_data(1, RPC_REQUEST, &request, Priority::Normal)
+    }
+
+    /// Call an RPC method on the host. The callback runs from process_packets with the reply,
+    /// the host's error, or a TimedOut error.
+    pub fn rpc_with_reply<F>(&mut self, method: &str, args: &[u8], callback: F) -> Result<(), Error>
+    where
+        F: FnOnce(Result<Vec<u8>, Error>) + Send + 'static,
+    {
+        let request = self.rpc.request(1, method, args, Some(Box::new(callback)))?;
+        self.send_game_data(1, RPC_REQUEST, &request, Priority::Normal).inspect_err(|_| self.rpc.cancel(&request))
+    }
+
+    /// Send an encoded voice frame (e.g. Opus) to the host and every other client.
+    /// Voice skips batching and is never retransmitted.
+    pub fn send_voice(&mut self, frame: &[u8]) -> Result<(), Error> {
+        let payload = self.voice.encode(frame)?;
+        let mut recipients: Vec<u16> = self.peers.iter().map(|peer| peer.client_id).collect();
+        recipients.push(1);
+        for peer_id in recipients {
+            self.send_voice_payload(peer_id, &payload)?;
+        }
+        Ok(())
+    }
+
+    /// Send an encoded voice frame to one peer (1 is the host)
+    pub fn send_voice_to(&mut self, peer_id: u16, frame: &[u8]) -> Result<(), Error> {
+        let payload = self.voice.encode(frame)?;
+        self.send_voice_payload(peer_id, &payload)
+    }
+
+    fn send_voice_payload(&mut self, peer_id: u16, payload: &[u8]) -> Result<(), Error> {
+        let Some(client_id) = self.client_id else {
+            return Err(Error::new(ErrorKind::NotConnected, "Client not connected"));
+        };
+        let packet = NeonPacket {
+            packet_type: VOICE_FRAME,
+            sequence: self.outgoing_sequence.advance(),
+            client_id,
+            destination_id: peer_id,
+            payload: PacketPayload::GamePacket(payload.to_vec()),
+        };
+        self.scheduler.push(packet, Priority::High);
+        self.drain_outgoing()
+    }
+
+    fn send_game_data(&mut self, peer_id: u16, packet_type: u8, data: &[u8], priority: Priority) -> Result<(), Error> {
+        let Some(client_id) = self.client_id else {
+            return Err(Error::new(ErrorKind::NotConnected, "Client not connected"));
+        };
+        // The packet size limit isn't known until the host's config arrives
+        if self.state == ConnectionState::AwaitingConfig {
+            return Err(Error::new(ErrorKind::WouldBlock, "Session config not received yet (see wait_until_configured)"));
+        }
+        if peer_id == 0 || peer_id == client_id {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Cannot address a game packet to {}", peer_id)));
+        }
+        protocol::check_packet_size(data, self.packet_size_limit(peer_id), self.socket.compression_threshold)?;
+
+        let packet = NeonPacket {
+            packet_type,
+            sequence: self.outgoing_sequence.advance(),
+            client_id,
+            destination_id: peer_id,
+            payload: PacketPayload::GamePacket(data.to_vec()),
+        };
+        if peer_id == 1 {
+            self.last_host_send = Some(Instant::now());
+        }
+        self.scheduler.push(packet, priority);
+        self.drain_outgoing()
+    }
+
+    /// Make the sends other threads queued through a ClientSender
+    fn send_queued(&mut self) {
+        // Left queued until the packet size limit is known
+        if self.state == ConnectionState::AwaitingConfig {
+            return;
+        }
+        while let Ok(send) = self.queued_sends.try_recv() {
+            let result = match send {
+                QueuedSend::Ping => self.send_ping(),
+                QueuedSend::Chat { text, destination_id } => self.send_chat(&text, destination_id),
+                QueuedSend::Game { peer_id, packet_type, data, priority } => {
+                    self.send_game_packet_with_priority(peer_id, packet_type, &data, priority)
+                }
+            };
+            if let Err(e) = result {
+                println!("[Client] Dropping a send queued by another thread: {}", e);
+            }
+        }
+    }
+
+    /// Pace outgoing traffic at the congestion controller's rate, or else the fixed limit
+    fn apply_bandwidth_settings(&mut self) {
+        if let Some(controller) = &mut self.congestion {
+            controller.set_max_rate(self.bandwidth_limit.unwrap_or(DEFAULT_MAX_RATE));
+        }
+        let rate = self.congestion.as_ref().map(CongestionController::rate).or(self.bandwidth_limit);
+        match (&mut self.bandwidth, rate) {
+            (Some(limiter), Some(rate)) => limiter.set_bytes_per_second(rate),
+            (limiter, rate) => *limiter = rate.map(BandwidthLimiter::new),
+        }
+    }
+
+    /// Send queued packets in priority order until the socket or the bandwidth cap pushes back.
+    /// Voice is never batched.
+    fn drain_outgoing(&mut self) -> Result<(), Error> {
+        let Some(relay_addr) = self.relay_addr else {
+            return Ok(());
+        };
+        if let (Some(controller), Some(limiter)) = (&mut self.congestion, &mut self.bandwidth) {
+            controller.update();
+            limiter.set_bytes_per_second(controller.rate());
+        }
+        let (socket, batcher, bandwidth) = (&self.socket, &mut self.batcher, &mut self.bandwidth);
+        let mut over_budget = false;
+        self.scheduler.drain(|packet| {
+            if let Some(limiter) = bandwidth.as_mut()
+                && !limiter.try_consume(packet.encoded_len(socket.version))
+            {
+                over_budget = true;
+                return Err(Error::new(ErrorKind::WouldBlock, "Outgoing bandwidth budget exhausted"));
+            }
+            match batcher {
+                Some(batcher) if packet.packet_type != VOICE_FRAME => batcher.push(socket, relay_addr, packet.clone()),
+                _ => socket.send_packet(packet, relay_addr),
+            }
+        })?;
+
+        if over_budget {
+            if !self.throttled
+                && let (Some(callback), Some(limiter)) = (&mut self.on_budget_exceeded, &self.bandwidth)
+            {
+                callback(self.scheduler.len(), limiter.bytes_per_second());
+            }
+            self.throttled = true;
+        } else if self.scheduler.is_empty() {
+            self.throttled = false;
+        }
+        Ok(())
+    }
+
+    /// Serialize and send a typed message (requires the `serde` feature)
+    #[cfg(feature = "serde")]
+    pub fn send_message<M: Message>(&mut self, message: &M) -> Result<(), Error> {
+        self.send_game_packet(M::PACKET_TYPE, &encode_message(message)?)
+    }
+
+    /// Advance a connection attempt, or process incoming packets once when connected
     pub fn process_packets(&mut self) -> Result<(), Error> {
-        if let Some(client_id) = self.client_id {
-            if self.auto_ping {
+        if self.connecting.is_some() {
+            self.poll_connect()?;
+            if self.connecting.is_some() {
+                return Ok(());
+            }
+        }
+
+        if self.client_id.is_some() {
+            self.flush()?;
+            self.rpc.expire_calls();
+
+            // Pings wait for the session config, like game traffic
+            if self.auto_ping && self.state == ConnectionState::Connected {
                 let should_ping = self.last_ping
                     .map(|t| t.elapsed() >= self.ping_interval)
                     .unwrap_or(true);
+                // Game traffic already tells the relay and host this client is alive
+                let idle = !self.piggyback || self.last_host_send.is_none_or(|t| t.elapsed() >= self.ping_interval);
                 
-                if should_ping {
+                if should_ping && idle {
                     self.send_ping()?;
+                    self.sync_time()?;
                     self.last_ping = Some(Instant::now());
                 }
             }
 
-            process_incoming_packets(
-                &self.socket,
-                self.relay_addr.unwrap(),
-                client_id,
-                &mut self.on_pong,
-                &mut self.on_session_config,
-                &mut self.on_packet_type_registry,
-                &mut self.on_unhandled_packet,
-                &mut self.on_wrong_destination,
-            )
+            process_incoming_packets(self)
         } else {
             Err(Error::new(ErrorKind::NotConnected, "Client not connected"))
         }
@@ -19,14 +19,12 @@ fn main() {
         return;
     }
     
-    let session_input = get_user_input("Enter session ID to connect to: ");
-    let target_session_id: u32 = match session_input.parse() {
-        Ok(id) => id,
-        Err(_) => {
-            println!("Invalid session ID! Please enter a valid number.");
-            return;
-        }
-    };
+    let session_input = get_user_input("Enter session ID or join code to connect to: ");
+    if session_input.is_empty() {
+        println!("Session ID cannot be empty!");
+        return;
+    }
+    let target_session_id: Option<u32> = session_input.parse().ok();
     
     let relay_input = get_user_input("Enter relay address (default: 127.0.0.1:7777): ");
     let relay_addr = if relay_input.is_empty() {
@@ -37,7 +35,10 @@ fn main() {
 
     println!("\nAttempting connection...");
     println!("Client Name: {}", client_name);
-    println!("Target Session ID: {}", target_session_id);
+    match target_session_id {
+        Some(id) => println!("Target Session ID: {}", id),
+        None => println!("Join Code: {}", session_input),
+    }
     println!("Relay Address: {}", relay_addr);
     println!();
 
@@ -73,7 +74,11 @@ fn main() {
         println!("Packet not for me! My ID: {} Packet ID: {}", my_id, packet_destination_id);
     });
 
-    match client.connect(target_session_id, relay_addr) {
+    let connected = match target_session_id {
+        Some(id) => client.connect(id, relay_addr),
+        None => client.connect_with_code(&session_input, relay_addr),
+    };
+    match connected {
         Ok(()) => {
             println!("Connection successful! Starting main loop...");
             println!("Press Ctrl+C to disconnect.\n");
@@ -1,25 +1,22 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::io::{Error, ErrorKind};
-use std::time::{SystemTime, Duration};
+use std::io::Error;
+use std::time::Duration;
 use super::types::*;
 use super::incoming::NeonSocket;
+use crate::protocol::header_size;
+use crate::transport::Transport;
 
 pub fn send_connect_request(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    client_name: &str,
-    target_session_id: u32,
+    connect_req: ConnectRequest,
+    sequence: u16,
 ) -> Result<(), Error> {
-    let connect_req = ConnectRequest {
-        client_version: 1,
-        desired_name: client_name.to_string(),
-        target_session_id,
-        game_identifier: 0,
-    };
-    
+    let target_session_id = connect_req.target_session_id;
     let connect_packet = NeonPacket {
         packet_type: PacketType::ConnectRequest as u8,
-        sequence: 1,
+        sequence,
         client_id: 0,
         destination_id: 1,
         payload: PacketPayload::ConnectRequest(connect_req),
@@ -31,14 +28,15 @@ pub fn send_connect_request(
 }
 
 pub fn send_connect_accept_confirmation(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    client_id: u8,
+    client_id: u16,
     accept: ConnectAccept,
+    sequence: u16,
 ) -> Result<(), Error> {
     let register_packet = NeonPacket {
         packet_type: PacketType::ConnectAccept as u8,
-        sequence: 2,
+        sequence,
         client_id,
         destination_id: 1,
         payload: PacketPayload::ConnectAccept(accept),
@@ -47,42 +45,202 @@ pub fn send_connect_accept_confirmation(
     Ok(())
 }
 
+/// Ping the host, carrying `ping_id` as the timestamp for the Pong to echo
 pub fn send_ping(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    client_id: u8,
+    client_id: u16,
+    ping_id: u64,
+    sequence: u16,
 ) -> Result<(), Error> {
-    let timestamp = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64;
-
     let packet = NeonPacket {
         packet_type: PacketType::Ping as u8,
-        sequence: 10,
+        sequence,
+        client_id,
+        destination_id: 1,
+        payload: PacketPayload::Ping(Ping { timestamp: ping_id }),
+    };
+
+    socket.send_packet(&packet, relay_addr)
+}
+
+/// Answer a peer's ping, echoing its timestamp
+pub fn send_pong(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    client_id: u16,
+    destination_id: u16,
+    original_timestamp: u64,
+    sequence: u16,
+) -> Result<(), Error> {
+    let packet = NeonPacket {
+        packet_type: PacketType::Pong as u8,
+        sequence,
+        client_id,
+        destination_id,
+        payload: PacketPayload::Pong(Pong { original_timestamp }),
+    };
+
+    socket.send_packet(&packet, relay_addr)
+}
+
+pub fn send_time_sync(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    client_id: u16,
+    client_time: u64,
+    sequence: u16,
+) -> Result<(), Error> {
+    let packet = NeonPacket {
+        packet_type: PacketType::TimeSync as u8,
+        sequence,
+        client_id,
+        destination_id: 1,
+        payload: PacketPayload::TimeSync(TimeSync { client_time, host_time: 0 }),
+    };
+
+    socket.send_packet(&packet, relay_addr)
+}
+
+pub fn send_chat(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    client_id: u16,
+    sender_name: &str,
+    text: &str,
+    destination_id: u16,
+    sequence: u16,
+) -> Result<(), Error> {
+    let packet = NeonPacket {
+        packet_type: PacketType::Chat as u8,
+        sequence,
+        client_id,
+        destination_id,
+        payload: PacketPayload::Chat(Chat {
+            sender_name: sender_name.to_string(),
+            text: text.to_string(),
+        }),
+    };
+
+    socket.send_packet(&packet, relay_addr)
+}
+
+/// Send a probe padded to exactly `probe_size` bytes; the host echoes it back if it arrives whole
+pub fn send_mtu_probe(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    client_id: u16,
+    probe_size: u16,
+    sequence: u16,
+) -> Result<(), Error> {
+    let packet = NeonPacket {
+        packet_type: PacketType::MtuProbe as u8,
+        sequence,
         client_id,
         destination_id: 1,
-        payload: PacketPayload::Ping(Ping { timestamp }),
+        payload: PacketPayload::MtuProbe(MtuProbe { probe_size }),
     };
 
     socket.send_packet(&packet, relay_addr)
 }
 
-pub fn wait_for_connect_response(
-    socket: &NeonSocket,
-    timeout: Duration,
-) -> Result<(u8, u32), Error> {
-    socket.socket.set_read_timeout(Some(timeout))?;
-    
-    let (response, _) = socket.receive_packet()?;
-    
-    socket.socket.set_read_timeout(None)?;
-
-    if let PacketPayload::ConnectAccept(accept) = response.payload {
-        Ok((accept.assigned_client_id, accept.session_id))
-    } else if let PacketPayload::ConnectDeny(deny) = response.payload {
-        Err(Error::new(ErrorKind::ConnectionRefused, deny.reason))
-    } else {
-        Err(Error::new(ErrorKind::ConnectionAborted, "Invalid ConnectAccept response"))
+/// Datagram size limit used until the session config says otherwise, and for traffic to other peers
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 1024;
+
+/// How long to wait for an answer before resending a connection request
+pub const DEFAULT_CONNECT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Connection requests sent to a relay address before moving on to the next one
+pub const DEFAULT_CONNECT_ATTEMPTS: u32 = 10;
+
+/// How often to ping the host unless set_ping_interval is called
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long the blocking connect waits for the socket between checks on the attempt
+pub const CONNECT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long connect waits for the host's SessionConfig once it has been accepted
+pub const CONFIG_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most relay redirects followed while connecting, so relays with conflicting directories can't bounce a client forever
+pub const MAX_REDIRECTS: usize = 3;
+
+/// Unanswered pings remembered for matching pongs; older ones count as lost
+pub const MAX_PINGS_IN_FLIGHT: usize = 8;
+
+/// Longest an ack waits for a game packet to ride on, with piggybacking on, before it is sent alone
+pub const PIGGYBACK_ACK_DELAY: Duration = Duration::from_millis(100);
+
+/// How long to wait on a resume request before asking again
+pub const RESUME_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Datagram sizes probed toward the host after the session config arrives, up to its advertised maximum
+pub const MTU_PROBE_SIZES: [u16; 3] = [1280, 1400, 1472];
+
+/// Collects outgoing packets per destination so several can share one datagram
+pub struct PacketBatcher {
+    max_packet_size: usize,
+    queues: HashMap<u16, (Vec<NeonPacket>, usize)>,
+}
+
+impl PacketBatcher {
+    pub fn new(max_packet_size: usize) -> Self {
+        Self {
+            max_packet_size,
+            queues: HashMap::new(),
+        }
+    }
+
+    pub fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    /// Queue a packet, first sending the pending batch for its destination if it would overflow
+    pub fn push(&mut self, socket: &NeonSocket<impl Transport>, relay_addr: SocketAddr, packet: NeonPacket) -> Result<(), Error> {
+        // Batch header + entry count (1), then a length prefix (2) per entry
+        let version = socket.version;
+        let batch_overhead = header_size(version) + 1;
+        let entry_len = 2 + packet.encoded_len(version);
+        if batch_overhead + entry_len > self.max_packet_size {
+            return socket.send_packet(&packet, relay_addr);
+        }
+
+        let destination_id = packet.destination_id;
+        let (queued, queued_len) = self.queues.entry(destination_id).or_insert_with(|| (Vec::new(), batch_overhead));
+        if queued.len() == u8::MAX as usize || *queued_len + entry_len > self.max_packet_size {
+            let full = std::mem::take(queued);
+            *queued_len = batch_overhead;
+            send_batch(socket, relay_addr, full)?;
+        }
+
+        queued.push(packet);
+        *queued_len += entry_len;
+        Ok(())
+    }
+
+    /// Send everything that is queued
+    pub fn flush(&mut self, socket: &NeonSocket<impl Transport>, relay_addr: SocketAddr) -> Result<(), Error> {
+        for (_, (queued, _)) in self.queues.drain() {
+            if !queued.is_empty() {
+                send_batch(socket, relay_addr, queued)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn send_batch(socket: &NeonSocket<impl Transport>, relay_addr: SocketAddr, mut packets: Vec<NeonPacket>) -> Result<(), Error> {
+    if packets.len() == 1 {
+        return socket.send_packet(&packets.remove(0), relay_addr);
     }
+
+    let first = &packets[0];
+    let batch_packet = NeonPacket {
+        packet_type: PacketType::Batch as u8,
+        sequence: first.sequence,
+        client_id: first.client_id,
+        destination_id: first.destination_id,
+        payload: PacketPayload::Batch(packets),
+    };
+    socket.send_packet(&batch_packet, relay_addr)
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+use std::io::Error;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use super::{ConnectionState, NeonClient};
+use crate::protocol::DisconnectReason;
+use crate::transport::{Outgoing, QueueTransport};
+
+/// Something that happened to a ClientProtocol
+#[derive(Debug)]
+pub enum ClientEvent {
+    Connected { client_id: u16, session_id: u32 },
+    ConnectFailed(Error),
+    GamePacket { packet_type: u8, from: u16, data: Vec<u8> },
+    ChatMessage { from: u16, name: String, text: String },
+    PeerJoined { client_id: u16, name: String },
+    PeerLeft { client_id: u16, name: String },
+    SessionClosed(DisconnectReason),
+}
+
+/// A client without a socket: feed it received datagrams and send what it returns. Events come
+/// out of `poll_event` instead of callbacks. Timers (retries, pings) read the system clock when
+/// `poll` or `handle_datagram` runs, so call `poll` every few milliseconds.
+pub struct ClientProtocol {
+    client: NeonClient<QueueTransport>,
+    transport: QueueTransport,
+    events: Arc<Mutex<VecDeque<ClientEvent>>>,
+}
+
+impl ClientProtocol {
+    pub fn new(name: String) -> Result<Self, Error> {
+        let transport = QueueTransport::new(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+        let mut client = NeonClient::with_transport(name, transport.clone())?;
+        let events = Arc::new(Mutex::new(VecDeque::new()));
+
+        let queue = events.clone();
+        client.on_connected(move |client_id, session_id| {
+            queue.lock().unwrap().push_back(ClientEvent::Connected { client_id, session_id });
+        });
+        let queue = events.clone();
+        client.on_connect_failed(move |e| {
+            queue.lock().unwrap().push_back(ClientEvent::ConnectFailed(Error::new(e.kind(), e.to_string())));
+        });
+        let queue = events.clone();
+        client.on_game_packet(move |packet_type, from, data| {
+            queue.lock().unwrap().push_back(ClientEvent::GamePacket { packet_type, from, data: data.to_vec() });
+        });
+        let queue = events.clone();
+        client.on_chat_message(move |from, name, text| {
+            queue.lock().unwrap().push_back(ClientEvent::ChatMessage { from, name, text });
+        });
+        let queue = events.clone();
+        client.on_peer_joined(move |client_id, name| {
+            queue.lock().unwrap().push_back(ClientEvent::PeerJoined { client_id, name });
+        });
+        let queue = events.clone();
+        client.on_peer_left(move |client_id, name| {
+            queue.lock().unwrap().push_back(ClientEvent::PeerLeft { client_id, name });
+        });
+        let queue = events.clone();
+        client.on_session_closed(move |reason| {
+            queue.lock().unwrap().push_back(ClientEvent::SessionClosed(reason));
+        });
+
+        Ok(Self { client, transport, events })
+    }
+
+    /// Start joining `session_id` through the relay at `relay_addr`, returning the request to send
+    pub fn connect(&mut self, session_id: u32, relay_addr: SocketAddr) -> Result<Outgoing, Error> {
+        self.client.begin_connect(session_id, relay_addr)?;
+        Ok(self.transport.take_outgoing())
+    }
+
+    /// Handle a datagram received from `from`, returning the datagrams to send in answer
+    pub fn handle_datagram(&mut self, data: &[u8], from: SocketAddr) -> Result<Outgoing, Error> {
+        self.transport.push_incoming(data, from);
+        self.poll()
+    }
+
+    /// Run timers and flush queued sends, returning the datagrams to send
+    pub fn poll(&mut self) -> Result<Outgoing, Error> {
+        if self.client.connection_state() != ConnectionState::Disconnected {
+            self.client.process_packets()?;
+        }
+        Ok(self.transport.take_outgoing())
+    }
+
+    /// Take the oldest event not yet taken
+    pub fn poll_event(&mut self) -> Option<ClientEvent> {
+        self.events.lock().unwrap().pop_front()
+    }
+
+    /// Get the client, to send or configure. Sends are returned by the next `poll`. Replacing a
+    /// callback ClientProtocol set stops that event being reported.
+    pub fn client(&mut self) -> &mut NeonClient<QueueTransport> {
+        &mut self.client
+    }
+}
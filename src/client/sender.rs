@@ -0,0 +1,57 @@
+use std::io::{Error, ErrorKind};
+use std::sync::mpsc::Sender;
+
+use crate::protocol;
+use crate::scheduler::Priority;
+
+/// A send requested through a ClientSender, made by the client at its next pump
+pub(crate) enum QueuedSend {
+    Ping,
+    Chat { text: String, destination_id: u16 },
+    Game { peer_id: u16, packet_type: u8, data: Vec<u8>, priority: Priority },
+}
+
+/// Handle for sending from other threads, from `NeonClient::sender`. Cloneable, Send and Sync.
+/// Sends are queued and go out on the client's next process_packets or flush; one that fails
+/// there is logged and dropped.
+#[derive(Clone)]
+pub struct ClientSender {
+    queue: Sender<QueuedSend>,
+}
+
+impl ClientSender {
+    pub(crate) fn new(queue: Sender<QueuedSend>) -> Self {
+        ClientSender { queue }
+    }
+
+    /// Queue a ping to the host
+    pub fn send_ping(&self) -> Result<(), Error> {
+        self.queue(QueuedSend::Ping)
+    }
+
+    /// Queue a chat message to one peer (1 is the host) or to everyone in the session (0)
+    pub fn send_chat(&self, text: &str, destination_id: u16) -> Result<(), Error> {
+        protocol::check_chat_length(text)?;
+        self.queue(QueuedSend::Chat { text: text.to_string(), destination_id })
+    }
+
+    /// Queue a game packet to the host
+    pub fn send_game_packet(&self, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.send_game_packet_to(1, packet_type, data)
+    }
+
+    /// Queue a game packet to another client in the session (or the host, 1)
+    pub fn send_game_packet_to(&self, peer_id: u16, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.send_game_packet_with_priority(peer_id, packet_type, data, Priority::Normal)
+    }
+
+    /// Queue a game packet to a peer at a priority
+    pub fn send_game_packet_with_priority(&self, peer_id: u16, packet_type: u8, data: &[u8], priority: Priority) -> Result<(), Error> {
+        protocol::check_game_packet_type(packet_type)?;
+        self.queue(QueuedSend::Game { peer_id, packet_type, data: data.to_vec(), priority })
+    }
+
+    fn queue(&self, send: QueuedSend) -> Result<(), Error> {
+        self.queue.send(send).map_err(|_| Error::new(ErrorKind::NotConnected, "Client has been dropped"))
+    }
+}
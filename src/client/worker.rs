@@ -0,0 +1,102 @@
+use std::io::Error;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{ClientSender, NeonClient};
+use crate::transport::Transport;
+
+/// How long the worker waits for traffic before pumping anyway, so queued sends and timers still fire
+const WORKER_WAKE: Duration = Duration::from_millis(10);
+
+/// The worker thread, which hands the client back with whatever stopped it
+type WorkerThread<T> = JoinHandle<(NeonClient<T>, Result<(), Error>)>;
+
+/// A client processing its packets on a background thread, from `NeonClient::spawn`.
+/// Callbacks run on that thread. Dropping the worker stops the thread and the client with it.
+pub struct ClientWorker<T: Transport = UdpSocket> {
+    sender: ClientSender,
+    running: Arc<AtomicBool>,
+    thread: Option<WorkerThread<T>>,
+}
+
+impl<T: Transport> ClientWorker<T> {
+    pub(crate) fn spawn(client: NeonClient<T>) -> Result<Self, Error> {
+        let sender = client.sender();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            thread::Builder::new()
+                .name(format!("neon-client-{}", client.name()))
+                .spawn(move || run(client, &running))?
+        };
+        Ok(Self { sender, running, thread: Some(thread) })
+    }
+
+    /// Get a handle other threads can send through
+    pub fn sender(&self) -> ClientSender {
+        self.sender.clone()
+    }
+
+    /// Queue a ping to the host
+    pub fn send_ping(&self) -> Result<(), Error> {
+        self.sender.send_ping()
+    }
+
+    /// Queue a chat message to one peer (1 is the host) or to everyone in the session (0)
+    pub fn send_chat(&self, text: &str, destination_id: u16) -> Result<(), Error> {
+        self.sender.send_chat(text, destination_id)
+    }
+
+    /// Queue a game packet to the host
+    pub fn send_game_packet(&self, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.sender.send_game_packet(packet_type, data)
+    }
+
+    /// Queue a game packet to another client in the session (or the host, 1)
+    pub fn send_game_packet_to(&self, peer_id: u16, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.sender.send_game_packet_to(peer_id, packet_type, data)
+    }
+
+    /// Check whether the worker is still processing packets; it stops on the first error
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Stop the worker and take the client back. Fails with the error that stopped the worker
+    /// early, if one did.
+    pub fn disconnect(mut self) -> Result<NeonClient<T>, Error> {
+        self.running.store(false, Ordering::Relaxed);
+        let thread = self.thread.take().expect("worker thread already joined");
+        let (client, result) = thread.join().map_err(|_| Error::other("Client worker panicked"))?;
+        result.map(|_| client)
+    }
+}
+
+impl<T: Transport> Drop for ClientWorker<T> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run<T: Transport>(mut client: NeonClient<T>, running: &AtomicBool) -> (NeonClient<T>, Result<(), Error>) {
+    let mut pump = || {
+        loop {
+            client.process_packets()?;
+            if !running.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            client.socket.socket.wait_readable(WORKER_WAKE)?;
+        }
+    };
+    let result = pump();
+    if let Err(e) = &result {
+        println!("[Client] Worker stopped: {}", e);
+    }
+    (client, result)
+}
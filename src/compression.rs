@@ -0,0 +1,62 @@
+use std::io::{Error, ErrorKind};
+
+/// Set in the header version byte when the payload is LZ4-compressed
+pub const COMPRESSED_FLAG: u8 = 0x80;
+
+/// A reasonable threshold to pass to set_compression_threshold: game payloads at or above this many bytes
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 256;
+
+/// Upper bound on the decompressed size, so a hostile length prefix can't force a huge allocation
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024;
+
+/// Whether this build was compiled with the `lz4` feature
+pub fn is_available() -> bool {
+    cfg!(feature = "lz4")
+}
+
+/// Compress a payload if it meets the threshold and actually gets smaller
+pub fn maybe_compress(data: &[u8], threshold: Option<usize>) -> Option<Vec<u8>> {
+    let threshold = threshold?;
+    if data.len() < threshold {
+        return None;
+    }
+    let compressed = compress(data)?;
+    if compressed.len() < data.len() {
+        Some(compressed)
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "lz4")]
+fn compress(data: &[u8]) -> Option<Vec<u8>> {
+    Some(lz4_flex::compress_prepend_size(data))
+}
+
+#[cfg(not(feature = "lz4"))]
+fn compress(_data: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+/// Decompress a payload produced by `maybe_compress`
+#[cfg(feature = "lz4")]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < 4 {
+        return Err(Error::new(ErrorKind::InvalidData, "Compressed payload too short"));
+    }
+    let size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if size > MAX_DECOMPRESSED_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "Compressed payload too large"));
+    }
+    lz4_flex::decompress(&data[4..], size)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Decompression failed: {}", e)))
+}
+
+/// Decompress a payload produced by `maybe_compress`
+#[cfg(not(feature = "lz4"))]
+pub fn decompress(_data: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "Received compressed packet but lz4 support is not compiled in",
+    ))
+}
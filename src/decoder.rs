@@ -0,0 +1,95 @@
+use std::io::{Error, ErrorKind};
+
+/// Bounds-checked little-endian reader over a received buffer.
+/// Every read either returns a value or an InvalidData error naming what was being parsed;
+/// nothing indexes past the end of the input.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    context: &'static str,
+}
+
+impl<'a> Decoder<'a> {
+    /// `context` names the structure being decoded and prefixes error messages
+    pub fn new(data: &'a [u8], context: &'static str) -> Self {
+        Self { data, pos: 0, context }
+    }
+
+    /// Bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Bytes consumed so far
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Error for input that is structurally wrong rather than short
+    pub fn malformed(&self) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("{} malformed", self.context))
+    }
+
+    fn too_short(&self) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("{} too short", self.context))
+    }
+
+    /// Take the next `len` bytes without copying
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.remaining() {
+            return Err(self.too_short());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// Read a string of `len` bytes, replacing invalid UTF-8
+    pub fn string(&mut self, len: usize) -> Result<String, Error> {
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    /// Read a string prefixed by a one-byte length
+    pub fn short_string(&mut self) -> Result<String, Error> {
+        let len = self.u8()? as usize;
+        self.string(len)
+    }
+
+    /// Take everything that is left
+    pub fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.pos..];
+        self.pos = self.data.len();
+        rest
+    }
+
+    /// Read everything that is left as a string, replacing invalid UTF-8
+    pub fn rest_string(&mut self) -> String {
+        String::from_utf8_lossy(self.rest()).into_owned()
+    }
+}
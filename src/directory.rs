@@ -0,0 +1,103 @@
+use std::io::{Error, ErrorKind};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::protocol::{self, NeonPacket, PacketPayload, PacketType, SessionRedirect, MAX_DATAGRAM_SIZE};
+use crate::resolve::unspecified_bind_addr;
+use crate::transport::Transport;
+
+/// How long to wait for a relay to answer a query before asking again
+pub const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Queries sent to one relay before it's treated as unreachable
+const QUERY_ATTEMPTS: usize = 2;
+
+/// Round-trip time each host or client registered with a relay counts as when choosing between relays
+pub const LOAD_COST: Duration = Duration::from_millis(1);
+
+/// Find the relay to use for `session_id` by asking `directory`, any relay in a peered group.
+/// If the session exists, that's the relay that owns it. Otherwise every relay the directory
+/// lists is probed and the one with the lowest round trip plus LOAD_COST per registered peer wins,
+/// which is where a host should register a new session.
+pub fn find_relay(directory: SocketAddr, session_id: u32) -> Result<SocketAddr, Error> {
+    let socket = UdpSocket::bind(unspecified_bind_addr(&directory))?;
+    find_relay_with(&socket, directory, session_id)
+}
+
+/// Like `find_relay`, over a blocking transport, e.g. the one a client will connect with
+pub fn find_relay_with<T: Transport>(transport: &T, directory: SocketAddr, session_id: u32) -> Result<SocketAddr, Error> {
+    let result = choose_relay(transport, directory, session_id);
+    transport.set_read_timeout(None)?;
+    result
+}
+
+fn choose_relay<T: Transport>(transport: &T, directory: SocketAddr, session_id: u32) -> Result<SocketAddr, Error> {
+    let (candidates, _) = query(transport, directory, session_id)?;
+    match candidates.as_slice() {
+        // A directory that can't name itself is the only choice
+        [] => return Ok(directory),
+        // The owner, or the only relay there is
+        [(only, _)] => return Ok(*only),
+        _ => {}
+    }
+
+    let mut best: Option<(Duration, SocketAddr)> = None;
+    for (candidate, _) in candidates {
+        let Ok((answer, round_trip)) = query(transport, candidate, session_id) else {
+            println!("[Directory] Relay {} didn't answer, skipping it", candidate);
+            continue;
+        };
+        // The session was created while probing
+        if let [(owner, _)] = answer.as_slice() {
+            return Ok(*owner);
+        }
+        // Relays list themselves first
+        let load = answer.first().map_or(0, |&(_, load)| load);
+        let score = round_trip + LOAD_COST * load;
+        if best.is_none_or(|(best_score, _)| score < best_score) {
+            best = Some((score, candidate));
+        }
+    }
+    best.map(|(_, relay)| relay)
+        .ok_or_else(|| Error::new(ErrorKind::TimedOut, "No relay the directory listed answered"))
+}
+
+/// Ask `relay` where `session_id` is, returning the relays it lists with their loads and the round trip
+fn query<T: Transport>(transport: &T, relay: SocketAddr, session_id: u32) -> Result<(Vec<(SocketAddr, u32)>, Duration), Error> {
+    let query = protocol::encode(&NeonPacket {
+        packet_type: PacketType::SessionRedirect as u8,
+        sequence: 0,
+        client_id: 0,
+        destination_id: 0,
+        payload: PacketPayload::SessionRedirect(SessionRedirect { session_id, relays: Vec::new() }),
+    });
+
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    for _ in 0..QUERY_ATTEMPTS {
+        let sent_at = Instant::now();
+        transport.send_to(&query, relay)?;
+
+        while let Some(remaining) = QUERY_TIMEOUT.checked_sub(sent_at.elapsed()) {
+            transport.set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+            let (len, from) = match transport.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(e) => return Err(e),
+            };
+            if from != relay {
+                continue;
+            }
+            if let Ok(packet) = protocol::decode(&buf[..len])
+                && let PacketPayload::SessionRedirect(answer) = packet.payload
+                && answer.session_id == session_id
+            {
+                let relays = answer.relays
+                    .iter()
+                    .filter_map(|info| Some((info.addr.parse().ok()?, info.load)))
+                    .collect();
+                return Ok((relays, sent_at.elapsed()));
+            }
+        }
+    }
+    Err(Error::new(ErrorKind::TimedOut, format!("Relay {} didn't answer", relay)))
+}
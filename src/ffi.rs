@@ -1,9 +1,18 @@
+// Every exported function null-checks its handle before dereferencing it
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 use std::ffi::{CStr, CString};
-use std::os::raw::c_char;
+use std::io::{Error, ErrorKind};
+use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle, sleep};
+use std::time::Duration;
 
-use crate::client::NeonClient;
-use crate::host::NeonHost;
+use crate::client::{ClientSender, ConnectionState, NeonClient};
+use crate::host::{HostSender, NeonHost};
+use crate::relay::{NeonRelay, RelayHandle};
 
 #[repr(C)]
 pub struct NeonClientHandle {
@@ -15,34 +24,202 @@ pub struct NeonHostHandle {
     _private: [u8; 0],
 }
 
-pub type PongCallbackC = extern "C" fn(response_time_ms: u64, timestamp: u64);
-pub type SessionConfigCallbackC = extern "C" fn(version: u8, tick_rate: u16, max_packet_size: u16);
-pub type PacketTypeRegistryCallbackC = extern "C" fn(count: usize, ids: *const u8, names: *const *const c_char, descriptions: *const *const c_char);
-pub type UnhandledPacketCallbackC = extern "C" fn(packet_type: u8, from_client_id: u8);
-pub type WrongDestinationCallbackC = extern "C" fn(my_id: u8, packet_destination_id: u8);
+#[repr(C)]
+pub struct NeonRelayHandle {
+    _private: [u8; 0],
+}
+
+/// What a NeonClientHandle points to. Calls from different threads take turns on the lock;
+/// sends that find it held, e.g. from inside a callback, are queued through the sender instead.
+struct ClientState {
+    client: Mutex<NeonClient>,
+    sender: ClientSender,
+}
+
+/// How long the host loop sleeps between passes
+const HOST_PASS_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What a NeonHostHandle points to, locked the same way as ClientState. The thread from
+/// neon_host_start_threaded shares the lock.
+struct HostState {
+    host: Arc<Mutex<NeonHost>>,
+    sender: HostSender,
+    registered: AtomicBool,
+    thread: Mutex<Option<HostThread>>,
+}
+
+/// The thread neon_host_start_threaded runs the host on
+struct HostThread {
+    running: Arc<AtomicBool>,
+    thread: JoinHandle<Result<(), Error>>,
+}
+
+impl HostState {
+    /// Register the session with the relay, unless an earlier call already did
+    fn ensure_registered(&self) -> Result<(), Error> {
+        let mut host = self.host.lock().unwrap_or_else(PoisonError::into_inner);
+        if !self.registered.load(Ordering::Relaxed) {
+            host.register()?;
+            self.registered.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Stop and join the host thread, returning what stopped it, or None if it wasn't running
+    fn stop_thread(&self) -> Option<Result<(), Error>> {
+        let thread = self.thread.lock().unwrap_or_else(PoisonError::into_inner).take()?;
+        thread.running.store(false, Ordering::Relaxed);
+        Some(thread.thread.join().unwrap_or_else(|_| Err(Error::other("Host thread panicked"))))
+    }
+}
+
+impl Drop for HostState {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+/// Pass over the host until told to stop or a pass fails, locking for each pass
+fn run_host(host: &Mutex<NeonHost>, running: &AtomicBool) -> Result<(), Error> {
+    while running.load(Ordering::Relaxed) {
+        host.lock().unwrap_or_else(PoisonError::into_inner).process_packets()?;
+        sleep(HOST_PASS_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Borrow the state behind a non-null client handle
+fn client_state<'a>(client: *mut NeonClientHandle) -> &'a ClientState {
+    unsafe { &*(client as *const ClientState) }
+}
+
+fn lock_client<'a>(client: *mut NeonClientHandle) -> MutexGuard<'a, NeonClient> {
+    // A panicking callback shouldn't leave the handle unusable
+    client_state(client).client.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Borrow the state behind a non-null host handle
+fn host_state<'a>(host: *mut NeonHostHandle) -> &'a HostState {
+    unsafe { &*(host as *const HostState) }
+}
+
+fn lock_host<'a>(host: *mut NeonHostHandle) -> MutexGuard<'a, NeonHost> {
+    host_state(host).host.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What a NeonRelayHandle points to. neon_relay_start holds the lock while the relay runs, so
+/// stopping and counting go through the relay's handle instead.
+struct RelayState {
+    relay: Mutex<NeonRelay>,
+    handle: RelayHandle,
+}
+
+/// Borrow the state behind a non-null relay handle
+fn relay_state<'a>(relay: *mut NeonRelayHandle) -> &'a RelayState {
+    unsafe { &*(relay as *const RelayState) }
+}
+
+fn lock_relay<'a>(relay: *mut NeonRelayHandle) -> MutexGuard<'a, NeonRelay> {
+    relay_state(relay).relay.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Result of a fallible call. Ok is 0; neon_get_last_error has the details of anything else.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeonErrorCode {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    NotConnected = 3,
+    NotFound = 4,
+    TimedOut = 5,
+    ConnectionDenied = 6,
+    WouldBlock = 7,
+    Io = 8,
+}
+
+/// Where a client is in joining a session
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeonConnectionState {
+    Disconnected = 0,
+    Connecting = 1,
+    Connected = 2,
+    AwaitingConfig = 3,
+    Disconnecting = 4,
+}
+
+impl From<ConnectionState> for NeonConnectionState {
+    fn from(state: ConnectionState) -> Self {
+        match state {
+            ConnectionState::Disconnected => NeonConnectionState::Disconnected,
+            ConnectionState::Connecting => NeonConnectionState::Connecting,
+            ConnectionState::AwaitingConfig => NeonConnectionState::AwaitingConfig,
+            ConnectionState::Connected => NeonConnectionState::Connected,
+            ConnectionState::Disconnecting => NeonConnectionState::Disconnecting,
+        }
+    }
+}
+
+impl From<ErrorKind> for NeonErrorCode {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::InvalidInput | ErrorKind::InvalidData | ErrorKind::Unsupported => NeonErrorCode::InvalidArgument,
+            ErrorKind::NotConnected => NeonErrorCode::NotConnected,
+            ErrorKind::NotFound | ErrorKind::AddrNotAvailable => NeonErrorCode::NotFound,
+            ErrorKind::TimedOut => NeonErrorCode::TimedOut,
+            ErrorKind::ConnectionRefused | ErrorKind::PermissionDenied => NeonErrorCode::ConnectionDenied,
+            ErrorKind::WouldBlock => NeonErrorCode::WouldBlock,
+            _ => NeonErrorCode::Io,
+        }
+    }
+}
 
-pub type ClientConnectCallbackC = extern "C" fn(client_id: u8, name: *const c_char, session_id: u32);
-pub type ClientDenyCallbackC = extern "C" fn(name: *const c_char, reason: *const c_char);
-pub type PingReceivedCallbackC = extern "C" fn(from_client_id: u8);
-pub type HostUnhandledPacketCallbackC = extern "C" fn(packet_type: u8, from_client_id: u8);
+/// The caller's context pointer, handed back to their callback untouched. Rust never reads
+/// through it, and the caller promises it may be used from whichever thread runs callbacks.
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+impl UserData {
+    // Called rather than reading the field, so closures capture the Send wrapper, not the bare pointer
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+pub type PongCallbackC = extern "C" fn(response_time_ms: u64, timestamp: u64, user_data: *mut c_void);
+pub type SessionConfigCallbackC = extern "C" fn(version: u8, tick_rate: u16, max_packet_size: u16, user_data: *mut c_void);
+pub type PacketTypeRegistryCallbackC = extern "C" fn(count: usize, ids: *const u8, names: *const *const c_char, descriptions: *const *const c_char, user_data: *mut c_void);
+pub type UnhandledPacketCallbackC = extern "C" fn(packet_type: u8, from_client_id: u16, user_data: *mut c_void);
+pub type WrongDestinationCallbackC = extern "C" fn(my_id: u16, packet_destination_id: u16, user_data: *mut c_void);
+pub type GamePacketCallbackC = extern "C" fn(packet_type: u8, from_client_id: u16, data: *const u8, len: usize, user_data: *mut c_void);
+
+pub type ClientConnectCallbackC = extern "C" fn(client_id: u16, name: *const c_char, session_id: u32, user_data: *mut c_void);
+pub type ClientDenyCallbackC = extern "C" fn(name: *const c_char, reason: *const c_char, user_data: *mut c_void);
+pub type PingReceivedCallbackC = extern "C" fn(from_client_id: u16, user_data: *mut c_void);
+pub type HostUnhandledPacketCallbackC = extern "C" fn(packet_type: u8, from_client_id: u16, user_data: *mut c_void);
+
+pub type RelaySessionCallbackC = extern "C" fn(session_id: u32, user_data: *mut c_void);
 
 /// Create a new Neon client
-/// Returns null on failure
+/// Returns null on failure (see neon_get_last_error)
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_client_new(name: *const c_char) -> *mut NeonClientHandle {
-    if name.is_null() {
-        return ptr::null_mut();
-    }
-
-    let c_str = unsafe { CStr::from_ptr(name) };
-    let name_str = match c_str.to_str() {
-        Ok(s) => s.to_string(),
+    let name = match c_string(name, "Client name") {
+        Ok(name) => name,
         Err(_) => return ptr::null_mut(),
     };
 
-    match NeonClient::new(name_str) {
-        Ok(client) => Box::into_raw(Box::new(client)) as *mut NeonClientHandle,
-        Err(_) => ptr::null_mut(),
+    match NeonClient::new(name.to_string()) {
+        Ok(client) => {
+            let state = ClientState { sender: client.sender(), client: Mutex::new(client) };
+            Box::into_raw(Box::new(state)) as *mut NeonClientHandle
+        }
+        Err(e) => {
+            set_last_error(&e.to_string());
+            ptr::null_mut()
+        }
     }
 }
 
@@ -51,14 +228,17 @@ pub extern "C" fn neon_client_new(name: *const c_char) -> *mut NeonClientHandle
 pub extern "C" fn neon_client_set_pong_callback(
     client: *mut NeonClientHandle,
     callback: PongCallbackC,
+    user_data: *mut c_void,
 ) {
     if client.is_null() {
+        null_handle("Client");
         return;
     }
 
-    let client = unsafe { &mut *(client as *mut NeonClient) };
+    let user_data = UserData(user_data);
+    let mut client = lock_client(client);
     client.on_pong(move |response_time, timestamp| {
-        callback(response_time, timestamp);
+        callback(response_time, timestamp, user_data.get());
     });
 }
 
@@ -67,14 +247,17 @@ pub extern "C" fn neon_client_set_pong_callback(
 pub extern "C" fn neon_client_set_session_config_callback(
     client: *mut NeonClientHandle,
     callback: SessionConfigCallbackC,
+    user_data: *mut c_void,
 ) {
     if client.is_null() {
+        null_handle("Client");
         return;
     }
 
-    let client = unsafe { &mut *(client as *mut NeonClient) };
+    let user_data = UserData(user_data);
+    let mut client = lock_client(client);
     client.on_session_config(move |version, tick_rate, max_packet_size| {
-        callback(version, tick_rate, max_packet_size);
+        callback(version, tick_rate, max_packet_size, user_data.get());
     });
 }
 
@@ -83,12 +266,15 @@ pub extern "C" fn neon_client_set_session_config_callback(
 pub extern "C" fn neon_client_set_packet_type_registry_callback(
     client: *mut NeonClientHandle,
     callback: PacketTypeRegistryCallbackC,
+    user_data: *mut c_void,
 ) {
     if client.is_null() {
+        null_handle("Client");
         return;
     }
 
-    let client = unsafe { &mut *(client as *mut NeonClient) };
+    let user_data = UserData(user_data);
+    let mut client = lock_client(client);
     
     client.on_packet_type_registry(move |entries| {
         let count = entries.len();
@@ -106,13 +292,13 @@ pub extern "C" fn neon_client_set_packet_type_registry_callback(
             })
             .collect();
         
-        callback(count, ids.as_ptr(), names.as_ptr(), descriptions.as_ptr());
+        callback(count, ids.as_ptr(), names.as_ptr(), descriptions.as_ptr(), user_data.get());
         
         for name in names {
-            unsafe { CString::from_raw(name as *mut c_char) };
+            drop(unsafe { CString::from_raw(name as *mut c_char) });
         }
         for desc in descriptions {
-            unsafe { CString::from_raw(desc as *mut c_char) };
+            drop(unsafe { CString::from_raw(desc as *mut c_char) });
         }
     });
 }
@@ -122,14 +308,17 @@ pub extern "C" fn neon_client_set_packet_type_registry_callback(
 pub extern "C" fn neon_client_set_unhandled_packet_callback(
     client: *mut NeonClientHandle,
     callback: UnhandledPacketCallbackC,
+    user_data: *mut c_void,
 ) {
     if client.is_null() {
+        null_handle("Client");
         return;
     }
 
-    let client = unsafe { &mut *(client as *mut NeonClient) };
+    let user_data = UserData(user_data);
+    let mut client = lock_client(client);
     client.on_unhandled_packet(move |packet_type, from_client_id| {
-        callback(packet_type, from_client_id);
+        callback(packet_type, from_client_id, user_data.get());
     });
 }
 
@@ -138,59 +327,122 @@ pub extern "C" fn neon_client_set_unhandled_packet_callback(
 pub extern "C" fn neon_client_set_wrong_destination_callback(
     client: *mut NeonClientHandle,
     callback: WrongDestinationCallbackC,
+    user_data: *mut c_void,
 ) {
     if client.is_null() {
+        null_handle("Client");
         return;
     }
 
-    let client = unsafe { &mut *(client as *mut NeonClient) };
+    let user_data = UserData(user_data);
+    let mut client = lock_client(client);
     client.on_wrong_destination(move |my_id, packet_destination_id| {
-        callback(my_id, packet_destination_id);
+        callback(my_id, packet_destination_id, user_data.get());
+    });
+}
+
+/// Set callback for game packets (0x10+). `data` is only valid during the call.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_set_game_packet_callback(
+    client: *mut NeonClientHandle,
+    callback: GamePacketCallbackC,
+    user_data: *mut c_void,
+) {
+    if client.is_null() {
+        null_handle("Client");
+        return;
+    }
+
+    let user_data = UserData(user_data);
+    let mut client = lock_client(client);
+    client.on_game_packet(move |packet_type, from_client_id, data| {
+        callback(packet_type, from_client_id, data.as_ptr(), data.len(), user_data.get());
     });
 }
 
 /// Connect the client to a session
-/// Returns true on success, false on failure
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_client_connect(
     client: *mut NeonClientHandle,
     session_id: u32,
     relay_addr: *const c_char,
-) -> bool {
-    if client.is_null() || relay_addr.is_null() {
-        return false;
+) -> NeonErrorCode {
+    if client.is_null() {
+        return null_handle("Client");
+    }
+    let addr = match c_string(relay_addr, "Relay address") {
+        Ok(addr) => addr,
+        Err(code) => return code,
+    };
+
+    report(lock_client(client).connect(session_id, addr))
+}
+
+/// Start connecting the client to a session without waiting; neon_client_process_packets advances the attempt
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_begin_connect(
+    client: *mut NeonClientHandle,
+    session_id: u32,
+    relay_addr: *const c_char,
+) -> NeonErrorCode {
+    if client.is_null() {
+        return null_handle("Client");
+    }
+    let addr = match c_string(relay_addr, "Relay address") {
+        Ok(addr) => addr,
+        Err(code) => return code,
+    };
+
+    report(lock_client(client).begin_connect(session_id, addr))
+}
+
+/// Process packets until the host's session config arrives, for up to `timeout_ms`
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_wait_until_configured(client: *mut NeonClientHandle, timeout_ms: u64) -> NeonErrorCode {
+    if client.is_null() {
+        return null_handle("Client");
     }
 
-    let client = unsafe { &mut *(client as *mut NeonClient) };
-    let c_str = unsafe { CStr::from_ptr(relay_addr) };
-    let addr = match c_str.to_str() {
-        Ok(s) => s,
-        Err(_) => return false,
+    report(lock_client(client).wait_until_configured(Duration::from_millis(timeout_ms)))
+}
+
+/// Connect the client to a session through whichever relay the directory relay picks
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_connect_via_directory(
+    client: *mut NeonClientHandle,
+    directory_addr: *const c_char,
+    session_id: u32,
+) -> NeonErrorCode {
+    if client.is_null() {
+        return null_handle("Client");
+    }
+    let addr = match c_string(directory_addr, "Directory address") {
+        Ok(addr) => addr,
+        Err(code) => return code,
     };
 
-    client.connect(session_id, addr).is_ok()
+    report(lock_client(client).connect_via_directory(addr, session_id))
 }
 
 /// Process incoming packets (call this regularly, e.g. in your game tick)
-/// Returns true on success, false on failure
 #[unsafe(no_mangle)]
-pub extern "C" fn neon_client_process_packets(client: *mut NeonClientHandle) -> bool {
+pub extern "C" fn neon_client_process_packets(client: *mut NeonClientHandle) -> NeonErrorCode {
     if client.is_null() {
-        return false;
+        return null_handle("Client");
     }
 
-    let client = unsafe { &mut *(client as *mut NeonClient) };
-    client.process_packets().is_ok()
+    report(lock_client(client).process_packets())
 }
 
 /// Get the client's assigned ID (returns 0 if not connected)
 #[unsafe(no_mangle)]
-pub extern "C" fn neon_client_get_id(client: *mut NeonClientHandle) -> u8 {
+pub extern "C" fn neon_client_get_id(client: *mut NeonClientHandle) -> u16 {
     if client.is_null() {
+        null_handle("Client");
         return 0;
     }
 
-    let client = unsafe { &*(client as *const NeonClient) };
+    let client = lock_client(client);
     client.client_id().unwrap_or(0)
 }
 
@@ -198,73 +450,158 @@ pub extern "C" fn neon_client_get_id(client: *mut NeonClientHandle) -> u8 {
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_client_get_session_id(client: *mut NeonClientHandle) -> u32 {
     if client.is_null() {
+        null_handle("Client");
         return 0;
     }
 
-    let client = unsafe { &*(client as *const NeonClient) };
+    let client = lock_client(client);
     client.session_id().unwrap_or(0)
 }
 
+/// Get whether the client is connected, connecting, or neither
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_get_connection_state(client: *mut NeonClientHandle) -> NeonConnectionState {
+    if client.is_null() {
+        null_handle("Client");
+        return NeonConnectionState::Disconnected;
+    }
+
+    let client = lock_client(client);
+    client.connection_state().into()
+}
+
+/// Get the client's state in full, including waiting on SessionConfig and disconnecting
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_get_state(client: *mut NeonClientHandle) -> NeonConnectionState {
+    if client.is_null() {
+        null_handle("Client");
+        return NeonConnectionState::Disconnected;
+    }
+
+    let client = lock_client(client);
+    client.state().into()
+}
+
+/// Leave the session, sending whatever is queued first
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_disconnect(client: *mut NeonClientHandle) -> NeonErrorCode {
+    if client.is_null() {
+        return null_handle("Client");
+    }
+
+    report(lock_client(client).disconnect())
+}
+
 /// Check if the client is connected
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_client_is_connected(client: *mut NeonClientHandle) -> bool {
     if client.is_null() {
+        null_handle("Client");
         return false;
     }
 
-    let client = unsafe { &*(client as *const NeonClient) };
-    client.client_id().is_some()
+    let client = lock_client(client);
+    client.connection_state() == ConnectionState::Connected
 }
 
 /// Manually send a ping
 #[unsafe(no_mangle)]
-pub extern "C" fn neon_client_send_ping(client: *mut NeonClientHandle) -> bool {
+pub extern "C" fn neon_client_send_ping(client: *mut NeonClientHandle) -> NeonErrorCode {
     if client.is_null() {
-        return false;
+        return null_handle("Client");
     }
 
-    let client = unsafe { &*(client as *const NeonClient) };
-    client.send_ping().is_ok()
+    let state = client_state(client);
+    let result = match state.client.try_lock() {
+        Ok(mut client) => client.send_ping(),
+        Err(_) => state.sender.send_ping(),
+    };
+    report(result)
+}
+
+/// Send a game packet to another client in the session, or the host (1)
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_send_game_packet(
+    client: *mut NeonClientHandle,
+    packet_type: u8,
+    destination_id: u16,
+    data: *const u8,
+    len: usize,
+) -> NeonErrorCode {
+    if client.is_null() {
+        return null_handle("Client");
+    }
+    let data = match payload(data, len) {
+        Ok(data) => data,
+        Err(code) => return code,
+    };
+    let state = client_state(client);
+    let result = match state.client.try_lock() {
+        Ok(mut client) => client.send_game_packet_to(destination_id, packet_type, data),
+        Err(_) => state.sender.send_game_packet_to(destination_id, packet_type, data),
+    };
+    report(result)
 }
 
 /// Set auto-ping enabled/disabled
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_client_set_auto_ping(client: *mut NeonClientHandle, enabled: bool) {
     if client.is_null() {
+        null_handle("Client");
         return;
     }
 
-    let client = unsafe { &mut *(client as *mut NeonClient) };
+    let mut client = lock_client(client);
     client.set_auto_ping(enabled);
 }
 
+/// Set the token sent with connection requests (null or zero length sends none)
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_client_set_auth_token(client: *mut NeonClientHandle, token: *const u8, len: usize) {
+    if client.is_null() {
+        null_handle("Client");
+        return;
+    }
+
+    let mut client = lock_client(client);
+    if token.is_null() || len == 0 {
+        client.set_auth_token(Vec::new());
+    } else {
+        client.set_auth_token(unsafe { std::slice::from_raw_parts(token, len) });
+    }
+}
+
 /// Free the client (call when done)
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_client_free(client: *mut NeonClientHandle) {
     if !client.is_null() {
-        unsafe {
-            Box::from_raw(client as *mut NeonClient);
-        }
+        drop(unsafe { Box::from_raw(client as *mut ClientState) });
     }
 }
 
 /// Create a new Neon host
-/// Returns null on failure
+/// Returns null on failure (see neon_get_last_error)
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_host_new(session_id: u32, relay_addr: *const c_char) -> *mut NeonHostHandle {
-    if relay_addr.is_null() {
-        return ptr::null_mut();
-    }
-
-    let c_str = unsafe { CStr::from_ptr(relay_addr) };
-    let addr = match c_str.to_str() {
-        Ok(s) => s,
+    let addr = match c_string(relay_addr, "Relay address") {
+        Ok(addr) => addr,
         Err(_) => return ptr::null_mut(),
     };
 
     match NeonHost::new(session_id, addr) {
-        Ok(host) => Box::into_raw(Box::new(host)) as *mut NeonHostHandle,
-        Err(_) => ptr::null_mut(),
+        Ok(host) => {
+            let state = HostState {
+                sender: host.sender(),
+                host: Arc::new(Mutex::new(host)),
+                registered: AtomicBool::new(false),
+                thread: Mutex::new(None),
+            };
+            Box::into_raw(Box::new(state)) as *mut NeonHostHandle
+        }
+        Err(e) => {
+            set_last_error(&e.to_string());
+            ptr::null_mut()
+        }
     }
 }
 
@@ -273,15 +610,18 @@ pub extern "C" fn neon_host_new(session_id: u32, relay_addr: *const c_char) -> *
 pub extern "C" fn neon_host_set_client_connect_callback(
     host: *mut NeonHostHandle,
     callback: ClientConnectCallbackC,
+    user_data: *mut c_void,
 ) {
     if host.is_null() {
+        null_handle("Host");
         return;
     }
 
-    let host = unsafe { &mut *(host as *mut NeonHost) };
+    let user_data = UserData(user_data);
+    let mut host = lock_host(host);
     host.on_client_connect(move |client_id, name, session_id| {
         let c_name = CString::new(name.as_str()).unwrap();
-        callback(client_id, c_name.as_ptr(), session_id);
+        callback(client_id, c_name.as_ptr(), session_id, user_data.get());
     });
 }
 
@@ -290,16 +630,19 @@ pub extern "C" fn neon_host_set_client_connect_callback(
 pub extern "C" fn neon_host_set_client_deny_callback(
     host: *mut NeonHostHandle,
     callback: ClientDenyCallbackC,
+    user_data: *mut c_void,
 ) {
     if host.is_null() {
+        null_handle("Host");
         return;
     }
 
-    let host = unsafe { &mut *(host as *mut NeonHost) };
+    let user_data = UserData(user_data);
+    let mut host = lock_host(host);
     host.on_client_deny(move |name, reason| {
         let c_name = CString::new(name.as_str()).unwrap();
         let c_reason = CString::new(reason.as_str()).unwrap();
-        callback(c_name.as_ptr(), c_reason.as_ptr());
+        callback(c_name.as_ptr(), c_reason.as_ptr(), user_data.get());
     });
 }
 
@@ -308,14 +651,17 @@ pub extern "C" fn neon_host_set_client_deny_callback(
 pub extern "C" fn neon_host_set_ping_received_callback(
     host: *mut NeonHostHandle,
     callback: PingReceivedCallbackC,
+    user_data: *mut c_void,
 ) {
     if host.is_null() {
+        null_handle("Host");
         return;
     }
 
-    let host = unsafe { &mut *(host as *mut NeonHost) };
+    let user_data = UserData(user_data);
+    let mut host = lock_host(host);
     host.on_ping_received(move |from_client_id| {
-        callback(from_client_id);
+        callback(from_client_id, user_data.get());
     });
 }
 
@@ -324,25 +670,67 @@ pub extern "C" fn neon_host_set_ping_received_callback(
 pub extern "C" fn neon_host_set_unhandled_packet_callback(
     host: *mut NeonHostHandle,
     callback: HostUnhandledPacketCallbackC,
+    user_data: *mut c_void,
 ) {
     if host.is_null() {
+        null_handle("Host");
         return;
     }
 
-    let host = unsafe { &mut *(host as *mut NeonHost) };
+    let user_data = UserData(user_data);
+    let mut host = lock_host(host);
     host.on_unhandled_packet(move |packet_type, from_client_id, _addr| {
-        callback(packet_type, from_client_id);
+        callback(packet_type, from_client_id, user_data.get());
     });
 }
 
+/// Set callback for game packets (0x10+). `data` is only valid during the call.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_host_set_game_packet_callback(
+    host: *mut NeonHostHandle,
+    callback: GamePacketCallbackC,
+    user_data: *mut c_void,
+) {
+    if host.is_null() {
+        null_handle("Host");
+        return;
+    }
+
+    let user_data = UserData(user_data);
+    let mut host = lock_host(host);
+    host.on_game_packet(move |packet_type, from_client_id, data| {
+        callback(packet_type, from_client_id, data.as_ptr(), data.len(), user_data.get());
+    });
+}
+
+/// Describe a game packet type (0x10+) in the registry sent to connecting clients
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_host_register_packet_type(
+    host: *mut NeonHostHandle,
+    packet_id: u8,
+    name: *const c_char,
+    description: *const c_char,
+) -> NeonErrorCode {
+    if host.is_null() {
+        return null_handle("Host");
+    }
+    let (name, description) = match (c_string(name, "Packet type name"), c_string(description, "Packet type description")) {
+        (Ok(name), Ok(description)) => (name, description),
+        (Err(code), _) | (_, Err(code)) => return code,
+    };
+
+    report(lock_host(host).register_packet_type(packet_id, name, description))
+}
+
 /// Get the host's session ID
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_host_get_session_id(host: *mut NeonHostHandle) -> u32 {
     if host.is_null() {
+        null_handle("Host");
         return 0;
     }
 
-    let host = unsafe { &*(host as *const NeonHost) };
+    let host = lock_host(host);
     host.session_id()
 }
 
@@ -350,37 +738,271 @@ pub extern "C" fn neon_host_get_session_id(host: *mut NeonHostHandle) -> u32 {
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_host_get_client_count(host: *mut NeonHostHandle) -> usize {
     if host.is_null() {
+        null_handle("Host");
         return 0;
     }
 
-    let host = unsafe { &*(host as *const NeonHost) };
+    let host = lock_host(host);
     host.client_count()
 }
 
 /// Start the host (this blocks! Run in a separate thread)
-/// Returns true on success, false on failure
+/// Only returns on failure
 #[unsafe(no_mangle)]
-pub extern "C" fn neon_host_start(host: *mut NeonHostHandle) -> bool {
+pub extern "C" fn neon_host_start(host: *mut NeonHostHandle) -> NeonErrorCode {
     if host.is_null() {
-        return false;
+        return null_handle("Host");
+    }
+
+    // Lock for each pass rather than for good, so other threads can use the handle meanwhile
+    let state = host_state(host);
+    let code = report(state.ensure_registered());
+    if code != NeonErrorCode::Ok {
+        return code;
+    }
+    loop {
+        let code = report(lock_host(host).process_packets());
+        if code != NeonErrorCode::Ok {
+            return code;
+        }
+        sleep(HOST_PASS_INTERVAL);
+    }
+}
+
+/// Run one pass of the host: send what's due and handle every packet waiting, then return.
+/// Call it from your own loop, e.g. once per frame. The first call registers with the relay,
+/// waiting for its reply.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_host_update(host: *mut NeonHostHandle) -> NeonErrorCode {
+    if host.is_null() {
+        return null_handle("Host");
+    }
+
+    let state = host_state(host);
+    let code = report(state.ensure_registered());
+    if code != NeonErrorCode::Ok {
+        return code;
+    }
+    report(lock_host(host).process_packets())
+}
+
+/// Register with the relay, then run the host on a thread the library owns until neon_host_stop.
+/// Callbacks run on that thread. Returns once registered, so clients can connect straight away.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_host_start_threaded(host: *mut NeonHostHandle) -> NeonErrorCode {
+    if host.is_null() {
+        return null_handle("Host");
+    }
+
+    let state = host_state(host);
+    let mut slot = state.thread.lock().unwrap_or_else(PoisonError::into_inner);
+    if slot.is_some() {
+        return fail(NeonErrorCode::InvalidArgument, "Host is already running on a thread");
+    }
+    let code = report(state.ensure_registered());
+    if code != NeonErrorCode::Ok {
+        return code;
+    }
+
+    let running = Arc::new(AtomicBool::new(true));
+    let spawned = {
+        let (host, running) = (state.host.clone(), running.clone());
+        thread::Builder::new().name("neon-host".to_string()).spawn(move || {
+            let result = run_host(&host, &running);
+            if let Err(e) = &result {
+                println!("[Host] Host thread stopped: {}", e);
+            }
+            result
+        })
+    };
+    match spawned {
+        Ok(thread) => {
+            *slot = Some(HostThread { running, thread });
+            NeonErrorCode::Ok
+        }
+        Err(e) => report(Err(e)),
+    }
+}
+
+/// Stop the thread from neon_host_start_threaded and wait for it to finish. Returns the error that
+/// stopped it early, if one did.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_host_stop(host: *mut NeonHostHandle) -> NeonErrorCode {
+    if host.is_null() {
+        return null_handle("Host");
     }
 
-    let host = unsafe { &mut *(host as *mut NeonHost) };
-    host.start().is_ok()
+    match host_state(host).stop_thread() {
+        Some(result) => report(result),
+        None => fail(NeonErrorCode::InvalidArgument, "Host isn't running on a thread"),
+    }
+}
+
+/// Send a game packet to a connected client
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_host_send_to_client(
+    host: *mut NeonHostHandle,
+    client_id: u16,
+    packet_type: u8,
+    data: *const u8,
+    len: usize,
+) -> NeonErrorCode {
+    if host.is_null() {
+        return null_handle("Host");
+    }
+    let data = match payload(data, len) {
+        Ok(data) => data,
+        Err(code) => return code,
+    };
+    let state = host_state(host);
+    let result = match state.host.try_lock() {
+        Ok(mut host) => host.send_game_packet(client_id, packet_type, data),
+        Err(_) => state.sender.send_game_packet(client_id, packet_type, data),
+    };
+    report(result)
 }
 
-/// Free the host (call when done)
+/// Send a game packet to every connected client
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_host_broadcast(host: *mut NeonHostHandle, packet_type: u8, data: *const u8, len: usize) -> NeonErrorCode {
+    if host.is_null() {
+        return null_handle("Host");
+    }
+    let data = match payload(data, len) {
+        Ok(data) => data,
+        Err(code) => return code,
+    };
+    let state = host_state(host);
+    let result = match state.host.try_lock() {
+        Ok(mut host) => host.broadcast_game_packet(packet_type, data),
+        Err(_) => state.sender.broadcast_game_packet(packet_type, data),
+    };
+    report(result)
+}
+
+/// Free the host (call when done), stopping its thread if neon_host_start_threaded started one
 #[unsafe(no_mangle)]
 pub extern "C" fn neon_host_free(host: *mut NeonHostHandle) {
     if !host.is_null() {
-        unsafe {
-            Box::from_raw(host as *mut NeonHost);
+        drop(unsafe { Box::from_raw(host as *mut HostState) });
+    }
+}
+
+/// Create a new relay bound to `bind_addr`, e.g. "0.0.0.0:7777"
+/// Returns null on failure (see neon_get_last_error)
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_relay_new(bind_addr: *const c_char) -> *mut NeonRelayHandle {
+    let addr = match c_string(bind_addr, "Bind address") {
+        Ok(addr) => addr,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    match NeonRelay::new(addr) {
+        Ok(relay) => {
+            let state = RelayState { handle: relay.handle(), relay: Mutex::new(relay) };
+            Box::into_raw(Box::new(state)) as *mut NeonRelayHandle
+        }
+        Err(e) => {
+            set_last_error(&e.to_string());
+            ptr::null_mut()
         }
     }
 }
 
+/// Set callback for when a host registers a new session. Runs on the thread inside neon_relay_start.
+/// Must be set before neon_relay_start.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_relay_set_session_created_callback(
+    relay: *mut NeonRelayHandle,
+    callback: RelaySessionCallbackC,
+    user_data: *mut c_void,
+) {
+    if relay.is_null() {
+        null_handle("Relay");
+        return;
+    }
+
+    let user_data = UserData(user_data);
+    let mut relay = lock_relay(relay);
+    relay.on_session_created(move |session_id| {
+        callback(session_id, user_data.get());
+    });
+}
+
+/// Set callback for when a session ends, including when the relay stops. Runs on the thread inside
+/// neon_relay_start. Must be set before neon_relay_start.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_relay_set_session_destroyed_callback(
+    relay: *mut NeonRelayHandle,
+    callback: RelaySessionCallbackC,
+    user_data: *mut c_void,
+) {
+    if relay.is_null() {
+        null_handle("Relay");
+        return;
+    }
+
+    let user_data = UserData(user_data);
+    let mut relay = lock_relay(relay);
+    relay.on_session_destroyed(move |session_id| {
+        callback(session_id, user_data.get());
+    });
+}
+
+/// Start the relay (this blocks! Run in a separate thread)
+/// Returns NEON_ERROR_CODE_OK once neon_relay_stop is called, or the error that stopped it
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_relay_start(relay: *mut NeonRelayHandle) -> NeonErrorCode {
+    if relay.is_null() {
+        return null_handle("Relay");
+    }
+
+    report(lock_relay(relay).start())
+}
+
+/// Ask a running relay to stop; neon_relay_start returns within a second. Safe from any thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_relay_stop(relay: *mut NeonRelayHandle) {
+    if relay.is_null() {
+        null_handle("Relay");
+        return;
+    }
+
+    relay_state(relay).handle.stop();
+}
+
+/// Get the number of active sessions. Safe from any thread, while the relay runs.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_relay_session_count(relay: *mut NeonRelayHandle) -> usize {
+    if relay.is_null() {
+        null_handle("Relay");
+        return 0;
+    }
+
+    relay_state(relay).handle.session_count()
+}
+
+/// Get the number of connected clients across all sessions. Safe from any thread, while the relay runs.
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_relay_total_client_count(relay: *mut NeonRelayHandle) -> usize {
+    if relay.is_null() {
+        null_handle("Relay");
+        return 0;
+    }
+
+    relay_state(relay).handle.total_client_count()
+}
+
+/// Free the relay (call when done, after neon_relay_start has returned)
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_relay_free(relay: *mut NeonRelayHandle) {
+    if !relay.is_null() {
+        drop(unsafe { Box::from_raw(relay as *mut RelayState) });
+    }
+}
+
 thread_local! {
-    static LAST_ERROR: std::cell::RefCell<Option<CString>> = std::cell::RefCell::new(None);
+    static LAST_ERROR: std::cell::RefCell<Option<CString>> = const { std::cell::RefCell::new(None) };
 }
 
 /// Get the last error message (or null if no error)
@@ -399,4 +1021,59 @@ fn set_last_error(err: &str) {
     LAST_ERROR.with(|e| {
         *e.borrow_mut() = CString::new(err).ok();
     });
-}
\ No newline at end of file
+}
+
+/// Get a static description of an error code, for when the last error message isn't at hand
+#[unsafe(no_mangle)]
+pub extern "C" fn neon_error_message(code: c_int) -> *const c_char {
+    let message = match code {
+        0 => c"Success",
+        1 => c"A required pointer was null",
+        2 => c"Invalid argument",
+        3 => c"Not connected",
+        4 => c"Not found",
+        5 => c"Timed out",
+        6 => c"Connection denied",
+        7 => c"Would block; try again",
+        8 => c"I/O error",
+        _ => c"Unknown error code",
+    };
+    message.as_ptr()
+}
+
+/// Record `message` for neon_get_last_error and return `code`
+fn fail(code: NeonErrorCode, message: &str) -> NeonErrorCode {
+    set_last_error(message);
+    code
+}
+
+fn null_handle(kind: &str) -> NeonErrorCode {
+    fail(NeonErrorCode::NullPointer, &format!("{} handle is null", kind))
+}
+
+/// Borrow a C string as UTF-8; `what` names it in the error
+fn c_string<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, NeonErrorCode> {
+    if ptr.is_null() {
+        return Err(fail(NeonErrorCode::NullPointer, &format!("{} is null", what)));
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|_| fail(NeonErrorCode::InvalidArgument, &format!("{} must be valid UTF-8", what)))
+}
+
+/// Borrow `len` bytes from C; `data` may be null when `len` is 0
+fn payload<'a>(data: *const u8, len: usize) -> Result<&'a [u8], NeonErrorCode> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(fail(NeonErrorCode::NullPointer, "Payload is null but its length isn't 0")),
+        (false, _) => Ok(unsafe { std::slice::from_raw_parts(data, len) }),
+    }
+}
+
+/// The code for a result, recording any error for neon_get_last_error
+fn report(result: Result<(), Error>) -> NeonErrorCode {
+    match result {
+        Ok(()) => NeonErrorCode::Ok,
+        Err(e) => fail(NeonErrorCode::from(e.kind()), &e.to_string()),
+    }
+}
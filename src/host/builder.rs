@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use super::{HostConfig, NeonHost};
+use crate::protocol::FeatureSet;
+use crate::resolve::resolve_addrs;
+use crate::socket_options::{LocalPort, SocketOptions};
+use crate::transport::Transport;
+
+/// Collects a host's socket and session settings, then creates it with `build` (or
+/// `build_with_transport`). Anything not set keeps the default `NeonHost::new` uses.
+#[derive(Debug, Clone)]
+pub struct HostBuilder<A> {
+    session_id: u32,
+    relay_addr: A,
+    config: HostConfig,
+    socket_options: SocketOptions,
+    game_identifier: u32,
+}
+
+impl<A: ToSocketAddrs> HostBuilder<A> {
+    /// Start building a host for `session_id` on the relay at `relay_addr`, which may be a hostname
+    pub fn new(session_id: u32, relay_addr: A) -> Self {
+        Self {
+            session_id,
+            relay_addr,
+            config: HostConfig::default(),
+            socket_options: SocketOptions::default(),
+            game_identifier: 0,
+        }
+    }
+
+    /// Take every setting in `config`, replacing any set before
+    pub fn config(mut self, config: HostConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Tune the socket. Replaces any bind address or port set before.
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket_options = options;
+        self
+    }
+
+    /// Bind this local address, e.g. one interface of a multi-homed machine. Only relay
+    /// addresses of the same family are tried.
+    pub fn bind_ip(mut self, ip: IpAddr) -> Self {
+        self.socket_options.local_ip = Some(ip);
+        self
+    }
+
+    /// Bind this local port
+    pub fn port(mut self, port: u16) -> Self {
+        self.socket_options.local_port = LocalPort::Fixed(port);
+        self
+    }
+
+    /// Bind the first free local port in `ports`
+    pub fn port_range(mut self, ports: RangeInclusive<u16>) -> Self {
+        self.socket_options.local_port = LocalPort::Range(*ports.start(), *ports.end());
+        self
+    }
+
+    /// How long a reliable packet waits for its ack before it is resent
+    pub fn ack_timeout(mut self, timeout: Duration) -> Self {
+        self.config.ack_timeout = timeout;
+        self
+    }
+
+    /// Resends of a reliable packet before on_delivery_failed fires
+    pub fn max_retries(mut self, retries: u8) -> Self {
+        self.config.max_retries = retries;
+        self
+    }
+
+    /// See `NeonHost::set_relay_keepalive`
+    pub fn relay_keepalive(mut self, interval: Option<Duration>) -> Self {
+        self.config.relay_keepalive = interval;
+        self
+    }
+
+    /// How long a client may go unheard before the host drops it
+    pub fn client_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.client_timeout = timeout;
+        self
+    }
+
+    /// See `NeonHost::set_ping_interval`
+    pub fn ping_interval(mut self, interval: Option<Duration>) -> Self {
+        self.config.ping_interval = interval;
+        self
+    }
+
+    /// Tick rate advertised to clients; `build` fails if it is 0
+    pub fn tick_rate(mut self, tick_rate: u16) -> Self {
+        self.config.tick_rate = tick_rate;
+        self
+    }
+
+    /// See `NeonHost::set_features`
+    pub fn features(mut self, features: FeatureSet) -> Self {
+        self.config.features = features;
+        self
+    }
+
+    /// See `NeonHost::set_max_clients`
+    pub fn max_clients(mut self, max_clients: Option<usize>) -> Self {
+        self.config.max_clients = max_clients;
+        self
+    }
+
+    /// See `NeonHost::set_game_identifier`
+    pub fn game_identifier(mut self, game_identifier: u32) -> Self {
+        self.game_identifier = game_identifier;
+        self
+    }
+
+    /// Bind a UDP socket for the first resolved relay address one can be bound for, and create the host.
+    /// register moves on to the later addresses if that relay doesn't answer.
+    pub fn build(self) -> Result<NeonHost, Error> {
+        let addrs = resolve_addrs(&self.relay_addr)?;
+        let mut failures = Vec::new();
+        for (i, &addr) in addrs.iter().enumerate() {
+            match self.socket_options.bind_local(&addr) {
+                Ok(socket) => {
+                    let mut host = NeonHost::with_transport(self.session_id, addr, socket)?;
+                    host.socket_options = self.socket_options;
+                    host.relay_candidates = addrs[i + 1..].iter().copied().collect();
+                    return self.finish(host);
+                }
+                Err(e) if e.kind() == ErrorKind::InvalidInput => return Err(e),
+                Err(e) => failures.push(format!("{}: {}", addr, e)),
+            }
+        }
+        Err(Error::new(
+            ErrorKind::AddrNotAvailable,
+            format!("Could not bind a socket for any resolved relay address ({})", failures.join("; ")),
+        ))
+    }
+
+    /// Create the host over `transport`, talking to the first resolved relay address and moving on
+    /// to the others as build does. The socket options and bind address aren't used.
+    pub fn build_with_transport<T: Transport>(self, transport: T) -> Result<NeonHost<T>, Error> {
+        let mut addrs: VecDeque<SocketAddr> = resolve_addrs(&self.relay_addr)?.into();
+        let relay_addr = addrs.pop_front().expect("resolve_addrs returns at least one address");
+        let mut host = NeonHost::with_transport(self.session_id, relay_addr, transport)?;
+        host.relay_candidates = addrs;
+        self.finish(host)
+    }
+
+    fn finish<T: Transport>(&self, mut host: NeonHost<T>) -> Result<NeonHost<T>, Error> {
+        host.set_config(self.config.clone())?;
+        host.set_game_identifier(self.game_identifier);
+        Ok(host)
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use crate::protocol::FeatureSet;
+
+/// Tick rate advertised in SessionConfig unless set_tick_rate is called
+pub const DEFAULT_TICK_RATE: u16 = 60;
+
+/// How often the host checks in with the relay unless set_relay_keepalive is called
+pub const DEFAULT_RELAY_KEEPALIVE: Duration = Duration::from_secs(5);
+
+/// How often the host pings each client unless set_ping_interval is called
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a reliable packet waits for its ack before it is resent
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Resends of a reliable packet before the host gives up on it
+pub const DEFAULT_MAX_RETRIES: u8 = 5;
+
+/// Host settings, passed to `NeonHost::with_config` or applied with `set_config`.
+/// Start from `HostConfig::default()` and change the fields that matter.
+#[derive(Debug, Clone)]
+pub struct HostConfig {
+    /// How long a reliable packet waits for its ack before it is resent
+    pub ack_timeout: Duration,
+    /// Resends of a reliable packet before on_delivery_failed fires
+    pub max_retries: u8,
+    /// How often to check in with the relay (None stops checking in; see `set_relay_keepalive`)
+    pub relay_keepalive: Option<Duration>,
+    /// How long a client may go unheard before the host drops it. None leaves it to the relay's peer timeout.
+    pub client_timeout: Option<Duration>,
+    /// Tick rate advertised to clients; must be at least 1
+    pub tick_rate: u16,
+    /// Most clients connected at once (None for no cap)
+    pub max_clients: Option<usize>,
+    /// How often to ping each client (None stops pinging; see `set_ping_interval`)
+    pub ping_interval: Option<Duration>,
+    /// Features the session uses, offered to each client that joins (see `set_features`)
+    pub features: FeatureSet,
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        Self {
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            max_retries: DEFAULT_MAX_RETRIES,
+            relay_keepalive: Some(DEFAULT_RELAY_KEEPALIVE),
+            client_timeout: None,
+            tick_rate: DEFAULT_TICK_RATE,
+            max_clients: None,
+            ping_interval: Some(DEFAULT_PING_INTERVAL),
+            features: FeatureSet::empty(),
+        }
+    }
+}
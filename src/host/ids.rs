@@ -0,0 +1,55 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a released client ID is held back before it's handed to a new client
+pub const DEFAULT_ID_REUSE_DELAY: Duration = Duration::from_secs(5);
+
+/// Hands out client IDs (2 and up). Released IDs go on a free list and are only reused once
+/// they've sat there for the reuse delay, so late packets from a departed client aren't
+/// credited to whoever joins next, unless nothing else is left.
+pub struct ClientIds {
+    next_unused: u32,
+    released: VecDeque<(u16, Instant)>,
+    reuse_delay: Duration,
+}
+
+impl ClientIds {
+    pub fn new() -> Self {
+        Self {
+            next_unused: 2,
+            released: VecDeque::new(),
+            reuse_delay: DEFAULT_ID_REUSE_DELAY,
+        }
+    }
+
+    pub fn set_reuse_delay(&mut self, delay: Duration) {
+        self.reuse_delay = delay;
+    }
+
+    /// Take an ID no higher than `max_id`: the longest-released one past its delay, else one
+    /// never handed out, else the longest-released one regardless. None when every ID is in use.
+    pub fn allocate(&mut self, max_id: u16) -> Option<u16> {
+        let now = Instant::now();
+        let settled = self
+            .released
+            .iter()
+            .position(|&(id, at)| id <= max_id && now.duration_since(at) >= self.reuse_delay);
+        if let Some(index) = settled {
+            return self.released.remove(index).map(|(id, _)| id);
+        }
+
+        if self.next_unused <= max_id as u32 {
+            let id = self.next_unused as u16;
+            self.next_unused += 1;
+            return Some(id);
+        }
+
+        let index = self.released.iter().position(|&(id, _)| id <= max_id)?;
+        self.released.remove(index).map(|(id, _)| id)
+    }
+
+    /// Return an ID to the free list
+    pub fn release(&mut self, id: u16) {
+        self.released.push_back((id, Instant::now()));
+    }
+}
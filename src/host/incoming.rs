@@ -1,61 +1,150 @@
 use std::net::{SocketAddr, UdpSocket};
-use std::io::Error;
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind};
 use super::types::*;
+use crate::compression;
+use crate::protocol::{self, MAX_DATAGRAM_SIZE, PROTOCOL_V1};
+use crate::transport::{self, Transport};
 
-pub struct NeonSocket {
-    pub socket: UdpSocket,
+pub struct NeonSocket<T: Transport = UdpSocket> {
+    pub socket: T,
+    pub compression_threshold: Option<usize>,
+    /// Protocol version agreed with each client; anyone else is sent v1
+    versions: HashMap<u16, u8>,
+    /// Acks carried by the last datagram received, for the host to take
+    pub last_acks: Vec<u16>,
+    unbatched: VecDeque<(NeonPacket, SocketAddr)>,
+    /// Datagrams skipped since the last take_malformed because they couldn't be decoded
+    malformed: Vec<(SocketAddr, Error)>,
 }
 
-impl NeonSocket {
-    pub fn new(bind_addr: &str) -> Result<Self, Error> {
-        let socket = UdpSocket::bind(bind_addr)?;
+impl<T: Transport> NeonSocket<T> {
+    pub fn from_transport(socket: T) -> Result<Self, Error> {
         socket.set_nonblocking(true)?;
-        Ok(Self { socket })
+        socket.disable_connection_reset()?;
+        Ok(Self {
+            socket,
+            compression_threshold: None,
+            versions: HashMap::new(),
+            last_acks: Vec::new(),
+            unbatched: VecDeque::new(),
+            malformed: Vec::new(),
+        })
+    }
+
+    /// Protocol version packets to this destination are written at
+    pub fn version_for(&self, destination_id: u16) -> u8 {
+        self.versions.get(&destination_id).copied().unwrap_or(PROTOCOL_V1)
+    }
+
+    pub fn set_version(&mut self, client_id: u16, version: u8) {
+        self.versions.insert(client_id, version);
+    }
+
+    pub fn forget_version(&mut self, client_id: u16) {
+        self.versions.remove(&client_id);
     }
 
     pub fn send_packet(&self, packet: &NeonPacket, addr: SocketAddr) -> Result<(), Error> {
-        let header = PacketHeader {
-            magic: 0x4E45,
-            version: 1,
-            packet_type: packet.packet_type,
-            sequence: packet.sequence,
-            client_id: packet.client_id,
-            destination_id: packet.destination_id,
-        };
+        self.send_packet_at(packet, self.version_for(packet.destination_id), addr)
+    }
+
+    /// Send a packet flagged for the destination to acknowledge. Version 1 headers can't carry
+    /// the flag, so those clients only ack the packets they already know to.
+    pub fn send_reliable(&self, packet: &NeonPacket, addr: SocketAddr) -> Result<(), Error> {
+        self.send_flagged(packet, self.version_for(packet.destination_id), protocol::FLAG_RELIABLE, addr)
+    }
+
+    /// Send with a header of the given version rather than the destination's
+    pub fn send_packet_at(&self, packet: &NeonPacket, version: u8, addr: SocketAddr) -> Result<(), Error> {
+        self.send_flagged(packet, version, 0, addr)
+    }
+
+    fn send_flagged(&self, packet: &NeonPacket, version: u8, flags: u8, addr: SocketAddr) -> Result<(), Error> {
+        let mut header = packet.header(version);
+        header.flags = flags;
+        let mut payload = packet.payload.to_bytes(version);
+        if matches!(packet.payload, PacketPayload::GamePacket(_) | PacketPayload::Batch(_))
+            && let Some(compressed) = compression::maybe_compress(&payload, self.compression_threshold)
+        {
+            header.flags |= protocol::FLAG_COMPRESSED;
+            payload = compressed;
+        }
+
         let mut bytes = header.to_bytes();
-        bytes.extend(packet.payload.to_bytes());
+        bytes.extend(payload);
         self.socket.send_to(&bytes, addr)?;
         Ok(())
     }
 
-    pub fn receive_packet(&self) -> Result<(NeonPacket, SocketAddr), Error> {
-        let mut buf = [0; 1024];
-        let (size, addr) = self.socket.recv_from(&mut buf)?;
-        let header = PacketHeader::from_bytes(&buf[..8])?;
-        let payload = PacketPayload::from_bytes(header.packet_type, &buf[8..size])?;
-        Ok((
-            NeonPacket {
-                packet_type: header.packet_type,
-                sequence: header.sequence,
-                client_id: header.client_id,
-                destination_id: header.destination_id,
-                payload,
-            },
-            addr,
-        ))
+    /// Receive the next packet, transparently unpacking batches
+    pub fn receive_packet(&mut self) -> Result<(NeonPacket, SocketAddr), Error> {
+        self.last_acks.clear();
+        if let Some(queued) = self.unbatched.pop_front() {
+            return Ok(queued);
+        }
+
+        let mut buf = [0; MAX_DATAGRAM_SIZE + 1];
+        loop {
+            let (size, addr) = transport::recv_skipping_resets(&self.socket, &mut buf)?;
+            match self.decode_datagram(&buf[..size], addr) {
+                // One bad datagram mustn't stop the ones behind it
+                Err(e) if protocol::is_malformed(&e) => self.malformed.push((addr, e)),
+                result => return result,
+            }
+        }
+    }
+
+    /// Take the senders of datagrams receive_packet skipped as malformed, with why
+    pub fn take_malformed(&mut self) -> Vec<(SocketAddr, Error)> {
+        std::mem::take(&mut self.malformed)
+    }
+
+    fn decode_datagram(&mut self, buf: &[u8], addr: SocketAddr) -> Result<(NeonPacket, SocketAddr), Error> {
+        if buf.len() > MAX_DATAGRAM_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Datagram from {} exceeds {} bytes", addr, MAX_DATAGRAM_SIZE)));
+        }
+        let header = PacketHeader::from_bytes(buf)?;
+        protocol::check_flags(header.flags)?;
+        let (acks, body) = protocol::split_acks(header.flags, &buf[header.size()..])?;
+        let payload = if header.flags & protocol::FLAG_COMPRESSED != 0 {
+            PacketPayload::from_bytes(header.packet_type, &compression::decompress(body)?, header.version)?
+        } else {
+            PacketPayload::from_bytes(header.packet_type, body, header.version)?
+        };
+        let packet = NeonPacket {
+            packet_type: header.packet_type,
+            sequence: header.sequence,
+            client_id: header.client_id,
+            destination_id: header.destination_id,
+            payload,
+        };
+        packet.check_batch()?;
+        self.last_acks = acks;
+
+        if let PacketPayload::Batch(packets) = packet.payload {
+            self.unbatched.extend(packets.into_iter().map(|p| (p, addr)));
+            return match self.unbatched.pop_front() {
+                Some(queued) => Ok(queued),
+                None => self.receive_packet(),
+            };
+        }
+
+        Ok((packet, addr))
     }
 }
 
 pub fn handle_ping(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    host_client_id: u8,
+    host_client_id: u16,
     packet: &NeonPacket,
+    sequence: u16,
 ) -> Result<(), Error> {
     if let PacketPayload::Ping(ping) = &packet.payload {
         let pong_packet = NeonPacket {
             packet_type: PacketType::Pong as u8,
-            sequence: packet.sequence,
+            sequence,
             client_id: host_client_id,
             destination_id: packet.client_id,
             payload: PacketPayload::Pong(Pong {
@@ -65,4 +154,49 @@ pub fn handle_ping(
         socket.send_packet(&pong_packet, relay_addr)?;
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Echo an MTU probe back to its sender at the same size
+pub fn handle_mtu_probe(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    host_client_id: u16,
+    packet: &NeonPacket,
+    sequence: u16,
+) -> Result<(), Error> {
+    if let PacketPayload::MtuProbe(probe) = &packet.payload {
+        let echo = NeonPacket {
+            packet_type: PacketType::MtuProbe as u8,
+            sequence,
+            client_id: host_client_id,
+            destination_id: packet.client_id,
+            payload: PacketPayload::MtuProbe(MtuProbe { probe_size: probe.probe_size }),
+        };
+        socket.send_packet(&echo, relay_addr)?;
+    }
+    Ok(())
+}
+
+pub fn handle_time_sync(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    host_client_id: u16,
+    packet: &NeonPacket,
+    host_time: u64,
+    sequence: u16,
+) -> Result<(), Error> {
+    if let PacketPayload::TimeSync(request) = &packet.payload {
+        let response = NeonPacket {
+            packet_type: PacketType::TimeSync as u8,
+            sequence,
+            client_id: host_client_id,
+            destination_id: packet.client_id,
+            payload: PacketPayload::TimeSync(TimeSync {
+                client_time: request.client_time,
+                host_time,
+            }),
+        };
+        socket.send_packet(&response, relay_addr)?;
+    }
+    Ok(())
+}
@@ -1,66 +1,283 @@
 mod types;
+mod builder;
+mod config;
 mod incoming;
 mod outgoing;
+mod ids;
+mod pings;
+mod multiplexer;
+mod sans_io;
+mod sender;
+mod worker;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::io::{Error, ErrorKind};
-use std::net::SocketAddr;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
 use std::thread::sleep;
 use std::time::Duration;
 use std::time::Instant;
 
 use types::*;
-use incoming::{NeonSocket, handle_ping};
+use incoming::{NeonSocket, handle_mtu_probe, handle_ping, handle_time_sync};
 use outgoing::*;
+use ids::ClientIds;
+use pings::ClientPings;
+pub use config::{DEFAULT_ACK_TIMEOUT, DEFAULT_MAX_RETRIES, DEFAULT_PING_INTERVAL, DEFAULT_RELAY_KEEPALIVE, DEFAULT_TICK_RATE, HostConfig};
+pub use pings::ClientStats;
+pub use builder::HostBuilder;
+pub use ids::DEFAULT_ID_REUSE_DELAY;
+pub use multiplexer::{
+    MultiplexedConnectCallback, MultiplexedDisconnectCallback, MultiplexedGamePacketCallback, MultiplexedSessionClosedCallback,
+    NeonHostMultiplexer, SessionHandle,
+};
+pub use sans_io::{HostEvent, HostProtocol};
+pub use sender::HostSender;
+use sender::QueuedSend;
+pub use worker::HostWorker;
+use crate::auth::Authenticator;
+use crate::bandwidth::{BandwidthLimiter, CongestionController, DEFAULT_MAX_RATE};
+use crate::join_code;
+use crate::name;
+use crate::message::MessageHandlers;
+#[cfg(feature = "serde")]
+use crate::message::{Message, encode_message};
+use crate::protocol::{self, DisconnectReason, FeatureSet, MAX_DATAGRAM_SIZE, PROTOCOL_V1, PROTOCOL_VERSION, ANNOUNCEMENT, CAPABILITIES, Capabilities, CONFIG_VALUE, NAME_CHANGE, PeerRole, RESUME_REFUSED, SESSION_FULL, SessionMetadata, spectator_may_send, max_client_id, REPLICATION_ACK, REPLICATION_SNAPSHOT, RESERVED_GAME_TYPES, RPC_REQUEST, RPC_RESPONSE, VOICE_FRAME};
+use crate::replication::{ReplicationServer, decode_ack};
+use crate::rpc::RpcEndpoint;
+use crate::scheduler::{OutgoingScheduler, Priority};
+use crate::timesync::unix_micros;
+use crate::sequence::{PeerSequences, SequenceCounter, SequenceStats};
+use crate::socket_options::SocketOptions;
+use crate::transport::Transport;
+use crate::voice::{JitterBuffer, VoiceChannel, VoiceFrame};
 
-pub type ClientConnectCallback = Box<dyn FnMut(u8, String, u32) + Send>; // (client_id, name, session_id)
+pub type ClientConnectCallback = Box<dyn FnMut(u16, String, u32) + Send>; // (client_id, name, session_id)
 pub type ClientDenyCallback = Box<dyn FnMut(String, String) + Send>; // (name, reason)
-pub type PingReceivedCallback = Box<dyn FnMut(u8) + Send>; // (from_client_id)
-pub type UnhandledPacketCallback = Box<dyn FnMut(u8, u8, SocketAddr) + Send>; // (packet_type, from_client_id, addr)
+pub type PingReceivedCallback = Box<dyn FnMut(u16) + Send>; // (from_client_id)
+pub type UnhandledPacketCallback = Box<dyn FnMut(u8, u16, SocketAddr) + Send>; // (packet_type, from_client_id, addr)
+pub type MalformedPacketCallback = Box<dyn FnMut(SocketAddr, &Error) + Send>; // (from, error)
+pub type GamePacketCallback = Box<dyn FnMut(u8, u16, &[u8]) + Send>; // (packet_type, from_client_id, data)
+pub type ClockSource = Box<dyn FnMut() -> u64 + Send>; // () -> host time in microseconds
+pub type ChatMessageCallback = Box<dyn FnMut(u16, String, String) + Send>; // (from_client_id, name, text)
+pub type VoiceFrameCallback = Box<dyn FnMut(u16, u32, &[u8]) + Send>; // (from_client_id, sequence, frame)
+pub type BudgetExceededCallback = Box<dyn FnMut(usize, u32) + Send>; // (queued_packets, bytes_per_second)
+pub type ClientDisconnectCallback = Box<dyn FnMut(u16, DisconnectReason) + Send>; // (client_id, reason)
+pub type SessionClosedCallback = Box<dyn FnMut(DisconnectReason) + Send>; // (reason)
+pub type DeliveryFailedCallback = Box<dyn FnMut(u16, u8) + Send>; // (client_id, packet_type)
+pub type ClientRenamedCallback = Box<dyn FnMut(u16, String, String) + Send>; // (client_id, old_name, new_name)
+pub type ConnectRequestCallback = Box<dyn FnMut(u32, &str, u32) -> ConnectDecision + Send>; // (request_id, name, game_identifier)
 
-pub struct NeonHost {
-    socket: NeonSocket,
+/// What to do with a connection request, as returned by the on_connect_request callback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectDecision {
+    Accept,
+    /// Admit the client with these capabilities instead of its role's defaults
+    AcceptWith(Capabilities),
+    Deny(String),
+    /// Hold the request until accept_request or deny_request is called with its ID
+    Defer,
+}
+
+pub struct NeonHost<T: Transport = UdpSocket> {
+    socket: NeonSocket<T>,
     relay_addr: SocketAddr,
-    client_id: u8,
+    /// Resolved relay addresses not tried yet, which register moves on to if the relay doesn't answer
+    relay_candidates: VecDeque<SocketAddr>,
+    socket_options: SocketOptions,
+    client_id: u16,
     session_id: u32,
-    connected_clients: HashMap<u8, String>,
-    next_client_id: u8,
-    pending_acks: HashMap<u8, PendingAck>,
+    game_identifier: u32,
+    join_code: Option<String>,
+    metadata: SessionMetadata,
+    /// Announced to each client as it joins
+    motd: Option<String>,
+    /// The session's key/value config, sent to each client as it joins and on every change
+    config_values: BTreeMap<String, Vec<u8>>,
+    connected_clients: HashMap<u16, String>,
+    /// Clients that joined as spectators
+    spectators: HashSet<u16>,
+    capabilities: HashMap<u16, Capabilities>,
+    /// Whether a client taking a name in use gets a numbered one ("Alice (2)") rather than being denied
+    auto_suffix_names: bool,
+    /// Names clients asked to join as, for those given another
+    requested_names: HashMap<u16, String>,
+    resume_tokens: HashMap<u16, u64>,
+    client_ids: ClientIds,
+    max_clients: Option<usize>,
+    deferred_requests: HashMap<u32, ConnectRequest>,
+    /// Client each relay nonce was admitted as, so a resent request gets the same answer
+    admitted: HashMap<u32, u16>,
+    /// Admitted clients the relay hasn't registered yet, and when they were admitted
+    awaiting_ready: HashMap<u16, Instant>,
+    next_request_id: u32,
+    authenticator: Option<Box<dyn Authenticator>>,
+    /// Reliable packets awaiting an Ack, keyed by client and sequence
+    pending_acks: HashMap<(u16, u16), PendingAck>,
+    ack_timeout: Duration,
+    max_retries: u8,
+    client_timeout: Option<Duration>,
+    /// When each connected client was last heard from
+    last_heard: HashMap<u16, Instant>,
+    ping_interval: Option<Duration>,
+    pings: ClientPings,
+    outgoing_sequences: HashMap<u16, SequenceCounter>,
+    incoming_sequences: PeerSequences,
+    malformed_packets: u64,
+    batcher: Option<PacketBatcher>,
+    packet_types: BTreeMap<u8, PacketTypeEntry>,
+    clock: ClockSource,
+    tick_rate: u16,
+    features: FeatureSet,
+    /// Features each client declared in its confirmation of the accept
+    declared_features: HashMap<u16, FeatureSet>,
+    max_packet_size: usize,
+    packet_sizes: HashMap<u16, usize>,
+    /// The session config each welcomed client was sent
+    session_configs: HashMap<u16, protocol::SessionConfig>,
+    replication: ReplicationServer,
+    rpc: RpcEndpoint,
+    voice: VoiceChannel,
+    scheduler: OutgoingScheduler<NeonPacket>,
+    bandwidth: Option<BandwidthLimiter>,
+    bandwidth_limit: Option<u32>,
+    congestion: Option<CongestionController>,
+    throttled: bool,
+    relay_keepalive: Option<Duration>,
+    last_keepalive: Instant,
+    closed: bool,
+    sender: HostSender,
+    queued_sends: Receiver<QueuedSend>,
 
     on_client_connect: Option<ClientConnectCallback>,
     on_client_deny: Option<ClientDenyCallback>,
+    on_client_renamed: Option<ClientRenamedCallback>,
+    on_client_disconnect: Option<ClientDisconnectCallback>,
+    on_session_closed: Option<SessionClosedCallback>,
+    on_delivery_failed: Option<DeliveryFailedCallback>,
+    on_connect_request: Option<ConnectRequestCallback>,
     on_ping_received: Option<PingReceivedCallback>,
     on_unhandled_packet: Option<UnhandledPacketCallback>,
+    on_malformed_packet: Option<MalformedPacketCallback>,
+    on_game_packet: Option<GamePacketCallback>,
+    on_chat_message: Option<ChatMessageCallback>,
+    on_voice_frame: Option<VoiceFrameCallback>,
+    on_budget_exceeded: Option<BudgetExceededCallback>,
+    messages: MessageHandlers,
 }
 
-const ACK_TIMEOUT: Duration = Duration::from_secs(2);
-const MAX_RETRIES: u8 = 5;
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long an admitted client waits for the relay to confirm its registration before being
+/// welcomed anyway, as relays that don't pass confirmations on never will
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
 
 impl NeonHost {
-    /// Create a new host with a specific session ID and relay address
-    pub fn new(session_id: u32, relay_addr: &str) -> Result<Self, Error> {
-        let relay_addr = relay_addr.parse()
-            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid relay address"))?;
-        
+    /// Create a new host with a specific session ID and relay address.
+    /// The relay address may be a hostname; the first resolved address a local socket
+    /// can be bound for is used.
+    pub fn new<A: ToSocketAddrs>(session_id: u32, relay_addr: A) -> Result<Self, Error> {
+        Self::builder(session_id, relay_addr).build()
+    }
+
+    /// Create a new host, like `new`, whose socket is tuned with `options`
+    pub fn with_socket_options<A: ToSocketAddrs>(session_id: u32, relay_addr: A, options: SocketOptions) -> Result<Self, Error> {
+        Self::builder(session_id, relay_addr).socket_options(options).build()
+    }
+
+    /// Create a new host, like `new`, with its settings taken from `config`
+    pub fn with_config<A: ToSocketAddrs>(session_id: u32, relay_addr: A, config: HostConfig) -> Result<Self, Error> {
+        Self::builder(session_id, relay_addr).config(config).build()
+    }
+
+    /// Start building a host, to set its socket and session settings before it is created
+    pub fn builder<A: ToSocketAddrs>(session_id: u32, relay_addr: A) -> HostBuilder<A> {
+        HostBuilder::new(session_id, relay_addr)
+    }
+}
+
+impl<T: Transport> NeonHost<T> {
+    /// Create a new host that talks to the relay over the given transport instead of a UDP socket
+    pub fn with_transport(session_id: u32, relay_addr: SocketAddr, transport: T) -> Result<Self, Error> {
+        let (queue, queued_sends) = mpsc::channel();
         Ok(Self {
-            socket: NeonSocket::new("0.0.0.0:0")?,
+            socket: NeonSocket::from_transport(transport)?,
             relay_addr,
+            relay_candidates: VecDeque::new(),
+            socket_options: SocketOptions::default(),
             client_id: 1,
             session_id,
+            game_identifier: 0,
+            join_code: None,
+            metadata: SessionMetadata::default(),
+            motd: None,
+            config_values: BTreeMap::new(),
             connected_clients: HashMap::new(),
-            next_client_id: 2,
+            spectators: HashSet::new(),
+            capabilities: HashMap::new(),
+            auto_suffix_names: false,
+            requested_names: HashMap::new(),
+            resume_tokens: HashMap::new(),
+            client_ids: ClientIds::new(),
+            max_clients: None,
+            deferred_requests: HashMap::new(),
+            admitted: HashMap::new(),
+            awaiting_ready: HashMap::new(),
+            next_request_id: 0,
+            authenticator: None,
             pending_acks: HashMap::new(),
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            max_retries: DEFAULT_MAX_RETRIES,
+            client_timeout: None,
+            last_heard: HashMap::new(),
+            ping_interval: Some(DEFAULT_PING_INTERVAL),
+            pings: ClientPings::new(),
+            outgoing_sequences: HashMap::new(),
+            incoming_sequences: PeerSequences::new(),
+            malformed_packets: 0,
+            batcher: None,
+            packet_types: BTreeMap::new(),
+            clock: Box::new(unix_micros),
+            tick_rate: DEFAULT_TICK_RATE,
+            features: FeatureSet::empty(),
+            declared_features: HashMap::new(),
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            packet_sizes: HashMap::new(),
+            session_configs: HashMap::new(),
+            replication: ReplicationServer::new(),
+            rpc: RpcEndpoint::new(),
+            voice: VoiceChannel::new(),
+            scheduler: OutgoingScheduler::new(),
+            bandwidth: None,
+            bandwidth_limit: None,
+            congestion: None,
+            throttled: false,
+            relay_keepalive: Some(DEFAULT_RELAY_KEEPALIVE),
+            last_keepalive: Instant::now(),
+            closed: false,
+            sender: HostSender::new(queue),
+            queued_sends,
             on_client_connect: None,
             on_client_deny: None,
+            on_client_renamed: None,
+            on_client_disconnect: None,
+            on_session_closed: None,
+            on_delivery_failed: None,
+            on_connect_request: None,
             on_ping_received: None,
             on_unhandled_packet: None,
+            on_malformed_packet: None,
+            on_game_packet: None,
+            on_chat_message: None,
+            on_voice_frame: None,
+            on_budget_exceeded: None,
+            messages: MessageHandlers::new(),
         })
     }
 
     /// Set callback for when a client connects
     pub fn on_client_connect<F>(&mut self, callback: F)
     where
-        F: FnMut(u8, String, u32) + Send + 'static,
+        F: FnMut(u16, String, u32) + Send + 'static,
     {
         self.on_client_connect = Some(Box::new(callback));
     }
@@ -73,10 +290,54 @@ impl NeonHost {
         self.on_client_deny = Some(Box::new(callback));
     }
 
+    /// Set callback for when a client changes its name with request_name_change
+    pub fn on_client_renamed<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, String, String) + Send + 'static,
+    {
+        self.on_client_renamed = Some(Box::new(callback));
+    }
+
+    /// Set callback for when the relay drops one of this session's clients, e.g. after it times out
+    pub fn on_client_disconnect<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, DisconnectReason) + Send + 'static,
+    {
+        self.on_client_disconnect = Some(Box::new(callback));
+    }
+
+    /// Set callback for when a client never acknowledges a reliable packet, even after every retry.
+    /// A client that misses part of its handshake (ConnectAccept, SessionConfig or PacketTypeRegistry)
+    /// is removed from the session once this returns.
+    pub fn on_delivery_failed<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, u8) + Send + 'static,
+    {
+        self.on_delivery_failed = Some(Box::new(callback));
+    }
+
+    /// Set callback for when the relay closes the session, e.g. for reaching its lifetime or idle limit.
+    /// The host has forgotten its clients by the time it runs; register opens the session again.
+    pub fn on_session_closed<F>(&mut self, callback: F)
+    where
+        F: FnMut(DisconnectReason) + Send + 'static,
+    {
+        self.on_session_closed = Some(Box::new(callback));
+    }
+
+    /// Set callback that decides whether a client may join. It's asked after the name check and
+    /// before an ID is assigned; without it every request with a free name is accepted.
+    pub fn on_connect_request<F>(&mut self, callback: F)
+    where
+        F: FnMut(u32, &str, u32) -> ConnectDecision + Send + 'static,
+    {
+        self.on_connect_request = Some(Box::new(callback));
+    }
+
     /// Set callback for when a ping is received
     pub fn on_ping_received<F>(&mut self, callback: F)
     where
-        F: FnMut(u8) + Send + 'static,
+        F: FnMut(u16) + Send + 'static,
     {
         self.on_ping_received = Some(Box::new(callback));
     }
@@ -84,16 +345,484 @@ impl NeonHost {
     /// Set callback for unhandled packets
     pub fn on_unhandled_packet<F>(&mut self, callback: F)
     where
-        F: FnMut(u8, u8, SocketAddr) + Send + 'static,
+        F: FnMut(u8, u16, SocketAddr) + Send + 'static,
     {
         self.on_unhandled_packet = Some(Box::new(callback));
     }
 
+    /// Set callback for datagrams dropped because they couldn't be decoded, with why
+    pub fn on_malformed_packet<F>(&mut self, callback: F)
+    where
+        F: FnMut(SocketAddr, &Error) + Send + 'static,
+    {
+        self.on_malformed_packet = Some(Box::new(callback));
+    }
+
+    /// Set callback for game packets (types 0x10+)
+    pub fn on_game_packet<F>(&mut self, callback: F)
+    where
+        F: FnMut(u8, u16, &[u8]) + Send + 'static,
+    {
+        self.on_game_packet = Some(Box::new(callback));
+    }
+
+    /// Set callback for chat messages from clients
+    pub fn on_chat_message<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, String, String) + Send + 'static,
+    {
+        self.on_chat_message = Some(Box::new(callback));
+    }
+
+    /// Set callback for each voice frame as it arrives, before any jitter buffering
+    pub fn on_voice_frame<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, u32, &[u8]) + Send + 'static,
+    {
+        self.on_voice_frame = Some(Box::new(callback));
+    }
+
+    /// Queue incoming voice in a jitter buffer per peer, created by `factory`; read it back
+    /// with next_voice_frame. e.g. `set_jitter_buffer(|_| Box::new(FixedJitterBuffer::new(3)))`
+    pub fn set_jitter_buffer<F>(&mut self, factory: F)
+    where
+        F: FnMut(u16) -> Box<dyn JitterBuffer> + Send + 'static,
+    {
+        self.voice.set_jitter_buffer(Box::new(factory));
+    }
+
+    /// Next buffered voice frame to play for a peer, called once per frame period.
+    /// None means silence or a lost frame to conceal.
+    pub fn next_voice_frame(&mut self, peer_id: u16) -> Option<VoiceFrame> {
+        self.voice.pop(peer_id)
+    }
+
+    /// Set a typed callback for one message type (requires the `serde` feature).
+    /// Takes priority over on_game_packet for that packet type.
+    #[cfg(feature = "serde")]
+    pub fn on_message<M, F>(&mut self, callback: F) -> Result<(), Error>
+    where
+        M: Message,
+        F: FnMut(u16, M) + Send + 'static,
+    {
+        self.messages.register(callback)
+    }
+
+    /// Handle calls to an RPC method. The handler gets the caller's ID and argument bytes and
+    /// returns reply bytes, or an error message passed to the caller.
+    pub fn register_rpc<F>(&mut self, method: &str, handler: F) -> Result<(), Error>
+    where
+        F: FnMut(u16, &[u8]) -> Result<Vec<u8>, String> + Send + 'static,
+    {
+        self.rpc.register(method, Box::new(handler))
+    }
+
+    /// Set how long rpc_with_reply waits before failing with TimedOut (default: 5 seconds)
+    pub fn set_rpc_timeout(&mut self, timeout: Duration) {
+        self.rpc.set_timeout(timeout);
+    }
+
+    /// Replace the clock clients synchronize to (default: microseconds since the Unix epoch).
+    /// Use this to share game time, e.g. microseconds since the match started.
+    pub fn set_clock_source<F>(&mut self, clock: F)
+    where
+        F: FnMut() -> u64 + Send + 'static,
+    {
+        self.clock = Box::new(clock);
+    }
+
+    /// Read the authoritative clock, in microseconds
+    pub fn host_time(&mut self) -> u64 {
+        (self.clock)()
+    }
+
+    /// Apply every setting in `config`. Fails, changing nothing, if the tick rate is 0.
+    pub fn set_config(&mut self, config: HostConfig) -> Result<(), Error> {
+        self.set_tick_rate(config.tick_rate)?;
+        self.ack_timeout = config.ack_timeout;
+        self.max_retries = config.max_retries;
+        self.relay_keepalive = config.relay_keepalive;
+        self.client_timeout = config.client_timeout;
+        self.max_clients = config.max_clients;
+        self.ping_interval = config.ping_interval;
+        self.features = config.features;
+        Ok(())
+    }
+
+    /// Set the features the session uses. Each client that joins from now on gets the ones it
+    /// also supports, in its SessionConfig.
+    pub fn set_features(&mut self, features: FeatureSet) {
+        self.features = features;
+    }
+
+    /// Get the features the session uses
+    pub fn features(&self) -> FeatureSet {
+        self.features
+    }
+
+    /// Get the features agreed with a client: those both the session and the client support.
+    /// None for unknown clients and ones still waiting to be welcomed.
+    pub fn client_features(&self, client_id: u16) -> Option<FeatureSet> {
+        self.session_configs.get(&client_id).map(|config| config.features)
+    }
+
+    /// Set the tick rate (ticks per second) advertised to clients that connect from now on
+    pub fn set_tick_rate(&mut self, tick_rate: u16) -> Result<(), Error> {
+        if tick_rate == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Tick rate must be at least 1"));
+        }
+        self.tick_rate = tick_rate;
+        Ok(())
+    }
+
+    /// Get the tick rate advertised to clients
+    pub fn tick_rate(&self) -> u16 {
+        self.tick_rate
+    }
+
+    /// Set the largest datagram advertised to clients that connect from now on (1024-1472).
+    /// Clients start at 1024 and probe upward; anything above 1024 is only used once a probe
+    /// of that size has made it through.
+    pub fn set_max_packet_size(&mut self, max_packet_size: u16) -> Result<(), Error> {
+        let size = max_packet_size as usize;
+        if !(DEFAULT_MAX_PACKET_SIZE..=MAX_DATAGRAM_SIZE).contains(&size) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Max packet size must be in {}-{}", DEFAULT_MAX_PACKET_SIZE, MAX_DATAGRAM_SIZE),
+            ));
+        }
+        self.max_packet_size = size;
+        Ok(())
+    }
+
+    /// Set how often to check in with the relay, so a relay restart is noticed and the host registers
+    /// again even when no traffic is flowing. The check-ins also tell the relay the host is alive:
+    /// one that hears nothing from the host for its peer timeout (15 seconds by default) closes the
+    /// session. None stops checking in.
+    pub fn set_relay_keepalive(&mut self, interval: Option<Duration>) {
+        self.relay_keepalive = interval;
+    }
+
+    /// Set how often to ping each client (DEFAULT_PING_INTERVAL unless set), so round trips are measured
+    /// for quiet clients too (see client_stats). Answered pings count as hearing from a client, so a
+    /// client timeout never drops a quiet client that's still there; clients are pinged at least three
+    /// times per timeout. None stops pinging.
+    pub fn set_ping_interval(&mut self, interval: Option<Duration>) {
+        self.ping_interval = interval;
+    }
+
+    /// Set the game this session belongs to, sent when registering so relays serving particular
+    /// games can tell (default: 0, unset). Set it before `register`.
+    pub fn set_game_identifier(&mut self, game_identifier: u32) {
+        self.game_identifier = game_identifier;
+    }
+
+    /// Give the session a short code players can join with (`NeonClient::connect_with_code`) instead
+    /// of its session ID. Up to 16 letters, digits and dashes; case doesn't matter. The relay holds it
+    /// from the next registration, which fails if another session holds it.
+    pub fn set_join_code(&mut self, code: &str) -> Result<(), Error> {
+        self.join_code = Some(join_code::normalize(code)?);
+        Ok(())
+    }
+
+    /// Set a random join code like "CRAB-7F2", returning it
+    pub fn generate_join_code(&mut self) -> &str {
+        self.join_code.insert(join_code::generate())
+    }
+
+    /// Set the name, player cap and properties the relay lists for this session, sent when
+    /// registering. To update a registered session, call `begin_register` after setting it.
+    /// InvalidInput if it's too large to send (see `SessionMetadata::validate`).
+    pub fn set_session_metadata(&mut self, metadata: SessionMetadata) -> Result<(), Error> {
+        metadata.validate()?;
+        self.metadata = metadata;
+        Ok(())
+    }
+
+    /// Set a message of the day, announced to each client as it joins (None stops it).
+    /// InvalidInput if it's longer than MAX_CHAT_LENGTH.
+    pub fn set_motd(&mut self, motd: Option<&str>) -> Result<(), Error> {
+        if let Some(text) = motd {
+            protocol::check_announcement_length(text)?;
+        }
+        self.motd = motd.map(str::to_string);
+        Ok(())
+    }
+
+    /// Set an entry in the session's key/value config, e.g. ("map", "canyon"). Clients get every entry
+    /// when they join and each change reliably, firing on_config_changed. InvalidInput if the key is
+    /// empty or longer than MAX_CONFIG_KEY_LENGTH, or the value larger than MAX_CONFIG_VALUE_SIZE.
+    pub fn set_config_value(&mut self, key: &str, value: impl AsRef<[u8]>) -> Result<(), Error> {
+        let value = value.as_ref();
+        protocol::check_config_value(key, value)?;
+        if self.config_values.get(key).is_some_and(|current| current == value) {
+            return Ok(());
+        }
+        self.config_values.insert(key.to_string(), value.to_vec());
+        self.broadcast_config_value(key, Some(value))
+    }
+
+    /// Remove an entry from the session's key/value config, returning whether there was one
+    pub fn remove_config_value(&mut self, key: &str) -> Result<bool, Error> {
+        if self.config_values.remove(key).is_none() {
+            return Ok(false);
+        }
+        self.broadcast_config_value(key, None)?;
+        Ok(true)
+    }
+
+    /// Get an entry of the session's key/value config
+    pub fn config_value(&self, key: &str) -> Option<&[u8]> {
+        self.config_values.get(key).map(Vec::as_slice)
+    }
+
+    /// Get the session's key/value config
+    pub fn config_values(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.config_values
+    }
+
+    /// Send a config change to every welcomed client; the rest get the whole config when welcomed
+    fn broadcast_config_value(&mut self, key: &str, value: Option<&[u8]>) -> Result<(), Error> {
+        let payload = protocol::encode_config_value(key, value);
+        let client_ids: Vec<u16> = self.connected_clients
+            .keys()
+            .filter(|client_id| !self.awaiting_ready.contains_key(client_id))
+            .copied()
+            .collect();
+        for client_id in client_ids {
+            self.send_reliable(client_id, CONFIG_VALUE, &payload)?;
+        }
+        Ok(())
+    }
+
+    /// Cap the number of connected clients; further requests are denied with SESSION_FULL (None removes the cap)
+    pub fn set_max_clients(&mut self, max_clients: Option<usize>) {
+        self.max_clients = max_clients;
+    }
+
+    /// Give clients that join or rename to a name already in use a numbered one, "Alice (2)", instead
+    /// of denying them. Off by default. Clients are told the name they were given.
+    pub fn set_auto_suffix_names(&mut self, enabled: bool) {
+        self.auto_suffix_names = enabled;
+    }
+
+    /// Check every connection request's auth token before the connect request callback sees it.
+    /// Requests the authenticator rejects are denied with the reason it returns.
+    pub fn set_authenticator<A: Authenticator + 'static>(&mut self, authenticator: A) {
+        self.authenticator = Some(Box::new(authenticator));
+    }
+
+    /// Stop checking auth tokens
+    pub fn clear_authenticator(&mut self) {
+        self.authenticator = None;
+    }
+
+    /// Admit a request the connect request callback deferred. The name and capacity checks run again,
+    /// so the client may still be denied. Returns false if no such request is waiting.
+    /// Clients give up on a connect after 10 seconds, and the relay forgets the request after 30.
+    pub fn accept_request(&mut self, request_id: u32) -> Result<bool, Error> {
+        self.accept_deferred(request_id, None)
+    }
+
+    /// Like accept_request, admitting the client with `capabilities` instead of its role's defaults
+    pub fn accept_request_with(&mut self, request_id: u32, capabilities: Capabilities) -> Result<bool, Error> {
+        self.accept_deferred(request_id, Some(capabilities))
+    }
+
+    fn accept_deferred(&mut self, request_id: u32, capabilities: Option<Capabilities>) -> Result<bool, Error> {
+        let Some(req) = self.deferred_requests.remove(&request_id) else {
+            return Ok(false);
+        };
+        if self.is_name_taken(&req.desired_name) && !self.auto_suffix_names {
+            let reason = format!("Name '{}' is already in use", req.desired_name);
+            self.deny_connect_request(req, reason)?;
+        } else {
+            self.admit_connect_request(req, capabilities)?;
+        }
+        Ok(true)
+    }
+
+    /// Turn away a request the connect request callback deferred. Returns false if no such request is waiting.
+    pub fn deny_request(&mut self, request_id: u32, reason: &str) -> Result<bool, Error> {
+        let Some(req) = self.deferred_requests.remove(&request_id) else {
+            return Ok(false);
+        };
+        self.deny_connect_request(req, reason.to_string())?;
+        Ok(true)
+    }
+
+    /// IDs of deferred requests still waiting for accept_request or deny_request
+    pub fn deferred_requests(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.deferred_requests.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Set how long a disconnected client's ID is held back before a new client may be given it
+    /// (DEFAULT_ID_REUSE_DELAY unless set). Held-back IDs are still reused rather than turning clients away.
+    pub fn set_id_reuse_delay(&mut self, delay: Duration) {
+        self.client_ids.set_reuse_delay(delay);
+    }
+
+    /// Get the max packet size advertised to clients
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    /// The session config a client connecting now would be sent, at this version of the protocol
+    pub fn session_config(&self) -> protocol::SessionConfig {
+        protocol::SessionConfig {
+            version: PROTOCOL_VERSION,
+            tick_rate: self.tick_rate,
+            max_packet_size: self.max_packet_size as u16,
+            features: self.features,
+        }
+    }
+
+    /// The session config a client was sent when it joined, at the version negotiated with it.
+    /// None for unknown clients and ones still waiting to be welcomed.
+    pub fn client_session_config(&self, client_id: u16) -> Option<&protocol::SessionConfig> {
+        self.session_configs.get(&client_id)
+    }
+
+    /// Largest datagram, header included, that may currently be sent to a client.
+    /// Assumes the path is symmetric, so it grows as the client's MTU probes arrive.
+    pub fn packet_size_limit(&self, client_id: u16) -> Option<usize> {
+        self.packet_sizes.get(&client_id).copied()
+    }
+
+    /// Compress game payloads at or above this size (default: None, off). Needs the `lz4` feature at
+    /// both ends: peers built without it drop compressed packets, so only turn this on when every peer has it.
+    pub fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.socket.compression_threshold = threshold;
+    }
+
+    /// Coalesce outgoing game packets into shared datagrams, flushed every loop iteration (default: false)
+    pub fn set_batching(&mut self, enabled: bool) {
+        self.batcher = enabled.then(|| PacketBatcher::new(DEFAULT_MAX_PACKET_SIZE));
+    }
+
+    /// Drop low-priority packets that have waited this long to be sent (None keeps them; default: 200ms)
+    pub fn set_stale_packet_age(&mut self, age: Option<Duration>) {
+        self.scheduler.set_stale_age(age);
+    }
+
+    /// Cap outgoing game traffic at this many bytes per second (None removes the cap).
+    /// Packets over budget wait in the priority queues and go out as budget refills.
+    pub fn set_bandwidth_limit(&mut self, bytes_per_second: Option<u32>) {
+        self.bandwidth_limit = bytes_per_second;
+        self.apply_bandwidth_settings();
+    }
+
+    /// Get the outgoing bandwidth cap in bytes per second
+    pub fn bandwidth_limit(&self) -> Option<u32> {
+        self.bandwidth_limit
+    }
+
+    /// Adapt the send rate to the link (default: false). The rate backs off when round trips
+    /// inflate or reliable packets need retransmitting and creeps back up while the link is clear,
+    /// never exceeding the bandwidth limit (1 MB/s if none is set).
+    pub fn set_congestion_control(&mut self, enabled: bool) {
+        if enabled != self.congestion.is_some() {
+            self.congestion = enabled.then(|| CongestionController::new(self.bandwidth_limit.unwrap_or(DEFAULT_MAX_RATE)));
+            self.apply_bandwidth_settings();
+        }
+    }
+
+    /// Get the rate outgoing game traffic is currently paced at (None when unpaced)
+    pub fn send_rate(&self) -> Option<u32> {
+        self.bandwidth.as_ref().map(BandwidthLimiter::bytes_per_second)
+    }
+
+    /// Set callback for when the bandwidth cap starts holding packets back. Fires again only
+    /// after the queues have emptied.
+    pub fn on_budget_exceeded<F>(&mut self, callback: F)
+    where
+        F: FnMut(usize, u32) + Send + 'static,
+    {
+        self.on_budget_exceeded = Some(Box::new(callback));
+    }
+
+    /// Get the number of packets waiting in the outgoing priority queues
+    pub fn queued_packets(&self) -> usize {
+        self.scheduler.len()
+    }
+
+    /// Get the number of low-priority packets dropped for going stale
+    pub fn dropped_packets(&self) -> u64 {
+        self.scheduler.dropped_packets()
+    }
+
+    /// Get a handle other threads can send through; see HostSender
+    pub fn sender(&self) -> HostSender {
+        self.sender.clone()
+    }
+
+    /// Register, then process packets on a background thread from now on, like start without
+    /// blocking past registration. Callbacks fire on that thread.
+    pub fn spawn(mut self) -> Result<HostWorker<T>, Error> {
+        self.register()?;
+        HostWorker::spawn(self)
+    }
+
+    /// Send queued packets, then any batched packets, immediately
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.send_queued();
+        self.drain_outgoing()?;
+        match &mut self.batcher {
+            Some(batcher) => batcher.flush(&self.socket, self.relay_addr),
+            None => Ok(()),
+        }
+    }
+
+    /// Describe a game packet type (0x10+) in the registry sent to each client on connect.
+    /// Registering an ID again replaces its entry.
+    pub fn register_packet_type(&mut self, packet_id: u8, name: &str, description: &str) -> Result<(), Error> {
+        let entry = validate_packet_type(packet_id, name, description)?;
+        self.packet_types.insert(packet_id, entry);
+        Ok(())
+    }
+
+    /// Register several packet types at once; nothing is registered if any entry is invalid
+    pub fn register_packet_types(&mut self, types: &[(u8, &str, &str)]) -> Result<(), Error> {
+        let entries = types
+            .iter()
+            .map(|(packet_id, name, description)| validate_packet_type(*packet_id, name, description))
+            .collect::<Result<Vec<_>, _>>()?;
+        for entry in entries {
+            self.packet_types.insert(entry.packet_id, entry);
+        }
+        Ok(())
+    }
+
+    /// Get the registered packet types as (id, name, description), ordered by ID
+    pub fn packet_types(&self) -> Vec<(u8, String, String)> {
+        self.packet_types
+            .values()
+            .map(|e| (e.packet_id, e.name.clone(), e.description.clone()))
+            .collect()
+    }
+
     /// Get the session ID
     pub fn session_id(&self) -> u32 {
         self.session_id
     }
 
+    /// Get the game identifier set with set_game_identifier
+    pub fn game_identifier(&self) -> u32 {
+        self.game_identifier
+    }
+
+    /// Get what the host advertises about the session
+    pub fn session_metadata(&self) -> &SessionMetadata {
+        &self.metadata
+    }
+
+    /// Get the join code, if one was set or generated
+    pub fn join_code(&self) -> Option<&str> {
+        self.join_code.as_deref()
+    }
+
     /// Get the relay address
     pub fn relay_addr(&self) -> SocketAddr {
         self.relay_addr
@@ -104,75 +833,695 @@ impl NeonHost {
         self.connected_clients.len()
     }
 
-    /// Start the host and begin accepting connections
-    pub fn start(&mut self) -> Result<(), Error> {
-        send_host_registration(&self.socket, self.relay_addr, self.client_id, self.session_id)?;
+    /// Get the role a connected client joined as
+    pub fn client_role(&self, client_id: u16) -> Option<PeerRole> {
+        if !self.connected_clients.contains_key(&client_id) {
+            return None;
+        }
+        Some(if self.spectators.contains(&client_id) { PeerRole::Spectator } else { PeerRole::Player })
+    }
+
+    /// Get what a connected client may do
+    pub fn client_capabilities(&self, client_id: u16) -> Option<Capabilities> {
+        self.capabilities.get(&client_id).copied()
+    }
+
+    /// Change what a connected client may do and tell it. Game packets from clients without
+    /// SEND_GAME and chat from clients without CHAT never reach this host's callbacks; spectators
+    /// can't be given SEND_GAME. NotFound if the client isn't connected.
+    pub fn set_client_capabilities(&mut self, client_id: u16, capabilities: Capabilities) -> Result<(), Error> {
+        let Some(role) = self.client_role(client_id) else {
+            return Err(Error::new(ErrorKind::NotFound, format!("Client {} is not connected", client_id)));
+        };
+        let capabilities = allowed_capabilities(role, capabilities);
+        if self.capabilities.insert(client_id, capabilities) == Some(capabilities) {
+            return Ok(());
+        }
+        // A client still joining is told when it's welcomed
+        if self.awaiting_ready.contains_key(&client_id) {
+            return Ok(());
+        }
+        self.send_reliable(client_id, CAPABILITIES, &[capabilities.bits()])
+    }
+
+    /// Whether a client may do something; packets from clients this host doesn't know aren't held back
+    fn client_may(&self, client_id: u16, capability: Capabilities) -> bool {
+        self.capabilities.get(&client_id).is_none_or(|capabilities| capabilities.contains(capability))
+    }
+
+    /// Forget a client and send the updated peer list to everyone still connected.
+    /// Returns false if the client wasn't connected.
+    pub fn remove_client(&mut self, client_id: u16) -> Result<bool, Error> {
+        if !self.forget_client(client_id) {
+            return Ok(false);
+        }
+        self.broadcast_peer_list()?;
+        Ok(true)
+    }
+
+    /// Close the session. The relay drops it and tells every client, whose on_session_closed fires;
+    /// the host forgets its clients and stops checking in until register is called again.
+    pub fn close_session(&mut self) -> Result<(), Error> {
+        let sequence = self.next_sequence(0);
+        send_session_close(&self.socket, self.relay_addr, self.client_id, sequence)?;
+        self.forget_session();
+        println!("[Host] Closed session {}", self.session_id);
+        Ok(())
+    }
+
+    /// Forget every client and stop checking in with the relay, once the session is closed
+    fn forget_session(&mut self) {
+        let clients: Vec<u16> = self.connected_clients.keys().copied().collect();
+        for client_id in clients {
+            self.forget_client(client_id);
+        }
+        self.deferred_requests.clear();
+        self.closed = true;
+    }
+
+    /// Drop a client's state without telling anyone, returning whether it was connected
+    fn forget_client(&mut self, client_id: u16) -> bool {
+        if self.connected_clients.remove(&client_id).is_none() {
+            return false;
+        }
+        self.resume_tokens.remove(&client_id);
+        self.spectators.remove(&client_id);
+        self.capabilities.remove(&client_id);
+        self.requested_names.remove(&client_id);
+        self.admitted.retain(|_, admitted| *admitted != client_id);
+        self.awaiting_ready.remove(&client_id);
+        self.last_heard.remove(&client_id);
+        self.pings.forget(client_id);
+        self.pending_acks.retain(|&(pending_client, _), _| pending_client != client_id);
+        self.outgoing_sequences.remove(&client_id);
+        self.packet_sizes.remove(&client_id);
+        self.session_configs.remove(&client_id);
+        self.declared_features.remove(&client_id);
+        self.socket.forget_version(client_id);
+        self.client_ids.release(client_id);
+        self.replication.forget(client_id);
+        self.voice.forget(client_id);
+        true
+    }
+
+    /// Get the round trips and pings measured for a connected client
+    pub fn client_stats(&self, client_id: u16) -> Option<ClientStats> {
+        let heard = self.last_heard.get(&client_id)?;
+        Some(ClientStats { since_heard: heard.elapsed(), ..self.pings.stats(client_id) })
+    }
+
+    /// Get the number of datagrams dropped because they couldn't be decoded
+    pub fn malformed_packet_count(&self) -> u64 {
+        self.malformed_packets
+    }
+
+    /// Get duplicate/out-of-order statistics for packets received from a client
+    pub fn sequence_stats(&self, client_id: u16) -> Option<SequenceStats> {
+        self.incoming_sequences.stats(client_id)
+    }
+
+    /// Duplicate/out-of-order statistics summed over every client heard from, including ones that left.
+    /// `rejected()` counts the packets dropped as replays.
+    pub fn sequence_totals(&self) -> SequenceStats {
+        self.incoming_sequences.totals()
+    }
+
+    /// Send a game packet to a connected client
+    pub fn send_game_packet(&mut self, client_id: u16, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.send_game_packet_with_priority(client_id, packet_type, data, Priority::Normal)
+    }
+
+    /// Send a game packet to a connected client, ahead of or behind other queued traffic when the link is backed up
+    pub fn send_game_packet_with_priority(&mut self, client_id: u16, packet_type: u8, data: &[u8], priority: Priority) -> Result<(), Error> {
+        protocol::check_game_packet_type(packet_type)?;
+        self.send_game_data(client_id, packet_type, data, priority)
+    }
+
+    /// Send a game packet to a connected client, flagged for it to acknowledge and resent until it does.
+    /// Skips the send queue. Fires on_delivery_failed if every retry goes unacknowledged.
+    pub fn send_reliable_game_packet(&mut self, client_id: u16, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        protocol::check_game_packet_type(packet_type)?;
+        self.send_reliable(client_id, packet_type, data)
+    }
 
+    fn send_reliable(&mut self, client_id: u16, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        let Some(&limit) = self.packet_sizes.get(&client_id) else {
+            return Err(Error::new(ErrorKind::NotFound, format!("Client {} is not connected", client_id)));
+        };
+        protocol::check_packet_size(data, limit, self.socket.compression_threshold)?;
+
+        let packet = NeonPacket {
+            packet_type,
+            sequence: self.next_sequence(client_id),
+            client_id: self.client_id,
+            destination_id: client_id,
+            payload: PacketPayload::GamePacket(data.to_vec()),
+        };
+        self.socket.send_reliable(&packet, self.relay_addr)?;
+        self.expect_ack(client_id, packet);
+        Ok(())
+    }
+
+    /// Send a game packet to every connected client
+    pub fn broadcast_game_packet(&mut self, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        protocol::check_game_packet_type(packet_type)?;
+        // Checked against the smallest limit up front, so either every client is sent it or none is
+        if let Some(&limit) = self.packet_sizes.values().min() {
+            protocol::check_packet_size(data, limit, self.socket.compression_threshold)?;
+        }
+        let client_ids: Vec<u16> = self.connected_clients.keys().copied().collect();
+        for client_id in client_ids {
+            self.send_game_data(client_id, packet_type, data, Priority::Normal)?;
+        }
+        Ok(())
+    }
+
+    /// Insert or replace a replicated entity, sent to clients on the next replicate()
+    pub fn set_entity(&mut self, entity_id: u32, data: &[u8]) -> Result<(), Error> {
+        self.replication.set(entity_id, data)
+    }
+
+    /// Stop replicating an entity. Returns false if it didn't exist.
+    pub fn remove_entity(&mut self, entity_id: u32) -> bool {
+        self.replication.remove(entity_id)
+    }
+
+    /// Get a replicated entity's current state
+    pub fn entity(&self, entity_id: u32) -> Option<&[u8]> {
+        self.replication.get(entity_id)
+    }
+
+    /// Send every connected client the entity state as of `tick` (which must increase each call).
+    /// Each client gets a delta against the last snapshot it acknowledged, or the full state
+    /// if it has none yet, such as just after joining.
+    pub fn replicate(&mut self, tick: u64) -> Result<(), Error> {
+        self.replication.commit(tick)?;
+        let mut client_ids: Vec<u16> = self.connected_clients.keys().copied().collect();
+        client_ids.sort_unstable();
+        for client_id in client_ids {
+            for part in self.replication.snapshot_for(client_id)? {
+                self.send_game_data(client_id, REPLICATION_SNAPSHOT, &part, Priority::Normal)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Call an RPC method on a client without waiting for a reply
+    pub fn rpc(&mut self, client_id: u16, method: &str, args: &[u8]) -> Result<(), Error> {
+        let request = self.rpc.request(client_id, method, args, None)?;
+        self.send_game_data(client_id, RPC_REQUEST, &request, Priority::Normal)
+    }
+
+    /// Call an RPC method on a client. The callback runs from process_packets with the reply,
+    /// the client's error, or a TimedOut error.
+    pub fn rpc_with_reply<F>(&mut self, client_id: u16, method: &str, args: &[u8], callback: F) -> Result<(), Error>
+    where
+        F: FnOnce(Result<Vec<u8>, Error>) + Send + 'static,
+    {
+        let request = self.rpc.request(client_id, method, args, Some(Box::new(callback)))?;
+        self.send_game_data(client_id, RPC_REQUEST, &request, Priority::Normal).inspect_err(|_| self.rpc.cancel(&request))
+    }
+
+    /// Send an encoded voice frame (e.g. Opus) to one client, or to every connected client (0).
+    /// Voice skips batching and is never retransmitted.
+    pub fn send_voice(&mut self, client_id: u16, frame: &[u8]) -> Result<(), Error> {
+        let recipients: Vec<u16> = if client_id == 0 {
+            self.connected_clients.keys().copied().collect()
+        } else if self.connected_clients.contains_key(&client_id) {
+            vec![client_id]
+        } else {
+            return Err(Error::new(ErrorKind::NotFound, format!("Client {} is not connected", client_id)));
+        };
+
+        let payload = self.voice.encode(frame)?;
+        for client_id in recipients {
+            let packet = NeonPacket {
+                packet_type: VOICE_FRAME,
+                sequence: self.next_sequence(client_id),
+                client_id: self.client_id,
+                destination_id: client_id,
+                payload: PacketPayload::GamePacket(payload.clone()),
+            };
+            self.scheduler.push(packet, Priority::High);
+        }
+        self.drain_outgoing()
+    }
+
+    fn send_game_data(&mut self, client_id: u16, packet_type: u8, data: &[u8], priority: Priority) -> Result<(), Error> {
+        let Some(&limit) = self.packet_sizes.get(&client_id) else {
+            return Err(Error::new(ErrorKind::NotFound, format!("Client {} is not connected", client_id)));
+        };
+        protocol::check_packet_size(data, limit, self.socket.compression_threshold)?;
+
+        let packet = NeonPacket {
+            packet_type,
+            sequence: self.next_sequence(client_id),
+            client_id: self.client_id,
+            destination_id: client_id,
+            payload: PacketPayload::GamePacket(data.to_vec()),
+        };
+        self.scheduler.push(packet, priority);
+        self.drain_outgoing()
+    }
+
+    /// Make the sends other threads queued through a HostSender
+    fn send_queued(&mut self) {
+        while let Ok(send) = self.queued_sends.try_recv() {
+            let result = match send {
+                QueuedSend::Chat { text, destination_id } => self.send_chat(&text, destination_id),
+                QueuedSend::Game { client_id, packet_type, data, priority } => {
+                    self.send_game_packet_with_priority(client_id, packet_type, &data, priority)
+                }
+                QueuedSend::Broadcast { packet_type, data } => self.broadcast_game_packet(packet_type, &data),
+            };
+            if let Err(e) = result {
+                println!("[Host] Dropping a send queued by another thread: {}", e);
+            }
+        }
+    }
+
+    /// Pace outgoing traffic at the congestion controller's rate, or else the fixed limit
+    fn apply_bandwidth_settings(&mut self) {
+        if let Some(controller) = &mut self.congestion {
+            controller.set_max_rate(self.bandwidth_limit.unwrap_or(DEFAULT_MAX_RATE));
+        }
+        let rate = self.congestion.as_ref().map(CongestionController::rate).or(self.bandwidth_limit);
+        match (&mut self.bandwidth, rate) {
+            (Some(limiter), Some(rate)) => limiter.set_bytes_per_second(rate),
+            (limiter, rate) => *limiter = rate.map(BandwidthLimiter::new),
+        }
+    }
+
+    /// Send queued packets in priority order until the socket or the bandwidth cap pushes back.
+    /// Voice is never batched.
+    fn drain_outgoing(&mut self) -> Result<(), Error> {
+        if let (Some(controller), Some(limiter)) = (&mut self.congestion, &mut self.bandwidth) {
+            controller.update();
+            limiter.set_bytes_per_second(controller.rate());
+        }
+        let (socket, batcher, relay_addr) = (&self.socket, &mut self.batcher, self.relay_addr);
+        let bandwidth = &mut self.bandwidth;
+        let mut over_budget = false;
+        self.scheduler.drain(|packet| {
+            if let Some(limiter) = bandwidth.as_mut()
+                && !limiter.try_consume(packet.encoded_len(socket.version_for(packet.destination_id)))
+            {
+                over_budget = true;
+                return Err(Error::new(ErrorKind::WouldBlock, "Outgoing bandwidth budget exhausted"));
+            }
+            match batcher {
+                Some(batcher) if packet.packet_type != VOICE_FRAME => batcher.push(socket, relay_addr, packet.clone()),
+                _ => socket.send_packet(packet, relay_addr),
+            }
+        })?;
+
+        if over_budget {
+            if !self.throttled
+                && let (Some(callback), Some(limiter)) = (&mut self.on_budget_exceeded, &self.bandwidth)
+            {
+                callback(self.scheduler.len(), limiter.bytes_per_second());
+            }
+            self.throttled = true;
+        } else if self.scheduler.is_empty() {
+            self.throttled = false;
+        }
+        Ok(())
+    }
+
+    /// Announce something to every connected client, e.g. "Server restarting in 5 minutes". Unlike chat,
+    /// announcements are sent reliably and fire on_announcement rather than on_chat_message.
+    pub fn announce(&mut self, text: &str) -> Result<(), Error> {
+        protocol::check_announcement_length(text)?;
+        let client_ids: Vec<u16> = self.connected_clients.keys().copied().collect();
+        for client_id in client_ids {
+            self.send_reliable(client_id, ANNOUNCEMENT, text.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Send a chat message to one client, or to every connected client (0)
+    pub fn send_chat(&mut self, text: &str, destination_id: u16) -> Result<(), Error> {
+        protocol::check_chat_length(text)?;
+
+        let recipients: Vec<u16> = if destination_id == 0 {
+            self.connected_clients.keys().copied().collect()
+        } else if self.connected_clients.contains_key(&destination_id) {
+            vec![destination_id]
+        } else {
+            return Err(Error::new(ErrorKind::NotFound, format!("Client {} is not connected", destination_id)));
+        };
+
+        // Each client gets its own copy so it stays in that client's sequence stream
+        for client_id in recipients {
+            let sequence = self.next_sequence(client_id);
+            send_chat(&self.socket, self.relay_addr, self.client_id, text, client_id, sequence)?;
+        }
+        Ok(())
+    }
+
+    /// Serialize and send a typed message to a connected client (requires the `serde` feature)
+    #[cfg(feature = "serde")]
+    pub fn send_message<M: Message>(&mut self, client_id: u16, message: &M) -> Result<(), Error> {
+        self.send_game_packet(client_id, M::PACKET_TYPE, &encode_message(message)?)
+    }
+
+    /// Register this host's session with the relay. Blocks until the relay's challenge arrives
+    /// and its cookie has been echoed, proving this host receives at its address. If the relay
+    /// address resolved to several, each is tried in turn until one answers.
+    pub fn register(&mut self) -> Result<(), Error> {
+        let mut failures = Vec::new();
         loop {
-            self.check_pending_acks()?;
+            let error = match self.register_at_relay() {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            // The relay answered and said no; another address won't change that
+            if error.kind() == ErrorKind::ConnectionRefused {
+                return Err(error);
+            }
+            failures.push(format!("{}: {}", self.relay_addr, error));
+            let Some(next) = self.relay_candidates.pop_front() else {
+                if failures.len() == 1 {
+                    return Err(error);
+                }
+                return Err(Error::new(
+                    ErrorKind::NotConnected,
+                    format!("Could not reach relay at any resolved address ({})", failures.join("; ")),
+                ));
+            };
+            println!("[Host] Relay {} did not answer, trying {}", self.relay_addr, next);
+            self.switch_relay(next)?;
+        }
+    }
+
+    fn register_at_relay(&mut self) -> Result<(), Error> {
+        self.begin_register()?;
+        let cookie = wait_for_challenge(&mut self.socket, REGISTRATION_TIMEOUT)?;
+        self.answer_challenge(cookie)
+    }
+
+    /// Talk to the relay at `relay_addr` from now on, reopening the socket if it can't reach it
+    fn switch_relay(&mut self, relay_addr: SocketAddr) -> Result<(), Error> {
+        // A socket pinned to a chosen address or port is kept, even if it can't reach the relay
+        if !self.socket_options.pins_local_addr()
+            && let Some(transport) = self.socket.socket.reopen_for(&relay_addr)?
+        {
+            transport.apply_socket_options(&self.socket_options)?;
+            let mut socket = NeonSocket::from_transport(transport)?;
+            socket.compression_threshold = self.socket.compression_threshold;
+            self.socket = socket;
+        }
+        self.relay_addr = relay_addr;
+        Ok(())
+    }
+
+    /// Start registering without waiting; process_packets answers the relay's challenge when it
+    /// arrives. Only the current relay address is tried.
+    pub fn begin_register(&mut self) -> Result<(), Error> {
+        self.closed = false;
+        let sequence = self.next_sequence(0);
+        send_host_registration(&self.socket, self.relay_addr, self.registration(0), sequence)
+    }
+
+    fn answer_challenge(&mut self, cookie: u64) -> Result<(), Error> {
+        let sequence = self.next_sequence(0);
+        send_host_registration(&self.socket, self.relay_addr, self.registration(cookie), sequence)
+    }
+
+    /// The ConnectAccept registering this host, echoing the relay's `cookie`
+    fn registration(&self, cookie: u64) -> ConnectAccept {
+        ConnectAccept {
+            cookie,
+            join_code: self.join_code.clone().unwrap_or_default(),
+            metadata: self.metadata.clone(),
+            ..self.accept_for(self.client_id, 0, 0)
+        }
+    }
+
+    /// Retry unacknowledged packets, flush batches, and handle every packet waiting (non-blocking)
+    pub fn process_packets(&mut self) -> Result<(), Error> {
+        self.check_pending_acks()?;
+        self.welcome_overdue_clients()?;
+        self.drop_silent_clients()?;
+        self.ping_clients()?;
+        if !self.closed && self.relay_keepalive.is_some_and(|interval| self.last_keepalive.elapsed() >= interval) {
+            let sequence = self.next_sequence(0);
+            send_relay_keepalive(&self.socket, self.relay_addr, self.client_id, sequence)?;
+            self.last_keepalive = Instant::now();
+        }
+        self.rpc.expire_calls();
+        self.flush()?;
 
-            match self.socket.receive_packet() {
-                Ok((packet, addr)) => match packet.payload {
+        loop {
+            let received = self.socket.receive_packet();
+            self.report_malformed();
+            // Piggybacked acks count even on a packet that turns out to be a duplicate
+            if let Ok((packet, _)) = &received
+                && !self.socket.last_acks.is_empty()
+            {
+                let acknowledged_sequences = std::mem::take(&mut self.socket.last_acks);
+                self.handle_ack(packet.client_id, Ack { acknowledged_sequences })?;
+            }
+            match received {
+                // Unassigned senders (client_id 0) are mid-handshake and have no sequence stream yet
+                Ok((packet, _)) if packet.client_id != 0
+                    && !self.incoming_sequences.record(packet.client_id, packet.sequence).should_accept() => {}
+                Ok((packet, addr)) => {
+                    if let Some(heard) = self.last_heard.get_mut(&packet.client_id) {
+                        *heard = Instant::now();
+                    }
+                    match packet.payload {
                     PacketPayload::ConnectRequest(req) => {
                         self.handle_connect_request(req, addr)?;
                     }
                     PacketPayload::Ack(ack) => {
                         self.handle_ack(packet.client_id, ack)?;
                     }
+                    // A client's confirmation of its accept, passed on once the relay has registered it
+                    PacketPayload::ConnectAccept(accept) if packet.client_id > 1 => {
+                        if self.awaiting_ready.contains_key(&packet.client_id) {
+                            self.declared_features.insert(packet.client_id, accept.features);
+                        }
+                        self.pending_acks.retain(|&(pending_client, _), pending| {
+                            pending_client != packet.client_id || pending.packet.packet_type != PacketType::ConnectAccept as u8
+                        });
+                        self.welcome_client(packet.client_id)?;
+                    }
+                    // Still answered if it arrives after register() stopped waiting. One without a
+                    // cookie means the relay lost the registration, and answering it starts over,
+                    // unless the session was closed on purpose.
+                    PacketPayload::Challenge(challenge) => {
+                        if !self.closed {
+                            self.answer_challenge(challenge.cookie)?;
+                        }
+                    }
+                    // Only the relay itself sends these; a client's would carry its own ID.
+                    // One naming the host means the relay closed the whole session.
+                    PacketPayload::DisconnectNotice(notice) if packet.client_id == 0 => {
+                        if notice.client_id == self.client_id {
+                            self.forget_session();
+                            println!("[Host] Session {} {}", self.session_id, notice.reason);
+                            if let Some(callback) = &mut self.on_session_closed {
+                                callback(notice.reason);
+                            }
+                        } else if self.remove_client(notice.client_id)? {
+                            println!("[Host] Client {} {}", notice.client_id, notice.reason);
+                            if let Some(callback) = &mut self.on_client_disconnect {
+                                callback(notice.client_id, notice.reason);
+                            }
+                        }
+                    }
                     PacketPayload::Ping(_) => {
-                        handle_ping(&self.socket, self.relay_addr, self.client_id, &packet)?;
+                        let sequence = self.next_sequence(packet.client_id);
+                        handle_ping(&self.socket, self.relay_addr, self.client_id, &packet, sequence)?;
                         
                         if let Some(callback) = &mut self.on_ping_received {
                             callback(packet.client_id);
                         }
                     }
+                    PacketPayload::Pong(pong) => {
+                        self.pings.pong(packet.client_id, pong.original_timestamp);
+                    }
+                    PacketPayload::MtuProbe(ref probe) => {
+                        let size = probe.probe_size as usize;
+                        if size <= self.max_packet_size
+                            && let Some(limit) = self.packet_sizes.get_mut(&packet.client_id)
+                        {
+                            *limit = (*limit).max(size);
+                            let sequence = self.next_sequence(packet.client_id);
+                            handle_mtu_probe(&self.socket, self.relay_addr, self.client_id, &packet, sequence)?;
+                        }
+                    }
+                    PacketPayload::TimeSync(_) => {
+                        let host_time = self.host_time();
+                        let sequence = self.next_sequence(packet.client_id);
+                        handle_time_sync(&self.socket, self.relay_addr, self.client_id, &packet, host_time, sequence)?;
+                    }
+                    PacketPayload::Chat(_) if !self.client_may(packet.client_id, Capabilities::CHAT) => {
+                        println!("[Host] Ignoring chat from client {}, which can't chat", packet.client_id);
+                    }
+                    PacketPayload::Chat(chat) if self.on_chat_message.is_some() => {
+                        if let Some(callback) = &mut self.on_chat_message {
+                            callback(packet.client_id, chat.sender_name, chat.text);
+                        }
+                    }
+                    PacketPayload::GamePacket(data) if packet.packet_type == NAME_CHANGE => {
+                        let renamed = self.handle_name_change(packet.client_id, &data);
+                        self.skip_malformed(addr, renamed)?;
+                    }
+                    // Like spectators, whose packets to other clients the relay drops, clients without
+                    // SEND_GAME can only answer what they receive
+                    PacketPayload::GamePacket(_)
+                        if !self.client_may(packet.client_id, Capabilities::SEND_GAME) && !spectator_may_send(packet.packet_type) =>
+                    {
+                        println!("[Host] Dropping packet type 0x{:02X} from client {}, which can't send game packets", packet.packet_type, packet.client_id);
+                    }
+                    PacketPayload::GamePacket(data) if packet.packet_type == REPLICATION_ACK => {
+                        if let Some(tick) = self.skip_malformed(addr, decode_ack(&data))? {
+                            self.replication.ack(packet.client_id, tick);
+                        }
+                    }
+                    PacketPayload::GamePacket(data) if packet.packet_type == RPC_REQUEST => {
+                        let response = self.rpc.handle_request(packet.client_id, &data);
+                        if let Some(Some(response)) = self.skip_malformed(addr, response)? {
+                            self.send_game_data(packet.client_id, RPC_RESPONSE, &response, Priority::Normal)?;
+                        }
+                    }
+                    PacketPayload::GamePacket(data) if packet.packet_type == RPC_RESPONSE => {
+                        let handled = self.rpc.handle_response(packet.client_id, &data);
+                        self.skip_malformed(addr, handled)?;
+                    }
+                    PacketPayload::GamePacket(data) if packet.packet_type == VOICE_FRAME => {
+                        let frame = self.voice.receive(packet.client_id, &data);
+                        if let Some(frame) = self.skip_malformed(addr, frame)?
+                            && let Some(callback) = &mut self.on_voice_frame
+                        {
+                            callback(packet.client_id, frame.sequence, &frame.data);
+                        }
+                    }
+                    PacketPayload::GamePacket(data) if self.messages.handles(packet.packet_type) => {
+                        let dispatched = self.messages.dispatch(packet.packet_type, packet.client_id, &data);
+                        self.skip_malformed(addr, dispatched)?;
+                    }
+                    // Reserved types this end has no use for never reach the game
+                    PacketPayload::GamePacket(data) if self.on_game_packet.is_some() && !RESERVED_GAME_TYPES.contains(&packet.packet_type) => {
+                        if let Some(callback) = &mut self.on_game_packet {
+                            callback(packet.packet_type, packet.client_id, &data);
+                        }
+                    }
                     _ => {
                         if let Some(callback) = &mut self.on_unhandled_packet {
                             callback(packet.packet_type, packet.client_id, addr);
                         }
                     }
-                },
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    sleep(Duration::from_millis(10));
+                    }
                 }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
                 Err(e) => return Err(e),
             }
         }
     }
 
+    /// Start the host and begin accepting connections (blocks)
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.register()?;
+
+        loop {
+            self.process_packets()?;
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn next_sequence(&mut self, destination_id: u16) -> u16 {
+        self.outgoing_sequences.entry(destination_id).or_default().advance()
+    }
+
+    /// Send every client the current roster
+    fn broadcast_peer_list(&mut self) -> Result<(), Error> {
+        let mut peers: Vec<PeerInfo> = self.connected_clients
+            .iter()
+            .map(|(&client_id, name)| PeerInfo { client_id, name: name.clone(), role: self.client_role(client_id).unwrap_or_default() })
+            .collect();
+        peers.sort_by_key(|peer| peer.client_id);
+
+        let client_ids: Vec<u16> = peers.iter().map(|peer| peer.client_id).collect();
+        for client_id in client_ids {
+            // v1 clients can't represent wider IDs, so they only see peers they could address
+            let max_id = max_client_id(self.socket.version_for(client_id));
+            let visible = peers.iter().filter(|peer| peer.client_id <= max_id).cloned().collect();
+            let sequence = self.next_sequence(client_id);
+            send_peer_list(&self.socket, self.relay_addr, self.client_id, client_id, visible, sequence)?;
+        }
+        Ok(())
+    }
+
     fn check_pending_acks(&mut self) -> Result<(), Error> {
         let mut to_retry = Vec::new();
         let mut to_remove = Vec::new();
 
-    for (client_id, pending) in &self.pending_acks {
-            if pending.sent_at.elapsed() >= ACK_TIMEOUT {
-                if pending.retry_count >= MAX_RETRIES {
-                    to_remove.push(*client_id);
+        for (&key, pending) in &self.pending_acks {
+            if pending.sent_at.elapsed() >= self.ack_timeout {
+                if pending.retry_count >= self.max_retries {
+                    to_remove.push(key);
                 } else {
-                    to_retry.push(*client_id);
+                    to_retry.push(key);
                 }
             }
         }
 
-    for client_id in to_retry {
-            if let Some(pending) = self.pending_acks.get_mut(&client_id) {
-                self.socket.send_packet(&pending.packet, self.relay_addr)?;
+        for key in to_retry {
+            if let Some(pending) = self.pending_acks.get_mut(&key) {
+                if let Some(controller) = &mut self.congestion {
+                    controller.on_loss();
+                }
+                if pending.packet.packet_type == PacketType::ConnectAccept as u8 {
+                    // Confirmed through the relay rather than acked
+                    self.socket.send_packet(&pending.packet, self.relay_addr)?;
+                } else {
+                    self.socket.send_reliable(&pending.packet, self.relay_addr)?;
+                }
                 pending.sent_at = Instant::now();
                 pending.retry_count += 1;
             }
         }
 
-        for client_id in to_remove {
-            self.pending_acks.remove(&client_id);
+        for key in to_remove {
+            let Some(pending) = self.pending_acks.remove(&key) else { continue };
+            let (client_id, packet_type) = (key.0, pending.packet.packet_type);
+            if let Some(callback) = &mut self.on_delivery_failed {
+                callback(client_id, packet_type);
+            }
+            if is_handshake(packet_type) && self.remove_client(client_id)? {
+                println!("[Host] Dropped client {}: it never acknowledged packet 0x{:02X}", client_id, packet_type);
+            }
         }
 
         Ok(())
     }
 
-    fn handle_ack(&mut self, client_id: u8, ack: Ack) -> Result<(), Error> {
-        if let Some(pending) = self.pending_acks.get(&client_id) {
-            if ack.acknowledged_sequences.contains(&pending.sequence) {
-                self.pending_acks.remove(&client_id);
+    /// Resend `packet` until `client_id` acknowledges it
+    fn expect_ack(&mut self, client_id: u16, packet: NeonPacket) {
+        // A resent handshake packet replaces the one still waiting
+        if is_handshake(packet.packet_type) {
+            self.pending_acks.retain(|&(pending_client, _), pending| {
+                pending_client != client_id || pending.packet.packet_type != packet.packet_type
+            });
+        }
+        self.pending_acks.insert((client_id, packet.sequence), PendingAck {
+            packet,
+            sent_at: Instant::now(),
+            retry_count: 0,
+        });
+    }
+
+    fn handle_ack(&mut self, client_id: u16, ack: Ack) -> Result<(), Error> {
+        for sequence in ack.acknowledged_sequences {
+            let Some(pending) = self.pending_acks.remove(&(client_id, sequence)) else { continue };
+            // Round trips of retransmitted packets are ambiguous, so only first sends are sampled
+            if pending.retry_count == 0
+                && let Some(controller) = &mut self.congestion
+            {
+                controller.on_rtt_sample(pending.sent_at.elapsed());
             }
         }
 
@@ -183,52 +1532,377 @@ impl NeonHost {
         self.connected_clients.values().any(|n| n == name)
     }
 
+    /// `name`, numbered if it's taken: "Alice (2)", "Alice (3)" and so on
+    fn unique_name(&self, name: &str) -> String {
+        if !self.is_name_taken(name) {
+            return name.to_string();
+        }
+        (2..).map(|n| format!("{} ({})", name, n)).find(|numbered| !self.is_name_taken(numbered)).unwrap()
+    }
+
+    /// The name a client asking for `requested` gets, or why it can't have it
+    fn grant_name(&self, client_id: u16, requested: &str) -> Result<String, String> {
+        let requested = name::normalize(requested).map_err(|e| e.to_string())?;
+        if self.connected_clients.get(&client_id) == Some(&requested) || !self.is_name_taken(&requested) {
+            return Ok(requested);
+        }
+        if self.auto_suffix_names {
+            return Ok(self.unique_name(&requested));
+        }
+        Err(format!("Name '{}' is already in use", requested))
+    }
+
+    /// Rename a client that asked to be, or tell it why not
+    fn handle_name_change(&mut self, client_id: u16, data: &[u8]) -> Result<(), Error> {
+        let Some(old_name) = self.connected_clients.get(&client_id).cloned() else { return Ok(()) };
+        let answer = match std::str::from_utf8(data) {
+            Ok(requested) => self.grant_name(client_id, requested),
+            Err(_) => Err("Names must be valid UTF-8".to_string()),
+        };
+        let name = match answer {
+            Ok(name) => name,
+            Err(reason) => {
+                println!("[Host] Refused to rename client {}: {}", client_id, reason);
+                return self.send_reliable(client_id, NAME_CHANGE, &protocol::encode_name_answer(Err(&reason)));
+            }
+        };
+        self.send_reliable(client_id, NAME_CHANGE, &protocol::encode_name_answer(Ok(&name)))?;
+        if name == old_name {
+            return Ok(());
+        }
+
+        println!("[Host] Client {} renamed from '{}' to '{}'", client_id, old_name, name);
+        self.connected_clients.insert(client_id, name.clone());
+        self.broadcast_peer_list()?;
+        if let Some(callback) = &mut self.on_client_renamed {
+            callback(client_id, old_name, name);
+        }
+        Ok(())
+    }
+
     fn handle_connect_request(
         &mut self,
-        req: ConnectRequest,
+        mut req: ConnectRequest,
         _client_addr: SocketAddr,
     ) -> Result<(), Error> {
         if req.target_session_id != self.session_id {
             return Ok(());
         }
+        req.desired_name = name::strip(&req.desired_name);
+
+        if req.resume_token != 0 {
+            return self.resume_client(req);
+        }
+
+        // The client resent a request this host already answered or is still deciding on
+        if req.nonce != 0 {
+            if let Some(&client_id) = self.admitted.get(&req.nonce)
+                && (self.connected_clients.get(&client_id) == Some(&req.desired_name)
+                    || self.requested_names.get(&client_id) == Some(&req.desired_name))
+            {
+                return self.resend_accept(client_id, req);
+            }
+            if self.deferred_requests.values().any(|deferred| deferred.nonce == req.nonce) {
+                return Ok(());
+            }
+        }
 
-        if self.is_name_taken(&req.desired_name) {
+        if let Err(e) = name::check(&req.desired_name) {
+            return self.deny_connect_request(req, e.to_string());
+        }
+        if self.is_name_taken(&req.desired_name) && !self.auto_suffix_names {
             let reason = format!("Name '{}' is already in use", req.desired_name);
-            
-            if let Some(callback) = &mut self.on_client_deny {
-                callback(req.desired_name.clone(), reason.clone());
+            return self.deny_connect_request(req, reason);
+        }
+
+        if let Some(authenticator) = &self.authenticator
+            && let Err(reason) = authenticator.authenticate(&req.desired_name, req.target_session_id, &req.auth_token)
+        {
+            return self.deny_connect_request(req, reason);
+        }
+
+        let decision = match &mut self.on_connect_request {
+            Some(callback) => {
+                self.next_request_id = self.next_request_id.wrapping_add(1);
+                callback(self.next_request_id, &req.desired_name, req.game_identifier)
             }
+            None => ConnectDecision::Accept,
+        };
 
-            send_connect_deny(&self.socket, self.relay_addr, reason)?;
+        match decision {
+            ConnectDecision::Accept => self.admit_connect_request(req, None),
+            ConnectDecision::AcceptWith(capabilities) => self.admit_connect_request(req, Some(capabilities)),
+            ConnectDecision::Deny(reason) => self.deny_connect_request(req, reason),
+            ConnectDecision::Defer => {
+                self.deferred_requests.insert(self.next_request_id, req);
+                Ok(())
+            }
+        }
+    }
+
+    /// Re-admit a client the relay lost track of, under its old ID, if it presents that ID's resume token
+    fn resume_client(&mut self, req: ConnectRequest) -> Result<(), Error> {
+        let known = self.resume_tokens.get(&req.resume_id) == Some(&req.resume_token)
+            && self.connected_clients.get(&req.resume_id) == Some(&req.desired_name);
+        if !known {
+            return self.deny_connect_request(req, RESUME_REFUSED.to_string());
+        }
+
+        println!("[Host] Client {} is resuming its session", req.resume_id);
+        let sequence = self.next_sequence(req.resume_id);
+        let accept_packet = send_connect_accept(&self.socket, self.relay_addr, self.accept_for(req.resume_id, req.nonce, req.resume_token), sequence)?;
+        self.expect_ack(req.resume_id, accept_packet);
+        Ok(())
+    }
+
+    /// Answer a resent request for a client already admitted with the same accept, if it's still here
+    fn resend_accept(&mut self, client_id: u16, req: ConnectRequest) -> Result<(), Error> {
+        let Some(&resume_token) = self.resume_tokens.get(&client_id) else { return Ok(()) };
+        println!("[Host] Resending ConnectAccept for client {}", client_id);
+        let sequence = self.next_sequence(client_id);
+        let accept_packet = send_connect_accept(&self.socket, self.relay_addr, self.accept_for(client_id, req.nonce, resume_token), sequence)?;
+        self.expect_ack(client_id, accept_packet);
+        Ok(())
+    }
+
+    /// The accept admitting `client_id`, answering the request with `nonce`
+    fn accept_for(&self, client_id: u16, nonce: u32, resume_token: u64) -> ConnectAccept {
+        ConnectAccept {
+            assigned_client_id: client_id,
+            session_id: self.session_id,
+            cookie: 0,
+            nonce,
+            resume_token,
+            game_identifier: self.game_identifier,
+            join_code: String::new(),
+            metadata: SessionMetadata::default(),
+            features: FeatureSet::empty(),
+        }
+    }
+
+    fn deny_connect_request(&mut self, req: ConnectRequest, reason: String) -> Result<(), Error> {
+        if let Some(callback) = &mut self.on_client_deny {
+            callback(req.desired_name.clone(), reason.clone());
+        }
+
+        let sequence = self.next_sequence(0);
+        send_connect_deny(&self.socket, self.relay_addr, reason, req.nonce, sequence)
+    }
+
+    /// Admit a client with `capabilities`, or its role's defaults if None
+    fn admit_connect_request(&mut self, req: ConnectRequest, capabilities: Option<Capabilities>) -> Result<(), Error> {
+        let version = req.client_version.clamp(PROTOCOL_V1, PROTOCOL_VERSION);
+        let at_capacity = self.max_clients.is_some_and(|max| self.connected_clients.len() >= max);
+        let assigned_id = if at_capacity { None } else { self.client_ids.allocate(max_client_id(version)) };
+        let Some(assigned_id) = assigned_id else {
+            return self.deny_connect_request(req, SESSION_FULL.to_string());
+        };
+        self.socket.set_version(assigned_id, version);
+
+        self.outgoing_sequences.insert(assigned_id, SequenceCounter::new());
+        self.incoming_sequences.reset(assigned_id);
+
+        let resume_token = rand::random::<u64>().max(1);
+        self.resume_tokens.insert(assigned_id, resume_token);
+        let sequence = self.next_sequence(assigned_id);
+        let accept_packet = send_connect_accept(&self.socket, self.relay_addr, self.accept_for(assigned_id, req.nonce, resume_token), sequence)?;
+        // Acknowledged by the client's confirmation, which the relay passes on
+        self.expect_ack(assigned_id, accept_packet);
+
+        let name = self.unique_name(&req.desired_name);
+        if name != req.desired_name {
+            println!("[Host] '{}' is taken, admitting client {} as '{}'", req.desired_name, assigned_id, name);
+            self.requested_names.insert(assigned_id, req.desired_name);
+        }
+        self.connected_clients.insert(assigned_id, name);
+        if req.role == PeerRole::Spectator {
+            self.spectators.insert(assigned_id);
+        }
+        let capabilities = capabilities.unwrap_or(Capabilities::for_role(req.role));
+        self.capabilities.insert(assigned_id, allowed_capabilities(req.role, capabilities));
+        self.last_heard.insert(assigned_id, Instant::now());
+        if req.nonce != 0 {
+            self.admitted.insert(req.nonce, assigned_id);
+        }
+        self.packet_sizes.insert(assigned_id, DEFAULT_MAX_PACKET_SIZE);
+        // The rest waits until the relay can reach the client
+        self.awaiting_ready.insert(assigned_id, Instant::now());
+        Ok(())
+    }
+
+    /// Send a newly registered client its session config and packet types, tell everyone it joined,
+    /// and fire on_client_connect
+    fn welcome_client(&mut self, client_id: u16) -> Result<(), Error> {
+        if self.awaiting_ready.remove(&client_id).is_none() {
             return Ok(());
         }
+        let Some(name) = self.connected_clients.get(&client_id).cloned() else { return Ok(()) };
 
-        let assigned_id = self.next_client_id;
-        self.next_client_id += 1;
+        // Clients that predate feature negotiation, or whose confirmation never came, declare none
+        let features = self.features & self.declared_features.remove(&client_id).unwrap_or_default();
+        let config = protocol::SessionConfig {
+            version: self.socket.version_for(client_id),
+            tick_rate: self.tick_rate,
+            max_packet_size: self.max_packet_size as u16,
+            features,
+        };
+        let sequence = self.next_sequence(client_id);
+        let config_packet = send_session_config(&self.socket, self.relay_addr, self.client_id, client_id, &config, sequence)?;
 
-        send_connect_accept(&self.socket, self.relay_addr, assigned_id, self.session_id)?;
+        self.expect_ack(client_id, config_packet);
+        self.session_configs.insert(client_id, config);
 
-        // Delay is needed because the client doesn't have enough time to register otherwise
-        sleep(Duration::from_millis(50));
+        let sequence = self.next_sequence(client_id);
+        let entries = self.packet_types.values().cloned().collect();
+        let registry_packet = send_packet_type_registry(&self.socket, self.relay_addr, self.client_id, client_id, entries, sequence)?;
+        self.expect_ack(client_id, registry_packet);
 
-        let sequence = 2;
-        let config_packet = send_session_config(&self.socket, self.relay_addr, assigned_id, sequence)?;
+        // Clients assume the name they asked for until told otherwise
+        if self.requested_names.contains_key(&client_id) {
+            self.send_reliable(client_id, NAME_CHANGE, &protocol::encode_name_answer(Ok(&name)))?;
+        }
 
-        self.pending_acks.insert(assigned_id, PendingAck {
-            packet: config_packet,
-            sequence,
-            sent_at: Instant::now(),
-            retry_count: 0,
-        });
-        
-        send_packet_type_registry(&self.socket, self.relay_addr, assigned_id)?;
+        // Clients assume their role's defaults until told otherwise
+        if let Some(capabilities) = self.client_capabilities(client_id)
+            && let Some(role) = self.client_role(client_id)
+            && capabilities != Capabilities::for_role(role)
+        {
+            self.send_reliable(client_id, CAPABILITIES, &[capabilities.bits()])?;
+        }
+
+        let config_values: Vec<Vec<u8>> = self.config_values
+            .iter()
+            .map(|(key, value)| protocol::encode_config_value(key, Some(value)))
+            .collect();
+        for payload in config_values {
+            self.send_reliable(client_id, CONFIG_VALUE, &payload)?;
+        }
+
+        if let Some(motd) = self.motd.clone() {
+            self.send_reliable(client_id, ANNOUNCEMENT, motd.as_bytes())?;
+        }
+
+        self.broadcast_peer_list()?;
 
-        self.connected_clients.insert(assigned_id, req.desired_name.clone());
-        
         if let Some(callback) = &mut self.on_client_connect {
-            callback(assigned_id, req.desired_name, req.target_session_id);
+            callback(client_id, name, self.session_id);
+        }
+
+        Ok(())
+    }
+
+    /// Drop clients not heard from within the client timeout, if one is set
+    fn drop_silent_clients(&mut self) -> Result<(), Error> {
+        let Some(timeout) = self.client_timeout else { return Ok(()) };
+        let silent: Vec<u16> = self.last_heard
+            .iter()
+            .filter(|(_, heard)| heard.elapsed() >= timeout)
+            .map(|(&client_id, _)| client_id)
+            .collect();
+        for client_id in silent {
+            if self.remove_client(client_id)? {
+                println!("[Host] Client {} {}", client_id, DisconnectReason::TimedOut);
+                if let Some(callback) = &mut self.on_client_disconnect {
+                    callback(client_id, DisconnectReason::TimedOut);
+                }
+            }
         }
+        Ok(())
+    }
+
+    /// Log, count and report the datagrams the socket skipped as malformed
+    fn report_malformed(&mut self) {
+        for (addr, e) in self.socket.take_malformed() {
+            self.drop_malformed(addr, e);
+        }
+    }
 
+    /// Pass on the result of handling a packet, unless its payload didn't decode: that packet is
+    /// dropped and reported like a malformed datagram, and None returned
+    fn skip_malformed<V>(&mut self, addr: SocketAddr, result: Result<V, Error>) -> Result<Option<V>, Error> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if protocol::is_malformed(&e) => {
+                self.drop_malformed(addr, e);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn drop_malformed(&mut self, addr: SocketAddr, e: Error) {
+        println!("[Host] Dropped malformed datagram from {}: {}", addr, e);
+        self.malformed_packets += 1;
+        if let Some(callback) = &mut self.on_malformed_packet {
+            callback(addr, &e);
+        }
+    }
+
+    /// Ping the clients due one
+    fn ping_clients(&mut self) -> Result<(), Error> {
+        let Some(mut interval) = self.ping_interval else { return Ok(()) };
+        if let Some(timeout) = self.client_timeout {
+            interval = interval.min(timeout / 3);
+        }
+        let welcomed: Vec<u16> = self.connected_clients.keys().copied().filter(|id| !self.awaiting_ready.contains_key(id)).collect();
+        for (client_id, ping_id) in self.pings.due(welcomed, interval) {
+            let sequence = self.next_sequence(client_id);
+            send_client_ping(&self.socket, self.relay_addr, self.client_id, client_id, ping_id, sequence)?;
+        }
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn welcome_overdue_clients(&mut self) -> Result<(), Error> {
+        let overdue: Vec<u16> = self.awaiting_ready
+            .iter()
+            .filter(|(_, admitted_at)| admitted_at.elapsed() >= READY_TIMEOUT)
+            .map(|(&client_id, _)| client_id)
+            .collect();
+        for client_id in overdue {
+            self.welcome_client(client_id)?;
+        }
+        Ok(())
+    }
+}
+
+/// `capabilities` less what `role` can never have
+fn allowed_capabilities(role: PeerRole, capabilities: Capabilities) -> Capabilities {
+    match role {
+        PeerRole::Player => capabilities,
+        PeerRole::Spectator => capabilities - Capabilities::SEND_GAME,
+    }
+}
+
+/// Whether a client missing this packet can't finish joining
+fn is_handshake(packet_type: u8) -> bool {
+    [PacketType::ConnectAccept, PacketType::SessionConfig, PacketType::PacketTypeRegistry]
+        .iter()
+        .any(|&handshake| handshake as u8 == packet_type)
+}
+
+fn validate_packet_type(packet_id: u8, name: &str, description: &str) -> Result<PacketTypeEntry, Error> {
+    if packet_id < 0x10 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Packet type 0x{:02X} is in the reserved core range (0x00-0x0F)", packet_id),
+        ));
+    }
+    if RESERVED_GAME_TYPES.contains(&packet_id) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Packet type 0x{:02X} is reserved for built-in subsystems (0xF0-0xFF)", packet_id),
+        ));
+    }
+    // Names and descriptions are sent with a one-byte length prefix
+    if name.len() > u8::MAX as usize || description.len() > u8::MAX as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Packet type 0x{:02X} name and description must be at most 255 bytes", packet_id),
+        ));
+    }
+    Ok(PacketTypeEntry {
+        packet_id,
+        name: name.to_string(),
+        description: description.to_string(),
+    })
+}
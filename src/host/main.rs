@@ -27,7 +27,13 @@ fn main() {
         }
     };
 
+    if let Err(e) = host.register_packet_type(0x10, "GamePacket", "Application-defined packet") {
+        println!("Failed to register packet type: {}", e);
+        return;
+    }
+
     println!("Host will create session ID: {}", host.session_id());
+    println!("Join code: {}", host.generate_join_code());
     println!();
 
     if let Err(e) = host.start() {
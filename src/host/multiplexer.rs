@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+use super::{HostConfig, NeonHost};
+use crate::protocol::DisconnectReason;
+use crate::resolve::{resolve_addrs, unspecified_bind_addr};
+use crate::transport::Transport;
+
+pub type MultiplexedConnectCallback = Box<dyn FnMut(SessionHandle, u16, String) + Send>; // (session, client_id, name)
+pub type MultiplexedDisconnectCallback = Box<dyn FnMut(SessionHandle, u16, DisconnectReason) + Send>; // (session, client_id, reason)
+pub type MultiplexedGamePacketCallback = Box<dyn FnMut(SessionHandle, u8, u16, &[u8]) + Send>; // (session, packet_type, from_client_id, data)
+pub type MultiplexedSessionClosedCallback = Box<dyn FnMut(SessionHandle, DisconnectReason) + Send>; // (session, reason)
+
+/// Shared with every session's host, so a callback set later reaches sessions added earlier
+type Slot<C> = Arc<Mutex<Option<C>>>;
+
+/// One session of a NeonHostMultiplexer, as passed to its callbacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SessionHandle(u32);
+
+impl SessionHandle {
+    pub fn session_id(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Hosts many sessions from one thread, with one set of callbacks told which session each
+/// event is for. The relay tells peers apart by address and client ID, and every host is client 1,
+/// so every session still has its own socket.
+pub struct NeonHostMultiplexer<T: Transport = UdpSocket> {
+    relay_addr: SocketAddr,
+    config: HostConfig,
+    hosts: BTreeMap<SessionHandle, NeonHost<T>>,
+
+    on_client_connect: Slot<MultiplexedConnectCallback>,
+    on_client_disconnect: Slot<MultiplexedDisconnectCallback>,
+    on_game_packet: Slot<MultiplexedGamePacketCallback>,
+    on_session_closed: Slot<MultiplexedSessionClosedCallback>,
+}
+
+impl NeonHostMultiplexer {
+    /// Create a multiplexer for sessions on the relay at `relay_addr`, which may be a hostname;
+    /// the first resolved address is used
+    pub fn new<A: ToSocketAddrs>(relay_addr: A) -> Result<Self, Error> {
+        let relay_addr = resolve_addrs(relay_addr)?[0];
+        Ok(Self::with_relay(relay_addr))
+    }
+
+    /// Host `session_id` on a new UDP socket and register it with the relay (blocks like `NeonHost::register`)
+    pub fn add_session(&mut self, session_id: u32) -> Result<SessionHandle, Error> {
+        let socket = UdpSocket::bind(unspecified_bind_addr(&self.relay_addr))?;
+        self.add_session_with_transport(session_id, socket)
+    }
+}
+
+impl<T: Transport> NeonHostMultiplexer<T> {
+    /// Create a multiplexer whose sessions are added with add_session_with_transport
+    pub fn with_relay(relay_addr: SocketAddr) -> Self {
+        Self {
+            relay_addr,
+            config: HostConfig::default(),
+            hosts: BTreeMap::new(),
+            on_client_connect: Arc::new(Mutex::new(None)),
+            on_client_disconnect: Arc::new(Mutex::new(None)),
+            on_game_packet: Arc::new(Mutex::new(None)),
+            on_session_closed: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Set callback for when a client joins any session
+    pub fn on_client_connect<F>(&mut self, callback: F)
+    where
+        F: FnMut(SessionHandle, u16, String) + Send + 'static,
+    {
+        *self.on_client_connect.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    /// Set callback for when a client leaves any session
+    pub fn on_client_disconnect<F>(&mut self, callback: F)
+    where
+        F: FnMut(SessionHandle, u16, DisconnectReason) + Send + 'static,
+    {
+        *self.on_client_disconnect.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    /// Set callback for game packets (0x10+) arriving in any session
+    pub fn on_game_packet<F>(&mut self, callback: F)
+    where
+        F: FnMut(SessionHandle, u8, u16, &[u8]) + Send + 'static,
+    {
+        *self.on_game_packet.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    /// Set callback for when the relay closes a session
+    pub fn on_session_closed<F>(&mut self, callback: F)
+    where
+        F: FnMut(SessionHandle, DisconnectReason) + Send + 'static,
+    {
+        *self.on_session_closed.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    /// Settings for sessions added from now on. A tick rate of 0 makes add_session fail.
+    pub fn set_config(&mut self, config: HostConfig) {
+        self.config = config;
+    }
+
+    /// Host `session_id` over `transport` and register it with the relay (blocks like `NeonHost::register`)
+    pub fn add_session_with_transport(&mut self, session_id: u32, transport: T) -> Result<SessionHandle, Error> {
+        let session = SessionHandle(session_id);
+        if self.hosts.contains_key(&session) {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("Session {} is already hosted here", session_id)));
+        }
+
+        let mut host = NeonHost::with_transport(session_id, self.relay_addr, transport)?;
+        host.set_config(self.config.clone())?;
+        self.install_callbacks(session, &mut host);
+        host.register()?;
+        self.hosts.insert(session, host);
+        Ok(session)
+    }
+
+    /// Close a session and hand its host back. Returns None if it isn't hosted here.
+    pub fn remove_session(&mut self, session: SessionHandle) -> Result<Option<NeonHost<T>>, Error> {
+        let Some(mut host) = self.hosts.remove(&session) else { return Ok(None) };
+        host.close_session()?;
+        Ok(Some(host))
+    }
+
+    /// Get a session's host, to send through or configure
+    pub fn session(&mut self, session: SessionHandle) -> Option<&mut NeonHost<T>> {
+        self.hosts.get_mut(&session)
+    }
+
+    /// Get every session hosted here, by session ID
+    pub fn sessions(&self) -> Vec<SessionHandle> {
+        self.hosts.keys().copied().collect()
+    }
+
+    pub fn session_count(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// Process every session's packets (non-blocking). A session whose host fails is removed,
+    /// and the first failure is returned once the rest have been processed.
+    pub fn process_packets(&mut self) -> Result<(), Error> {
+        let mut failed = Vec::new();
+        for (&session, host) in &mut self.hosts {
+            if let Err(e) = host.process_packets() {
+                failed.push((session, e));
+            }
+        }
+
+        let mut first = None;
+        for (session, e) in failed {
+            println!("[Host] Dropping session {}: {}", session.session_id(), e);
+            self.hosts.remove(&session);
+            first.get_or_insert(Error::new(e.kind(), format!("Session {}: {}", session.session_id(), e)));
+        }
+        first.map_or(Ok(()), Err)
+    }
+
+    /// Process every session in a loop (blocks), returning the first failure
+    pub fn run(&mut self) -> Result<(), Error> {
+        loop {
+            self.process_packets()?;
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn install_callbacks(&self, session: SessionHandle, host: &mut NeonHost<T>) {
+        let slot = self.on_client_connect.clone();
+        host.on_client_connect(move |client_id, name, _session_id| {
+            if let Some(callback) = slot.lock().unwrap().as_mut() {
+                callback(session, client_id, name);
+            }
+        });
+        let slot = self.on_client_disconnect.clone();
+        host.on_client_disconnect(move |client_id, reason| {
+            if let Some(callback) = slot.lock().unwrap().as_mut() {
+                callback(session, client_id, reason);
+            }
+        });
+        let slot = self.on_game_packet.clone();
+        host.on_game_packet(move |packet_type, from, data| {
+            if let Some(callback) = slot.lock().unwrap().as_mut() {
+                callback(session, packet_type, from, data);
+            }
+        });
+        let slot = self.on_session_closed.clone();
+        host.on_session_closed(move |reason| {
+            if let Some(callback) = slot.lock().unwrap().as_mut() {
+                callback(session, reason);
+            }
+        });
+    }
+}
@@ -1,43 +1,125 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
 use super::types::*;
 use super::incoming::NeonSocket;
+use crate::protocol::{self, DisconnectReason, PROTOCOL_VERSION, header_size};
+use crate::transport::Transport;
 
 pub fn send_host_registration(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    host_client_id: u8,
-    session_id: u32,
+    registration: ConnectAccept,
+    sequence: u16,
 ) -> Result<(), Error> {
     let host_register_packet = NeonPacket {
         packet_type: PacketType::ConnectAccept as u8,
-        sequence: 0,
-        client_id: host_client_id,
+        sequence,
+        client_id: registration.assigned_client_id,
         destination_id: 1,
-        payload: PacketPayload::ConnectAccept(ConnectAccept {
-            assigned_client_id: host_client_id,
-            session_id,
-        }),
+        payload: PacketPayload::ConnectAccept(registration),
     };
 
     socket.send_packet(&host_register_packet, relay_addr)?;
     Ok(())
 }
 
-pub fn send_connect_accept(
-    socket: &NeonSocket,
+/// Wait for the relay's answer to a cookieless registration, skipping anything else that arrives
+pub fn wait_for_challenge(socket: &mut NeonSocket<impl Transport>, timeout: Duration) -> Result<u64, Error> {
+    let deadline = Instant::now() + timeout;
+    socket.socket.set_nonblocking(false)?;
+    let result = loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break Err(Error::new(ErrorKind::TimedOut, "Relay did not answer the host registration"));
+        }
+        socket.socket.set_read_timeout(Some(remaining))?;
+        match socket.receive_packet() {
+            Ok((packet, _)) => {
+                match packet.payload {
+                    PacketPayload::Challenge(challenge) => break Ok(challenge.cookie),
+                    PacketPayload::ConnectDeny(deny) => break Err(Error::new(ErrorKind::ConnectionRefused, deny.reason)),
+                    _ => {}
+                }
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    socket.socket.set_read_timeout(None)?;
+    socket.socket.set_nonblocking(true)?;
+    result
+}
+
+/// Ping the relay itself (destination 0). The relay only answers when it has no registration for this
+/// address, which is how an otherwise quiet host learns the relay restarted.
+pub fn send_relay_keepalive(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    host_client_id: u16,
+    sequence: u16,
+) -> Result<(), Error> {
+    let keepalive = NeonPacket {
+        packet_type: PacketType::Ping as u8,
+        sequence,
+        client_id: host_client_id,
+        destination_id: 0,
+        payload: PacketPayload::Ping(Ping { timestamp: 0 }),
+    };
+    socket.send_packet(&keepalive, relay_addr)
+}
+
+/// Ping a client, which answers with a Pong echoing `timestamp` (an id for matching, not a clock reading)
+pub fn send_client_ping(
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    assigned_id: u8,
-    session_id: u32,
+    host_client_id: u16,
+    client_id: u16,
+    timestamp: u64,
+    sequence: u16,
 ) -> Result<(), Error> {
-    let accept = ConnectAccept {
-        assigned_client_id: assigned_id,
-        session_id,
+    let ping = NeonPacket {
+        packet_type: PacketType::Ping as u8,
+        sequence,
+        client_id: host_client_id,
+        destination_id: client_id,
+        payload: PacketPayload::Ping(Ping { timestamp }),
     };
+    socket.send_packet(&ping, relay_addr)
+}
 
+/// Tell the relay (destination 0) the host is closing its session
+pub fn send_session_close(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    host_client_id: u16,
+    sequence: u16,
+) -> Result<(), Error> {
+    let notice = NeonPacket {
+        packet_type: PacketType::DisconnectNotice as u8,
+        sequence,
+        client_id: host_client_id,
+        destination_id: 0,
+        payload: PacketPayload::DisconnectNotice(DisconnectNotice {
+            client_id: host_client_id,
+            reason: DisconnectReason::HostClosed,
+        }),
+    };
+    socket.send_packet(&notice, relay_addr)
+}
+
+/// Send `accept` to its client through the relay. The relay adds the client's cookie when it passes it on.
+pub fn send_connect_accept(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    accept: ConnectAccept,
+    sequence: u16,
+) -> Result<NeonPacket, Error> {
+    let assigned_id = accept.assigned_client_id;
     let accept_packet = NeonPacket {
         packet_type: PacketType::ConnectAccept as u8,
-        sequence: 1,
+        sequence,
         client_id: assigned_id,
         destination_id: assigned_id,
         payload: PacketPayload::ConnectAccept(accept),
@@ -45,76 +127,188 @@ pub fn send_connect_accept(
 
     socket.send_packet(&accept_packet, relay_addr)?;
     println!("[Host] Sent ConnectAccept to relay for client {}", assigned_id);
-    Ok(())
+    Ok(accept_packet)
 }
 
 pub fn send_connect_deny(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
     reason: String,
+    nonce: u32,
+    sequence: u16,
 ) -> Result<(), Error> {
     let deny_packet = NeonPacket {
         packet_type: PacketType::ConnectDeny as u8,
-        sequence: 1,
+        sequence,
         client_id: 1,
         destination_id: 0,
-        payload: PacketPayload::ConnectDeny(ConnectDeny { reason }),
+        payload: PacketPayload::ConnectDeny(ConnectDeny { reason, nonce }),
     };
 
-    socket.send_packet(&deny_packet, relay_addr)?;
+    // Only the relay reads this, and the nonce needs a version 2 header
+    socket.send_packet_at(&deny_packet, PROTOCOL_VERSION, relay_addr)?;
     println!("[Host] Sent ConnectDeny to relay");
     Ok(())
 }
 
 pub fn send_session_config(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    assigned_id: u8,
+    host_client_id: u16,
+    assigned_id: u16,
+    config: &protocol::SessionConfig,
     sequence: u16,
 ) -> Result<NeonPacket, Error> {
     let config = SessionConfig {
-        version: 1,
-        tick_rate: 60,
-        max_packet_size: 1024,
+        version: config.version,
+        tick_rate: config.tick_rate,
+        max_packet_size: config.max_packet_size,
+        features: config.features,
     };
 
     let config_packet = NeonPacket {
         packet_type: PacketType::SessionConfig as u8,
         sequence,
-        client_id: assigned_id,
+        client_id: host_client_id,
         destination_id: assigned_id,
         payload: PacketPayload::SessionConfig(config),
     };
 
-    socket.send_packet(&config_packet, relay_addr)?;
+    socket.send_reliable(&config_packet, relay_addr)?;
     println!("[Host] Sent SessionConfig to relay for client {}", assigned_id);
     Ok(config_packet)
 }
 
 pub fn send_packet_type_registry(
-    socket: &NeonSocket,
+    socket: &NeonSocket<impl Transport>,
     relay_addr: SocketAddr,
-    assigned_id: u8,
-) -> Result<(), Error> {
-    let registry = PacketTypeRegistry {
-        entries: vec![
-            PacketTypeEntry {
-                packet_id: 0x10,
-                name: "GamePacket".to_string(),
-                description: "Application-defined packet".to_string(),
-            },
-        ],
-    };
-    
+    host_client_id: u16,
+    assigned_id: u16,
+    entries: Vec<PacketTypeEntry>,
+    sequence: u16,
+) -> Result<NeonPacket, Error> {
+    let registry = PacketTypeRegistry { entries };
+
     let registry_packet = NeonPacket {
         packet_type: PacketType::PacketTypeRegistry as u8,
-        sequence: 3,
-        client_id: assigned_id,
+        sequence,
+        client_id: host_client_id,
         destination_id: assigned_id,
         payload: PacketPayload::PacketTypeRegistry(registry),
     };
     
-    socket.send_packet(&registry_packet, relay_addr)?;
+    socket.send_reliable(&registry_packet, relay_addr)?;
     println!("[Host] Sent PacketTypeRegistry to relay for client {}", assigned_id);
-    Ok(())
+    Ok(registry_packet)
+}
+
+pub fn send_peer_list(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    host_client_id: u16,
+    assigned_id: u16,
+    peers: Vec<PeerInfo>,
+    sequence: u16,
+) -> Result<(), Error> {
+    let packet = NeonPacket {
+        packet_type: PacketType::PeerList as u8,
+        sequence,
+        client_id: host_client_id,
+        destination_id: assigned_id,
+        payload: PacketPayload::PeerList(PeerList { peers }),
+    };
+
+    socket.send_packet(&packet, relay_addr)
+}
+
+pub fn send_chat(
+    socket: &NeonSocket<impl Transport>,
+    relay_addr: SocketAddr,
+    host_client_id: u16,
+    text: &str,
+    destination_id: u16,
+    sequence: u16,
+) -> Result<(), Error> {
+    let packet = NeonPacket {
+        packet_type: PacketType::Chat as u8,
+        sequence,
+        client_id: host_client_id,
+        destination_id,
+        payload: PacketPayload::Chat(Chat {
+            sender_name: HOST_CHAT_NAME.to_string(),
+            text: text.to_string(),
+        }),
+    };
+
+    socket.send_packet(&packet, relay_addr)
+}
+
+/// Name chat messages from the host are shown under
+pub const HOST_CHAT_NAME: &str = "Host";
+
+/// Datagram size every client starts at, and the default advertised in SessionConfig
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 1024;
+
+/// Collects outgoing packets per destination so several can share one datagram
+pub struct PacketBatcher {
+    max_packet_size: usize,
+    queues: HashMap<u16, (Vec<NeonPacket>, usize)>,
+}
+
+impl PacketBatcher {
+    pub fn new(max_packet_size: usize) -> Self {
+        Self {
+            max_packet_size,
+            queues: HashMap::new(),
+        }
+    }
+
+    /// Queue a packet, first sending the pending batch for its destination if it would overflow
+    pub fn push(&mut self, socket: &NeonSocket<impl Transport>, relay_addr: SocketAddr, packet: NeonPacket) -> Result<(), Error> {
+        // Batch header + entry count (1), then a length prefix (2) per entry
+        let version = socket.version_for(packet.destination_id);
+        let batch_overhead = header_size(version) + 1;
+        let entry_len = 2 + packet.encoded_len(version);
+        if batch_overhead + entry_len > self.max_packet_size {
+            return socket.send_packet(&packet, relay_addr);
+        }
+
+        let destination_id = packet.destination_id;
+        let (queued, queued_len) = self.queues.entry(destination_id).or_insert_with(|| (Vec::new(), batch_overhead));
+        if queued.len() == u8::MAX as usize || *queued_len + entry_len > self.max_packet_size {
+            let full = std::mem::take(queued);
+            *queued_len = batch_overhead;
+            send_batch(socket, relay_addr, full)?;
+        }
+
+        queued.push(packet);
+        *queued_len += entry_len;
+        Ok(())
+    }
+
+    /// Send everything that is queued
+    pub fn flush(&mut self, socket: &NeonSocket<impl Transport>, relay_addr: SocketAddr) -> Result<(), Error> {
+        for (_, (queued, _)) in self.queues.drain() {
+            if !queued.is_empty() {
+                send_batch(socket, relay_addr, queued)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn send_batch(socket: &NeonSocket<impl Transport>, relay_addr: SocketAddr, mut packets: Vec<NeonPacket>) -> Result<(), Error> {
+    if packets.len() == 1 {
+        return socket.send_packet(&packets.remove(0), relay_addr);
+    }
+
+    let first = &packets[0];
+    let batch_packet = NeonPacket {
+        packet_type: PacketType::Batch as u8,
+        sequence: first.sequence,
+        client_id: first.client_id,
+        destination_id: first.destination_id,
+        payload: PacketPayload::Batch(packets),
+    };
+    socket.send_packet(&batch_packet, relay_addr)
 }
\ No newline at end of file
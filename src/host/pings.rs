@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::timesync::RttEstimator;
+
+/// Shortest wait for a pong before its ping counts as lost
+const MIN_PONG_WAIT: Duration = Duration::from_millis(500);
+
+/// Round trips a pong may take, going by the last one measured, before its ping counts as lost
+const PONG_WAIT_RTTS: u32 = 4;
+
+/// What the host has measured of a client's connection by pinging it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClientStats {
+    /// Latest round trip (None until a ping is answered)
+    pub rtt: Option<Duration>,
+    /// Round trip averaged over recent pings
+    pub smoothed_rtt: Option<Duration>,
+    /// Average variation between round trips
+    pub jitter: Duration,
+    pub pings_sent: u64,
+    pub pongs_received: u64,
+    /// Pings not answered in time
+    pub pings_lost: u64,
+    /// Time since anything was last heard from the client
+    pub since_heard: Duration,
+}
+
+#[derive(Default)]
+struct PeerPings {
+    stats: ClientStats,
+    rtt: RttEstimator,
+    last_sent: Option<Instant>,
+    /// Id of the ping awaiting a pong, and when it went
+    outstanding: Option<(u64, Instant)>,
+}
+
+/// Schedules the host's pings to each client and matches their pongs into round trips
+pub struct ClientPings {
+    peers: HashMap<u16, PeerPings>,
+    next_id: u64,
+}
+
+impl ClientPings {
+    pub fn new() -> Self {
+        Self { peers: HashMap::new(), next_id: 0 }
+    }
+
+    /// The clients due a ping at `interval`, each with the id its ping should carry as the timestamp.
+    /// A client waits for the pong to its last ping until it's overdue, by its own round trip, and the
+    /// ping counts as lost. Round trips are timed on the local monotonic clock, never from the id.
+    pub fn due(&mut self, client_ids: impl IntoIterator<Item = u16>, interval: Duration) -> Vec<(u16, u64)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for client_id in client_ids {
+            let peer = self.peers.entry(client_id).or_default();
+            if let Some((_, sent_at)) = peer.outstanding {
+                let wait = peer.rtt.smoothed().map_or(MIN_PONG_WAIT, |rtt| (rtt * PONG_WAIT_RTTS).max(MIN_PONG_WAIT));
+                if now.duration_since(sent_at) < wait {
+                    continue;
+                }
+                peer.stats.pings_lost += 1;
+                peer.outstanding = None;
+            }
+            if peer.last_sent.is_some_and(|sent_at| now.duration_since(sent_at) < interval) {
+                continue;
+            }
+            self.next_id = self.next_id.wrapping_add(1);
+            peer.last_sent = Some(now);
+            peer.outstanding = Some((self.next_id, now));
+            peer.stats.pings_sent += 1;
+            due.push((client_id, self.next_id));
+        }
+        due
+    }
+
+    /// Record a client's pong, returning the round trip if it answers the ping still awaited
+    pub fn pong(&mut self, client_id: u16, ping_id: u64) -> Option<Duration> {
+        let peer = self.peers.get_mut(&client_id)?;
+        let (_, sent_at) = peer.outstanding.filter(|&(id, _)| id == ping_id)?;
+        let rtt = sent_at.elapsed();
+        peer.outstanding = None;
+        peer.rtt.add_sample(rtt);
+        peer.stats.rtt = Some(rtt);
+        peer.stats.smoothed_rtt = peer.rtt.smoothed();
+        peer.stats.jitter = peer.rtt.jitter();
+        peer.stats.pongs_received += 1;
+        Some(rtt)
+    }
+
+    pub fn stats(&self, client_id: u16) -> ClientStats {
+        self.peers.get(&client_id).map(|peer| peer.stats).unwrap_or_default()
+    }
+
+    pub fn forget(&mut self, client_id: u16) {
+        self.peers.remove(&client_id);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+use std::io::Error;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use super::NeonHost;
+use crate::protocol::DisconnectReason;
+use crate::transport::{Outgoing, QueueTransport};
+
+/// Something that happened to a HostProtocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostEvent {
+    ClientConnected { client_id: u16, name: String },
+    ClientDisconnected { client_id: u16, reason: DisconnectReason },
+    GamePacket { packet_type: u8, from: u16, data: Vec<u8> },
+    ChatMessage { from: u16, name: String, text: String },
+    DeliveryFailed { client_id: u16, packet_type: u8 },
+    SessionClosed(DisconnectReason),
+}
+
+/// A host without a socket: feed it received datagrams and send what it returns. Events come
+/// out of `poll_event` instead of callbacks. Timers (acks, keepalives, timeouts) read the system
+/// clock when `poll` or `handle_datagram` runs, so call `poll` every few milliseconds.
+pub struct HostProtocol {
+    host: NeonHost<QueueTransport>,
+    transport: QueueTransport,
+    events: Arc<Mutex<VecDeque<HostEvent>>>,
+}
+
+impl HostProtocol {
+    /// Create a host for `session_id` on the relay at `relay_addr`. Nothing is sent until `register`.
+    pub fn new(session_id: u32, relay_addr: SocketAddr) -> Result<Self, Error> {
+        let transport = QueueTransport::new(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+        let mut host = NeonHost::with_transport(session_id, relay_addr, transport.clone())?;
+        let events = Arc::new(Mutex::new(VecDeque::new()));
+
+        let queue = events.clone();
+        host.on_client_connect(move |client_id, name, _session_id| {
+            queue.lock().unwrap().push_back(HostEvent::ClientConnected { client_id, name });
+        });
+        let queue = events.clone();
+        host.on_client_disconnect(move |client_id, reason| {
+            queue.lock().unwrap().push_back(HostEvent::ClientDisconnected { client_id, reason });
+        });
+        let queue = events.clone();
+        host.on_game_packet(move |packet_type, from, data| {
+            queue.lock().unwrap().push_back(HostEvent::GamePacket { packet_type, from, data: data.to_vec() });
+        });
+        let queue = events.clone();
+        host.on_chat_message(move |from, name, text| {
+            queue.lock().unwrap().push_back(HostEvent::ChatMessage { from, name, text });
+        });
+        let queue = events.clone();
+        host.on_delivery_failed(move |client_id, packet_type| {
+            queue.lock().unwrap().push_back(HostEvent::DeliveryFailed { client_id, packet_type });
+        });
+        let queue = events.clone();
+        host.on_session_closed(move |reason| {
+            queue.lock().unwrap().push_back(HostEvent::SessionClosed(reason));
+        });
+
+        Ok(Self { host, transport, events })
+    }
+
+    /// Start registering the session, returning the registration to send. The relay's challenge
+    /// is answered when it's handed to `handle_datagram`.
+    pub fn register(&mut self) -> Result<Outgoing, Error> {
+        self.host.begin_register()?;
+        Ok(self.transport.take_outgoing())
+    }
+
+    /// Handle a datagram received from `from`, returning the datagrams to send in answer
+    pub fn handle_datagram(&mut self, data: &[u8], from: SocketAddr) -> Result<Outgoing, Error> {
+        self.transport.push_incoming(data, from);
+        self.poll()
+    }
+
+    /// Run timers and flush queued sends, returning the datagrams to send
+    pub fn poll(&mut self) -> Result<Outgoing, Error> {
+        self.host.process_packets()?;
+        Ok(self.transport.take_outgoing())
+    }
+
+    /// Take the oldest event not yet taken
+    pub fn poll_event(&mut self) -> Option<HostEvent> {
+        self.events.lock().unwrap().pop_front()
+    }
+
+    /// Get the host, to send or configure. Sends are returned by the next `poll`. Replacing a
+    /// callback HostProtocol set stops that event being reported.
+    pub fn host(&mut self) -> &mut NeonHost<QueueTransport> {
+        &mut self.host
+    }
+}
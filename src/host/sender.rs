@@ -0,0 +1,53 @@
+use std::io::{Error, ErrorKind};
+use std::sync::mpsc::Sender;
+
+use crate::protocol;
+use crate::scheduler::Priority;
+
+/// A send requested through a HostSender, made by the host at its next pump
+pub(crate) enum QueuedSend {
+    Chat { text: String, destination_id: u16 },
+    Game { client_id: u16, packet_type: u8, data: Vec<u8>, priority: Priority },
+    Broadcast { packet_type: u8, data: Vec<u8> },
+}
+
+/// Handle for sending from other threads, from `NeonHost::sender`. Cloneable, Send and Sync.
+/// Sends are queued and go out on the host's next process_packets or flush; one that fails
+/// there is logged and dropped.
+#[derive(Clone)]
+pub struct HostSender {
+    queue: Sender<QueuedSend>,
+}
+
+impl HostSender {
+    pub(crate) fn new(queue: Sender<QueuedSend>) -> Self {
+        HostSender { queue }
+    }
+
+    /// Queue a chat message to one client, or to every connected client (0)
+    pub fn send_chat(&self, text: &str, destination_id: u16) -> Result<(), Error> {
+        protocol::check_chat_length(text)?;
+        self.queue(QueuedSend::Chat { text: text.to_string(), destination_id })
+    }
+
+    /// Queue a game packet to a connected client
+    pub fn send_game_packet(&self, client_id: u16, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.send_game_packet_with_priority(client_id, packet_type, data, Priority::Normal)
+    }
+
+    /// Queue a game packet to a connected client at a priority
+    pub fn send_game_packet_with_priority(&self, client_id: u16, packet_type: u8, data: &[u8], priority: Priority) -> Result<(), Error> {
+        protocol::check_game_packet_type(packet_type)?;
+        self.queue(QueuedSend::Game { client_id, packet_type, data: data.to_vec(), priority })
+    }
+
+    /// Queue a game packet to every connected client
+    pub fn broadcast_game_packet(&self, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        protocol::check_game_packet_type(packet_type)?;
+        self.queue(QueuedSend::Broadcast { packet_type, data: data.to_vec() })
+    }
+
+    fn queue(&self, send: QueuedSend) -> Result<(), Error> {
+        self.queue.send(send).map_err(|_| Error::new(ErrorKind::NotConnected, "Host has been dropped"))
+    }
+}
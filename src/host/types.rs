@@ -1,15 +1,19 @@
 use std::io::{Error, ErrorKind};
-use std::convert::TryInto;
+use crate::decoder::Decoder;
+use crate::protocol::{DisconnectReason, FeatureSet, PeerRole, SPECTATOR_FLAG, SessionMetadata, header_size, is_v1, push_client_id, push_version, read_client_id, read_version};
 use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub struct PacketHeader {
     pub magic: u16,
+    /// Protocol version, without any flags older versions carry in the same byte
     pub version: u8,
+    /// FLAG_* bits from protocol
+    pub flags: u8,
     pub packet_type: u8,
     pub sequence: u16,
-    pub client_id: u8,
-    pub destination_id: u8,
+    pub client_id: u16,
+    pub destination_id: u16,
 }
 
 #[derive(Debug, Clone)]
@@ -20,9 +24,16 @@ pub enum PacketPayload {
     ConnectRequest(ConnectRequest),
     ConnectAccept(ConnectAccept),
     ConnectDeny(ConnectDeny),
+    Challenge(Challenge),
+    DisconnectNotice(DisconnectNotice),
     SessionConfig(SessionConfig),
     PacketTypeRegistry(PacketTypeRegistry),
+    Chat(Chat),
+    PeerList(PeerList),
+    TimeSync(TimeSync),
+    MtuProbe(MtuProbe),
     Ack(Ack),
+    Batch(Vec<NeonPacket>),
     GamePacket(Vec<u8>),
 }
 
@@ -30,8 +41,8 @@ pub enum PacketPayload {
 pub struct NeonPacket {
     pub packet_type: u8,
     pub sequence: u16,
-    pub client_id: u8,
-    pub destination_id: u8,
+    pub client_id: u16,
+    pub destination_id: u16,
     pub payload: PacketPayload,
 }
 
@@ -41,17 +52,57 @@ pub struct ConnectRequest {
     pub desired_name: String,
     pub target_session_id: u32,
     pub game_identifier: u32,
+    /// Set by the relay when forwarding so the host's answer can be matched to this request.
+    /// Not carried in version 1 headers.
+    pub nonce: u32,
+    /// ID to take back after the relay lost track of this client, with the host's resume token.
+    /// Both 0 for a fresh join; not carried in version 1 headers.
+    pub resume_id: u16,
+    pub resume_token: u64,
+    /// Opaque credential for the host's or relay's Authenticator, empty if none
+    pub auth_token: Vec<u8>,
+    /// Role the client asks to join as, flagged in the client_version byte
+    pub role: PeerRole,
 }
 
 #[derive(Debug, Clone)]
 pub struct ConnectAccept {
-    pub assigned_client_id: u8,
+    pub assigned_client_id: u16,
     pub session_id: u32,
+    /// Relay cookie proving the sender can receive at its address (0 when there is none)
+    pub cookie: u64,
+    /// Nonce of the ConnectRequest being answered (0 when there is none)
+    pub nonce: u32,
+    /// Secret the client presents to resume its ID after a relay restart (0 when there is none)
+    pub resume_token: u64,
+    /// Game the session belongs to (0 when unset). Only sent when set.
+    pub game_identifier: u32,
+    /// Code clients can join the session with, carried only in host registrations (empty when unset)
+    pub join_code: String,
+    /// What the host advertises about the session, carried only in host registrations (empty when unset)
+    pub metadata: SessionMetadata,
+    /// Features the client supports, carried only in its confirmation of the accept (empty when unset)
+    pub features: FeatureSet,
 }
 
 #[derive(Debug, Clone)]
 pub struct ConnectDeny {
     pub reason: String,
+    /// Nonce of the ConnectRequest being answered. Not carried in version 1 headers.
+    pub nonce: u32,
+}
+
+/// Sent by the relay in answer to an unproven registration; echo the cookie in ConnectAccept
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub cookie: u64,
+}
+
+/// Sent by the relay to a session's host when it drops one of the host's clients
+#[derive(Debug, Clone)]
+pub struct DisconnectNotice {
+    pub client_id: u16,
+    pub reason: DisconnectReason,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -69,6 +120,8 @@ pub struct SessionConfig {
     pub version: u8,
     pub tick_rate: u16,
     pub max_packet_size: u16,
+    /// Features both ends support; empty from hosts that predate feature negotiation
+    pub features: FeatureSet,
 }
 
 #[derive(Debug, Clone)]
@@ -76,9 +129,41 @@ pub struct Ack {
     pub acknowledged_sequences: Vec<u16>,
 }
 
+#[derive(Debug, Clone)]
+pub struct MtuProbe {
+    /// Total datagram size being tested, header included; the payload is padded to reach it
+    pub probe_size: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeSync {
+    /// Client's local clock when the request was sent (microseconds)
+    pub client_time: u64,
+    /// Host clock when the request was answered (microseconds, 0 in requests)
+    pub host_time: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PeerList {
+    pub peers: Vec<PeerInfo>,
+}
+
+/// A client in the session, as listed in PeerList
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub client_id: u16,
+    pub name: String,
+    pub role: PeerRole,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chat {
+    pub sender_name: String,
+    pub text: String,
+}
+
 pub struct PendingAck {
     pub packet: NeonPacket,
-    pub sequence: u16,
     pub sent_at: Instant,
     pub retry_count: u8,
 }
@@ -97,44 +182,98 @@ pub struct PacketTypeEntry {
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
+#[allow(dead_code, clippy::enum_variant_names)]
 pub enum PacketType {
     ConnectRequest = 0x01,
     ConnectAccept = 0x02,
     ConnectDeny = 0x03,
     SessionConfig = 0x04,
     PacketTypeRegistry = 0x05,
+    Batch = 0x06,
+    Chat = 0x07,
+    PeerList = 0x08,
+    TimeSync = 0x09,
+    MtuProbe = 0x0A,
     Ping = 0x0B,
     Pong = 0x0C,
     DisconnectNotice = 0x0D,
     Ack = 0x0E,
+    Challenge = 0x0F,
     GamePacket = 0x10,
 }
 
 impl PacketPayload {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    /// Encode for a packet whose header carries `version`, which decides the width of client IDs
+    pub fn to_bytes(&self, version: u8) -> Vec<u8> {
         match self {
             PacketPayload::None => vec![],
             PacketPayload::Ping(ping) => ping.timestamp.to_le_bytes().to_vec(),
             PacketPayload::Pong(pong) => pong.original_timestamp.to_le_bytes().to_vec(),
             PacketPayload::ConnectRequest(req) => {
-                let mut bytes = vec![req.client_version];
+                let role_flag = if req.role == PeerRole::Spectator { SPECTATOR_FLAG } else { 0 };
+                let mut bytes = vec![req.client_version | role_flag];
                 bytes.extend(&req.target_session_id.to_le_bytes());
                 bytes.extend(&req.game_identifier.to_le_bytes());
+                if !is_v1(version) {
+                    bytes.extend(&req.nonce.to_le_bytes());
+                    bytes.extend(&req.resume_id.to_le_bytes());
+                    bytes.extend(&req.resume_token.to_le_bytes());
+                }
                 bytes.extend(req.desired_name.as_bytes());
+                // Names can't contain NUL, so requests without a token are unchanged
+                if !req.auth_token.is_empty() {
+                    bytes.push(0);
+                    bytes.extend(&req.auth_token);
+                }
                 bytes
             }
             PacketPayload::ConnectAccept(accept) => {
-                let mut bytes = vec![accept.assigned_client_id];
+                let mut bytes = Vec::new();
+                push_client_id(&mut bytes, accept.assigned_client_id, version);
                 bytes.extend(&accept.session_id.to_le_bytes());
+                bytes.extend(&accept.cookie.to_le_bytes());
+                bytes.extend(&accept.nonce.to_le_bytes());
+                bytes.extend(&accept.resume_token.to_le_bytes());
+                // Each trailing field is written when it or a later one is set
+                let has_features = !accept.features.is_empty();
+                let has_metadata = !accept.metadata.is_empty() || has_features;
+                if accept.game_identifier != 0 || !accept.join_code.is_empty() || has_metadata {
+                    bytes.extend(&accept.game_identifier.to_le_bytes());
+                }
+                if !accept.join_code.is_empty() || has_metadata {
+                    bytes.push(accept.join_code.len() as u8);
+                    bytes.extend(accept.join_code.as_bytes());
+                }
+                if has_metadata {
+                    accept.metadata.encode(&mut bytes);
+                }
+                if has_features {
+                    bytes.extend(&accept.features.bits().to_le_bytes());
+                }
+                bytes
+            }
+            PacketPayload::Challenge(challenge) => challenge.cookie.to_le_bytes().to_vec(),
+            PacketPayload::DisconnectNotice(notice) => {
+                let mut bytes = Vec::new();
+                push_client_id(&mut bytes, notice.client_id, version);
+                bytes.push(notice.reason as u8);
                 bytes
             }
             PacketPayload::ConnectDeny(deny) => {
-                deny.reason.as_bytes().to_vec()
+                let mut bytes = Vec::new();
+                if !is_v1(version) {
+                    bytes.extend(&deny.nonce.to_le_bytes());
+                }
+                bytes.extend(deny.reason.as_bytes());
+                bytes
             }
             PacketPayload::SessionConfig(config) => {
                 let mut bytes = vec![config.version];
                 bytes.extend(&config.tick_rate.to_le_bytes());
                 bytes.extend(&config.max_packet_size.to_le_bytes());
+                if !config.features.is_empty() {
+                    bytes.extend(&config.features.bits().to_le_bytes());
+                }
                 bytes
             }
             PacketPayload::PacketTypeRegistry(registry) => {
@@ -151,6 +290,38 @@ impl PacketPayload {
                 }
                 bytes
             }
+            PacketPayload::Chat(chat) => {
+                let name_bytes = &chat.sender_name.as_bytes()[..chat.sender_name.floor_char_boundary(u8::MAX as usize)];
+                let mut bytes = vec![name_bytes.len() as u8];
+                bytes.extend(name_bytes);
+                bytes.extend(chat.text.as_bytes());
+                bytes
+            }
+            PacketPayload::PeerList(list) => {
+                let mut bytes = Vec::new();
+                push_client_id(&mut bytes, list.peers.len() as u16, version);
+                for peer in &list.peers {
+                    let name_bytes = &peer.name.as_bytes()[..peer.name.floor_char_boundary(u8::MAX as usize)];
+                    push_client_id(&mut bytes, peer.client_id, version);
+                    bytes.push(name_bytes.len() as u8);
+                    bytes.extend(name_bytes);
+                }
+                // Roles follow every entry, so lists of players alone are unchanged
+                if list.peers.iter().any(|peer| peer.role != PeerRole::Player) {
+                    bytes.extend(list.peers.iter().map(|peer| peer.role as u8));
+                }
+                bytes
+            }
+            PacketPayload::TimeSync(sync) => {
+                let mut bytes = sync.client_time.to_le_bytes().to_vec();
+                bytes.extend(&sync.host_time.to_le_bytes());
+                bytes
+            }
+            PacketPayload::MtuProbe(probe) => {
+                let mut bytes = probe.probe_size.to_le_bytes().to_vec();
+                bytes.resize((probe.probe_size as usize).saturating_sub(header_size(version)).max(2), 0);
+                bytes
+            }
             PacketPayload::Ack(ack) => {
                 let mut bytes = Vec::new();
                 bytes.push(ack.acknowledged_sequences.len() as u8);
@@ -159,157 +330,285 @@ impl PacketPayload {
                 }
                 bytes
             }
+            PacketPayload::Batch(packets) => {
+                let mut bytes = vec![packets.len() as u8];
+                for packet in packets {
+                    let encoded = packet.to_bytes(version);
+                    bytes.extend(&(encoded.len() as u16).to_le_bytes());
+                    bytes.extend(encoded);
+                }
+                bytes
+            }
             PacketPayload::GamePacket(data) => data.clone(),
         }
     }
 
-    pub fn from_bytes(packet_type: u8, data: &[u8]) -> Result<Self, Error> {
+    /// Decode a payload from a packet whose header carries `version`
+    pub fn from_bytes(packet_type: u8, data: &[u8], version: u8) -> Result<Self, Error> {
         match packet_type {
             x if x == PacketType::Ping as u8 => {
-                if data.len() < 8 {
-                    return Err(Error::new(ErrorKind::InvalidData, "Ping too short"));
-                }
-                Ok(PacketPayload::Ping(Ping {
-                    timestamp: u64::from_le_bytes(data[0..8].try_into().unwrap()),
-                }))
+                let timestamp = Decoder::new(data, "Ping").u64()?;
+                Ok(PacketPayload::Ping(Ping { timestamp }))
             }
             x if x == PacketType::Pong as u8 => {
-                if data.len() < 8 {
-                    return Err(Error::new(ErrorKind::InvalidData, "Pong too short"));
-                }
-                Ok(PacketPayload::Pong(Pong {
-                    original_timestamp: u64::from_le_bytes(data[0..8].try_into().unwrap()),
-                }))
+                let original_timestamp = Decoder::new(data, "Pong").u64()?;
+                Ok(PacketPayload::Pong(Pong { original_timestamp }))
             }
             x if x == PacketType::ConnectRequest as u8 => {
-                if data.len() < 9 {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "ConnectRequest too short",
-                    ));
-                }
-                let client_version = data[0];
-                let target_session_id = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
-                let game_identifier = u32::from_le_bytes([data[5], data[6], data[7], data[8]]);
-                let desired_name = String::from_utf8_lossy(&data[9..]).to_string();
+                let mut decoder = Decoder::new(data, "ConnectRequest");
+                let version_byte = decoder.u8()?;
+                let client_version = version_byte & !SPECTATOR_FLAG;
+                let role = if version_byte & SPECTATOR_FLAG != 0 { PeerRole::Spectator } else { PeerRole::Player };
+                let target_session_id = decoder.u32()?;
+                let game_identifier = decoder.u32()?;
+                let (nonce, resume_id, resume_token) = if is_v1(version) {
+                    (0, 0, 0)
+                } else {
+                    (decoder.u32()?, decoder.u16()?, decoder.u64()?)
+                };
+                let rest = decoder.rest();
+                let (name, auth_token) = match rest.iter().position(|&b| b == 0) {
+                    Some(end) => (&rest[..end], rest[end + 1..].to_vec()),
+                    None => (rest, Vec::new()),
+                };
                 Ok(PacketPayload::ConnectRequest(ConnectRequest {
                     client_version,
-                    desired_name,
+                    desired_name: String::from_utf8_lossy(name).into_owned(),
                     target_session_id,
                     game_identifier,
+                    nonce,
+                    resume_id,
+                    resume_token,
+                    auth_token,
+                    role,
                 }))
             }
             x if x == PacketType::ConnectAccept as u8 => {
-                if data.len() < 5 {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "ConnectAccept too short",
-                    ));
-                }
-                let client_id = data[0];
-                let session_id = u32::from_le_bytes(data[1..5].try_into().unwrap());
+                let mut decoder = Decoder::new(data, "ConnectAccept");
                 Ok(PacketPayload::ConnectAccept(ConnectAccept {
-                    assigned_client_id: client_id,
-                    session_id,
+                    assigned_client_id: read_client_id(&mut decoder, version)?,
+                    session_id: decoder.u32()?,
+                    // Older peers send no cookie, nonce, resume token, game identifier, join code, metadata or features
+                    cookie: if decoder.is_empty() { 0 } else { decoder.u64()? },
+                    nonce: if decoder.is_empty() { 0 } else { decoder.u32()? },
+                    resume_token: if decoder.is_empty() { 0 } else { decoder.u64()? },
+                    game_identifier: if decoder.is_empty() { 0 } else { decoder.u32()? },
+                    join_code: if decoder.is_empty() { String::new() } else { decoder.short_string()? },
+                    metadata: if decoder.is_empty() { SessionMetadata::default() } else { SessionMetadata::decode(&mut decoder)? },
+                    features: if decoder.is_empty() { FeatureSet::empty() } else { FeatureSet::from_bits_retain(decoder.u64()?) },
+                }))
+            }
+            x if x == PacketType::Challenge as u8 => {
+                let cookie = Decoder::new(data, "Challenge").u64()?;
+                Ok(PacketPayload::Challenge(Challenge { cookie }))
+            }
+            x if x == PacketType::DisconnectNotice as u8 => {
+                let mut decoder = Decoder::new(data, "DisconnectNotice");
+                Ok(PacketPayload::DisconnectNotice(DisconnectNotice {
+                    client_id: read_client_id(&mut decoder, version)?,
+                    reason: DisconnectReason::from(decoder.u8()?),
                 }))
             }
             x if x == PacketType::ConnectDeny as u8 => {
-                let reason = String::from_utf8_lossy(data).to_string();
-                Ok(PacketPayload::ConnectDeny(ConnectDeny { reason }))
+                let mut decoder = Decoder::new(data, "ConnectDeny");
+                let nonce = if is_v1(version) { 0 } else { decoder.u32()? };
+                let reason = decoder.rest_string();
+                Ok(PacketPayload::ConnectDeny(ConnectDeny { reason, nonce }))
             }
             x if x == PacketType::SessionConfig as u8 => {
-                if data.len() < 5 {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "SessionConfig too short",
-                    ));
-                }
-                let version = data[0];
-                let tick_rate = u16::from_le_bytes([data[1], data[2]]);
-                let max_packet_size = u16::from_le_bytes([data[3], data[4]]);
+                let mut decoder = Decoder::new(data, "SessionConfig");
                 Ok(PacketPayload::SessionConfig(SessionConfig {
-                    version,
-                    tick_rate,
-                    max_packet_size,
+                    version: decoder.u8()?,
+                    tick_rate: decoder.u16()?,
+                    max_packet_size: decoder.u16()?,
+                    features: if decoder.is_empty() { FeatureSet::empty() } else { FeatureSet::from_bits_retain(decoder.u64()?) },
                 }))
             }
             x if x == PacketType::PacketTypeRegistry as u8 => {
-                if data.is_empty() {
-                    return Ok(PacketPayload::PacketTypeRegistry(PacketTypeRegistry { entries: vec![] }));
-                }
-                let mut idx = 0;
+                let mut decoder = Decoder::new(data, "PacketTypeRegistry");
                 let mut entries = Vec::new();
-                if idx >= data.len() {
-                    return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed"));
+                if !decoder.is_empty() {
+                    let count = decoder.u8()?;
+                    for _ in 0..count {
+                        entries.push(PacketTypeEntry {
+                            packet_id: decoder.u8()?,
+                            name: decoder.short_string()?,
+                            description: decoder.short_string()?,
+                        });
+                    }
                 }
-                let count = data[idx] as usize;
-                idx += 1;
+                Ok(PacketPayload::PacketTypeRegistry(PacketTypeRegistry { entries }))
+            }
+            x if x == PacketType::Chat as u8 => {
+                let mut decoder = Decoder::new(data, "Chat");
+                Ok(PacketPayload::Chat(Chat {
+                    sender_name: decoder.short_string()?,
+                    text: decoder.rest_string(),
+                }))
+            }
+            x if x == PacketType::PeerList as u8 => {
+                let mut decoder = Decoder::new(data, "PeerList");
+                let count = read_client_id(&mut decoder, version)?;
+                let mut peers = Vec::new();
                 for _ in 0..count {
-                    if idx + 2 > data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                    let packet_id = data[idx]; idx += 1;
-                    let name_len = data[idx] as usize; idx += 1;
-                    if idx + name_len > data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                    let name = String::from_utf8_lossy(&data[idx..idx+name_len]).to_string(); idx += name_len;
-                    if idx >= data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                    let desc_len = data[idx] as usize; idx += 1;
-                    if idx + desc_len > data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                    let description = String::from_utf8_lossy(&data[idx..idx+desc_len]).to_string(); idx += desc_len;
-                    entries.push(PacketTypeEntry { packet_id, name, description });
+                    peers.push(PeerInfo {
+                        client_id: read_client_id(&mut decoder, version)?,
+                        name: decoder.short_string()?,
+                        role: PeerRole::Player,
+                    });
                 }
-                Ok(PacketPayload::PacketTypeRegistry(PacketTypeRegistry { entries }))
+                if !decoder.is_empty() {
+                    for peer in &mut peers {
+                        peer.role = PeerRole::from(decoder.u8()?);
+                    }
+                }
+                Ok(PacketPayload::PeerList(PeerList { peers }))
+            }
+            x if x == PacketType::TimeSync as u8 => {
+                let mut decoder = Decoder::new(data, "TimeSync");
+                Ok(PacketPayload::TimeSync(TimeSync {
+                    client_time: decoder.u64()?,
+                    host_time: decoder.u64()?,
+                }))
+            }
+            x if x == PacketType::MtuProbe as u8 => {
+                let mut decoder = Decoder::new(data, "MtuProbe");
+                Ok(PacketPayload::MtuProbe(MtuProbe {
+                    probe_size: decoder.u16()?,
+                }))
             }
             x if x == PacketType::Ack as u8 => {
-                if data.is_empty() {
-                    return Ok(PacketPayload::Ack(Ack { acknowledged_sequences: vec![] }));
+                let mut decoder = Decoder::new(data, "Ack");
+                let mut acknowledged_sequences = Vec::new();
+                if !decoder.is_empty() {
+                    let count = decoder.u8()?;
+                    for _ in 0..count {
+                        acknowledged_sequences.push(decoder.u16()?);
+                    }
                 }
-                let count = data[0] as usize;
-                let mut sequences = Vec::new();
-                let mut offset = 1;
+                Ok(PacketPayload::Ack(Ack { acknowledged_sequences }))
+            }
+            x if x == PacketType::Batch as u8 => {
+                let mut decoder = Decoder::new(data, "Batch");
+                let count = decoder.u8()? as usize;
+                let mut packets = Vec::with_capacity(count);
                 for _ in 0..count {
-                    if offset + 2 <= data.len() {
-                        sequences.push(u16::from_le_bytes([data[offset], data[offset + 1]]));
-                        offset += 2;
+                    let len = decoder.u16()? as usize;
+                    let packet = NeonPacket::from_bytes(decoder.bytes(len)?)?;
+                    if let PacketPayload::Batch(_) = packet.payload {
+                        return Err(Error::new(ErrorKind::InvalidData, "Nested batches are not allowed"));
                     }
+                    packets.push(packet);
                 }
-                Ok(PacketPayload::Ack(Ack { acknowledged_sequences: sequences }))
-            }
-            x if x >= 0x10 => {
-                Ok(PacketPayload::GamePacket(data.to_vec()))
+                Ok(PacketPayload::Batch(packets))
             }
+            x if x >= 0x10 => Ok(PacketPayload::GamePacket(data.to_vec())),
             _ => Ok(PacketPayload::None),
         }
     }
 }
 
+impl NeonPacket {
+    /// Build the header for this packet with the given version byte
+    pub fn header(&self, version: u8) -> PacketHeader {
+        PacketHeader {
+            magic: 0x4E45,
+            version,
+            flags: 0,
+            packet_type: self.packet_type,
+            sequence: self.sequence,
+            client_id: self.client_id,
+            destination_id: self.destination_id,
+        }
+    }
+
+    /// Encode header and payload without compression
+    pub fn to_bytes(&self, version: u8) -> Vec<u8> {
+        let mut bytes = self.header(version).to_bytes();
+        bytes.extend(self.payload.to_bytes(version));
+        bytes
+    }
+
+    /// Decode an uncompressed packet
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let header = PacketHeader::from_bytes(data)?;
+        if header.flags & crate::protocol::FLAG_COMPRESSED != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Unexpected compressed packet"));
+        }
+        Ok(NeonPacket {
+            packet_type: header.packet_type,
+            sequence: header.sequence,
+            client_id: header.client_id,
+            destination_id: header.destination_id,
+            payload: PacketPayload::from_bytes(header.packet_type, &data[header.size()..], header.version)?,
+        })
+    }
+
+    /// Size of this packet on the wire at `version`, without compression
+    pub fn encoded_len(&self, version: u8) -> usize {
+        header_size(version) + match &self.payload {
+            PacketPayload::GamePacket(data) => data.len(),
+            payload => payload.to_bytes(version).len(),
+        }
+    }
+
+    /// Check that every packet in a batch is a game packet with the batch's own sender and
+    /// destination, so a batch can't smuggle in packets from the host or the relay
+    pub fn check_batch(&self) -> Result<(), Error> {
+        let PacketPayload::Batch(packets) = &self.payload else {
+            return Ok(());
+        };
+        for packet in packets {
+            if packet.client_id != self.client_id || packet.destination_id != self.destination_id {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Batch from {} to {} carries a packet from {} to {}",
+                        self.client_id, self.destination_id, packet.client_id, packet.destination_id
+                    ),
+                ));
+            }
+            if !matches!(packet.payload, PacketPayload::GamePacket(_)) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Batch carries core packet type 0x{:02X}", packet.packet_type)));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl PacketHeader {
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
         bytes.extend(&self.magic.to_le_bytes());
-        bytes.push(self.version);
+        push_version(&mut bytes, self.version, self.flags);
         bytes.push(self.packet_type);
         bytes.extend(&self.sequence.to_le_bytes());
-        bytes.push(self.client_id);
-        bytes.push(self.destination_id);
+        push_client_id(&mut bytes, self.client_id, self.version);
+        push_client_id(&mut bytes, self.destination_id, self.version);
         bytes
     }
 
-    pub fn from_bytes(data: &[u8]) -> Result<PacketHeader, Error> {
-        if data.len() < 8 {
-            return Err(Error::new(ErrorKind::InvalidData, "Data too short"));
-        }
+    /// Encoded size of this header, which depends on its version
+    pub fn size(&self) -> usize {
+        header_size(self.version)
+    }
 
-        let magic = u16::from_le_bytes([data[0], data[1]]);
+    pub fn from_bytes(data: &[u8]) -> Result<PacketHeader, Error> {
+        let mut decoder = Decoder::new(data, "Packet header");
+        let magic = decoder.u16()?;
         if magic != 0x4E45 {
             return Err(Error::new(ErrorKind::InvalidData, "Invalid magic number"));
         }
 
+        let (version, flags) = read_version(&mut decoder)?;
         Ok(PacketHeader {
             magic,
-            version: data[2],
-            packet_type: data[3],
-            sequence: u16::from_le_bytes([data[4], data[5]]),
-            client_id: data[6],
-            destination_id: data[7],
+            version,
+            flags,
+            packet_type: decoder.u8()?,
+            sequence: decoder.u16()?,
+            client_id: read_client_id(&mut decoder, version)?,
+            destination_id: read_client_id(&mut decoder, version)?,
         })
     }
 }
\ No newline at end of file
@@ -0,0 +1,97 @@
+use std::io::Error;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{HostSender, NeonHost};
+use crate::transport::Transport;
+
+/// How long the worker waits for traffic before pumping anyway, so queued sends and ack retries still fire
+const WORKER_WAKE: Duration = Duration::from_millis(10);
+
+/// The worker thread, which hands the host back with whatever stopped it
+type WorkerThread<T> = JoinHandle<(NeonHost<T>, Result<(), Error>)>;
+
+/// A host processing its packets on a background thread, from `NeonHost::spawn`.
+/// Callbacks run on that thread. Dropping the worker stops the thread and the host with it.
+pub struct HostWorker<T: Transport = UdpSocket> {
+    sender: HostSender,
+    running: Arc<AtomicBool>,
+    thread: Option<WorkerThread<T>>,
+}
+
+impl<T: Transport> HostWorker<T> {
+    pub(crate) fn spawn(host: NeonHost<T>) -> Result<Self, Error> {
+        let sender = host.sender();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            thread::Builder::new()
+                .name(format!("neon-host-{}", host.session_id()))
+                .spawn(move || run(host, &running))?
+        };
+        Ok(Self { sender, running, thread: Some(thread) })
+    }
+
+    /// Get a handle other threads can send through
+    pub fn sender(&self) -> HostSender {
+        self.sender.clone()
+    }
+
+    /// Queue a chat message to one client, or to every connected client (0)
+    pub fn send_chat(&self, text: &str, destination_id: u16) -> Result<(), Error> {
+        self.sender.send_chat(text, destination_id)
+    }
+
+    /// Queue a game packet to a connected client
+    pub fn send_game_packet(&self, client_id: u16, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.sender.send_game_packet(client_id, packet_type, data)
+    }
+
+    /// Queue a game packet to every connected client
+    pub fn broadcast_game_packet(&self, packet_type: u8, data: &[u8]) -> Result<(), Error> {
+        self.sender.broadcast_game_packet(packet_type, data)
+    }
+
+    /// Check whether the worker is still processing packets; it stops on the first error
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Stop the worker and take the host back. Fails with the error that stopped the worker
+    /// early, if one did.
+    pub fn shutdown(mut self) -> Result<NeonHost<T>, Error> {
+        self.running.store(false, Ordering::Relaxed);
+        let thread = self.thread.take().expect("worker thread already joined");
+        let (host, result) = thread.join().map_err(|_| Error::other("Host worker panicked"))?;
+        result.map(|_| host)
+    }
+}
+
+impl<T: Transport> Drop for HostWorker<T> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run<T: Transport>(mut host: NeonHost<T>, running: &AtomicBool) -> (NeonHost<T>, Result<(), Error>) {
+    let mut pump = || {
+        loop {
+            host.process_packets()?;
+            if !running.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            host.socket.socket.wait_readable(WORKER_WAKE)?;
+        }
+    };
+    let result = pump();
+    if let Err(e) = &result {
+        println!("[Host] Worker stopped: {}", e);
+    }
+    (host, result)
+}
@@ -0,0 +1,113 @@
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::decoder::Decoder;
+use crate::directory::QUERY_TIMEOUT;
+use crate::protocol::{self, NeonPacket, PacketPayload, JOIN_CODE_ANSWER, JOIN_CODE_LOOKUP, MAX_DATAGRAM_SIZE};
+use crate::relay::SessionKey;
+use crate::transport::Transport;
+
+/// Characters generated codes are made of, leaving out 0, O, 1 and I, which are easily misread
+const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Longest join code a host can set
+pub const MAX_JOIN_CODE_LENGTH: usize = 16;
+
+/// Lookups sent to a relay before it's treated as unreachable
+const LOOKUP_ATTEMPTS: usize = 3;
+
+/// A random code like "CRAB-7F2": four characters, a dash, then three more
+pub fn generate() -> String {
+    (0..8)
+        .map(|i| if i == 4 { '-' } else { ALPHABET[rand::random_range(0..ALPHABET.len())] as char })
+        .collect()
+}
+
+/// Check a code is 1 to MAX_JOIN_CODE_LENGTH letters, digits and dashes, returning it uppercased.
+/// Codes are compared that way, so players can type them in any case.
+pub fn normalize(code: &str) -> Result<String, Error> {
+    let code = code.trim();
+    if code.is_empty() || code.len() > MAX_JOIN_CODE_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Join codes must be 1 to {} characters", MAX_JOIN_CODE_LENGTH),
+        ));
+    }
+    if !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(Error::new(ErrorKind::InvalidInput, "Join codes can only contain letters, digits and dashes"));
+    }
+    Ok(code.to_ascii_uppercase())
+}
+
+/// Ask `relay` which session has `code`, over a blocking transport, e.g. the one a client will
+/// connect with. NotFound if no session on that relay has it.
+pub fn lookup_with<T: Transport>(transport: &T, relay: SocketAddr, code: &str) -> Result<SessionKey, Error> {
+    let code = normalize(code)?;
+    let result = lookup(transport, relay, &code);
+    transport.set_read_timeout(None)?;
+    match result? {
+        Some(session) => Ok(session),
+        None => Err(Error::new(ErrorKind::NotFound, format!("No session has join code {}", code))),
+    }
+}
+
+fn lookup<T: Transport>(transport: &T, relay: SocketAddr, code: &str) -> Result<Option<SessionKey>, Error> {
+    let lookup = protocol::encode(&NeonPacket {
+        packet_type: JOIN_CODE_LOOKUP,
+        sequence: 0,
+        client_id: 0,
+        destination_id: 0,
+        payload: PacketPayload::GamePacket(code.as_bytes().to_vec()),
+    });
+
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    for _ in 0..LOOKUP_ATTEMPTS {
+        let sent_at = Instant::now();
+        transport.send_to(&lookup, relay)?;
+
+        while let Some(remaining) = QUERY_TIMEOUT.checked_sub(sent_at.elapsed()) {
+            transport.set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+            let (len, from) = match transport.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(e) => return Err(e),
+            };
+            if from != relay {
+                continue;
+            }
+            if let Ok(packet) = protocol::decode(&buf[..len])
+                && packet.packet_type == JOIN_CODE_ANSWER
+                && let PacketPayload::GamePacket(data) = packet.payload
+                && let Ok((answered, session)) = decode_answer(&data)
+                && answered == code
+            {
+                return Ok(session);
+            }
+        }
+    }
+    Err(Error::new(ErrorKind::TimedOut, format!("Relay {} didn't answer", relay)))
+}
+
+/// Payload of a JOIN_CODE_ANSWER
+pub fn encode_answer(code: &str, session: Option<SessionKey>) -> Vec<u8> {
+    let mut bytes = vec![code.len() as u8];
+    bytes.extend(code.as_bytes());
+    if let Some(session) = session {
+        bytes.extend(&session.session_id.to_le_bytes());
+        bytes.extend(&session.game_identifier.to_le_bytes());
+    }
+    bytes
+}
+
+/// The code a JOIN_CODE_ANSWER is for, and the session that has it, if any
+pub fn decode_answer(data: &[u8]) -> Result<(String, Option<SessionKey>), Error> {
+    let mut decoder = Decoder::new(data, "Join code answer");
+    let code = decoder.short_string()?;
+    if decoder.is_empty() {
+        return Ok((code, None));
+    }
+    let session_id = decoder.u32()?;
+    let game_identifier = decoder.u32()?;
+    Ok((code, Some(SessionKey::new(game_identifier, session_id))))
+}
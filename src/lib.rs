@@ -14,4 +14,29 @@ pub use client::NeonClient;
 pub use host::NeonHost;
 pub use relay::NeonRelay;
 
-pub mod ffi;
\ No newline at end of file
+pub mod auth;
+pub mod bandwidth;
+pub mod compression;
+pub mod decoder;
+pub mod directory;
+pub mod ffi;
+pub mod join_code;
+pub mod message;
+#[cfg(target_os = "linux")]
+mod mmsg;
+pub mod name;
+pub mod protocol;
+pub mod replication;
+pub mod resolve;
+pub mod rpc;
+pub mod scheduler;
+pub mod sequence;
+pub mod simulation;
+pub mod socket_options;
+pub mod testing;
+pub mod tick;
+pub mod timesync;
+pub mod transport;
+pub mod voice;
+#[cfg(windows)]
+mod winsock;
\ No newline at end of file
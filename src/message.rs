@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::io::Error;
+
+#[cfg(feature = "serde")]
+use std::io::ErrorKind;
+#[cfg(feature = "serde")]
+use crate::protocol::RESERVED_GAME_TYPES;
+#[cfg(feature = "serde")]
+use serde::{Serialize, de::DeserializeOwned};
+
+/// A game message type carried in its own packet type (0x10+).
+/// Derive `Serialize`/`Deserialize` and pick an ID:
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct PlayerMove { x: f32, y: f32 }
+///
+/// impl Message for PlayerMove {
+///     const PACKET_TYPE: u8 = 0x10;
+/// }
+/// ```
+#[cfg(feature = "serde")]
+pub trait Message: Serialize + DeserializeOwned {
+    const PACKET_TYPE: u8;
+}
+
+/// Serialize a message into a game packet payload
+#[cfg(feature = "serde")]
+pub fn encode_message<M: Message>(message: &M) -> Result<Vec<u8>, Error> {
+    bincode::serialize(message).map_err(|e| Error::new(ErrorKind::InvalidData, format!("Failed to encode message: {}", e)))
+}
+
+/// Deserialize a game packet payload into a message
+#[cfg(feature = "serde")]
+pub fn decode_message<M: Message>(data: &[u8]) -> Result<M, Error> {
+    bincode::deserialize(data).map_err(|e| {
+        Error::new(ErrorKind::InvalidData, format!("Failed to decode message 0x{:02X}: {}", M::PACKET_TYPE, e))
+    })
+}
+
+type MessageHandler = Box<dyn FnMut(u16, &[u8]) -> Result<(), Error> + Send>; // (from_client_id, data)
+
+/// Typed message callbacks keyed by packet type
+#[derive(Default)]
+pub struct MessageHandlers {
+    handlers: HashMap<u8, MessageHandler>,
+}
+
+impl MessageHandlers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a callback for a message type, replacing any previous one for the same packet type
+    #[cfg(feature = "serde")]
+    pub fn register<M, F>(&mut self, mut callback: F) -> Result<(), Error>
+    where
+        M: Message,
+        F: FnMut(u16, M) + Send + 'static,
+    {
+        if M::PACKET_TYPE < 0x10 || RESERVED_GAME_TYPES.contains(&M::PACKET_TYPE) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Message packet types must be in 0x10-0xEF"));
+        }
+        self.handlers.insert(M::PACKET_TYPE, Box::new(move |from, data| {
+            callback(from, decode_message::<M>(data)?);
+            Ok(())
+        }));
+        Ok(())
+    }
+
+    /// Whether a message callback is registered for this packet type
+    pub fn handles(&self, packet_type: u8) -> bool {
+        self.handlers.contains_key(&packet_type)
+    }
+
+    /// Decode and deliver a game packet to its callback. Returns false if no callback is registered.
+    pub fn dispatch(&mut self, packet_type: u8, from_client_id: u16, data: &[u8]) -> Result<bool, Error> {
+        match self.handlers.get_mut(&packet_type) {
+            Some(handler) => handler(from_client_id, data).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
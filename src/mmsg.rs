@@ -0,0 +1,138 @@
+use std::io::Error;
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+use std::os::fd::AsRawFd;
+use std::ptr;
+
+/// Receive up to `bufs.len()` datagrams with a single recvmmsg call.
+/// Returns (length, source) for each buffer filled, in order from the front.
+pub fn recv_batch(socket: &UdpSocket, bufs: &mut [Vec<u8>]) -> Result<Vec<(usize, Option<SocketAddr>)>, Error> {
+    let count = bufs.len();
+    let mut addrs: Vec<libc::sockaddr_storage> = vec![unsafe { mem::zeroed() }; count];
+    let mut iovecs: Vec<libc::iovec> = bufs
+        .iter_mut()
+        .map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        })
+        .collect();
+
+    let mut msgs: Vec<libc::mmsghdr> = Vec::with_capacity(count);
+    for i in 0..count {
+        let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+        msg.msg_hdr.msg_name = &mut addrs[i] as *mut _ as *mut libc::c_void;
+        msg.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        msg.msg_hdr.msg_iov = &mut iovecs[i];
+        msg.msg_hdr.msg_iovlen = 1;
+        msgs.push(msg);
+    }
+
+    let received = unsafe {
+        libc::recvmmsg(
+            socket.as_raw_fd(),
+            msgs.as_mut_ptr(),
+            count as libc::c_uint,
+            libc::MSG_DONTWAIT,
+            ptr::null_mut(),
+        )
+    };
+    if received < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok((0..received as usize)
+        .map(|i| (msgs[i].msg_len as usize, from_sockaddr(&addrs[i])))
+        .collect())
+}
+
+/// Send datagrams with as few sendmmsg calls as possible.
+/// Returns how many were sent before the first failure; the caller decides what to do with the rest.
+pub fn send_batch(socket: &UdpSocket, datagrams: &[(Vec<u8>, SocketAddr)]) -> Result<usize, Error> {
+    let count = datagrams.len();
+    let mut addrs: Vec<(libc::sockaddr_storage, libc::socklen_t)> =
+        datagrams.iter().map(|(_, addr)| to_sockaddr(addr)).collect();
+    let mut iovecs: Vec<libc::iovec> = datagrams
+        .iter()
+        .map(|(data, _)| libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        })
+        .collect();
+
+    let mut msgs: Vec<libc::mmsghdr> = Vec::with_capacity(count);
+    for i in 0..count {
+        let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+        msg.msg_hdr.msg_name = &mut addrs[i].0 as *mut _ as *mut libc::c_void;
+        msg.msg_hdr.msg_namelen = addrs[i].1;
+        msg.msg_hdr.msg_iov = &mut iovecs[i];
+        msg.msg_hdr.msg_iovlen = 1;
+        msgs.push(msg);
+    }
+
+    let mut sent = 0;
+    while sent < count {
+        let result = unsafe {
+            libc::sendmmsg(
+                socket.as_raw_fd(),
+                msgs[sent..].as_mut_ptr(),
+                (count - sent) as libc::c_uint,
+                0,
+            )
+        };
+        if result < 0 {
+            if sent == 0 {
+                return Err(Error::last_os_error());
+            }
+            break;
+        }
+        if result == 0 {
+            break;
+        }
+        sent += result as usize;
+    }
+    Ok(sent)
+}
+
+fn from_sockaddr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET => {
+            let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+            Some(SocketAddr::V4(SocketAddrV4::new(ip, u16::from_be(addr.sin_port))))
+        }
+        libc::AF_INET6 => {
+            let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+            let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                ip,
+                u16::from_be(addr.sin6_port),
+                addr.sin6_flowinfo,
+                addr.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+fn to_sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(v4) => {
+            let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = v4.port().to_be();
+            sin.sin_addr.s_addr = u32::from(*v4.ip()).to_be();
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(v6) => {
+            let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = v6.port().to_be();
+            sin6.sin6_addr.s6_addr = v6.ip().octets();
+            sin6.sin6_flowinfo = v6.flowinfo();
+            sin6.sin6_scope_id = v6.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
@@ -0,0 +1,94 @@
+use std::io::{Error, ErrorKind};
+
+/// Longest name, in bytes of UTF-8, that clients, hosts, and relays accept
+pub const MAX_NAME_BYTES: usize = 64;
+
+/// Most characters a name can show, counting a base character and whatever joins it as one
+pub const MAX_NAME_CHARACTERS: usize = 32;
+
+/// Most combining marks that can stack on one character
+pub const MAX_COMBINING_MARKS: usize = 4;
+
+/// Check a name fits the limits once control characters are stripped, returning it stripped.
+/// Clients normalize their names before sending them; hosts and relays again on receiving them.
+pub fn normalize(name: &str) -> Result<String, Error> {
+    let name = strip(name);
+    check(&name)?;
+    Ok(name)
+}
+
+/// `name` less control characters, bidirectional overrides and surrounding whitespace, none of which
+/// should reach anyone's screen
+pub fn strip(name: &str) -> String {
+    name.chars().filter(|&c| !is_hidden(c)).collect::<String>().trim().to_string()
+}
+
+/// Check a stripped name is 1 to MAX_NAME_BYTES long, shows at most MAX_NAME_CHARACTERS characters,
+/// and stacks at most MAX_COMBINING_MARKS on any of them
+pub fn check(name: &str) -> Result<(), Error> {
+    if name.is_empty() || name.len() > MAX_NAME_BYTES {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Names must be 1 to {} bytes", MAX_NAME_BYTES)));
+    }
+
+    let mut characters = 0;
+    let mut marks = 0;
+    let mut joined = false;
+    for c in name.chars() {
+        if is_combining_mark(c) {
+            marks += 1;
+            if marks > MAX_COMBINING_MARKS {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Names can't stack more than {} marks on a character", MAX_COMBINING_MARKS),
+                ));
+            }
+        } else if !joined && !is_modifier(c) {
+            characters += 1;
+            marks = 0;
+        }
+        joined = c == ZERO_WIDTH_JOINER;
+    }
+    if characters > MAX_NAME_CHARACTERS {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Names are limited to {} characters", MAX_NAME_CHARACTERS)));
+    }
+    Ok(())
+}
+
+/// Joins emoji into one, e.g. a family from its members
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// Control characters and the invisible formatting ones that can reorder or hide text around a name
+fn is_hidden(c: char) -> bool {
+    c.is_control()
+        || matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+        || matches!(c, '\u{2028}' | '\u{2029}' | '\u{FEFF}')
+}
+
+/// Combining marks, which draw on the character before them and can be stacked to deface a line
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{0483}'..='\u{0489}'
+            | '\u{0591}'..='\u{05BD}'
+            | '\u{0610}'..='\u{061A}'
+            | '\u{064B}'..='\u{065F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+/// Characters that change the one before them rather than showing on their own: joiners, variation
+/// selectors, skin tones and emoji tags
+fn is_modifier(c: char) -> bool {
+    matches!(
+        c,
+        ZERO_WIDTH_JOINER
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{1F3FB}'..='\u{1F3FF}'
+            | '\u{E0020}'..='\u{E007F}'
+            | '\u{E0100}'..='\u{E01EF}'
+    )
+}
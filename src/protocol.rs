@@ -0,0 +1,611 @@
+use std::io::{Error, ErrorKind};
+use std::ops::RangeInclusive;
+
+use crate::compression;
+use crate::decoder::Decoder;
+
+pub use crate::client::types::{
+    Ack, Chat, Challenge, ConnectAccept, ConnectDeny, ConnectRequest, MtuProbe, NeonPacket, PacketHeader, PacketPayload,
+    DisconnectNotice, PacketType, PacketTypeEntry, PacketTypeRegistry, PeerInfo, PeerList, Ping, Pong, RelayInfo,
+    SessionConfig, SessionRedirect, TimeSync,
+};
+
+/// First two bytes of every packet ("NE")
+pub const MAGIC: u16 = 0x4E45;
+
+/// Size of the packet header in bytes
+pub const HEADER_SIZE: usize = 11;
+
+/// Size of a version 2 header, which has no flags byte
+pub const V2_HEADER_SIZE: usize = 10;
+
+/// Size of a version 1 header, which carries client IDs as single bytes
+pub const V1_HEADER_SIZE: usize = 8;
+
+/// Largest datagram any peer reads: a 1500-byte Ethernet MTU less the IPv4 and UDP headers.
+/// Anything longer is treated as truncated.
+pub const MAX_DATAGRAM_SIZE: usize = 1472;
+
+/// Longest chat message, in bytes of UTF-8, that clients, hosts, and relays accept
+pub const MAX_CHAT_LENGTH: usize = 500;
+
+/// Game packet types used by built-in subsystems. Packets of these types are consumed
+/// by the library and never reach on_game_packet.
+pub const RESERVED_GAME_TYPES: RangeInclusive<u8> = 0xF0..=0xFF;
+
+/// Host to client: one part of a replication snapshot
+pub const REPLICATION_SNAPSHOT: u8 = 0xF0;
+
+/// Client to host: latest replication snapshot applied
+pub const REPLICATION_ACK: u8 = 0xF1;
+
+/// Either direction: a remote procedure call
+pub const RPC_REQUEST: u8 = 0xF2;
+
+/// Either direction: the reply to an RPC_REQUEST
+pub const RPC_RESPONSE: u8 = 0xF3;
+
+/// Either direction: one encoded voice frame, sent unreliably and never batched
+pub const VOICE_FRAME: u8 = 0xF4;
+
+/// Client to relay (destination 0): the session a join code names. Payload: the code.
+pub const JOIN_CODE_LOOKUP: u8 = 0xF5;
+
+/// Relay to client: the answer to a JOIN_CODE_LOOKUP. Payload: the code, then the session ID and
+/// game identifier, which are left out when no session has the code.
+pub const JOIN_CODE_ANSWER: u8 = 0xF6;
+
+/// Host to client: what the client may do. Payload: one byte of Capabilities bits. Sent reliably
+/// when the client joins with other than its role's defaults, and whenever the host changes them.
+pub const CAPABILITIES: u8 = 0xF7;
+
+/// Client to host: the name the client wants. Payload: the name. Host to client, sent reliably: the
+/// answer, a 1 byte then the name granted, or a 0 byte then why the request was refused. Hosts also
+/// grant a name unasked when they change the one a client joined with, and relays note granted names.
+pub const NAME_CHANGE: u8 = 0xF8;
+
+/// Host to client, sent reliably: a session-wide announcement, such as a warning the server is
+/// restarting, or the message of the day when the client joins. Payload: the text.
+pub const ANNOUNCEMENT: u8 = 0xF9;
+
+/// Host to client, sent reliably: one entry of the session's key/value config, for every entry when
+/// the client joins and for each one the host changes. Payload: 1 then the key's length, the key and
+/// the value, or 0 then the key's length and a key that was removed.
+pub const CONFIG_VALUE: u8 = 0xFA;
+
+/// Longest key, in bytes of UTF-8, in a session's key/value config
+pub const MAX_CONFIG_KEY_LENGTH: usize = 64;
+
+/// Largest value in a session's key/value config, so an entry fits the smallest packet size limit
+pub const MAX_CONFIG_VALUE_SIZE: usize = 900;
+
+/// Original protocol: 8-byte header, client IDs up to 255
+pub const PROTOCOL_V1: u8 = 1;
+
+/// 10-byte header with u16 client IDs; flags ride in the top bits of the version byte
+pub const PROTOCOL_V2: u8 = 2;
+
+/// Protocol version written in the header by this implementation: 11-byte header with u16 client IDs
+/// and a flags byte after the version. Peers agree on a version in the handshake, and every version
+/// up to this one is decoded.
+pub const PROTOCOL_VERSION: u8 = 3;
+
+/// Header flag: the sender wants an Ack for the packet
+pub const FLAG_RELIABLE: u8 = 0x01;
+
+/// Header flag: the payload is LZ4-compressed
+pub const FLAG_COMPRESSED: u8 = 0x02;
+
+/// Header flag: the payload is encrypted. Reserved; this implementation rejects such packets.
+pub const FLAG_ENCRYPTED: u8 = 0x04;
+
+/// Header flag: the payload is one fragment of a larger packet. Reserved; this implementation rejects such packets.
+pub const FLAG_FRAGMENT: u8 = 0x08;
+
+/// Header flag: the payload starts with acks for the receiver, a count byte then that many u16
+/// sequence numbers. Only carried ahead of game and batch payloads.
+pub const FLAG_ACKS: u8 = 0x10;
+
+/// Most acks one packet carries
+pub const MAX_PIGGYBACKED_ACKS: usize = 32;
+
+/// Before version 3, set in the header version byte in place of FLAG_RELIABLE. Only sent at version 2.
+pub const RELIABLE_FLAG: u8 = 0x40;
+
+/// Set in a ConnectRequest's client_version byte by clients joining as spectators. Older hosts
+/// clamp the version and admit them as players.
+pub const SPECTATOR_FLAG: u8 = 0x80;
+
+/// ConnectDeny reason sent when the host has no client ID or slot left to give out
+pub const SESSION_FULL: &str = "Session is full";
+
+/// ConnectDeny reason sent when a client asks to resume an ID the host doesn't recognise
+pub const RESUME_REFUSED: &str = "Cannot resume session";
+
+/// Why a peer or session went away, as carried in DisconnectNotice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DisconnectReason {
+    /// Nothing was heard from the peer for too long
+    TimedOut = 0,
+    /// The peer's address was banned through the relay's admin API
+    Banned = 1,
+    /// The host closed the session with close_session
+    HostClosed = 2,
+    /// The session outlived the relay's session lifetime limit
+    Expired = 3,
+    /// The session went without game traffic for longer than the relay's idle limit
+    Idle = 4,
+    /// A reason this version doesn't know
+    Other = 0xFF,
+}
+
+impl From<u8> for DisconnectReason {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => DisconnectReason::TimedOut,
+            1 => DisconnectReason::Banned,
+            2 => DisconnectReason::HostClosed,
+            3 => DisconnectReason::Expired,
+            4 => DisconnectReason::Idle,
+            _ => DisconnectReason::Other,
+        }
+    }
+}
+
+impl std::fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            DisconnectReason::TimedOut => "timed out",
+            DisconnectReason::Banned => "banned",
+            DisconnectReason::HostClosed => "closed by the host",
+            DisconnectReason::Expired => "expired",
+            DisconnectReason::Idle => "went idle",
+            DisconnectReason::Other => "disconnected",
+        })
+    }
+}
+
+/// What a peer may do in a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PeerRole {
+    #[default]
+    Player = 0,
+    /// Receives the host's game traffic, but the relay and host drop game packets it sends
+    Spectator = 1,
+}
+
+impl From<u8> for PeerRole {
+    /// Roles this version doesn't know are treated as the most restricted
+    fn from(value: u8) -> Self {
+        match value {
+            0 => PeerRole::Player,
+            _ => PeerRole::Spectator,
+        }
+    }
+}
+
+impl std::fmt::Display for PeerRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            PeerRole::Player => "player",
+            PeerRole::Spectator => "spectator",
+        })
+    }
+}
+
+bitflags::bitflags! {
+    /// What a connected client may do, set by the host and enforced on what it receives
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Capabilities: u8 {
+        /// Send game packets the host acts on
+        const SEND_GAME = 0x01;
+        /// Send chat messages the host hears
+        const CHAT = 0x02;
+        /// Administer the session. Nothing is granted for it here; it's for the game to check.
+        const ADMIN = 0x04;
+    }
+}
+
+impl Capabilities {
+    /// What clients joining as `role` get unless the host says otherwise: players send game
+    /// packets and chat, spectators only chat
+    pub fn for_role(role: PeerRole) -> Self {
+        match role {
+            PeerRole::Player => Capabilities::SEND_GAME | Capabilities::CHAT,
+            PeerRole::Spectator => Capabilities::CHAT,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Optional parts of a game that a host and client agree on when the client joins: the host
+    /// declares what its session uses, the client what it supports, and both get the intersection.
+    /// Bits without a name are kept, so games can negotiate their own; the upper 32 are left for that.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct FeatureSet: u64 {
+        /// Movement and position updates
+        const MOVEMENT = 1 << 0;
+        /// Voice chat (see send_voice)
+        const VOIP = 1 << 1;
+        /// Entity replication snapshots
+        const REPLICATION = 1 << 2;
+        /// Remote procedure calls
+        const RPC = 1 << 3;
+        /// Text chat
+        const CHAT = 1 << 4;
+    }
+}
+
+/// Whether a spectator may send a game packet of this type: only the library's answers to what it
+/// receives, replication acks and RPC responses, get through
+pub fn spectator_may_send(packet_type: u8) -> bool {
+    packet_type < 0x10 || packet_type == REPLICATION_ACK || packet_type == RPC_RESPONSE
+}
+
+/// Largest encoded SessionMetadata a host can advertise
+pub const MAX_SESSION_METADATA_SIZE: usize = 1024;
+
+/// What a host advertises about its session for lobby browsers, carried in its registration and
+/// listed by the relay
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionMetadata {
+    /// Display name, empty if none
+    pub name: String,
+    /// Player cap to show, 0 if none. Only advertised; `NeonHost::set_max_clients` enforces a cap.
+    pub max_players: u16,
+    /// Anything else the game wants listed, e.g. ("map", "dust")
+    pub properties: Vec<(String, String)>,
+}
+
+impl SessionMetadata {
+    /// Value of the first property with this key
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_empty() && self.max_players == 0 && self.properties.is_empty()
+    }
+
+    /// Check the metadata fits its encoding: the name, keys and values up to 255 bytes each, at most
+    /// 255 properties, and MAX_SESSION_METADATA_SIZE bytes in all
+    pub fn validate(&self) -> Result<(), Error> {
+        let too_long = std::iter::once(&self.name)
+            .chain(self.properties.iter().flat_map(|(key, value)| [key, value]))
+            .find(|text| text.len() > u8::MAX as usize);
+        if let Some(text) = too_long {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Session metadata text is over 255 bytes: {:?}", text)));
+        }
+        if self.properties.len() > u8::MAX as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, "Session metadata has over 255 properties"));
+        }
+        let mut bytes = Vec::new();
+        self.encode(&mut bytes);
+        if bytes.len() > MAX_SESSION_METADATA_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Session metadata is {} bytes, over the {}-byte limit", bytes.len(), MAX_SESSION_METADATA_SIZE),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Append the name, player cap, property count, then each key and value, strings length-prefixed
+    pub(crate) fn encode(&self, bytes: &mut Vec<u8>) {
+        let push_string = |bytes: &mut Vec<u8>, text: &str| {
+            bytes.push(text.len() as u8);
+            bytes.extend(text.as_bytes());
+        };
+        push_string(bytes, &self.name);
+        bytes.extend(&self.max_players.to_le_bytes());
+        bytes.push(self.properties.len() as u8);
+        for (key, value) in &self.properties {
+            push_string(bytes, key);
+            push_string(bytes, value);
+        }
+    }
+
+    pub(crate) fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        let name = decoder.short_string()?;
+        let max_players = decoder.u16()?;
+        let count = decoder.u8()?;
+        let mut properties = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            properties.push((decoder.short_string()?, decoder.short_string()?));
+        }
+        Ok(SessionMetadata { name, max_players, properties })
+    }
+}
+
+/// Protocol version in a header version byte, without the compression and reliable flags
+pub fn base_version(version: u8) -> u8 {
+    version & !(compression::COMPRESSED_FLAG | RELIABLE_FLAG)
+}
+
+/// Whether a header version byte (flags allowed) is version 1
+pub fn is_v1(version: u8) -> bool {
+    base_version(version) == PROTOCOL_V1
+}
+
+/// Largest client ID that can be addressed at a protocol version
+pub fn max_client_id(version: u8) -> u16 {
+    if is_v1(version) { u8::MAX as u16 } else { u16::MAX }
+}
+
+/// Size of the header written at a protocol version
+pub fn header_size(version: u8) -> usize {
+    match base_version(version) {
+        PROTOCOL_V1 => V1_HEADER_SIZE,
+        PROTOCOL_V2 => V2_HEADER_SIZE,
+        _ => HEADER_SIZE,
+    }
+}
+
+/// Whether headers at a protocol version carry a flags byte
+pub fn has_flags_byte(version: u8) -> bool {
+    base_version(version) >= PROTOCOL_VERSION
+}
+
+/// Reject header versions this implementation can't decode
+pub fn check_version(version: u8) -> Result<(), Error> {
+    match base_version(version) {
+        PROTOCOL_V1..=PROTOCOL_VERSION => Ok(()),
+        other => Err(Error::new(ErrorKind::InvalidData, format!("Unsupported protocol version {}", other))),
+    }
+}
+
+/// Reject packets using header flags this implementation has no support for
+pub fn check_flags(flags: u8) -> Result<(), Error> {
+    if flags & (FLAG_ENCRYPTED | FLAG_FRAGMENT) != 0 {
+        return Err(Error::new(ErrorKind::Unsupported, format!("Unsupported header flags 0x{:02X}", flags)));
+    }
+    Ok(())
+}
+
+/// Whether a receive error means only that one datagram was bad: it didn't decode, or needs a
+/// feature this build lacks. Receive loops skip such datagrams and carry on.
+pub fn is_malformed(error: &Error) -> bool {
+    matches!(error.kind(), ErrorKind::InvalidData | ErrorKind::Unsupported)
+}
+
+/// Append the version byte, and the flags byte at versions that have one. Older versions carry
+/// the compressed and reliable flags in the version byte and can't express the others.
+pub(crate) fn push_version(bytes: &mut Vec<u8>, version: u8, flags: u8) {
+    if has_flags_byte(version) {
+        bytes.push(version);
+        bytes.push(flags);
+        return;
+    }
+    let mut version = version;
+    if flags & FLAG_COMPRESSED != 0 {
+        version |= compression::COMPRESSED_FLAG;
+    }
+    if flags & FLAG_RELIABLE != 0 && !is_v1(version) {
+        version |= RELIABLE_FLAG;
+    }
+    bytes.push(version);
+}
+
+/// Read the version byte, and the flags byte if the version has one, returning the bare version and its flags
+pub(crate) fn read_version(decoder: &mut Decoder) -> Result<(u8, u8), Error> {
+    let byte = decoder.u8()?;
+    check_version(byte)?;
+    let version = base_version(byte);
+    if has_flags_byte(version) {
+        return Ok((version, decoder.u8()?));
+    }
+    let mut flags = 0;
+    if byte & compression::COMPRESSED_FLAG != 0 {
+        flags |= FLAG_COMPRESSED;
+    }
+    if byte & RELIABLE_FLAG != 0 {
+        flags |= FLAG_RELIABLE;
+    }
+    Ok((version, flags))
+}
+
+/// Reject game packet types outside 0x10-0xEF: lower types are core packets, higher ones are reserved
+pub fn check_game_packet_type(packet_type: u8) -> Result<(), Error> {
+    if packet_type < 0x10 || RESERVED_GAME_TYPES.contains(&packet_type) {
+        return Err(Error::new(ErrorKind::InvalidInput, "Game packet types must be in 0x10-0xEF"));
+    }
+    Ok(())
+}
+
+/// Reject chat messages longer than MAX_CHAT_LENGTH
+pub fn check_chat_length(text: &str) -> Result<(), Error> {
+    if text.len() > MAX_CHAT_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Chat messages are limited to {} bytes", MAX_CHAT_LENGTH),
+        ));
+    }
+    Ok(())
+}
+
+/// Payload of a host's NAME_CHANGE: the name granted, or why the request was refused
+pub(crate) fn encode_name_answer(answer: Result<&str, &str>) -> Vec<u8> {
+    let (granted, text) = match answer {
+        Ok(name) => (1, name),
+        Err(reason) => (0, reason),
+    };
+    let mut bytes = vec![granted];
+    bytes.extend(text.as_bytes());
+    bytes
+}
+
+/// The name a host's NAME_CHANGE grants, or why it refused
+pub(crate) fn decode_name_answer(data: &[u8]) -> Result<Result<String, String>, Error> {
+    let Some((&granted, text)) = data.split_first() else {
+        return Err(Error::new(ErrorKind::InvalidData, "Empty name change answer"));
+    };
+    let text = String::from_utf8_lossy(text).into_owned();
+    Ok(if granted == 1 { Ok(text) } else { Err(text) })
+}
+
+/// Reject config keys that are empty or longer than MAX_CONFIG_KEY_LENGTH, and values larger than
+/// MAX_CONFIG_VALUE_SIZE
+pub fn check_config_value(key: &str, value: &[u8]) -> Result<(), Error> {
+    if key.is_empty() || key.len() > MAX_CONFIG_KEY_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Config keys must be 1-{} bytes", MAX_CONFIG_KEY_LENGTH),
+        ));
+    }
+    if value.len() > MAX_CONFIG_VALUE_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Config values are limited to {} bytes", MAX_CONFIG_VALUE_SIZE),
+        ));
+    }
+    Ok(())
+}
+
+/// Payload of a host's CONFIG_VALUE: a key and its value, or None for a key that was removed
+pub(crate) fn encode_config_value(key: &str, value: Option<&[u8]>) -> Vec<u8> {
+    let mut bytes = vec![value.is_some() as u8, key.len() as u8];
+    bytes.extend(key.as_bytes());
+    bytes.extend(value.unwrap_or_default());
+    bytes
+}
+
+/// The key a host's CONFIG_VALUE sets, and its value (None if it was removed)
+pub(crate) fn decode_config_value(data: &[u8]) -> Result<(String, Option<Vec<u8>>), Error> {
+    let mut decoder = Decoder::new(data, "CONFIG_VALUE");
+    let set = decoder.u8()? == 1;
+    let key = decoder.short_string()?;
+    Ok((key, set.then(|| decoder.rest().to_vec())))
+}
+
+/// Reject announcements longer than MAX_CHAT_LENGTH
+pub fn check_announcement_length(text: &str) -> Result<(), Error> {
+    if text.len() > MAX_CHAT_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Announcements are limited to {} bytes", MAX_CHAT_LENGTH),
+        ));
+    }
+    Ok(())
+}
+
+/// Append a client ID at the width used by `version`
+pub(crate) fn push_client_id(bytes: &mut Vec<u8>, client_id: u16, version: u8) {
+    if is_v1(version) {
+        bytes.push(client_id as u8);
+    } else {
+        bytes.extend(&client_id.to_le_bytes());
+    }
+}
+
+/// Read a client ID at the width used by `version`
+pub(crate) fn read_client_id(decoder: &mut Decoder, version: u8) -> Result<u16, Error> {
+    if is_v1(version) {
+        Ok(decoder.u8()? as u16)
+    } else {
+        decoder.u16()
+    }
+}
+
+/// Encode a packet into a datagram without compression
+pub fn encode(packet: &NeonPacket) -> Vec<u8> {
+    packet.to_bytes(PROTOCOL_VERSION)
+}
+
+/// Encode a packet, compressing game and batch payloads at or above `threshold`
+/// (None never compresses; compression also requires the `lz4` feature)
+pub fn encode_compressed(packet: &NeonPacket, threshold: Option<usize>) -> Vec<u8> {
+    encode_versioned(packet, PROTOCOL_VERSION, threshold)
+}
+
+/// Encode a packet at a negotiated protocol version, compressing as encode_compressed does
+pub fn encode_versioned(packet: &NeonPacket, version: u8, threshold: Option<usize>) -> Vec<u8> {
+    encode_with_acks(packet, version, threshold, &[])
+}
+
+/// Whether a packet sent at `version` can carry acks: it needs a flags byte and a game or batch payload
+pub fn can_carry_acks(packet: &NeonPacket, version: u8) -> bool {
+    has_flags_byte(version) && matches!(packet.payload, PacketPayload::GamePacket(_) | PacketPayload::Batch(_))
+}
+
+/// Encode a packet as encode_versioned does, with up to MAX_PIGGYBACKED_ACKS of `acks` ahead of
+/// its payload. Check can_carry_acks first; acks are left off packets that can't carry them.
+pub fn encode_with_acks(packet: &NeonPacket, version: u8, threshold: Option<usize>, acks: &[u16]) -> Vec<u8> {
+    let mut header = packet.header(version);
+    let mut payload = packet.payload.to_bytes(version);
+    if matches!(packet.payload, PacketPayload::GamePacket(_) | PacketPayload::Batch(_))
+        && let Some(compressed) = compression::maybe_compress(&payload, threshold)
+    {
+        header.flags |= FLAG_COMPRESSED;
+        payload = compressed;
+    }
+
+    let acks = if can_carry_acks(packet, version) { &acks[..acks.len().min(MAX_PIGGYBACKED_ACKS)] } else { &[] };
+    if !acks.is_empty() {
+        header.flags |= FLAG_ACKS;
+    }
+    let mut bytes = header.to_bytes();
+    if !acks.is_empty() {
+        bytes.push(acks.len() as u8);
+        for sequence in acks {
+            bytes.extend(&sequence.to_le_bytes());
+        }
+    }
+    bytes.extend(payload);
+    bytes
+}
+
+/// Split the acks a packet carries, if its header says it has any, from the rest of its body
+pub fn split_acks(flags: u8, body: &[u8]) -> Result<(Vec<u16>, &[u8]), Error> {
+    if flags & FLAG_ACKS == 0 {
+        return Ok((Vec::new(), body));
+    }
+    let mut decoder = Decoder::new(body, "Piggybacked acks");
+    let count = decoder.u8()?;
+    let acks = (0..count).map(|_| decoder.u16()).collect::<Result<_, _>>()?;
+    Ok((acks, decoder.rest()))
+}
+
+/// Check that a payload fits in one datagram of at most `max_packet_size` bytes, header
+/// included, counting it compressed if it is at or above `compression_threshold`
+pub fn check_packet_size(payload: &[u8], max_packet_size: usize, compression_threshold: Option<usize>) -> Result<(), Error> {
+    if HEADER_SIZE + payload.len() <= max_packet_size {
+        return Ok(());
+    }
+    if let Some(compressed) = compression::maybe_compress(payload, compression_threshold)
+        && HEADER_SIZE + compressed.len() <= max_packet_size
+    {
+        return Ok(());
+    }
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("Packet of {} bytes exceeds the {}-byte limit for this path", HEADER_SIZE + payload.len(), max_packet_size),
+    ))
+}
+
+/// Decode just the header of a datagram
+pub fn decode_header(data: &[u8]) -> Result<PacketHeader, Error> {
+    PacketHeader::from_bytes(data)
+}
+
+/// Decode a datagram into a packet, decompressing the payload if the header says so. Any acks
+/// it carries are skipped; split_acks reads them.
+pub fn decode(data: &[u8]) -> Result<NeonPacket, Error> {
+    let header = decode_header(data)?;
+    check_flags(header.flags)?;
+    let (_, body) = split_acks(header.flags, &data[header.size()..])?;
+    let payload = if header.flags & FLAG_COMPRESSED != 0 {
+        PacketPayload::from_bytes(header.packet_type, &compression::decompress(body)?, header.version)?
+    } else {
+        PacketPayload::from_bytes(header.packet_type, body, header.version)?
+    };
+
+    Ok(NeonPacket {
+        packet_type: header.packet_type,
+        sequence: header.sequence,
+        client_id: header.client_id,
+        destination_id: header.destination_id,
+        payload,
+    })
+}
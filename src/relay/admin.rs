@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::iter::Peekable;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::Chars;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::logging::{Logger, json_string};
+use super::relay::shard_of;
+use super::session::{SessionKey, Traffic};
+use super::worker::{EventKind, WorkerInput, WorkerStats};
+use crate::protocol::{PeerRole, SessionMetadata};
+
+/// Live dashboard served at GET /, polling the JSON endpoints
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Largest request, head and body together, the admin API reads
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// How long a slow admin client gets to send its request or read the response
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for a worker to answer
+const WORKER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A session as listed by GET /sessions
+pub struct SessionSummary {
+    pub session_id: u32,
+    pub game_identifier: u32,
+    pub host: Option<SocketAddr>,
+    pub peers: usize,
+    pub forwarded: Traffic,
+    pub metadata: SessionMetadata,
+}
+
+/// A registered host or client as listed by GET /sessions/{id}/peers
+pub struct PeerSummary {
+    pub client_id: u16,
+    pub addr: SocketAddr,
+    pub name: String,
+    pub role: PeerRole,
+    pub is_host: bool,
+    pub idle: Duration,
+    pub sent: Traffic,
+    pub received: Traffic,
+}
+
+/// An entry in a worker's event log
+#[derive(Debug, Clone)]
+pub struct RelayEvent {
+    pub at: SystemTime,
+    pub kind: EventKind,
+    pub session_id: u32,
+    /// 0 for events about the whole session
+    pub client_id: u16,
+}
+
+/// Sent to workers by the admin API; each carries where to send the answer
+pub enum AdminRequest {
+    Sessions(Sender<Vec<SessionSummary>>),
+    Events(Sender<Vec<RelayEvent>>),
+    /// None if the worker has no such session
+    Peers(SessionKey, Sender<Option<Vec<PeerSummary>>>),
+    /// Answered with how many peers were dropped, or None if there was no such session
+    CloseSession(SessionKey, Sender<Option<usize>>),
+    /// Ban an IP for a while, or for good; answered with how many peers were dropped
+    Ban(IpAddr, Option<Duration>, Sender<usize>),
+}
+
+/// A parsed HTTP request
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|&(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Status code and JSON body
+type Response = (u16, String);
+
+/// Embedded HTTP/1.1 server for managing a running relay. Serves one connection at a time,
+/// which is plenty for operators, scripts and a dashboard or two.
+pub struct AdminServer {
+    listener: TcpListener,
+    token: Option<String>,
+    workers: Vec<Sender<WorkerInput>>,
+    stats: Vec<Arc<WorkerStats>>,
+    started_at: Instant,
+    log: Logger,
+}
+
+impl AdminServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        Ok(AdminServer {
+            listener: TcpListener::bind(addr)?,
+            token: None,
+            workers: Vec::new(),
+            stats: Vec::new(),
+            started_at: Instant::now(),
+            log: Logger::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener.local_addr()
+    }
+
+    /// Serve requests on a background thread, passing them to `workers` and reporting their `stats`
+    pub fn spawn(
+        mut self,
+        token: Option<String>,
+        workers: Vec<Sender<WorkerInput>>,
+        stats: Vec<Arc<WorkerStats>>,
+        log: Logger,
+    ) -> Result<(), Error> {
+        self.token = token;
+        self.workers = workers;
+        self.stats = stats;
+        self.started_at = Instant::now();
+        self.log = log;
+        self.log.info(format_args!("[Relay] Admin API listening on http://{}", self.local_addr()?));
+        thread::Builder::new()
+            .name("neon-relay-admin".to_string())
+            .spawn(move || self.run())?;
+        Ok(())
+    }
+
+    fn run(self) {
+        for stream in self.listener.incoming() {
+            let result = stream.and_then(|stream| self.serve(stream));
+            if let Err(e) = result {
+                self.log.info(format_args!("[Relay] Admin API connection failed: {}", e));
+            }
+        }
+    }
+
+    fn serve(&self, mut stream: TcpStream) -> Result<(), Error> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let request = match read_request(&stream) {
+            Ok(request) => request,
+            Err(e) => return write_response(&mut stream, 400, "application/json", &error_body(&e.to_string())),
+        };
+        // The page holds no data and asks for the token itself, since a browser can't send one to load it
+        if request.method == "GET" && matches!(request.path.as_str(), "/" | "/dashboard") {
+            return write_response(&mut stream, 200, "text/html; charset=utf-8", DASHBOARD);
+        }
+        let (status, body) = self.handle(&request);
+        write_response(&mut stream, status, "application/json", &body)
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        if let Some(token) = &self.token {
+            let authorized = request
+                .header("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|given| given == token);
+            if !authorized {
+                return (401, error_body("Missing or wrong bearer token"));
+            }
+        }
+
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let result = match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["stats"]) => Ok(self.stats()),
+            ("GET", ["events"]) => self.list_events(),
+            ("GET", ["sessions"]) => self.list_sessions(),
+            ("GET", ["sessions", id, "peers"]) => parse_session(id, request).and_then(|session| self.list_peers(session)),
+            ("DELETE", ["sessions", id]) => parse_session(id, request).and_then(|session| self.close_session(session)),
+            ("POST", ["bans"]) => self.ban(&request.body),
+            (_, ["stats"] | ["events"] | ["sessions"] | ["sessions", _, "peers"] | ["sessions", _] | ["bans"]) => {
+                Ok((405, error_body("Method not allowed")))
+            }
+            _ => Ok((404, error_body("Not found"))),
+        };
+        result.unwrap_or_else(|e| match e.kind() {
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => (400, error_body(&e.to_string())),
+            _ => (503, error_body(&e.to_string())),
+        })
+    }
+
+    fn stats(&self) -> Response {
+        let total = |counter: fn(&WorkerStats) -> u64| self.stats.iter().map(|stats| counter(stats)).sum::<u64>();
+        let body = format!(
+            "{{\"uptime_secs\":{},\"sessions\":{},\"clients\":{},\"packets_relayed\":{},\"bytes_relayed\":{},\"spoofed_packets\":{},\"throttled_requests\":{},\"malformed_packets\":{}}}",
+            self.started_at.elapsed().as_secs(),
+            total(|stats| stats.sessions.load(Ordering::Relaxed) as u64),
+            total(|stats| stats.clients.load(Ordering::Relaxed) as u64),
+            total(|stats| stats.packets_relayed.load(Ordering::Relaxed)),
+            total(|stats| stats.bytes_relayed.load(Ordering::Relaxed)),
+            total(|stats| stats.spoofed_packets.load(Ordering::Relaxed)),
+            total(|stats| stats.throttled_requests.load(Ordering::Relaxed)),
+            total(|stats| stats.malformed_packets.load(Ordering::Relaxed)),
+        );
+        (200, body)
+    }
+
+    /// The most recent events across every worker, oldest first
+    fn list_events(&self) -> Result<Response, Error> {
+        let mut events: Vec<RelayEvent> = self.ask_all(AdminRequest::Events)?.into_iter().flatten().collect();
+        events.sort_by_key(|event| event.at);
+
+        let entries: Vec<String> = events
+            .iter()
+            .map(|event| {
+                let kind = match event.kind {
+                    EventKind::Joined => "joined",
+                    EventKind::TimedOut => "timed_out",
+                    EventKind::SessionEnded => "session_ended",
+                    EventKind::SessionClosed => "session_closed",
+                    EventKind::Banned => "banned",
+                };
+                let at_ms = event.at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+                format!(
+                    "{{\"at_ms\":{},\"kind\":\"{}\",\"session_id\":{},\"client_id\":{}}}",
+                    at_ms, kind, event.session_id, event.client_id
+                )
+            })
+            .collect();
+        Ok((200, format!("{{\"events\":[{}]}}", entries.join(","))))
+    }
+
+    fn list_sessions(&self) -> Result<Response, Error> {
+        let mut sessions: Vec<SessionSummary> = self.ask_all(AdminRequest::Sessions)?.into_iter().flatten().collect();
+        sessions.sort_by_key(|session| (session.session_id, session.game_identifier));
+
+        let entries: Vec<String> = sessions
+            .iter()
+            .map(|session| {
+                let host = session.host.map_or("null".to_string(), |addr| json_string(&addr.to_string()));
+                let properties: Vec<String> = session
+                    .metadata
+                    .properties
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+                    .collect();
+                format!(
+                    "{{\"session_id\":{},\"game_identifier\":{},\"host\":{},\"peers\":{},\"packets_forwarded\":{},\"bytes_forwarded\":{},\"name\":{},\"max_players\":{},\"properties\":{{{}}}}}",
+                    session.session_id,
+                    session.game_identifier,
+                    host,
+                    session.peers,
+                    session.forwarded.packets,
+                    session.forwarded.bytes,
+                    json_string(&session.metadata.name),
+                    session.metadata.max_players,
+                    properties.join(",")
+                )
+            })
+            .collect();
+        Ok((200, format!("{{\"sessions\":[{}]}}", entries.join(","))))
+    }
+
+    fn list_peers(&self, session: SessionKey) -> Result<Response, Error> {
+        let Some(peers) = self.ask(session.session_id, |reply| AdminRequest::Peers(session, reply))? else {
+            return Ok((404, error_body(&format!("No session {}", session))));
+        };
+
+        let entries: Vec<String> = peers
+            .iter()
+            .map(|peer| {
+                format!(
+                    "{{\"client_id\":{},\"addr\":{},\"name\":{},\"role\":\"{}\",\"host\":{},\"idle_ms\":{},\"packets_sent\":{},\"bytes_sent\":{},\"packets_received\":{},\"bytes_received\":{}}}",
+                    peer.client_id,
+                    json_string(&peer.addr.to_string()),
+                    json_string(&peer.name),
+                    peer.role,
+                    peer.is_host,
+                    peer.idle.as_millis(),
+                    peer.sent.packets,
+                    peer.sent.bytes,
+                    peer.received.packets,
+                    peer.received.bytes
+                )
+            })
+            .collect();
+        Ok((200, format!("{{\"session_id\":{},\"peers\":[{}]}}", session.session_id, entries.join(","))))
+    }
+
+    fn close_session(&self, session: SessionKey) -> Result<Response, Error> {
+        match self.ask(session.session_id, |reply| AdminRequest::CloseSession(session, reply))? {
+            Some(removed) => Ok((200, format!("{{\"session_id\":{},\"removed\":{}}}", session.session_id, removed))),
+            None => Ok((404, error_body(&format!("No session {}", session)))),
+        }
+    }
+
+    /// Body: {"ip": "203.0.113.7", "duration_secs": 600}; leave out the duration to ban for good
+    fn ban(&self, body: &[u8]) -> Result<Response, Error> {
+        let text = std::str::from_utf8(body).map_err(|_| invalid("Body must be UTF-8"))?;
+        let fields = parse_object(text)?;
+
+        let ip: IpAddr = match fields.get("ip") {
+            Some(JsonValue::String(ip)) => ip.parse().map_err(|_| invalid("\"ip\" is not an IP address"))?,
+            _ => return Err(invalid("Body needs an \"ip\" string")),
+        };
+        let duration = match fields.get("duration_secs") {
+            None | Some(JsonValue::Null) => None,
+            Some(JsonValue::Number(secs)) if *secs >= 0.0 => Some(Duration::from_secs_f64(secs.min(u64::MAX as f64))),
+            Some(_) => return Err(invalid("\"duration_secs\" must be a non-negative number")),
+        };
+
+        let removed: usize = self.ask_all(|reply| AdminRequest::Ban(ip, duration, reply))?.into_iter().sum();
+        let ip_text = ip.to_string();
+        match duration {
+            Some(duration) => self.log.event(
+                "ban",
+                &[("ip", ip_text.as_str().into()), ("duration_secs", duration.as_secs().into()), ("removed", removed.into())],
+                format_args!("[Relay] Banned {} for {:?} through the admin API", ip, duration),
+            ),
+            None => self.log.event(
+                "ban",
+                &[("ip", ip_text.as_str().into()), ("removed", removed.into())],
+                format_args!("[Relay] Banned {} through the admin API", ip),
+            ),
+        }
+        let duration = duration.map_or("null".to_string(), |duration| duration.as_secs().to_string());
+        Ok((
+            201,
+            format!("{{\"ip\":{},\"duration_secs\":{},\"removed\":{}}}", json_string(&ip.to_string()), duration, removed),
+        ))
+    }
+
+    /// Send a request to the worker that owns `session_id` and wait for its answer
+    fn ask<R>(&self, session_id: u32, request: impl FnOnce(Sender<R>) -> AdminRequest) -> Result<R, Error> {
+        let (reply, answer) = mpsc::channel();
+        let shard = shard_of(session_id, self.workers.len());
+        send(&self.workers[shard], request(reply))?;
+        wait(&answer)
+    }
+
+    /// Send a request to every worker and collect their answers
+    fn ask_all<R>(&self, request: impl Fn(Sender<R>) -> AdminRequest) -> Result<Vec<R>, Error> {
+        let (reply, answer) = mpsc::channel();
+        for worker in &self.workers {
+            send(worker, request(reply.clone()))?;
+        }
+        self.workers.iter().map(|_| wait(&answer)).collect()
+    }
+}
+
+fn send(worker: &Sender<WorkerInput>, request: AdminRequest) -> Result<(), Error> {
+    worker
+        .send(WorkerInput::Admin(request))
+        .map_err(|_| Error::new(ErrorKind::BrokenPipe, "Relay worker stopped"))
+}
+
+fn wait<R>(answer: &mpsc::Receiver<R>) -> Result<R, Error> {
+    answer
+        .recv_timeout(WORKER_TIMEOUT)
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "Relay worker didn't answer"))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+/// The session a path's ID names, in the game given by a `game` query parameter, decimal or 0x hex.
+/// Without one, the only session with that ID.
+fn parse_session(id: &str, request: &Request) -> Result<SessionKey, Error> {
+    let session_id = id.parse().map_err(|_| invalid("Session ID must be a number"))?;
+    let game_identifier = match request.query_param("game") {
+        None => 0,
+        Some(game) => match game.strip_prefix("0x").or_else(|| game.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => game.parse(),
+        }
+        .map_err(|_| invalid("Game identifier must be a number"))?,
+    };
+    Ok(SessionKey::new(game_identifier, session_id))
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request, Error> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Error::new(ErrorKind::InvalidData, "Malformed request line"));
+    };
+    let method = method.to_string();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Request ended before its headers did"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(Error::new(ErrorKind::InvalidData, "Malformed header"));
+        };
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = Request { method, path, query, headers, body: Vec::new() };
+    if let Some(length) = request.header("Content-Length") {
+        let length: u64 = length.parse().map_err(|_| Error::new(ErrorKind::InvalidData, "Malformed Content-Length"))?;
+        if length > MAX_REQUEST_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "Request body too large"));
+        }
+        request.body = vec![0; length as usize];
+        reader.read_exact(&mut request.body)?;
+    }
+    Ok(request)
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, content_type: &str, body: &str) -> Result<(), Error> {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Service Unavailable",
+    };
+    let challenge = if status == 401 { "WWW-Authenticate: Bearer\r\n" } else { "" };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        challenge,
+        body
+    )?;
+    stream.flush()
+}
+
+fn error_body(message: &str) -> String {
+    format!("{{\"error\":{}}}", json_string(message))
+}
+
+/// A value in a flat JSON object; no endpoint needs booleans, arrays or nested objects
+enum JsonValue {
+    String(String),
+    Number(f64),
+    Null,
+}
+
+fn parse_object(text: &str) -> Result<HashMap<String, JsonValue>, Error> {
+    let malformed = || Error::new(ErrorKind::InvalidData, "Body must be a flat JSON object");
+    let mut chars = text.chars().peekable();
+    let mut fields = HashMap::new();
+
+    skip_whitespace(&mut chars);
+    if chars.next() != Some('{') {
+        return Err(malformed());
+    }
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_whitespace(&mut chars);
+            let key = parse_string(&mut chars).ok_or_else(malformed)?;
+            skip_whitespace(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(malformed());
+            }
+            skip_whitespace(&mut chars);
+            let value = parse_value(&mut chars).ok_or_else(malformed)?;
+            fields.insert(key, value);
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err(malformed()),
+            }
+        }
+    }
+    skip_whitespace(&mut chars);
+    if chars.next().is_some() {
+        return Err(malformed());
+    }
+    Ok(fields)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Option<JsonValue> {
+    match chars.peek()? {
+        '"' => parse_string(chars).map(JsonValue::String),
+        'n' => parse_literal(chars, "null", JsonValue::Null),
+        _ => {
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                number.push(c);
+            }
+            number.parse().ok().map(JsonValue::Number)
+        }
+    }
+}
+
+fn parse_literal(chars: &mut Peekable<Chars>, literal: &str, value: JsonValue) -> Option<JsonValue> {
+    for expected in literal.chars() {
+        if chars.next()? != expected {
+            return None;
+        }
+    }
+    Some(value)
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                'u' => {
+                    let code: String = (0..4).map(|_| chars.next()).collect::<Option<_>>()?;
+                    value.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                }
+                escaped => value.push(escaped),
+            },
+            c => value.push(c),
+        }
+    }
+}
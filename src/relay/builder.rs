@@ -0,0 +1,141 @@
+use std::io::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use super::NeonRelay;
+use super::relay::RelayNode;
+use super::config::{ConnectLimits, DEFAULT_PEER_TIMEOUT, GameFilter, RelayConfig, SessionLimits};
+use super::logging::LogFormat;
+use crate::socket_options::SocketOptions;
+use crate::transport::Transport;
+
+/// Address a built relay binds unless `bind` is called
+pub const DEFAULT_BIND_ADDR: &str = "0.0.0.0:7777";
+
+/// Collects a relay's settings, then creates it with `build` (or `build_with_transport`).
+/// Anything not set keeps the default `NeonRelay::new` uses.
+#[derive(Debug, Clone)]
+pub struct RelayBuilder {
+    bind_addr: String,
+    config: RelayConfig,
+    session_limits: SessionLimits,
+    connect_limits: ConnectLimits,
+    peer_timeout: Duration,
+    log_format: LogFormat,
+    public_addr: Option<SocketAddr>,
+}
+
+impl RelayBuilder {
+    pub fn new() -> Self {
+        Self {
+            bind_addr: DEFAULT_BIND_ADDR.to_string(),
+            config: RelayConfig::default(),
+            session_limits: SessionLimits::default(),
+            connect_limits: ConnectLimits::default(),
+            peer_timeout: DEFAULT_PEER_TIMEOUT,
+            log_format: LogFormat::default(),
+            public_addr: None,
+        }
+    }
+
+    /// Bind this address (default: 0.0.0.0:7777)
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.bind_addr = addr.into();
+        self
+    }
+
+    /// Take every setting in `config`, replacing any worker count, games or socket options set before
+    pub fn config(mut self, config: RelayConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Number of threads sessions are sharded across
+    pub fn worker_threads(mut self, threads: usize) -> Self {
+        self.config.worker_threads = threads;
+        self
+    }
+
+    /// Games whose hosts may register and whose clients may connect
+    pub fn games(mut self, games: GameFilter) -> Self {
+        self.config.games = games;
+        self
+    }
+
+    /// Buffer sizes, TTL and DSCP marking for the relay's socket
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.config.socket = options;
+        self
+    }
+
+    /// See `NeonRelay::set_session_limits`
+    pub fn session_limits(mut self, limits: SessionLimits) -> Self {
+        self.session_limits = limits;
+        self
+    }
+
+    /// Most sessions the relay holds at once
+    pub fn max_sessions(mut self, max_sessions: usize) -> Self {
+        self.session_limits.max_sessions = Some(max_sessions);
+        self
+    }
+
+    /// Most clients in one session, not counting the host
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.session_limits.max_clients_per_session = Some(max_clients);
+        self
+    }
+
+    /// See `NeonRelay::set_connect_limits`
+    pub fn connect_limits(mut self, limits: ConnectLimits) -> Self {
+        self.connect_limits = limits;
+        self
+    }
+
+    /// See `NeonRelay::set_peer_timeout`
+    pub fn peer_timeout(mut self, timeout: Duration) -> Self {
+        self.peer_timeout = timeout;
+        self
+    }
+
+    /// See `NeonRelay::set_log_format`
+    pub fn log_format(mut self, format: LogFormat) -> Self {
+        self.log_format = format;
+        self
+    }
+
+    /// See `NeonRelay::set_public_addr`
+    pub fn public_addr(mut self, addr: SocketAddr) -> Self {
+        self.public_addr = Some(addr);
+        self
+    }
+
+    /// Bind a UDP socket and create the relay
+    pub fn build(self) -> Result<NeonRelay, Error> {
+        let relay = NeonRelay { relay: RelayNode::with_config(&self.bind_addr, self.config.clone())? };
+        Ok(self.finish(relay))
+    }
+
+    /// Create the relay over `transport`. The bind address and socket options aren't used.
+    pub fn build_with_transport<T: Transport>(self, transport: T) -> Result<NeonRelay<T>, Error> {
+        let relay = NeonRelay::with_transport(transport, self.config.clone())?;
+        Ok(self.finish(relay))
+    }
+
+    fn finish<T: Transport>(self, mut relay: NeonRelay<T>) -> NeonRelay<T> {
+        relay.set_session_limits(self.session_limits);
+        relay.set_connect_limits(self.connect_limits);
+        relay.set_peer_timeout(self.peer_timeout);
+        relay.set_log_format(self.log_format);
+        if let Some(addr) = self.public_addr {
+            relay.set_public_addr(addr);
+        }
+        relay
+    }
+}
+
+impl Default for RelayBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
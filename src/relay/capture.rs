@@ -0,0 +1,226 @@
+use std::fs::{self, File};
+use std::io::{Error, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::decoder::Decoder;
+use crate::protocol::{self, PacketHeader};
+
+/// Classic pcap magic, microsecond timestamps
+const PCAP_MAGIC: u32 = 0xA1B2_C3D4;
+
+/// LINKTYPE_RAW: each record is an IPv4 or IPv6 packet
+const LINKTYPE_RAW: u32 = 101;
+
+/// Longest record written; datagrams never come close
+const SNAPLEN: u32 = 65_535;
+
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const UDP_HEADER_SIZE: usize = 8;
+const UDP_PROTOCOL: u8 = 17;
+
+/// Writes forwarded datagrams to a pcap file that Wireshark or tcpdump can open. Each datagram is
+/// wrapped in IP and UDP headers carrying its original sender and destination.
+#[derive(Clone)]
+pub struct Capture {
+    file: Arc<Mutex<File>>,
+    sessions: Vec<u32>,
+}
+
+impl Capture {
+    /// Start a capture at `path`, keeping only `sessions` unless that's empty
+    pub fn create(path: &Path, sessions: Vec<u32>) -> Result<Self, Error> {
+        let mut file = File::create(path)?;
+        let mut header = Vec::with_capacity(24);
+        header.extend(&PCAP_MAGIC.to_le_bytes());
+        header.extend(&2u16.to_le_bytes());
+        header.extend(&4u16.to_le_bytes());
+        header.extend(&0i32.to_le_bytes());
+        header.extend(&0u32.to_le_bytes());
+        header.extend(&SNAPLEN.to_le_bytes());
+        header.extend(&LINKTYPE_RAW.to_le_bytes());
+        file.write_all(&header)?;
+        Ok(Capture { file: Arc::new(Mutex::new(file)), sessions })
+    }
+
+    pub fn wants(&self, session_id: u32) -> bool {
+        self.sessions.is_empty() || self.sessions.contains(&session_id)
+    }
+
+    /// Append a datagram `from` sent to `to`
+    pub fn record(&self, from: SocketAddr, to: SocketAddr, data: &[u8]) -> Result<(), Error> {
+        let packet = encapsulate(from, to, data);
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend(&(since_epoch.as_secs() as u32).to_le_bytes());
+        record.extend(&since_epoch.subsec_micros().to_le_bytes());
+        record.extend(&(packet.len() as u32).to_le_bytes());
+        record.extend(&(packet.len() as u32).to_le_bytes());
+        record.extend(&packet);
+        self.file.lock().unwrap().write_all(&record)
+    }
+}
+
+/// A datagram read back from a capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPacket {
+    pub timestamp: SystemTime,
+    pub from: SocketAddr,
+    pub to: SocketAddr,
+    /// The datagram as the relay forwarded it
+    pub data: Vec<u8>,
+}
+
+impl CapturedPacket {
+    /// Decode the Neon header; `protocol::decode` gives the whole packet
+    pub fn header(&self) -> Result<PacketHeader, Error> {
+        protocol::decode_header(&self.data)
+    }
+}
+
+/// Read every datagram from a capture written by a relay
+pub fn read_capture(path: impl AsRef<Path>) -> Result<Vec<CapturedPacket>, Error> {
+    let data = fs::read(path)?;
+    let mut decoder = Decoder::new(&data, "Packet capture");
+    if decoder.u32()? != PCAP_MAGIC {
+        return Err(decoder.malformed());
+    }
+    decoder.bytes(16)?;
+    if decoder.u32()? != LINKTYPE_RAW {
+        return Err(decoder.malformed());
+    }
+
+    let mut packets = Vec::new();
+    while !decoder.is_empty() {
+        let secs = decoder.u32()?;
+        let micros = decoder.u32()?;
+        let captured_len = decoder.u32()? as usize;
+        decoder.u32()?;
+        let packet = decoder.bytes(captured_len)?;
+        let (from, to, payload) = decapsulate(packet).ok_or_else(|| decoder.malformed())?;
+        packets.push(CapturedPacket {
+            timestamp: UNIX_EPOCH + Duration::new(secs as u64, micros * 1000),
+            from,
+            to,
+            data: payload.to_vec(),
+        });
+    }
+    Ok(packets)
+}
+
+/// Wrap a datagram in IP and UDP headers. Mixed address families are written as IPv6,
+/// with IPv4 addresses mapped.
+fn encapsulate(from: SocketAddr, to: SocketAddr, data: &[u8]) -> Vec<u8> {
+    let udp_len = (UDP_HEADER_SIZE + data.len()) as u16;
+    let mut udp = Vec::with_capacity(udp_len as usize);
+    udp.extend(&from.port().to_be_bytes());
+    udp.extend(&to.port().to_be_bytes());
+    udp.extend(&udp_len.to_be_bytes());
+    udp.extend(&[0, 0]);
+    udp.extend(data);
+
+    let mut packet = Vec::with_capacity(IPV6_HEADER_SIZE + udp.len());
+    match (from.ip(), to.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            let pseudo = [&source.octets()[..], &destination.octets(), &[0, UDP_PROTOCOL], &udp_len.to_be_bytes()].concat();
+            let udp_checksum = checksum(&[&pseudo, &udp]);
+            udp[6..8].copy_from_slice(&udp_checksum.to_be_bytes());
+
+            packet.extend(&[0x45, 0]);
+            packet.extend(&((IPV4_HEADER_SIZE + udp.len()) as u16).to_be_bytes());
+            // Identification 0, don't fragment, TTL 64
+            packet.extend(&[0, 0, 0x40, 0, 64, UDP_PROTOCOL, 0, 0]);
+            packet.extend(&source.octets());
+            packet.extend(&destination.octets());
+            let header_checksum = checksum(&[&packet]);
+            packet[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+        }
+        (source, destination) => {
+            let source = to_ipv6(source);
+            let destination = to_ipv6(destination);
+            let pseudo = [
+                &source.octets()[..],
+                &destination.octets(),
+                &(udp_len as u32).to_be_bytes(),
+                &[0, 0, 0, UDP_PROTOCOL],
+            ]
+            .concat();
+            let udp_checksum = checksum(&[&pseudo, &udp]);
+            udp[6..8].copy_from_slice(&udp_checksum.to_be_bytes());
+
+            packet.extend(&[0x60, 0, 0, 0]);
+            packet.extend(&udp_len.to_be_bytes());
+            // Next header UDP, hop limit 64
+            packet.extend(&[UDP_PROTOCOL, 64]);
+            packet.extend(&source.octets());
+            packet.extend(&destination.octets());
+        }
+    }
+    packet.extend(&udp);
+    packet
+}
+
+/// Undo `encapsulate`: sender, destination and the datagram
+fn decapsulate(packet: &[u8]) -> Option<(SocketAddr, SocketAddr, &[u8])> {
+    let (source, destination, udp): (IpAddr, IpAddr, &[u8]) = match packet.first()? >> 4 {
+        4 => {
+            let source: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            (Ipv4Addr::from(source).into(), Ipv4Addr::from(destination).into(), packet.get(IPV4_HEADER_SIZE..)?)
+        }
+        6 => {
+            let source: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            (Ipv6Addr::from(source).into(), Ipv6Addr::from(destination).into(), packet.get(IPV6_HEADER_SIZE..)?)
+        }
+        _ => return None,
+    };
+    let source_port = u16::from_be_bytes(udp.get(0..2)?.try_into().ok()?);
+    let destination_port = u16::from_be_bytes(udp.get(2..4)?.try_into().ok()?);
+    Some((
+        SocketAddr::new(unmap(source), source_port),
+        SocketAddr::new(unmap(destination), destination_port),
+        udp.get(UDP_HEADER_SIZE..)?,
+    ))
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn unmap(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        ip => ip,
+    }
+}
+
+/// Internet checksum over the concatenated parts
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut odd: Option<u8> = None;
+    for &byte in parts.iter().flat_map(|part| part.iter()) {
+        match odd.take() {
+            Some(high) => sum += u16::from_be_bytes([high, byte]) as u32,
+            None => odd = Some(byte),
+        }
+    }
+    if let Some(high) = odd {
+        sum += u16::from_be_bytes([high, 0]) as u32;
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    match !(sum as u16) {
+        // A computed UDP checksum of zero is sent as all ones
+        0 => 0xFFFF,
+        checksum => checksum,
+    }
+}
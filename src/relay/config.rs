@@ -0,0 +1,88 @@
+use std::thread;
+use std::time::Duration;
+
+use crate::socket_options::SocketOptions;
+
+/// How long a client or host may go unheard before the relay drops it, unless set_peer_timeout is called
+pub const DEFAULT_PEER_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    /// Number of threads sessions are sharded across
+    pub worker_threads: usize,
+    /// Games whose hosts may register and whose clients may connect
+    pub games: GameFilter,
+    /// Buffer sizes, TTL and DSCP marking for the relay's socket; ignored by `with_transport`
+    pub socket: SocketOptions,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            worker_threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            games: GameFilter::Any,
+            socket: SocketOptions::default(),
+        }
+    }
+}
+
+/// Which game identifiers a relay serves, checked against connection requests and host registrations.
+/// Peers that don't set one send 0, so list 0 to allow them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum GameFilter {
+    #[default]
+    Any,
+    /// Only these games
+    Allow(Vec<u32>),
+    /// Every game but these
+    Block(Vec<u32>),
+}
+
+impl GameFilter {
+    pub fn accepts(&self, game_identifier: u32) -> bool {
+        match self {
+            GameFilter::Any => true,
+            GameFilter::Allow(games) => games.contains(&game_identifier),
+            GameFilter::Block(games) => !games.contains(&game_identifier),
+        }
+    }
+}
+
+/// Defences against floods of connection requests, such as ones from spoofed addresses, set with
+/// `NeonRelay::set_connect_limits`. Requests past a limit are dropped unanswered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectLimits {
+    /// Most requests each worker thread holds while they wait for the host's answer
+    pub max_pending: usize,
+    /// How long a request waits for the host's answer before it's forgotten
+    pub pending_timeout: Duration,
+    /// Requests one address may send per second once its burst is spent
+    pub requests_per_second: u32,
+    /// Requests one address may send in quick succession
+    pub burst: u32,
+}
+
+impl Default for ConnectLimits {
+    fn default() -> Self {
+        Self {
+            max_pending: 1024,
+            pending_timeout: Duration::from_secs(30),
+            requests_per_second: 2,
+            // Enough for a client's ten retries to one relay
+            burst: 10,
+        }
+    }
+}
+
+/// Caps on what a relay holds, set with `NeonRelay::set_session_limits`. None means no limit.
+#[derive(Debug, Clone, Default)]
+pub struct SessionLimits {
+    /// Most sessions the relay holds at once; hosts registering a new one past it are denied
+    pub max_sessions: Option<usize>,
+    /// Most clients in one session, not counting the host; joins past it are denied
+    pub max_clients_per_session: Option<usize>,
+    /// How long a session may exist before the relay closes it
+    pub max_lifetime: Option<Duration>,
+    /// How long a session may go without game traffic (0x10+) before the relay closes it
+    pub idle_timeout: Option<Duration>,
+}
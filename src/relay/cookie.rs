@@ -0,0 +1,44 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::session::SessionKey;
+
+/// Cookies roll over this often; one from the previous period is still accepted
+const COOKIE_PERIOD_SECS: u64 = 30;
+
+/// Stateless registration cookies: a keyed hash of the registrant's address, session, and
+/// client ID. Only someone receiving at that address learns the value, so echoing it back
+/// proves the source address isn't spoofed.
+#[derive(Clone)]
+pub struct CookieJar {
+    key: RandomState,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self { key: RandomState::new() }
+    }
+
+    pub fn issue(&self, addr: SocketAddr, session: SessionKey, client_id: u16) -> u64 {
+        self.cookie(addr, session, client_id, current_period())
+    }
+
+    pub fn verify(&self, addr: SocketAddr, session: SessionKey, client_id: u16, cookie: u64) -> bool {
+        let period = current_period();
+        cookie == self.cookie(addr, session, client_id, period)
+            || cookie == self.cookie(addr, session, client_id, period.saturating_sub(1))
+    }
+
+    fn cookie(&self, addr: SocketAddr, session: SessionKey, client_id: u16, period: u64) -> u64 {
+        // 0 means "no cookie" on the wire
+        self.key.hash_one((addr, session, client_id, period)).max(1)
+    }
+}
+
+fn current_period() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / COOKIE_PERIOD_SECS)
+        .unwrap_or(0)
+}
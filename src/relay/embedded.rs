@@ -0,0 +1,143 @@
+use std::io::Error;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::auth::Authenticator;
+pub use crate::transport::Outgoing;
+use crate::transport::QueueTransport;
+use super::config::{ConnectLimits, GameFilter, SessionLimits};
+use super::cookie::CookieJar;
+use super::logging::Logger;
+use super::session::{SessionKey, SessionManager, SessionStats};
+use super::socket::NeonSocket;
+use super::worker::{RelayWorker, WorkerStats};
+
+/// The relay's routing without a socket or threads, for embedding in a server with its own event
+/// loop. Feed it every datagram received with `handle_datagram` and send what it returns; call
+/// `poll` now and then so silent peers and expired sessions are dropped.
+///
+/// One RelayCore holds every session on one thread. Peer relays, state files, packet capture and
+/// the admin API need `NeonRelay`.
+pub struct RelayCore {
+    worker: RelayWorker<QueueTransport>,
+    transport: QueueTransport,
+    stats: Arc<WorkerStats>,
+    last_cleanup: Instant,
+}
+
+impl RelayCore {
+    pub fn new() -> Self {
+        Self::with_logger(Logger::default())
+    }
+
+    /// Create a relay core that logs through `log`
+    pub fn with_logger(log: Logger) -> Self {
+        let transport = QueueTransport::new(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+        let mut socket = NeonSocket::from_transport(transport.clone());
+        socket.set_logger(log);
+        let stats = Arc::new(WorkerStats::default());
+        // Nothing reads the feedback: there is no receive thread routing addresses to workers
+        let (feedback, _) = mpsc::channel();
+        Self {
+            worker: RelayWorker::new(socket, CookieJar::new(), None, feedback, stats.clone(), log),
+            transport,
+            stats,
+            last_cleanup: Instant::now(),
+        }
+    }
+
+    /// Deny connection requests whose auth token the authenticator rejects, before they reach the host
+    pub fn set_authenticator<A: Authenticator + 'static>(&mut self, authenticator: A) {
+        self.worker.set_authenticator(Arc::new(authenticator));
+    }
+
+    /// Deny hosts and clients of games the filter doesn't accept
+    pub fn set_game_filter(&mut self, games: GameFilter) {
+        self.worker.set_game_filter(games);
+    }
+
+    /// Cap sessions, clients per session, and session lifetimes
+    pub fn set_session_limits(&mut self, limits: SessionLimits) {
+        self.worker.enforce_limits(limits, Vec::new());
+    }
+
+    /// Cap pending connection requests and how fast each address may send them
+    pub fn set_connect_limits(&mut self, limits: ConnectLimits) {
+        self.worker.set_connect_limits(limits);
+    }
+
+    /// Drop peers unheard from for this long, hosts along with their sessions
+    pub fn set_peer_timeout(&mut self, timeout: Duration) {
+        self.worker.set_peer_timeout(timeout);
+    }
+
+    /// Handle a datagram received from `addr`, returning the datagrams to send in answer.
+    /// Fails if the datagram isn't a Neon packet.
+    pub fn handle_datagram(&mut self, data: &[u8], addr: SocketAddr) -> Result<Outgoing, Error> {
+        self.worker.handle_datagram(data, addr)?;
+        self.worker.flush();
+        Ok(self.transport.take_outgoing())
+    }
+
+    /// Drop peers and sessions past their time limits if a cleanup is due, returning the
+    /// notices to send. Call at least as often as `poll_interval()`.
+    pub fn poll(&mut self) -> Outgoing {
+        if self.last_cleanup.elapsed() >= self.worker.cleanup_interval() {
+            self.worker.cleanup();
+            self.last_cleanup = Instant::now();
+        }
+        self.transport.take_outgoing()
+    }
+
+    /// How often `poll` needs calling, which shortens with the peer timeout and session limits
+    pub fn poll_interval(&self) -> Duration {
+        self.worker.cleanup_interval()
+    }
+
+    /// Every session and peer the relay holds
+    pub fn sessions(&self) -> &SessionManager {
+        self.worker.sessions()
+    }
+
+    pub fn session_count(&self) -> usize {
+        self.sessions().sessions.len()
+    }
+
+    pub fn total_client_count(&self) -> usize {
+        self.sessions().sessions.values().map(|peers| peers.len()).sum()
+    }
+
+    /// Bytes and packets a session has forwarded, in total and per peer
+    pub fn session_stats(&self, session: impl Into<SessionKey>) -> Option<SessionStats> {
+        self.sessions().resolve(session.into()).and_then(|session| self.sessions().session_stats(session))
+    }
+
+    pub fn spoofed_packet_count(&self) -> u64 {
+        self.stats.spoofed_packets.load(Ordering::Relaxed)
+    }
+
+    pub fn throttled_request_count(&self) -> u64 {
+        self.stats.throttled_requests.load(Ordering::Relaxed)
+    }
+
+    pub fn malformed_packet_count(&self) -> u64 {
+        self.stats.malformed_packets.load(Ordering::Relaxed)
+    }
+
+    pub fn packets_relayed(&self) -> u64 {
+        self.stats.packets_relayed.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_relayed(&self) -> u64 {
+        self.stats.bytes_relayed.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for RelayCore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
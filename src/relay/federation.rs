@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use super::types::*;
+
+/// How often a relay re-announces the sessions it owns to its peers
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sessions a peer stops announcing are forgotten after this long
+const DIRECTORY_TTL: Duration = Duration::from_secs(15);
+
+/// Most relays listed in an answer to a query
+const MAX_CANDIDATES: usize = 8;
+
+/// What a peer relay last announced about itself
+struct PeerStatus {
+    public_addr: SocketAddr,
+    load: u32,
+    heard_at: Instant,
+}
+
+/// Tracks which sessions this relay owns and which its peer relays own.
+/// A session is owned by the relay its host registered with; peers announce theirs with
+/// SessionRedirect packets, and requests for their sessions are redirected to them.
+pub struct Federation {
+    peers: Vec<SocketAddr>,
+    public_addr: Option<SocketAddr>,
+    /// Sessions with a registered host, per worker shard
+    local: Vec<Vec<u32>>,
+    directory: HashMap<u32, (SocketAddr, Instant)>,
+    /// Keyed by the address the peer is configured under
+    statuses: HashMap<SocketAddr, PeerStatus>,
+    last_announce: Option<Instant>,
+}
+
+impl Federation {
+    pub fn new() -> Self {
+        Self {
+            peers: Vec::new(),
+            public_addr: None,
+            local: Vec::new(),
+            directory: HashMap::new(),
+            statuses: HashMap::new(),
+            last_announce: None,
+        }
+    }
+
+    pub fn add_peer(&mut self, addr: SocketAddr) {
+        if !self.peers.contains(&addr) {
+            self.peers.push(addr);
+        }
+    }
+
+    pub fn set_public_addr(&mut self, addr: SocketAddr) {
+        self.public_addr = Some(addr);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.peers.is_empty()
+    }
+
+    /// Settle the address given out for this relay, defaulting to the one it is bound to.
+    /// Peering needs one; a lone relay bound to an unspecified address just leaves itself out of answers.
+    pub fn start(&mut self, local_addr: SocketAddr, shards: usize) -> Result<(), Error> {
+        self.local = vec![Vec::new(); shards];
+        if self.public_addr.is_none() {
+            if !local_addr.ip().is_unspecified() {
+                self.public_addr = Some(local_addr);
+            } else if self.is_enabled() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Relay is bound to an unspecified address; set a public address to peer with other relays",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the sessions a shard hosts, announcing at the next chance if they changed
+    pub fn set_local(&mut self, shard: usize, mut sessions: Vec<u32>) {
+        sessions.sort_unstable();
+        if self.local[shard] != sessions {
+            self.local[shard] = sessions;
+            self.last_announce = None;
+        }
+    }
+
+    /// Record a peer's announcement. Returns false if `from` isn't a configured peer.
+    pub fn learn(&mut self, from: SocketAddr, redirect: &SessionRedirect) -> bool {
+        if !self.peers.contains(&from) {
+            return false;
+        }
+        if let Some(owner) = redirect.relays.first()
+            && let Ok(owner_addr) = owner.addr.parse::<SocketAddr>()
+            && Some(owner_addr) != self.public_addr
+        {
+            let now = Instant::now();
+            self.directory.insert(redirect.session_id, (owner_addr, now));
+            self.statuses.insert(from, PeerStatus { public_addr: owner_addr, load: owner.load, heard_at: now });
+        }
+        true
+    }
+
+    fn hosts_locally(&self, session_id: u32) -> bool {
+        self.local.iter().any(|sessions| sessions.binary_search(&session_id).is_ok())
+    }
+
+    /// The peer relay to send a request for `session_id` to, unless this relay owns it
+    pub fn owner(&self, session_id: u32) -> Option<SocketAddr> {
+        if self.hosts_locally(session_id) {
+            return None;
+        }
+        match self.directory.get(&session_id) {
+            Some(&(owner, learned_at)) if learned_at.elapsed() < DIRECTORY_TTL => Some(owner),
+            _ => None,
+        }
+    }
+
+    /// Answer a query for `session_id`: the owner alone if it is known, otherwise this relay and
+    /// every peer, each with its last known load
+    pub fn answer(&self, session_id: u32, own_load: u32) -> Vec<RelayInfo> {
+        let own = self.public_addr.map(|addr| RelayInfo { addr: addr.to_string(), load: own_load });
+        if self.hosts_locally(session_id) {
+            return own.into_iter().collect();
+        }
+        if let Some(owner) = self.owner(session_id) {
+            let load = self.statuses.values().find(|status| status.public_addr == owner).map_or(0, |status| status.load);
+            return vec![RelayInfo { addr: owner.to_string(), load }];
+        }
+
+        let peers = self.peers.iter().map(|peer| match self.statuses.get(peer) {
+            Some(status) if status.heard_at.elapsed() < DIRECTORY_TTL => {
+                RelayInfo { addr: status.public_addr.to_string(), load: status.load }
+            }
+            // A peer that owns no sessions announces nothing; assume it's idle and reachable where configured
+            _ => RelayInfo { addr: peer.to_string(), load: 0 },
+        });
+        own.into_iter().chain(peers).take(MAX_CANDIDATES).collect()
+    }
+
+    /// Announcements to send now: every local session to every peer, when the interval is up or
+    /// the sessions changed. Also drops directory entries that have expired.
+    pub fn announcements(&mut self, own_load: u32) -> Vec<(NeonPacket, SocketAddr)> {
+        let Some(public_addr) = self.public_addr else { return Vec::new() };
+        if self.last_announce.is_some_and(|at| at.elapsed() < ANNOUNCE_INTERVAL) {
+            return Vec::new();
+        }
+        self.last_announce = Some(Instant::now());
+        self.directory.retain(|_, (_, learned_at)| learned_at.elapsed() < DIRECTORY_TTL);
+
+        let own = RelayInfo { addr: public_addr.to_string(), load: own_load };
+        let mut packets = Vec::new();
+        for &session_id in self.local.iter().flatten() {
+            for &peer in &self.peers {
+                let announcement = NeonPacket {
+                    packet_type: CorePacketType::SessionRedirect as u8,
+                    sequence: 0,
+                    client_id: 0,
+                    destination_id: 0,
+                    payload: PacketPayload::SessionRedirect(SessionRedirect {
+                        session_id,
+                        relays: vec![own.clone()],
+                    }),
+                };
+                packets.push((announcement, peer));
+            }
+        }
+        packets
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use super::session::SessionKey;
+
+/// The join code each hosted session registered with, shared by every worker so whichever one
+/// a lookup reaches can answer it. Codes are stored uppercased.
+#[derive(Default)]
+pub struct JoinCodes {
+    sessions: HashMap<String, SessionKey>,
+    codes: HashMap<SessionKey, String>,
+}
+
+impl JoinCodes {
+    /// Whether `session` could claim a code
+    pub fn available(&self, code: &str, session: SessionKey) -> bool {
+        self.sessions.get(&code.to_ascii_uppercase()).is_none_or(|&holder| holder == session)
+    }
+
+    /// Give `session` a code, replacing any it had, or take its code away if `code` is empty.
+    /// Returns false, changing nothing, if another session holds the code.
+    pub fn claim(&mut self, code: &str, session: SessionKey) -> bool {
+        let code = code.to_ascii_uppercase();
+        if !self.available(&code, session) {
+            return false;
+        }
+        self.release(session);
+        if !code.is_empty() {
+            self.sessions.insert(code.clone(), session);
+            self.codes.insert(session, code);
+        }
+        true
+    }
+
+    /// The session holding a code, whatever case it's typed in
+    pub fn find(&self, code: &str) -> Option<SessionKey> {
+        self.sessions.get(&code.to_ascii_uppercase()).copied()
+    }
+
+    /// Free the code of a session that ended
+    pub fn release(&mut self, session: SessionKey) {
+        if let Some(code) = self.codes.remove(&session) {
+            self.sessions.remove(&code);
+        }
+    }
+}
@@ -1,21 +1,70 @@
 pub mod types;
+mod builder;
+mod capture;
+mod config;
+mod cookie;
+mod embedded;
 mod socket;
 mod session;
+mod state;
+mod federation;
+mod join_codes;
+mod logging;
+#[cfg(feature = "admin-api")]
+mod admin;
+mod pool;
+mod throttle;
+mod worker;
+#[allow(clippy::module_inception)]
 mod relay;
 
 use std::io::Error;
-pub use relay::RelayNode;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+use crate::auth::Authenticator;
+use crate::transport::Transport;
+pub use builder::{DEFAULT_BIND_ADDR, RelayBuilder};
+pub use capture::{CapturedPacket, read_capture};
+pub use config::{ConnectLimits, DEFAULT_PEER_TIMEOUT, GameFilter, RelayConfig, SessionLimits};
+pub use embedded::{Outgoing, RelayCore};
+pub use logging::{LogFormat, LogValue, Logger};
+pub use relay::{RelayHandle, RelayNode};
+pub use session::{PeerStats, SessionKey, SessionManager, SessionStats, Traffic};
 pub use types::{NeonPacket, PacketPayload};
+pub use worker::DropReason;
 
-pub struct NeonRelay {
-    relay: RelayNode,
+pub type SessionCallback = Box<dyn FnMut(u32) + Send>; // (session_id)
+pub type ClientJoinedCallback = Box<dyn FnMut(u32, u16, SocketAddr) + Send>; // (session_id, client_id, addr)
+pub type ClientTimedOutCallback = Box<dyn FnMut(u32, u16) + Send>; // (session_id, client_id)
+pub type ForwardErrorCallback = Box<dyn FnMut(SocketAddr, &Error) + Send>; // (destination, error)
+pub type PacketDroppedCallback = Box<dyn FnMut(SocketAddr, DropReason) + Send>; // (sender, reason)
+
+pub struct NeonRelay<T: Transport = UdpSocket> {
+    relay: RelayNode<T>,
 }
 
 impl NeonRelay {
     /// Create a new relay server bound to the specified address
     pub fn new(bind_addr: &str) -> Result<Self, Error> {
+        Self::builder().bind(bind_addr).build()
+    }
+
+    /// Create a new relay server with explicit settings
+    pub fn with_config(bind_addr: &str, config: RelayConfig) -> Result<Self, Error> {
+        Self::builder().bind(bind_addr).config(config).build()
+    }
+
+    /// Start building a relay, to set its address, limits and timings before it is created
+    pub fn builder() -> RelayBuilder {
+        RelayBuilder::new()
+    }
+}
+
+impl<T: Transport> NeonRelay<T> {
+    /// Create a relay that runs over any transport, e.g. an in-memory one for tests
+    pub fn with_transport(transport: T, config: RelayConfig) -> Result<Self, Error> {
         Ok(Self {
-            relay: RelayNode::new(bind_addr)?,
+            relay: RelayNode::with_transport(transport, config)?,
         })
     }
 
@@ -29,7 +78,171 @@ impl NeonRelay {
         self.relay.total_client_count()
     }
 
-    /// Start the relay server (blocks)
+    /// Get the number of datagram buffers allocated; stays flat once the pool is warm
+    pub fn buffer_allocations(&self) -> u64 {
+        self.relay.buffer_allocations()
+    }
+
+    /// Get the number of packets dropped because their client_id didn't match the sender's registered address
+    pub fn spoofed_packet_count(&self) -> u64 {
+        self.relay.spoofed_packet_count()
+    }
+
+    /// Get the number of connection requests dropped by the connect limits
+    pub fn throttled_request_count(&self) -> u64 {
+        self.relay.throttled_request_count()
+    }
+
+    /// Get the number of datagrams dropped because they couldn't be decoded
+    pub fn malformed_packet_count(&self) -> u64 {
+        self.relay.malformed_packet_count()
+    }
+
+    /// Get the number of packets forwarded from one peer to another
+    pub fn packets_relayed(&self) -> u64 {
+        self.relay.packets_relayed()
+    }
+
+    /// Get the number of bytes forwarded from one peer to another, headers included
+    pub fn bytes_relayed(&self) -> u64 {
+        self.relay.bytes_relayed()
+    }
+
+    /// Get the bytes and packets a session has forwarded, in total and per peer. Only answers while
+    /// the relay runs, so call it through `handle()` from another thread. A bare session ID finds the
+    /// only session with that ID; pass a `SessionKey` to pick a game's.
+    pub fn session_stats(&self, session: impl Into<SessionKey>) -> Option<SessionStats> {
+        self.relay.handle().session_stats(session)
+    }
+
+    /// Deny connection requests whose auth token the authenticator rejects, before they reach the host.
+    /// Must be set before `start`.
+    pub fn set_authenticator<A: Authenticator + 'static>(&mut self, authenticator: A) {
+        self.relay.set_authenticator(authenticator);
+    }
+
+    /// Cap how many sessions the relay holds, how many clients each may have, and how long they may
+    /// last or sit idle. Sessions past a time limit are closed and their peers told why. Must be set before `start`.
+    pub fn set_session_limits(&mut self, limits: SessionLimits) {
+        self.relay.set_session_limits(limits);
+    }
+
+    /// Cap how many connection requests wait on hosts' answers and how fast one address may send them,
+    /// so spoofed requests can't fill the relay's memory. Requests past a limit are dropped unanswered,
+    /// reported to `on_packet_dropped` as `DropReason::Throttled`. Must be set before `start`.
+    pub fn set_connect_limits(&mut self, limits: ConnectLimits) {
+        self.relay.set_connect_limits(limits);
+    }
+
+    /// Set how long a client or host may go unheard before the relay drops it. A silent host takes its
+    /// session with it and the clients are told, so hosts check in more often than this (see
+    /// `NeonHost::set_relay_keepalive`). Must be set before `start`.
+    pub fn set_peer_timeout(&mut self, timeout: Duration) {
+        self.relay.set_peer_timeout(timeout);
+    }
+
+    /// Save session registrations to `path` as they change, and restore them from it when the relay
+    /// starts, so hosts and clients carry on after a restart. Must be set before `start`.
+    pub fn set_state_file(&mut self, path: impl AsRef<std::path::Path>) {
+        self.relay.set_state_file(path);
+    }
+
+    /// Write every packet the relay forwards between peers to a pcap file at `path`, for Wireshark or
+    /// `read_capture`. Pass session IDs to capture only those sessions. Must be set before `start`.
+    pub fn set_capture(&mut self, path: impl AsRef<std::path::Path>, sessions: &[u32]) {
+        self.relay.set_capture(path, sessions);
+    }
+
+    /// Log as human-readable text (the default) or as one JSON object per line. Must be set before `start`.
+    pub fn set_log_format(&mut self, format: LogFormat) {
+        self.relay.set_log_format(format);
+    }
+
+    /// Peer with another relay: each tells the other which sessions it owns, and a client asking one
+    /// relay for a session the other owns is redirected there. Peer both ways. Must be set before `start`.
+    pub fn add_peer_relay(&mut self, addr: SocketAddr) {
+        self.relay.add_peer_relay(addr);
+    }
+
+    /// Set the address peer relays redirect clients to for this relay's sessions. Needed when the relay
+    /// is bound to an unspecified address like 0.0.0.0. Must be set before `start`.
+    pub fn set_public_addr(&mut self, addr: SocketAddr) {
+        self.relay.set_public_addr(addr);
+    }
+
+    /// Serve the HTTP admin API on `addr`, returning the address it listens on. Bind it to loopback
+    /// or a private network, or set a token. Must be called before `start`.
+    #[cfg(feature = "admin-api")]
+    pub fn enable_admin_api(&mut self, addr: impl std::net::ToSocketAddrs) -> Result<SocketAddr, Error> {
+        self.relay.enable_admin_api(addr)
+    }
+
+    /// Require `Authorization: Bearer <token>` on every admin API request. Must be set before `start`.
+    #[cfg(feature = "admin-api")]
+    pub fn set_admin_token(&mut self, token: impl Into<String>) {
+        self.relay.set_admin_token(token);
+    }
+
+    /// Set callback for when a host registers a new session. Runs on the thread that called `start`.
+    /// Must be set before `start`.
+    pub fn on_session_created<F>(&mut self, callback: F)
+    where
+        F: FnMut(u32) + Send + 'static,
+    {
+        self.relay.on_session_created(Box::new(callback));
+    }
+
+    /// Set callback for when a session ends, because everyone left or timed out, it was closed, or the
+    /// relay stopped. Runs on the thread that called `start`. Must be set before `start`.
+    pub fn on_session_destroyed<F>(&mut self, callback: F)
+    where
+        F: FnMut(u32) + Send + 'static,
+    {
+        self.relay.on_session_destroyed(Box::new(callback));
+    }
+
+    /// Set callback for when a client joins a session. Runs on the thread that called `start`.
+    /// Must be set before `start`.
+    pub fn on_client_joined<F>(&mut self, callback: F)
+    where
+        F: FnMut(u32, u16, SocketAddr) + Send + 'static,
+    {
+        self.relay.on_client_joined(Box::new(callback));
+    }
+
+    /// Set callback for when the relay times out a client that stopped sending. Runs on the thread
+    /// that called `start`. Must be set before `start`.
+    pub fn on_client_timed_out<F>(&mut self, callback: F)
+    where
+        F: FnMut(u32, u16) + Send + 'static,
+    {
+        self.relay.on_client_timed_out(Box::new(callback));
+    }
+
+    /// Set callback for when sending a forwarded packet fails. Runs on the thread that called `start`.
+    /// Must be set before `start`.
+    pub fn on_forward_error<F>(&mut self, callback: F)
+    where
+        F: FnMut(SocketAddr, &Error) + Send + 'static,
+    {
+        self.relay.on_forward_error(Box::new(callback));
+    }
+
+    /// Set callback for when the relay drops a datagram instead of handling it. Runs on the thread
+    /// that called `start`. Must be set before `start`.
+    pub fn on_packet_dropped<F>(&mut self, callback: F)
+    where
+        F: FnMut(SocketAddr, DropReason) + Send + 'static,
+    {
+        self.relay.on_packet_dropped(Box::new(callback));
+    }
+
+    /// Get a handle that stops the relay and reads its session and client counts from other threads
+    pub fn handle(&self) -> RelayHandle {
+        self.relay.handle()
+    }
+
+    /// Start the relay server (blocks until stopped through a `RelayHandle`)
     pub fn start(&mut self) -> Result<(), Error> {
         self.relay.run()
     }
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How the relay writes its log to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line: `ts`, `event`, the event's fields and the text `message`
+    Json,
+}
+
+/// A field value in a structured log event
+#[derive(Debug, Clone, Copy)]
+pub enum LogValue<'a> {
+    Number(u64),
+    Text(&'a str),
+    Addr(SocketAddr),
+}
+
+impl From<u16> for LogValue<'_> {
+    fn from(value: u16) -> Self {
+        LogValue::Number(value as u64)
+    }
+}
+
+impl From<u32> for LogValue<'_> {
+    fn from(value: u32) -> Self {
+        LogValue::Number(value as u64)
+    }
+}
+
+impl From<u64> for LogValue<'_> {
+    fn from(value: u64) -> Self {
+        LogValue::Number(value)
+    }
+}
+
+impl From<usize> for LogValue<'_> {
+    fn from(value: usize) -> Self {
+        LogValue::Number(value as u64)
+    }
+}
+
+impl<'a> From<&'a str> for LogValue<'a> {
+    fn from(value: &'a str) -> Self {
+        LogValue::Text(value)
+    }
+}
+
+impl From<SocketAddr> for LogValue<'_> {
+    fn from(value: SocketAddr) -> Self {
+        LogValue::Addr(value)
+    }
+}
+
+/// Writes relay log lines in the configured format
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Logger {
+    format: LogFormat,
+}
+
+impl Logger {
+    pub fn new(format: LogFormat) -> Self {
+        Logger { format }
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    /// Log a named event. Text mode prints `message`; JSON mode prints the event, its fields and the message.
+    pub fn event(&self, event: &str, fields: &[(&str, LogValue)], message: fmt::Arguments) {
+        match self.format {
+            LogFormat::Text => println!("{}", message),
+            LogFormat::Json => {
+                let mut line = format!("{{\"ts\":\"{}\",\"event\":{}", timestamp(SystemTime::now()), json_string(event));
+                for (name, value) in fields {
+                    let value = match value {
+                        LogValue::Number(number) => number.to_string(),
+                        LogValue::Text(text) => json_string(text),
+                        LogValue::Addr(addr) => json_string(&addr.to_string()),
+                    };
+                    line.push_str(&format!(",{}:{}", json_string(name), value));
+                }
+                line.push_str(&format!(",\"message\":{}}}", json_string(&message.to_string())));
+                println!("{}", line);
+            }
+        }
+    }
+
+    /// Log a diagnostic that isn't one of the named events
+    pub fn info(&self, message: fmt::Arguments) {
+        self.event("message", &[], message);
+    }
+}
+
+/// RFC 3339 UTC time with milliseconds
+fn timestamp(at: SystemTime) -> String {
+    let since_epoch = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Year, month and day of a count of days since 1970-01-01 (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
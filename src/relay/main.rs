@@ -1,20 +1,149 @@
-use project_neon::relay::NeonRelay;
+use std::process::ExitCode;
+use std::time::Duration;
 
-fn main() {
-    println!("Project Neon Protocol v0.2 - Relay");
-    println!("===================================");
-    println!("Starting relay node...");
-    println!();
+use project_neon::relay::{ConnectLimits, GameFilter, LogFormat, Logger, NeonRelay, SessionLimits};
+use project_neon::socket_options::SocketOptions;
 
-    let mut relay = match NeonRelay::new("0.0.0.0:7777") {
+const USAGE: &str = "Usage: relay [--bind <addr>] [--log-format text|json] [--capture <file.pcap> [--capture-session <id>]...]
+             [--max-sessions <n>] [--max-clients <n>] [--session-lifetime <secs>] [--idle-timeout <secs>]
+             [--peer-timeout <secs>] [--max-pending <n>] [--connect-rate <per sec>] [--connect-burst <n>]
+             [--recv-buffer <bytes>] [--send-buffer <bytes>] [--dscp <0-63>]
+             [--allow-game <id>]... [--block-game <id>]...";
+
+struct Args {
+    bind: String,
+    log_format: LogFormat,
+    capture: Option<String>,
+    capture_sessions: Vec<u32>,
+    limits: SessionLimits,
+    connect_limits: ConnectLimits,
+    peer_timeout: Option<Duration>,
+    games: GameFilter,
+    socket: SocketOptions,
+}
+
+fn parse_number<N: std::str::FromStr>(value: &str, what: &str) -> Result<N, String> {
+    value.parse().map_err(|_| format!("Invalid {} '{}'", what, value))
+}
+
+/// A game identifier in decimal or, with a 0x prefix, hex
+fn parse_game(value: &str) -> Result<u32, String> {
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).map_err(|_| format!("Invalid game ID '{}'", value)),
+        None => parse_number(value, "game ID"),
+    }
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        bind: "0.0.0.0:7777".to_string(),
+        log_format: LogFormat::Text,
+        capture: None,
+        capture_sessions: Vec::new(),
+        limits: SessionLimits::default(),
+        connect_limits: ConnectLimits::default(),
+        peer_timeout: None,
+        games: GameFilter::Any,
+        socket: SocketOptions::default(),
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--bind" => args.bind = value()?,
+            "--log-format" => {
+                args.log_format = match value()?.as_str() {
+                    "text" => LogFormat::Text,
+                    "json" => LogFormat::Json,
+                    other => return Err(format!("Unknown log format '{}'", other)),
+                }
+            }
+            "--capture" => args.capture = Some(value()?),
+            "--capture-session" => {
+                let id = value()?;
+                args.capture_sessions.push(id.parse().map_err(|_| format!("Invalid session ID '{}'", id))?);
+            }
+            "--max-sessions" => args.limits.max_sessions = Some(parse_number(&value()?, "session count")?),
+            "--max-clients" => args.limits.max_clients_per_session = Some(parse_number(&value()?, "client count")?),
+            "--session-lifetime" => {
+                args.limits.max_lifetime = Some(Duration::from_secs(parse_number(&value()?, "lifetime")?));
+            }
+            "--idle-timeout" => {
+                args.limits.idle_timeout = Some(Duration::from_secs(parse_number(&value()?, "idle timeout")?));
+            }
+            "--peer-timeout" => args.peer_timeout = Some(Duration::from_secs(parse_number(&value()?, "peer timeout")?)),
+            "--max-pending" => args.connect_limits.max_pending = parse_number(&value()?, "request count")?,
+            "--connect-rate" => args.connect_limits.requests_per_second = parse_number(&value()?, "request rate")?,
+            "--connect-burst" => args.connect_limits.burst = parse_number(&value()?, "request count")?,
+            "--recv-buffer" => args.socket.recv_buffer_size = Some(parse_number(&value()?, "buffer size")?),
+            "--send-buffer" => args.socket.send_buffer_size = Some(parse_number(&value()?, "buffer size")?),
+            "--dscp" => args.socket.dscp = Some(parse_number(&value()?, "DSCP code point")?),
+            "--allow-game" => {
+                let game = parse_game(&value()?)?;
+                match &mut args.games {
+                    GameFilter::Any => args.games = GameFilter::Allow(vec![game]),
+                    GameFilter::Allow(games) => games.push(game),
+                    GameFilter::Block(_) => return Err("--allow-game and --block-game can't be combined".to_string()),
+                }
+            }
+            "--block-game" => {
+                let game = parse_game(&value()?)?;
+                match &mut args.games {
+                    GameFilter::Any => args.games = GameFilter::Block(vec![game]),
+                    GameFilter::Block(games) => games.push(game),
+                    GameFilter::Allow(_) => return Err("--allow-game and --block-game can't be combined".to_string()),
+                }
+            }
+            _ => return Err(format!("Unknown argument '{}'", arg)),
+        }
+    }
+    if args.capture.is_none() && !args.capture_sessions.is_empty() {
+        return Err("--capture-session needs --capture".to_string());
+    }
+    Ok(args)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let log = Logger::new(args.log_format);
+
+    if args.log_format == LogFormat::Text {
+        println!("Project Neon Protocol v0.2 - Relay");
+        println!("===================================");
+        println!("Starting relay node...");
+        println!();
+    }
+
+    let mut builder = NeonRelay::builder()
+        .bind(args.bind)
+        .games(args.games)
+        .socket_options(args.socket)
+        .session_limits(args.limits)
+        .connect_limits(args.connect_limits)
+        .log_format(args.log_format);
+    if let Some(timeout) = args.peer_timeout {
+        builder = builder.peer_timeout(timeout);
+    }
+    let mut relay = match builder.build() {
         Ok(relay) => relay,
         Err(e) => {
-            println!("Failed to start relay: {}", e);
-            return;
+            log.event("error", &[("error", e.to_string().as_str().into())], format_args!("Failed to start relay: {}", e));
+            return ExitCode::FAILURE;
         }
     };
+    if let Some(path) = &args.capture {
+        relay.set_capture(path, &args.capture_sessions);
+    }
 
     if let Err(e) = relay.start() {
-        println!("Relay failed: {}", e);
+        log.event("error", &[("error", e.to_string().as_str().into())], format_args!("Relay failed: {}", e));
+        return ExitCode::FAILURE;
     }
-}
\ No newline at end of file
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,43 @@
+use crate::protocol::MAX_DATAGRAM_SIZE;
+
+/// Buffers kept around for reuse; anything beyond this is freed
+const MAX_POOLED_BUFFERS: usize = 1024;
+
+/// Recycles datagram buffers so the relay doesn't allocate per packet
+pub struct BufferPool {
+    free: Vec<Vec<u8>>,
+    allocations: u64,
+}
+
+impl BufferPool {
+    pub fn new() -> Self {
+        Self {
+            free: Vec::new(),
+            allocations: 0,
+        }
+    }
+
+    /// Take an empty buffer with room for a full datagram plus one byte to detect truncation
+    pub fn acquire(&mut self) -> Vec<u8> {
+        match self.free.pop() {
+            Some(buf) => buf,
+            None => {
+                self.allocations += 1;
+                Vec::with_capacity(MAX_DATAGRAM_SIZE + 1)
+            }
+        }
+    }
+
+    /// Return a buffer to the pool
+    pub fn release(&mut self, mut buf: Vec<u8>) {
+        if self.free.len() < MAX_POOLED_BUFFERS {
+            buf.clear();
+            self.free.push(buf);
+        }
+    }
+
+    /// Total buffers allocated since creation; flat in steady state
+    pub fn allocations(&self) -> u64 {
+        self.allocations
+    }
+}
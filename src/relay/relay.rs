@@ -1,281 +1,675 @@
 use std::collections::HashMap;
 use std::io::{Error, ErrorKind};
-use std::net::SocketAddr;
-use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-use super::socket::NeonSocket;
-use super::session::SessionManager;
+use crate::auth::Authenticator;
+use crate::protocol::PROTOCOL_VERSION;
+use crate::transport::Transport;
+use super::capture::Capture;
+use super::config::{ConnectLimits, DEFAULT_PEER_TIMEOUT, RelayConfig, SessionLimits};
+use super::cookie::CookieJar;
+use super::federation::Federation;
+use super::logging::{LogFormat, Logger};
+use super::socket::{NeonSocket, decode_packet};
+use super::join_codes::JoinCodes;
+use super::session::{SessionKey, SessionStats};
+use super::state::{SavedPeer, load_state, save_state};
+use super::{ClientJoinedCallback, ClientTimedOutCallback, ForwardErrorCallback, PacketDroppedCallback, SessionCallback};
 use super::types::*;
+use super::worker::{DatagramBatch, DropReason, Observation, RelayWorker, WorkerFeedback, WorkerInput, WorkerStats};
+#[cfg(feature = "admin-api")]
+use super::admin::AdminServer;
 
-pub struct RelayNode {
-    socket: NeonSocket,
-    session_manager: SessionManager,
-    pending_connections: HashMap<SocketAddr, PendingConnection>,
+/// How long to wait for a worker to answer a handle
+const WORKER_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct WorkerHandle {
+    inbox: Sender<WorkerInput>,
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+/// Stops a running relay and reads its counts from other threads, from `NeonRelay::handle`
+#[derive(Clone)]
+pub struct RelayHandle {
+    stopping: Arc<AtomicBool>,
+    worker_stats: Vec<Arc<WorkerStats>>,
+    /// Worker inboxes while the relay is running
+    inboxes: Arc<Mutex<Vec<Sender<WorkerInput>>>>,
+}
+
+impl RelayHandle {
+    /// Ask the relay to stop; `start` returns within a second. Stopping before `start` makes it return at once.
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::Relaxed);
+    }
+
+    pub fn session_count(&self) -> usize {
+        self.worker_stats.iter()
+            .map(|stats| stats.sessions.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn total_client_count(&self) -> usize {
+        self.worker_stats.iter()
+            .map(|stats| stats.clients.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Bytes and packets a session has forwarded, in total and per peer. None if the relay isn't
+    /// running or has no such session.
+    pub fn session_stats(&self, session: impl Into<SessionKey>) -> Option<SessionStats> {
+        let session = session.into();
+        let (reply, answer) = mpsc::channel();
+        {
+            let inboxes = self.inboxes.lock().unwrap();
+            if inboxes.is_empty() {
+                return None;
+            }
+            let shard = shard_of(session.session_id, inboxes.len());
+            inboxes[shard].send(WorkerInput::SessionStats(session, reply)).ok()?;
+        }
+        answer.recv_timeout(WORKER_TIMEOUT).ok().flatten()
+    }
+}
+
+/// Receives on one thread and shards sessions across worker threads by session ID
+pub struct RelayNode<T: Transport = UdpSocket> {
+    socket: NeonSocket<T>,
+    config: RelayConfig,
+    workers: Vec<WorkerHandle>,
+    worker_stats: Vec<Arc<WorkerStats>>,
+    inboxes: Arc<Mutex<Vec<Sender<WorkerInput>>>>,
+    feedback: Option<Receiver<WorkerFeedback>>,
+    routes: HashMap<(SocketAddr, u16), u32>,
+    received: DatagramBatch,
+    cookies: CookieJar,
+    authenticator: Option<Arc<dyn Authenticator>>,
+    limits: SessionLimits,
+    connect_limits: ConnectLimits,
+    peer_timeout: Duration,
+    state_file: Option<PathBuf>,
+    snapshots: Vec<Vec<SavedPeer>>,
+    federation: Federation,
+    capture: Option<(PathBuf, Vec<u32>)>,
+    log: Logger,
+    stopping: Arc<AtomicBool>,
+    /// Sessions each shard hosted when it last reported, for the session callbacks
+    hosted: Vec<Vec<u32>>,
+    on_session_created: Option<SessionCallback>,
+    on_session_destroyed: Option<SessionCallback>,
+    on_client_joined: Option<ClientJoinedCallback>,
+    on_client_timed_out: Option<ClientTimedOutCallback>,
+    on_forward_error: Option<ForwardErrorCallback>,
+    on_packet_dropped: Option<PacketDroppedCallback>,
+    #[cfg(feature = "admin-api")]
+    admin: Option<AdminServer>,
+    #[cfg(feature = "admin-api")]
+    admin_token: Option<String>,
+}
+
+/// The worker that owns `session_id` out of `shards`
+pub fn shard_of(session_id: u32, shards: usize) -> usize {
+    // Fibonacci hashing spreads sequential session IDs evenly
+    (session_id.wrapping_mul(0x9E37_79B9) >> 16) as usize % shards
 }
 
 impl RelayNode {
     pub fn new(bind_addr: &str) -> Result<Self, Error> {
+        Self::with_config(bind_addr, RelayConfig::default())
+    }
+
+    pub fn with_config(bind_addr: &str, config: RelayConfig) -> Result<Self, Error> {
+        Self::with_transport(config.socket.bind(bind_addr)?, config)
+    }
+}
+
+impl<T: Transport> RelayNode<T> {
+    pub fn with_transport(transport: T, config: RelayConfig) -> Result<Self, Error> {
+        if config.worker_threads == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Relay needs at least one worker thread"));
+        }
+        transport.disable_connection_reset()?;
+        let worker_stats = (0..config.worker_threads).map(|_| Arc::default()).collect();
         Ok(RelayNode {
-            socket: NeonSocket::new(bind_addr)?,
-            session_manager: SessionManager::new(),
-            pending_connections: HashMap::new(),
+            socket: NeonSocket::from_transport(transport),
+            config,
+            workers: Vec::new(),
+            worker_stats,
+            inboxes: Arc::default(),
+            feedback: None,
+            routes: HashMap::new(),
+            received: Vec::new(),
+            cookies: CookieJar::new(),
+            authenticator: None,
+            limits: SessionLimits::default(),
+            connect_limits: ConnectLimits::default(),
+            peer_timeout: DEFAULT_PEER_TIMEOUT,
+            state_file: None,
+            snapshots: Vec::new(),
+            federation: Federation::new(),
+            capture: None,
+            log: Logger::default(),
+            stopping: Arc::new(AtomicBool::new(false)),
+            hosted: Vec::new(),
+            on_session_created: None,
+            on_session_destroyed: None,
+            on_client_joined: None,
+            on_client_timed_out: None,
+            on_forward_error: None,
+            on_packet_dropped: None,
+            #[cfg(feature = "admin-api")]
+            admin: None,
+            #[cfg(feature = "admin-api")]
+            admin_token: None,
         })
     }
 
+    /// Check connection requests' auth tokens before forwarding them; takes effect when the relay starts
+    pub fn set_authenticator<A: Authenticator + 'static>(&mut self, authenticator: A) {
+        self.authenticator = Some(Arc::new(authenticator));
+    }
+
+    /// Cap sessions, clients per session, and session lifetimes; takes effect when the relay starts
+    pub fn set_session_limits(&mut self, limits: SessionLimits) {
+        self.limits = limits;
+    }
+
+    /// Cap pending connection requests and how fast each address may send them; takes effect when the relay starts
+    pub fn set_connect_limits(&mut self, limits: ConnectLimits) {
+        self.connect_limits = limits;
+    }
+
+    /// Drop peers unheard from for this long; takes effect when the relay starts
+    pub fn set_peer_timeout(&mut self, timeout: Duration) {
+        self.peer_timeout = timeout;
+    }
+
+    /// Save registrations to `path` whenever they change and restore them from it on start
+    pub fn set_state_file(&mut self, path: impl AsRef<Path>) {
+        self.state_file = Some(path.as_ref().to_path_buf());
+    }
+
+    /// Write forwarded packets to a pcap file at `path`, only for `sessions` unless that's empty
+    pub fn set_capture(&mut self, path: impl AsRef<Path>, sessions: &[u32]) {
+        self.capture = Some((path.as_ref().to_path_buf(), sessions.to_vec()));
+    }
+
+    /// Write the log as text or as JSON lines
+    pub fn set_log_format(&mut self, format: LogFormat) {
+        self.log = Logger::new(format);
+        self.socket.set_logger(self.log);
+    }
+
+    /// Exchange session ownership with another relay, redirecting joins for its sessions there
+    pub fn add_peer_relay(&mut self, addr: SocketAddr) {
+        self.federation.add_peer(addr);
+    }
+
+    /// Set the address peer relays send clients to for this relay's sessions (default: the bound address)
+    pub fn set_public_addr(&mut self, addr: SocketAddr) {
+        self.federation.set_public_addr(addr);
+    }
+
+    /// Call `callback` with the session ID whenever a host registers a new session
+    pub fn on_session_created(&mut self, callback: SessionCallback) {
+        self.on_session_created = Some(callback);
+    }
+
+    /// Call `callback` with the session ID whenever a session ends, including when the relay stops
+    pub fn on_session_destroyed(&mut self, callback: SessionCallback) {
+        self.on_session_destroyed = Some(callback);
+    }
+
+    /// Call `callback` with the session ID, client ID and address whenever a client joins a session
+    pub fn on_client_joined(&mut self, callback: ClientJoinedCallback) {
+        self.on_client_joined = Some(callback);
+    }
+
+    /// Call `callback` with the session ID and client ID whenever the relay times a client out
+    pub fn on_client_timed_out(&mut self, callback: ClientTimedOutCallback) {
+        self.on_client_timed_out = Some(callback);
+    }
+
+    /// Call `callback` with the destination and error whenever forwarding a packet fails
+    pub fn on_forward_error(&mut self, callback: ForwardErrorCallback) {
+        self.on_forward_error = Some(callback);
+    }
+
+    /// Call `callback` with the sender and reason whenever the relay drops a datagram
+    pub fn on_packet_dropped(&mut self, callback: PacketDroppedCallback) {
+        self.on_packet_dropped = Some(callback);
+    }
+
+    /// Get a handle that stops the relay and reads its counts from other threads
+    pub fn handle(&self) -> RelayHandle {
+        RelayHandle {
+            stopping: self.stopping.clone(),
+            worker_stats: self.worker_stats.clone(),
+            inboxes: self.inboxes.clone(),
+        }
+    }
+
+    /// Serve the admin API on `addr`, returning the address it is listening on
+    #[cfg(feature = "admin-api")]
+    pub fn enable_admin_api(&mut self, addr: impl std::net::ToSocketAddrs) -> Result<SocketAddr, Error> {
+        let admin = AdminServer::bind(addr)?;
+        let local_addr = admin.local_addr()?;
+        self.admin = Some(admin);
+        Ok(local_addr)
+    }
+
+    /// Require `Authorization: Bearer <token>` on admin API requests
+    #[cfg(feature = "admin-api")]
+    pub fn set_admin_token(&mut self, token: impl Into<String>) {
+        self.admin_token = Some(token.into());
+    }
+
     pub fn run(&mut self) -> Result<(), Error> {
-        println!("Relay node listening on {}...", "0.0.0.0:7777");
-        println!("Protocol Version: 0.2");
-        println!();
-        
+        let local_addr = self.socket.local_addr()?;
+        self.log.event(
+            "listening",
+            &[("addr", local_addr.into()), ("worker_threads", self.config.worker_threads.into())],
+            format_args!(
+                "Relay node listening on {}...\nProtocol Version: 0.2\nWorker threads: {}\n",
+                local_addr, self.config.worker_threads
+            ),
+        );
+
         self.socket.set_nonblocking(true)?;
-        
-        let mut last_cleanup = Instant::now();
-        let cleanup_interval = Duration::from_secs(5);
+        self.federation.start(local_addr, self.config.worker_threads)?;
+        self.restore_state()?;
+        self.spawn_workers()?;
+        #[cfg(feature = "admin-api")]
+        if let Some(admin) = self.admin.take() {
+            let inboxes = self.workers.iter().map(|worker| worker.inbox.clone()).collect();
+            admin.spawn(self.admin_token.clone(), inboxes, self.worker_stats.clone(), self.log)?;
+        }
 
         loop {
-            match self.socket.receive_packet() {
-                Ok((packet, addr)) => {
-                    self.handle_packet(packet, addr)?;
+            if self.stopping.swap(false, Ordering::Relaxed) {
+                return self.stop_workers();
+            }
+            self.drain_feedback();
+            self.announce_sessions();
+
+            if self.socket.wait_readable(Duration::from_secs(1))? {
+                let mut received = std::mem::take(&mut self.received);
+                self.socket.receive_datagrams(&mut received)?;
+                for addr in self.socket.take_oversized() {
+                    self.notify_dropped(addr, DropReason::Oversized);
                 }
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    // No packets available
+
+                let mut batches: Vec<DatagramBatch> = (0..self.workers.len()).map(|_| Vec::new()).collect();
+                for (data, addr) in received.drain(..) {
+                    let packet = match decode_packet(&data) {
+                        Ok(packet) => packet,
+                        Err(e) => {
+                            self.log.info(format_args!("[Relay] Dropped malformed datagram from {}: {}", addr, e));
+                            // Counted against the first shard, which owns every unrouted datagram
+                            self.worker_stats[0].malformed_packets.fetch_add(1, Ordering::Relaxed);
+                            self.notify_dropped(addr, DropReason::Malformed);
+                            self.socket.recycle(data);
+                            continue;
+                        }
+                    };
+                    if self.federate(&packet, addr) {
+                        self.socket.recycle(data);
+                        continue;
+                    }
+                    let shard = match self.route(&packet, addr) {
+                        Some(session_id) => self.shard_for(session_id),
+                        None => 0,
+                    };
+                    batches[shard].push((data, addr));
                 }
-                Err(e) => return Err(e),
-            }
+                self.received = received;
 
-            if last_cleanup.elapsed() >= cleanup_interval {
-                self.session_manager.cleanup_dead_connections();
-                last_cleanup = Instant::now();
+                for (shard, batch) in batches.into_iter().enumerate() {
+                    if !batch.is_empty() {
+                        self.dispatch(shard, batch)?;
+                    }
+                }
             }
-
-            sleep(Duration::from_millis(1));
         }
     }
 
-    fn handle_packet(&mut self, packet: NeonPacket, addr: SocketAddr) -> Result<(), Error> {
-        if packet.packet_type < 0x10 {
-            self.handle_core_packet(packet, addr)
-        } else {
-            self.handle_game_packet(packet, addr)
+    /// Shut every worker down and forget their sessions, leaving the relay ready to start again
+    fn stop_workers(&mut self) -> Result<(), Error> {
+        self.inboxes.lock().unwrap().clear();
+        for worker in &self.workers {
+            let _ = worker.inbox.send(WorkerInput::Stop);
+        }
+        for mut worker in self.workers.drain(..) {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+        for stats in &self.worker_stats {
+            stats.sessions.store(0, Ordering::Relaxed);
+            stats.clients.store(0, Ordering::Relaxed);
+        }
+        self.drain_feedback();
+        self.feedback = None;
+        self.routes.clear();
+        for shard in 0..self.hosted.len() {
+            self.notify_sessions(shard, Vec::new());
         }
+        self.log.info(format_args!("[Relay] Stopped"));
+        Ok(())
     }
 
-    fn handle_core_packet(&mut self, packet: NeonPacket, addr: SocketAddr) -> Result<(), Error> {
-        match packet.packet_type {
-            x if x == CorePacketType::ConnectRequest as u8 => {
-                if let PacketPayload::ConnectRequest(req) = packet.payload {
-                    self.handle_connect_request(req, addr)?;
-                }
+    fn spawn_workers(&mut self) -> Result<(), Error> {
+        let capture = match &self.capture {
+            Some((path, sessions)) => {
+                self.log.info(format_args!("[Relay] Capturing forwarded packets to {}", path.display()));
+                Some(Capture::create(path, sessions.clone())?)
             }
-            x if x == CorePacketType::ConnectAccept as u8 => {
-                if let PacketPayload::ConnectAccept(accept) = packet.payload.clone() {
-                    if let Some(host_addr) = self.session_manager.hosts.get(&accept.session_id) {
-                        if addr == *host_addr && packet.client_id != 1 {
-                            self.route_connect_accept_to_client(accept, packet.client_id)?;
-                            return Ok(());
-                        }
-                    }
-
-                    if packet.client_id == 1 {
-                        self.session_manager.register_host(accept.session_id, addr);
-                    } else {
-                        self.session_manager.register_client(accept.session_id, packet.client_id, addr);
-                    }
-                }
+            None => None,
+        };
+        let (feedback_tx, feedback_rx) = mpsc::channel();
+        let join_codes = Arc::new(Mutex::new(JoinCodes::default()));
+        for index in 0..self.config.worker_threads {
+            let (inbox_tx, inbox_rx) = mpsc::channel();
+            let stats = self.worker_stats[index].clone();
+            let mut worker = RelayWorker::new(
+                self.socket.try_clone()?,
+                self.cookies.clone(),
+                self.authenticator.clone(),
+                feedback_tx.clone(),
+                stats.clone(),
+                self.log,
+            );
+            if self.state_file.is_some() {
+                worker.restore(&self.snapshots[index]);
+                worker.publish_snapshots(index);
             }
-            x if x == CorePacketType::ConnectDeny as u8 => {
-                if let PacketPayload::ConnectDeny(deny) = packet.payload {
-                    self.handle_connect_deny(deny, addr)?;
-                }
+            if self.federation.is_enabled() || self.on_session_created.is_some() || self.on_session_destroyed.is_some() {
+                worker.publish_sessions(index);
             }
-            _ => {
-                self.forward_to_peers(&packet, addr)?;
-                if let Some(session_id) = self.session_manager.find_session_for_addr(addr) {
-                    self.session_manager.update_client_activity(packet.client_id, session_id);
-                }
+            if self.on_client_joined.is_some()
+                || self.on_client_timed_out.is_some()
+                || self.on_forward_error.is_some()
+                || self.on_packet_dropped.is_some()
+            {
+                worker.publish_observations();
             }
+            if let Some(capture) = &capture {
+                worker.capture_to(capture.clone());
+            }
+            worker.enforce_limits(self.limits.clone(), self.worker_stats.clone());
+            worker.set_connect_limits(self.connect_limits.clone());
+            worker.set_peer_timeout(self.peer_timeout);
+            worker.set_game_filter(self.config.games.clone());
+            worker.share_join_codes(join_codes.clone());
+            let thread = thread::Builder::new()
+                .name(format!("neon-relay-worker-{}", index))
+                .spawn(move || worker.run(inbox_rx))?;
+
+            self.inboxes.lock().unwrap().push(inbox_tx.clone());
+            self.workers.push(WorkerHandle {
+                inbox: inbox_tx,
+                thread: Some(thread),
+            });
         }
+        self.feedback = Some(feedback_rx);
         Ok(())
     }
 
-    fn handle_game_packet(&mut self, packet: NeonPacket, addr: SocketAddr) -> Result<(), Error> {
-        self.forward_to_peers(&packet, addr)?;
-        if let Some(session_id) = self.session_manager.find_session_for_addr(addr) {
-            self.session_manager.update_client_activity(packet.client_id, session_id);
+    /// Load saved registrations, grouped by the shard that will own them, and route their addresses
+    fn restore_state(&mut self) -> Result<(), Error> {
+        self.snapshots = vec![Vec::new(); self.config.worker_threads];
+        self.hosted = vec![Vec::new(); self.config.worker_threads];
+        let Some(path) = &self.state_file else { return Ok(()) };
+
+        let peers = load_state(path)?;
+        if !peers.is_empty() {
+            self.log.info(format_args!("[Relay] Restoring {} registration(s) from {}", peers.len(), path.display()));
+        }
+        for peer in peers {
+            self.routes.insert((peer.addr, peer.client_id), peer.session_id);
+            let shard = self.shard_for(peer.session_id);
+            self.snapshots[shard].push(peer);
+        }
+        for (shard, peers) in self.snapshots.iter().enumerate() {
+            let mut hosted: Vec<u32> = peers.iter().filter(|peer| peer.client_id == 1).map(|peer| peer.session_id).collect();
+            hosted.sort_unstable();
+            // Restored sessions carry on rather than being created again
+            self.hosted[shard] = hosted.clone();
+            self.federation.set_local(shard, hosted);
         }
         Ok(())
     }
 
-    fn handle_connect_request(
-        &mut self,
-        req: ConnectRequest,
-        client_addr: SocketAddr,
-    ) -> Result<(), Error> {
-        let target_session = req.target_session_id;
+    fn save_state(&self) {
+        let Some(path) = &self.state_file else { return };
+        let peers: Vec<SavedPeer> = self.snapshots.concat();
+        if let Err(e) = save_state(path, &peers) {
+            self.log.info(format_args!("[Relay] Failed to save state to {}: {}", path.display(), e));
+        }
+    }
 
-        println!(
-            "[Relay] Client '{}' from {} requesting to join session {}",
-            req.desired_name, client_addr, target_session
-        );
-        
-        if let Some(game_id) = req.game_identifier {
-            println!("[Relay]   Game ID: 0x{:08X}", game_id);
+    /// Send peer relays the sessions this relay owns, when due
+    fn announce_sessions(&mut self) {
+        let load = self.total_client_count() as u32;
+        for (announcement, peer) in self.federation.announcements(load) {
+            if let Err(e) = self.socket.send_packet(&announcement, PROTOCOL_VERSION, peer) {
+                self.log.info(format_args!("[Relay] Failed to announce sessions to peer relay {}: {}", peer, e));
+            }
+        }
+    }
+
+    /// Handle federation traffic on the receive thread: answer queries for where a session is,
+    /// record peer relays' announcements, and redirect requests for sessions a peer owns.
+    /// Returns true if the datagram was consumed.
+    fn federate(&mut self, packet: &PacketRef, addr: SocketAddr) -> bool {
+        match &packet.payload {
+            PayloadRef::SessionRedirect(query) if query.relays.is_empty() => {
+                self.send_session_location(query.session_id, packet, addr);
+                true
+            }
+            PayloadRef::SessionRedirect(announcement) => {
+                if !self.federation.learn(addr, announcement) {
+                    self.log.info(format_args!(
+                        "[Relay] Ignoring session announcement from {}, which isn't a peer relay",
+                        addr
+                    ));
+                }
+                true
+            }
+            PayloadRef::ConnectRequest(req) => {
+                let Some(owner) = self.federation.owner(req.target_session_id) else {
+                    return false;
+                };
+                self.log.event(
+                    "redirect",
+                    &[("session_id", req.target_session_id.into()), ("addr", addr.into()), ("relay", owner.into())],
+                    format_args!(
+                        "[Relay] Session {} is owned by relay {}, redirecting {}",
+                        req.target_session_id, owner, addr
+                    ),
+                );
+                self.send_session_location(req.target_session_id, packet, addr);
+                true
+            }
+            _ => false,
         }
+    }
 
-        if let Some(host_addr) = self.session_manager.hosts.get(&target_session) {
-            println!(
-                "[Relay] Forwarding connection request to host at {}",
-                host_addr
-            );
+    /// Answer `packet` with the relays that own or could take `session_id`
+    fn send_session_location(&self, session_id: u32, packet: &PacketRef, addr: SocketAddr) {
+        let relays = self.federation.answer(session_id, self.total_client_count() as u32);
+        let answer = NeonPacket {
+            packet_type: CorePacketType::SessionRedirect as u8,
+            sequence: packet.sequence,
+            client_id: 0,
+            destination_id: 0,
+            payload: PacketPayload::SessionRedirect(SessionRedirect { session_id, relays }),
+        };
+        if let Err(e) = self.socket.send_packet(&answer, packet.version, addr) {
+            self.log.info(format_args!("[Relay] Failed to tell {} where session {} is: {}", addr, session_id, e));
+        }
+    }
 
-            self.pending_connections.insert(
-                client_addr,
-                PendingConnection {
-                    client_addr,
-                    session_id: target_session,
-                    client_name: req.desired_name.clone(),
-                },
-            );
+    /// Find the session a datagram belongs to. Handshake packets carry the session ID;
+    /// everything else is looked up by sender address and client ID.
+    fn route(&mut self, packet: &PacketRef, addr: SocketAddr) -> Option<u32> {
+        match &packet.payload {
+            PayloadRef::ConnectRequest(req) => Some(req.target_session_id),
+            PayloadRef::ConnectAccept(accept) => {
+                // Only proven registrations are remembered; the rest still reach the session's worker
+                if self.cookies.verify(addr, SessionKey::new(accept.game_identifier, accept.session_id), packet.client_id, accept.cookie) {
+                    self.routes.insert((addr, packet.client_id), accept.session_id);
+                }
+                Some(accept.session_id)
+            }
+            _ => self.routes.get(&(addr, packet.client_id)).copied(),
+        }
+    }
 
-            let forward_packet = NeonPacket {
-                packet_type: CorePacketType::ConnectRequest as u8,
-                sequence: 1,
-                client_id: 0,
-                destination_id: 1,
-                payload: PacketPayload::ConnectRequest(req.clone()),
-            };
-
-            self.socket.send_packet(&forward_packet, *host_addr)?;
-        } else {
-            println!(
-                "[Relay] Session {} not found (no host registered)",
-                target_session
-            );
+    fn shard_for(&self, session_id: u32) -> usize {
+        shard_of(session_id, self.config.worker_threads)
+    }
+
+    fn dispatch(&mut self, shard: usize, batch: DatagramBatch) -> Result<(), Error> {
+        if self.workers[shard].inbox.send(WorkerInput::Datagrams(batch)).is_ok() {
+            return Ok(());
         }
 
-        Ok(())
+        // The worker only drops its inbox when it exits, so surface why it stopped
+        match self.workers[shard].thread.take().map(|thread| thread.join()) {
+            Some(Ok(Err(e))) => Err(e),
+            Some(Err(_)) => Err(Error::other(format!("Relay worker {} panicked", shard))),
+            _ => Err(Error::other(format!("Relay worker {} stopped", shard))),
+        }
     }
 
-    fn handle_connect_deny(
-        &mut self,
-        deny: ConnectDeny,
-        host_addr: SocketAddr,
-    ) -> Result<(), Error> {
-        let mut client_addr_to_send = None;
-        
-        for (session_id, host) in &self.session_manager.hosts {
-            if *host == host_addr {
-                for (addr, pending) in &self.pending_connections {
-                    if pending.session_id == *session_id {
-                        client_addr_to_send = Some(*addr);
-                        break;
+    fn drain_feedback(&mut self) {
+        // Taken for the duration so the callbacks can borrow the relay
+        let Some(feedback) = self.feedback.take() else { return };
+        let mut changed = false;
+        while let Ok(message) = feedback.try_recv() {
+            match message {
+                WorkerFeedback::Recycle(buf) => self.socket.recycle(buf),
+                WorkerFeedback::Route(addr, client_id, session_id) => {
+                    self.routes.insert((addr, client_id), session_id);
+                }
+                WorkerFeedback::Unroute(addr, client_id, session_id) => {
+                    if self.routes.get(&(addr, client_id)) == Some(&session_id) {
+                        self.routes.remove(&(addr, client_id));
                     }
                 }
-                break;
+                WorkerFeedback::Snapshot(shard, peers) => {
+                    self.snapshots[shard] = peers;
+                    changed = true;
+                }
+                WorkerFeedback::Sessions(shard, sessions) => {
+                    self.notify_sessions(shard, sessions.clone());
+                    self.federation.set_local(shard, sessions);
+                }
+                WorkerFeedback::Observed(observation) => self.notify_observed(observation),
             }
         }
-        
-        if let Some(client_addr) = client_addr_to_send {
-            println!(
-                "[Relay] Routing ConnectDeny back to {}",
-                client_addr
-            );
-            
-            let deny_packet = NeonPacket {
-                packet_type: CorePacketType::ConnectDeny as u8,
-                sequence: 1,
-                client_id: 0,
-                destination_id: 0,
-                payload: PacketPayload::ConnectDeny(deny),
-            };
-            
-            self.socket.send_packet(&deny_packet, client_addr)?;
-            self.pending_connections.remove(&client_addr);
-        } else {
-            println!("[Relay] No pending connection found for ConnectDeny");
+        self.feedback = Some(feedback);
+        if changed {
+            self.save_state();
         }
-        
-        Ok(())
     }
 
-    fn route_connect_accept_to_client(
-        &mut self,
-        accept: ConnectAccept,
-        client_id: u8,
-    ) -> Result<(), Error> {
-        let mut client_addr_to_send = None;
-
-        for (addr, pending) in &self.pending_connections {
-            if pending.session_id == accept.session_id {
-                client_addr_to_send = Some(*addr);
-                break;
+    fn notify_observed(&mut self, observation: Observation) {
+        match observation {
+            Observation::ClientJoined(session_id, client_id, addr) => {
+                if let Some(callback) = &mut self.on_client_joined {
+                    callback(session_id, client_id, addr);
+                }
+            }
+            Observation::ClientTimedOut(session_id, client_id) => {
+                if let Some(callback) = &mut self.on_client_timed_out {
+                    callback(session_id, client_id);
+                }
+            }
+            Observation::ForwardError(addr, e) => {
+                if let Some(callback) = &mut self.on_forward_error {
+                    callback(addr, &e);
+                }
             }
+            Observation::PacketDropped(addr, reason) => self.notify_dropped(addr, reason),
         }
+    }
 
-        if let Some(client_addr) = client_addr_to_send {
-            println!(
-                "[Relay] Routing ConnectAccept for client {} back to {}",
-                client_id, client_addr
-            );
-
-            let response_packet = NeonPacket {
-                packet_type: CorePacketType::ConnectAccept as u8,
-                sequence: 1,
-                client_id,
-                destination_id: client_id,
-                payload: PacketPayload::ConnectAccept(accept),
-            };
-
-            self.socket.send_packet(&response_packet, client_addr)?;
-            self.pending_connections.remove(&client_addr);
-        } else {
-            println!("[Relay] No pending connection found for ConnectAccept");
+    fn notify_dropped(&mut self, addr: SocketAddr, reason: DropReason) {
+        if let Some(callback) = &mut self.on_packet_dropped {
+            callback(addr, reason);
         }
-
-        Ok(())
     }
 
-    fn forward_to_peers(&self, packet: &NeonPacket, sender_addr: SocketAddr) -> Result<(), Error> {
-        for (_session_id, peers) in &self.session_manager.sessions {
-            if let Some(_sender) = peers.iter().find(|p| p.addr == sender_addr) {
-                if let Some(dest_peer) = peers.iter().find(|p| p.client_id == packet.destination_id) {
-                    if dest_peer.addr != sender_addr {
-                        match self.socket.send_packet(packet, dest_peer.addr) {
-                            Ok(()) => {
-                                // Successfully forwarded
-                            }
-                            Err(e) => {
-                                println!(
-                                    "[Relay] Failed to forward packet from {} to client {} at {}: {}",
-                                    sender_addr, packet.destination_id, dest_peer.addr, e
-                                );
-                            }
-                        }
-                    }
-                } else {
-                    println!(
-                        "[Relay] Destination client {} not found in session, dropping packet from {}",
-                        packet.destination_id, sender_addr
-                    );
-                    println!("{:?}", packet)
-                }
-                
-                return Ok(());
+    /// Tell the session callbacks how a shard's hosted sessions changed
+    fn notify_sessions(&mut self, shard: usize, mut sessions: Vec<u32>) {
+        sessions.sort_unstable();
+        let previous = std::mem::replace(&mut self.hosted[shard], sessions);
+        if let Some(callback) = &mut self.on_session_destroyed {
+            for &session_id in previous.iter().filter(|id| self.hosted[shard].binary_search(id).is_err()) {
+                callback(session_id);
+            }
+        }
+        if let Some(callback) = &mut self.on_session_created {
+            for &session_id in self.hosted[shard].iter().filter(|id| previous.binary_search(id).is_err()) {
+                callback(session_id);
             }
         }
+    }
 
-        println!("[Relay] Unknown sender: {}, dropping packet", sender_addr);
-        Ok(())
+    pub fn buffer_allocations(&self) -> u64 {
+        self.socket.buffer_allocations()
+            + self.worker_stats.iter()
+                .map(|stats| stats.buffer_allocations.load(Ordering::Relaxed))
+                .sum::<u64>()
     }
 
     pub fn session_count(&self) -> usize {
-        self.session_manager.sessions.len()
+        self.worker_stats.iter()
+            .map(|stats| stats.sessions.load(Ordering::Relaxed))
+            .sum()
     }
 
     pub fn total_client_count(&self) -> usize {
-        self.session_manager.sessions.values()
-            .map(|peers| peers.len())
+        self.worker_stats.iter()
+            .map(|stats| stats.clients.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn spoofed_packet_count(&self) -> u64 {
+        self.worker_stats.iter()
+            .map(|stats| stats.spoofed_packets.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn throttled_request_count(&self) -> u64 {
+        self.worker_stats.iter()
+            .map(|stats| stats.throttled_requests.load(Ordering::Relaxed))
             .sum()
     }
-}
\ No newline at end of file
+
+    pub fn malformed_packet_count(&self) -> u64 {
+        self.worker_stats.iter()
+            .map(|stats| stats.malformed_packets.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn packets_relayed(&self) -> u64 {
+        self.worker_stats.iter()
+            .map(|stats| stats.packets_relayed.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn bytes_relayed(&self) -> u64 {
+        self.worker_stats.iter()
+            .map(|stats| stats.bytes_relayed.load(Ordering::Relaxed))
+            .sum()
+    }
+}
@@ -1,35 +1,339 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::net::SocketAddr;
 use std::time::{Duration, Instant};
+use super::config::DEFAULT_PEER_TIMEOUT;
+use super::logging::{LogFormat, Logger};
+use super::state::SavedPeer;
 use super::types::PeerInfo;
+use crate::protocol::{DisconnectReason, PeerRole, SessionMetadata};
+
+/// When a session started and last carried game traffic, for the relay's session limits, what
+/// it has forwarded, and what its host advertises
+struct SessionActivity {
+    started: Instant,
+    last_traffic: Instant,
+    forwarded: Traffic,
+    peers: HashMap<u16, PeerStats>,
+    metadata: SessionMetadata,
+}
+
+impl SessionActivity {
+    fn new() -> Self {
+        let now = Instant::now();
+        SessionActivity {
+            started: now,
+            last_traffic: now,
+            forwarded: Traffic::default(),
+            peers: HashMap::new(),
+            metadata: SessionMetadata::default(),
+        }
+    }
+}
+
+/// A session as the relay keys it: session IDs only need to be unique within a game, so two
+/// games can both use session 1. Hosts and clients that send no game identifier use game 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SessionKey {
+    pub game_identifier: u32,
+    pub session_id: u32,
+}
+
+impl SessionKey {
+    pub fn new(game_identifier: u32, session_id: u32) -> Self {
+        SessionKey { game_identifier, session_id }
+    }
+}
+
+impl From<u32> for SessionKey {
+    /// A session of a host that sent no game identifier
+    fn from(session_id: u32) -> Self {
+        SessionKey::new(0, session_id)
+    }
+}
+
+impl fmt::Display for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.game_identifier == 0 {
+            write!(f, "{}", self.session_id)
+        } else {
+            write!(f, "{} (game ID 0x{:08X})", self.session_id, self.game_identifier)
+        }
+    }
+}
+
+/// A count of forwarded packets and their bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl Traffic {
+    fn add(&mut self, bytes: usize) {
+        self.packets += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// What the relay forwarded from and to one peer in a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerStats {
+    pub client_id: u16,
+    /// Forwarded from this peer to others
+    pub sent: Traffic,
+    /// Forwarded to this peer from others
+    pub received: Traffic,
+}
+
+/// What the relay forwarded within a session since it started. Peers that left are counted in
+/// `forwarded` but no longer listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    pub session_id: u32,
+    pub game_identifier: u32,
+    pub forwarded: Traffic,
+    /// By client ID
+    pub peers: Vec<PeerStats>,
+}
 
 pub struct SessionManager {
-    pub sessions: HashMap<u32, Vec<PeerInfo>>,
-    pub hosts: HashMap<u32, SocketAddr>,
+    pub sessions: HashMap<SessionKey, Vec<PeerInfo>>,
+    pub hosts: HashMap<SessionKey, SocketAddr>,
+    /// Session of each client ID registered at an address. A host and its clients can share an address.
+    by_addr: HashMap<SocketAddr, HashMap<u16, SessionKey>>,
+    by_id: HashMap<(SessionKey, u16), SocketAddr>,
+    names: HashMap<(SessionKey, u16), String>,
+    /// Clients that didn't join as players
+    roles: HashMap<(SessionKey, u16), PeerRole>,
+    activity: HashMap<SessionKey, SessionActivity>,
+    peer_timeout: Duration,
+    generation: u64,
+    log: Logger,
 }
 
 impl SessionManager {
-    pub fn new() -> Self {
+    pub fn new(log: Logger) -> Self {
         SessionManager {
             sessions: HashMap::new(),
             hosts: HashMap::new(),
+            by_addr: HashMap::new(),
+            by_id: HashMap::new(),
+            names: HashMap::new(),
+            roles: HashMap::new(),
+            activity: HashMap::new(),
+            peer_timeout: DEFAULT_PEER_TIMEOUT,
+            generation: 0,
+            log,
+        }
+    }
+
+    /// The hosted session a packet naming `session` means: that game's session, or, when the packet
+    /// or the host named no game, the only session with that ID. None if there is none, or several.
+    pub fn resolve(&self, session: SessionKey) -> Option<SessionKey> {
+        if self.hosts.contains_key(&session) {
+            return Some(session);
+        }
+        if session.game_identifier != 0 {
+            let unnamed = SessionKey::from(session.session_id);
+            return self.hosts.contains_key(&unnamed).then_some(unnamed);
+        }
+        let mut matching = self.hosts.keys().filter(|key| key.session_id == session.session_id);
+        match (matching.next(), matching.next()) {
+            (Some(&key), None) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Find which session a client ID is registered in from an address
+    pub fn lookup_peer(&self, addr: SocketAddr, client_id: u16) -> Option<SessionKey> {
+        self.by_addr.get(&addr)?.get(&client_id).copied()
+    }
+
+    /// Every session and client ID registered from an address, by client ID
+    pub fn peers_at(&self, addr: SocketAddr) -> Vec<(SessionKey, u16)> {
+        let mut peers: Vec<(SessionKey, u16)> = self
+            .by_addr
+            .get(&addr)
+            .map(|ids| ids.iter().map(|(&client_id, &session)| (session, client_id)).collect())
+            .unwrap_or_default();
+        peers.sort_unstable_by_key(|&(_, client_id)| client_id);
+        peers
+    }
+
+    /// Find the address of a client within a session
+    pub fn peer_addr(&self, session: SessionKey, client_id: u16) -> Option<SocketAddr> {
+        self.by_id.get(&(session, client_id)).copied()
+    }
+
+    /// Remember the name a client was accepted under
+    pub fn set_peer_name(&mut self, session: SessionKey, client_id: u16, name: String) {
+        self.names.insert((session, client_id), name);
+        self.generation += 1;
+    }
+
+    /// Remember the role a client was accepted as
+    pub fn set_peer_role(&mut self, session: SessionKey, client_id: u16, role: PeerRole) {
+        let previous = match role {
+            PeerRole::Player => self.roles.remove(&(session, client_id)),
+            _ => self.roles.insert((session, client_id), role),
+        };
+        if previous.unwrap_or_default() != role {
+            self.generation += 1;
+        }
+    }
+
+    /// Get the role a client was accepted as; hosts and unknown peers are players
+    pub fn peer_role(&self, session: SessionKey, client_id: u16) -> PeerRole {
+        self.roles.get(&(session, client_id)).copied().unwrap_or_default()
+    }
+
+    /// Counter that changes whenever a registration, name, role or session's metadata does
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Every registered peer, for saving across restarts
+    pub fn saved_peers(&self) -> Vec<SavedPeer> {
+        let mut peers: Vec<SavedPeer> = self
+            .sessions
+            .values()
+            .flatten()
+            .map(|peer| SavedPeer {
+                session_id: peer.session.session_id,
+                game_identifier: peer.session.game_identifier,
+                client_id: peer.client_id,
+                addr: peer.addr,
+                name: self.peer_name(peer.session, peer.client_id).unwrap_or_default().to_string(),
+                role: self.peer_role(peer.session, peer.client_id),
+                metadata: if peer.is_host {
+                    self.metadata(peer.session).cloned().unwrap_or_default()
+                } else {
+                    SessionMetadata::default()
+                },
+            })
+            .collect();
+        peers.sort_by_key(|peer| (peer.game_identifier, peer.session_id, peer.client_id));
+        peers
+    }
+
+    /// Register peers saved before a restart; clients count as just seen
+    pub fn restore(&mut self, peers: &[SavedPeer]) {
+        for peer in peers {
+            let session = SessionKey::new(peer.game_identifier, peer.session_id);
+            if peer.client_id == 1 {
+                self.register_host(session, peer.addr);
+            } else {
+                self.register_client(session, peer.client_id, peer.addr);
+            }
+            if !peer.name.is_empty() {
+                self.set_peer_name(session, peer.client_id, peer.name.clone());
+            }
+            self.set_peer_role(session, peer.client_id, peer.role);
+            if !peer.metadata.is_empty() {
+                self.set_metadata(session, peer.metadata.clone());
+            }
+        }
+    }
+
+    /// Store what a session's host advertises about it
+    pub fn set_metadata(&mut self, session: SessionKey, metadata: SessionMetadata) {
+        let Some(activity) = self.activity.get_mut(&session) else { return };
+        if activity.metadata != metadata {
+            activity.metadata = metadata;
+            self.generation += 1;
         }
     }
 
-    pub fn cleanup_dead_connections(&mut self) {
-        let timeout = Duration::from_secs(15);
+    /// What a session's host advertises about it, or None if there is no such session
+    pub fn metadata(&self, session: SessionKey) -> Option<&SessionMetadata> {
+        self.activity.get(&session).map(|activity| &activity.metadata)
+    }
+
+    /// Get the name a client was accepted under
+    pub fn peer_name(&self, session: SessionKey, client_id: u16) -> Option<&str> {
+        self.names.get(&(session, client_id)).map(String::as_str)
+    }
+
+    /// Addresses of everyone in a session except the given client
+    pub fn other_peers(&self, session: SessionKey, client_id: u16) -> Vec<(u16, SocketAddr)> {
+        self.sessions
+            .get(&session)
+            .map(|peers| {
+                peers
+                    .iter()
+                    .filter(|peer| peer.client_id != client_id)
+                    .map(|peer| (peer.client_id, peer.addr))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn index_peer(&mut self, session: SessionKey, client_id: u16, addr: SocketAddr) {
+        if let Some(old_addr) = self.by_id.insert((session, client_id), addr)
+            && old_addr != addr
+        {
+            self.remove_addr_entry(old_addr, client_id, session);
+        }
+        if let Some(old_session) = self.by_addr.entry(addr).or_default().insert(client_id, session)
+            && old_session != session
+            && self.by_id.get(&(old_session, client_id)) == Some(&addr)
+        {
+            self.by_id.remove(&(old_session, client_id));
+        }
+    }
+
+    fn unindex_peer(&mut self, session: SessionKey, client_id: u16, addr: SocketAddr) {
+        if self.by_id.get(&(session, client_id)) == Some(&addr) {
+            self.by_id.remove(&(session, client_id));
+        }
+        self.remove_addr_entry(addr, client_id, session);
+        self.names.remove(&(session, client_id));
+        self.roles.remove(&(session, client_id));
+        if let Some(activity) = self.activity.get_mut(&session) {
+            activity.peers.remove(&client_id);
+        }
+    }
+
+    fn remove_addr_entry(&mut self, addr: SocketAddr, client_id: u16, session: SessionKey) {
+        let Some(ids) = self.by_addr.get_mut(&addr) else { return };
+        if ids.get(&client_id) == Some(&session) {
+            ids.remove(&client_id);
+        }
+        if ids.is_empty() {
+            self.by_addr.remove(&addr);
+        }
+    }
+
+    /// Drop peers unheard from for this long
+    pub fn set_peer_timeout(&mut self, timeout: Duration) {
+        self.peer_timeout = timeout;
+    }
+
+    pub fn peer_timeout(&self) -> Duration {
+        self.peer_timeout
+    }
+
+    /// Drop timed-out clients and empty sessions, returning who was removed from which session.
+    /// Hosts (client ID 1) are only removed along with their session; see expired_sessions for silent ones.
+    pub fn cleanup_dead_connections(&mut self) -> Vec<(SocketAddr, SessionKey, u16)> {
+        let timeout = self.peer_timeout;
         let now = Instant::now();
 
-        let mut sessions_to_remove: Vec<u32> = Vec::new();
+        let mut sessions_to_remove: Vec<SessionKey> = Vec::new();
+        let mut removed: Vec<(SocketAddr, SessionKey, u16)> = Vec::new();
+        let log = self.log;
 
-        for (session_id, peers) in &mut self.sessions {
+        for (session, peers) in &mut self.sessions {
             peers.retain(|peer| {
                 if !peer.is_host {
                     let is_alive = now.duration_since(peer.last_seen) < timeout;
                     if !is_alive {
-                        println!(
-                            "[Relay] Client {} in session {} timed out",
-                            peer.client_id, session_id
+                        removed.push((peer.addr, *session, peer.client_id));
+                        log.event(
+                            "timeout",
+                            &[("session_id", session.session_id.into()), ("client_id", peer.client_id.into()), ("addr", peer.addr.into())],
+                            format_args!("[Relay] Client {} in session {} timed out", peer.client_id, session),
                         );
                     }
                     is_alive
@@ -39,19 +343,35 @@ impl SessionManager {
             });
 
             if peers.is_empty() {
-                sessions_to_remove.push(*session_id);
+                sessions_to_remove.push(*session);
             }
         }
 
-        for session_id in sessions_to_remove {
-            self.sessions.remove(&session_id);
-            self.hosts.remove(&session_id);
-            println!("[Relay] Removed empty session {}", session_id)
+        for session in sessions_to_remove {
+            self.sessions.remove(&session);
+            self.activity.remove(&session);
+            if let Some(host_addr) = self.hosts.remove(&session) {
+                removed.push((host_addr, session, 1));
+            }
+            self.log.event(
+                "session_destroyed",
+                &[("session_id", session.session_id.into()), ("reason", "empty".into())],
+                format_args!("[Relay] Removed empty session {}", session),
+            );
+        }
+
+        for &(addr, session, client_id) in &removed {
+            self.unindex_peer(session, client_id, addr);
+        }
+        if !removed.is_empty() {
+            self.generation += 1;
         }
+
+        removed
     }
 
-    pub fn update_client_activity(&mut self, client_id: u8, session_id: u32) {
-        if let Some(peers) = self.sessions.get_mut(&session_id) {
+    pub fn update_client_activity(&mut self, client_id: u16, session: SessionKey) {
+        if let Some(peers) = self.sessions.get_mut(&session) {
             for peer in peers.iter_mut() {
                 if peer.client_id == client_id {
                     peer.last_seen = Instant::now();
@@ -61,86 +381,233 @@ impl SessionManager {
         }
     }
 
-    pub fn find_session_for_addr(&self, addr: SocketAddr) -> Option<u32> {
-        for (session_id, peers) in &self.sessions {
-            if peers.iter().any(|p| p.addr == addr) {
-                return Some(*session_id);
-            }
+    /// Note game traffic in a session, which keeps it from going idle
+    pub fn record_traffic(&mut self, session: SessionKey) {
+        if let Some(activity) = self.activity.get_mut(&session) {
+            activity.last_traffic = Instant::now();
         }
-        None
     }
 
-    pub fn register_host(&mut self, session_id: u32, addr: SocketAddr) {
-        self.hosts.insert(session_id, addr);
+    /// Count a packet of `bytes` forwarded from one peer to another in a session
+    pub fn record_forwarded(&mut self, session: SessionKey, from: u16, to: u16, bytes: usize) {
+        let Some(activity) = self.activity.get_mut(&session) else { return };
+        activity.forwarded.add(bytes);
+        activity.peers.entry(from).or_insert(PeerStats { client_id: from, ..PeerStats::default() }).sent.add(bytes);
+        activity.peers.entry(to).or_insert(PeerStats { client_id: to, ..PeerStats::default() }).received.add(bytes);
+    }
+
+    /// What a session has forwarded, or None if there is no such session
+    pub fn session_stats(&self, session: SessionKey) -> Option<SessionStats> {
+        let activity = self.activity.get(&session)?;
+        let mut peers: Vec<PeerStats> = activity.peers.values().copied().collect();
+        peers.sort_unstable_by_key(|peer| peer.client_id);
+        Some(SessionStats { session_id: session.session_id, game_identifier: session.game_identifier, forwarded: activity.forwarded, peers })
+    }
+
+    /// Number of clients in a session, not counting the host
+    pub fn client_count(&self, session: SessionKey) -> usize {
+        self.sessions.get(&session).map_or(0, |peers| peers.iter().filter(|peer| !peer.is_host).count())
+    }
+
+    /// Sessions to close, with why: their host was unheard from for the peer timeout (TimedOut), they're
+    /// older than `max_lifetime` (Expired), or they had no game traffic for `idle_timeout` (Idle)
+    pub fn expired_sessions(&self, max_lifetime: Option<Duration>, idle_timeout: Option<Duration>) -> Vec<(SessionKey, DisconnectReason)> {
+        self.activity
+            .iter()
+            .filter_map(|(&session, activity)| {
+                let host_silent = self.sessions.get(&session).is_some_and(|peers| {
+                    peers.iter().any(|peer| peer.is_host && peer.last_seen.elapsed() >= self.peer_timeout)
+                });
+                if host_silent {
+                    Some((session, DisconnectReason::TimedOut))
+                } else if max_lifetime.is_some_and(|limit| activity.started.elapsed() >= limit) {
+                    Some((session, DisconnectReason::Expired))
+                } else if idle_timeout.is_some_and(|limit| activity.last_traffic.elapsed() >= limit) {
+                    Some((session, DisconnectReason::Idle))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn register_host(&mut self, session: SessionKey, addr: SocketAddr) {
+        let created = !self.sessions.contains_key(&session);
+        self.hosts.insert(session, addr);
 
         let peer = PeerInfo {
             addr,
             client_id: 1,
-            session_id,
+            session,
             is_host: true,
             last_seen: Instant::now(),
         };
 
         self.sessions
-            .entry(session_id)
-            .or_insert_with(Vec::new)
+            .entry(session)
+            .or_default()
             .retain(|p| p.client_id != 1);
-        self.sessions.get_mut(&session_id).unwrap().push(peer);
+        self.sessions.get_mut(&session).unwrap().push(peer);
+        self.activity.entry(session).or_insert_with(SessionActivity::new);
+        self.index_peer(session, 1, addr);
+        self.generation += 1;
 
-        println!(
-            "[Relay] Host registered for session {} at {}",
-            session_id, addr
+        if created {
+            self.log.event(
+                "session_created",
+                &[("session_id", session.session_id.into()), ("game_id", session.game_identifier.into()), ("addr", addr.into())],
+                format_args!("[Relay] Session {} created", session),
+            );
+        }
+        self.log.event(
+            "host_registered",
+            &[("session_id", session.session_id.into()), ("client_id", 1u16.into()), ("addr", addr.into())],
+            format_args!("[Relay] Host registered for session {} at {}", session, addr),
         );
         self.print_active_sessions();
     }
 
-    pub fn register_client(&mut self, session_id: u32, client_id: u8, addr: SocketAddr) {
+    pub fn register_client(&mut self, session: SessionKey, client_id: u16, addr: SocketAddr) {
         let peer = PeerInfo {
             addr,
             client_id,
-            session_id,
+            session,
             is_host: false,
             last_seen: Instant::now(),
         };
 
         self.sessions
-            .entry(session_id)
-            .or_insert_with(Vec::new)
+            .entry(session)
+            .or_default()
             .retain(|p| p.client_id != client_id);
-        self.sessions.get_mut(&session_id).unwrap().push(peer);
+        self.sessions.get_mut(&session).unwrap().push(peer);
+        self.activity.entry(session).or_insert_with(SessionActivity::new);
+        self.index_peer(session, client_id, addr);
+        self.generation += 1;
 
-        println!(
-            "[Relay] Client {} registered to session {} from {}",
-            client_id, session_id, addr
+        self.log.event(
+            "client_registered",
+            &[("session_id", session.session_id.into()), ("client_id", client_id.into()), ("addr", addr.into())],
+            format_args!("[Relay] Client {} registered to session {} from {}", client_id, session, addr),
         );
-        self.print_session_info(session_id);
+        self.print_session_info(session);
+    }
+
+    /// Drop a session and everyone in it, returning their addresses and IDs, or None if there is no such session
+    pub fn remove_session(&mut self, session: SessionKey) -> Option<Vec<(SocketAddr, u16)>> {
+        let peers = self.sessions.remove(&session)?;
+        self.hosts.remove(&session);
+        self.activity.remove(&session);
+        for peer in &peers {
+            self.unindex_peer(session, peer.client_id, peer.addr);
+        }
+        self.generation += 1;
+        Some(peers.into_iter().map(|peer| (peer.addr, peer.client_id)).collect())
     }
 
+    /// Print a table of sessions; left out of JSON logs, which have the events it summarises
     pub fn print_active_sessions(&self) {
+        if self.log.format() != LogFormat::Text {
+            return;
+        }
         println!("\n=== Active Sessions ===");
         if self.sessions.is_empty() {
             println!("No active sessions");
         } else {
-            for (session_id, peers) in &self.sessions {
+            for (session, peers) in &self.sessions {
                 let host_count = peers.iter().filter(|p| p.is_host).count();
                 let client_count = peers.iter().filter(|p| !p.is_host).count();
                 println!(
                     "Session {}: {} host(s), {} client(s)",
-                    session_id, host_count, client_count
+                    session, host_count, client_count
                 );
             }
         }
         println!("========================\n");
     }
 
-    fn print_session_info(&self, session_id: u32) {
-        if let Some(peers) = self.sessions.get(&session_id) {
+    fn print_session_info(&self, session: SessionKey) {
+        if self.log.format() != LogFormat::Text {
+            return;
+        }
+        if let Some(peers) = self.sessions.get(&session) {
             let clients: Vec<_> = peers.iter().filter(|p| !p.is_host).collect();
             println!(
                 "  Session {} now has {} client(s) connected",
-                session_id,
+                session,
                 clients.len()
             );
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(feature = "admin-api")]
+impl SessionManager {
+    /// Every session with its host and how many peers it has
+    pub fn summaries(&self) -> Vec<super::admin::SessionSummary> {
+        self.sessions
+            .iter()
+            .map(|(&session, peers)| super::admin::SessionSummary {
+                session_id: session.session_id,
+                game_identifier: session.game_identifier,
+                host: self.hosts.get(&session).copied(),
+                peers: peers.len(),
+                forwarded: self.activity.get(&session).map(|activity| activity.forwarded).unwrap_or_default(),
+                metadata: self.metadata(session).cloned().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Everyone registered in a session, or None if there is no such session
+    pub fn peer_summaries(&self, session: SessionKey) -> Option<Vec<super::admin::PeerSummary>> {
+        let peers = self.sessions.get(&session)?;
+        let traffic = self.activity.get(&session).map(|activity| &activity.peers);
+        let mut summaries: Vec<_> = peers
+            .iter()
+            .map(|peer| {
+                let stats = traffic.and_then(|traffic| traffic.get(&peer.client_id)).copied().unwrap_or_default();
+                super::admin::PeerSummary {
+                    client_id: peer.client_id,
+                    addr: peer.addr,
+                    name: self.peer_name(session, peer.client_id).unwrap_or_default().to_string(),
+                    role: self.peer_role(session, peer.client_id),
+                    is_host: peer.is_host,
+                    idle: peer.last_seen.elapsed(),
+                    sent: stats.sent,
+                    received: stats.received,
+                }
+            })
+            .collect();
+        summaries.sort_by_key(|peer| peer.client_id);
+        Some(summaries)
+    }
+
+    /// Drop every peer registered from `ip`, returning who was removed from which session.
+    /// A host takes its whole session with it.
+    pub fn remove_ip(&mut self, ip: std::net::IpAddr) -> Vec<(SocketAddr, SessionKey, u16)> {
+        let matching: Vec<(SocketAddr, SessionKey, u16)> = self
+            .by_addr
+            .iter()
+            .filter(|(addr, _)| addr.ip() == ip)
+            .flat_map(|(&addr, ids)| ids.iter().map(move |(&client_id, &session)| (addr, session, client_id)))
+            .collect();
+
+        let mut removed = Vec::new();
+        for (addr, session, client_id) in matching {
+            if client_id == 1 {
+                if let Some(peers) = self.remove_session(session) {
+                    removed.extend(peers.into_iter().map(|(addr, client_id)| (addr, session, client_id)));
+                }
+            } else if let Some(peers) = self.sessions.get_mut(&session) {
+                peers.retain(|peer| peer.client_id != client_id);
+                if peers.is_empty() {
+                    self.sessions.remove(&session);
+                    self.activity.remove(&session);
+                }
+                self.unindex_peer(session, client_id, addr);
+                self.generation += 1;
+                removed.push((addr, session, client_id));
+            }
+        }
+        removed
+    }
+}
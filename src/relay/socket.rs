@@ -1,50 +1,176 @@
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 use std::net::{SocketAddr, UdpSocket};
-use super::types::{NeonPacket, PacketHeader, PacketPayload};
+use std::time::Duration;
+use crate::transport::Transport;
+use super::logging::Logger;
+use super::pool::BufferPool;
+use crate::protocol::MAX_DATAGRAM_SIZE;
+use super::types::{NeonPacket, PacketHeader, PacketRef, PayloadRef};
 
-pub struct NeonSocket {
-    socket: UdpSocket,
+/// Maximum datagrams read per receive call
+pub const RECV_BATCH_SIZE: usize = 32;
+
+pub struct NeonSocket<T: Transport = UdpSocket> {
+    socket: T,
+    pool: BufferPool,
+    recv_bufs: Vec<Vec<u8>>,
+    outgoing: Vec<(Vec<u8>, SocketAddr)>,
+    /// Senders of datagrams dropped for being oversized since the last take_oversized
+    oversized: Vec<SocketAddr>,
+    log: Logger,
 }
 
-impl NeonSocket {
-    pub fn new(addr: &str) -> Result<Self, Error> {
-        let socket = UdpSocket::bind(addr)?;
-        Ok(NeonSocket { socket })
+impl<T: Transport> NeonSocket<T> {
+    pub fn from_transport(socket: T) -> Self {
+        NeonSocket {
+            socket,
+            pool: BufferPool::new(),
+            recv_bufs: Vec::with_capacity(RECV_BATCH_SIZE),
+            outgoing: Vec::new(),
+            oversized: Vec::new(),
+            log: Logger::default(),
+        }
+    }
+
+    /// Clone the underlying transport for another thread, with its own buffers
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        let mut clone = Self::from_transport(self.socket.try_clone()?);
+        clone.log = self.log;
+        Ok(clone)
+    }
+
+    pub fn set_logger(&mut self, log: Logger) {
+        self.log = log;
+    }
+
+    /// Hand a received datagram buffer back for reuse
+    pub fn recycle(&mut self, buf: Vec<u8>) {
+        self.pool.release(buf);
+    }
+
+    /// Total datagram buffers allocated so far
+    pub fn buffer_allocations(&self) -> u64 {
+        self.pool.allocations()
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.socket.local_addr()
     }
 
     pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
         self.socket.set_nonblocking(nonblocking)
     }
 
-    pub fn send_packet(&self, packet: &NeonPacket, addr: SocketAddr) -> Result<(), Error> {
+    /// Encode and send a packet with a header of the given protocol version
+    pub fn send_packet(&self, packet: &NeonPacket, version: u8, addr: SocketAddr) -> Result<(), Error> {
         let header = PacketHeader {
             magic: 0x4E45,
-            version: 1,
+            version,
+            flags: 0,
             packet_type: packet.packet_type,
             sequence: packet.sequence,
             client_id: packet.client_id,
             destination_id: packet.destination_id,
         };
         let mut bytes = header.to_bytes();
-        bytes.extend(packet.payload.to_bytes());
+        bytes.extend(packet.payload.to_bytes(version));
         self.socket.send_to(&bytes, addr)?;
         Ok(())
     }
 
-    pub fn receive_packet(&self) -> Result<(NeonPacket, SocketAddr), Error> {
-        let mut buf = [0; 1024];
-        let (size, addr) = self.socket.recv_from(&mut buf)?;
-        let header = PacketHeader::from_bytes(&buf[..8])?;
-        let payload = PacketPayload::from_bytes(header.packet_type, &buf[8..size])?;
-        Ok((
-            NeonPacket {
-                packet_type: header.packet_type,
-                sequence: header.sequence,
-                client_id: header.client_id,
-                destination_id: header.destination_id,
-                payload,
-            },  
-            addr,
-        ))
-    }
-}
\ No newline at end of file
+    /// Block until a datagram is waiting or the timeout elapses.
+    /// Returns true if the socket is readable.
+    pub fn wait_readable(&self, timeout: Duration) -> Result<bool, Error> {
+        self.socket.wait_readable(timeout)
+    }
+
+    /// Queue an already-encoded datagram to be sent as-is on the next flush,
+    /// preserving header flags set by the sender
+    pub fn queue_raw(&mut self, data: &[u8], addr: SocketAddr) {
+        let mut buf = self.pool.acquire();
+        buf.extend_from_slice(data);
+        self.outgoing.push((buf, addr));
+    }
+
+    /// Send every queued datagram, batched where the transport supports it.
+    /// Returns the datagrams that couldn't be sent, by destination.
+    pub fn flush(&mut self) -> Vec<(SocketAddr, Error)> {
+        let mut failures = Vec::new();
+        if self.outgoing.is_empty() {
+            return failures;
+        }
+        let outgoing = std::mem::take(&mut self.outgoing);
+
+        let sent = self.socket.send_batch(&outgoing).unwrap_or(0);
+
+        // Anything the batch send didn't take is retried one at a time so failures are attributed
+        for (data, addr) in &outgoing[sent..] {
+            if let Err(e) = self.socket.send_to(data, *addr) {
+                self.log.event(
+                    "forward_error",
+                    &[("addr", (*addr).into()), ("error", e.to_string().as_str().into())],
+                    format_args!("[Relay] Failed to forward packet to {}: {}", addr, e),
+                );
+                failures.push((*addr, e));
+            }
+        }
+
+        self.outgoing = outgoing;
+        for (buf, _) in self.outgoing.drain(..) {
+            self.pool.release(buf);
+        }
+        failures
+    }
+
+    /// Take the senders of datagrams receive_datagrams dropped for being oversized
+    pub fn take_oversized(&mut self) -> Vec<SocketAddr> {
+        std::mem::take(&mut self.oversized)
+    }
+
+    /// Receive up to RECV_BATCH_SIZE datagrams without blocking into pooled buffers.
+    /// Appends nothing when no datagrams are waiting; pass buffers back through recycle().
+    pub fn receive_datagrams(&mut self, out: &mut Vec<(Vec<u8>, SocketAddr)>) -> Result<(), Error> {
+        while self.recv_bufs.len() < RECV_BATCH_SIZE {
+            let mut buf = self.pool.acquire();
+            buf.resize(MAX_DATAGRAM_SIZE + 1, 0);
+            self.recv_bufs.push(buf);
+        }
+
+        let received = match self.socket.recv_batch(&mut self.recv_bufs) {
+            Ok(received) => received,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        // Filled buffers are moved out in order; the rest stay for the next call
+        let filled: Vec<Vec<u8>> = self.recv_bufs.drain(..received.len()).collect();
+        for (mut buf, (size, addr)) in filled.into_iter().zip(received) {
+            match addr {
+                Some(addr) if size > MAX_DATAGRAM_SIZE => {
+                    self.log.info(format_args!("[Relay] Dropped oversized datagram from {}", addr));
+                    self.oversized.push(addr);
+                    self.pool.release(buf);
+                }
+                Some(addr) => {
+                    buf.truncate(size);
+                    out.push((buf, addr));
+                }
+                None => self.pool.release(buf),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode a received datagram without copying its payload
+pub fn decode_packet(data: &[u8]) -> Result<PacketRef<'_>, Error> {
+    let header = PacketHeader::from_bytes(data)?;
+    let payload = PayloadRef::parse(header.packet_type, &data[header.size()..], header.version)?;
+    Ok(PacketRef {
+        packet_type: header.packet_type,
+        sequence: header.sequence,
+        client_id: header.client_id,
+        destination_id: header.destination_id,
+        version: header.version,
+        payload,
+    })
+}
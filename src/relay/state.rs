@@ -0,0 +1,89 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::path::Path;
+use crate::decoder::Decoder;
+use crate::protocol::{PeerRole, SessionMetadata};
+
+/// First bytes of a relay state file
+const STATE_MAGIC: &[u8; 4] = b"NEOS";
+
+/// Layout version written after the magic. Format 1 had no game identifiers; its peers load into game 0.
+/// Format 2 had no session metadata, and formats before 4 no roles; their clients load as players.
+const STATE_FORMAT: u8 = 4;
+
+/// A registered host (client ID 1) or client as saved across relay restarts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedPeer {
+    pub session_id: u32,
+    pub game_identifier: u32,
+    pub client_id: u16,
+    pub addr: SocketAddr,
+    /// Name the client was accepted under, empty for hosts
+    pub name: String,
+    pub role: PeerRole,
+    /// What a host advertises about its session, empty for clients
+    pub metadata: SessionMetadata,
+}
+
+pub fn encode_state(peers: &[SavedPeer]) -> Vec<u8> {
+    let mut bytes = STATE_MAGIC.to_vec();
+    bytes.push(STATE_FORMAT);
+    bytes.extend(&(peers.len() as u32).to_le_bytes());
+    for peer in peers {
+        bytes.extend(&peer.session_id.to_le_bytes());
+        bytes.extend(&peer.game_identifier.to_le_bytes());
+        bytes.extend(&peer.client_id.to_le_bytes());
+        let addr = peer.addr.to_string();
+        bytes.push(addr.len() as u8);
+        bytes.extend(addr.as_bytes());
+        bytes.extend(&(peer.name.len() as u16).to_le_bytes());
+        bytes.extend(peer.name.as_bytes());
+        bytes.push(peer.role as u8);
+        peer.metadata.encode(&mut bytes);
+    }
+    bytes
+}
+
+pub fn decode_state(data: &[u8]) -> Result<Vec<SavedPeer>, Error> {
+    let mut decoder = Decoder::new(data, "Relay state");
+    if decoder.bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+        return Err(decoder.malformed());
+    }
+    let format = decoder.u8()?;
+    if !(1..=STATE_FORMAT).contains(&format) {
+        return Err(decoder.malformed());
+    }
+
+    let count = decoder.u32()?;
+    let mut peers = Vec::new();
+    for _ in 0..count {
+        let session_id = decoder.u32()?;
+        let game_identifier = if format >= 2 { decoder.u32()? } else { 0 };
+        let client_id = decoder.u16()?;
+        let addr = decoder.short_string()?.parse().map_err(|_| decoder.malformed())?;
+        let name_len = decoder.u16()? as usize;
+        let name = decoder.string(name_len)?;
+        let role = if format >= 4 { PeerRole::from(decoder.u8()?) } else { PeerRole::Player };
+        let metadata = if format >= 3 { SessionMetadata::decode(&mut decoder)? } else { SessionMetadata::default() };
+        peers.push(SavedPeer { session_id, game_identifier, client_id, addr, name, role, metadata });
+    }
+    Ok(peers)
+}
+
+/// Write the state through a temporary file so a crash mid-write leaves the old state intact
+pub fn save_state(path: &Path, peers: &[SavedPeer]) -> Result<(), Error> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, encode_state(peers))?;
+    fs::rename(&temp, path)
+}
+
+/// Read a saved state; a missing file means there is nothing to restore
+pub fn load_state(path: &Path) -> Result<Vec<SavedPeer>, Error> {
+    match fs::read(path) {
+        Ok(data) => decode_state(&data),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// Addresses tracked at once; requests from further addresses are refused until some go quiet
+const MAX_TRACKED_ADDRESSES: usize = 65_536;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Token bucket per source address, limiting how fast each may send connection requests
+#[derive(Debug, Clone)]
+pub struct ConnectThrottle {
+    buckets: HashMap<SocketAddr, Bucket>,
+    rate: f64,
+    burst: f64,
+}
+
+impl ConnectThrottle {
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        Self { buckets: HashMap::new(), rate: requests_per_second as f64, burst: burst as f64 }
+    }
+
+    /// Spend one of `addr`'s requests, returning whether it had one left
+    pub fn allow(&mut self, addr: SocketAddr) -> bool {
+        let now = Instant::now();
+        if !self.buckets.contains_key(&addr) && self.buckets.len() >= MAX_TRACKED_ADDRESSES {
+            self.prune();
+            if self.buckets.len() >= MAX_TRACKED_ADDRESSES {
+                return false;
+            }
+        }
+        let bucket = self.buckets.entry(addr).or_insert(Bucket { tokens: self.burst, refilled: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * self.rate).min(self.burst);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// Forget addresses whose buckets have filled up again, which are no different from new ones
+    pub fn prune(&mut self) {
+        let (rate, burst) = (self.rate, self.burst);
+        self.buckets.retain(|_, bucket| bucket.tokens + bucket.refilled.elapsed().as_secs_f64() * rate < burst);
+    }
+}
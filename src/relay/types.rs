@@ -1,16 +1,21 @@
-use std::convert::TryInto;
 use std::io::{Error, ErrorKind};
+use crate::decoder::Decoder;
+use crate::protocol::{DisconnectReason, FeatureSet, PeerRole, SPECTATOR_FLAG, SessionMetadata, header_size, is_v1, push_client_id, push_version, read_client_id, read_version};
 use std::net::SocketAddr;
 use std::time::Instant;
+use super::session::SessionKey;
 
 #[derive(Debug, Clone)]
 pub struct PacketHeader {
     pub magic: u16,
+    /// Protocol version, without any flags older versions carry in the same byte
     pub version: u8,
+    /// FLAG_* bits from protocol
+    pub flags: u8,
     pub packet_type: u8,
     pub sequence: u16,
-    pub client_id: u8,
-    pub destination_id: u8,
+    pub client_id: u16,
+    pub destination_id: u16,
 }
 
 #[derive(Debug, Clone)]
@@ -21,18 +26,44 @@ pub enum PacketPayload {
     ConnectRequest(ConnectRequest),
     ConnectAccept(ConnectAccept),
     ConnectDeny(ConnectDeny),
+    Challenge(Challenge),
+    DisconnectNotice(DisconnectNotice),
+    SessionRedirect(SessionRedirect),
     SessionConfig(SessionConfig),
+    Chat(Chat),
     Ack(Ack),
     PacketTypeRegistry(PacketTypeRegistry),
     GamePacket(Vec<u8>),
 }
 
+/// Payload view used on the relay hot path. Only the handshake packets the relay
+/// acts on are decoded; everything else stays a borrowed slice of the datagram.
+#[derive(Debug)]
+pub enum PayloadRef<'a> {
+    ConnectRequest(ConnectRequest),
+    ConnectAccept(ConnectAccept),
+    ConnectDeny(ConnectDeny),
+    Chat(Chat),
+    SessionRedirect(SessionRedirect),
+    Raw(&'a [u8]),
+}
+
+#[derive(Debug)]
+pub struct PacketRef<'a> {
+    pub packet_type: u8,
+    pub sequence: u16,
+    pub client_id: u16,
+    pub destination_id: u16,
+    pub version: u8,
+    pub payload: PayloadRef<'a>,
+}
+
 #[derive(Debug, Clone)]
 pub struct NeonPacket {
     pub packet_type: u8,
     pub sequence: u16,
-    pub client_id: u8,
-    pub destination_id: u8,
+    pub client_id: u16,
+    pub destination_id: u16,
     pub payload: PacketPayload,
 }
 
@@ -41,18 +72,76 @@ pub struct ConnectRequest {
     pub client_version: u8,
     pub desired_name: String,
     pub target_session_id: u32,
-    pub game_identifier: Option<u32>,
+    pub game_identifier: u32,
+    /// Set by the relay when forwarding so the host's answer can be matched to this request.
+    /// Not carried in version 1 headers.
+    pub nonce: u32,
+    /// ID to take back after the relay lost track of this client, with the host's resume token.
+    /// Both 0 for a fresh join; not carried in version 1 headers.
+    pub resume_id: u16,
+    pub resume_token: u64,
+    /// Opaque credential for the host's or relay's Authenticator, empty if none
+    pub auth_token: Vec<u8>,
+    /// Role the client asks to join as, flagged in the client_version byte
+    pub role: PeerRole,
 }
 
 #[derive(Debug, Clone)]
 pub struct ConnectAccept {
-    pub assigned_client_id: u8,
+    pub assigned_client_id: u16,
     pub session_id: u32,
+    /// Relay cookie proving the sender can receive at its address (0 when there is none)
+    pub cookie: u64,
+    /// Nonce of the ConnectRequest being answered (0 when there is none)
+    pub nonce: u32,
+    /// Secret the client presents to resume its ID after a relay restart (0 when there is none)
+    pub resume_token: u64,
+    /// Game the session belongs to (0 when unset). Only sent when set.
+    pub game_identifier: u32,
+    /// Code clients can join the session with, carried only in host registrations (empty when unset)
+    pub join_code: String,
+    /// What the host advertises about the session, carried only in host registrations (empty when unset)
+    pub metadata: SessionMetadata,
+    /// Features the client supports, carried only in its confirmation of the accept (empty when unset)
+    pub features: FeatureSet,
 }
 
 #[derive(Debug, Clone)]
 pub struct ConnectDeny {
     pub reason: String,
+    /// Nonce of the ConnectRequest being answered. Not carried in version 1 headers.
+    pub nonce: u32,
+}
+
+/// Sent by the relay in answer to an unproven registration; echo the cookie in ConnectAccept
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub cookie: u64,
+}
+
+/// Sent by the relay to a session's host when it drops one of the host's clients
+#[derive(Debug, Clone)]
+pub struct DisconnectNotice {
+    pub client_id: u16,
+    pub reason: DisconnectReason,
+}
+
+/// Where to find a session. A relay answers a ConnectRequest for a session another relay owns with
+/// the owner, and peered relays announce their sessions with these. Sent with no relays, it asks a
+/// relay where a session is; the answer names the owner alone, or every relay that could take it.
+#[derive(Debug, Clone)]
+pub struct SessionRedirect {
+    pub session_id: u32,
+    /// Best first; the answering or announcing relay lists itself first
+    pub relays: Vec<RelayInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RelayInfo {
+    /// e.g. "203.0.113.7:7777"
+    pub addr: String,
+    /// Hosts and clients registered with the relay, 0 if unknown
+    pub load: u32,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -70,6 +159,8 @@ pub struct SessionConfig {
     pub version: u8,
     pub tick_rate: u16,
     pub max_packet_size: u16,
+    /// Features both ends support; empty from hosts that predate feature negotiation
+    pub features: FeatureSet,
 }
 
 #[derive(Debug, Clone)]
@@ -77,6 +168,12 @@ pub struct Ack {
     pub acknowledged_sequences: Vec<u16>,
 }
 
+#[derive(Debug, Clone)]
+pub struct Chat {
+    pub sender_name: String,
+    pub text: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct PacketTypeRegistry {
     pub entries: Vec<PacketTypeEntry>,
@@ -92,22 +189,26 @@ pub struct PacketTypeEntry {
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum CorePacketType {
+    SessionRedirect = 0x00,
     ConnectRequest = 0x01,
     ConnectAccept = 0x02,
     ConnectDeny = 0x03,
     SessionConfig = 0x04,
     PacketTypeRegistry = 0x05,
+    Batch = 0x06,
+    Chat = 0x07,
     Ping = 0x0B,
     Pong = 0x0C,
     DisconnectNotice = 0x0D,
     Ack = 0x0E,
+    Challenge = 0x0F,
 }
 
 #[derive(Debug, Clone)]
 pub struct PeerInfo {
     pub addr: SocketAddr,
-    pub client_id: u8,
-    pub session_id: u32,
+    pub client_id: u16,
+    pub session: SessionKey,
     pub last_seen: Instant,
     pub is_host: bool,
 }
@@ -115,81 +216,156 @@ pub struct PeerInfo {
 #[derive(Debug, Clone)]
 pub struct PendingConnection {
     pub client_addr: SocketAddr,
-    pub session_id: u32,
+    pub session: SessionKey,
     pub client_name: String,
+    pub role: PeerRole,
+    /// Header version the request arrived with, used for a denial
+    pub version: u8,
+    pub requested_at: Instant,
 }
 
 impl PacketHeader {
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
         bytes.extend(&self.magic.to_le_bytes());
-        bytes.push(self.version);
+        push_version(&mut bytes, self.version, self.flags);
         bytes.push(self.packet_type);
         bytes.extend(&self.sequence.to_le_bytes());
-        bytes.push(self.client_id);
-        bytes.push(self.destination_id);
+        push_client_id(&mut bytes, self.client_id, self.version);
+        push_client_id(&mut bytes, self.destination_id, self.version);
         bytes
     }
 
-    pub fn from_bytes(data: &[u8]) -> Result<PacketHeader, Error> {
-        if data.len() < 8 {
-            return Err(Error::new(ErrorKind::InvalidData, "Data too short"));
-        }
+    /// Encoded size of this header, which depends on its version
+    pub fn size(&self) -> usize {
+        header_size(self.version)
+    }
 
-        let magic = u16::from_le_bytes([data[0], data[1]]);
+    pub fn from_bytes(data: &[u8]) -> Result<PacketHeader, Error> {
+        let mut decoder = Decoder::new(data, "Packet header");
+        let magic = decoder.u16()?;
         if magic != 0x4E45 {
             return Err(Error::new(ErrorKind::InvalidData, "Invalid magic number"));
         }
 
-        let version = data[2];
-        let packet_type = data[3];
-        let sequence = u16::from_le_bytes([data[4], data[5]]);
-        let client_id = data[6];
-        let destination_id = data[7];
-
+        let (version, flags) = read_version(&mut decoder)?;
         Ok(PacketHeader {
             magic,
             version,
-            packet_type,
-            sequence,
-            client_id,
-            destination_id,
+            flags,
+            packet_type: decoder.u8()?,
+            sequence: decoder.u16()?,
+            client_id: read_client_id(&mut decoder, version)?,
+            destination_id: read_client_id(&mut decoder, version)?,
         })
     }
 }
 
+impl<'a> PayloadRef<'a> {
+    pub fn parse(packet_type: u8, data: &'a [u8], version: u8) -> Result<Self, Error> {
+        match packet_type {
+            x if x == CorePacketType::ConnectRequest as u8
+                || x == CorePacketType::ConnectAccept as u8
+                || x == CorePacketType::ConnectDeny as u8
+                || x == CorePacketType::Chat as u8
+                || x == CorePacketType::SessionRedirect as u8 =>
+            {
+                match PacketPayload::from_bytes(packet_type, data, version)? {
+                    PacketPayload::ConnectRequest(req) => Ok(PayloadRef::ConnectRequest(req)),
+                    PacketPayload::ConnectAccept(accept) => Ok(PayloadRef::ConnectAccept(accept)),
+                    PacketPayload::ConnectDeny(deny) => Ok(PayloadRef::ConnectDeny(deny)),
+                    PacketPayload::Chat(chat) => Ok(PayloadRef::Chat(chat)),
+                    PacketPayload::SessionRedirect(redirect) => Ok(PayloadRef::SessionRedirect(redirect)),
+                    _ => Ok(PayloadRef::Raw(data)),
+                }
+            }
+            _ => Ok(PayloadRef::Raw(data)),
+        }
+    }
+}
+
 impl PacketPayload {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    /// Encode for a packet whose header carries `version`, which decides the width of client IDs
+    pub fn to_bytes(&self, version: u8) -> Vec<u8> {
         match self {
             PacketPayload::None => vec![],
             PacketPayload::Ping(ping) => ping.timestamp.to_le_bytes().to_vec(),
             PacketPayload::Pong(pong) => pong.original_timestamp.to_le_bytes().to_vec(),
             PacketPayload::ConnectRequest(req) => {
-                let mut bytes = vec![req.client_version];
+                let role_flag = if req.role == PeerRole::Spectator { SPECTATOR_FLAG } else { 0 };
+                let mut bytes = vec![req.client_version | role_flag];
                 bytes.extend(&req.target_session_id.to_le_bytes());
-                
-                if let Some(game_id) = req.game_identifier {
-                    bytes.push(1);
-                    bytes.extend(&game_id.to_le_bytes());
-                } else {
-                    bytes.push(0);
+                bytes.extend(&req.game_identifier.to_le_bytes());
+                if !is_v1(version) {
+                    bytes.extend(&req.nonce.to_le_bytes());
+                    bytes.extend(&req.resume_id.to_le_bytes());
+                    bytes.extend(&req.resume_token.to_le_bytes());
                 }
-                
                 bytes.extend(req.desired_name.as_bytes());
+                // Names can't contain NUL, so requests without a token are unchanged
+                if !req.auth_token.is_empty() {
+                    bytes.push(0);
+                    bytes.extend(&req.auth_token);
+                }
                 bytes
             }
             PacketPayload::ConnectAccept(accept) => {
-                let mut bytes = vec![accept.assigned_client_id];
+                let mut bytes = Vec::new();
+                push_client_id(&mut bytes, accept.assigned_client_id, version);
                 bytes.extend(&accept.session_id.to_le_bytes());
+                bytes.extend(&accept.cookie.to_le_bytes());
+                bytes.extend(&accept.nonce.to_le_bytes());
+                bytes.extend(&accept.resume_token.to_le_bytes());
+                // Each trailing field is written when it or a later one is set
+                let has_features = !accept.features.is_empty();
+                let has_metadata = !accept.metadata.is_empty() || has_features;
+                if accept.game_identifier != 0 || !accept.join_code.is_empty() || has_metadata {
+                    bytes.extend(&accept.game_identifier.to_le_bytes());
+                }
+                if !accept.join_code.is_empty() || has_metadata {
+                    bytes.push(accept.join_code.len() as u8);
+                    bytes.extend(accept.join_code.as_bytes());
+                }
+                if has_metadata {
+                    accept.metadata.encode(&mut bytes);
+                }
+                if has_features {
+                    bytes.extend(&accept.features.bits().to_le_bytes());
+                }
+                bytes
+            }
+            PacketPayload::Challenge(challenge) => challenge.cookie.to_le_bytes().to_vec(),
+            PacketPayload::DisconnectNotice(notice) => {
+                let mut bytes = Vec::new();
+                push_client_id(&mut bytes, notice.client_id, version);
+                bytes.push(notice.reason as u8);
+                bytes
+            }
+            PacketPayload::SessionRedirect(redirect) => {
+                let mut bytes = redirect.session_id.to_le_bytes().to_vec();
+                bytes.push(redirect.relays.len() as u8);
+                for relay in &redirect.relays {
+                    bytes.extend(&relay.load.to_le_bytes());
+                    bytes.push(relay.addr.len() as u8);
+                    bytes.extend(relay.addr.as_bytes());
+                }
                 bytes
             }
             PacketPayload::ConnectDeny(deny) => {
-                deny.reason.as_bytes().to_vec()
+                let mut bytes = Vec::new();
+                if !is_v1(version) {
+                    bytes.extend(&deny.nonce.to_le_bytes());
+                }
+                bytes.extend(deny.reason.as_bytes());
+                bytes
             }
             PacketPayload::SessionConfig(config) => {
                 let mut bytes = vec![config.version];
                 bytes.extend(&config.tick_rate.to_le_bytes());
                 bytes.extend(&config.max_packet_size.to_le_bytes());
+                if !config.features.is_empty() {
+                    bytes.extend(&config.features.bits().to_le_bytes());
+                }
                 bytes
             }
             PacketPayload::PacketTypeRegistry(registry) => {
@@ -211,6 +387,13 @@ impl PacketPayload {
 
                 bytes
             }
+            PacketPayload::Chat(chat) => {
+                let name_bytes = &chat.sender_name.as_bytes()[..chat.sender_name.floor_char_boundary(u8::MAX as usize)];
+                let mut bytes = vec![name_bytes.len() as u8];
+                bytes.extend(name_bytes);
+                bytes.extend(chat.text.as_bytes());
+                bytes
+            }
             PacketPayload::Ack(ack) => {
                 let mut bytes = Vec::new();
                 bytes.push(ack.acknowledged_sequences.len() as u8);
@@ -223,129 +406,133 @@ impl PacketPayload {
         }
     }
 
-    pub fn from_bytes(packet_type: u8, data: &[u8]) -> Result<Self, Error> {
+    /// Decode a payload from a packet whose header carries `version`
+    pub fn from_bytes(packet_type: u8, data: &[u8], version: u8) -> Result<Self, Error> {
         match packet_type {
             x if x == CorePacketType::Ping as u8 => {
-                if data.len() < 8 {
-                    return Err(Error::new(ErrorKind::InvalidData, "Ping too short"));
-                }
-                let timestamp = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let timestamp = Decoder::new(data, "Ping").u64()?;
                 Ok(PacketPayload::Ping(Ping { timestamp }))
             }
             x if x == CorePacketType::Pong as u8 => {
-                if data.len() < 8 {
-                    return Err(Error::new(ErrorKind::InvalidData, "Pong too short"));
-                }
-                let timestamp = u64::from_le_bytes(data[0..8].try_into().unwrap());
-                Ok(PacketPayload::Pong(Pong {
-                    original_timestamp: timestamp,
-                }))
+                let original_timestamp = Decoder::new(data, "Pong").u64()?;
+                Ok(PacketPayload::Pong(Pong { original_timestamp }))
             }
             x if x == CorePacketType::ConnectRequest as u8 => {
-                if data.len() < 6 {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "ConnectRequest too short",
-                    ));
-                }
-                let client_version = data[0];
-                let target_session_id = u32::from_le_bytes(data[1..5].try_into().unwrap());
-                
-                let has_game_id = data[5];
-                let (game_identifier, name_start) = if has_game_id == 1 {
-                    if data.len() < 10 {
-                        return Err(Error::new(
-                            ErrorKind::InvalidData,
-                            "ConnectRequest with game_id too short",
-                        ));
-                    }
-                    (Some(u32::from_le_bytes(data[6..10].try_into().unwrap())), 10)
+                let mut decoder = Decoder::new(data, "ConnectRequest");
+                let version_byte = decoder.u8()?;
+                let client_version = version_byte & !SPECTATOR_FLAG;
+                let role = if version_byte & SPECTATOR_FLAG != 0 { PeerRole::Spectator } else { PeerRole::Player };
+                let target_session_id = decoder.u32()?;
+                let game_identifier = decoder.u32()?;
+                let (nonce, resume_id, resume_token) = if is_v1(version) {
+                    (0, 0, 0)
                 } else {
-                    (None, 6)
+                    (decoder.u32()?, decoder.u16()?, decoder.u64()?)
+                };
+                let rest = decoder.rest();
+                let (name, auth_token) = match rest.iter().position(|&b| b == 0) {
+                    Some(end) => (&rest[..end], rest[end + 1..].to_vec()),
+                    None => (rest, Vec::new()),
                 };
-                
-                let desired_name = String::from_utf8_lossy(&data[name_start..]).to_string();
                 Ok(PacketPayload::ConnectRequest(ConnectRequest {
                     client_version,
-                    desired_name,
+                    desired_name: String::from_utf8_lossy(name).into_owned(),
                     target_session_id,
                     game_identifier,
+                    nonce,
+                    resume_id,
+                    resume_token,
+                    auth_token,
+                    role,
                 }))
             }
             x if x == CorePacketType::ConnectAccept as u8 => {
-                if data.len() < 5 {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "ConnectAccept too short",
-                    ));
-                }
-                let client_id = data[0];
-                let session_id = u32::from_le_bytes(data[1..5].try_into().unwrap());
+                let mut decoder = Decoder::new(data, "ConnectAccept");
                 Ok(PacketPayload::ConnectAccept(ConnectAccept {
-                    assigned_client_id: client_id,
-                    session_id,
+                    assigned_client_id: read_client_id(&mut decoder, version)?,
+                    session_id: decoder.u32()?,
+                    // Older peers send no cookie, nonce, resume token, game identifier, join code, metadata or features
+                    cookie: if decoder.is_empty() { 0 } else { decoder.u64()? },
+                    nonce: if decoder.is_empty() { 0 } else { decoder.u32()? },
+                    resume_token: if decoder.is_empty() { 0 } else { decoder.u64()? },
+                    game_identifier: if decoder.is_empty() { 0 } else { decoder.u32()? },
+                    join_code: if decoder.is_empty() { String::new() } else { decoder.short_string()? },
+                    metadata: if decoder.is_empty() { SessionMetadata::default() } else { SessionMetadata::decode(&mut decoder)? },
+                    features: if decoder.is_empty() { FeatureSet::empty() } else { FeatureSet::from_bits_retain(decoder.u64()?) },
                 }))
             }
+            x if x == CorePacketType::Challenge as u8 => {
+                let cookie = Decoder::new(data, "Challenge").u64()?;
+                Ok(PacketPayload::Challenge(Challenge { cookie }))
+            }
+            x if x == CorePacketType::DisconnectNotice as u8 => {
+                let mut decoder = Decoder::new(data, "DisconnectNotice");
+                Ok(PacketPayload::DisconnectNotice(DisconnectNotice {
+                    client_id: read_client_id(&mut decoder, version)?,
+                    reason: DisconnectReason::from(decoder.u8()?),
+                }))
+            }
+            x if x == CorePacketType::SessionRedirect as u8 => {
+                let mut decoder = Decoder::new(data, "SessionRedirect");
+                let session_id = decoder.u32()?;
+                let count = decoder.u8()?;
+                let mut relays = Vec::new();
+                for _ in 0..count {
+                    let load = decoder.u32()?;
+                    relays.push(RelayInfo { addr: decoder.short_string()?, load });
+                }
+                Ok(PacketPayload::SessionRedirect(SessionRedirect { session_id, relays }))
+            }
             x if x == CorePacketType::ConnectDeny as u8 => {
-                let reason = String::from_utf8_lossy(data).to_string();
-                Ok(PacketPayload::ConnectDeny(ConnectDeny { reason }))
+                let mut decoder = Decoder::new(data, "ConnectDeny");
+                let nonce = if is_v1(version) { 0 } else { decoder.u32()? };
+                let reason = decoder.rest_string();
+                Ok(PacketPayload::ConnectDeny(ConnectDeny { reason, nonce }))
             }
             x if x == CorePacketType::SessionConfig as u8 => {
-                if data.len() < 5 {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "SessionConfig too short",
-                    ));
-                }
-                let version = data[0];
-                let tick_rate = u16::from_le_bytes([data[1], data[2]]);
-                let max_packet_size = u16::from_le_bytes([data[3], data[4]]);
+                let mut decoder = Decoder::new(data, "SessionConfig");
                 Ok(PacketPayload::SessionConfig(SessionConfig {
-                    version,
-                    tick_rate,
-                    max_packet_size,
+                    version: decoder.u8()?,
+                    tick_rate: decoder.u16()?,
+                    max_packet_size: decoder.u16()?,
+                    features: if decoder.is_empty() { FeatureSet::empty() } else { FeatureSet::from_bits_retain(decoder.u64()?) },
                 }))
             }
             x if x == CorePacketType::PacketTypeRegistry as u8 => {
-                if data.is_empty() {
-                    return Ok(PacketPayload::PacketTypeRegistry(PacketTypeRegistry { entries: vec![] }));
-                }
-                let mut offset = 0;
-                if offset >= data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                let entry_count = data[offset] as usize;
-                offset += 1;
+                let mut decoder = Decoder::new(data, "PacketTypeRegistry");
                 let mut entries = Vec::new();
-                for _ in 0..entry_count {
-                    if offset + 2 > data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                    let packet_id = data[offset]; offset += 1;
-                    let name_len = data[offset] as usize; offset += 1;
-                    if offset + name_len > data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                    let name = String::from_utf8_lossy(&data[offset..offset + name_len]).to_string(); offset += name_len;
-                    if offset >= data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                    let desc_len = data[offset] as usize; offset += 1;
-                    if offset + desc_len > data.len() { return Err(Error::new(ErrorKind::InvalidData, "PacketTypeRegistry malformed")); }
-                    let description = String::from_utf8_lossy(&data[offset..offset + desc_len]).to_string(); offset += desc_len;
-                    entries.push(PacketTypeEntry { packet_id, name, description });
+                if !decoder.is_empty() {
+                    let count = decoder.u8()?;
+                    for _ in 0..count {
+                        entries.push(PacketTypeEntry {
+                            packet_id: decoder.u8()?,
+                            name: decoder.short_string()?,
+                            description: decoder.short_string()?,
+                        });
+                    }
                 }
                 Ok(PacketPayload::PacketTypeRegistry(PacketTypeRegistry { entries }))
             }
+            x if x == CorePacketType::Chat as u8 => {
+                let mut decoder = Decoder::new(data, "Chat");
+                Ok(PacketPayload::Chat(Chat {
+                    sender_name: decoder.short_string()?,
+                    text: decoder.rest_string(),
+                }))
+            }
             x if x == CorePacketType::Ack as u8 => {
-                if data.is_empty() {
-                    return Ok(PacketPayload::Ack(Ack { acknowledged_sequences: vec![] }));
-                }
-                let count = data[0] as usize;
-                let mut sequences = Vec::new();
-                let mut offset = 1;
-                for _ in 0..count {
-                    if offset + 2 <= data.len() {
-                        sequences.push(u16::from_le_bytes([data[offset], data[offset + 1]]));
-                        offset += 2;
+                let mut decoder = Decoder::new(data, "Ack");
+                let mut acknowledged_sequences = Vec::new();
+                if !decoder.is_empty() {
+                    let count = decoder.u8()?;
+                    for _ in 0..count {
+                        acknowledged_sequences.push(decoder.u16()?);
                     }
                 }
-                Ok(PacketPayload::Ack(Ack { acknowledged_sequences: sequences }))
+                Ok(PacketPayload::Ack(Ack { acknowledged_sequences }))
             }
             x if x >= 0x10 => Ok(PacketPayload::GamePacket(data.to_vec())),
             _ => Ok(PacketPayload::None),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,1193 @@
+use std::collections::HashMap;
+#[cfg(feature = "admin-api")]
+use std::collections::VecDeque;
+use std::io::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use crate::auth::Authenticator;
+use crate::transport::Transport;
+use super::capture::Capture;
+use super::config::{ConnectLimits, GameFilter, SessionLimits};
+use super::cookie::CookieJar;
+use super::join_codes::JoinCodes;
+use super::logging::Logger;
+use super::socket::{NeonSocket, decode_packet};
+use super::session::{SessionKey, SessionManager, SessionStats};
+use super::state::SavedPeer;
+use super::throttle::ConnectThrottle;
+use super::types::*;
+use crate::join_code;
+use crate::name;
+use crate::protocol::{self, DisconnectReason, JOIN_CODE_ANSWER, JOIN_CODE_LOOKUP, MAX_CHAT_LENGTH, NAME_CHANGE, PROTOCOL_VERSION, PeerRole, SESSION_FULL, spectator_may_send};
+#[cfg(feature = "admin-api")]
+use super::admin::{AdminRequest, RelayEvent};
+
+/// How often dead connections are cleaned up, unless a session time limit needs checking sooner
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Least time between "not registered" notices to one address
+const NOTICE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sessions closed through the admin API can't be registered again for this long
+const CLOSED_SESSION_HOLD: Duration = Duration::from_secs(60);
+
+/// Reason given to clients asking to join a session the admin API closed
+const SESSION_CLOSED: &str = "Session was closed by the relay";
+
+/// Reason given to hosts registering a new session when the relay holds its maximum
+const RELAY_FULL: &str = "Relay is full";
+
+/// Reason given to hosts and clients of a game the relay doesn't serve
+fn game_not_served(game_identifier: u32) -> String {
+    format!("Game 0x{:08X} is not served by this relay", game_identifier)
+}
+
+/// Reason given to hosts registering with a join code another session holds
+fn join_code_taken(code: &str) -> String {
+    format!("Join code {} is taken", code.to_ascii_uppercase())
+}
+
+/// Most recent events a worker keeps for the dashboard
+#[cfg(feature = "admin-api")]
+const MAX_EVENTS: usize = 100;
+
+/// Datagrams handed from the receive thread to a worker
+pub type DatagramBatch = Vec<(Vec<u8>, SocketAddr)>;
+
+/// Sent to a worker by the receive thread or the admin API
+pub enum WorkerInput {
+    Datagrams(DatagramBatch),
+    #[cfg(feature = "admin-api")]
+    Admin(AdminRequest),
+    /// Answered with what a session forwarded, or None if the worker has no such session
+    SessionStats(SessionKey, Sender<Option<SessionStats>>),
+    /// The relay is stopping; drop every session and exit
+    Stop,
+}
+
+/// Sent from workers back to the receive thread
+pub enum WorkerFeedback {
+    /// A received buffer that can go back into the receive pool
+    Recycle(Vec<u8>),
+    /// A client registered under a game other than the one its registration named, so the receive
+    /// thread couldn't prove it and needs telling where to route it
+    Route(SocketAddr, u16, u32),
+    /// A peer address left the given session and no longer needs routing
+    Unroute(SocketAddr, u16, u32),
+    /// Every peer registered with the given shard, sent when they change if state is being saved
+    Snapshot(usize, Vec<SavedPeer>),
+    /// Sessions with a registered host on the given shard, sent when they change if the relay has peers
+    /// or session callbacks
+    Sessions(usize, Vec<u32>),
+    /// Something the relay's observability callbacks report, sent if any are set
+    Observed(Observation),
+}
+
+/// What a worker saw that the relay's observability callbacks report
+pub enum Observation {
+    ClientJoined(u32, u16, SocketAddr),
+    ClientTimedOut(u32, u16),
+    ForwardError(SocketAddr, Error),
+    PacketDropped(SocketAddr, DropReason),
+}
+
+/// Why the relay dropped a datagram, as passed to `on_packet_dropped`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// Not a Neon packet, or not one this relay can decode
+    Malformed,
+    /// Larger than the largest datagram the protocol allows
+    Oversized,
+    /// Sent from a banned address
+    Banned,
+    /// Its client_id isn't one the sending address registered with
+    Spoofed,
+    /// Sent from an address with no registration, e.g. from before a relay restart
+    Unregistered,
+    /// Addressed to a client that isn't in the sender's session
+    UnknownDestination,
+    /// Game traffic a spectator addressed to another client
+    Spectator,
+    /// A connection request past its address's rate, or one arriving while the relay holds as many
+    /// unanswered requests as its connect limits allow
+    Throttled,
+}
+
+/// Counters a worker publishes so the relay can report totals while running
+#[derive(Default)]
+pub struct WorkerStats {
+    pub sessions: AtomicUsize,
+    pub clients: AtomicUsize,
+    pub buffer_allocations: AtomicU64,
+    pub spoofed_packets: AtomicU64,
+    pub throttled_requests: AtomicU64,
+    pub malformed_packets: AtomicU64,
+    pub packets_relayed: AtomicU64,
+    pub bytes_relayed: AtomicU64,
+}
+
+/// Something that happened to a session, kept for the admin API's event log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Joined,
+    TimedOut,
+    /// The host closed the session, or its last client left or timed out, taking the host's registration with it
+    SessionEnded,
+    /// Closed through the admin API
+    #[cfg(feature = "admin-api")]
+    SessionClosed,
+    /// Dropped because its address was banned
+    #[cfg(feature = "admin-api")]
+    Banned,
+}
+
+/// Owns a shard of sessions and handles every packet routed to them
+pub struct RelayWorker<T: Transport> {
+    socket: NeonSocket<T>,
+    session_manager: SessionManager,
+    pending_connections: HashMap<u32, PendingConnection>,
+    connect_limits: ConnectLimits,
+    throttle: ConnectThrottle,
+    next_nonce: u32,
+    spoof_attempts: HashMap<SocketAddr, u64>,
+    notified: HashMap<SocketAddr, Instant>,
+    snapshot_shard: Option<usize>,
+    sessions_shard: Option<usize>,
+    observed: bool,
+    published_generation: u64,
+    /// Banned addresses with when the ban ends, if it does
+    banned: HashMap<IpAddr, Option<Instant>>,
+    /// Sessions closed through the admin API, with when
+    closed_sessions: HashMap<SessionKey, Instant>,
+    #[cfg(feature = "admin-api")]
+    events: VecDeque<RelayEvent>,
+    cookies: CookieJar,
+    authenticator: Option<Arc<dyn Authenticator>>,
+    capture: Option<Capture>,
+    limits: SessionLimits,
+    games: GameFilter,
+    /// Every worker's sessions' join codes
+    join_codes: Arc<Mutex<JoinCodes>>,
+    /// Every worker's stats, this one's included, for counting sessions relay-wide
+    shards: Vec<Arc<WorkerStats>>,
+    feedback: Sender<WorkerFeedback>,
+    stats: Arc<WorkerStats>,
+    log: Logger,
+}
+
+impl<T: Transport> RelayWorker<T> {
+    pub fn new(
+        socket: NeonSocket<T>,
+        cookies: CookieJar,
+        authenticator: Option<Arc<dyn Authenticator>>,
+        feedback: Sender<WorkerFeedback>,
+        stats: Arc<WorkerStats>,
+        log: Logger,
+    ) -> Self {
+        RelayWorker {
+            socket,
+            session_manager: SessionManager::new(log),
+            pending_connections: HashMap::new(),
+            connect_limits: ConnectLimits::default(),
+            throttle: ConnectThrottle::new(ConnectLimits::default().requests_per_second, ConnectLimits::default().burst),
+            // Hosts remember the nonces they admitted, so a restarted relay mustn't reissue them
+            next_nonce: rand::random(),
+            spoof_attempts: HashMap::new(),
+            notified: HashMap::new(),
+            snapshot_shard: None,
+            sessions_shard: None,
+            observed: false,
+            published_generation: 0,
+            banned: HashMap::new(),
+            closed_sessions: HashMap::new(),
+            #[cfg(feature = "admin-api")]
+            events: VecDeque::new(),
+            cookies,
+            authenticator,
+            capture: None,
+            limits: SessionLimits::default(),
+            games: GameFilter::Any,
+            join_codes: Arc::default(),
+            shards: Vec::new(),
+            feedback,
+            stats,
+            log,
+        }
+    }
+
+    /// Take over registrations saved before the relay restarted
+    pub fn restore(&mut self, peers: &[SavedPeer]) {
+        self.session_manager.restore(peers);
+        self.published_generation = self.session_manager.generation();
+    }
+
+    /// Report this worker's registrations as `shard` whenever they change, so they can be saved
+    pub fn publish_snapshots(&mut self, shard: usize) {
+        self.snapshot_shard = Some(shard);
+    }
+
+    /// Report the sessions this worker hosts as `shard` whenever they change, for peer relays and session callbacks
+    pub fn publish_sessions(&mut self, shard: usize) {
+        self.sessions_shard = Some(shard);
+    }
+
+    /// Report joins, timeouts, forward errors and dropped packets to the receive thread
+    pub fn publish_observations(&mut self) {
+        self.observed = true;
+    }
+
+    /// Check connection requests' auth tokens with `authenticator` before forwarding them
+    pub fn set_authenticator(&mut self, authenticator: Arc<dyn Authenticator>) {
+        self.authenticator = Some(authenticator);
+    }
+
+    /// Record the packets this worker forwards
+    pub fn capture_to(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    /// Deny and close sessions past `limits`, counting sessions across every worker in `shards`
+    pub fn enforce_limits(&mut self, limits: SessionLimits, shards: Vec<Arc<WorkerStats>>) {
+        self.limits = limits;
+        self.shards = shards;
+    }
+
+    /// Deny hosts and clients of games the filter doesn't accept
+    pub fn set_game_filter(&mut self, games: GameFilter) {
+        self.games = games;
+    }
+
+    /// Register and look up join codes in `join_codes`, shared with the relay's other workers
+    pub fn share_join_codes(&mut self, join_codes: Arc<Mutex<JoinCodes>>) {
+        self.join_codes = join_codes;
+    }
+
+    /// Cap pending connection requests and how fast each address may send them
+    pub fn set_connect_limits(&mut self, limits: ConnectLimits) {
+        self.throttle = ConnectThrottle::new(limits.requests_per_second, limits.burst);
+        self.connect_limits = limits;
+    }
+
+    /// Drop peers unheard from for this long, hosts along with their sessions
+    pub fn set_peer_timeout(&mut self, timeout: Duration) {
+        self.session_manager.set_peer_timeout(timeout);
+    }
+
+    /// Process batches until the receive thread hangs up
+    pub fn run(mut self, inbox: Receiver<WorkerInput>) -> Result<(), Error> {
+        let mut last_cleanup = Instant::now();
+        let cleanup_interval = self.cleanup_interval();
+
+        loop {
+            let until_cleanup = cleanup_interval.saturating_sub(last_cleanup.elapsed());
+            match inbox.recv_timeout(until_cleanup) {
+                Ok(WorkerInput::Datagrams(batch)) => {
+                    for (data, addr) in batch {
+                        // Malformed datagrams were already logged and counted; nothing else stops the shard
+                        if let Err(e) = self.handle_datagram(&data, addr)
+                            && !protocol::is_malformed(&e)
+                        {
+                            return Err(e);
+                        }
+                        let _ = self.feedback.send(WorkerFeedback::Recycle(data));
+                    }
+                    self.flush();
+                }
+                #[cfg(feature = "admin-api")]
+                Ok(WorkerInput::Admin(request)) => self.handle_admin(request),
+                Ok(WorkerInput::SessionStats(session, reply)) => {
+                    let stats = self.session_manager.resolve(session).and_then(|session| self.session_manager.session_stats(session));
+                    let _ = reply.send(stats);
+                }
+                Ok(WorkerInput::Stop) => return Ok(()),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            if last_cleanup.elapsed() >= cleanup_interval {
+                self.cleanup();
+                last_cleanup = Instant::now();
+            }
+
+            self.publish_stats();
+            self.publish_changes();
+        }
+    }
+
+    /// How often `cleanup` should run: often enough to drop peers and close sessions within a
+    /// quarter of their time limit
+    pub fn cleanup_interval(&self) -> Duration {
+        [Some(self.session_manager.peer_timeout()), Some(self.connect_limits.pending_timeout), self.limits.max_lifetime, self.limits.idle_timeout]
+            .into_iter()
+            .flatten()
+            .map(|limit| limit / 4)
+            .fold(CLEANUP_INTERVAL, Duration::min)
+    }
+
+    /// Forget stale requests and bans, close expired sessions and drop peers that timed out
+    pub fn cleanup(&mut self) {
+        self.expire_pending();
+        self.throttle.prune();
+        self.notified.retain(|_, at| at.elapsed() < NOTICE_INTERVAL);
+        self.banned.retain(|_, until| until.is_none_or(|until| until > Instant::now()));
+        self.closed_sessions.retain(|_, at| at.elapsed() < CLOSED_SESSION_HOLD);
+        // Sessions whose host went silent close as a whole, before their clients could time out one by one
+        let expired = self.session_manager.expired_sessions(self.limits.max_lifetime, self.limits.idle_timeout);
+        for (session, reason) in expired {
+            if let Some(peers) = self.close_session(session, reason) {
+                let name = match reason {
+                    DisconnectReason::Expired => "expired",
+                    DisconnectReason::Idle => "idle",
+                    _ => "host_timeout",
+                };
+                self.log.event(
+                    "session_destroyed",
+                    &[("session_id", session.session_id.into()), ("reason", name.into()), ("peers", peers.into())],
+                    format_args!("[Relay] Session {} {}, dropping {} peer(s)", session, reason, peers),
+                );
+                self.record(EventKind::SessionEnded, session.session_id, 0);
+            }
+        }
+        for (addr, session, client_id) in self.session_manager.cleanup_dead_connections() {
+            self.unroute(addr, client_id, session);
+            let kind = if client_id == 1 { EventKind::SessionEnded } else { EventKind::TimedOut };
+            self.record(kind, session.session_id, client_id);
+            if client_id != 1 {
+                self.observe(Observation::ClientTimedOut(session.session_id, client_id));
+            }
+            self.notify_disconnect(session, client_id, DisconnectReason::TimedOut);
+        }
+    }
+
+    /// Decode and handle one datagram. Anything it sends is queued until `flush`.
+    pub fn handle_datagram(&mut self, data: &[u8], addr: SocketAddr) -> Result<(), Error> {
+        let result = self.decode_and_handle(data, addr);
+        if let Err(e) = &result
+            && protocol::is_malformed(e)
+        {
+            self.drop_malformed(addr, e);
+        }
+        result
+    }
+
+    fn decode_and_handle(&mut self, data: &[u8], addr: SocketAddr) -> Result<(), Error> {
+        // Judged on the header alone, so a flood of requests is dropped before its payloads are decoded
+        let header = PacketHeader::from_bytes(data)?;
+        if header.packet_type == CorePacketType::ConnectRequest as u8 && !self.throttle.allow(addr) {
+            self.drop_throttled(addr);
+            return Ok(());
+        }
+        let packet = decode_packet(data)?;
+        self.handle_packet(packet, data, addr)
+    }
+
+    /// Send whatever handling datagrams queued
+    pub fn flush(&mut self) {
+        for (addr, e) in self.socket.flush() {
+            self.observe(Observation::ForwardError(addr, e));
+        }
+    }
+
+    /// Registrations this worker holds
+    pub fn sessions(&self) -> &SessionManager {
+        &self.session_manager
+    }
+
+    fn publish_changes(&mut self) {
+        let generation = self.session_manager.generation();
+        if generation == self.published_generation {
+            return;
+        }
+        self.published_generation = generation;
+        if let Some(shard) = self.snapshot_shard {
+            let _ = self.feedback.send(WorkerFeedback::Snapshot(shard, self.session_manager.saved_peers()));
+        }
+        if let Some(shard) = self.sessions_shard {
+            // Two games' sessions can share an ID; peer relays only redirect by ID
+            let mut sessions: Vec<u32> = self.session_manager.hosts.keys().map(|session| session.session_id).collect();
+            sessions.sort_unstable();
+            sessions.dedup();
+            let _ = self.feedback.send(WorkerFeedback::Sessions(shard, sessions));
+        }
+    }
+
+    fn observe(&self, observation: Observation) {
+        if self.observed {
+            let _ = self.feedback.send(WorkerFeedback::Observed(observation));
+        }
+    }
+
+    /// Forget everything about a client ID at an address that left `session`
+    fn unroute(&mut self, addr: SocketAddr, client_id: u16, session: SessionKey) {
+        if self.session_manager.peers_at(addr).is_empty() {
+            self.spoof_attempts.remove(&addr);
+        }
+        if client_id == 1 {
+            self.join_codes.lock().unwrap().release(session);
+        }
+        let _ = self.feedback.send(WorkerFeedback::Unroute(addr, client_id, session.session_id));
+    }
+
+    /// Add to the event log the admin API serves
+    #[cfg(feature = "admin-api")]
+    fn record(&mut self, kind: EventKind, session_id: u32, client_id: u16) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(RelayEvent { at: std::time::SystemTime::now(), kind, session_id, client_id });
+    }
+
+    #[cfg(not(feature = "admin-api"))]
+    fn record(&mut self, _kind: EventKind, _session_id: u32, _client_id: u16) {}
+
+    #[cfg(feature = "admin-api")]
+    fn handle_admin(&mut self, request: AdminRequest) {
+        match request {
+            AdminRequest::Sessions(reply) => {
+                let _ = reply.send(self.session_manager.summaries());
+            }
+            AdminRequest::Peers(session, reply) => {
+                let peers = self.session_manager.resolve(session).and_then(|session| self.session_manager.peer_summaries(session));
+                let _ = reply.send(peers);
+            }
+            AdminRequest::Events(reply) => {
+                let _ = reply.send(self.events.iter().cloned().collect());
+            }
+            AdminRequest::CloseSession(session, reply) => {
+                let session = self.session_manager.resolve(session).unwrap_or(session);
+                let removed = self.session_manager.remove_session(session);
+                if let Some(peers) = &removed {
+                    self.log.event(
+                        "session_destroyed",
+                        &[("session_id", session.session_id.into()), ("reason", "closed".into()), ("peers", peers.len().into())],
+                        format_args!("[Relay] Session {} closed through the admin API, dropping {} peer(s)", session, peers.len()),
+                    );
+                    self.closed_sessions.insert(session, Instant::now());
+                    self.pending_connections.retain(|_, pending| pending.session != session);
+                    for &(addr, client_id) in peers {
+                        self.unroute(addr, client_id, session);
+                    }
+                    self.record(EventKind::SessionClosed, session.session_id, 0);
+                }
+                let _ = reply.send(removed.map(|peers| peers.len()));
+            }
+            AdminRequest::Ban(ip, duration, reply) => {
+                // A ban too long to represent never ends
+                self.banned.insert(ip, duration.and_then(|duration| Instant::now().checked_add(duration)));
+                let removed = self.session_manager.remove_ip(ip);
+                for &(addr, session, client_id) in &removed {
+                    self.unroute(addr, client_id, session);
+                    self.record(EventKind::Banned, session.session_id, client_id);
+                    self.notify_disconnect(session, client_id, DisconnectReason::Banned);
+                }
+                let _ = reply.send(removed.len());
+            }
+        }
+    }
+
+    fn expire_pending(&mut self) {
+        let timeout = self.connect_limits.pending_timeout;
+        self.pending_connections.retain(|_, pending| pending.requested_at.elapsed() < timeout);
+    }
+
+    fn drop_throttled(&mut self, addr: SocketAddr) {
+        self.stats.throttled_requests.fetch_add(1, Ordering::Relaxed);
+        self.observe(Observation::PacketDropped(addr, DropReason::Throttled));
+    }
+
+    fn drop_malformed(&mut self, addr: SocketAddr, e: &Error) {
+        self.log.info(format_args!("[Relay] Dropped malformed datagram from {}: {}", addr, e));
+        self.stats.malformed_packets.fetch_add(1, Ordering::Relaxed);
+        self.observe(Observation::PacketDropped(addr, DropReason::Malformed));
+    }
+
+    fn is_banned(&self, addr: SocketAddr) -> bool {
+        self.banned
+            .get(&addr.ip())
+            .is_some_and(|until| until.is_none_or(|until| until > Instant::now()))
+    }
+
+    fn publish_stats(&self) {
+        let sessions = &self.session_manager.sessions;
+        self.stats.sessions.store(sessions.len(), Ordering::Relaxed);
+        self.stats.clients.store(sessions.values().map(|peers| peers.len()).sum(), Ordering::Relaxed);
+        self.stats.buffer_allocations.store(self.socket.buffer_allocations(), Ordering::Relaxed);
+    }
+
+    fn handle_packet(&mut self, packet: PacketRef, data: &[u8], addr: SocketAddr) -> Result<(), Error> {
+        if self.is_banned(addr) {
+            self.observe(Observation::PacketDropped(addr, DropReason::Banned));
+            return Ok(());
+        }
+        // Sent before joining, so from an address with no registration or one under another ID
+        if packet.packet_type == JOIN_CODE_LOOKUP && packet.destination_id == 0 {
+            return self.answer_join_code(&packet, addr);
+        }
+        if !self.sender_matches(&packet, addr) {
+            self.observe(Observation::PacketDropped(addr, DropReason::Spoofed));
+            return Ok(());
+        }
+        if packet.packet_type < 0x10 {
+            self.handle_core_packet(packet, data, addr)
+        } else {
+            self.handle_game_packet(packet, data, addr)
+        }
+    }
+
+    /// Check a packet's client_id against the IDs its address registered with, counting mismatches.
+    /// Handshake packets are exempt: a joining client has no ID yet and the host's answer names the
+    /// joining client rather than itself. Unregistered senders are dropped later when routed.
+    fn sender_matches(&mut self, packet: &PacketRef, addr: SocketAddr) -> bool {
+        if matches!(
+            packet.payload,
+            PayloadRef::ConnectRequest(_) | PayloadRef::ConnectAccept(_) | PayloadRef::ConnectDeny(_)
+        ) {
+            return true;
+        }
+        let registered = self.session_manager.peers_at(addr);
+        let Some(&(session, client_id)) = registered.first() else {
+            return true;
+        };
+        if registered.iter().any(|&(_, id)| id == packet.client_id) {
+            return true;
+        }
+
+        let attempts = self.spoof_attempts.entry(addr).or_insert(0);
+        *attempts += 1;
+        self.stats.spoofed_packets.fetch_add(1, Ordering::Relaxed);
+        self.log.event(
+            "spoofed_packet",
+            &[
+                ("session_id", session.session_id.into()),
+                ("client_id", client_id.into()),
+                ("claimed_client_id", packet.client_id.into()),
+                ("addr", addr.into()),
+            ],
+            format_args!(
+                "[Relay] Dropping packet from {} claiming to be client {} in session {} (registered as {}, {} mismatch(es))",
+                addr, packet.client_id, session, client_id, attempts
+            ),
+        );
+        false
+    }
+
+    fn handle_core_packet(&mut self, packet: PacketRef, data: &[u8], addr: SocketAddr) -> Result<(), Error> {
+        match packet.packet_type {
+            x if x == CorePacketType::ConnectRequest as u8 => {
+                if let PayloadRef::ConnectRequest(req) = packet.payload {
+                    self.handle_connect_request(req, addr, packet.sequence, packet.version)?;
+                }
+            }
+            x if x == CorePacketType::ConnectAccept as u8 => {
+                if let PayloadRef::ConnectAccept(accept) = packet.payload {
+                    // A client sharing its host's address registers with its cookie; the host's answers carry none
+                    if let Some(session) = self.session_manager.resolve(SessionKey::new(accept.game_identifier, accept.session_id))
+                        && self.session_manager.hosts.get(&session) == Some(&addr)
+                        && packet.client_id != 1
+                        && !self.cookies.verify(addr, session, packet.client_id, accept.cookie)
+                    {
+                        self.route_connect_accept_to_client(session, accept, packet.client_id, packet.sequence, packet.version)?;
+                        return Ok(());
+                    }
+
+                    self.handle_registration(accept, packet.client_id, addr, packet.sequence, packet.version)?;
+                }
+            }
+            x if x == CorePacketType::ConnectDeny as u8 => {
+                if let PayloadRef::ConnectDeny(deny) = packet.payload {
+                    self.handle_connect_deny(deny, addr, packet.sequence)?;
+                }
+            }
+            // Client 0 is the relay itself; hosts ping it to check they're still registered
+            x if x == CorePacketType::Ping as u8 && packet.destination_id == 0 => {
+                match self.session_manager.lookup_peer(addr, packet.client_id) {
+                    Some(session_id) => self.session_manager.update_client_activity(packet.client_id, session_id),
+                    None => self.notify_unregistered(&packet, addr)?,
+                }
+            }
+            // A host addressing the relay a DisconnectNotice is closing its session
+            x if x == CorePacketType::DisconnectNotice as u8 && packet.destination_id == 0 => {
+                if packet.client_id == 1
+                    && let Some(session) = self.session_manager.lookup_peer(addr, 1)
+                    && let Some(peers) = self.close_session(session, DisconnectReason::HostClosed)
+                {
+                    self.log.event(
+                        "session_destroyed",
+                        &[("session_id", session.session_id.into()), ("reason", "host_closed".into()), ("peers", peers.into())],
+                        format_args!("[Relay] Host closed session {}, dropping {} peer(s)", session, peers),
+                    );
+                    self.record(EventKind::SessionEnded, session.session_id, 0);
+                }
+            }
+            x if x == CorePacketType::Chat as u8 => {
+                if let PayloadRef::Chat(chat) = &packet.payload {
+                    self.handle_chat(&packet, chat.clone(), addr)?;
+                }
+            }
+            _ => {
+                if packet.packet_type == CorePacketType::Batch as u8 {
+                    if self.drop_spectator_traffic(&packet, addr) {
+                        return Ok(());
+                    }
+                    // The sender's ID was checked on the batch; its packets have to carry the same one
+                    if let Err(e) = protocol::decode(data).and_then(|batch| batch.check_batch()) {
+                        self.drop_malformed(addr, &e);
+                        return Ok(());
+                    }
+                }
+                self.forward_to_peers(&packet, data, addr)?;
+                if let Some(session_id) = self.session_manager.lookup_peer(addr, packet.client_id) {
+                    self.session_manager.update_client_activity(packet.client_id, session_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_game_packet(&mut self, packet: PacketRef, data: &[u8], addr: SocketAddr) -> Result<(), Error> {
+        if !spectator_may_send(packet.packet_type) && self.drop_spectator_traffic(&packet, addr) {
+            return Ok(());
+        }
+        if packet.packet_type == NAME_CHANGE && packet.client_id == 1 {
+            self.note_granted_name(&packet, data, addr);
+        }
+        self.forward_to_peers(&packet, data, addr)?;
+        if let Some(session_id) = self.session_manager.lookup_peer(addr, packet.client_id) {
+            self.session_manager.update_client_activity(packet.client_id, session_id);
+            self.session_manager.record_traffic(session_id);
+        }
+        Ok(())
+    }
+
+    /// Note the name a host gave one of its clients, for chat and the admin API, if this NAME_CHANGE grants one
+    fn note_granted_name(&mut self, packet: &PacketRef, data: &[u8], addr: SocketAddr) {
+        let Some(session) = self.session_manager.lookup_peer(addr, 1) else { return };
+        if self.session_manager.peer_addr(session, packet.destination_id).is_none() {
+            return;
+        }
+        if let Ok(protocol::NeonPacket { payload: protocol::PacketPayload::GamePacket(answer), .. }) = protocol::decode(data)
+            && let Ok(Ok(name)) = protocol::decode_name_answer(&answer)
+        {
+            self.log.info(format_args!("[Relay] Client {} in session {} is now '{}'", packet.destination_id, session, name));
+            self.session_manager.set_peer_name(session, packet.destination_id, name);
+        }
+    }
+
+    /// Drop a packet if it's a spectator's, addressed to another client rather than the host, returning
+    /// whether it was. The host decides what to do with what spectators send it.
+    fn drop_spectator_traffic(&mut self, packet: &PacketRef, addr: SocketAddr) -> bool {
+        if packet.destination_id == 1 {
+            return false;
+        }
+        let Some(session) = self.session_manager.lookup_peer(addr, packet.client_id) else { return false };
+        if self.session_manager.peer_role(session, packet.client_id) != PeerRole::Spectator {
+            return false;
+        }
+        self.log.info(format_args!(
+            "[Relay] Dropping packet type 0x{:02X} from spectator {} in session {} to client {}",
+            packet.packet_type, packet.client_id, session, packet.destination_id
+        ));
+        self.observe(Observation::PacketDropped(addr, DropReason::Spectator));
+        true
+    }
+
+    fn handle_connect_request(
+        &mut self,
+        mut req: ConnectRequest,
+        client_addr: SocketAddr,
+        sequence: u16,
+        version: u8,
+    ) -> Result<(), Error> {
+        let target_session = req.target_session_id;
+        req.desired_name = name::strip(&req.desired_name);
+        // A client that names no game joins the only session with its ID, whatever the host's game
+        let requested = SessionKey::new(req.game_identifier, target_session);
+        let session = self.session_manager.resolve(requested).unwrap_or(requested);
+
+        let game_id = if req.game_identifier != 0 { format!(" (game ID 0x{:08X})", req.game_identifier) } else { String::new() };
+        self.log.event(
+            "connect",
+            &[
+                ("session_id", target_session.into()),
+                ("name", req.desired_name.as_str().into()),
+                ("addr", client_addr.into()),
+                ("game_id", req.game_identifier.into()),
+            ],
+            format_args!(
+                "[Relay] Client '{}' from {} requesting to join session {}{}",
+                req.desired_name, client_addr, target_session, game_id
+            ),
+        );
+
+        let rejection = if let Err(e) = name::check(&req.desired_name) {
+            Some(e.to_string())
+        } else if !self.games.accepts(req.game_identifier) {
+            Some(game_not_served(req.game_identifier))
+        } else if self.closed_sessions.contains_key(&session) {
+            Some(SESSION_CLOSED.to_string())
+        } else if self.limits.max_clients_per_session.is_some_and(|max| {
+            self.session_manager.client_count(session) >= max
+                && !self.session_manager.peers_at(client_addr).iter().any(|&(joined, client_id)| joined == session && client_id != 1)
+        }) {
+            Some(SESSION_FULL.to_string())
+        } else if let Some(authenticator) = &self.authenticator {
+            authenticator.authenticate(&req.desired_name, target_session, &req.auth_token).err()
+        } else {
+            None
+        };
+        if let Some(reason) = rejection {
+            self.log.event(
+                "deny",
+                &[
+                    ("session_id", target_session.into()),
+                    ("name", req.desired_name.as_str().into()),
+                    ("addr", client_addr.into()),
+                    ("reason", reason.as_str().into()),
+                ],
+                format_args!("[Relay] Rejected '{}' from {}: {}", req.desired_name, client_addr, reason),
+            );
+            let deny_packet = NeonPacket {
+                packet_type: CorePacketType::ConnectDeny as u8,
+                sequence,
+                client_id: 0,
+                destination_id: 0,
+                payload: PacketPayload::ConnectDeny(ConnectDeny { reason, nonce: 0 }),
+            };
+            return self.socket.send_packet(&deny_packet, version, client_addr);
+        }
+
+        if let Some(host_addr) = self.session_manager.hosts.get(&session).copied() {
+            self.log.info(format_args!("[Relay] Forwarding connection request to host at {}", host_addr));
+
+            // A retry keeps its first request's nonce, so the host can tell it's the same request;
+            // one for a different session replaces it
+            let retried = self.pending_connections.iter()
+                .find(|(_, pending)| pending.client_addr == client_addr && pending.session == session)
+                .map(|(&nonce, _)| nonce);
+            req.nonce = match retried {
+                Some(nonce) => nonce,
+                None => {
+                    self.pending_connections.retain(|_, pending| pending.client_addr != client_addr);
+                    if self.pending_connections.len() >= self.connect_limits.max_pending {
+                        self.expire_pending();
+                    }
+                    if self.pending_connections.len() >= self.connect_limits.max_pending {
+                        self.log.info(format_args!(
+                            "[Relay] Dropping connection request from {}: {} requests already await an answer",
+                            client_addr, self.pending_connections.len()
+                        ));
+                        self.drop_throttled(client_addr);
+                        return Ok(());
+                    }
+                    self.next_nonce = self.next_nonce.wrapping_add(1).max(1);
+                    self.next_nonce
+                }
+            };
+            self.pending_connections.insert(
+                req.nonce,
+                PendingConnection {
+                    client_addr,
+                    session,
+                    client_name: req.desired_name.clone(),
+                    role: req.role,
+                    version,
+                    requested_at: Instant::now(),
+                },
+            );
+
+            let forward_packet = NeonPacket {
+                packet_type: CorePacketType::ConnectRequest as u8,
+                sequence,
+                client_id: 0,
+                destination_id: 1,
+                payload: PacketPayload::ConnectRequest(req),
+            };
+
+            // The nonce needs a version 2 header; the host answers the client at whatever it negotiates
+            self.socket.send_packet(&forward_packet, PROTOCOL_VERSION, host_addr)?;
+
+            // Lets the client tell a silent host from an unreachable relay
+            let ack_packet = NeonPacket {
+                packet_type: CorePacketType::Ack as u8,
+                sequence,
+                client_id: 0,
+                destination_id: 0,
+                payload: PacketPayload::Ack(Ack { acknowledged_sequences: vec![sequence] }),
+            };
+            self.socket.send_packet(&ack_packet, version, client_addr)?;
+        } else {
+            self.log.event(
+                "session_not_found",
+                &[("session_id", target_session.into()), ("addr", client_addr.into())],
+                format_args!("[Relay] Session {} not found (no host registered)", requested),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Take the pending request a host's answer refers to, if it was for that host's session
+    fn take_pending(&mut self, nonce: u32, session: SessionKey) -> Option<PendingConnection> {
+        match self.pending_connections.get(&nonce) {
+            Some(pending) if pending.session == session => self.pending_connections.remove(&nonce),
+            _ => None,
+        }
+    }
+
+    /// Register a host (client ID 1) or client whose ConnectAccept echoes a valid cookie.
+    /// A host without one is challenged for it; a client already got its cookie in the
+    /// routed ConnectAccept, so a client without one is ignored.
+    fn handle_registration(
+        &mut self,
+        accept: ConnectAccept,
+        client_id: u16,
+        addr: SocketAddr,
+        sequence: u16,
+        version: u8,
+    ) -> Result<(), Error> {
+        // Hosts register the session they name; clients that predate game identifiers echo without one
+        let named = SessionKey::new(accept.game_identifier, accept.session_id);
+        let session = if client_id == 1 { named } else { self.session_manager.resolve(named).unwrap_or(named) };
+        if self.closed_sessions.contains_key(&session) {
+            self.log.info(format_args!(
+                "[Relay] Ignoring registration for client {} in closed session {} from {}",
+                client_id, session, addr
+            ));
+        } else if client_id == 1 && !self.games.accepts(accept.game_identifier) {
+            self.deny_registration(session, addr, game_not_served(accept.game_identifier), sequence, version)?;
+        } else if client_id == 1 && !self.session_manager.sessions.contains_key(&session) && self.at_session_limit() {
+            self.deny_registration(session, addr, RELAY_FULL.to_string(), sequence, version)?;
+        } else if client_id == 1 && !self.join_codes.lock().unwrap().available(&accept.join_code, session) {
+            self.deny_registration(session, addr, join_code_taken(&accept.join_code), sequence, version)?;
+        } else if self.cookies.verify(addr, session, client_id, accept.cookie) {
+            // Checked again on the proven registration, since another host could have claimed it since
+            if client_id == 1 && !self.join_codes.lock().unwrap().claim(&accept.join_code, session) {
+                return self.deny_registration(session, addr, join_code_taken(&accept.join_code), sequence, version);
+            }
+            // Registrations are repeated, e.g. by hosts re-registering after a relay restart
+            if self.session_manager.lookup_peer(addr, client_id) != Some(session) {
+                self.record(EventKind::Joined, session.session_id, client_id);
+                if client_id != 1 {
+                    self.observe(Observation::ClientJoined(session.session_id, client_id, addr));
+                }
+            }
+            if client_id == 1 {
+                self.session_manager.register_host(session, addr);
+                self.session_manager.set_metadata(session, accept.metadata);
+            } else {
+                self.session_manager.register_client(session, client_id, addr);
+                if session != named {
+                    let _ = self.feedback.send(WorkerFeedback::Route(addr, client_id, session.session_id));
+                }
+                self.pending_connections.retain(|_, pending| pending.client_addr != addr);
+
+                // Tells the host the client can be reached, so it can send the session config
+                if let Some(host_addr) = self.session_manager.hosts.get(&session) {
+                    let ready_packet = NeonPacket {
+                        packet_type: CorePacketType::ConnectAccept as u8,
+                        sequence,
+                        client_id,
+                        destination_id: 1,
+                        payload: PacketPayload::ConnectAccept(accept),
+                    };
+                    self.socket.send_packet(&ready_packet, PROTOCOL_VERSION, *host_addr)?;
+                }
+            }
+        } else if client_id == 1 {
+            let challenge_packet = NeonPacket {
+                packet_type: CorePacketType::Challenge as u8,
+                sequence,
+                client_id: 0,
+                destination_id: 1,
+                payload: PacketPayload::Challenge(Challenge {
+                    cookie: self.cookies.issue(addr, session, 1),
+                }),
+            };
+            self.socket.send_packet(&challenge_packet, version, addr)?;
+        } else {
+            self.log.info(format_args!(
+                "[Relay] Ignoring registration for client {} in session {} from {} without a valid cookie",
+                client_id, session, addr
+            ));
+        }
+        Ok(())
+    }
+
+    /// Refuse a host's registration, telling it why
+    fn deny_registration(&mut self, session: SessionKey, addr: SocketAddr, reason: String, sequence: u16, version: u8) -> Result<(), Error> {
+        self.log.event(
+            "deny",
+            &[("session_id", session.session_id.into()), ("addr", addr.into()), ("reason", reason.as_str().into())],
+            format_args!("[Relay] Refusing session {} from {}: {}", session, addr, reason),
+        );
+        let deny_packet = NeonPacket {
+            packet_type: CorePacketType::ConnectDeny as u8,
+            sequence,
+            client_id: 0,
+            destination_id: 1,
+            payload: PacketPayload::ConnectDeny(ConnectDeny { reason, nonce: 0 }),
+        };
+        self.socket.send_packet(&deny_packet, version, addr)
+    }
+
+    /// Tell a client which session has the join code it asked about, if any
+    fn answer_join_code(&mut self, packet: &PacketRef, addr: SocketAddr) -> Result<(), Error> {
+        let PayloadRef::Raw(code) = packet.payload else { return Ok(()) };
+        let code = String::from_utf8_lossy(code).to_ascii_uppercase();
+        let session = self.join_codes.lock().unwrap().find(&code);
+        match session {
+            Some(session) => self.log.info(format_args!("[Relay] {} looked up join code {}: session {}", addr, code, session)),
+            None => self.log.info(format_args!("[Relay] {} looked up unknown join code {}", addr, code)),
+        }
+        let answer = NeonPacket {
+            packet_type: JOIN_CODE_ANSWER,
+            sequence: packet.sequence,
+            client_id: 0,
+            destination_id: 0,
+            payload: PacketPayload::GamePacket(join_code::encode_answer(&code, session)),
+        };
+        self.socket.send_packet(&answer, packet.version, addr)
+    }
+
+    /// Whether the relay already holds its maximum number of sessions, counting every worker's
+    fn at_session_limit(&self) -> bool {
+        let Some(max) = self.limits.max_sessions else { return false };
+        let elsewhere: usize = self
+            .shards
+            .iter()
+            .filter(|stats| !Arc::ptr_eq(stats, &self.stats))
+            .map(|stats| stats.sessions.load(Ordering::Relaxed))
+            .sum();
+        elsewhere + self.session_manager.sessions.len() >= max
+    }
+
+    fn handle_connect_deny(
+        &mut self,
+        deny: ConnectDeny,
+        host_addr: SocketAddr,
+        sequence: u16,
+    ) -> Result<(), Error> {
+        let pending = match self.session_manager.lookup_peer(host_addr, 1) {
+            Some(session) => self.take_pending(deny.nonce, session),
+            None => None,
+        };
+        
+        if let Some(pending) = pending {
+            self.log.event(
+                "deny",
+                &[
+                    ("session_id", pending.session.session_id.into()),
+                    ("name", pending.client_name.as_str().into()),
+                    ("addr", pending.client_addr.into()),
+                    ("reason", deny.reason.as_str().into()),
+                ],
+                format_args!("[Relay] Routing ConnectDeny back to {}", pending.client_addr),
+            );
+            
+            let deny_packet = NeonPacket {
+                packet_type: CorePacketType::ConnectDeny as u8,
+                sequence,
+                client_id: 0,
+                destination_id: 0,
+                payload: PacketPayload::ConnectDeny(deny),
+            };
+            
+            self.socket.send_packet(&deny_packet, pending.version, pending.client_addr)?;
+        } else {
+            self.log.info(format_args!("[Relay] No pending connection found for ConnectDeny"));
+        }
+        
+        Ok(())
+    }
+
+    fn route_connect_accept_to_client(
+        &mut self,
+        session: SessionKey,
+        mut accept: ConnectAccept,
+        client_id: u16,
+        sequence: u16,
+        version: u8,
+    ) -> Result<(), Error> {
+        // Kept until the client registers, in case the accept is lost and the client asks again
+        let pending = self.pending_connections.get(&accept.nonce).filter(|pending| pending.session == session).cloned();
+        if let Some(pending) = pending {
+            self.log.event(
+                "accept",
+                &[
+                    ("session_id", accept.session_id.into()),
+                    ("client_id", client_id.into()),
+                    ("name", pending.client_name.as_str().into()),
+                    ("addr", pending.client_addr.into()),
+                ],
+                format_args!("[Relay] Routing ConnectAccept for client {} back to {}", client_id, pending.client_addr),
+            );
+            self.session_manager.set_peer_name(session, client_id, pending.client_name);
+            self.session_manager.set_peer_role(session, client_id, pending.role);
+
+            accept.cookie = self.cookies.issue(pending.client_addr, session, client_id);
+            let response_packet = NeonPacket {
+                packet_type: CorePacketType::ConnectAccept as u8,
+                sequence,
+                client_id,
+                destination_id: client_id,
+                payload: PacketPayload::ConnectAccept(accept),
+            };
+
+            self.socket.send_packet(&response_packet, version, pending.client_addr)?;
+        } else {
+            self.log.info(format_args!("[Relay] No pending connection found for ConnectAccept"));
+        }
+
+        Ok(())
+    }
+
+    /// Stamp the sender's accepted name onto a chat message and deliver it,
+    /// to every other peer in the session when the destination is 0
+    fn handle_chat(&mut self, packet: &PacketRef, mut chat: Chat, sender_addr: SocketAddr) -> Result<(), Error> {
+        let sender_id = packet.client_id;
+        let Some(session_id) = self.session_manager.lookup_peer(sender_addr, sender_id) else {
+            self.log.info(format_args!("[Relay] Unknown sender: {}, dropping chat", sender_addr));
+            return self.notify_unregistered(packet, sender_addr);
+        };
+        if chat.text.len() > MAX_CHAT_LENGTH {
+            self.log.info(format_args!(
+                "[Relay] Dropping oversized chat message from client {} in session {}",
+                sender_id, session_id
+            ));
+            return Ok(());
+        }
+        if let Some(name) = self.session_manager.peer_name(session_id, sender_id) {
+            chat.sender_name = name.to_string();
+        }
+        self.session_manager.update_client_activity(sender_id, session_id);
+
+        let recipients = if packet.destination_id == 0 {
+            self.session_manager.other_peers(session_id, sender_id)
+        } else {
+            self.session_manager
+                .peer_addr(session_id, packet.destination_id)
+                .map(|addr| vec![(packet.destination_id, addr)])
+                .unwrap_or_default()
+        };
+
+        for (destination_id, addr) in recipients {
+            let chat_packet = NeonPacket {
+                packet_type: CorePacketType::Chat as u8,
+                sequence: packet.sequence,
+                client_id: sender_id,
+                destination_id,
+                payload: PacketPayload::Chat(chat.clone()),
+            };
+            self.socket.send_packet(&chat_packet, packet.version, addr)?;
+        }
+
+        Ok(())
+    }
+
+    fn forward_to_peers(&mut self, packet: &PacketRef, data: &[u8], sender_addr: SocketAddr) -> Result<(), Error> {
+        let Some(session_id) = self.session_manager.lookup_peer(sender_addr, packet.client_id) else {
+            self.log.info(format_args!("[Relay] Unknown sender: {}, dropping packet", sender_addr));
+            return self.notify_unregistered(packet, sender_addr);
+        };
+
+        match self.session_manager.peer_addr(session_id, packet.destination_id) {
+            Some(dest_addr) => {
+                if packet.destination_id != packet.client_id {
+                    self.socket.queue_raw(data, dest_addr);
+                    self.stats.packets_relayed.fetch_add(1, Ordering::Relaxed);
+                    self.stats.bytes_relayed.fetch_add(data.len() as u64, Ordering::Relaxed);
+                    self.session_manager.record_forwarded(session_id, packet.client_id, packet.destination_id, data.len());
+                    self.capture(session_id, sender_addr, dest_addr, data);
+                }
+            }
+            None => {
+                self.log.info(format_args!(
+                    "[Relay] Destination client {} not found in session {}, dropping packet from {}",
+                    packet.destination_id, session_id, sender_addr
+                ));
+                self.log.info(format_args!("{:?}", packet));
+                self.observe(Observation::PacketDropped(sender_addr, DropReason::UnknownDestination));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn capture(&mut self, session: SessionKey, from: SocketAddr, to: SocketAddr, data: &[u8]) {
+        let Some(capture) = &self.capture else { return };
+        if !capture.wants(session.session_id) {
+            return;
+        }
+        if let Err(e) = capture.record(from, to, data) {
+            // Likely a full disk; keep relaying rather than failing on every packet
+            self.log.info(format_args!("[Relay] Stopping packet capture: {}", e));
+            self.capture = None;
+        }
+    }
+
+    /// Tell a session's host that the relay dropped one of its clients, so it can free the slot and
+    /// update everyone's peer list. Nobody is told when the host itself goes.
+    fn notify_disconnect(&mut self, session: SessionKey, client_id: u16, reason: DisconnectReason) {
+        if client_id == 1 {
+            return;
+        }
+        let Some(&host_addr) = self.session_manager.hosts.get(&session) else { return };
+        let notice = NeonPacket {
+            packet_type: CorePacketType::DisconnectNotice as u8,
+            sequence: 0,
+            client_id: 0,
+            destination_id: 1,
+            payload: PacketPayload::DisconnectNotice(DisconnectNotice { client_id, reason }),
+        };
+        if let Err(e) = self.socket.send_packet(&notice, PROTOCOL_VERSION, host_addr) {
+            self.log.info(format_args!("[Relay] Failed to tell session {}'s host client {} left: {}", session, client_id, e));
+        }
+    }
+
+    /// Drop a session and tell each of its peers why, returning how many it had
+    fn close_session(&mut self, session: SessionKey, reason: DisconnectReason) -> Option<usize> {
+        let peers = self.session_manager.remove_session(session)?;
+        self.pending_connections.retain(|_, pending| pending.session != session);
+        for &(addr, client_id) in &peers {
+            self.unroute(addr, client_id, session);
+            // A host that closed its own session already knows
+            if client_id == 1 && reason == DisconnectReason::HostClosed {
+                continue;
+            }
+            let notice = NeonPacket {
+                packet_type: CorePacketType::DisconnectNotice as u8,
+                sequence: 0,
+                client_id: 0,
+                destination_id: client_id,
+                payload: PacketPayload::DisconnectNotice(DisconnectNotice { client_id: 1, reason }),
+            };
+            if let Err(e) = self.socket.send_packet(&notice, PROTOCOL_VERSION, addr) {
+                self.log.info(format_args!("[Relay] Failed to tell client {} session {} {}: {}", client_id, session, reason, e));
+            }
+        }
+        Some(peers.len())
+    }
+
+    /// Tell a sender the relay has no registration for its address, e.g. because the relay restarted,
+    /// with a Challenge carrying no cookie. Hosts register again and clients ask to resume.
+    fn notify_unregistered(&mut self, packet: &PacketRef, addr: SocketAddr) -> Result<(), Error> {
+        self.observe(Observation::PacketDropped(addr, DropReason::Unregistered));
+        if self.notified.get(&addr).is_some_and(|at| at.elapsed() < NOTICE_INTERVAL) {
+            return Ok(());
+        }
+        self.notified.insert(addr, Instant::now());
+
+        let notice = NeonPacket {
+            packet_type: CorePacketType::Challenge as u8,
+            sequence: 0,
+            client_id: 0,
+            destination_id: packet.client_id,
+            payload: PacketPayload::Challenge(Challenge { cookie: 0 }),
+        };
+        self.socket.send_packet(&notice, packet.version, addr)
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+use crate::decoder::Decoder;
+use crate::protocol::HEADER_SIZE;
+
+/// Committed states kept per side, so deltas can be built against anything acked this recently
+const HISTORY_LENGTH: usize = 32;
+
+/// Incomplete multi-part snapshots a client keeps assembling at once
+const MAX_PENDING_SNAPSHOTS: usize = 8;
+
+/// Datagram size snapshot parts are split to fit
+const MAX_DATAGRAM_SIZE: usize = 1024;
+
+/// tick, base tick, flags, part, parts, update count, removal count
+const PART_HEADER_SIZE: usize = 8 + 8 + 1 + 1 + 1 + 2 + 2;
+
+/// Room for entity records in one snapshot part
+const MAX_PART_BODY: usize = MAX_DATAGRAM_SIZE - HEADER_SIZE - PART_HEADER_SIZE;
+
+/// Largest entity blob, so any single entity fits in one part (id u32 and length u16 precede it)
+pub const MAX_ENTITY_SIZE: usize = MAX_PART_BODY - 6;
+
+/// Part flag: the snapshot holds every entity rather than a delta
+const FLAG_FULL: u8 = 0x01;
+
+type EntityState = BTreeMap<u32, Arc<[u8]>>;
+
+/// Host side of replication: the current entity state, recent committed states,
+/// and the newest tick each client has acknowledged
+#[derive(Default)]
+pub struct ReplicationServer {
+    entities: EntityState,
+    history: VecDeque<(u64, EntityState)>,
+    acked: HashMap<u16, u64>,
+}
+
+impl ReplicationServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert or replace an entity's state
+    pub fn set(&mut self, entity_id: u32, data: &[u8]) -> Result<(), Error> {
+        if data.len() > MAX_ENTITY_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Entity state is limited to {} bytes", MAX_ENTITY_SIZE),
+            ));
+        }
+        if self.entities.get(&entity_id).is_none_or(|old| **old != *data) {
+            self.entities.insert(entity_id, data.into());
+        }
+        Ok(())
+    }
+
+    /// Remove an entity. Returns false if it didn't exist.
+    pub fn remove(&mut self, entity_id: u32) -> bool {
+        self.entities.remove(&entity_id).is_some()
+    }
+
+    pub fn get(&self, entity_id: u32) -> Option<&[u8]> {
+        self.entities.get(&entity_id).map(|data| &**data)
+    }
+
+    /// Number of entities currently replicated
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Most recently committed tick
+    pub fn tick(&self) -> Option<u64> {
+        self.history.back().map(|(tick, _)| *tick)
+    }
+
+    /// Record the current state as `tick`; ticks must increase
+    pub fn commit(&mut self, tick: u64) -> Result<(), Error> {
+        if self.tick().is_some_and(|last| tick <= last) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Tick {} is not after the last replicated tick", tick),
+            ));
+        }
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back((tick, self.entities.clone()));
+        Ok(())
+    }
+
+    /// Note that a client has applied the snapshot for `tick`
+    pub fn ack(&mut self, client_id: u16, tick: u64) {
+        let acked = self.acked.entry(client_id).or_insert(tick);
+        *acked = (*acked).max(tick);
+    }
+
+    /// Newest tick a client has acknowledged
+    pub fn acked_tick(&self, client_id: u16) -> Option<u64> {
+        self.acked.get(&client_id).copied()
+    }
+
+    /// Drop a client's acknowledgement so a returning ID starts from a full snapshot
+    pub fn forget(&mut self, client_id: u16) {
+        self.acked.remove(&client_id);
+    }
+
+    /// Encode the last committed tick for one client: a delta against the newest state it
+    /// acknowledged, or a full snapshot if it hasn't acknowledged one still in history.
+    /// Returns the payloads of each part, in order.
+    pub fn snapshot_for(&self, client_id: u16) -> Result<Vec<Vec<u8>>, Error> {
+        let Some((tick, state)) = self.history.back() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "No tick has been committed"));
+        };
+        let base = self
+            .acked_tick(client_id)
+            .and_then(|acked| self.history.iter().find(|(t, _)| *t == acked));
+
+        let empty = EntityState::new();
+        let (base_tick, base_state, flags) = match base {
+            Some((base_tick, base_state)) => (*base_tick, base_state, 0),
+            None => (0, &empty, FLAG_FULL),
+        };
+
+        let updates: Vec<(u32, &[u8])> = state
+            .iter()
+            .filter(|(id, data)| base_state.get(id).is_none_or(|old| !Arc::ptr_eq(old, data) && old != *data))
+            .map(|(id, data)| (*id, &**data))
+            .collect();
+        let removals: Vec<u32> = base_state.keys().filter(|id| !state.contains_key(id)).copied().collect();
+
+        encode_parts(*tick, base_tick, flags, &updates, &removals)
+    }
+}
+
+/// Records for one snapshot part
+#[derive(Default)]
+struct PartBody<'a> {
+    updates: Vec<(u32, &'a [u8])>,
+    removals: Vec<u32>,
+    size: usize,
+}
+
+fn encode_parts(tick: u64, base_tick: u64, flags: u8, updates: &[(u32, &[u8])], removals: &[u32]) -> Result<Vec<Vec<u8>>, Error> {
+    let mut bodies = vec![PartBody::default()];
+    for &(id, data) in updates {
+        let size = 6 + data.len();
+        let mut body = bodies.last_mut().unwrap();
+        if body.size + size > MAX_PART_BODY {
+            bodies.push(PartBody::default());
+            body = bodies.last_mut().unwrap();
+        }
+        body.updates.push((id, data));
+        body.size += size;
+    }
+    for &id in removals {
+        let mut body = bodies.last_mut().unwrap();
+        if body.size + 4 > MAX_PART_BODY {
+            bodies.push(PartBody::default());
+            body = bodies.last_mut().unwrap();
+        }
+        body.removals.push(id);
+        body.size += 4;
+    }
+
+    if bodies.len() > u8::MAX as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, "Snapshot needs more than 255 parts"));
+    }
+    let parts = bodies.len() as u8;
+
+    Ok(bodies
+        .into_iter()
+        .enumerate()
+        .map(|(part, body)| {
+            let mut bytes = Vec::with_capacity(PART_HEADER_SIZE + body.size);
+            bytes.extend_from_slice(&tick.to_le_bytes());
+            bytes.extend_from_slice(&base_tick.to_le_bytes());
+            bytes.push(flags);
+            bytes.push(part as u8);
+            bytes.push(parts);
+            bytes.extend_from_slice(&(body.updates.len() as u16).to_le_bytes());
+            for (id, data) in body.updates {
+                bytes.extend_from_slice(&id.to_le_bytes());
+                bytes.extend_from_slice(&(data.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(data);
+            }
+            bytes.extend_from_slice(&(body.removals.len() as u16).to_le_bytes());
+            for id in body.removals {
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+            bytes
+        })
+        .collect())
+}
+
+/// Payload of a replication acknowledgement
+pub fn encode_ack(tick: u64) -> Vec<u8> {
+    tick.to_le_bytes().to_vec()
+}
+
+pub fn decode_ack(data: &[u8]) -> Result<u64, Error> {
+    Decoder::new(data, "Replication ack").u64()
+}
+
+/// One decoded snapshot part
+struct Part {
+    tick: u64,
+    base_tick: u64,
+    full: bool,
+    part: u8,
+    parts: u8,
+    updates: Vec<(u32, Vec<u8>)>,
+    removals: Vec<u32>,
+}
+
+impl Part {
+    fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut decoder = Decoder::new(data, "Replication snapshot");
+        let tick = decoder.u64()?;
+        let base_tick = decoder.u64()?;
+        let flags = decoder.u8()?;
+        let part = decoder.u8()?;
+        let parts = decoder.u8()?;
+        if part >= parts {
+            return Err(decoder.malformed());
+        }
+
+        let update_count = decoder.u16()?;
+        let mut updates = Vec::with_capacity(update_count as usize);
+        for _ in 0..update_count {
+            let id = decoder.u32()?;
+            let len = decoder.u16()? as usize;
+            updates.push((id, decoder.bytes(len)?.to_vec()));
+        }
+        let removal_count = decoder.u16()?;
+        let mut removals = Vec::with_capacity(removal_count as usize);
+        for _ in 0..removal_count {
+            removals.push(decoder.u32()?);
+        }
+
+        Ok(Self { tick, base_tick, full: flags & FLAG_FULL != 0, part, parts, updates, removals })
+    }
+}
+
+/// A snapshot whose parts are still arriving
+struct PendingSnapshot {
+    base_tick: u64,
+    full: bool,
+    parts: Vec<Option<Part>>,
+}
+
+/// Client side of replication: reassembles snapshot parts and applies them in tick order.
+/// Snapshots older than the applied state, or deltas against a state this client never had,
+/// are dropped; the host keeps sending deltas against the last acknowledged tick until one lands.
+#[derive(Default)]
+pub struct ReplicationClient {
+    entities: HashMap<u32, Vec<u8>>,
+    history: VecDeque<(u64, HashMap<u32, Vec<u8>>)>,
+    pending: BTreeMap<u64, PendingSnapshot>,
+}
+
+impl ReplicationClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Entity state as of the last applied snapshot
+    pub fn entities(&self) -> &HashMap<u32, Vec<u8>> {
+        &self.entities
+    }
+
+    /// Tick of the last applied snapshot
+    pub fn tick(&self) -> Option<u64> {
+        self.history.back().map(|(tick, _)| *tick)
+    }
+
+    /// Take in one snapshot part. Returns the tick if it completed a snapshot that was applied.
+    pub fn receive(&mut self, data: &[u8]) -> Result<Option<u64>, Error> {
+        let part = Part::decode(data)?;
+        let tick = part.tick;
+        if self.tick().is_some_and(|applied| tick <= applied) {
+            return Ok(None);
+        }
+
+        let pending = self.pending.entry(tick).or_insert_with(|| PendingSnapshot {
+            base_tick: part.base_tick,
+            full: part.full,
+            parts: (0..part.parts).map(|_| None).collect(),
+        });
+        if pending.parts.len() != part.parts as usize || pending.base_tick != part.base_tick || pending.full != part.full {
+            // Parts for the same tick disagree, so the host rebuilt it; keep the newest
+            *pending = PendingSnapshot {
+                base_tick: part.base_tick,
+                full: part.full,
+                parts: (0..part.parts).map(|_| None).collect(),
+            };
+        }
+        let index = part.part as usize;
+        pending.parts[index] = Some(part);
+
+        if pending.parts.iter().any(Option::is_none) {
+            while self.pending.len() > MAX_PENDING_SNAPSHOTS {
+                self.pending.pop_first();
+            }
+            return Ok(None);
+        }
+
+        let snapshot = self.pending.remove(&tick).unwrap();
+        self.pending.retain(|&pending_tick, _| pending_tick > tick);
+        Ok(self.apply(tick, snapshot).then_some(tick))
+    }
+
+    fn apply(&mut self, tick: u64, snapshot: PendingSnapshot) -> bool {
+        let mut entities = if snapshot.full {
+            HashMap::new()
+        } else {
+            match self.history.iter().find(|(t, _)| *t == snapshot.base_tick) {
+                Some((_, base)) => base.clone(),
+                None => return false,
+            }
+        };
+
+        for part in snapshot.parts.into_iter().flatten() {
+            entities.extend(part.updates);
+            for id in part.removals {
+                entities.remove(&id);
+            }
+        }
+
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back((tick, entities.clone()));
+        self.entities = entities;
+        true
+    }
+}
@@ -0,0 +1,22 @@
+use std::io::{Error, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// Resolve an address (IP literal or hostname) into every candidate socket address, in order
+pub fn resolve_addrs<A: ToSocketAddrs>(addr: A) -> Result<Vec<SocketAddr>, Error> {
+    let addrs: Vec<SocketAddr> = addr
+        .to_socket_addrs()
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Invalid relay address: {}", e)))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Invalid relay address: no addresses resolved"));
+    }
+    Ok(addrs)
+}
+
+/// Wildcard bind address in the same family as `addr`
+pub fn unspecified_bind_addr(addr: &SocketAddr) -> &'static str {
+    match addr {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    }
+}
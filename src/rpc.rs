@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+
+use crate::decoder::Decoder;
+
+pub type RpcHandler = Box<dyn FnMut(u16, &[u8]) -> Result<Vec<u8>, String> + Send>; // (from_id, args) -> reply or error message
+pub type RpcReplyCallback = Box<dyn FnOnce(Result<Vec<u8>, Error>) + Send>; // (reply or error)
+
+/// How long a call waits for its reply unless set_rpc_timeout is called
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Call ID of requests that don't want a reply
+const NO_REPLY: u32 = 0;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+const STATUS_UNKNOWN_METHOD: u8 = 2;
+
+struct PendingCall {
+    peer_id: u16,
+    method: String,
+    deadline: Instant,
+    callback: RpcReplyCallback,
+}
+
+/// Named procedure handlers plus calls waiting for replies, shared by client and host.
+///
+/// Requests carry a call ID (0 when no reply is wanted), the method name, and raw argument bytes;
+/// responses echo the call ID with a status byte and the reply bytes or error message.
+pub struct RpcEndpoint {
+    handlers: HashMap<String, RpcHandler>,
+    pending: HashMap<u32, PendingCall>,
+    next_call_id: u32,
+    timeout: Duration,
+}
+
+impl Default for RpcEndpoint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RpcEndpoint {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+            pending: HashMap::new(),
+            next_call_id: 1,
+            timeout: DEFAULT_RPC_TIMEOUT,
+        }
+    }
+
+    /// Register a handler for a method, replacing any previous one with the same name
+    pub fn register(&mut self, method: &str, handler: RpcHandler) -> Result<(), Error> {
+        validate_method(method)?;
+        self.handlers.insert(method.to_string(), handler);
+        Ok(())
+    }
+
+    /// Time a call waits for its reply before its callback gets a TimedOut error
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Calls still waiting for a reply
+    pub fn pending_calls(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Encode a request to `peer_id`, remembering the callback (if any) until the reply arrives
+    pub fn request(&mut self, peer_id: u16, method: &str, args: &[u8], callback: Option<RpcReplyCallback>) -> Result<Vec<u8>, Error> {
+        validate_method(method)?;
+        let call_id = match callback {
+            Some(callback) => {
+                let call_id = self.allocate_call_id();
+                self.pending.insert(call_id, PendingCall {
+                    peer_id,
+                    method: method.to_string(),
+                    deadline: Instant::now() + self.timeout,
+                    callback,
+                });
+                call_id
+            }
+            None => NO_REPLY,
+        };
+
+        let mut bytes = Vec::with_capacity(4 + 1 + method.len() + args.len());
+        bytes.extend_from_slice(&call_id.to_le_bytes());
+        bytes.push(method.len() as u8);
+        bytes.extend_from_slice(method.as_bytes());
+        bytes.extend_from_slice(args);
+        Ok(bytes)
+    }
+
+    /// Forget a call whose request could not be sent
+    pub fn cancel(&mut self, request: &[u8]) {
+        if let Ok(call_id) = Decoder::new(request, "RPC request").u32() {
+            self.pending.remove(&call_id);
+        }
+    }
+
+    /// Run the handler for a request. Returns the response to send back, if the caller wants one.
+    pub fn handle_request(&mut self, from_id: u16, data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut decoder = Decoder::new(data, "RPC request");
+        let call_id = decoder.u32()?;
+        let method = decoder.short_string()?;
+        let args = decoder.rest();
+
+        let (status, body) = match self.handlers.get_mut(&method) {
+            Some(handler) => match handler(from_id, args) {
+                Ok(reply) => (STATUS_OK, reply),
+                Err(message) => (STATUS_ERROR, message.into_bytes()),
+            },
+            None => (STATUS_UNKNOWN_METHOD, Vec::new()),
+        };
+        if call_id == NO_REPLY {
+            return Ok(None);
+        }
+
+        let mut bytes = Vec::with_capacity(5 + body.len());
+        bytes.extend_from_slice(&call_id.to_le_bytes());
+        bytes.push(status);
+        bytes.extend_from_slice(&body);
+        Ok(Some(bytes))
+    }
+
+    /// Deliver a response to the call waiting for it. Responses nobody is waiting for are ignored.
+    pub fn handle_response(&mut self, from_id: u16, data: &[u8]) -> Result<(), Error> {
+        let mut decoder = Decoder::new(data, "RPC response");
+        let call_id = decoder.u32()?;
+        let status = decoder.u8()?;
+        let body = decoder.rest();
+
+        if self.pending.get(&call_id).is_none_or(|call| call.peer_id != from_id) {
+            return Ok(());
+        }
+        let call = self.pending.remove(&call_id).unwrap();
+        let result = match status {
+            STATUS_OK => Ok(body.to_vec()),
+            STATUS_UNKNOWN_METHOD => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("Peer {} has no RPC handler for '{}'", from_id, call.method),
+            )),
+            _ => Err(Error::other(String::from_utf8_lossy(body).into_owned())),
+        };
+        (call.callback)(result);
+        Ok(())
+    }
+
+    /// Fail every call whose deadline has passed
+    pub fn expire_calls(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<u32> = self.pending
+            .iter()
+            .filter(|(_, call)| call.deadline <= now)
+            .map(|(call_id, _)| *call_id)
+            .collect();
+        for call_id in expired {
+            let call = self.pending.remove(&call_id).unwrap();
+            (call.callback)(Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("RPC '{}' to peer {} timed out", call.method, call.peer_id),
+            )));
+        }
+    }
+
+    fn allocate_call_id(&mut self) -> u32 {
+        loop {
+            let call_id = self.next_call_id;
+            self.next_call_id = self.next_call_id.wrapping_add(1);
+            if call_id != NO_REPLY && !self.pending.contains_key(&call_id) {
+                return call_id;
+            }
+        }
+    }
+}
+
+fn validate_method(method: &str) -> Result<(), Error> {
+    // Method names are sent with a one-byte length prefix
+    if method.is_empty() || method.len() > u8::MAX as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, "RPC method names must be 1-255 bytes"));
+    }
+    Ok(())
+}
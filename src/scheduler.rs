@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+
+/// Low-priority packets still queued after this long are dropped unless set_stale_packet_age says otherwise
+pub const DEFAULT_STALE_AGE: Duration = Duration::from_millis(200);
+
+/// How urgently an outgoing packet should be sent when the link can't take everything at once.
+/// Connection control, pings, and acks bypass the scheduler and always go first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// Bulk data that is worthless once stale; dropped if it waits too long
+    Low,
+    /// Ordinary game packets, RPCs, and replication
+    #[default]
+    Normal,
+    /// Latency-sensitive traffic such as voice
+    High,
+}
+
+struct Queued<P> {
+    packet: P,
+    queued_at: Instant,
+}
+
+/// Per-priority queues of outgoing packets, drained highest priority first.
+/// Packets only wait here while the sender is held back; otherwise they go straight out.
+pub struct OutgoingScheduler<P> {
+    queues: [VecDeque<Queued<P>>; 3],
+    stale_age: Option<Duration>,
+    dropped: u64,
+}
+
+impl<P> Default for OutgoingScheduler<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> OutgoingScheduler<P> {
+    pub fn new() -> Self {
+        Self {
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            stale_age: Some(DEFAULT_STALE_AGE),
+            dropped: 0,
+        }
+    }
+
+    /// How long a low-priority packet may wait before it is dropped (None keeps them indefinitely)
+    pub fn set_stale_age(&mut self, age: Option<Duration>) {
+        self.stale_age = age;
+    }
+
+    pub fn push(&mut self, packet: P, priority: Priority) {
+        self.queues[priority as usize].push_back(Queued { packet, queued_at: Instant::now() });
+    }
+
+    /// Packets waiting to be sent
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    /// Low-priority packets dropped for going stale
+    pub fn dropped_packets(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Drop stale packets, then hand the rest to `send` highest priority first, oldest first
+    /// within a priority. A WouldBlock from `send` leaves that packet and everything after it
+    /// queued for the next drain. Returns how many packets were sent.
+    pub fn drain<F>(&mut self, mut send: F) -> Result<usize, Error>
+    where
+        F: FnMut(&P) -> Result<(), Error>,
+    {
+        if let Some(age) = self.stale_age {
+            let low = &mut self.queues[Priority::Low as usize];
+            let before = low.len();
+            low.retain(|queued| queued.queued_at.elapsed() < age);
+            self.dropped += (before - low.len()) as u64;
+        }
+
+        let mut sent = 0;
+        for queue in self.queues.iter_mut().rev() {
+            while let Some(queued) = queue.front() {
+                match send(&queued.packet) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(sent),
+                    Err(e) => {
+                        queue.pop_front();
+                        return Err(e);
+                    }
+                }
+                queue.pop_front();
+                sent += 1;
+            }
+        }
+        Ok(sent)
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+/// Number of sequences behind the latest one that are still tracked for duplicates
+pub const SEQUENCE_WINDOW: u16 = 64;
+
+/// Compare two sequence numbers, accounting for u16 wraparound
+pub fn sequence_greater_than(a: u16, b: u16) -> bool {
+    a != b && a.wrapping_sub(b) < 0x8000
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceStatus {
+    /// Newest packet seen from this peer so far
+    New,
+    /// Older than the newest packet but not seen before
+    OutOfOrder,
+    /// Already received, should be dropped
+    Duplicate,
+    /// Too far behind the window to tell, should be dropped
+    Stale,
+}
+
+impl SequenceStatus {
+    pub fn should_accept(&self) -> bool {
+        matches!(self, SequenceStatus::New | SequenceStatus::OutOfOrder)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    pub received: u64,
+    pub duplicates: u64,
+    pub out_of_order: u64,
+    pub stale: u64,
+}
+
+impl SequenceStats {
+    /// Packets dropped as replays: repeats inside the window and anything older than it
+    pub fn rejected(&self) -> u64 {
+        self.duplicates + self.stale
+    }
+
+    fn add(&mut self, other: SequenceStats) {
+        self.received += other.received;
+        self.duplicates += other.duplicates;
+        self.out_of_order += other.out_of_order;
+        self.stale += other.stale;
+    }
+}
+
+/// Hands out monotonically increasing sequence numbers for outgoing packets
+#[derive(Debug, Clone, Default)]
+pub struct SequenceCounter {
+    next: u16,
+}
+
+impl SequenceCounter {
+    pub fn new() -> Self {
+        Self { next: 0 }
+    }
+
+    /// Get the next sequence number, wrapping at u16::MAX
+    pub fn advance(&mut self) -> u16 {
+        let sequence = self.next;
+        self.next = self.next.wrapping_add(1);
+        sequence
+    }
+}
+
+/// Tracks incoming sequence numbers from a single peer
+#[derive(Debug, Clone, Default)]
+pub struct SequenceTracker {
+    latest: Option<u16>,
+    // Bit N set means (latest - N) has been received
+    received_mask: u64,
+    stats: SequenceStats,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an incoming sequence number and classify it
+    pub fn record(&mut self, sequence: u16) -> SequenceStatus {
+        let status = match self.latest {
+            None => {
+                self.latest = Some(sequence);
+                self.received_mask = 1;
+                SequenceStatus::New
+            }
+            Some(latest) if sequence_greater_than(sequence, latest) => {
+                let shift = sequence.wrapping_sub(latest) as u32;
+                self.received_mask = if shift >= 64 { 0 } else { self.received_mask << shift };
+                self.received_mask |= 1;
+                self.latest = Some(sequence);
+                SequenceStatus::New
+            }
+            Some(latest) => {
+                let behind = latest.wrapping_sub(sequence);
+                if behind >= SEQUENCE_WINDOW {
+                    SequenceStatus::Stale
+                } else if self.received_mask & (1u64 << behind) != 0 {
+                    SequenceStatus::Duplicate
+                } else {
+                    self.received_mask |= 1u64 << behind;
+                    SequenceStatus::OutOfOrder
+                }
+            }
+        };
+
+        match status {
+            SequenceStatus::New => self.stats.received += 1,
+            SequenceStatus::OutOfOrder => {
+                self.stats.received += 1;
+                self.stats.out_of_order += 1;
+            }
+            SequenceStatus::Duplicate => self.stats.duplicates += 1,
+            SequenceStatus::Stale => self.stats.stale += 1,
+        }
+
+        status
+    }
+
+    /// Get the most recent sequence number received
+    pub fn latest(&self) -> Option<u16> {
+        self.latest
+    }
+
+    pub fn stats(&self) -> SequenceStats {
+        self.stats
+    }
+}
+
+/// Per-peer incoming sequence trackers keyed by client ID. Each sender numbers everything it sends
+/// in one stream (the host one per client), so the tracker for a sender is its replay window.
+#[derive(Debug, Clone, Default)]
+pub struct PeerSequences {
+    trackers: HashMap<u16, SequenceTracker>,
+    /// Counts from trackers since reset, kept for the totals
+    retired: SequenceStats,
+}
+
+impl PeerSequences {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a sequence number from a peer
+    pub fn record(&mut self, peer_id: u16, sequence: u16) -> SequenceStatus {
+        self.trackers.entry(peer_id).or_default().record(sequence)
+    }
+
+    /// Forget a peer, e.g. when its ID is reassigned
+    pub fn reset(&mut self, peer_id: u16) {
+        if let Some(tracker) = self.trackers.remove(&peer_id) {
+            self.retired.add(tracker.stats());
+        }
+    }
+
+    /// Forget every peer, e.g. on joining a new session where numbering starts over
+    pub fn reset_all(&mut self) {
+        for (_, tracker) in self.trackers.drain() {
+            self.retired.add(tracker.stats());
+        }
+    }
+
+    /// Counts across every peer, including ones since reset
+    pub fn totals(&self) -> SequenceStats {
+        let mut totals = self.retired;
+        for tracker in self.trackers.values() {
+            totals.add(tracker.stats());
+        }
+        totals
+    }
+
+    pub fn stats(&self, peer_id: u16) -> Option<SequenceStats> {
+        self.trackers.get(&peer_id).map(|t| t.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_compare_across_wraparound() {
+        assert!(sequence_greater_than(1, 0));
+        assert!(sequence_greater_than(0, u16::MAX));
+        assert!(sequence_greater_than(5, 65_530));
+        assert!(!sequence_greater_than(65_530, 5));
+        assert!(!sequence_greater_than(7, 7));
+    }
+
+    #[test]
+    fn counters_wrap_at_u16_max() {
+        let mut counter = SequenceCounter { next: u16::MAX };
+        assert_eq!(counter.advance(), u16::MAX);
+        assert_eq!(counter.advance(), 0);
+    }
+
+    #[test]
+    fn trackers_follow_the_stream_across_wraparound() {
+        let mut tracker = SequenceTracker::new();
+        assert_eq!(tracker.record(65_534), SequenceStatus::New);
+        assert_eq!(tracker.record(u16::MAX), SequenceStatus::New);
+        assert_eq!(tracker.record(1), SequenceStatus::New);
+        assert_eq!(tracker.latest(), Some(1));
+        assert_eq!(tracker.record(0), SequenceStatus::OutOfOrder);
+        assert_eq!(tracker.record(u16::MAX), SequenceStatus::Duplicate);
+        assert_eq!(tracker.record(65_534), SequenceStatus::Duplicate);
+    }
+
+    #[test]
+    fn duplicates_inside_the_window_are_rejected() {
+        let mut tracker = SequenceTracker::new();
+        for sequence in [100, 102, 101] {
+            assert!(tracker.record(sequence).should_accept());
+        }
+        assert_eq!(tracker.record(102), SequenceStatus::Duplicate);
+        assert_eq!(tracker.record(101), SequenceStatus::Duplicate);
+        assert_eq!(tracker.record(100), SequenceStatus::Duplicate);
+
+        // The oldest sequence the window still covers
+        assert_eq!(tracker.record(102 - (SEQUENCE_WINDOW - 1)), SequenceStatus::OutOfOrder);
+        assert_eq!(tracker.record(102 - (SEQUENCE_WINDOW - 1)), SequenceStatus::Duplicate);
+        let stats = tracker.stats();
+        assert_eq!((stats.received, stats.out_of_order, stats.duplicates), (4, 2, 4));
+    }
+
+    #[test]
+    fn packets_behind_the_window_are_stale() {
+        let mut tracker = SequenceTracker::new();
+        tracker.record(100);
+        assert_eq!(tracker.record(100 - SEQUENCE_WINDOW), SequenceStatus::Stale);
+        assert_eq!(tracker.record(1), SequenceStatus::Stale);
+
+        // A jump past the window forgets everything before it
+        assert_eq!(tracker.record(100 + 2 * SEQUENCE_WINDOW), SequenceStatus::New);
+        assert_eq!(tracker.record(100), SequenceStatus::Stale);
+        assert_eq!(tracker.record(100 + 2 * SEQUENCE_WINDOW - 1), SequenceStatus::OutOfOrder);
+        assert_eq!(tracker.stats().rejected(), 3);
+    }
+
+    #[test]
+    fn peers_are_tracked_apart_and_reset_keeps_the_totals() {
+        let mut peers = PeerSequences::new();
+        assert_eq!(peers.record(2, 5), SequenceStatus::New);
+        assert_eq!(peers.record(3, 5), SequenceStatus::New);
+        assert_eq!(peers.record(2, 5), SequenceStatus::Duplicate);
+
+        // A reassigned ID starts a fresh stream
+        peers.reset(2);
+        assert_eq!(peers.stats(2), None);
+        assert_eq!(peers.record(2, 5), SequenceStatus::New);
+        assert_eq!(peers.totals().received, 3);
+        assert_eq!(peers.totals().duplicates, 1);
+
+        peers.reset_all();
+        assert_eq!(peers.stats(3), None);
+        assert_eq!(peers.record(3, 5), SequenceStatus::New);
+        assert_eq!(peers.totals().received, 4);
+    }
+}
@@ -0,0 +1,305 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::socket_options::SocketOptions;
+use crate::transport::Transport;
+
+/// How one-way delay is sampled for each datagram
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Latency {
+    Fixed(Duration),
+    /// Evenly spread between min and max
+    Uniform { min: Duration, max: Duration },
+    /// Bell curve around the mean, clamped at zero
+    Normal { mean: Duration, std_dev: Duration },
+}
+
+/// Network conditions applied to datagrams arriving at a SimulatedTransport
+#[derive(Debug, Clone)]
+pub struct NetworkConditions {
+    pub latency: Latency,
+    /// Extra random delay of up to this much, added on top of the latency
+    pub jitter: Duration,
+    /// Chance (0-100) that a datagram is dropped
+    pub loss_percent: f64,
+    /// Chance (0-100) that a datagram is delivered twice
+    pub duplicate_percent: f64,
+    /// Chance (0-100) that a datagram is held back so later ones overtake it
+    pub reorder_percent: f64,
+    /// How long reordered datagrams are held back
+    pub reorder_delay: Duration,
+    /// Seed for repeatable runs; None picks one at random
+    pub seed: Option<u64>,
+}
+
+impl Default for NetworkConditions {
+    fn default() -> Self {
+        Self {
+            latency: Latency::Fixed(Duration::ZERO),
+            jitter: Duration::ZERO,
+            loss_percent: 0.0,
+            duplicate_percent: 0.0,
+            reorder_percent: 0.0,
+            reorder_delay: Duration::from_millis(20),
+            seed: None,
+        }
+    }
+}
+
+impl NetworkConditions {
+    /// A typical home connection: 40ms +-10ms, 1% loss
+    pub fn home() -> Self {
+        Self {
+            latency: Latency::Fixed(Duration::from_millis(40)),
+            jitter: Duration::from_millis(10),
+            loss_percent: 1.0,
+            ..Self::default()
+        }
+    }
+
+    /// A poor mobile connection: 150ms +-50ms, 5% loss, some duplication and reordering
+    pub fn mobile() -> Self {
+        Self {
+            latency: Latency::Normal {
+                mean: Duration::from_millis(150),
+                std_dev: Duration::from_millis(30),
+            },
+            jitter: Duration::from_millis(50),
+            loss_percent: 5.0,
+            duplicate_percent: 1.0,
+            reorder_percent: 2.0,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimulationStats {
+    pub received: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+}
+
+/// (release time, arrival order, data, sender)
+type HeldDatagram = (Instant, u64, Vec<u8>, SocketAddr);
+
+struct SimulationState {
+    conditions: NetworkConditions,
+    rng: StdRng,
+    // Ordered by release time, then arrival order so equal times stay FIFO
+    held: BinaryHeap<Reverse<HeldDatagram>>,
+    arrivals: u64,
+    stats: SimulationStats,
+}
+
+impl SimulationState {
+    fn roll(&mut self, percent: f64) -> bool {
+        percent > 0.0 && self.rng.random::<f64>() * 100.0 < percent
+    }
+
+    fn sample_delay(&mut self) -> Duration {
+        let base = match self.conditions.latency {
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform { min, max } if max > min => {
+                min + (max - min).mul_f64(self.rng.random::<f64>())
+            }
+            Latency::Uniform { min, .. } => min,
+            Latency::Normal { mean, std_dev } => {
+                // Box-Muller transform
+                let u1: f64 = self.rng.random::<f64>().max(f64::MIN_POSITIVE);
+                let u2: f64 = self.rng.random();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                Duration::from_secs_f64((mean.as_secs_f64() + z * std_dev.as_secs_f64()).max(0.0))
+            }
+        };
+        base + self.conditions.jitter.mul_f64(self.rng.random::<f64>())
+    }
+
+    fn hold(&mut self, data: Vec<u8>, from: SocketAddr, now: Instant) {
+        self.stats.received += 1;
+        if self.roll(self.conditions.loss_percent) {
+            self.stats.dropped += 1;
+            return;
+        }
+
+        let copies = if self.roll(self.conditions.duplicate_percent) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        for copy in 0..copies {
+            let mut delay = self.sample_delay();
+            if copy == 0 && self.roll(self.conditions.reorder_percent) {
+                self.stats.reordered += 1;
+                delay += self.conditions.reorder_delay;
+            }
+            self.arrivals += 1;
+            self.held.push(Reverse((now + delay, self.arrivals, data.clone(), from)));
+        }
+    }
+
+    fn next_release(&self) -> Option<Instant> {
+        self.held.peek().map(|Reverse((at, ..))| *at)
+    }
+}
+
+/// Wraps another transport and applies latency, jitter, loss, duplication, and reordering
+/// to everything it receives. Wrap both ends of a link to degrade traffic in both directions.
+pub struct SimulatedTransport<T: Transport> {
+    inner: T,
+    state: Arc<Mutex<SimulationState>>,
+    nonblocking: AtomicBool,
+    read_timeout: Mutex<Option<Duration>>,
+}
+
+impl<T: Transport> SimulatedTransport<T> {
+    pub fn new(inner: T, conditions: NetworkConditions) -> Result<Self, Error> {
+        // Held datagrams are released on our schedule, so the inner transport is always polled
+        inner.set_nonblocking(true)?;
+        let rng = match conditions.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        Ok(Self {
+            inner,
+            state: Arc::new(Mutex::new(SimulationState {
+                conditions,
+                rng,
+                held: BinaryHeap::new(),
+                arrivals: 0,
+                stats: SimulationStats::default(),
+            })),
+            nonblocking: AtomicBool::new(false),
+            read_timeout: Mutex::new(None),
+        })
+    }
+
+    /// Change conditions on the fly, e.g. to simulate a connection getting worse
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        self.state.lock().unwrap().conditions = conditions;
+    }
+
+    pub fn stats(&self) -> SimulationStats {
+        self.state.lock().unwrap().stats
+    }
+
+    /// Get the wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Move every datagram waiting on the inner transport into the hold queue
+    fn drain_inner(&self, state: &mut SimulationState) -> Result<(), Error> {
+        let mut buf = [0u8; 65536];
+        loop {
+            match self.inner.recv_from(&mut buf) {
+                Ok((size, from)) => state.hold(buf[..size].to_vec(), from, Instant::now()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if crate::transport::is_connection_reset(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Wait until a held datagram is due or the deadline passes. Returns true if one is due.
+    fn wait_until_due(&self, deadline: Option<Instant>) -> Result<bool, Error> {
+        loop {
+            let next_release = {
+                let mut state = self.state.lock().unwrap();
+                self.drain_inner(&mut state)?;
+                state.next_release()
+            };
+
+            let now = Instant::now();
+            if next_release.is_some_and(|at| at <= now) {
+                return Ok(true);
+            }
+            if deadline.is_some_and(|deadline| deadline <= now) {
+                return Ok(false);
+            }
+
+            let wake = match (next_release, deadline) {
+                (Some(at), Some(deadline)) => at.min(deadline),
+                (Some(at), None) => at,
+                (None, Some(deadline)) => deadline,
+                (None, None) => now + Duration::from_secs(1),
+            };
+            self.inner.wait_readable(wake - now)?;
+        }
+    }
+}
+
+impl<T: Transport> Transport for SimulatedTransport<T> {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, Error> {
+        self.inner.send_to(data, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+        let ready = if self.nonblocking.load(Ordering::Relaxed) {
+            self.wait_until_due(Some(Instant::now()))?
+        } else {
+            let timeout = *self.read_timeout.lock().unwrap();
+            self.wait_until_due(timeout.map(|t| Instant::now() + t))?
+        };
+        if !ready {
+            return Err(Error::new(ErrorKind::WouldBlock, "No datagram available"));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let Some(Reverse((_, _, data, from))) = state.held.pop() else {
+            return Err(Error::new(ErrorKind::WouldBlock, "No datagram available"));
+        };
+        let size = data.len().min(buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        Ok((size, from))
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.inner.local_addr()
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Cannot set a zero read timeout"));
+        }
+        *self.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> Result<Self, Error> {
+        Ok(Self {
+            inner: self.inner.try_clone()?,
+            state: self.state.clone(),
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Relaxed)),
+            read_timeout: Mutex::new(*self.read_timeout.lock().unwrap()),
+        })
+    }
+
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, Error> {
+        self.wait_until_due(Some(Instant::now() + timeout))
+    }
+
+    fn disable_connection_reset(&self) -> Result<(), Error> {
+        self.inner.disable_connection_reset()
+    }
+
+    fn apply_socket_options(&self, options: &SocketOptions) -> Result<(), Error> {
+        self.inner.apply_socket_options(options)
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use project_neon::protocol::{
+    self, NeonPacket, PacketPayload, PacketType, ANNOUNCEMENT, CAPABILITIES, FLAG_COMPRESSED, FLAG_RELIABLE, JOIN_CODE_ANSWER, JOIN_CODE_LOOKUP, MAX_DATAGRAM_SIZE, NAME_CHANGE, REPLICATION_ACK,
+    REPLICATION_SNAPSHOT, RPC_REQUEST, RPC_RESPONSE, VOICE_FRAME,
+};
+use project_neon::relay::read_capture;
+use project_neon::sequence::{SequenceStatus, SequenceTracker};
+
+const USAGE: &str = "Usage: neon-sniff (--bind <addr> | --read <file.pcap>)";
+
+/// Game payload bytes shown before the dump is cut short
+const HEX_PREVIEW: usize = 32;
+
+enum Source {
+    Bind(String),
+    Read(String),
+}
+
+fn parse_args() -> Result<Source, String> {
+    let mut source = None;
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--bind" => source = Some(Source::Bind(value()?)),
+            "--read" => source = Some(Source::Read(value()?)),
+            _ => return Err(format!("Unknown argument '{}'", arg)),
+        }
+    }
+    source.ok_or_else(|| "Give --bind or --read".to_string())
+}
+
+/// Decodes datagrams and prints them, tracking what earlier packets revealed
+#[derive(Default)]
+struct Sniffer {
+    started_at: Option<SystemTime>,
+    /// When each sender was last heard from
+    last_seen: HashMap<SocketAddr, SystemTime>,
+    /// Sequences per sender and destination, as the receiving peer tracks them
+    sequences: HashMap<(SocketAddr, u16), SequenceTracker>,
+    /// Game packet names learned from PacketTypeRegistry packets
+    names: HashMap<u8, String>,
+}
+
+impl Sniffer {
+    fn show(&mut self, at: SystemTime, from: SocketAddr, to: SocketAddr, data: &[u8]) {
+        let started_at = *self.started_at.get_or_insert(at);
+        let elapsed = at.duration_since(started_at).unwrap_or_default();
+        let delta = match self.last_seen.insert(from, at) {
+            Some(previous) => format!("+{:.1}ms", millis(at.duration_since(previous).unwrap_or_default())),
+            None => "first".to_string(),
+        };
+        print!("{:>10.6}s {:>10}  {} -> {}  ", elapsed.as_secs_f64(), delta, from, to);
+
+        let (header, packet) = match protocol::decode_header(data).and_then(|header| Ok((header, protocol::decode(data)?))) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                println!("undecodable ({} bytes): {}", data.len(), e);
+                return;
+            }
+        };
+        println!("v{}{} {}", header.version, flag_names(header.flags), self.describe(from, &packet));
+        self.show_payload(from, &packet.payload, "    ");
+    }
+
+    /// One-line summary of a packet's header, with a note on sequence gaps
+    fn describe(&mut self, from: SocketAddr, packet: &NeonPacket) -> String {
+        let mut line = format!(
+            "{} seq={} {}->{}",
+            self.type_name(packet.packet_type),
+            packet.sequence,
+            packet.client_id,
+            packet.destination_id
+        );
+        // A batch takes its first packet's sequence, so only the packets inside are counted
+        if !matches!(packet.payload, PacketPayload::Batch(_)) {
+            let tracker = self.sequences.entry((from, packet.destination_id)).or_default();
+            let latest = tracker.latest();
+            match tracker.record(packet.sequence) {
+                SequenceStatus::New => {
+                    let missing = latest.map_or(0, |latest| packet.sequence.wrapping_sub(latest).wrapping_sub(1));
+                    if missing > 0 {
+                        line.push_str(&format!("  [gap: {} missing]", missing));
+                    }
+                }
+                status => line.push_str(&format!("  [{:?}]", status)),
+            }
+        }
+        line
+    }
+
+    fn show_payload(&mut self, from: SocketAddr, payload: &PacketPayload, indent: &str) {
+        match payload {
+            PacketPayload::None => {}
+            PacketPayload::GamePacket(data) => println!("{}{} bytes: {}", indent, data.len(), hex(data)),
+            PacketPayload::PacketTypeRegistry(registry) => {
+                for entry in &registry.entries {
+                    println!("{}0x{:02X} {}: {}", indent, entry.packet_id, entry.name, entry.description);
+                    self.names.insert(entry.packet_id, entry.name.clone());
+                }
+            }
+            PacketPayload::Batch(packets) => {
+                for packet in packets {
+                    println!("{}{}", indent, self.describe(from, packet));
+                    self.show_payload(from, &packet.payload, &format!("{}    ", indent));
+                }
+            }
+            other => println!("{}{:?}", indent, other),
+        }
+    }
+
+    fn type_name(&self, packet_type: u8) -> String {
+        let name = match packet_type {
+            REPLICATION_SNAPSHOT => Some("ReplicationSnapshot"),
+            REPLICATION_ACK => Some("ReplicationAck"),
+            RPC_REQUEST => Some("RpcRequest"),
+            RPC_RESPONSE => Some("RpcResponse"),
+            VOICE_FRAME => Some("VoiceFrame"),
+            JOIN_CODE_LOOKUP => Some("JoinCodeLookup"),
+            JOIN_CODE_ANSWER => Some("JoinCodeAnswer"),
+            CAPABILITIES => Some("Capabilities"),
+            NAME_CHANGE => Some("NameChange"),
+            ANNOUNCEMENT => Some("Announcement"),
+            id if id >= PacketType::GamePacket as u8 => self.names.get(&id).map(String::as_str),
+            _ => None,
+        };
+        match name {
+            Some(name) => format!("{}(0x{:02X})", name, packet_type),
+            None => format!("{}(0x{:02X})", core_type_name(packet_type), packet_type),
+        }
+    }
+}
+
+fn core_type_name(packet_type: u8) -> &'static str {
+    const CORE_TYPES: [&str; 17] = [
+        "SessionRedirect",
+        "ConnectRequest",
+        "ConnectAccept",
+        "ConnectDeny",
+        "SessionConfig",
+        "PacketTypeRegistry",
+        "Batch",
+        "Chat",
+        "PeerList",
+        "TimeSync",
+        "MtuProbe",
+        "Ping",
+        "Pong",
+        "DisconnectNotice",
+        "Ack",
+        "Challenge",
+        "GamePacket",
+    ];
+    CORE_TYPES.get(packet_type as usize).copied().unwrap_or("GamePacket")
+}
+
+/// Header flags as a suffix for the version, e.g. "+reliable+lz4"
+fn flag_names(flags: u8) -> String {
+    let mut names = String::new();
+    if flags & FLAG_RELIABLE != 0 {
+        names.push_str("+reliable");
+    }
+    if flags & FLAG_COMPRESSED != 0 {
+        names.push_str("+lz4");
+    }
+    names
+}
+
+fn hex(data: &[u8]) -> String {
+    let shown: Vec<String> = data.iter().take(HEX_PREVIEW).map(|byte| format!("{:02x}", byte)).collect();
+    let more = if data.len() > HEX_PREVIEW { " ..." } else { "" };
+    format!("{}{}", shown.join(" "), more)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn main() -> ExitCode {
+    let source = match parse_args() {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let mut sniffer = Sniffer::default();
+
+    match source {
+        Source::Read(path) => match read_capture(&path) {
+            Ok(packets) => {
+                for packet in packets {
+                    sniffer.show(packet.timestamp, packet.from, packet.to, &packet.data);
+                }
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path, e);
+                ExitCode::FAILURE
+            }
+        },
+        Source::Bind(addr) => {
+            let socket = match UdpSocket::bind(&addr) {
+                Ok(socket) => socket,
+                Err(e) => {
+                    eprintln!("Failed to bind {}: {}", addr, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let local_addr = socket.local_addr().expect("bound socket has an address");
+            println!("Listening on {}", local_addr);
+
+            let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+            loop {
+                match socket.recv_from(&mut buf) {
+                    Ok((len, from)) => sniffer.show(SystemTime::now(), from, local_addr, &buf[..len]),
+                    Err(e) => {
+                        eprintln!("Receive failed: {}", e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+        }
+    }
+}
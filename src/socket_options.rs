@@ -0,0 +1,148 @@
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+
+/// DSCP Expedited Forwarding, the class for latency-sensitive traffic such as game state and voice
+pub const DSCP_EXPEDITED_FORWARDING: u8 = 46;
+
+/// Local port a client or host binds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LocalPort {
+    /// Whichever free port the operating system picks
+    #[default]
+    Any,
+    Fixed(u16),
+    /// The first free port from the first to the last, inclusive, for firewalls that only open a few
+    Range(u16, u16),
+}
+
+/// Tuning for the UDP sockets the client, host and relay open.
+/// Fields left at their defaults keep the operating system's settings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Local address a client or host binds, e.g. one interface of a multi-homed machine.
+    /// None binds the wildcard address. The relay binds the address it is given instead.
+    pub local_ip: Option<IpAddr>,
+    /// Local port a client or host binds
+    pub local_port: LocalPort,
+    /// Receive buffer in bytes (SO_RCVBUF); a busy relay wants more than the default to ride out bursts
+    pub recv_buffer_size: Option<usize>,
+    /// Send buffer in bytes (SO_SNDBUF)
+    pub send_buffer_size: Option<usize>,
+    /// Hop limit of outgoing packets (IP_TTL, or IPV6_UNICAST_HOPS on IPv6 sockets)
+    pub ttl: Option<u32>,
+    /// DSCP code point (0-63) marked on outgoing packets, e.g. `DSCP_EXPEDITED_FORWARDING`
+    pub dscp: Option<u8>,
+    /// Allow binding an address another socket still holds (SO_REUSEADDR)
+    pub reuse_address: bool,
+}
+
+impl SocketOptions {
+    /// Open a UDP socket bound to `addr` with these options. Like `UdpSocket::bind`, each resolved
+    /// address is tried in turn.
+    pub fn bind<A: ToSocketAddrs>(&self, addr: A) -> Result<UdpSocket, Error> {
+        self.check()?;
+        let mut last_error = None;
+        for addr in addr.to_socket_addrs()? {
+            match self.bind_one(addr) {
+                Ok(socket) => return Ok(socket),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::new(ErrorKind::InvalidInput, "No addresses to bind")))
+    }
+
+    /// Open a client or host socket able to reach `remote`, on `local_ip` (or the wildcard
+    /// address of `remote`'s family) and a port `local_port` allows
+    pub fn bind_local(&self, remote: &SocketAddr) -> Result<UdpSocket, Error> {
+        let ip = match self.local_ip {
+            Some(ip) if ip.is_ipv4() != remote.is_ipv4() => {
+                return Err(Error::new(ErrorKind::AddrNotAvailable, format!("Local address {} can't reach {}", ip, remote)));
+            }
+            Some(ip) => ip,
+            None if remote.is_ipv4() => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            None => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let (first, last) = match self.local_port {
+            LocalPort::Any => return self.bind(SocketAddr::new(ip, 0)),
+            LocalPort::Fixed(port) => return self.bind(SocketAddr::new(ip, port)),
+            LocalPort::Range(first, last) => (first, last),
+        };
+        if first > last {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Port range {}-{} is empty", first, last)));
+        }
+        for port in first..=last {
+            match self.bind(SocketAddr::new(ip, port)) {
+                // Windows refuses ports it has reserved with PermissionDenied
+                Err(e) if matches!(e.kind(), ErrorKind::AddrInUse | ErrorKind::PermissionDenied) => continue,
+                result => return result,
+            }
+        }
+        Err(Error::new(ErrorKind::AddrInUse, format!("No free port on {} from {} to {}", ip, first, last)))
+    }
+
+    /// Whether `local_ip` or `local_port` pins the socket to a chosen address
+    pub fn pins_local_addr(&self) -> bool {
+        self.local_ip.is_some() || self.local_port != LocalPort::Any
+    }
+
+    fn bind_one(&self, addr: SocketAddr) -> Result<UdpSocket, Error> {
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+        if self.reuse_address {
+            socket.set_reuse_address(true)?;
+        }
+        socket.bind(&addr.into())?;
+        let socket = UdpSocket::from(socket);
+        self.apply(&socket)?;
+        Ok(socket)
+    }
+
+    /// Apply the options to a socket that is already bound. `reuse_address` only counts before binding
+    /// and is skipped.
+    pub fn apply(&self, socket: &UdpSocket) -> Result<(), Error> {
+        self.check()?;
+        let ipv4 = socket.local_addr()?.is_ipv4();
+        let socket = SockRef::from(socket);
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(ttl) = self.ttl {
+            if ipv4 {
+                socket.set_ttl_v4(ttl)?;
+            } else {
+                socket.set_unicast_hops_v6(ttl)?;
+            }
+        }
+        if let Some(dscp) = self.dscp {
+            // DSCP is the top six bits of the TOS / traffic class byte
+            let tos = u32::from(dscp) << 2;
+            if ipv4 {
+                socket.set_tos_v4(tos)?;
+            } else {
+                set_traffic_class(&socket, tos)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.dscp.is_some_and(|dscp| dscp > 63) {
+            return Err(Error::new(ErrorKind::InvalidInput, "DSCP code points run from 0 to 63"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd"))]
+fn set_traffic_class(socket: &SockRef, tclass: u32) -> Result<(), Error> {
+    socket.set_tclass_v6(tclass)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd")))]
+fn set_traffic_class(_socket: &SockRef, _tclass: u32) -> Result<(), Error> {
+    Err(Error::new(ErrorKind::Unsupported, "DSCP marking on IPv6 sockets isn't supported on this platform"))
+}
@@ -0,0 +1,215 @@
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::client::NeonClient;
+use crate::host::NeonHost;
+use crate::relay::{NeonRelay, RelayConfig};
+pub use crate::simulation::{Latency, NetworkConditions, SimulatedTransport, SimulationStats};
+pub use crate::transport::{MemoryNetwork, MemoryTransport, Transport};
+
+/// Address the cluster's relay is bound to on its memory network
+pub const CLUSTER_RELAY_ADDR: &str = "127.0.0.1:7777";
+
+/// How often the host thread and run_until wake without traffic, so timers such as
+/// ack retries and throttled sends still fire
+const IDLE_WAKE: Duration = Duration::from_millis(10);
+
+/// Relay, host, and any number of clients wired together over an in-memory network
+/// in one process. Delivery is lossless and in order, and the relay runs a single
+/// worker, so a given sequence of calls always produces the same packet flow.
+///
+/// The relay and host run on background threads. Clients are driven by the caller
+/// through `pump` or `run_until`.
+pub struct LocalCluster {
+    network: MemoryNetwork,
+    relay_addr: SocketAddr,
+    session_id: u32,
+    host: Arc<Mutex<NeonHost<MemoryTransport>>>,
+    clients: Vec<NeonClient<MemoryTransport>>,
+    running: Arc<AtomicBool>,
+    host_thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl LocalCluster {
+    /// Start a relay and a host for `session_id`, with no clients yet
+    pub fn new(session_id: u32) -> Result<Self, Error> {
+        Self::with_relay(session_id, |_| {})
+    }
+
+    /// Like `new`, but lets `configure` adjust the relay before it starts
+    pub fn with_relay<F>(session_id: u32, configure: F) -> Result<Self, Error>
+    where
+        F: FnOnce(&mut NeonRelay<MemoryTransport>),
+    {
+        let network = MemoryNetwork::new();
+        let relay_addr = start_relay(&network, configure)?;
+
+        let host_transport = network.bind("127.0.0.1:0")?;
+        let host_waker = host_transport.try_clone()?;
+        let mut host = NeonHost::with_transport(session_id, relay_addr, host_transport)?;
+        host.register()?;
+        let host = Arc::new(Mutex::new(host));
+
+        let running = Arc::new(AtomicBool::new(true));
+        let host_thread = {
+            let host = host.clone();
+            let running = running.clone();
+            thread::Builder::new()
+                .name("neon-cluster-host".to_string())
+                .spawn(move || {
+                    while running.load(Ordering::Relaxed) {
+                        host_waker.wait_readable(IDLE_WAKE)?;
+                        host.lock().unwrap().process_packets()?;
+                    }
+                    Ok(())
+                })?
+        };
+
+        Ok(Self {
+            network,
+            relay_addr,
+            session_id,
+            host,
+            clients: Vec::new(),
+            running,
+            host_thread: Some(host_thread),
+        })
+    }
+
+    /// Start a cluster and connect a client for each name, in order
+    pub fn with_clients(session_id: u32, names: &[&str]) -> Result<Self, Error> {
+        let mut cluster = Self::new(session_id)?;
+        for name in names {
+            cluster.add_client(name)?;
+        }
+        Ok(cluster)
+    }
+
+    /// Connect a new client to the session, returning its index in the cluster
+    pub fn add_client(&mut self, name: &str) -> Result<usize, Error> {
+        let mut client = NeonClient::with_transport(name.to_string(), self.network.bind("127.0.0.1:0")?)?;
+        client.connect(self.session_id, self.relay_addr)?;
+        self.clients.push(client);
+        Ok(self.clients.len() - 1)
+    }
+
+    /// Connect a new client to the session as a spectator, returning its index in the cluster
+    pub fn add_spectator(&mut self, name: &str) -> Result<usize, Error> {
+        let mut client = NeonClient::with_transport(name.to_string(), self.network.bind("127.0.0.1:0")?)?;
+        client.connect_as_spectator(self.session_id, self.relay_addr)?;
+        self.clients.push(client);
+        Ok(self.clients.len() - 1)
+    }
+
+    /// Kill the relay and start a fresh one at the same address, letting `configure` adjust it first.
+    /// Unless it restores a state file, the new relay knows nothing of the session, so the host and
+    /// clients have to register again.
+    pub fn restart_relay<F>(&mut self, configure: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut NeonRelay<MemoryTransport>),
+    {
+        self.network.close(self.relay_addr);
+        start_relay(&self.network, configure)?;
+        Ok(())
+    }
+
+    /// Get a client by index
+    pub fn client(&mut self, index: usize) -> &mut NeonClient<MemoryTransport> {
+        &mut self.clients[index]
+    }
+
+    /// Number of clients in the cluster
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Run a closure against the host while its thread is paused
+    pub fn host<R>(&self, f: impl FnOnce(&mut NeonHost<MemoryTransport>) -> R) -> R {
+        f(&mut self.host.lock().unwrap())
+    }
+
+    /// The network every cluster endpoint is bound to
+    pub fn network(&self) -> &MemoryNetwork {
+        &self.network
+    }
+
+    pub fn relay_addr(&self) -> SocketAddr {
+        self.relay_addr
+    }
+
+    pub fn session_id(&self) -> u32 {
+        self.session_id
+    }
+
+    /// Process packets on every client once
+    pub fn pump(&mut self) -> Result<(), Error> {
+        for client in &mut self.clients {
+            client.process_packets()?;
+        }
+        Ok(())
+    }
+
+    /// Pump clients whenever traffic moves (and at least every 10ms) until `condition` holds
+    /// or `timeout` elapses.
+    /// Returns whether the condition was met.
+    pub fn run_until<F>(&mut self, timeout: Duration, mut condition: F) -> Result<bool, Error>
+    where
+        F: FnMut(&mut Self) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let seen = self.network.delivered();
+            self.pump()?;
+            if condition(self) {
+                return Ok(true);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            self.network.wait_for_delivery(seen, (deadline - now).min(IDLE_WAKE));
+        }
+    }
+
+    /// Stop the host thread and report whether it exited cleanly
+    pub fn shutdown(mut self) -> Result<(), Error> {
+        self.stop_host()
+    }
+
+    fn stop_host(&mut self) -> Result<(), Error> {
+        self.running.store(false, Ordering::Relaxed);
+        match self.host_thread.take().map(|thread| thread.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(Error::other("Cluster host thread panicked")),
+            None => Err(Error::new(ErrorKind::NotConnected, "Cluster already shut down")),
+        }
+    }
+}
+
+impl Drop for LocalCluster {
+    fn drop(&mut self) {
+        if self.host_thread.is_some() {
+            let _ = self.stop_host();
+        }
+    }
+}
+
+/// Bind a single-worker relay at CLUSTER_RELAY_ADDR and run it on a background thread
+fn start_relay<F>(network: &MemoryNetwork, configure: F) -> Result<SocketAddr, Error>
+where
+    F: FnOnce(&mut NeonRelay<MemoryTransport>),
+{
+    let transport = network.bind(CLUSTER_RELAY_ADDR)?;
+    let relay_addr = transport.local_addr()?;
+    let mut relay = NeonRelay::with_transport(transport, RelayConfig { worker_threads: 1, ..RelayConfig::default() })?;
+    configure(&mut relay);
+    thread::Builder::new()
+        .name("neon-cluster-relay".to_string())
+        .spawn(move || relay.start())?;
+    Ok(relay_addr)
+}
@@ -0,0 +1,143 @@
+use std::io::Error;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::client::NeonClient;
+use crate::host::NeonHost;
+use crate::transport::Transport;
+
+/// Ticks run back to back before the driver gives up catching up and drops the backlog
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+/// A client or host a TickDriver can run
+pub trait Tickable {
+    fn process_packets(&mut self) -> Result<(), Error>;
+
+    /// Tick rate the session runs at, if known yet
+    fn tick_rate(&self) -> Option<u16>;
+}
+
+impl<T: Transport> Tickable for NeonClient<T> {
+    fn process_packets(&mut self) -> Result<(), Error> {
+        NeonClient::process_packets(self)
+    }
+
+    fn tick_rate(&self) -> Option<u16> {
+        NeonClient::tick_rate(self)
+    }
+}
+
+impl<T: Transport> Tickable for NeonHost<T> {
+    fn process_packets(&mut self) -> Result<(), Error> {
+        NeonHost::process_packets(self)
+    }
+
+    fn tick_rate(&self) -> Option<u16> {
+        Some(NeonHost::tick_rate(self))
+    }
+}
+
+/// Runs a game update closure at the session's tick rate, processing packets before each tick.
+/// Ticks are scheduled against absolute deadlines so sleep overshoot doesn't accumulate; if the
+/// loop falls more than a few ticks behind, the backlog is dropped rather than run in a burst.
+///
+/// The driver follows the node's tick rate, so a client driver switches to the host's rate
+/// once SessionConfig arrives.
+pub struct TickDriver {
+    tick: u64,
+    tick_rate: u16,
+    interval: Duration,
+    next_tick: Instant,
+    skipped: u64,
+}
+
+impl TickDriver {
+    /// Create a driver that ticks at `tick_rate` until the node reports its own
+    pub fn new(tick_rate: u16) -> Self {
+        let tick_rate = tick_rate.max(1);
+        Self {
+            tick: 0,
+            tick_rate,
+            interval: Duration::from_secs(1) / tick_rate as u32,
+            next_tick: Instant::now(),
+            skipped: 0,
+        }
+    }
+
+    /// Number of the next tick to run (ticks run so far plus ticks skipped)
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn tick_rate(&self) -> u16 {
+        self.tick_rate
+    }
+
+    /// Time between ticks
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Ticks dropped because the loop fell too far behind
+    pub fn skipped_ticks(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Time left before the next tick is due
+    pub fn time_until_next_tick(&self) -> Duration {
+        self.next_tick.saturating_duration_since(Instant::now())
+    }
+
+    /// Change the tick rate; the next tick is rescheduled one new interval from now
+    pub fn set_tick_rate(&mut self, tick_rate: u16) {
+        let tick_rate = tick_rate.max(1);
+        if tick_rate != self.tick_rate {
+            self.tick_rate = tick_rate;
+            self.interval = Duration::from_secs(1) / tick_rate as u32;
+            self.next_tick = Instant::now() + self.interval;
+        }
+    }
+
+    /// Process packets, then run every tick that is due. Returns how many ticks ran.
+    /// Does not sleep; call again around `time_until_next_tick`.
+    pub fn step<N, F>(&mut self, node: &mut N, mut on_tick: F) -> Result<u32, Error>
+    where
+        N: Tickable,
+        F: FnMut(&mut N, u64) -> Result<(), Error>,
+    {
+        if let Some(rate) = node.tick_rate() {
+            self.set_tick_rate(rate);
+        }
+        node.process_packets()?;
+
+        let mut ran = 0;
+        while Instant::now() >= self.next_tick {
+            if ran == MAX_CATCH_UP_TICKS {
+                let behind = (Instant::now() - self.next_tick).as_nanos() / self.interval.as_nanos();
+                let dropped = behind as u64 + 1;
+                self.skipped += dropped;
+                self.tick += dropped;
+                self.next_tick += self.interval * dropped as u32;
+                break;
+            }
+
+            on_tick(node, self.tick)?;
+            self.tick += 1;
+            self.next_tick += self.interval;
+            ran += 1;
+        }
+        Ok(ran)
+    }
+
+    /// Step and sleep until the next tick, forever (blocks)
+    pub fn run<N, F>(&mut self, node: &mut N, mut on_tick: F) -> Result<(), Error>
+    where
+        N: Tickable,
+        F: FnMut(&mut N, u64) -> Result<(), Error>,
+    {
+        loop {
+            self.step(node, &mut on_tick)?;
+            sleep(self.time_until_next_tick());
+        }
+    }
+}
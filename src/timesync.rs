@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Number of recent exchanges kept for filtering
+const SAMPLE_WINDOW: usize = 8;
+
+/// Skew is only estimated once samples span at least this long (microseconds)
+const MIN_SKEW_SPAN: f64 = 2_000_000.0;
+
+/// Largest clock drift believed, as a fraction (500 ppm)
+const MAX_SKEW: f64 = 0.0005;
+
+/// Default host clock: microseconds since the Unix epoch
+pub fn unix_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// Signed `later - earlier` between two readings of a u64 clock, taken the short way round: a reading
+/// just past u64::MAX counts as after one just before it, and a clock that stepped back gives a negative delta
+pub fn timestamp_delta(earlier: u64, later: u64) -> i64 {
+    later.wrapping_sub(earlier) as i64
+}
+
+/// Time from `earlier` to `later` on a u64 clock, or 0 if the clock went backwards in between
+pub fn elapsed_between(earlier: u64, later: u64) -> u64 {
+    timestamp_delta(earlier, later).max(0) as u64
+}
+
+/// Monotonic clock counting from its own epoch, immune to wall clock changes. Clients start a new one
+/// for each connection.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalClock {
+    epoch: Instant,
+}
+
+impl LocalClock {
+    pub fn new() -> Self {
+        Self { epoch: Instant::now() }
+    }
+
+    /// Microseconds since the epoch
+    pub fn now(&self) -> u64 {
+        self.epoch.elapsed().as_micros() as u64
+    }
+
+    /// Milliseconds since the epoch
+    pub fn now_millis(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+}
+
+impl Default for LocalClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// Local time halfway through the exchange
+    local_mid: f64,
+    /// Host clock minus local clock
+    offset: f64,
+    round_trip: u64,
+}
+
+/// NTP-style estimate of a remote clock from request/response timestamps.
+/// The offset comes from the lowest-latency recent exchange, since queueing delay
+/// is what makes samples asymmetric; drift is fitted across the whole window.
+#[derive(Debug, Clone, Default)]
+pub struct ClockEstimator {
+    samples: VecDeque<Sample>,
+}
+
+impl ClockEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an exchange: request sent at `local_sent`, stamped `remote_time` by the host,
+    /// and answered at `local_received`, both local times from the same clock
+    pub fn add_sample(&mut self, local_sent: u64, remote_time: u64, local_received: u64) {
+        let round_trip = timestamp_delta(local_sent, local_received);
+        if round_trip < 0 {
+            return;
+        }
+        let local_mid = local_sent as f64 + round_trip as f64 / 2.0;
+        if self.samples.len() == SAMPLE_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            local_mid,
+            offset: remote_time as f64 - local_mid,
+            round_trip: round_trip as u64,
+        });
+    }
+
+    /// Whether at least one exchange has completed
+    pub fn is_synced(&self) -> bool {
+        !self.samples.is_empty()
+    }
+
+    /// Round trip of the best recent exchange, in microseconds
+    pub fn round_trip(&self) -> Option<u64> {
+        self.best().map(|s| s.round_trip)
+    }
+
+    /// Estimated drift of the remote clock relative to ours (e.g. 0.0001 = 100 ppm fast)
+    pub fn skew(&self) -> f64 {
+        let span = match (self.samples.front(), self.samples.back()) {
+            (Some(first), Some(last)) => last.local_mid - first.local_mid,
+            _ => return 0.0,
+        };
+        if span < MIN_SKEW_SPAN {
+            return 0.0;
+        }
+
+        // Least-squares slope of offset against local time
+        let n = self.samples.len() as f64;
+        let mean_x = self.samples.iter().map(|s| s.local_mid).sum::<f64>() / n;
+        let mean_y = self.samples.iter().map(|s| s.offset).sum::<f64>() / n;
+        let (mut num, mut den) = (0.0, 0.0);
+        for s in &self.samples {
+            num += (s.local_mid - mean_x) * (s.offset - mean_y);
+            den += (s.local_mid - mean_x) * (s.local_mid - mean_x);
+        }
+        if den == 0.0 {
+            return 0.0;
+        }
+        (num / den).clamp(-MAX_SKEW, MAX_SKEW)
+    }
+
+    /// Estimated remote minus local time at the given local time, in microseconds
+    pub fn offset_at(&self, local_time: u64) -> Option<i64> {
+        let best = self.best()?;
+        let drift = self.skew() * (local_time as f64 - best.local_mid);
+        Some((best.offset + drift).round() as i64)
+    }
+
+    /// Estimated remote clock reading at the given local time
+    pub fn remote_time_at(&self, local_time: u64) -> Option<u64> {
+        let offset = self.offset_at(local_time)?;
+        Some((local_time as i64).saturating_add(offset).max(0) as u64)
+    }
+
+    fn best(&self) -> Option<&Sample> {
+        self.samples.iter().min_by_key(|s| s.round_trip)
+    }
+}
+
+/// Smoothed round trip and jitter, kept the way TCP keeps them (RFC 6298): each sample moves the
+/// average an eighth of the way towards it and the jitter a quarter of the way towards its distance
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RttEstimator {
+    latest: Option<Duration>,
+    smoothed: Option<Duration>,
+    jitter: Duration,
+    samples: u64,
+}
+
+impl RttEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_sample(&mut self, rtt: Duration) {
+        self.latest = Some(rtt);
+        self.samples += 1;
+        match self.smoothed {
+            None => {
+                self.smoothed = Some(rtt);
+                self.jitter = rtt / 2;
+            }
+            Some(smoothed) => {
+                self.jitter = (self.jitter * 3 + smoothed.abs_diff(rtt)) / 4;
+                self.smoothed = Some((smoothed * 7 + rtt) / 8);
+            }
+        }
+    }
+
+    /// Most recent round trip
+    pub fn latest(&self) -> Option<Duration> {
+        self.latest
+    }
+
+    /// Smoothed round trip (None until the first sample)
+    pub fn smoothed(&self) -> Option<Duration> {
+        self.smoothed
+    }
+
+    /// Smoothed variation between round trips
+    pub fn jitter(&self) -> Duration {
+        self.jitter
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+}
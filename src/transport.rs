@@ -0,0 +1,500 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::socket_options::SocketOptions;
+
+/// Datagram transport the client, host, and relay send through.
+/// Receive calls follow UdpSocket semantics: WouldBlock when non-blocking and nothing is
+/// waiting, WouldBlock or TimedOut when a read timeout elapses.
+pub trait Transport: Send + Sized + 'static {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, Error>;
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error>;
+
+    fn local_addr(&self) -> Result<SocketAddr, Error>;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error>;
+
+    /// Another handle to the same endpoint, e.g. for a worker thread
+    fn try_clone(&self) -> Result<Self, Error>;
+
+    /// Block until a datagram is waiting or the timeout elapses.
+    /// Returns true if the transport is readable.
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, Error>;
+
+    /// Stop an unreachable peer failing later receives with ConnectionReset, as Windows UDP
+    /// sockets do after a send to a closed port
+    fn disable_connection_reset(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Apply buffer sizes, TTL and DSCP marking to the underlying socket.
+    /// Transports without one ignore them.
+    fn apply_socket_options(&self, _options: &SocketOptions) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Open a replacement able to reach `addr` if this transport can't, e.g. an IPv4 socket
+    /// asked to talk to an IPv6 relay
+    fn reopen_for(&self, _addr: &SocketAddr) -> Result<Option<Self>, Error> {
+        Ok(None)
+    }
+
+    /// Receive up to `bufs.len()` datagrams without blocking.
+    /// Returns (length, source) for each buffer filled, in order from the front.
+    fn recv_batch(&self, bufs: &mut [Vec<u8>]) -> Result<Vec<(usize, Option<SocketAddr>)>, Error> {
+        recv_each(self, bufs)
+    }
+
+    /// Send several datagrams, returning how many went out before the first failure
+    fn send_batch(&self, datagrams: &[(Vec<u8>, SocketAddr)]) -> Result<usize, Error> {
+        send_each(self, datagrams)
+    }
+}
+
+/// Whether a receive failed only because an earlier send was refused. Windows reports the ICMP
+/// port unreachable a send to a departed peer brings back this way; the socket is still fine.
+pub fn is_connection_reset(error: &Error) -> bool {
+    error.kind() == ErrorKind::ConnectionReset
+}
+
+/// Receive one datagram, skipping the resets of earlier sends
+pub fn recv_skipping_resets<T: Transport>(transport: &T, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+    loop {
+        match transport.recv_from(buf) {
+            Err(e) if is_connection_reset(&e) => continue,
+            result => return result,
+        }
+    }
+}
+
+fn recv_each<T: Transport>(transport: &T, bufs: &mut [Vec<u8>]) -> Result<Vec<(usize, Option<SocketAddr>)>, Error> {
+    let mut received = Vec::new();
+    for buf in bufs.iter_mut() {
+        match recv_skipping_resets(transport, buf) {
+            Ok((size, addr)) => received.push((size, Some(addr))),
+            Err(e) if e.kind() == ErrorKind::WouldBlock && !received.is_empty() => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(received)
+}
+
+fn send_each<T: Transport>(transport: &T, datagrams: &[(Vec<u8>, SocketAddr)]) -> Result<usize, Error> {
+    for (sent, (data, addr)) in datagrams.iter().enumerate() {
+        if let Err(e) = transport.send_to(data, *addr) {
+            return if sent == 0 { Err(e) } else { Ok(sent) };
+        }
+    }
+    Ok(datagrams.len())
+}
+
+impl Transport for UdpSocket {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, Error> {
+        UdpSocket::send_to(self, data, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, Error> {
+        UdpSocket::local_addr(self)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        UdpSocket::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        UdpSocket::set_read_timeout(self, timeout)
+    }
+
+    fn try_clone(&self) -> Result<Self, Error> {
+        UdpSocket::try_clone(self)
+    }
+
+    #[cfg(unix)]
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, Error> {
+        use std::os::fd::AsRawFd;
+
+        let mut fds = [libc::pollfd {
+            fd: self.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout_ms) };
+        if ready < 0 {
+            let err = Error::last_os_error();
+            return if err.kind() == ErrorKind::Interrupted { Ok(false) } else { Err(err) };
+        }
+        Ok(ready > 0)
+    }
+
+    #[cfg(not(unix))]
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, Error> {
+        if timeout.is_zero() {
+            return Ok(false);
+        }
+        UdpSocket::set_nonblocking(self, false)?;
+        UdpSocket::set_read_timeout(self, Some(timeout))?;
+        let result = self.peek_from(&mut [0u8; 1]);
+        UdpSocket::set_nonblocking(self, true)?;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => Ok(false),
+            // A datagram larger than the peek buffer still means the socket is readable
+            Err(_) => Ok(true),
+        }
+    }
+
+    #[cfg(windows)]
+    fn disable_connection_reset(&self) -> Result<(), Error> {
+        crate::winsock::disable_udp_connreset(self)
+    }
+
+    fn apply_socket_options(&self, options: &SocketOptions) -> Result<(), Error> {
+        options.apply(self)
+    }
+
+    fn reopen_for(&self, addr: &SocketAddr) -> Result<Option<Self>, Error> {
+        if UdpSocket::local_addr(self)?.is_ipv4() == addr.is_ipv4() {
+            return Ok(None);
+        }
+        let socket = UdpSocket::bind(crate::resolve::unspecified_bind_addr(addr))?;
+        socket.set_nonblocking(true)?;
+        Ok(Some(socket))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn recv_batch(&self, bufs: &mut [Vec<u8>]) -> Result<Vec<(usize, Option<SocketAddr>)>, Error> {
+        crate::mmsg::recv_batch(self, bufs)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn send_batch(&self, datagrams: &[(Vec<u8>, SocketAddr)]) -> Result<usize, Error> {
+        crate::mmsg::send_batch(self, datagrams)
+    }
+}
+
+/// First port handed out to memory endpoints bound to port 0
+const MEMORY_EPHEMERAL_PORT: u16 = 49152;
+
+struct Mailbox {
+    queue: Mutex<VecDeque<(Vec<u8>, SocketAddr)>>,
+    ready: Condvar,
+    closed: AtomicBool,
+}
+
+#[derive(Default)]
+struct NetworkState {
+    // Mailbox plus the number of open handles bound to it
+    endpoints: HashMap<SocketAddr, (Arc<Mailbox>, usize)>,
+    next_port: u16,
+    delivered: u64,
+    max_datagram_size: Option<usize>,
+}
+
+/// An in-process datagram network. Endpoints bound on the same network can reach each other;
+/// delivery is instant, lossless, and in order.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    state: Arc<Mutex<NetworkState>>,
+    traffic: Arc<Condvar>,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind an endpoint. Port 0 picks a free port; an unspecified IP binds to loopback
+    /// so the address can be handed to peers.
+    pub fn bind(&self, addr: &str) -> Result<MemoryTransport, Error> {
+        let mut addr: SocketAddr = addr
+            .parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid bind address"))?;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if addr.port() == 0 {
+            loop {
+                let port = MEMORY_EPHEMERAL_PORT.wrapping_add(state.next_port);
+                state.next_port = state.next_port.wrapping_add(1);
+                addr.set_port(port);
+                if port != 0 && !state.endpoints.contains_key(&addr) {
+                    break;
+                }
+            }
+        } else if state.endpoints.contains_key(&addr) {
+            return Err(Error::new(ErrorKind::AddrInUse, format!("{} is already bound", addr)));
+        }
+
+        let mailbox = Arc::new(Mailbox {
+            queue: Mutex::new(VecDeque::new()),
+            ready: Condvar::new(),
+            closed: AtomicBool::new(false),
+        });
+        state.endpoints.insert(addr, (mailbox.clone(), 1));
+
+        Ok(MemoryTransport {
+            network: self.clone(),
+            addr,
+            mailbox,
+            nonblocking: AtomicBool::new(false),
+            read_timeout: Mutex::new(None),
+        })
+    }
+
+    /// Tear down the endpoint bound at `addr`, as if its process died: receives on its handles fail
+    /// from now on and the address can be bound again. Returns false if nothing was bound there.
+    pub fn close(&self, addr: SocketAddr) -> bool {
+        let Some((mailbox, _)) = self.state.lock().unwrap().endpoints.remove(&addr) else {
+            return false;
+        };
+        let _queue = mailbox.queue.lock().unwrap();
+        mailbox.closed.store(true, Ordering::Relaxed);
+        mailbox.ready.notify_all();
+        true
+    }
+
+    /// Silently drop datagrams longer than this, like a path with a smaller MTU (None removes the limit)
+    pub fn set_max_datagram_size(&self, max_datagram_size: Option<usize>) {
+        self.state.lock().unwrap().max_datagram_size = max_datagram_size;
+    }
+
+    /// Total datagrams delivered on this network so far
+    pub fn delivered(&self) -> u64 {
+        self.state.lock().unwrap().delivered
+    }
+
+    /// Block until more than `seen` datagrams have been delivered or the timeout elapses.
+    /// Returns true if new traffic arrived.
+    pub fn wait_for_delivery(&self, seen: u64, timeout: Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .traffic
+            .wait_timeout_while(state, timeout, |state| state.delivered <= seen)
+            .unwrap();
+        state.delivered > seen
+    }
+
+    /// Number of datagrams waiting to be read at an address
+    pub fn pending(&self, addr: SocketAddr) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .endpoints
+            .get(&addr)
+            .map(|(mailbox, _)| mailbox.queue.lock().unwrap().len())
+            .unwrap_or(0)
+    }
+}
+
+/// Transport endpoint on a MemoryNetwork
+pub struct MemoryTransport {
+    network: MemoryNetwork,
+    addr: SocketAddr,
+    mailbox: Arc<Mailbox>,
+    nonblocking: AtomicBool,
+    read_timeout: Mutex<Option<Duration>>,
+}
+
+impl MemoryTransport {
+    fn check_open(&self) -> Result<(), Error> {
+        if self.mailbox.closed.load(Ordering::Relaxed) {
+            return Err(Error::new(ErrorKind::NotConnected, format!("{} was closed", self.addr)));
+        }
+        Ok(())
+    }
+
+    /// Wait on the mailbox until a datagram arrives or the deadline passes
+    fn wait_for_datagram(&self, deadline: Option<Instant>) -> std::sync::MutexGuard<'_, VecDeque<(Vec<u8>, SocketAddr)>> {
+        let mut queue = self.mailbox.queue.lock().unwrap();
+        while queue.is_empty() && !self.mailbox.closed.load(Ordering::Relaxed) {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    queue = self.mailbox.ready.wait_timeout(queue, deadline - now).unwrap().0;
+                }
+                None => queue = self.mailbox.ready.wait(queue).unwrap(),
+            }
+        }
+        queue
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, Error> {
+        let mut state = self.network.state.lock().unwrap();
+        if state.max_datagram_size.is_some_and(|max| data.len() > max) {
+            return Ok(data.len());
+        }
+        // Like UDP, sending to nobody silently drops the datagram
+        if let Some((mailbox, _)) = state.endpoints.get(&addr) {
+            mailbox.queue.lock().unwrap().push_back((data.to_vec(), self.addr));
+            mailbox.ready.notify_all();
+            state.delivered += 1;
+            self.network.traffic.notify_all();
+        }
+        Ok(data.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+        self.check_open()?;
+        let mut queue = if self.nonblocking.load(Ordering::Relaxed) {
+            self.mailbox.queue.lock().unwrap()
+        } else {
+            let timeout = *self.read_timeout.lock().unwrap();
+            self.wait_for_datagram(timeout.map(|t| Instant::now() + t))
+        };
+
+        let Some((data, from)) = queue.pop_front() else {
+            return Err(Error::new(ErrorKind::WouldBlock, "No datagram available"));
+        };
+        // Datagrams larger than the buffer are truncated, as with UDP
+        let size = data.len().min(buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        Ok((size, from))
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.addr)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Cannot set a zero read timeout"));
+        }
+        *self.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> Result<Self, Error> {
+        if let Some((mailbox, handles)) = self.network.state.lock().unwrap().endpoints.get_mut(&self.addr)
+            && Arc::ptr_eq(mailbox, &self.mailbox)
+        {
+            *handles += 1;
+        }
+        Ok(MemoryTransport {
+            network: self.network.clone(),
+            addr: self.addr,
+            mailbox: self.mailbox.clone(),
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Relaxed)),
+            read_timeout: Mutex::new(*self.read_timeout.lock().unwrap()),
+        })
+    }
+
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, Error> {
+        let readable = !self.wait_for_datagram(Some(Instant::now() + timeout)).is_empty();
+        self.check_open()?;
+        Ok(readable)
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        let mut state = self.network.state.lock().unwrap();
+        // A closed endpoint's address may already belong to a new one
+        if let Some((mailbox, handles)) = state.endpoints.get_mut(&self.addr)
+            && Arc::ptr_eq(mailbox, &self.mailbox)
+        {
+            *handles -= 1;
+            if *handles == 0 {
+                state.endpoints.remove(&self.addr);
+            }
+        }
+    }
+}
+
+/// Datagrams to send, with where to
+pub type Outgoing = Vec<(Vec<u8>, SocketAddr)>;
+
+#[derive(Default)]
+struct Queues {
+    incoming: VecDeque<(Vec<u8>, SocketAddr)>,
+    outgoing: Outgoing,
+}
+
+/// Transport the caller feeds and drains by hand, so the client, host, or relay can run behind any
+/// socket or runtime. Receives never block: with nothing pushed they fail with WouldBlock.
+#[derive(Clone)]
+pub struct QueueTransport {
+    addr: SocketAddr,
+    queues: Arc<Mutex<Queues>>,
+}
+
+impl QueueTransport {
+    /// Create a transport that reports `addr` as its local address
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            queues: Arc::default(),
+        }
+    }
+
+    /// Queue a datagram received from `from` to be read
+    pub fn push_incoming(&self, data: &[u8], from: SocketAddr) {
+        self.queues.lock().unwrap().incoming.push_back((data.to_vec(), from));
+    }
+
+    /// Take every datagram sent since the last call
+    pub fn take_outgoing(&self) -> Outgoing {
+        std::mem::take(&mut self.queues.lock().unwrap().outgoing)
+    }
+}
+
+impl Transport for QueueTransport {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, Error> {
+        self.queues.lock().unwrap().outgoing.push((data.to_vec(), addr));
+        Ok(data.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+        let Some((data, from)) = self.queues.lock().unwrap().incoming.pop_front() else {
+            return Err(Error::new(ErrorKind::WouldBlock, "No datagram pushed"));
+        };
+        let size = data.len().min(buf.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        Ok((size, from))
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.addr)
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn try_clone(&self) -> Result<Self, Error> {
+        Ok(self.clone())
+    }
+
+    fn wait_readable(&self, _timeout: Duration) -> Result<bool, Error> {
+        Ok(!self.queues.lock().unwrap().incoming.is_empty())
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+
+use crate::decoder::Decoder;
+use crate::protocol::HEADER_SIZE;
+
+pub type JitterBufferFactory = Box<dyn FnMut(u16) -> Box<dyn JitterBuffer> + Send>; // (peer_id) -> buffer
+
+/// Largest encoded frame that fits in one datagram after the header and sequence number
+pub const MAX_VOICE_FRAME_SIZE: usize = 1024 - HEADER_SIZE - 4;
+
+/// One encoded audio frame (e.g. Opus) and its position in the sender's stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoiceFrame {
+    pub sequence: u32,
+    pub data: Vec<u8>,
+}
+
+/// Reorders and paces one peer's incoming frames for playback.
+/// Implement this to plug in an audio stack's own buffer; `FixedJitterBuffer` is a simple default.
+pub trait JitterBuffer: Send {
+    /// Queue a frame as it arrives from the network, in whatever order that is
+    fn push(&mut self, frame: VoiceFrame);
+
+    /// Called once per frame period by the audio clock. None means there is nothing to play:
+    /// either the peer is silent or the next frame is missing and should be concealed.
+    fn pop(&mut self) -> Option<VoiceFrame>;
+}
+
+/// Jitter buffer with a fixed playout delay: waits for `depth` frames before starting,
+/// then plays one frame per pop in sequence order. Frames that arrive after their turn
+/// are dropped. When it runs dry it waits for `depth` frames again.
+pub struct FixedJitterBuffer {
+    depth: usize,
+    frames: BTreeMap<u32, Vec<u8>>,
+    next: Option<u32>,
+    playing: bool,
+}
+
+impl FixedJitterBuffer {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth: depth.max(1),
+            frames: BTreeMap::new(),
+            next: None,
+            playing: false,
+        }
+    }
+
+    /// Frames waiting to be played
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl JitterBuffer for FixedJitterBuffer {
+    fn push(&mut self, frame: VoiceFrame) {
+        if self.next.is_some_and(|next| frame.sequence < next) {
+            return;
+        }
+        self.frames.insert(frame.sequence, frame.data);
+        // Bound memory if the consumer stops popping
+        while self.frames.len() > self.depth * 4 {
+            self.frames.pop_first();
+        }
+    }
+
+    fn pop(&mut self) -> Option<VoiceFrame> {
+        if !self.playing {
+            if self.frames.len() < self.depth {
+                return None;
+            }
+            self.playing = true;
+            self.next = self.frames.keys().next().copied();
+        }
+
+        let sequence = self.next?;
+        self.next = Some(sequence.wrapping_add(1));
+        match self.frames.remove(&sequence) {
+            Some(data) => Some(VoiceFrame { sequence, data }),
+            None => {
+                if self.frames.is_empty() {
+                    self.playing = false;
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Outgoing frame numbering plus per-peer jitter buffers, shared by client and host
+#[derive(Default)]
+pub struct VoiceChannel {
+    next_sequence: u32,
+    buffers: HashMap<u16, Box<dyn JitterBuffer>>,
+    buffer_factory: Option<JitterBufferFactory>,
+}
+
+impl VoiceChannel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Buffer incoming frames, creating one buffer per peer with `factory`
+    pub fn set_jitter_buffer(&mut self, factory: JitterBufferFactory) {
+        self.buffer_factory = Some(factory);
+        self.buffers.clear();
+    }
+
+    /// Number the next outgoing frame and encode it as a packet payload
+    pub fn encode(&mut self, frame: &[u8]) -> Result<Vec<u8>, Error> {
+        if frame.len() > MAX_VOICE_FRAME_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Voice frames are limited to {} bytes", MAX_VOICE_FRAME_SIZE),
+            ));
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+
+        let mut bytes = Vec::with_capacity(4 + frame.len());
+        bytes.extend_from_slice(&sequence.to_le_bytes());
+        bytes.extend_from_slice(frame);
+        Ok(bytes)
+    }
+
+    /// Decode a received frame and queue it in the sender's jitter buffer, if buffering
+    pub fn receive(&mut self, from_id: u16, data: &[u8]) -> Result<VoiceFrame, Error> {
+        let mut decoder = Decoder::new(data, "Voice frame");
+        let frame = VoiceFrame {
+            sequence: decoder.u32()?,
+            data: decoder.rest().to_vec(),
+        };
+
+        if let Some(factory) = &mut self.buffer_factory {
+            self.buffers
+                .entry(from_id)
+                .or_insert_with(|| factory(from_id))
+                .push(frame.clone());
+        }
+        Ok(frame)
+    }
+
+    /// Next frame to play for a peer (always None without a jitter buffer)
+    pub fn pop(&mut self, peer_id: u16) -> Option<VoiceFrame> {
+        self.buffers.get_mut(&peer_id)?.pop()
+    }
+
+    /// Drop a peer's buffered audio
+    pub fn forget(&mut self, peer_id: u16) {
+        self.buffers.remove(&peer_id);
+    }
+}
@@ -0,0 +1,46 @@
+use std::ffi::c_void;
+use std::io::Error;
+use std::net::UdpSocket;
+use std::os::windows::io::AsRawSocket;
+use std::ptr;
+
+/// _WSAIOW(IOC_VENDOR, 12): whether ICMP port unreachable fails the next receive with WSAECONNRESET
+const SIO_UDP_CONNRESET: u32 = 0x9800_000C;
+
+#[link(name = "ws2_32")]
+unsafe extern "system" {
+    fn WSAIoctl(
+        socket: usize,
+        control_code: u32,
+        in_buffer: *const c_void,
+        in_len: u32,
+        out_buffer: *mut c_void,
+        out_len: u32,
+        bytes_returned: *mut u32,
+        overlapped: *mut c_void,
+        completion_routine: *mut c_void,
+    ) -> i32;
+}
+
+/// Stop a send to a closed port failing the socket's next recv_from with ConnectionReset
+pub fn disable_udp_connreset(socket: &UdpSocket) -> Result<(), Error> {
+    let enabled: u32 = 0;
+    let mut returned: u32 = 0;
+    let result = unsafe {
+        WSAIoctl(
+            socket.as_raw_socket() as usize,
+            SIO_UDP_CONNRESET,
+            &enabled as *const u32 as *const c_void,
+            size_of::<u32>() as u32,
+            ptr::null_mut(),
+            0,
+            &mut returned,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    if result != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
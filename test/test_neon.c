@@ -2,52 +2,52 @@
 #include <stdlib.h>
 #include <unistd.h>
 #include <pthread.h>
-#include "project_neon.h"
+#include "neon.h"
 
 // Client callbacks
-void on_pong(uint64_t response_time_ms, uint64_t timestamp) {
+void on_pong(uint64_t response_time_ms, uint64_t timestamp, void* user_data) {
     printf("[Client Callback] Pong received! RTT: %lu ms, Timestamp: %lu\n", 
            response_time_ms, timestamp);
 }
 
-void on_session_config(uint8_t version, uint16_t tick_rate, uint16_t max_packet_size) {
+void on_session_config(uint8_t version, uint16_t tick_rate, uint16_t max_packet_size, void* user_data) {
     printf("[Client Callback] Session Config - Version: %u, Tick Rate: %u Hz, Max Packet Size: %u bytes\n",
            version, tick_rate, max_packet_size);
 }
 
-void on_packet_type_registry(size_t count, const uint8_t* ids, const char** names, const char** descriptions) {
+void on_packet_type_registry(size_t count, const uint8_t* ids, const char* const* names, const char* const* descriptions, void* user_data) {
     printf("[Client Callback] Packet Type Registry received with %zu types:\n", count);
     for (size_t i = 0; i < count; i++) {
         printf("  [%u] %s - %s\n", ids[i], names[i], descriptions[i]);
     }
 }
 
-void on_unhandled_packet(uint8_t packet_type, uint8_t from_client_id) {
+void on_unhandled_packet(uint8_t packet_type, uint16_t from_client_id, void* user_data) {
     printf("[Client Callback] Unhandled packet type %u from client %u\n", 
            packet_type, from_client_id);
 }
 
-void on_wrong_destination(uint8_t my_id, uint8_t packet_destination_id) {
+void on_wrong_destination(uint16_t my_id, uint16_t packet_destination_id, void* user_data) {
     printf("[Client Callback] Wrong destination! My ID: %u, Packet for: %u\n",
            my_id, packet_destination_id);
 }
 
 // Host callbacks
-void on_client_connect(uint8_t client_id, const char* name, uint32_t session_id) {
+void on_client_connect(uint16_t client_id, const char* name, uint32_t session_id, void* user_data) {
     printf("[Host Callback] Client connected! ID: %u, Name: %s, Session: %u\n",
            client_id, name, session_id);
 }
 
-void on_client_deny(const char* name, const char* reason) {
+void on_client_deny(const char* name, const char* reason, void* user_data) {
     printf("[Host Callback] Client denied! Name: %s, Reason: %s\n",
            name, reason);
 }
 
-void on_ping_received(uint8_t from_client_id) {
+void on_ping_received(uint16_t from_client_id, void* user_data) {
     printf("[Host Callback] Ping received from client %u\n", from_client_id);
 }
 
-void on_host_unhandled_packet(uint8_t packet_type, uint8_t from_client_id) {
+void on_host_unhandled_packet(uint8_t packet_type, uint16_t from_client_id, void* user_data) {
     printf("[Host Callback] Unhandled packet type %u from client %u\n",
            packet_type, from_client_id);
 }
@@ -56,7 +56,7 @@ void* host_thread_func(void* arg) {
     NeonHostHandle* host = (NeonHostHandle*)arg;
     printf("[Host Thread] Starting host...\n");
     
-    if (!neon_host_start(host)) {
+    if (neon_host_start(host) != NEON_ERROR_CODE_OK) {
         printf("[Host Thread] Failed to start host\n");
         const char* err = neon_get_last_error();
         if (err) printf("[Host Thread] Error: %s\n", err);
@@ -85,10 +85,10 @@ int main() {
     
     // Register all host callbacks
     printf("[Main] Registering host callbacks...\n");
-    neon_host_set_client_connect_callback(host, on_client_connect);
-    neon_host_set_client_deny_callback(host, on_client_deny);
-    neon_host_set_ping_received_callback(host, on_ping_received);
-    neon_host_set_unhandled_packet_callback(host, on_host_unhandled_packet);
+    neon_host_set_client_connect_callback(host, on_client_connect, NULL);
+    neon_host_set_client_deny_callback(host, on_client_deny, NULL);
+    neon_host_set_ping_received_callback(host, on_ping_received, NULL);
+    neon_host_set_unhandled_packet_callback(host, on_host_unhandled_packet, NULL);
     
     // Start host in separate thread
     pthread_t host_thread;
@@ -116,22 +116,22 @@ int main() {
     
     // Register all client callbacks for both clients
     printf("[Main] Registering client 1 callbacks...\n");
-    neon_client_set_pong_callback(client1, on_pong);
-    neon_client_set_session_config_callback(client1, on_session_config);
-    neon_client_set_packet_type_registry_callback(client1, on_packet_type_registry);
-    neon_client_set_unhandled_packet_callback(client1, on_unhandled_packet);
-    neon_client_set_wrong_destination_callback(client1, on_wrong_destination);
+    neon_client_set_pong_callback(client1, on_pong, NULL);
+    neon_client_set_session_config_callback(client1, on_session_config, NULL);
+    neon_client_set_packet_type_registry_callback(client1, on_packet_type_registry, NULL);
+    neon_client_set_unhandled_packet_callback(client1, on_unhandled_packet, NULL);
+    neon_client_set_wrong_destination_callback(client1, on_wrong_destination, NULL);
     
     printf("[Main] Registering client 2 callbacks...\n");
-    neon_client_set_pong_callback(client2, on_pong);
-    neon_client_set_session_config_callback(client2, on_session_config);
-    neon_client_set_packet_type_registry_callback(client2, on_packet_type_registry);
-    neon_client_set_unhandled_packet_callback(client2, on_unhandled_packet);
-    neon_client_set_wrong_destination_callback(client2, on_wrong_destination);
+    neon_client_set_pong_callback(client2, on_pong, NULL);
+    neon_client_set_session_config_callback(client2, on_session_config, NULL);
+    neon_client_set_packet_type_registry_callback(client2, on_packet_type_registry, NULL);
+    neon_client_set_unhandled_packet_callback(client2, on_unhandled_packet, NULL);
+    neon_client_set_wrong_destination_callback(client2, on_wrong_destination, NULL);
     
     // Connect client 1
     printf("\n[Main] Connecting client 1...\n");
-    if (neon_client_connect(client1, session_id, relay_addr)) {
+    if (neon_client_connect(client1, session_id, relay_addr) == NEON_ERROR_CODE_OK) {
         printf("[Main] Client 1 connected! ID: %u\n", neon_client_get_id(client1));
     } else {
         printf("[Main] Client 1 failed to connect\n");
@@ -143,7 +143,7 @@ int main() {
     
     // Connect client 2
     printf("\n[Main] Connecting client 2...\n");
-    if (neon_client_connect(client2, session_id, relay_addr)) {
+    if (neon_client_connect(client2, session_id, relay_addr) == NEON_ERROR_CODE_OK) {
         printf("[Main] Client 2 connected! ID: %u\n", neon_client_get_id(client2));
     } else {
         printf("[Main] Client 2 failed to connect\n");
@@ -156,7 +156,7 @@ int main() {
     
     // Test manual pings
     printf("\n[Main] Testing manual ping from client 1...\n");
-    if (neon_client_send_ping(client1)) {
+    if (neon_client_send_ping(client1) == NEON_ERROR_CODE_OK) {
         printf("[Main] Ping sent from client 1\n");
     } else {
         printf("[Main] Failed to send ping from client 1\n");
@@ -165,7 +165,7 @@ int main() {
     sleep(1);
     
     printf("\n[Main] Testing manual ping from client 2...\n");
-    if (neon_client_send_ping(client2)) {
+    if (neon_client_send_ping(client2) == NEON_ERROR_CODE_OK) {
         printf("[Main] Ping sent from client 2\n");
     } else {
         printf("[Main] Failed to send ping from client 2\n");
@@ -177,12 +177,12 @@ int main() {
     
     for (int i = 0; i < 150; i++) {
         if (neon_client_is_connected(client1)) {
-            if (!neon_client_process_packets(client1)) {
+            if (neon_client_process_packets(client1) != NEON_ERROR_CODE_OK) {
                 printf("[Main] Client 1 process_packets failed\n");
             }
         }
         if (neon_client_is_connected(client2)) {
-            if (!neon_client_process_packets(client2)) {
+            if (neon_client_process_packets(client2) != NEON_ERROR_CODE_OK) {
                 printf("[Main] Client 2 process_packets failed\n");
             }
         }
@@ -0,0 +1,221 @@
+#![cfg(feature = "admin-api")]
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use project_neon::NeonClient;
+use project_neon::protocol::{DisconnectReason, SessionMetadata};
+use project_neon::relay::NeonRelay;
+use project_neon::testing::LocalCluster;
+use project_neon::transport::MemoryTransport;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start a cluster whose relay serves the admin API on a free loopback port
+fn cluster_with_admin(session_id: u32, token: Option<&str>) -> (LocalCluster, SocketAddr) {
+    let mut admin = None;
+    let cluster = LocalCluster::with_relay(session_id, |relay: &mut NeonRelay<MemoryTransport>| {
+        admin = Some(relay.enable_admin_api("127.0.0.1:0").unwrap());
+        if let Some(token) = token {
+            relay.set_admin_token(token);
+        }
+    })
+    .unwrap();
+    (cluster, admin.unwrap())
+}
+
+/// Send one HTTP request and return the status code and body
+fn request(admin: SocketAddr, method: &str, path: &str, extra_headers: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(admin).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: relay\r\nContent-Length: {}\r\n{}\r\n{}",
+        method,
+        path,
+        body.len(),
+        extra_headers,
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, body.to_string())
+}
+
+fn get(admin: SocketAddr, path: &str) -> (u16, String) {
+    request(admin, "GET", path, "", "")
+}
+
+#[test]
+fn lists_sessions_and_their_peers() {
+    let (mut cluster, admin) = cluster_with_admin(160, None);
+    cluster.add_client("alice").unwrap();
+
+    // The client registers with the relay just after it's accepted
+    assert!(cluster.run_until(TIMEOUT, |_| get(admin, "/sessions").1.contains("\"peers\":2")).unwrap());
+    let (status, body) = get(admin, "/sessions");
+    assert_eq!(status, 200);
+    assert!(body.starts_with("{\"sessions\":[{\"session_id\":160,\"game_identifier\":0,\"host\":\"127.0.0.1:"), "{}", body);
+    assert!(body.contains("\"packets_forwarded\":") && body.contains("\"bytes_forwarded\":"), "{}", body);
+
+    let (status, body) = get(admin, "/sessions/160/peers");
+    assert_eq!(status, 200);
+    assert!(body.contains("\"client_id\":1,") && body.contains("\"host\":true"), "{}", body);
+    assert!(body.contains("\"client_id\":2,") && body.contains("\"name\":\"alice\",\"role\":\"player\""), "{}", body);
+    assert!(body.contains("\"bytes_sent\":") && body.contains("\"bytes_received\":"), "{}", body);
+
+    assert_eq!(get(admin, "/sessions/160/peers?game=0").0, 200);
+    assert_eq!(get(admin, "/sessions/160/peers?game=0xBEEF").0, 200);
+    assert_eq!(get(admin, "/sessions/160/peers?game=beef").0, 400);
+    assert_eq!(get(admin, "/sessions/999/peers").0, 404);
+    assert_eq!(get(admin, "/sessions/abc/peers").0, 400);
+    assert_eq!(get(admin, "/nothing").0, 404);
+    assert_eq!(request(admin, "PUT", "/sessions", "", "").0, 405);
+}
+
+#[test]
+fn lists_what_hosts_advertise() {
+    let (mut cluster, admin) = cluster_with_admin(161, None);
+    assert!(cluster.run_until(TIMEOUT, |_| get(admin, "/sessions").1.contains("\"session_id\":161")).unwrap());
+    let body = get(admin, "/sessions").1;
+    assert!(body.contains("\"name\":\"\",\"max_players\":0,\"properties\":{}"), "{}", body);
+
+    let metadata = SessionMetadata {
+        name: "Crab \"Cove\"".to_string(),
+        max_players: 8,
+        properties: vec![("map".to_string(), "dust".to_string()), ("mode".to_string(), "ctf".to_string())],
+    };
+    cluster.host(|host| {
+        host.set_session_metadata(metadata).unwrap();
+        host.begin_register().unwrap();
+    });
+
+    assert!(cluster.run_until(TIMEOUT, |_| get(admin, "/sessions").1.contains("\"max_players\":8")).unwrap());
+    let body = get(admin, "/sessions").1;
+    assert!(
+        body.contains("\"name\":\"Crab \\\"Cove\\\"\",\"max_players\":8,\"properties\":{\"map\":\"dust\",\"mode\":\"ctf\"}"),
+        "{}",
+        body
+    );
+}
+
+#[test]
+fn closed_sessions_drop_everyone_and_stay_closed() {
+    let (mut cluster, admin) = cluster_with_admin(161, None);
+    cluster.add_client("alice").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| get(admin, "/sessions").1.contains("\"peers\":2")).unwrap());
+
+    assert_eq!(request(admin, "DELETE", "/sessions/161", "", ""), (200, "{\"session_id\":161,\"removed\":2}".to_string()));
+    assert_eq!(request(admin, "DELETE", "/sessions/161", "", "").0, 404);
+
+    // The host keeps pinging and can't register again; the client's attempt to resume is refused
+    let error = cluster
+        .run_until(TIMEOUT, |cluster| {
+            cluster.client(0).send_game_packet(0x10, b"anyone there?").unwrap();
+            false
+        })
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(get(admin, "/sessions"), (200, "{\"sessions\":[]}".to_string()));
+
+    let mut late = NeonClient::with_transport("bob".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    let error = late.connect(161, cluster.relay_addr()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+}
+
+#[test]
+fn bans_drop_and_ignore_an_address() {
+    let (mut cluster, admin) = cluster_with_admin(162, None);
+    cluster.add_client("alice").unwrap();
+    let mut banned = NeonClient::with_transport("mallory".to_string(), cluster.network().bind("127.0.0.5:0").unwrap()).unwrap();
+    banned.connect(162, cluster.relay_addr()).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| get(admin, "/sessions").1.contains("\"peers\":3")).unwrap());
+
+    let (status, body) = request(admin, "POST", "/bans", "", "{\"ip\": \"127.0.0.5\", \"duration_secs\": 600}");
+    assert_eq!(status, 201);
+    assert_eq!(body, "{\"ip\":\"127.0.0.5\",\"duration_secs\":600,\"removed\":1}");
+
+    // Whatever the banned client sends is dropped, so it can't register again
+    for _ in 0..5 {
+        banned.send_game_packet(0x10, b"let me back in").unwrap();
+        banned.process_packets().unwrap();
+        thread::sleep(Duration::from_millis(50));
+    }
+    let (_, body) = get(admin, "/sessions/162/peers");
+    assert!(!body.contains("mallory") && body.contains("alice"), "{}", body);
+
+    assert_eq!(request(admin, "POST", "/bans", "", "{\"ip\": \"not an ip\"}").0, 400);
+    assert_eq!(request(admin, "POST", "/bans", "", "[1, 2]").0, 400);
+}
+
+#[test]
+fn bans_tell_the_host_and_peers_who_left() {
+    let (mut cluster, admin) = cluster_with_admin(163, None);
+    let alice = cluster.add_client("alice").unwrap();
+    let mut banned = NeonClient::with_transport("mallory".to_string(), cluster.network().bind("127.0.0.6:0").unwrap()).unwrap();
+    banned.connect(163, cluster.relay_addr()).unwrap();
+    let banned_id = banned.client_id().unwrap();
+
+    let disconnects = Arc::new(Mutex::new(Vec::new()));
+    let seen = disconnects.clone();
+    cluster.host(|host| host.on_client_disconnect(move |client_id, reason| seen.lock().unwrap().push((client_id, reason))));
+    let left = Arc::new(Mutex::new(Vec::new()));
+    let seen = left.clone();
+    cluster.client(alice).on_peer_left(move |client_id, _| seen.lock().unwrap().push(client_id));
+    assert!(cluster.run_until(TIMEOUT, |_| get(admin, "/sessions").1.contains("\"peers\":3")).unwrap());
+
+    assert_eq!(request(admin, "POST", "/bans", "", "{\"ip\": \"127.0.0.6\"}").0, 201);
+    assert!(cluster.run_until(TIMEOUT, |_| !left.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*disconnects.lock().unwrap(), vec![(banned_id, DisconnectReason::Banned)]);
+    assert_eq!(*left.lock().unwrap(), vec![banned_id]);
+    assert_eq!(cluster.host(|host| host.client_count()), 1);
+}
+
+#[test]
+fn serves_a_dashboard_with_live_stats_and_events() {
+    let (mut cluster, admin) = cluster_with_admin(164, Some("s3cret"));
+    cluster.add_client("alice").unwrap();
+
+    // The page itself needs no token; it asks for one when the API refuses it
+    let (status, page) = get(admin, "/");
+    assert_eq!(status, 200);
+    assert!(page.contains("<title>Neon Relay</title>"));
+
+    let auth = "Authorization: Bearer s3cret\r\n";
+    let stats = || request(admin, "GET", "/stats", auth, "").1;
+    let relayed = || -> u64 {
+        let body = stats();
+        let (_, rest) = body.split_once("\"packets_relayed\":").unwrap();
+        rest.split(',').next().unwrap().parse().unwrap()
+    };
+    // The host's welcome may already have gone through the relay
+    let before = relayed();
+    assert!(cluster
+        .run_until(TIMEOUT, |cluster| {
+            cluster.client(0).send_game_packet(0x10, b"hello").unwrap();
+            relayed() > before
+        })
+        .unwrap());
+    assert!(stats().contains("\"sessions\":1,\"clients\":2,"), "{}", stats());
+
+    let events = || request(admin, "GET", "/events", auth, "").1;
+    assert!(events().contains("\"kind\":\"joined\",\"session_id\":164,\"client_id\":2}"), "{}", events());
+    request(admin, "DELETE", "/sessions/164", auth, "");
+    assert!(events().ends_with("\"kind\":\"session_closed\",\"session_id\":164,\"client_id\":0}]}"), "{}", events());
+}
+
+#[test]
+fn a_token_is_required_once_set() {
+    let (mut cluster, admin) = cluster_with_admin(163, Some("s3cret"));
+
+    assert_eq!(get(admin, "/sessions").0, 401);
+    assert_eq!(request(admin, "GET", "/sessions", "Authorization: Bearer wrong\r\n", "").0, 401);
+    let authorized = || request(admin, "GET", "/sessions", "Authorization: Bearer s3cret\r\n", "");
+    assert_eq!(authorized().0, 200);
+    assert!(cluster.run_until(TIMEOUT, |_| authorized().1.contains("\"session_id\":163")).unwrap());
+}
@@ -0,0 +1,44 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::protocol::MAX_CHAT_LENGTH;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Log = Arc<Mutex<Vec<String>>>;
+
+#[test]
+fn hosts_announce_to_everyone() {
+    let mut cluster = LocalCluster::new(110).unwrap();
+    cluster.host(|host| {
+        host.set_motd(Some("Welcome to Crab Cove")).unwrap();
+        let err = host.set_motd(Some(&"x".repeat(MAX_CHAT_LENGTH + 1))).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    });
+
+    let mut logs = Vec::new();
+    for name in ["alice", "bob"] {
+        let index = cluster.add_client(name).unwrap();
+        let log = Log::default();
+        let sink = log.clone();
+        cluster.client(index).on_announcement(move |text| sink.lock().unwrap().push(text));
+        let chat = log.clone();
+        cluster.client(index).on_chat_message(move |_, _, text| chat.lock().unwrap().push(format!("chat: {}", text)));
+        logs.push(log);
+    }
+    assert!(cluster.run_until(TIMEOUT, |_| logs.iter().all(|log| log.lock().unwrap().len() == 1)).unwrap());
+
+    cluster.host(|host| {
+        host.announce("Server restarting in 5 minutes").unwrap();
+        assert_eq!(host.announce(&"x".repeat(MAX_CHAT_LENGTH + 1)).unwrap_err().kind(), ErrorKind::InvalidInput);
+    });
+    assert!(cluster.run_until(TIMEOUT, |_| logs.iter().all(|log| log.lock().unwrap().len() == 2)).unwrap());
+
+    for log in &logs {
+        assert_eq!(*log.lock().unwrap(), ["Welcome to Crab Cove", "Server restarting in 5 minutes"]);
+    }
+
+    cluster.shutdown().unwrap();
+}
@@ -0,0 +1,67 @@
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+
+use project_neon::NeonClient;
+use project_neon::auth::{AUTH_INVALID, AUTH_REQUIRED, Authenticator, TokenList};
+use project_neon::host::ConnectDecision;
+use project_neon::testing::LocalCluster;
+
+#[test]
+fn token_list_accepts_only_listed_tokens() {
+    let tokens = TokenList::new().allow("invite-1").allow(b"invite-2".to_vec());
+    assert_eq!(tokens.authenticate("alice", 1, b"invite-2"), Ok(()));
+    assert_eq!(tokens.authenticate("alice", 1, b""), Err(AUTH_REQUIRED.to_string()));
+    assert_eq!(tokens.authenticate("alice", 1, b"invite-3"), Err(AUTH_INVALID.to_string()));
+}
+
+#[test]
+fn host_authenticator_checks_the_client_token() {
+    let mut cluster = LocalCluster::new(140).unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    cluster.host(|host| {
+        host.set_authenticator(move |name: &str, session_id: u32, token: &[u8]| {
+            sink.lock().unwrap().push((name.to_string(), session_id, token.to_vec()));
+            if token == b"secret" { Ok(()) } else { Err("Bad ticket".to_string()) }
+        })
+    });
+
+    let err = cluster.add_client("mallory").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(err.to_string(), "Bad ticket");
+
+    assert!(connect_with_token(&cluster, "alice", b"secret").is_none());
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![("mallory".to_string(), 140, Vec::new()), ("alice".to_string(), 140, b"secret".to_vec())]
+    );
+}
+
+#[test]
+fn relay_authenticator_turns_clients_away_before_the_host() {
+    let mut cluster = LocalCluster::with_relay(141, |relay| relay.set_authenticator(TokenList::new().allow("invite"))).unwrap();
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let sink = asked.clone();
+    cluster.host(|host| {
+        host.on_connect_request(move |_, name, _| {
+            sink.lock().unwrap().push(name.to_string());
+            ConnectDecision::Accept
+        })
+    });
+
+    let err = cluster.add_client("mallory").unwrap_err();
+    assert_eq!(err.to_string(), AUTH_REQUIRED);
+    assert_eq!(connect_with_token(&cluster, "eve", b"guess").unwrap().to_string(), AUTH_INVALID);
+    assert!(connect_with_token(&cluster, "alice", b"invite").is_none());
+
+    assert_eq!(*asked.lock().unwrap(), ["alice"]);
+}
+
+/// Connect a client carrying `token` over the cluster's network, returning the error if it was refused
+fn connect_with_token(cluster: &LocalCluster, name: &str, token: &[u8]) -> Option<Error> {
+    let transport = cluster.network().bind("127.0.0.1:0").unwrap();
+    let mut client = NeonClient::with_transport(name.to_string(), transport).unwrap();
+    client.set_auth_token(token);
+    client.connect(cluster.session_id(), cluster.relay_addr()).err()
+}
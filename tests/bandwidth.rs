@@ -0,0 +1,106 @@
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use project_neon::bandwidth::{BandwidthLimiter, CongestionController, DEFAULT_MAX_RATE};
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn limiter_refills_at_its_rate() {
+    let mut limiter = BandwidthLimiter::new(100_000);
+    // A tenth of a second of burst
+    assert!(limiter.try_consume(10_000));
+    assert!(!limiter.try_consume(500));
+
+    sleep(Duration::from_millis(20));
+    assert!(limiter.try_consume(1_000));
+    assert!(limiter.available() < 2_000);
+}
+
+#[test]
+fn oversized_packets_pass_on_a_full_bucket() {
+    let mut limiter = BandwidthLimiter::new(1_000);
+    assert!(limiter.try_consume(4_000));
+    assert!(!limiter.try_consume(1));
+}
+
+#[test]
+fn capped_client_spreads_traffic_out() {
+    let mut cluster = LocalCluster::with_clients(101, &["alice"]).unwrap();
+    let received = Arc::new(Mutex::new(0));
+    let counter = received.clone();
+    cluster.host(|host| host.on_game_packet(move |_, _, _| *counter.lock().unwrap() += 1));
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    let client = cluster.client(0);
+    client.set_bandwidth_limit(Some(10_000));
+    client.on_budget_exceeded(move |queued, rate| sink.lock().unwrap().push((queued, rate)));
+
+    let started = Instant::now();
+    for _ in 0..10 {
+        client.send_game_packet(0x10, &[0; 500]).unwrap();
+    }
+    assert_eq!(client.queued_packets(), 8);
+
+    assert!(cluster.run_until(TIMEOUT, |_| *received.lock().unwrap() == 10).unwrap());
+    // 8 held packets of 508 bytes at 10 KB/s
+    assert!(started.elapsed() >= Duration::from_millis(350), "drained in {:?}", started.elapsed());
+    // Reported once, when the third packet was the first held back
+    assert_eq!(*reports.lock().unwrap(), vec![(1, 10_000)]);
+}
+
+#[test]
+fn controller_backs_off_on_inflated_rtt_and_loss() {
+    let mut controller = CongestionController::new(100_000);
+    controller.on_rtt_sample(Duration::from_millis(40));
+    controller.on_rtt_sample(Duration::from_millis(50));
+    assert_eq!(controller.rate(), 100_000, "within normal jitter");
+
+    controller.on_rtt_sample(Duration::from_millis(200));
+    assert_eq!(controller.rate(), 70_000);
+    // Signals within one round trip of a decrease count once
+    controller.on_loss();
+    assert_eq!(controller.rate(), 70_000);
+
+    sleep(Duration::from_millis(110));
+    controller.on_loss();
+    assert_eq!(controller.rate(), 49_000);
+    assert_eq!(controller.base_rtt(), Some(Duration::from_millis(40)));
+}
+
+#[test]
+fn controller_recovers_while_the_link_is_clear() {
+    let mut controller = CongestionController::new(100_000);
+    controller.on_loss();
+    assert_eq!(controller.rate(), 70_000);
+
+    sleep(Duration::from_millis(250));
+    controller.update();
+    // 2% of the ceiling per clear 100ms
+    assert!((74_000..80_000).contains(&controller.rate()), "rate {}", controller.rate());
+
+    controller.set_max_rate(72_000);
+    assert_eq!(controller.rate(), 72_000);
+    sleep(Duration::from_millis(110));
+    controller.update();
+    assert_eq!(controller.rate(), 72_000);
+}
+
+#[test]
+fn congestion_control_paces_at_the_ceiling_initially() {
+    let mut cluster = LocalCluster::with_clients(102, &["alice"]).unwrap();
+    let client = cluster.client(0);
+    assert_eq!(client.send_rate(), None);
+
+    client.set_congestion_control(true);
+    assert_eq!(client.send_rate(), Some(DEFAULT_MAX_RATE));
+    client.set_bandwidth_limit(Some(50_000));
+    assert_eq!(client.send_rate(), Some(50_000));
+
+    client.set_congestion_control(false);
+    assert_eq!(client.send_rate(), Some(50_000));
+    assert_eq!(client.bandwidth_limit(), Some(50_000));
+}
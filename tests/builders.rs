@@ -0,0 +1,107 @@
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use project_neon::client::ConnectionState;
+use project_neon::host::{HostConfig, NeonHost};
+use project_neon::relay::{NeonRelay, RelayConfig};
+use project_neon::testing::{MemoryNetwork, Transport};
+use project_neon::NeonClient;
+
+mod common;
+use common::{TIMEOUT, drive, join, start_relay, start_relay_with};
+
+#[test]
+fn built_endpoints_take_their_settings() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay_with(&network, |relay| relay.max_sessions(1));
+
+    let mut host = NeonHost::builder(190, relay_addr)
+        .tick_rate(30)
+        .max_clients(Some(1))
+        .ping_interval(None)
+        .build_with_transport(network.bind("127.0.0.1:0").unwrap())
+        .unwrap();
+    assert_eq!(host.tick_rate(), 30);
+    host.register().unwrap();
+
+    let mut alice = NeonClient::builder("alice").auto_ping(false).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    alice.begin_connect(190, relay_addr).unwrap();
+    join(&network, &mut host, &mut alice);
+    assert_eq!(alice.tick_rate(), Some(30));
+
+    // The session is full, and the relay holds no more sessions
+    let mut bob = NeonClient::builder("bob").build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    bob.begin_connect(190, relay_addr).unwrap();
+    let err = drive(&network, &mut host, &mut bob, |client| client.connection_state() != ConnectionState::Connecting).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(bob.connection_state(), ConnectionState::Disconnected);
+    let mut second = NeonHost::builder(191, relay_addr).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    assert!(second.register().is_err());
+}
+
+#[test]
+fn built_clients_use_their_connect_retry() {
+    let network = MemoryNetwork::new();
+    let silent = network.bind("127.0.0.1:0").unwrap();
+    let mut client = NeonClient::builder("alice")
+        .connect_retry(Duration::from_millis(20), 3)
+        .build_with_transport(network.bind("127.0.0.1:0").unwrap())
+        .unwrap();
+    let err = client.connect(192, silent.local_addr().unwrap()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert_eq!(network.pending(silent.local_addr().unwrap()), 3);
+}
+
+#[test]
+fn hosts_move_on_to_the_next_resolved_relay_address() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let silent = network.bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let candidates = [silent, relay_addr];
+    let mut host = NeonHost::builder(194, &candidates[..]).ping_interval(None).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    assert_eq!(host.relay_addr(), silent);
+    host.register().unwrap();
+    assert_eq!(host.relay_addr(), relay_addr);
+
+    let mut client = NeonClient::builder("alice").auto_ping(false).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.begin_connect(194, relay_addr).unwrap();
+    join(&network, &mut host, &mut client);
+}
+
+#[test]
+fn hosts_and_clients_find_the_relay_by_hostname() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let relay_addr = format!("localhost:{}", socket.local_addr().unwrap().port());
+    let mut relay = NeonRelay::with_transport(socket, RelayConfig::default()).unwrap();
+    let handle = relay.handle();
+    let relay_thread = thread::spawn(move || relay.start());
+
+    let mut host = NeonHost::new(195, relay_addr.as_str()).unwrap();
+    let (tx, rx) = mpsc::channel();
+    host.on_game_packet(move |_, from, data| tx.send((from, data.to_vec())).unwrap());
+    let worker = host.spawn().unwrap();
+
+    let mut client = NeonClient::new("alice".to_string()).unwrap();
+    client.connect(195, relay_addr.as_str()).unwrap();
+    client.send_game_packet(0x10, b"by name").unwrap();
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap(), (client.client_id().unwrap(), b"by name".to_vec()));
+
+    worker.shutdown().unwrap();
+    handle.stop();
+    relay_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn invalid_settings_fail_the_build() {
+    let network = MemoryNetwork::new();
+    let err = NeonHost::builder(193, "127.0.0.1:7777").tick_rate(0).build_with_transport(network.bind("127.0.0.1:0").unwrap()).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let config = HostConfig { tick_rate: 0, ..HostConfig::default() };
+    assert_eq!(NeonHost::with_config(193, "127.0.0.1:7777", config).err().unwrap().kind(), ErrorKind::InvalidInput);
+    let err = NeonRelay::builder().worker_threads(0).build_with_transport(network.bind("127.0.0.1:0").unwrap()).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
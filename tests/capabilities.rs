@@ -0,0 +1,83 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::host::ConnectDecision;
+use project_neon::protocol::Capabilities;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Log<T> = Arc<Mutex<Vec<T>>>;
+
+#[test]
+fn hosts_hold_back_what_clients_may_not_do() {
+    let mut cluster = LocalCluster::new(95).unwrap();
+    let alice = cluster.add_client("alice").unwrap();
+    assert_eq!(cluster.client(alice).capabilities(), Capabilities::SEND_GAME | Capabilities::CHAT);
+
+    let packets: Log<(u8, Vec<u8>)> = Log::default();
+    let chats: Log<String> = Log::default();
+    let changes: Log<Capabilities> = Log::default();
+    cluster.host(|host| {
+        let sink = packets.clone();
+        host.on_game_packet(move |packet_type, _, data| sink.lock().unwrap().push((packet_type, data.to_vec())));
+        let sink = chats.clone();
+        host.on_chat_message(move |_, _, text| sink.lock().unwrap().push(text));
+        assert_eq!(host.client_capabilities(2), Some(Capabilities::SEND_GAME | Capabilities::CHAT));
+        host.set_client_capabilities(2, Capabilities::CHAT).unwrap();
+    });
+    let sink = changes.clone();
+    cluster.client(alice).on_capabilities_changed(move |capabilities| sink.lock().unwrap().push(capabilities));
+
+    assert!(cluster.run_until(TIMEOUT, |cluster| cluster.client(alice).capabilities() == Capabilities::CHAT).unwrap());
+    assert_eq!(*changes.lock().unwrap(), [Capabilities::CHAT]);
+
+    cluster.client(alice).send_game_packet(0x20, b"muted").unwrap();
+    cluster.client(alice).send_chat("still here", 1).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !chats.lock().unwrap().is_empty()).unwrap());
+    // The relay may forward the game packet after the chat; let it land before SEND_GAME is granted
+    cluster.run_until(Duration::from_millis(100), |_| false).unwrap();
+
+    cluster.host(|host| host.set_client_capabilities(2, Capabilities::SEND_GAME).unwrap());
+    assert!(cluster.run_until(TIMEOUT, |cluster| cluster.client(alice).capabilities() == Capabilities::SEND_GAME).unwrap());
+    cluster.client(alice).send_chat("hello?", 1).unwrap();
+    cluster.client(alice).send_game_packet(0x20, b"unmuted").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !packets.lock().unwrap().is_empty()).unwrap());
+    // Give anything that slipped through time to arrive
+    cluster.run_until(Duration::from_millis(100), |_| false).unwrap();
+
+    assert_eq!(*packets.lock().unwrap(), [(0x20, b"unmuted".to_vec())]);
+    assert_eq!(*chats.lock().unwrap(), ["still here"]);
+
+    cluster.shutdown().unwrap();
+}
+
+#[test]
+fn capabilities_can_be_set_on_accept() {
+    let mut cluster = LocalCluster::new(96).unwrap();
+    cluster.host(|host| {
+        host.on_connect_request(|_, name, _| match name {
+            "admin" => ConnectDecision::AcceptWith(Capabilities::all()),
+            _ => ConnectDecision::Accept,
+        })
+    });
+    let admin = cluster.add_client("admin").unwrap();
+    let watcher = cluster.add_spectator("watcher").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |cluster| cluster.client(admin).capabilities() == Capabilities::all()).unwrap());
+    assert_eq!(cluster.client(watcher).capabilities(), Capabilities::CHAT);
+
+    cluster.host(|host| {
+        assert_eq!(host.client_capabilities(2), Some(Capabilities::all()));
+        // Spectators never send game packets
+        host.set_client_capabilities(3, Capabilities::all()).unwrap();
+        assert_eq!(host.client_capabilities(3), Some(Capabilities::CHAT | Capabilities::ADMIN));
+        assert_eq!(host.set_client_capabilities(4, Capabilities::CHAT).unwrap_err().kind(), ErrorKind::NotFound);
+    });
+    assert!(cluster.run_until(TIMEOUT, |cluster| {
+        cluster.client(watcher).capabilities() == Capabilities::CHAT | Capabilities::ADMIN
+    }).unwrap());
+
+    cluster.shutdown().unwrap();
+}
@@ -0,0 +1,85 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::protocol::MAX_CHAT_LENGTH;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type ChatLog = Arc<Mutex<Vec<(u16, String, String)>>>;
+
+fn record_client_chat(cluster: &mut LocalCluster, index: usize) -> ChatLog {
+    let log = ChatLog::default();
+    let sink = log.clone();
+    cluster.client(index).on_chat_message(move |from, name, text| sink.lock().unwrap().push((from, name, text)));
+    log
+}
+
+fn record_host_chat(cluster: &LocalCluster) -> ChatLog {
+    let log = ChatLog::default();
+    let sink = log.clone();
+    cluster.host(|host| host.on_chat_message(move |from, name, text| sink.lock().unwrap().push((from, name, text))));
+    log
+}
+
+fn entry(from: u16, name: &str, text: &str) -> (u16, String, String) {
+    (from, name.to_string(), text.to_string())
+}
+
+#[test]
+fn broadcast_chat_reaches_everyone_but_the_sender() {
+    let mut cluster = LocalCluster::with_clients(31, &["alice", "bob", "carol"]).unwrap();
+    let host_log = record_host_chat(&cluster);
+    let logs: Vec<ChatLog> = (0..3).map(|i| record_client_chat(&mut cluster, i)).collect();
+
+    cluster.client(0).send_chat("gg", 0).unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| logs[1..].iter().all(|log| !log.lock().unwrap().is_empty())).unwrap());
+    assert!(cluster.run_until(TIMEOUT, |_| !host_log.lock().unwrap().is_empty()).unwrap());
+    for log in [&logs[1], &logs[2], &host_log] {
+        assert_eq!(*log.lock().unwrap(), vec![entry(2, "alice", "gg")]);
+    }
+    assert!(logs[0].lock().unwrap().is_empty());
+}
+
+#[test]
+fn direct_chat_reaches_only_its_target() {
+    let mut cluster = LocalCluster::with_clients(32, &["alice", "bob", "carol"]).unwrap();
+    let bob = record_client_chat(&mut cluster, 1);
+    let carol = record_client_chat(&mut cluster, 2);
+
+    cluster.client(0).send_chat("psst", 3).unwrap();
+    cluster.client(0).send_chat("everyone", 0).unwrap();
+
+    // Once carol has the later broadcast, the direct message would have reached her too
+    assert!(cluster.run_until(TIMEOUT, |_| !carol.lock().unwrap().is_empty()).unwrap());
+    assert!(cluster.run_until(TIMEOUT, |_| bob.lock().unwrap().len() == 2).unwrap());
+    assert_eq!(*bob.lock().unwrap(), vec![entry(2, "alice", "psst"), entry(2, "alice", "everyone")]);
+    assert_eq!(*carol.lock().unwrap(), vec![entry(2, "alice", "everyone")]);
+}
+
+#[test]
+fn host_chat_reaches_clients() {
+    let mut cluster = LocalCluster::with_clients(33, &["alice", "bob"]).unwrap();
+    let logs: Vec<ChatLog> = (0..2).map(|i| record_client_chat(&mut cluster, i)).collect();
+
+    cluster.host(|host| host.send_chat("welcome", 0)).unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| logs.iter().all(|log| !log.lock().unwrap().is_empty())).unwrap());
+    for log in &logs {
+        assert_eq!(*log.lock().unwrap(), vec![entry(1, "Host", "welcome")]);
+    }
+    let err = cluster.host(|host| host.send_chat("hello?", 9)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn long_messages_are_rejected() {
+    let mut cluster = LocalCluster::with_clients(34, &["alice"]).unwrap();
+    let text = "a".repeat(MAX_CHAT_LENGTH + 1);
+
+    assert_eq!(cluster.client(0).send_chat(&text, 0).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(cluster.host(|host| host.send_chat(&text, 0)).unwrap_err().kind(), ErrorKind::InvalidInput);
+    cluster.client(0).send_chat(&text[1..], 0).unwrap();
+}
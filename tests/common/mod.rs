@@ -0,0 +1,52 @@
+//! Fixture shared by the tests that run a relay on its own thread and pump a host and client by hand
+#![allow(dead_code)]
+
+use std::io::Error;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use project_neon::client::ConnectionState;
+use project_neon::host::NeonHost;
+use project_neon::relay::{NeonRelay, RelayBuilder};
+use project_neon::testing::{MemoryNetwork, MemoryTransport, Transport};
+use project_neon::NeonClient;
+
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start a single-worker relay at 127.0.0.1:7777
+pub fn start_relay(network: &MemoryNetwork) -> SocketAddr {
+    start_relay_with(network, |builder| builder)
+}
+
+/// Start a single-worker relay at 127.0.0.1:7777, with settings of the test's own
+pub fn start_relay_with(network: &MemoryNetwork, configure: impl FnOnce(RelayBuilder) -> RelayBuilder) -> SocketAddr {
+    let transport = network.bind("127.0.0.1:7777").unwrap();
+    let addr = transport.local_addr().unwrap();
+    let mut relay = configure(NeonRelay::builder().worker_threads(1)).build_with_transport(transport).unwrap();
+    thread::spawn(move || relay.start());
+    addr
+}
+
+/// Drive a host and client until `done` holds or time runs out, stopping at the client's first error
+pub fn drive(
+    network: &MemoryNetwork,
+    host: &mut NeonHost<MemoryTransport>,
+    client: &mut NeonClient<MemoryTransport>,
+    done: impl Fn(&NeonClient<MemoryTransport>) -> bool,
+) -> Result<(), Error> {
+    let deadline = Instant::now() + TIMEOUT;
+    while !done(client) && Instant::now() < deadline {
+        let seen = network.delivered();
+        host.process_packets().unwrap();
+        client.process_packets()?;
+        network.wait_for_delivery(seen, Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+/// Drive a host and client until the client is configured
+pub fn join(network: &MemoryNetwork, host: &mut NeonHost<MemoryTransport>, client: &mut NeonClient<MemoryTransport>) {
+    drive(network, host, client, |client| client.state() == ConnectionState::Connected).unwrap();
+    assert_eq!(client.state(), ConnectionState::Connected);
+}
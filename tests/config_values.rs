@@ -0,0 +1,82 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+
+use project_neon::client::ConnectionState;
+use project_neon::host::NeonHost;
+use project_neon::protocol::{MAX_CONFIG_KEY_LENGTH, MAX_CONFIG_VALUE_SIZE};
+use project_neon::testing::{MemoryNetwork, MemoryTransport};
+use project_neon::NeonClient;
+
+mod common;
+use common::{drive, start_relay};
+
+type Changes = Arc<Mutex<Vec<(String, Option<Vec<u8>>)>>>;
+
+fn record_changes(client: &mut NeonClient<MemoryTransport>) -> Changes {
+    let changes: Changes = Arc::new(Mutex::new(Vec::new()));
+    let sink = changes.clone();
+    client.on_config_changed(move |key, value| sink.lock().unwrap().push((key.to_string(), value.map(<[u8]>::to_vec))));
+    changes
+}
+
+#[test]
+fn clients_receive_the_config_on_join_and_on_change() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let mut host = NeonHost::builder(220, relay_addr).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.set_config_value("map", "canyon").unwrap();
+    host.set_config_value("mode", "ctf").unwrap();
+    host.register().unwrap();
+
+    let mut client = NeonClient::builder("alice").build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    let changes = record_changes(&mut client);
+    client.begin_connect(220, relay_addr).unwrap();
+    drive(&network, &mut host, &mut client, |client| client.config_values().len() == 2).unwrap();
+    assert_eq!(client.state(), ConnectionState::Connected);
+    assert_eq!(client.config_str("map"), Some("canyon"));
+    assert_eq!(client.config_value("mode"), Some(&b"ctf"[..]));
+    assert_eq!(changes.lock().unwrap().len(), 2);
+    changes.lock().unwrap().clear();
+
+    // Setting a value it already has sends nothing
+    host.set_config_value("mode", "ctf").unwrap();
+    host.set_config_value("map", "harbour").unwrap();
+    host.set_config_value("score", [0xFF, 0x00]).unwrap();
+    drive(&network, &mut host, &mut client, |client| client.config_values().len() == 3).unwrap();
+    assert_eq!(client.config_str("map"), Some("harbour"));
+    assert_eq!(client.config_value("score"), Some(&[0xFF, 0x00][..]));
+    assert_eq!(client.config_str("score"), None);
+
+    assert!(host.remove_config_value("mode").unwrap());
+    assert!(!host.remove_config_value("mode").unwrap());
+    drive(&network, &mut host, &mut client, |client| client.config_value("mode").is_none()).unwrap();
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            ("map".to_string(), Some(b"harbour".to_vec())),
+            ("score".to_string(), Some(vec![0xFF, 0x00])),
+            ("mode".to_string(), None),
+        ]
+    );
+    assert_eq!(host.config_values(), client.config_values());
+
+    client.disconnect().unwrap();
+    assert!(client.config_values().is_empty());
+}
+
+#[test]
+fn oversized_entries_are_rejected() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let mut host = NeonHost::builder(221, relay_addr).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+
+    assert_eq!(host.set_config_value("", "x").unwrap_err().kind(), ErrorKind::InvalidInput);
+    let long_key = "k".repeat(MAX_CONFIG_KEY_LENGTH + 1);
+    assert_eq!(host.set_config_value(&long_key, "x").unwrap_err().kind(), ErrorKind::InvalidInput);
+    let big_value = vec![0u8; MAX_CONFIG_VALUE_SIZE + 1];
+    assert_eq!(host.set_config_value("map", &big_value).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(host.config_values().is_empty());
+
+    host.set_config_value(&"k".repeat(MAX_CONFIG_KEY_LENGTH), vec![0u8; MAX_CONFIG_VALUE_SIZE]).unwrap();
+    assert_eq!(host.config_values().len(), 1);
+}
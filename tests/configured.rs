@@ -0,0 +1,130 @@
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use project_neon::client::ConnectionState;
+use project_neon::host::NeonHost;
+use project_neon::protocol::PROTOCOL_VERSION;
+use project_neon::testing::MemoryNetwork;
+use project_neon::NeonClient;
+
+mod common;
+use common::{drive, start_relay};
+
+#[test]
+fn game_packets_wait_for_the_session_config() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let mut host = NeonHost::builder(200, relay_addr).tick_rate(20).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.register().unwrap();
+    let mut client = NeonClient::builder("alice").build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    assert_eq!(client.wait_until_configured(Duration::ZERO).unwrap_err().kind(), ErrorKind::NotConnected);
+    let connected = Arc::new(AtomicBool::new(false));
+    let flag = connected.clone();
+    client.on_connected(move |_, _| flag.store(true, Ordering::Relaxed));
+
+    // The host sends its config only once it hears the client's confirmation, which the host
+    // hasn't processed yet when the accept lands
+    client.begin_connect(200, relay_addr).unwrap();
+    drive(&network, &mut host, &mut client, |client| client.state() != ConnectionState::Connecting).unwrap();
+    assert_eq!(client.state(), ConnectionState::AwaitingConfig);
+    assert_eq!(client.connection_state(), ConnectionState::Connecting);
+    assert!(!connected.load(Ordering::Relaxed));
+    assert_eq!(client.tick_rate(), None);
+    assert_eq!(client.send_game_packet(0x10, b"early").unwrap_err().kind(), ErrorKind::WouldBlock);
+    let err = client.wait_until_configured(Duration::from_millis(50)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    drive(&network, &mut host, &mut client, |client| client.state() == ConnectionState::Connected).unwrap();
+    client.wait_until_configured(Duration::ZERO).unwrap();
+    assert!(connected.load(Ordering::Relaxed));
+    assert_eq!(client.connection_state(), ConnectionState::Connected);
+    assert_eq!(client.tick_rate(), Some(20));
+    client.send_game_packet(0x10, b"on time").unwrap();
+    // Varied enough that compression can't bring it under the limit
+    let oversized: Vec<u8> = (0..2048u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    let err = client.send_game_packet(0x10, &oversized).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn connect_returns_once_configured() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let mut host = NeonHost::builder(201, relay_addr).tick_rate(30).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.register().unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+    let stop = done.clone();
+    let host_thread = thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            host.process_packets().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+    });
+
+    let mut client = NeonClient::builder("bob").build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.connect(201, relay_addr).unwrap();
+    assert_eq!(client.state(), ConnectionState::Connected);
+    assert_eq!(client.tick_rate(), Some(30));
+    client.send_game_packet(0x10, b"hello").unwrap();
+    done.store(true, Ordering::Relaxed);
+    host_thread.join().unwrap();
+}
+
+#[test]
+fn waiting_for_the_config_covers_the_connect() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let mut host = NeonHost::builder(203, relay_addr).tick_rate(40).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.register().unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+    let stop = done.clone();
+    let host_thread = thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            host.process_packets().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+    });
+
+    let mut client = NeonClient::builder("dave").build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.begin_connect(203, relay_addr).unwrap();
+    assert_eq!(client.state(), ConnectionState::Connecting);
+    client.wait_until_configured(Duration::from_secs(5)).unwrap();
+    assert_eq!(client.state(), ConnectionState::Connected);
+    assert_eq!(client.tick_rate(), Some(40));
+    done.store(true, Ordering::Relaxed);
+    host_thread.join().unwrap();
+}
+
+#[test]
+fn both_ends_keep_the_negotiated_config() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let mut host = NeonHost::builder(202, relay_addr).tick_rate(25).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.set_max_packet_size(1400).unwrap();
+    host.register().unwrap();
+    let advertised = host.session_config();
+    assert_eq!((advertised.version, advertised.tick_rate, advertised.max_packet_size), (PROTOCOL_VERSION, 25, 1400));
+
+    let mut client = NeonClient::builder("carol").build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    assert!(client.session_config().is_none());
+    assert_eq!(client.max_packet_size(), None);
+    client.begin_connect(202, relay_addr).unwrap();
+    drive(&network, &mut host, &mut client, |client| client.state() == ConnectionState::Connected).unwrap();
+    let client_id = client.client_id().unwrap();
+    assert_eq!(client.session_config(), Some(&advertised));
+    assert_eq!(host.client_session_config(client_id), Some(&advertised));
+    assert_eq!(client.tick_rate(), Some(25));
+    assert_eq!(client.max_packet_size(), Some(1400));
+
+    // Later changes reach clients that join from then on, not this one
+    host.set_tick_rate(50).unwrap();
+    assert_eq!(host.session_config().tick_rate, 50);
+    assert_eq!(host.client_session_config(client_id).unwrap().tick_rate, 25);
+
+    client.disconnect().unwrap();
+    assert!(client.session_config().is_none());
+    assert_eq!(client.tick_rate(), None);
+}
@@ -0,0 +1,180 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use project_neon::NeonClient;
+use project_neon::client::ConnectionState;
+use project_neon::host::ConnectDecision;
+use project_neon::protocol::{self, ConnectAccept, ConnectDeny, NeonPacket, PacketPayload, PacketType};
+use project_neon::testing::{LocalCluster, MemoryTransport, Transport};
+
+/// Call process_packets until the client stops connecting, returning what the last call said
+fn drive(cluster: &LocalCluster, client: &mut NeonClient<MemoryTransport>) -> Result<(), std::io::Error> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while client.connection_state() == ConnectionState::Connecting && Instant::now() < deadline {
+        let seen = cluster.network().delivered();
+        client.process_packets()?;
+        cluster.network().wait_for_delivery(seen, Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+fn new_client(cluster: &LocalCluster, name: &str) -> NeonClient<MemoryTransport> {
+    NeonClient::with_transport(name.to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap()
+}
+
+#[test]
+fn begin_connect_returns_at_once_and_process_packets_finishes_the_join() {
+    let cluster = LocalCluster::new(150).unwrap();
+    let mut client = new_client(&cluster, "alice");
+    let joined = Arc::new(Mutex::new(None));
+    let sink = joined.clone();
+    client.on_connected(move |client_id, session_id| *sink.lock().unwrap() = Some((client_id, session_id)));
+    client.on_connect_failed(|e| panic!("connect failed: {}", e));
+
+    assert_eq!(client.connection_state(), ConnectionState::Disconnected);
+    client.begin_connect(150, cluster.relay_addr()).unwrap();
+    assert_eq!(client.connection_state(), ConnectionState::Connecting);
+    assert_eq!(client.client_id(), None);
+
+    drive(&cluster, &mut client).unwrap();
+    assert_eq!(client.connection_state(), ConnectionState::Connected);
+    let client_id = client.client_id().unwrap();
+    assert_eq!(*joined.lock().unwrap(), Some((client_id, 150)));
+}
+
+#[test]
+fn denied_joins_report_through_on_connect_failed() {
+    let cluster = LocalCluster::new(151).unwrap();
+    cluster.host(|host| host.set_authenticator(|_: &str, _: u32, _: &[u8]| Err("Invite only".to_string())));
+    let mut client = new_client(&cluster, "mallory");
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let sink = failures.clone();
+    client.on_connect_failed(move |e| sink.lock().unwrap().push((e.kind(), e.to_string())));
+
+    client.begin_connect(151, cluster.relay_addr()).unwrap();
+    let err = drive(&cluster, &mut client).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(client.connection_state(), ConnectionState::Disconnected);
+    assert_eq!(*failures.lock().unwrap(), [(ErrorKind::ConnectionRefused, "Invite only".to_string())]);
+}
+
+#[test]
+fn unanswered_requests_are_resent_then_blamed_on_the_relay() {
+    let cluster = LocalCluster::new(152).unwrap();
+    let silent = cluster.network().bind("127.0.0.1:0").unwrap();
+    let mut client = new_client(&cluster, "alice");
+    client.set_connect_retry(Duration::from_millis(20), 3);
+
+    client.begin_connect(152, silent.local_addr().unwrap()).unwrap();
+    let err = drive(&cluster, &mut client).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(err.to_string().contains("never answered (3 connection requests sent)"), "{}", err);
+    assert_eq!(cluster.network().pending(silent.local_addr().unwrap()), 3);
+}
+
+#[test]
+fn a_silent_host_is_told_apart_from_the_relay_and_asked_once() {
+    let cluster = LocalCluster::new(153).unwrap();
+    let asked = Arc::new(Mutex::new(0));
+    let sink = asked.clone();
+    cluster.host(|host| {
+        host.on_connect_request(move |_, _, _| {
+            *sink.lock().unwrap() += 1;
+            ConnectDecision::Defer
+        })
+    });
+    let mut client = new_client(&cluster, "alice");
+    client.set_connect_retry(Duration::from_millis(50), 3);
+
+    client.begin_connect(153, cluster.relay_addr()).unwrap();
+    let err = drive(&cluster, &mut client).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(err.to_string().contains("the host of session 153 never answered"), "{}", err);
+    assert_eq!(*asked.lock().unwrap(), 1);
+}
+
+#[test]
+fn state_walks_through_the_handshake_and_back_out() {
+    let cluster = LocalCluster::new(154).unwrap();
+    let mut client = new_client(&cluster, "alice");
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let sink = changes.clone();
+    client.on_state_changed(move |old, new| sink.lock().unwrap().push((old, new)));
+
+    assert_eq!(client.state(), ConnectionState::Disconnected);
+    client.begin_connect(154, cluster.relay_addr()).unwrap();
+    assert_eq!(client.state(), ConnectionState::Connecting);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while client.state() != ConnectionState::Connected && Instant::now() < deadline {
+        let seen = cluster.network().delivered();
+        client.process_packets().unwrap();
+        cluster.network().wait_for_delivery(seen, Duration::from_millis(10));
+    }
+    assert_eq!(client.state(), ConnectionState::Connected);
+    assert!(client.tick_rate().is_some());
+
+    client.disconnect().unwrap();
+    assert_eq!(client.state(), ConnectionState::Disconnected);
+    assert_eq!(client.client_id(), None);
+    assert_eq!(client.process_packets().unwrap_err().kind(), ErrorKind::NotConnected);
+
+    use ConnectionState::*;
+    assert_eq!(*changes.lock().unwrap(), [
+        (Disconnected, Connecting),
+        (Connecting, AwaitingConfig),
+        (AwaitingConfig, Connected),
+        (Connected, Disconnecting),
+        (Disconnecting, Disconnected),
+    ]);
+}
+
+#[test]
+fn answers_from_anyone_but_the_relay_are_ignored() {
+    let cluster = LocalCluster::new(156).unwrap();
+    let transport = cluster.network().bind("127.0.0.1:0").unwrap();
+    let client_addr = transport.local_addr().unwrap();
+    let mut client = NeonClient::with_transport("alice".to_string(), transport).unwrap();
+    client.begin_connect(156, cluster.relay_addr()).unwrap();
+
+    let accept = ConnectAccept {
+        assigned_client_id: 9,
+        session_id: 156,
+        cookie: 0,
+        nonce: 0,
+        resume_token: 0,
+        game_identifier: 0,
+        join_code: String::new(),
+        metadata: Default::default(),
+        features: Default::default(),
+    };
+    let deny = ConnectDeny { reason: "Forged".to_string(), nonce: 0 };
+    let attacker = cluster.network().bind("127.0.0.1:0").unwrap();
+    for (packet_type, payload) in [(PacketType::ConnectDeny, PacketPayload::ConnectDeny(deny)), (PacketType::ConnectAccept, PacketPayload::ConnectAccept(accept))] {
+        let packet = NeonPacket { packet_type: packet_type as u8, sequence: 1, client_id: 1, destination_id: 0, payload };
+        attacker.send_to(&protocol::encode(&packet), client_addr).unwrap();
+    }
+
+    drive(&cluster, &mut client).unwrap();
+    assert_eq!(client.connection_state(), ConnectionState::Connected);
+    assert_eq!(client.client_id(), Some(2));
+}
+
+#[test]
+fn failed_joins_go_back_to_disconnected() {
+    let cluster = LocalCluster::new(155).unwrap();
+    cluster.host(|host| host.set_authenticator(|_: &str, _: u32, _: &[u8]| Err("Invite only".to_string())));
+    let mut client = new_client(&cluster, "mallory");
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let sink = changes.clone();
+    client.on_state_changed(move |old, new| sink.lock().unwrap().push((old, new)));
+
+    client.begin_connect(155, cluster.relay_addr()).unwrap();
+    drive(&cluster, &mut client).unwrap_err();
+    assert_eq!(client.state(), ConnectionState::Disconnected);
+    assert_eq!(*changes.lock().unwrap(), [
+        (ConnectionState::Disconnected, ConnectionState::Connecting),
+        (ConnectionState::Connecting, ConnectionState::Disconnected),
+    ]);
+}
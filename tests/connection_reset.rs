@@ -0,0 +1,137 @@
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use project_neon::NeonClient;
+use project_neon::client::ConnectionState;
+use project_neon::host::NeonHost;
+use project_neon::relay::{NeonRelay, RelayConfig};
+use project_neon::testing::{LocalCluster, MemoryNetwork, MemoryTransport, Transport};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A memory endpoint whose receives fail with ConnectionReset while resets are queued, the way
+/// a Windows UDP socket's do after sends to closed ports
+struct ResettingTransport {
+    inner: MemoryTransport,
+    resets: Arc<AtomicUsize>,
+}
+
+impl ResettingTransport {
+    fn bind(network: &MemoryNetwork, addr: &str) -> (Self, Arc<AtomicUsize>) {
+        let resets = Arc::new(AtomicUsize::new(0));
+        (Self { inner: network.bind(addr).unwrap(), resets: resets.clone() }, resets)
+    }
+}
+
+impl Transport for ResettingTransport {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, Error> {
+        self.inner.send_to(data, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+        if self.resets.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok() {
+            return Err(Error::new(ErrorKind::ConnectionReset, "An existing connection was forcibly closed by the remote host"));
+        }
+        self.inner.recv_from(buf)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.inner.local_addr()
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        self.inner.set_nonblocking(nonblocking)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn try_clone(&self) -> Result<Self, Error> {
+        Ok(Self { inner: self.inner.try_clone()?, resets: self.resets.clone() })
+    }
+
+    fn wait_readable(&self, timeout: Duration) -> Result<bool, Error> {
+        if self.resets.load(Ordering::Relaxed) > 0 {
+            return Ok(true);
+        }
+        self.inner.wait_readable(timeout)
+    }
+}
+
+/// Drive a host and client until the client finishes joining
+fn join(network: &MemoryNetwork, host: &mut NeonHost<impl Transport>, client: &mut NeonClient<impl Transport>) {
+    let deadline = Instant::now() + TIMEOUT;
+    while client.connection_state() == ConnectionState::Connecting && Instant::now() < deadline {
+        let seen = network.delivered();
+        host.process_packets().unwrap();
+        client.process_packets().unwrap();
+        network.wait_for_delivery(seen, Duration::from_millis(10));
+    }
+    assert_eq!(client.connection_state(), ConnectionState::Connected);
+}
+
+#[test]
+fn clients_keep_receiving_through_connection_resets() {
+    let cluster = LocalCluster::new(180).unwrap();
+    let (transport, resets) = ResettingTransport::bind(cluster.network(), "127.0.0.1:0");
+    let mut client = NeonClient::with_transport("alice".to_string(), transport).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    client.on_game_packet(move |_, _, data| sink.lock().unwrap().push(data.to_vec()));
+    client.connect(180, cluster.relay_addr()).unwrap();
+    let client_id = client.client_id().unwrap();
+
+    resets.store(3, Ordering::Relaxed);
+    client.process_packets().unwrap();
+    assert_eq!(resets.load(Ordering::Relaxed), 0);
+
+    resets.store(2, Ordering::Relaxed);
+    cluster.host(|host| host.send_game_packet(client_id, 0x10, b"still here")).unwrap();
+    let deadline = Instant::now() + TIMEOUT;
+    while received.lock().unwrap().is_empty() && Instant::now() < deadline {
+        let seen = cluster.network().delivered();
+        client.process_packets().unwrap();
+        cluster.network().wait_for_delivery(seen, Duration::from_millis(10));
+    }
+    assert_eq!(*received.lock().unwrap(), [b"still here".to_vec()]);
+    assert_eq!(client.connection_state(), ConnectionState::Connected);
+}
+
+#[test]
+fn hosts_keep_accepting_through_connection_resets() {
+    let cluster = LocalCluster::new(181).unwrap();
+    let (transport, resets) = ResettingTransport::bind(cluster.network(), "127.0.0.1:0");
+    let mut host = NeonHost::with_transport(182, cluster.relay_addr(), transport).unwrap();
+    resets.store(2, Ordering::Relaxed);
+    host.register().unwrap();
+
+    resets.store(3, Ordering::Relaxed);
+    host.process_packets().unwrap();
+    let mut client = NeonClient::with_transport("bob".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    client.begin_connect(182, cluster.relay_addr()).unwrap();
+    join(cluster.network(), &mut host, &mut client);
+    assert_eq!(host.client_count(), 1);
+}
+
+#[test]
+fn relays_keep_routing_through_connection_resets() {
+    let network = MemoryNetwork::new();
+    let (transport, resets) = ResettingTransport::bind(&network, "127.0.0.1:7777");
+    let relay_addr = transport.local_addr().unwrap();
+    resets.store(5, Ordering::Relaxed);
+    let mut relay = NeonRelay::with_transport(transport, RelayConfig { worker_threads: 1, ..RelayConfig::default() }).unwrap();
+    let relay_thread = thread::spawn(move || relay.start());
+
+    let mut host = NeonHost::with_transport(183, relay_addr, network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.register().unwrap();
+    resets.store(5, Ordering::Relaxed);
+    let mut client = NeonClient::with_transport("carol".to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.begin_connect(183, relay_addr).unwrap();
+    join(&network, &mut host, &mut client);
+    assert!(!relay_thread.is_finished());
+}
@@ -0,0 +1,48 @@
+use project_neon::host::NeonHost;
+use project_neon::protocol::FeatureSet;
+use project_neon::testing::MemoryNetwork;
+use project_neon::NeonClient;
+
+mod common;
+use common::{join, start_relay};
+
+/// A feature no library part uses, negotiated by the game alone
+const ZOMBIES: FeatureSet = FeatureSet::from_bits_retain(1 << 40);
+
+#[test]
+fn both_ends_agree_on_the_shared_features() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let offered = FeatureSet::MOVEMENT | FeatureSet::VOIP | ZOMBIES;
+    let mut host = NeonHost::builder(210, relay_addr).features(offered).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.register().unwrap();
+    assert_eq!(host.features(), offered);
+    assert_eq!(host.session_config().features, offered);
+
+    let supported = FeatureSet::VOIP | FeatureSet::RPC | ZOMBIES;
+    let mut alice = NeonClient::builder("alice").features(supported).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    assert_eq!(alice.supported_features(), supported);
+    assert_eq!(alice.features(), None);
+    alice.begin_connect(210, relay_addr).unwrap();
+    join(&network, &mut host, &mut alice);
+
+    assert_eq!(alice.features(), Some(FeatureSet::VOIP | ZOMBIES));
+    assert_eq!(host.client_features(alice.client_id().unwrap()), Some(FeatureSet::VOIP | ZOMBIES));
+}
+
+#[test]
+fn clients_that_declare_nothing_get_nothing() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let mut host = NeonHost::builder(211, relay_addr).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.set_features(FeatureSet::all());
+    host.register().unwrap();
+
+    let mut bob = NeonClient::builder("bob").build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    bob.begin_connect(211, relay_addr).unwrap();
+    join(&network, &mut host, &mut bob);
+
+    assert_eq!(bob.features(), Some(FeatureSet::empty()));
+    assert_eq!(host.client_features(bob.client_id().unwrap()), Some(FeatureSet::empty()));
+    assert_eq!(host.client_features(99), None);
+}
@@ -0,0 +1,192 @@
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use project_neon::protocol::{
+    self, ConnectRequest, NeonPacket, PacketPayload, PacketType, RelayInfo, SessionRedirect, PROTOCOL_VERSION,
+};
+use project_neon::directory;
+use project_neon::relay::RelayConfig;
+use project_neon::transport::{MemoryNetwork, MemoryTransport, Transport};
+use project_neon::{NeonClient, NeonHost, NeonRelay};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start a relay at `addr` on the network, peered with `peer`
+fn start_relay(network: &MemoryNetwork, addr: &str, peer: &str) -> SocketAddr {
+    let transport = network.bind(addr).unwrap();
+    let addr = transport.local_addr().unwrap();
+    let mut relay = NeonRelay::with_transport(transport, RelayConfig { worker_threads: 2, ..RelayConfig::default() }).unwrap();
+    relay.add_peer_relay(peer.parse().unwrap());
+    thread::spawn(move || relay.start());
+    addr
+}
+
+/// Register a host for `session_id` at `relay` and run it on a background thread
+fn start_host(network: &MemoryNetwork, session_id: u32, relay: SocketAddr) {
+    let mut host = NeonHost::with_transport(session_id, relay, network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.register().unwrap();
+    thread::spawn(move || -> std::io::Result<()> {
+        loop {
+            host.process_packets()?;
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+}
+
+fn connect_request(session_id: u32) -> Vec<u8> {
+    protocol::encode(&NeonPacket {
+        packet_type: PacketType::ConnectRequest as u8,
+        sequence: 1,
+        client_id: 0,
+        destination_id: 1,
+        payload: PacketPayload::ConnectRequest(ConnectRequest {
+            client_version: PROTOCOL_VERSION,
+            desired_name: "probe".to_string(),
+            target_session_id: session_id,
+            game_identifier: 0,
+            nonce: 0,
+            resume_id: 0,
+            resume_token: 0,
+            auth_token: Vec::new(),
+            role: Default::default(),
+        }),
+    })
+}
+
+/// Ask `relay` for `session_id` until it answers with a redirect
+fn wait_for_redirect(socket: &MemoryTransport, relay: SocketAddr, session_id: u32) -> Option<SessionRedirect> {
+    socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let deadline = Instant::now() + TIMEOUT;
+    let mut buf = [0; 1500];
+    while Instant::now() < deadline {
+        socket.send_to(&connect_request(session_id), relay).unwrap();
+        if let Ok((len, _)) = socket.recv_from(&mut buf)
+            && let PacketPayload::SessionRedirect(redirect) = protocol::decode(&buf[..len]).unwrap().payload
+        {
+            return Some(redirect);
+        }
+    }
+    None
+}
+
+#[test]
+fn joins_through_a_peer_relay_are_redirected_to_the_owner() {
+    let network = MemoryNetwork::new();
+    let owner = start_relay(&network, "127.0.0.1:7777", "127.0.0.2:7777");
+    let peer = start_relay(&network, "127.0.0.2:7777", "127.0.0.1:7777");
+    start_host(&network, 170, owner);
+
+    let probe = network.bind("127.0.0.1:0").unwrap();
+    let redirect = wait_for_redirect(&probe, peer, 170).expect("peer relay never learned the session");
+    assert_eq!(redirect.relays[0].addr, owner.to_string());
+
+    let mut client = NeonClient::with_transport("alice".to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.connect(170, peer).unwrap();
+    assert_eq!(client.relay_addr(), Some(owner));
+    assert_eq!(client.client_id(), Some(2));
+}
+
+#[test]
+fn each_relay_redirects_to_the_other_for_its_sessions() {
+    let network = MemoryNetwork::new();
+    let owner = start_relay(&network, "127.0.0.1:7777", "127.0.0.2:7777");
+    let peer = start_relay(&network, "127.0.0.2:7777", "127.0.0.1:7777");
+    start_host(&network, 171, owner);
+    start_host(&network, 172, peer);
+
+    let probe = network.bind("127.0.0.1:0").unwrap();
+    assert_eq!(wait_for_redirect(&probe, owner, 172).unwrap().relays[0].addr, peer.to_string());
+    assert_eq!(wait_for_redirect(&probe, peer, 171).unwrap().relays[0].addr, owner.to_string());
+
+    let mut alice = NeonClient::with_transport("alice".to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+    alice.connect(171, owner).unwrap();
+    assert_eq!(alice.relay_addr(), Some(owner));
+}
+
+#[test]
+fn announcements_from_strangers_are_ignored() {
+    let network = MemoryNetwork::new();
+    let relay = start_relay(&network, "127.0.0.1:7777", "127.0.0.2:7777");
+
+    let stranger = network.bind("127.0.0.3:7777").unwrap();
+    let announcement = NeonPacket {
+        packet_type: PacketType::SessionRedirect as u8,
+        sequence: 0,
+        client_id: 0,
+        destination_id: 0,
+        payload: PacketPayload::SessionRedirect(SessionRedirect {
+            session_id: 173,
+            relays: vec![RelayInfo { addr: "127.0.0.3:7777".to_string(), load: 0 }],
+        }),
+    };
+    stranger.send_to(&protocol::encode(&announcement), relay).unwrap();
+
+    // No host and no valid announcement, so the request goes unanswered
+    let probe = network.bind("127.0.0.1:0").unwrap();
+    probe.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+    probe.send_to(&connect_request(173), relay).unwrap();
+    assert!(probe.recv_from(&mut [0; 1500]).is_err());
+}
+
+#[test]
+fn clients_find_an_existing_session_through_the_directory() {
+    let network = MemoryNetwork::new();
+    let owner = start_relay(&network, "127.0.0.1:7777", "127.0.0.2:7777");
+    let directory = start_relay(&network, "127.0.0.2:7777", "127.0.0.1:7777");
+    start_host(&network, 174, owner);
+
+    let probe = network.bind("127.0.0.1:0").unwrap();
+    wait_for_redirect(&probe, directory, 174).expect("directory never learned the session");
+
+    let mut client = NeonClient::with_transport("alice".to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.connect_via_directory(directory, 174).unwrap();
+    assert_eq!(client.relay_addr(), Some(owner));
+    assert_eq!(client.client_id(), Some(2));
+}
+
+#[test]
+fn new_sessions_are_placed_on_the_less_loaded_relay() {
+    let network = MemoryNetwork::new();
+    let busy = start_relay(&network, "127.0.0.1:7777", "127.0.0.2:7777");
+    let idle = start_relay(&network, "127.0.0.2:7777", "127.0.0.1:7777");
+    start_host(&network, 175, busy);
+    let mut players = Vec::new();
+    for index in 0..8 {
+        let mut player = NeonClient::with_transport(format!("player{}", index), network.bind("127.0.0.1:0").unwrap()).unwrap();
+        player.connect(175, busy).unwrap();
+        players.push(player);
+    }
+
+    // Wait for the busy relay to count its host and players, which it reports first in its answers
+    let probe = network.bind("127.0.0.1:0").unwrap();
+    probe.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let query = protocol::encode(&NeonPacket {
+        packet_type: PacketType::SessionRedirect as u8,
+        sequence: 0,
+        client_id: 0,
+        destination_id: 0,
+        payload: PacketPayload::SessionRedirect(SessionRedirect { session_id: 176, relays: Vec::new() }),
+    });
+    let deadline = Instant::now() + TIMEOUT;
+    let mut load = 0;
+    let mut buf = [0; 1500];
+    while load < 9 && Instant::now() < deadline {
+        probe.send_to(&query, busy).unwrap();
+        if let Ok((len, _)) = probe.recv_from(&mut buf)
+            && let PacketPayload::SessionRedirect(answer) = protocol::decode(&buf[..len]).unwrap().payload
+        {
+            assert_eq!(answer.relays.len(), 2);
+            load = answer.relays[0].load;
+        }
+    }
+    assert_eq!(load, 9);
+
+    for _ in 0..3 {
+        assert_eq!(directory::find_relay_with(&probe, busy, 176).unwrap(), idle);
+        assert_eq!(directory::find_relay_with(&probe, idle, 176).unwrap(), idle);
+    }
+    // An existing session still goes to its owner however busy it is
+    wait_for_redirect(&probe, idle, 175).expect("idle relay never learned the session");
+    assert_eq!(directory::find_relay_with(&probe, idle, 175).unwrap(), busy);
+}
@@ -0,0 +1,245 @@
+use std::ffi::{CStr, CString, c_int, c_void};
+use std::net::UdpSocket;
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use project_neon::client::NeonClient;
+use project_neon::ffi::{self, NeonClientHandle, NeonErrorCode, NeonHostHandle, NeonRelayHandle};
+use project_neon::host::NeonHost;
+use project_neon::relay::{NeonRelay, RelayConfig};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Received = Mutex<Vec<(u8, u16, Vec<u8>)>>;
+
+/// Records into the `Received` that user_data points to
+extern "C" fn record_game_packet(packet_type: u8, from_client_id: u16, data: *const u8, len: usize, user_data: *mut c_void) {
+    let received = unsafe { &*(user_data as *const Received) };
+    let data = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+    received.lock().unwrap().push((packet_type, from_client_id, data));
+}
+
+fn context(received: &Received) -> *mut c_void {
+    received as *const Received as *mut c_void
+}
+
+/// Start a relay on a free loopback port, left running for the rest of the test process
+fn start_relay() -> CString {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let mut relay = NeonRelay::with_transport(socket, RelayConfig { worker_threads: 1, ..RelayConfig::default() }).unwrap();
+    thread::spawn(move || relay.start());
+    CString::new(addr.to_string()).unwrap()
+}
+
+fn connect(name: &str, session_id: u32, relay: &CString, received: &Received) -> *mut NeonClientHandle {
+    let name = CString::new(name).unwrap();
+    let client = ffi::neon_client_new(name.as_ptr());
+    ffi::neon_client_set_game_packet_callback(client, record_game_packet, context(received));
+    assert_eq!(ffi::neon_client_connect(client, session_id, relay.as_ptr()), NeonErrorCode::Ok);
+    client
+}
+
+/// Pump the clients until `condition` holds
+fn wait_for(clients: &[*mut NeonClientHandle], condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out");
+        for &client in clients {
+            ffi::neon_client_process_packets(client);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn game_packets_flow_through_the_c_api() {
+    // Each side records into its own list, reached through the callback's user_data
+    let (host_received, alice_received, bob_received) = (Received::default(), Received::default(), Received::default());
+    let relay = start_relay();
+    let host = ffi::neon_host_new(300, relay.as_ptr());
+    assert!(!host.is_null());
+    ffi::neon_host_set_game_packet_callback(host, record_game_packet, context(&host_received));
+    assert_eq!(ffi::neon_host_start_threaded(host), NeonErrorCode::Ok);
+
+    let alice = connect("alice", 300, &relay, &alice_received);
+    let bob = connect("bob", 300, &relay, &bob_received);
+    let (alice_id, bob_id) = (ffi::neon_client_get_id(alice), ffi::neon_client_get_id(bob));
+
+    assert_eq!(ffi::neon_client_send_game_packet(alice, 0x10, 1, b"move".as_ptr(), 4), NeonErrorCode::Ok);
+    assert_eq!(ffi::neon_client_send_game_packet(bob, 0x10, 1, ptr::null(), 0), NeonErrorCode::Ok);
+    wait_for(&[alice, bob], || host_received.lock().unwrap().len() == 2);
+    let mut received = host_received.lock().unwrap().clone();
+    received.sort();
+    let mut expected = vec![(0x10, alice_id, b"move".to_vec()), (0x10, bob_id, Vec::new())];
+    expected.sort();
+    assert_eq!(received, expected);
+
+    assert_eq!(ffi::neon_host_send_to_client(host, bob_id, 0x11, b"just bob".as_ptr(), 8), NeonErrorCode::Ok);
+    assert_eq!(ffi::neon_host_broadcast(host, 0x12, b"all".as_ptr(), 3), NeonErrorCode::Ok);
+    assert_eq!(ffi::neon_client_send_game_packet(alice, 0x13, bob_id, b"hi bob".as_ptr(), 6), NeonErrorCode::Ok);
+    wait_for(&[alice, bob], || alice_received.lock().unwrap().len() == 1 && bob_received.lock().unwrap().len() == 3);
+    assert_eq!(*alice_received.lock().unwrap(), vec![(0x12, 1, b"all".to_vec())]);
+    let received = bob_received.lock().unwrap();
+    assert!(received.contains(&(0x11, 1, b"just bob".to_vec())));
+    assert!(received.contains(&(0x12, 1, b"all".to_vec())));
+    assert!(received.contains(&(0x13, alice_id, b"hi bob".to_vec())));
+    assert_eq!(ffi::neon_host_stop(host), NeonErrorCode::Ok);
+    ffi::neon_host_free(host);
+}
+
+#[test]
+fn host_runs_from_the_callers_loop() {
+    let relay = start_relay();
+    let host = ffi::neon_host_new(301, relay.as_ptr());
+    let host_received = Received::default();
+    ffi::neon_host_set_game_packet_callback(host, record_game_packet, context(&host_received));
+    // The first update registers, so a client can connect as soon as it returns
+    assert_eq!(ffi::neon_host_update(host), NeonErrorCode::Ok);
+    let host_addr = host as usize;
+    // Connecting waits for the session config, which the host sends after admitting the client
+    static JOINED: AtomicBool = AtomicBool::new(false);
+    let pump = thread::spawn(move || {
+        let host = host_addr as *mut NeonHostHandle;
+        let deadline = Instant::now() + TIMEOUT;
+        while !JOINED.load(Ordering::Relaxed) {
+            assert!(Instant::now() < deadline, "client never joined");
+            assert_eq!(ffi::neon_host_update(host), NeonErrorCode::Ok);
+            thread::sleep(Duration::from_millis(5));
+        }
+    });
+    let client_received = Received::default();
+    let client = connect("erin", 301, &relay, &client_received);
+    JOINED.store(true, Ordering::Relaxed);
+    pump.join().unwrap();
+    assert_eq!(ffi::neon_host_get_client_count(host), 1);
+
+    assert_eq!(ffi::neon_client_send_game_packet(client, 0x10, 1, b"tick".as_ptr(), 4), NeonErrorCode::Ok);
+    let deadline = Instant::now() + TIMEOUT;
+    while host_received.lock().unwrap().is_empty() {
+        assert!(Instant::now() < deadline, "timed out");
+        assert_eq!(ffi::neon_host_update(host), NeonErrorCode::Ok);
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(host_received.lock().unwrap()[0].2, b"tick");
+    ffi::neon_client_free(client);
+    ffi::neon_host_free(host);
+}
+
+#[test]
+fn host_thread_starts_once_and_stops() {
+    let relay = start_relay();
+    let host = ffi::neon_host_new(302, relay.as_ptr());
+    assert_eq!(ffi::neon_host_stop(host), NeonErrorCode::InvalidArgument);
+    assert_eq!(last_error(), "Host isn't running on a thread");
+    assert_eq!(ffi::neon_host_start_threaded(host), NeonErrorCode::Ok);
+    assert_eq!(ffi::neon_host_start_threaded(host), NeonErrorCode::InvalidArgument);
+    assert_eq!(ffi::neon_host_stop(host), NeonErrorCode::Ok);
+    // Can be started again, and freeing a running host stops it first
+    assert_eq!(ffi::neon_host_start_threaded(host), NeonErrorCode::Ok);
+    ffi::neon_host_free(host);
+}
+
+type SessionEvents = Mutex<Vec<(&'static str, u32)>>;
+
+extern "C" fn record_created(session_id: u32, user_data: *mut c_void) {
+    let events = unsafe { &*(user_data as *const SessionEvents) };
+    events.lock().unwrap().push(("created", session_id));
+}
+
+extern "C" fn record_destroyed(session_id: u32, user_data: *mut c_void) {
+    let events = unsafe { &*(user_data as *const SessionEvents) };
+    events.lock().unwrap().push(("destroyed", session_id));
+}
+
+#[test]
+fn relay_runs_and_stops_through_the_c_api() {
+    // Take a free port for the relay to bind
+    let addr = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let bind_addr = CString::new(addr.to_string()).unwrap();
+    let events = SessionEvents::default();
+    let relay = ffi::neon_relay_new(bind_addr.as_ptr());
+    assert!(!relay.is_null());
+    let user_data = &events as *const SessionEvents as *mut c_void;
+    ffi::neon_relay_set_session_created_callback(relay, record_created, user_data);
+    ffi::neon_relay_set_session_destroyed_callback(relay, record_destroyed, user_data);
+    let relay_addr = relay as usize;
+    let relay_thread = thread::spawn(move || ffi::neon_relay_start(relay_addr as *mut NeonRelayHandle));
+
+    let mut host = NeonHost::new(400, addr).unwrap();
+    static JOINED: AtomicBool = AtomicBool::new(false);
+    let host_thread = thread::spawn(move || {
+        host.register().unwrap();
+        while !JOINED.load(Ordering::Relaxed) {
+            host.process_packets().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        host
+    });
+    let deadline = Instant::now() + TIMEOUT;
+    while ffi::neon_relay_session_count(relay) == 0 {
+        assert!(Instant::now() < deadline, "session never registered");
+        thread::sleep(Duration::from_millis(5));
+    }
+    let mut client = NeonClient::new("dave".to_string()).unwrap();
+    client.connect(400, addr).unwrap();
+    JOINED.store(true, Ordering::Relaxed);
+    let host = host_thread.join().unwrap();
+    assert_eq!(host.client_count(), 1);
+    while ffi::neon_relay_total_client_count(relay) < 2 {
+        assert!(Instant::now() < deadline, "client never counted");
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(ffi::neon_relay_session_count(relay), 1);
+
+    ffi::neon_relay_stop(relay);
+    assert_eq!(relay_thread.join().unwrap(), NeonErrorCode::Ok);
+    assert_eq!(*events.lock().unwrap(), vec![("created", 400), ("destroyed", 400)]);
+    assert_eq!(ffi::neon_relay_session_count(relay), 0);
+    assert_eq!(ffi::neon_relay_total_client_count(relay), 0);
+    ffi::neon_relay_free(relay);
+}
+
+#[test]
+fn failures_return_codes_and_messages() {
+    let name = CString::new("carol").unwrap();
+    let client = ffi::neon_client_new(name.as_ptr());
+
+    assert_eq!(ffi::neon_client_send_game_packet(client, 0x10, 1, b"x".as_ptr(), 1), NeonErrorCode::NotConnected);
+    assert_eq!(last_error(), "Client not connected");
+    assert_eq!(ffi::neon_client_send_game_packet(client, 0x05, 1, b"x".as_ptr(), 1), NeonErrorCode::InvalidArgument);
+    assert_eq!(ffi::neon_client_send_game_packet(client, 0x10, 1, ptr::null(), 4), NeonErrorCode::NullPointer);
+    assert_eq!(ffi::neon_client_connect(client, 1, ptr::null()), NeonErrorCode::NullPointer);
+    assert_eq!(last_error(), "Relay address is null");
+    let bad_utf8 = CString::new(vec![0xFF, 0xFE]).unwrap();
+    assert_eq!(ffi::neon_client_connect(client, 1, bad_utf8.as_ptr()), NeonErrorCode::InvalidArgument);
+    assert_eq!(ffi::neon_host_broadcast(ptr::null_mut(), 0x10, ptr::null(), 0), NeonErrorCode::NullPointer);
+    assert_eq!(last_error(), "Host handle is null");
+
+    // Even calls with no code to return leave a message
+    assert_eq!(ffi::neon_client_get_id(ptr::null_mut()), 0);
+    assert_eq!(last_error(), "Client handle is null");
+    assert!(ffi::neon_host_new(1, ptr::null()).is_null());
+    assert_eq!(last_error(), "Relay address is null");
+    assert_eq!(ffi::neon_relay_start(ptr::null_mut()), NeonErrorCode::NullPointer);
+    assert_eq!(last_error(), "Relay handle is null");
+    assert!(ffi::neon_relay_new(ptr::null()).is_null());
+    assert_eq!(last_error(), "Bind address is null");
+    ffi::neon_client_free(client);
+}
+
+#[test]
+fn every_code_has_a_message() {
+    let message = |code: NeonErrorCode| unsafe { CStr::from_ptr(ffi::neon_error_message(code as c_int)) }.to_str().unwrap();
+    assert_eq!(message(NeonErrorCode::Ok), "Success");
+    assert_eq!(message(NeonErrorCode::ConnectionDenied), "Connection denied");
+    assert_eq!(message(NeonErrorCode::Io), "I/O error");
+    assert_eq!(unsafe { CStr::from_ptr(ffi::neon_error_message(99)) }.to_str().unwrap(), "Unknown error code");
+}
+
+fn last_error() -> String {
+    unsafe { CStr::from_ptr(ffi::neon_get_last_error()) }.to_str().unwrap().to_string()
+}
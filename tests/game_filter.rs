@@ -0,0 +1,73 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::thread;
+
+use project_neon::relay::{GameFilter, RelayConfig};
+use project_neon::transport::{MemoryNetwork, Transport};
+use project_neon::{NeonClient, NeonHost, NeonRelay};
+
+const GAME: u32 = 0x4E45_4F4E;
+const OTHER_GAME: u32 = 0x0000_BEEF;
+
+fn start_relay(network: &MemoryNetwork, games: GameFilter) -> SocketAddr {
+    let transport = network.bind("127.0.0.1:0").unwrap();
+    let addr = transport.local_addr().unwrap();
+    let mut relay = NeonRelay::with_transport(transport, RelayConfig { worker_threads: 1, games, ..RelayConfig::default() }).unwrap();
+    thread::spawn(move || relay.start());
+    addr
+}
+
+fn host_for(network: &MemoryNetwork, session_id: u32, relay: SocketAddr, game: u32) -> NeonHost<impl Transport> {
+    let mut host = NeonHost::with_transport(session_id, relay, network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.set_game_identifier(game);
+    host
+}
+
+#[test]
+fn an_allowlist_turns_away_other_games_with_a_reason() {
+    let network = MemoryNetwork::new();
+    let relay = start_relay(&network, GameFilter::Allow(vec![GAME]));
+
+    let error = host_for(&network, 330, relay, OTHER_GAME).register().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(error.to_string(), "Game 0x0000BEEF is not served by this relay");
+    // Hosts that never set a game send 0, which isn't listed
+    assert_eq!(host_for(&network, 331, relay, 0).register().unwrap_err().kind(), ErrorKind::ConnectionRefused);
+
+    let mut host = host_for(&network, 332, relay, GAME);
+    host.register().unwrap();
+    let worker = host.spawn().unwrap();
+
+    let mut stranger = NeonClient::with_transport("mallory".to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+    stranger.set_game_identifier(OTHER_GAME);
+    let error = stranger.connect(332, relay).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(error.to_string(), "Game 0x0000BEEF is not served by this relay");
+
+    let mut player = NeonClient::with_transport("alice".to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+    player.set_game_identifier(GAME);
+    player.connect(332, relay).unwrap();
+    assert_eq!(player.session_id(), Some(332));
+
+    worker.shutdown().unwrap();
+}
+
+#[test]
+fn a_blocklist_turns_away_only_the_listed_games() {
+    let network = MemoryNetwork::new();
+    let relay = start_relay(&network, GameFilter::Block(vec![OTHER_GAME]));
+
+    assert_eq!(host_for(&network, 333, relay, OTHER_GAME).register().unwrap_err().kind(), ErrorKind::ConnectionRefused);
+    host_for(&network, 334, relay, GAME).register().unwrap();
+    host_for(&network, 335, relay, 0).register().unwrap();
+}
+
+#[test]
+fn filters_match_game_identifiers() {
+    assert!(GameFilter::Any.accepts(0));
+    assert!(GameFilter::Allow(vec![GAME]).accepts(GAME));
+    assert!(!GameFilter::Allow(vec![GAME]).accepts(0));
+    assert!(GameFilter::Block(vec![GAME]).accepts(OTHER_GAME));
+    assert!(!GameFilter::Block(vec![GAME]).accepts(GAME));
+    assert_eq!(RelayConfig::default().games, GameFilter::Any);
+}
@@ -0,0 +1,102 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use project_neon::relay::{LogFormat, Logger, SessionKey, SessionManager};
+use project_neon::transport::{MemoryNetwork, Transport};
+use project_neon::{NeonClient, NeonHost, NeonRelay};
+
+const GAME: u32 = 0x4E45_4F4E;
+const OTHER_GAME: u32 = 0x0000_BEEF;
+
+fn start_relay(network: &MemoryNetwork) -> SocketAddr {
+    let transport = network.bind("127.0.0.1:0").unwrap();
+    let addr = transport.local_addr().unwrap();
+    let mut relay = NeonRelay::with_transport(transport, Default::default()).unwrap();
+    thread::spawn(move || relay.start());
+    addr
+}
+
+/// A host for `session_id` in `game`, recording the names of clients it admits
+fn host_for(network: &MemoryNetwork, session_id: u32, relay: SocketAddr, game: u32) -> (NeonHost<impl Transport>, Arc<Mutex<Vec<String>>>) {
+    let mut host = NeonHost::with_transport(session_id, relay, network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.set_game_identifier(game);
+    let admitted = Arc::new(Mutex::new(Vec::new()));
+    let names = admitted.clone();
+    host.on_client_connect(move |_client_id, name, _session_id| names.lock().unwrap().push(name));
+    (host, admitted)
+}
+
+fn client_for(network: &MemoryNetwork, name: &str, game: u32) -> NeonClient<impl Transport> {
+    let mut client = NeonClient::with_transport(name.to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.set_game_identifier(game);
+    client
+}
+
+fn wait_for(admitted: &Mutex<Vec<String>>, expected: &[&str]) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while *admitted.lock().unwrap() != expected && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(*admitted.lock().unwrap(), expected);
+}
+
+#[test]
+fn two_games_can_host_the_same_session_id() {
+    let network = MemoryNetwork::new();
+    let relay = start_relay(&network);
+
+    let (mut neon_host, neon_admitted) = host_for(&network, 340, relay, GAME);
+    neon_host.register().unwrap();
+    let (mut beef_host, beef_admitted) = host_for(&network, 340, relay, OTHER_GAME);
+    beef_host.register().unwrap();
+    let neon_worker = neon_host.spawn().unwrap();
+    let beef_worker = beef_host.spawn().unwrap();
+
+    let mut alice = client_for(&network, "alice", GAME);
+    alice.connect(340, relay).unwrap();
+    let mut bob = client_for(&network, "bob", OTHER_GAME);
+    bob.connect(340, relay).unwrap();
+
+    wait_for(&neon_admitted, &["alice"]);
+    wait_for(&beef_admitted, &["bob"]);
+
+    neon_worker.shutdown().unwrap();
+    beef_worker.shutdown().unwrap();
+}
+
+#[test]
+fn clients_without_a_game_join_the_only_session_with_their_id() {
+    let network = MemoryNetwork::new();
+    let relay = start_relay(&network);
+
+    let (mut host, admitted) = host_for(&network, 341, relay, GAME);
+    host.register().unwrap();
+    let worker = host.spawn().unwrap();
+
+    let mut legacy = client_for(&network, "carol", 0);
+    legacy.connect(341, relay).unwrap();
+    assert_eq!(legacy.session_id(), Some(341));
+    wait_for(&admitted, &["carol"]);
+
+    worker.shutdown().unwrap();
+}
+
+#[test]
+fn session_ids_without_a_game_resolve_only_when_unambiguous() {
+    let addr = |last: u8| SocketAddr::from((Ipv4Addr::new(10, 0, 0, last), 5000));
+    let mut sessions = SessionManager::new(Logger::new(LogFormat::Json));
+    sessions.register_host(SessionKey::new(GAME, 7), addr(1));
+    sessions.register_host(SessionKey::from(8), addr(2));
+
+    assert_eq!(sessions.resolve(SessionKey::from(7)), Some(SessionKey::new(GAME, 7)));
+    // Hosts that name no game take clients of any game
+    assert_eq!(sessions.resolve(SessionKey::new(GAME, 8)), Some(SessionKey::from(8)));
+    assert_eq!(sessions.resolve(SessionKey::new(OTHER_GAME, 7)), None);
+
+    sessions.register_host(SessionKey::new(OTHER_GAME, 7), addr(3));
+    assert_eq!(sessions.resolve(SessionKey::from(7)), None);
+    assert_eq!(sessions.resolve(SessionKey::new(OTHER_GAME, 7)), Some(SessionKey::new(OTHER_GAME, 7)));
+    assert_eq!(sessions.lookup_peer(addr(3), 1), Some(SessionKey::new(OTHER_GAME, 7)));
+}
@@ -0,0 +1,108 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use project_neon::join_code;
+use project_neon::transport::{MemoryNetwork, Transport};
+use project_neon::{NeonClient, NeonHost, NeonRelay};
+
+const GAME: u32 = 0x4E45_4F4E;
+
+fn start_relay(network: &MemoryNetwork) -> SocketAddr {
+    let transport = network.bind("127.0.0.1:0").unwrap();
+    let addr = transport.local_addr().unwrap();
+    let mut relay = NeonRelay::with_transport(transport, Default::default()).unwrap();
+    thread::spawn(move || relay.start());
+    addr
+}
+
+/// A host for `session_id`, recording the names of clients it admits
+fn host_for(network: &MemoryNetwork, session_id: u32, relay: SocketAddr) -> (NeonHost<impl Transport>, Arc<Mutex<Vec<String>>>) {
+    let mut host = NeonHost::with_transport(session_id, relay, network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.set_game_identifier(GAME);
+    let admitted = Arc::new(Mutex::new(Vec::new()));
+    let names = admitted.clone();
+    host.on_client_connect(move |_client_id, name, _session_id| names.lock().unwrap().push(name));
+    (host, admitted)
+}
+
+fn client_for(network: &MemoryNetwork, name: &str) -> NeonClient<impl Transport> {
+    NeonClient::with_transport(name.to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap()
+}
+
+fn wait_for(admitted: &Mutex<Vec<String>>, expected: &[&str]) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while *admitted.lock().unwrap() != expected && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(*admitted.lock().unwrap(), expected);
+}
+
+#[test]
+fn clients_join_by_code() {
+    let network = MemoryNetwork::new();
+    let relay = start_relay(&network);
+
+    let (mut host, admitted) = host_for(&network, 350, relay);
+    let code = host.generate_join_code().to_string();
+    host.register().unwrap();
+    let worker = host.spawn().unwrap();
+
+    let mut alice = client_for(&network, "alice");
+    alice.connect_with_code(&code.to_lowercase(), relay).unwrap();
+    assert_eq!(alice.session_id(), Some(350));
+    wait_for(&admitted, &["alice"]);
+
+    let mut bob = client_for(&network, "bob");
+    let err = bob.connect_with_code("NOPE-123", relay).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert_eq!(bob.session_id(), None);
+
+    worker.shutdown().unwrap();
+}
+
+#[test]
+fn codes_are_held_by_one_session_until_it_closes() {
+    let network = MemoryNetwork::new();
+    let relay = start_relay(&network);
+
+    let (mut first, _) = host_for(&network, 351, relay);
+    first.set_join_code("crab-7f2").unwrap();
+    assert_eq!(first.join_code(), Some("CRAB-7F2"));
+    first.register().unwrap();
+
+    let (mut second, admitted) = host_for(&network, 352, relay);
+    second.set_join_code("CRAB-7F2").unwrap();
+    let err = second.register().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert!(err.to_string().contains("Join code CRAB-7F2 is taken"), "{}", err);
+
+    first.close_session().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while let Err(e) = second.register() {
+        assert!(Instant::now() < deadline, "code was never released: {}", e);
+        thread::sleep(Duration::from_millis(10));
+    }
+    let worker = second.spawn().unwrap();
+
+    let mut carol = client_for(&network, "carol");
+    carol.connect_with_code("CRAB-7F2", relay).unwrap();
+    assert_eq!(carol.session_id(), Some(352));
+    wait_for(&admitted, &["carol"]);
+
+    worker.shutdown().unwrap();
+}
+
+#[test]
+fn malformed_codes_are_rejected() {
+    assert_eq!(join_code::normalize(" crab-7f2 ").unwrap(), "CRAB-7F2");
+    for code in ["", "CRAB 7F2", "CRAB_7F2", "ABCDEFGHIJKLMNOPQ"] {
+        assert_eq!(join_code::normalize(code).unwrap_err().kind(), ErrorKind::InvalidInput, "{:?}", code);
+    }
+
+    let code = join_code::generate();
+    assert_eq!(join_code::normalize(&code).unwrap(), code);
+    assert_eq!(code.len(), 8);
+}
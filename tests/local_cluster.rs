@@ -0,0 +1,845 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::protocol::{
+    self, Ack, ConnectAccept, ConnectRequest, DisconnectReason, NeonPacket, PacketPayload, PacketType, PROTOCOL_V1, PROTOCOL_VERSION, SESSION_FULL,
+};
+use project_neon::NeonClient;
+use project_neon::host::{ConnectDecision, HostConfig};
+use project_neon::testing::{LocalCluster, MemoryTransport};
+use project_neon::transport::Transport;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn clients_get_distinct_ids() {
+    let mut cluster = LocalCluster::with_clients(42, &["alice", "bob", "carol"]).unwrap();
+
+    let ids: Vec<u16> = (0..3).map(|i| cluster.client(i).client_id().unwrap()).collect();
+    assert_eq!(ids, vec![2, 3, 4]);
+    for i in 0..3 {
+        assert_eq!(cluster.client(i).session_id(), Some(42));
+    }
+    assert_eq!(cluster.host(|host| host.client_count()), 3);
+}
+
+#[test]
+fn client_receives_session_config() {
+    let cluster = LocalCluster::new(7).unwrap();
+    let mut client = NeonClient::with_transport("alice".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    let config = Arc::new(Mutex::new(None));
+    {
+        let config = config.clone();
+        client.on_session_config(move |version, tick_rate, max_packet_size| {
+            *config.lock().unwrap() = Some((version, tick_rate, max_packet_size));
+        });
+    }
+
+    // connect returns once the config is in
+    client.connect(7, cluster.relay_addr()).unwrap();
+    assert_eq!(*config.lock().unwrap(), Some((PROTOCOL_VERSION, 60, 1024)));
+    assert_eq!(client.tick_rate(), Some(60));
+}
+
+#[test]
+fn duplicate_name_is_denied() {
+    let mut cluster = LocalCluster::with_clients(3, &["alice"]).unwrap();
+
+    let err = cluster.add_client("alice").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(cluster.host(|host| host.client_count()), 1);
+}
+
+#[test]
+fn game_packet_reaches_host() {
+    let mut cluster = LocalCluster::new(11).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    {
+        let received = received.clone();
+        cluster.host(|host| {
+            host.on_game_packet(move |packet_type, from, data| {
+                received.lock().unwrap().push((packet_type, from, data.to_vec()));
+            })
+        });
+    }
+    cluster.add_client("alice").unwrap();
+
+    cluster.client(0).send_game_packet(0x10, b"hello").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(received.lock().unwrap()[0], (0x10, 2, b"hello".to_vec()));
+}
+
+#[test]
+fn game_packet_reaches_client() {
+    let mut cluster = LocalCluster::with_clients(12, &["alice", "bob"]).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    {
+        let received = received.clone();
+        cluster.client(1).on_game_packet(move |packet_type, from, data| {
+            received.lock().unwrap().push((packet_type, from, data.to_vec()));
+        });
+    }
+
+    cluster.host(|host| host.send_game_packet(3, 0x20, b"state")).unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(received.lock().unwrap()[0], (0x20, 1, b"state".to_vec()));
+}
+
+#[test]
+fn ping_gets_pong() {
+    let mut cluster = LocalCluster::with_clients(13, &["alice"]).unwrap();
+    let pongs = Arc::new(Mutex::new(0));
+    {
+        let pongs = pongs.clone();
+        cluster.client(0).on_pong(move |_, _| *pongs.lock().unwrap() += 1);
+    }
+
+    cluster.client(0).send_ping().unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| *pongs.lock().unwrap() > 0).unwrap());
+}
+
+#[test]
+fn batched_packets_arrive_in_order() {
+    let mut cluster = LocalCluster::new(14).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    {
+        let received = received.clone();
+        cluster.host(|host| {
+            host.on_game_packet(move |_, _, data| received.lock().unwrap().push(data[0]));
+        });
+    }
+    cluster.add_client("alice").unwrap();
+    cluster.client(0).set_batching(true);
+
+    for i in 0..20u8 {
+        cluster.client(0).send_game_packet(0x10, &[i; 16]).unwrap();
+    }
+    cluster.client(0).flush().unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| received.lock().unwrap().len() == 20).unwrap());
+    assert_eq!(*received.lock().unwrap(), (0..20).collect::<Vec<u8>>());
+}
+
+#[test]
+fn shutdown_stops_host_cleanly() {
+    let cluster = LocalCluster::with_clients(15, &["alice"]).unwrap();
+    cluster.shutdown().unwrap();
+}
+
+#[test]
+fn registry_lists_host_packet_types() {
+    let mut cluster = LocalCluster::new(16).unwrap();
+    cluster.host(|host| {
+        host.register_packet_type(0x20, "Chat", "UTF-8 text")?;
+        host.register_packet_types(&[(0x10, "Move", "x, y as f32"), (0x11, "Fire", "")])
+    }).unwrap();
+    cluster.add_client("alice").unwrap();
+    let registry = Arc::new(Mutex::new(None));
+    {
+        let registry = registry.clone();
+        cluster.client(0).on_packet_type_registry(move |entries| *registry.lock().unwrap() = Some(entries));
+    }
+
+    assert!(cluster.run_until(TIMEOUT, |_| registry.lock().unwrap().is_some()).unwrap());
+    let expected = [(0x10, "Move", "x, y as f32"), (0x11, "Fire", ""), (0x20, "Chat", "UTF-8 text")]
+        .map(|(id, name, description)| (id, name.to_string(), description.to_string()));
+    assert_eq!(registry.lock().unwrap().as_deref(), Some(&expected[..]));
+}
+
+#[test]
+fn reserved_packet_types_cannot_be_registered() {
+    let cluster = LocalCluster::new(17).unwrap();
+    cluster.host(|host| {
+        let err = host.register_packet_type(0x0B, "Ping", "").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        // One bad entry rejects the whole batch
+        let long_name = "x".repeat(256);
+        assert!(host.register_packet_types(&[(0x10, "Move", ""), (0x11, &long_name, "")]).is_err());
+        assert!(host.packet_types().is_empty());
+    });
+}
+
+#[test]
+fn schema_mismatch_reports_unknown_and_missing_types() {
+    let mut cluster = LocalCluster::new(18).unwrap();
+    cluster.host(|host| host.register_packet_types(&[(0x10, "Move", ""), (0x12, "Emote", "")])).unwrap();
+    cluster.add_client("alice").unwrap();
+    let mismatch = Arc::new(Mutex::new(None));
+    {
+        let mismatch = mismatch.clone();
+        let client = cluster.client(0);
+        client.expect_packet_types(&[0x10, 0x11]).unwrap();
+        client.on_schema_mismatch(move |unknown, missing| *mismatch.lock().unwrap() = Some((unknown, missing)));
+    }
+
+    assert!(cluster.run_until(TIMEOUT, |_| mismatch.lock().unwrap().is_some()).unwrap());
+    assert_eq!(*mismatch.lock().unwrap(), Some((vec![0x12], vec![0x11])));
+}
+
+#[test]
+fn matching_schema_is_silent() {
+    let mut cluster = LocalCluster::new(19).unwrap();
+    cluster.host(|host| host.register_packet_types(&[(0x10, "Move", ""), (0x11, "Fire", "")])).unwrap();
+    cluster.add_client("alice").unwrap();
+    let mismatched = Arc::new(Mutex::new(false));
+    let registry_seen = Arc::new(Mutex::new(false));
+    {
+        let mismatched = mismatched.clone();
+        let registry_seen = registry_seen.clone();
+        let client = cluster.client(0);
+        client.expect_packet_types(&[0x11, 0x10]).unwrap();
+        client.on_schema_mismatch(move |_, _| *mismatched.lock().unwrap() = true);
+        client.on_packet_type_registry(move |_| *registry_seen.lock().unwrap() = true);
+    }
+
+    assert!(cluster.run_until(TIMEOUT, |_| *registry_seen.lock().unwrap()).unwrap());
+    assert!(!*mismatched.lock().unwrap());
+    assert_eq!(cluster.client(0).expect_packet_types(&[0x05]).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn clients_can_address_each_other() {
+    let mut cluster = LocalCluster::with_clients(20, &["alice", "bob", "carol"]).unwrap();
+    let logs: Vec<_> = (0..3)
+        .map(|i| {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let sink = log.clone();
+            cluster.client(i).on_game_packet(move |packet_type, from, data| {
+                sink.lock().unwrap().push((packet_type, from, data.to_vec()));
+            });
+            log
+        })
+        .collect();
+
+    cluster.client(0).send_game_packet_to(4, 0x30, b"to carol").unwrap();
+    cluster.client(2).set_batching(true);
+    cluster.client(2).send_game_packet_to(3, 0x31, b"one").unwrap();
+    cluster.client(2).send_game_packet_to(3, 0x31, b"two").unwrap();
+    cluster.client(2).flush().unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| {
+        !logs[2].lock().unwrap().is_empty() && logs[1].lock().unwrap().len() == 2
+    }).unwrap());
+    assert_eq!(*logs[2].lock().unwrap(), vec![(0x30, 2, b"to carol".to_vec())]);
+    assert_eq!(*logs[1].lock().unwrap(), vec![(0x31, 4, b"one".to_vec()), (0x31, 4, b"two".to_vec())]);
+    assert!(logs[0].lock().unwrap().is_empty());
+
+    for target in [0, 2] {
+        let err = cluster.client(0).send_game_packet_to(target, 0x30, b"").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn clients_negotiate_v2_and_reuse_freed_ids() {
+    let mut cluster = LocalCluster::with_clients(21, &["alice", "bob", "carol"]).unwrap();
+    assert_eq!(cluster.client(0).protocol_version(), PROTOCOL_VERSION);
+
+    cluster.host(|host| host.set_id_reuse_delay(Duration::ZERO));
+    assert!(cluster.host(|host| host.remove_client(3)).unwrap());
+    let dave = cluster.add_client("dave").unwrap();
+    assert_eq!(cluster.client(dave).client_id(), Some(3));
+    let erin = cluster.add_client("erin").unwrap();
+    assert_eq!(cluster.client(erin).client_id(), Some(5));
+}
+
+#[test]
+fn recently_freed_ids_are_held_back() {
+    let mut cluster = LocalCluster::with_clients(23, &["alice", "bob"]).unwrap();
+    assert!(cluster.host(|host| host.remove_client(2)).unwrap());
+
+    let carol = cluster.add_client("carol").unwrap();
+    assert_eq!(cluster.client(carol).client_id(), Some(4), "a never-used ID beats one just released");
+}
+
+#[test]
+fn full_sessions_deny_until_a_slot_frees_up() {
+    let mut cluster = LocalCluster::with_clients(24, &["alice", "bob"]).unwrap();
+    cluster.host(|host| host.set_max_clients(Some(2)));
+
+    let err = cluster.add_client("carol").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(err.to_string(), SESSION_FULL);
+
+    assert!(cluster.host(|host| host.remove_client(2)).unwrap());
+    let carol = cluster.add_client("carol").unwrap();
+    assert_eq!(cluster.client(carol).client_id(), Some(4));
+}
+
+/// Join the cluster's session by hand over a bare transport, speaking up to `version`.
+/// Returns the transport, the header version the host answered with, and the assigned ID.
+fn join_raw(cluster: &LocalCluster, name: &str, version: u8) -> (MemoryTransport, u8, u16) {
+    let socket = request_join(cluster, name, version);
+    let (accepted_version, client_id) = confirm_join(cluster, &socket);
+    (socket, accepted_version, client_id)
+}
+
+/// Send a ConnectRequest from a fresh transport without waiting for the answer
+fn request_join(cluster: &LocalCluster, name: &str, version: u8) -> MemoryTransport {
+    let socket = cluster.network().bind("0.0.0.0:0").unwrap();
+    socket.set_read_timeout(Some(TIMEOUT)).unwrap();
+    send_join_request(cluster, &socket, cluster.session_id(), name, version);
+    socket
+}
+
+/// Ask to join `session_id` from `socket`
+fn send_join_request(cluster: &LocalCluster, socket: &MemoryTransport, session_id: u32, name: &str, version: u8) {
+    let request = NeonPacket {
+        packet_type: PacketType::ConnectRequest as u8,
+        sequence: 1,
+        client_id: 0,
+        destination_id: 1,
+        payload: PacketPayload::ConnectRequest(ConnectRequest {
+            client_version: version,
+            desired_name: name.to_string(),
+            target_session_id: session_id,
+            game_identifier: 0,
+            nonce: 0,
+            resume_id: 0,
+            resume_token: 0,
+            auth_token: Vec::new(),
+            role: Default::default(),
+        }),
+    };
+    socket.send_to(&protocol::encode_versioned(&request, PROTOCOL_V1, None), cluster.relay_addr()).unwrap();
+}
+
+/// Wait for the answer to a `request_join`, past the relay's Ack, returning its header version too
+fn receive_answer(socket: &MemoryTransport) -> (u8, NeonPacket) {
+    let mut buf = [0; 1500];
+    loop {
+        let (len, _) = socket.recv_from(&mut buf).unwrap();
+        let packet = protocol::decode(&buf[..len]).unwrap();
+        if !matches!(packet.payload, PacketPayload::Ack(_)) {
+            return (buf[2], packet);
+        }
+    }
+}
+
+/// Wait for the ConnectAccept to a `request_join` and echo it back.
+/// Returns the header version the host answered with and the assigned ID.
+fn confirm_join(cluster: &LocalCluster, socket: &MemoryTransport) -> (u8, u16) {
+    let (accepted_version, answer) = receive_answer(socket);
+    let PacketPayload::ConnectAccept(accept) = answer.payload else {
+        panic!("expected ConnectAccept");
+    };
+
+    // Echoing the accept is what registers this address with the relay
+    let client_id = accept.assigned_client_id;
+    let confirmation = NeonPacket {
+        packet_type: PacketType::ConnectAccept as u8,
+        sequence: 2,
+        client_id,
+        destination_id: 1,
+        payload: PacketPayload::ConnectAccept(accept),
+    };
+    socket.send_to(&protocol::encode_versioned(&confirmation, accepted_version, None), cluster.relay_addr()).unwrap();
+    (accepted_version, client_id)
+}
+
+#[test]
+fn v1_requests_get_a_v1_session() {
+    let cluster = LocalCluster::new(22).unwrap();
+    let (_, version, client_id) = join_raw(&cluster, "legacy", PROTOCOL_V1);
+    assert_eq!(version, PROTOCOL_V1);
+    assert_eq!(client_id, 2);
+}
+
+#[test]
+fn relay_drops_packets_with_a_spoofed_client_id() {
+    let mut cluster = LocalCluster::with_clients(25, &["alice"]).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.host(|host| host.on_game_packet(move |_, from, data| sink.lock().unwrap().push((from, data.to_vec()))));
+
+    let (socket, version, mallory) = join_raw(&cluster, "mallory", PROTOCOL_VERSION);
+    assert_eq!(mallory, 3);
+    for (sequence, claimed, data) in [(3, 2, b"forged"), (4, mallory, b"honest")] {
+        let packet = NeonPacket {
+            packet_type: 0x10,
+            sequence,
+            client_id: claimed,
+            destination_id: 1,
+            payload: PacketPayload::GamePacket(data.to_vec()),
+        };
+        socket.send_to(&protocol::encode_versioned(&packet, version, None), cluster.relay_addr()).unwrap();
+    }
+
+    // Delivery is in order, so the forged packet would have arrived first
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*received.lock().unwrap(), vec![(mallory, b"honest".to_vec())]);
+}
+
+fn registration(client_id: u16, session_id: u32, cookie: u64) -> Vec<u8> {
+    let packet = NeonPacket {
+        packet_type: PacketType::ConnectAccept as u8,
+        sequence: 1,
+        client_id,
+        destination_id: 1,
+        payload: PacketPayload::ConnectAccept(ConnectAccept { assigned_client_id: client_id, session_id, cookie, nonce: 0, resume_token: 0, game_identifier: 0, join_code: String::new(), metadata: Default::default(), features: Default::default() }),
+    };
+    protocol::encode(&packet)
+}
+
+#[test]
+fn host_registration_needs_the_relay_cookie() {
+    let mut cluster = LocalCluster::new(26).unwrap();
+    let impostor = cluster.network().bind("0.0.0.0:0").unwrap();
+    impostor.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    impostor.send_to(&registration(1, 26, 0), cluster.relay_addr()).unwrap();
+    let mut buf = [0; 1500];
+    let (len, _) = impostor.recv_from(&mut buf).unwrap();
+    let PacketPayload::Challenge(challenge) = protocol::decode(&buf[..len]).unwrap().payload else {
+        panic!("expected Challenge");
+    };
+
+    // A cookie is only good for the address it was sent to
+    let other = cluster.network().bind("0.0.0.0:0").unwrap();
+    other.send_to(&registration(1, 26, challenge.cookie), cluster.relay_addr()).unwrap();
+    other.send_to(&registration(1, 26, 0xBAD), cluster.relay_addr()).unwrap();
+
+    // The real host still owns the session, so joins reach it
+    let alice = cluster.add_client("alice").unwrap();
+    assert_eq!(cluster.client(alice).client_id(), Some(2));
+}
+
+#[test]
+fn client_registration_needs_the_routed_cookie() {
+    let mut cluster = LocalCluster::with_clients(27, &["alice"]).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.client(0).on_game_packet(move |_, _, data| sink.lock().unwrap().push(data.to_vec()));
+
+    // Claiming alice's ID without her cookie must not take over her address binding
+    let impostor = cluster.network().bind("0.0.0.0:0").unwrap();
+    impostor.send_to(&registration(2, 27, 0), cluster.relay_addr()).unwrap();
+
+    cluster.host(|host| host.send_game_packet(2, 0x10, b"for alice")).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(cluster.network().pending(impostor.local_addr().unwrap()), 0);
+}
+
+#[test]
+fn simultaneous_joins_get_their_own_accepts() {
+    let mut cluster = LocalCluster::new(27).unwrap();
+    let joined = Arc::new(Mutex::new(Vec::new()));
+    let sink = joined.clone();
+    cluster.host(|host| host.on_client_connect(move |id, name, _| sink.lock().unwrap().push((id, name))));
+
+    // All three requests are pending at the relay before the host answers either
+    let names = ["alice", "bob", "carol"];
+    let sockets: Vec<_> = names.iter().map(|name| request_join(&cluster, name, PROTOCOL_VERSION)).collect();
+    let ids: Vec<_> = sockets.iter().map(|socket| confirm_join(&cluster, socket).1).collect();
+    assert!(cluster.run_until(TIMEOUT, |_| joined.lock().unwrap().len() == names.len()).unwrap());
+
+    let mut joined = joined.lock().unwrap().clone();
+    joined.sort();
+    let expected: Vec<_> = ids.iter().zip(names).map(|(&id, name)| (id, name.to_string())).collect();
+    assert_eq!(joined, expected);
+}
+
+#[test]
+fn clients_connecting_from_threads_all_join() {
+    let cluster = LocalCluster::new(28).unwrap();
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let network = cluster.network().clone();
+            let (session_id, relay_addr) = (cluster.session_id(), cluster.relay_addr());
+            std::thread::spawn(move || {
+                let mut client = NeonClient::with_transport(format!("player{i}"), network.bind("127.0.0.1:0").unwrap()).unwrap();
+                client.connect(session_id, relay_addr).unwrap();
+                client.client_id().unwrap()
+            })
+        })
+        .collect();
+
+    let mut ids: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![2, 3, 4, 5]);
+}
+
+#[test]
+fn connect_request_callback_can_turn_clients_away() {
+    let mut cluster = LocalCluster::new(29).unwrap();
+    let denied = Arc::new(Mutex::new(Vec::new()));
+    let sink = denied.clone();
+    cluster.host(|host| {
+        host.on_connect_request(|_, name, _| match name {
+            "mallory" => ConnectDecision::Deny("Not on the guest list".to_string()),
+            _ => ConnectDecision::Accept,
+        });
+        host.on_client_deny(move |name, reason| sink.lock().unwrap().push((name, reason)));
+    });
+
+    let err = cluster.add_client("mallory").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(err.to_string(), "Not on the guest list");
+    assert_eq!(*denied.lock().unwrap(), vec![("mallory".to_string(), "Not on the guest list".to_string())]);
+
+    let alice = cluster.add_client("alice").unwrap();
+    assert_eq!(cluster.client(alice).client_id(), Some(2));
+}
+
+#[test]
+fn deferred_requests_wait_for_the_host() {
+    let cluster = LocalCluster::new(30).unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let sink = requests.clone();
+    cluster.host(|host| {
+        host.on_connect_request(move |request_id, name, _| {
+            sink.lock().unwrap().push((request_id, name.to_string()));
+            ConnectDecision::Defer
+        })
+    });
+
+    let alice = request_join(&cluster, "alice", PROTOCOL_VERSION);
+    let bob = request_join(&cluster, "bob", PROTOCOL_VERSION);
+    let deadline = std::time::Instant::now() + TIMEOUT;
+    while cluster.host(|host| host.deferred_requests().len()) < 2 {
+        assert!(std::time::Instant::now() < deadline, "requests never reached the host");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    let requests = requests.lock().unwrap().clone();
+    assert_eq!(requests.iter().map(|(_, name)| name.as_str()).collect::<Vec<_>>(), ["alice", "bob"]);
+    let (alice_request, bob_request) = (requests[0].0, requests[1].0);
+
+    assert!(cluster.host(|host| host.deny_request(bob_request, "Maybe later")).unwrap());
+    let PacketPayload::ConnectDeny(deny) = receive_answer(&bob).1.payload else {
+        panic!("expected ConnectDeny");
+    };
+    assert_eq!(deny.reason, "Maybe later");
+
+    assert!(cluster.host(|host| host.accept_request(alice_request)).unwrap());
+    assert_eq!(confirm_join(&cluster, &alice).1, 2);
+
+    // Each request can only be answered once
+    assert!(!cluster.host(|host| host.accept_request(bob_request)).unwrap());
+    assert!(cluster.host(|host| host.deferred_requests()).is_empty());
+}
+
+#[test]
+fn hosts_welcome_clients_once_the_relay_registers_them() {
+    let cluster = LocalCluster::new(31).unwrap();
+    let connected = Arc::new(Mutex::new(Vec::new()));
+    let sink = connected.clone();
+    cluster.host(|host| host.on_client_connect(move |id, name, _| sink.lock().unwrap().push((id, name))));
+
+    let socket = request_join(&cluster, "alice", PROTOCOL_VERSION);
+    let mut buf = [0; 1500];
+    let (_, answer) = receive_answer(&socket);
+    assert!(matches!(answer.payload, PacketPayload::ConnectAccept(_)));
+
+    // Admitted, but nothing more is sent until the relay can reach the client
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(connected.lock().unwrap().is_empty());
+    socket.set_nonblocking(true).unwrap();
+    assert!(socket.recv_from(&mut buf).is_err());
+    socket.set_nonblocking(false).unwrap();
+
+    let PacketPayload::ConnectAccept(accept) = answer.payload else { unreachable!() };
+    let confirmation = NeonPacket {
+        packet_type: PacketType::ConnectAccept as u8,
+        sequence: 2,
+        client_id: accept.assigned_client_id,
+        destination_id: 1,
+        payload: PacketPayload::ConnectAccept(accept),
+    };
+    socket.send_to(&protocol::encode(&confirmation), cluster.relay_addr()).unwrap();
+
+    let (_, welcome) = receive_answer(&socket);
+    assert!(matches!(welcome.payload, PacketPayload::SessionConfig(_)), "{:?}", welcome.payload);
+    // on_client_connect fires on the host thread just after the welcome goes out
+    let deadline = std::time::Instant::now() + TIMEOUT;
+    while connected.lock().unwrap().is_empty() && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(*connected.lock().unwrap(), [(2, "alice".to_string())]);
+}
+
+#[test]
+fn unacknowledged_handshake_packets_are_resent() {
+    let cluster = LocalCluster::new(32).unwrap();
+    let socket = request_join(&cluster, "alice", PROTOCOL_VERSION);
+    socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+
+    // Never confirmed, so the host sends the accept again
+    let first = next_of_type(&socket, PacketType::ConnectAccept);
+    let again = next_of_type(&socket, PacketType::ConnectAccept);
+    assert_eq!(first.sequence, again.sequence);
+
+    let PacketPayload::ConnectAccept(accept) = again.payload else { unreachable!() };
+    let client_id = accept.assigned_client_id;
+    assert_eq!(client_id, 2);
+    let confirmation = NeonPacket {
+        packet_type: PacketType::ConnectAccept as u8,
+        sequence: 2,
+        client_id,
+        destination_id: 1,
+        payload: PacketPayload::ConnectAccept(accept),
+    };
+    socket.send_to(&protocol::encode(&confirmation), cluster.relay_addr()).unwrap();
+
+    // Acknowledging only the session config leaves the registry to be resent
+    let config = next_of_type(&socket, PacketType::SessionConfig);
+    let ack = NeonPacket {
+        packet_type: PacketType::Ack as u8,
+        sequence: 3,
+        client_id,
+        destination_id: 1,
+        payload: PacketPayload::Ack(Ack { acknowledged_sequences: vec![config.sequence] }),
+    };
+    socket.send_to(&protocol::encode(&ack), cluster.relay_addr()).unwrap();
+    let registry = next_of_type(&socket, PacketType::PacketTypeRegistry);
+    let resent = next_of_type(&socket, PacketType::PacketTypeRegistry);
+    assert_eq!(registry.sequence, resent.sequence);
+}
+
+/// Read from `socket` until a packet of `packet_type` arrives
+fn next_of_type(socket: &MemoryTransport, packet_type: PacketType) -> NeonPacket {
+    let mut buf = [0; 1500];
+    loop {
+        let (len, _) = socket.recv_from(&mut buf).unwrap();
+        let packet = protocol::decode(&buf[..len]).unwrap();
+        if packet.packet_type == packet_type as u8 {
+            return packet;
+        }
+    }
+}
+
+#[test]
+fn clients_that_never_finish_the_handshake_are_dropped() {
+    let cluster = LocalCluster::new(33).unwrap();
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let sink = failures.clone();
+    cluster.host(|host| host.on_delivery_failed(move |client_id, packet_type| sink.lock().unwrap().push((client_id, packet_type))));
+
+    // Never confirms its accept
+    let _socket = request_join(&cluster, "alice", PROTOCOL_VERSION);
+    let deadline = std::time::Instant::now() + Duration::from_secs(20);
+    while failures.lock().unwrap().is_empty() {
+        assert!(std::time::Instant::now() < deadline, "the accept never gave up");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    assert_eq!(*failures.lock().unwrap(), [(2, PacketType::ConnectAccept as u8)]);
+    assert_eq!(cluster.host(|host| host.client_count()), 0);
+}
+
+#[test]
+fn host_config_sets_how_soon_a_handshake_gives_up() {
+    let cluster = LocalCluster::new(34).unwrap();
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let sink = failures.clone();
+    cluster.host(|host| {
+        host.set_config(HostConfig { ack_timeout: Duration::from_millis(100), max_retries: 1, ..HostConfig::default() }).unwrap();
+        host.on_delivery_failed(move |client_id, packet_type| sink.lock().unwrap().push((client_id, packet_type)));
+    });
+
+    let _socket = request_join(&cluster, "alice", PROTOCOL_VERSION);
+    let deadline = std::time::Instant::now() + Duration::from_secs(2);
+    while failures.lock().unwrap().is_empty() {
+        assert!(std::time::Instant::now() < deadline, "the accept was still being resent");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(*failures.lock().unwrap(), [(2, PacketType::ConnectAccept as u8)]);
+}
+
+#[test]
+fn host_config_rejects_a_zero_tick_rate() {
+    let cluster = LocalCluster::new(35).unwrap();
+    let err = cluster.host(|host| host.set_config(HostConfig { tick_rate: 0, ..HostConfig::default() })).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn silent_clients_are_dropped_after_the_client_timeout() {
+    let cluster = LocalCluster::new(36).unwrap();
+    let disconnects = Arc::new(Mutex::new(Vec::new()));
+    let sink = disconnects.clone();
+    cluster.host(|host| {
+        host.set_config(HostConfig { client_timeout: Some(Duration::from_millis(300)), ..HostConfig::default() }).unwrap();
+        host.on_client_disconnect(move |client_id, reason| sink.lock().unwrap().push((client_id, reason)));
+    });
+
+    let socket = request_join(&cluster, "alice", PROTOCOL_VERSION);
+    let (_, client_id) = confirm_join(&cluster, &socket);
+    let deadline = std::time::Instant::now() + Duration::from_secs(2);
+    while disconnects.lock().unwrap().is_empty() {
+        assert!(std::time::Instant::now() < deadline, "the silent client was never dropped");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(*disconnects.lock().unwrap(), [(client_id, DisconnectReason::TimedOut)]);
+    assert_eq!(cluster.host(|host| host.client_count()), 0);
+}
+
+#[test]
+fn reliable_game_packets_are_acked_and_delivered_once() {
+    let mut cluster = LocalCluster::with_clients(37, &["alice"]).unwrap();
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let sink = failures.clone();
+    cluster.host(|host| {
+        host.set_config(HostConfig { ack_timeout: Duration::from_millis(50), max_retries: 20, ..HostConfig::default() }).unwrap();
+        host.on_delivery_failed(move |client_id, packet_type| sink.lock().unwrap().push((client_id, packet_type)));
+    });
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.client(0).on_game_packet(move |packet_type, from, data| sink.lock().unwrap().push((packet_type, from, data.to_vec())));
+
+    cluster.host(|host| host.send_reliable_game_packet(2, 0x20, b"state")).unwrap();
+    // Left unread long enough for the host to resend it a few times
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    cluster.run_until(Duration::from_millis(300), |_| false).unwrap();
+
+    assert_eq!(*received.lock().unwrap(), [(0x20, 1, b"state".to_vec())]);
+    assert!(failures.lock().unwrap().is_empty());
+}
+
+#[test]
+fn piggybacked_acks_ride_on_game_packets_or_go_alone_when_idle() {
+    let mut cluster = LocalCluster::with_clients(38, &["alice"]).unwrap();
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let (failure_sink, received_sink) = (failures.clone(), received.clone());
+    cluster.host(|host| {
+        host.set_config(HostConfig { ack_timeout: Duration::from_millis(500), max_retries: 0, ..HostConfig::default() }).unwrap();
+        host.on_delivery_failed(move |client_id, packet_type| failure_sink.lock().unwrap().push((client_id, packet_type)));
+        host.on_game_packet(move |packet_type, from, data| received_sink.lock().unwrap().push((packet_type, from, data.to_vec())));
+    });
+    let replies = Arc::new(Mutex::new(0));
+    let sink = replies.clone();
+    cluster.client(0).set_piggyback(true);
+    cluster.client(0).on_game_packet(move |_, _, _| *sink.lock().unwrap() += 1);
+
+    // Answered with a game packet, which carries the ack
+    cluster.host(|host| host.send_reliable_game_packet(2, 0x20, b"state")).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| *replies.lock().unwrap() == 1).unwrap());
+    cluster.client(0).send_game_packet(0x21, b"input").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*received.lock().unwrap(), [(0x21, 2, b"input".to_vec())]);
+
+    // Nothing to ride on, so the ack goes alone
+    cluster.host(|host| host.send_reliable_game_packet(2, 0x20, b"state")).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| *replies.lock().unwrap() == 2).unwrap());
+    cluster.run_until(Duration::from_millis(800), |_| false).unwrap();
+    assert!(failures.lock().unwrap().is_empty(), "{:?}", failures.lock().unwrap());
+}
+
+/// Register `socket` as the host of `session_id`, answering the relay's challenge
+fn register_raw_host(cluster: &LocalCluster, socket: &MemoryTransport, session_id: u32) {
+    socket.set_read_timeout(Some(TIMEOUT)).unwrap();
+    socket.send_to(&registration(1, session_id, 0), cluster.relay_addr()).unwrap();
+    let PacketPayload::Challenge(challenge) = next_of_type(socket, PacketType::Challenge).payload else {
+        panic!("expected Challenge");
+    };
+    socket.send_to(&registration(1, session_id, challenge.cookie), cluster.relay_addr()).unwrap();
+}
+
+/// Accept the next ConnectRequest `host` receives as `client_id` of `session_id`
+fn accept_raw_join(cluster: &LocalCluster, host: &MemoryTransport, session_id: u32, client_id: u16) {
+    let PacketPayload::ConnectRequest(request) = next_of_type(host, PacketType::ConnectRequest).payload else {
+        panic!("expected ConnectRequest");
+    };
+    let accept = NeonPacket {
+        packet_type: PacketType::ConnectAccept as u8,
+        sequence: 1,
+        client_id,
+        destination_id: client_id,
+        payload: PacketPayload::ConnectAccept(ConnectAccept {
+            assigned_client_id: client_id,
+            session_id,
+            cookie: 0,
+            nonce: request.nonce,
+            resume_token: 0,
+            game_identifier: 0,
+            join_code: String::new(),
+            metadata: Default::default(),
+            features: Default::default(),
+        }),
+    };
+    host.send_to(&protocol::encode(&accept), cluster.relay_addr()).unwrap();
+}
+
+fn send_game_packet(cluster: &LocalCluster, socket: &MemoryTransport, from: u16, to: u16, data: &[u8]) {
+    let packet = NeonPacket {
+        packet_type: 0x10,
+        sequence: 10,
+        client_id: from,
+        destination_id: to,
+        payload: PacketPayload::GamePacket(data.to_vec()),
+    };
+    socket.send_to(&protocol::encode(&packet), cluster.relay_addr()).unwrap();
+}
+
+/// Read from `socket` until a game packet arrives, returning who it's from and its data
+fn next_game_packet(socket: &MemoryTransport) -> (u16, u16, Vec<u8>) {
+    let mut buf = [0; 1500];
+    loop {
+        let (len, _) = socket.recv_from(&mut buf).unwrap();
+        let packet = protocol::decode(&buf[..len]).unwrap();
+        if let PacketPayload::GamePacket(data) = packet.payload {
+            return (packet.client_id, packet.destination_id, data);
+        }
+    }
+}
+
+#[test]
+fn a_host_can_join_its_own_session_from_the_same_address() {
+    let cluster = LocalCluster::new(39).unwrap();
+    let socket = cluster.network().bind("0.0.0.0:0").unwrap();
+    register_raw_host(&cluster, &socket, 139);
+
+    // The relay forwards the request to the very address that sent it
+    send_join_request(&cluster, &socket, 139, "local", PROTOCOL_VERSION);
+    accept_raw_join(&cluster, &socket, 139, 2);
+    let (_, client_id) = confirm_join(&cluster, &socket);
+    assert_eq!(client_id, 2);
+
+    send_game_packet(&cluster, &socket, 2, 1, b"to host");
+    assert_eq!(next_game_packet(&socket), (2, 1, b"to host".to_vec()));
+    send_game_packet(&cluster, &socket, 1, 2, b"to client");
+    assert_eq!(next_game_packet(&socket), (1, 2, b"to client".to_vec()));
+}
+
+#[test]
+fn one_address_can_host_a_session_and_play_in_another() {
+    let mut cluster = LocalCluster::new(40).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.host(|host| host.on_game_packet(move |_, from, data| sink.lock().unwrap().push((from, data.to_vec()))));
+
+    let socket = cluster.network().bind("0.0.0.0:0").unwrap();
+    register_raw_host(&cluster, &socket, 140);
+    let guest = request_join_session(&cluster, 140, "guest");
+    accept_raw_join(&cluster, &socket, 140, 2);
+    confirm_join(&cluster, &guest);
+    // The relay tells the host its guest is reachable
+    next_of_type(&socket, PacketType::ConnectAccept);
+
+    // The host of session 140 joins session 40 as its client 2 too
+    send_join_request(&cluster, &socket, 40, "player", PROTOCOL_VERSION);
+    let (_, client_id) = confirm_join(&cluster, &socket);
+    assert_eq!(client_id, 2);
+
+    send_game_packet(&cluster, &socket, 2, 1, b"as a player");
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*received.lock().unwrap(), [(2, b"as a player".to_vec())]);
+
+    send_game_packet(&cluster, &socket, 1, 2, b"as a host");
+    assert_eq!(next_game_packet(&guest), (1, 2, b"as a host".to_vec()));
+}
+
+/// Ask to join `session_id` from a fresh transport
+fn request_join_session(cluster: &LocalCluster, session_id: u32, name: &str) -> MemoryTransport {
+    let socket = cluster.network().bind("0.0.0.0:0").unwrap();
+    socket.set_read_timeout(Some(TIMEOUT)).unwrap();
+    send_join_request(cluster, &socket, session_id, name, PROTOCOL_VERSION);
+    socket
+}
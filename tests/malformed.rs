@@ -0,0 +1,265 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use project_neon::NeonClient;
+use project_neon::client::ConnectionState;
+use project_neon::host::NeonHost;
+use project_neon::protocol::{self, DisconnectNotice, DisconnectReason, NeonPacket, PacketPayload, PacketType};
+use project_neon::testing::{LocalCluster, MemoryNetwork, MemoryTransport, Transport};
+
+mod common;
+use common::{join, start_relay};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const GARBAGE: [&[u8]; 3] = [&[0xFF; 4], b"not a neon packet", &[]];
+
+#[test]
+fn clients_skip_malformed_datagrams_and_keep_receiving() {
+    let cluster = LocalCluster::new(170).unwrap();
+    let transport = cluster.network().bind("127.0.0.1:0").unwrap();
+    let client_addr = transport.local_addr().unwrap();
+    let mut client = NeonClient::with_transport("alice".to_string(), transport).unwrap();
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let sink = dropped.clone();
+    client.on_malformed_packet(move |from, _| sink.lock().unwrap().push(from));
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    client.on_game_packet(move |_, _, data| sink.lock().unwrap().push(data.to_vec()));
+    client.connect(170, cluster.relay_addr()).unwrap();
+    let client_id = client.client_id().unwrap();
+
+    let attacker = cluster.network().bind("127.0.0.1:0").unwrap();
+    for garbage in GARBAGE {
+        attacker.send_to(garbage, client_addr).unwrap();
+    }
+    cluster.host(|host| host.send_game_packet(client_id, 0x10, b"still here")).unwrap();
+
+    let deadline = Instant::now() + TIMEOUT;
+    while received.lock().unwrap().is_empty() && Instant::now() < deadline {
+        let seen = cluster.network().delivered();
+        client.process_packets().unwrap();
+        cluster.network().wait_for_delivery(seen, Duration::from_millis(10));
+    }
+    assert_eq!(*received.lock().unwrap(), [b"still here".to_vec()]);
+    assert_eq!(client.malformed_packet_count(), 3);
+    assert_eq!(*dropped.lock().unwrap(), [attacker.local_addr().unwrap(); 3]);
+    assert_eq!(client.connection_state(), ConnectionState::Connected);
+}
+
+#[test]
+fn hosts_skip_malformed_datagrams_and_keep_accepting() {
+    let cluster = LocalCluster::new(171).unwrap();
+    let transport = cluster.network().bind("127.0.0.1:0").unwrap();
+    let host_addr = transport.local_addr().unwrap();
+    let mut host = NeonHost::with_transport(172, cluster.relay_addr(), transport).unwrap();
+    host.register().unwrap();
+    let dropped = Arc::new(Mutex::new(0));
+    let sink = dropped.clone();
+    host.on_malformed_packet(move |_, _| *sink.lock().unwrap() += 1);
+
+    let attacker = cluster.network().bind("127.0.0.1:0").unwrap();
+    for garbage in GARBAGE {
+        attacker.send_to(garbage, host_addr).unwrap();
+    }
+    host.process_packets().unwrap();
+    assert_eq!(host.malformed_packet_count(), 3);
+    assert_eq!(*dropped.lock().unwrap(), 3);
+
+    let mut client = NeonClient::with_transport("bob".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    client.begin_connect(172, cluster.relay_addr()).unwrap();
+    let deadline = Instant::now() + TIMEOUT;
+    while client.connection_state() != ConnectionState::Connected && Instant::now() < deadline {
+        let seen = cluster.network().delivered();
+        host.process_packets().unwrap();
+        client.process_packets().unwrap();
+        cluster.network().wait_for_delivery(seen, Duration::from_millis(10));
+    }
+    assert_eq!(client.connection_state(), ConnectionState::Connected);
+    assert_eq!(host.client_count(), 1);
+}
+
+type Received = Arc<Mutex<Vec<(u8, u16, Vec<u8>)>>>;
+
+fn game_packet(packet_type: u8, sequence: u16, client_id: u16, destination_id: u16, data: &[u8]) -> Vec<u8> {
+    protocol::encode(&NeonPacket { packet_type, sequence, client_id, destination_id, payload: PacketPayload::GamePacket(data.to_vec()) })
+}
+
+fn batch(client_id: u16, destination_id: u16, packets: Vec<NeonPacket>) -> Vec<u8> {
+    protocol::encode(&NeonPacket { packet_type: PacketType::Batch as u8, sequence: 2000, client_id, destination_id, payload: PacketPayload::Batch(packets) })
+}
+
+fn relay_notice(client_id: u16, destination_id: u16, departed: u16) -> NeonPacket {
+    NeonPacket {
+        packet_type: PacketType::DisconnectNotice as u8,
+        sequence: 2001,
+        client_id,
+        destination_id,
+        payload: PacketPayload::DisconnectNotice(DisconnectNotice { client_id: departed, reason: DisconnectReason::HostClosed }),
+    }
+}
+
+#[test]
+fn batches_cannot_carry_packets_from_another_sender() {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let transport = network.bind("127.0.0.1:0").unwrap();
+    let host_addr = transport.local_addr().unwrap();
+    let mut host = NeonHost::builder(176, relay_addr).ping_interval(None).build_with_transport(transport).unwrap();
+    host.register().unwrap();
+
+    let transport = network.bind("127.0.0.1:0").unwrap();
+    let client_addr = transport.local_addr().unwrap();
+    let mut client = NeonClient::builder("alice").auto_ping(false).build_with_transport(transport).unwrap();
+    let announced = Arc::new(Mutex::new(Vec::new()));
+    let sink = announced.clone();
+    client.on_announcement(move |text| sink.lock().unwrap().push(text));
+    let received = Received::default();
+    let sink = received.clone();
+    client.on_game_packet(move |packet_type, from, data| sink.lock().unwrap().push((packet_type, from, data.to_vec())));
+    client.begin_connect(176, relay_addr).unwrap();
+    join(&network, &mut host, &mut client);
+    let client_id = client.client_id().unwrap();
+    announced.lock().unwrap().clear();
+    let attacker = network.bind("127.0.0.1:0").unwrap();
+
+    // A relay notice that the client left, inside a batch the client sent
+    let from_relay = relay_notice(0, 1, client_id);
+    attacker.send_to(&batch(client_id, 1, vec![from_relay]), host_addr).unwrap();
+    host.process_packets().unwrap();
+    assert_eq!(host.malformed_packet_count(), 1);
+    assert_eq!(host.client_count(), 1);
+
+    // Another client's batch carrying a host announcement, one carrying the relay closing the
+    // session, and a host batch carrying a core packet
+    let from_host = game_packet(protocol::ANNOUNCEMENT, 2002, 1, client_id, b"forged");
+    let from_host = NeonPacket::from_bytes(&from_host).unwrap();
+    attacker.send_to(&batch(client_id + 1, client_id, vec![from_host]), client_addr).unwrap();
+    attacker.send_to(&batch(client_id + 1, client_id, vec![relay_notice(0, client_id, 1)]), client_addr).unwrap();
+    attacker.send_to(&batch(1, client_id, vec![relay_notice(1, client_id, 1)]), client_addr).unwrap();
+    attacker.send_to(&game_packet(0x10, 2003, 1, client_id, b"after"), client_addr).unwrap();
+    client.process_packets().unwrap();
+
+    assert_eq!(client.malformed_packet_count(), 3);
+    assert!(announced.lock().unwrap().is_empty());
+    assert_eq!(*received.lock().unwrap(), [(0x10, 1, b"after".to_vec())]);
+    assert_eq!(client.connection_state(), ConnectionState::Connected);
+}
+
+/// Send both ends of a session a reserved packet type with a one-byte payload, then a game packet
+/// that has to arrive in the same call. Returns how many packets the host and the client dropped.
+fn send_truncated(packet_type: u8) -> (u64, u64) {
+    send_truncated_with(packet_type, |_, _| {})
+}
+
+fn send_truncated_with(
+    packet_type: u8,
+    configure: impl FnOnce(&mut NeonHost<MemoryTransport>, &mut NeonClient<MemoryTransport>),
+) -> (u64, u64) {
+    let network = MemoryNetwork::new();
+    let relay_addr = start_relay(&network);
+    let transport = network.bind("127.0.0.1:0").unwrap();
+    let host_addr = transport.local_addr().unwrap();
+    let mut host = NeonHost::builder(175, relay_addr).ping_interval(None).build_with_transport(transport).unwrap();
+    let host_received = Received::default();
+    let sink = host_received.clone();
+    host.on_game_packet(move |packet_type, from, data| sink.lock().unwrap().push((packet_type, from, data.to_vec())));
+    host.register().unwrap();
+
+    let transport = network.bind("127.0.0.1:0").unwrap();
+    let client_addr = transport.local_addr().unwrap();
+    let mut client = NeonClient::builder("alice").auto_ping(false).build_with_transport(transport).unwrap();
+    let client_received = Received::default();
+    let sink = client_received.clone();
+    client.on_game_packet(move |packet_type, from, data| sink.lock().unwrap().push((packet_type, from, data.to_vec())));
+    configure(&mut host, &mut client);
+    client.begin_connect(175, relay_addr).unwrap();
+    join(&network, &mut host, &mut client);
+    let client_id = client.client_id().unwrap();
+
+    let attacker = network.bind("127.0.0.1:0").unwrap();
+    attacker.send_to(&game_packet(packet_type, 1000, client_id, 1, &[0xFF]), host_addr).unwrap();
+    attacker.send_to(&game_packet(0x10, 1001, client_id, 1, b"after"), host_addr).unwrap();
+    host.process_packets().unwrap();
+    attacker.send_to(&game_packet(packet_type, 1000, 1, client_id, &[0xFF]), client_addr).unwrap();
+    attacker.send_to(&game_packet(0x10, 1001, 1, client_id, b"after"), client_addr).unwrap();
+    client.process_packets().unwrap();
+
+    assert_eq!(*host_received.lock().unwrap(), [(0x10, client_id, b"after".to_vec())]);
+    assert_eq!(*client_received.lock().unwrap(), [(0x10, 1, b"after".to_vec())]);
+    (host.malformed_packet_count(), client.malformed_packet_count())
+}
+
+#[test]
+fn truncated_replication_snapshots_are_dropped() {
+    assert_eq!(send_truncated(protocol::REPLICATION_SNAPSHOT), (0, 1));
+}
+
+#[test]
+fn truncated_replication_acks_are_dropped() {
+    assert_eq!(send_truncated(protocol::REPLICATION_ACK), (1, 0));
+}
+
+#[test]
+fn truncated_rpc_requests_are_dropped() {
+    assert_eq!(send_truncated(protocol::RPC_REQUEST), (1, 1));
+}
+
+#[test]
+fn truncated_rpc_responses_are_dropped() {
+    assert_eq!(send_truncated(protocol::RPC_RESPONSE), (1, 1));
+}
+
+#[test]
+fn truncated_voice_frames_are_dropped() {
+    assert_eq!(send_truncated(protocol::VOICE_FRAME), (1, 1));
+}
+
+#[test]
+fn stray_join_code_lookups_are_ignored() {
+    assert_eq!(send_truncated(protocol::JOIN_CODE_LOOKUP), (0, 0));
+}
+
+#[test]
+fn stray_join_code_answers_are_ignored() {
+    assert_eq!(send_truncated(protocol::JOIN_CODE_ANSWER), (0, 0));
+}
+
+#[test]
+fn short_capability_updates_are_handled() {
+    assert_eq!(send_truncated(protocol::CAPABILITIES), (0, 0));
+}
+
+#[test]
+fn short_name_changes_are_handled() {
+    assert_eq!(send_truncated(protocol::NAME_CHANGE), (0, 0));
+}
+
+#[test]
+fn short_announcements_are_handled() {
+    assert_eq!(send_truncated(protocol::ANNOUNCEMENT), (0, 0));
+}
+
+#[test]
+fn truncated_config_values_are_dropped() {
+    assert_eq!(send_truncated(protocol::CONFIG_VALUE), (0, 1));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn undecodable_messages_are_dropped() {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Score {
+        points: u32,
+    }
+
+    impl project_neon::message::Message for Score {
+        const PACKET_TYPE: u8 = 0x20;
+    }
+
+    let dropped = send_truncated_with(0x20, |host, client| {
+        host.on_message(|_, _: Score| {}).unwrap();
+        client.on_message(|_, _: Score| {}).unwrap();
+    });
+    assert_eq!(dropped, (1, 1));
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "serde")]
+
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::message::{Message, decode_message, encode_message};
+use project_neon::testing::LocalCluster;
+use serde::{Deserialize, Serialize};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PlayerMove {
+    x: f32,
+    y: f32,
+    running: bool,
+}
+
+impl Message for PlayerMove {
+    const PACKET_TYPE: u8 = 0x10;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ChatLine {
+    text: String,
+}
+
+impl Message for ChatLine {
+    const PACKET_TYPE: u8 = 0x11;
+}
+
+#[derive(Serialize, Deserialize)]
+struct Reserved;
+
+impl Message for Reserved {
+    const PACKET_TYPE: u8 = 0x0B;
+}
+
+#[test]
+fn messages_round_trip() {
+    let message = PlayerMove { x: 1.5, y: -2.0, running: true };
+    let bytes = encode_message(&message).unwrap();
+    assert_eq!(decode_message::<PlayerMove>(&bytes).unwrap(), message);
+    assert_eq!(decode_message::<PlayerMove>(&bytes[..3]).unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn client_message_reaches_host_callback() {
+    let mut cluster = LocalCluster::new(21).unwrap();
+    let moves = Arc::new(Mutex::new(Vec::new()));
+    let raw = Arc::new(Mutex::new(Vec::new()));
+    {
+        let moves = moves.clone();
+        let raw = raw.clone();
+        cluster.host(|host| {
+            host.on_message(move |from, message: PlayerMove| moves.lock().unwrap().push((from, message)))?;
+            host.on_game_packet(move |packet_type, _, _| raw.lock().unwrap().push(packet_type));
+            Ok::<_, std::io::Error>(())
+        }).unwrap();
+    }
+    cluster.add_client("alice").unwrap();
+
+    let message = PlayerMove { x: 3.0, y: 4.0, running: false };
+    cluster.client(0).send_message(&message).unwrap();
+    cluster.client(0).send_message(&ChatLine { text: "hi".to_string() }).unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| !raw.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*moves.lock().unwrap(), vec![(2, message)]);
+    // Types without a message callback still reach on_game_packet
+    assert_eq!(*raw.lock().unwrap(), vec![ChatLine::PACKET_TYPE]);
+}
+
+#[test]
+fn host_message_reaches_client_callback() {
+    let mut cluster = LocalCluster::with_clients(22, &["alice"]).unwrap();
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    {
+        let lines = lines.clone();
+        cluster.client(0).on_message(move |from, message: ChatLine| {
+            lines.lock().unwrap().push((from, message.text));
+        }).unwrap();
+    }
+
+    cluster.host(|host| host.send_message(2, &ChatLine { text: "welcome".to_string() })).unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| !lines.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*lines.lock().unwrap(), vec![(1, "welcome".to_string())]);
+}
+
+#[test]
+fn core_packet_types_are_rejected() {
+    let mut cluster = LocalCluster::with_clients(23, &["alice"]).unwrap();
+
+    let err = cluster.client(0).on_message(|_, _: Reserved| {}).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = cluster.client(0).send_message(&Reserved).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,78 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::protocol::{HEADER_SIZE, MAX_DATAGRAM_SIZE, check_packet_size};
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bytes that don't compress
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_F491u32;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+fn cluster_with_max(session_id: u32, max_packet_size: u16) -> LocalCluster {
+    let mut cluster = LocalCluster::new(session_id).unwrap();
+    cluster.host(|host| host.set_max_packet_size(max_packet_size)).unwrap();
+    cluster.add_client("alice").unwrap();
+    cluster.client(0).set_compression_threshold(None);
+    cluster
+}
+
+#[test]
+fn size_check_counts_the_header_and_compression() {
+    assert!(check_packet_size(&[0; 1024 - HEADER_SIZE], 1024, None).is_ok());
+    let err = check_packet_size(&[0; 1024 - HEADER_SIZE + 1], 1024, None).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(check_packet_size(&noise(2000), 1024, Some(64)).is_err());
+}
+
+#[test]
+fn oversized_game_packets_are_rejected() {
+    let mut cluster = LocalCluster::with_clients(111, &["alice"]).unwrap();
+    let client = cluster.client(0);
+    client.set_compression_threshold(None);
+    assert_eq!(client.packet_size_limit(1), 1024);
+
+    let err = client.send_game_packet(0x10, &noise(1100)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(client.send_game_packet(0x10, &noise(1000)).is_ok());
+
+    let err = cluster.host(|host| host.send_game_packet(2, 0x10, &noise(1100))).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(cluster.host(|host| host.set_max_packet_size(1500)).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn probes_raise_the_limit_to_the_advertised_size() {
+    let mut cluster = cluster_with_max(112, MAX_DATAGRAM_SIZE as u16);
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).packet_size_limit(1) == MAX_DATAGRAM_SIZE).unwrap());
+    assert_eq!(cluster.host(|host| host.packet_size_limit(2)), Some(MAX_DATAGRAM_SIZE));
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.host(|host| host.on_game_packet(move |_, _, data| sink.lock().unwrap().push(data.len())));
+    cluster.client(0).send_game_packet(0x10, &noise(1400)).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*received.lock().unwrap(), vec![1400]);
+}
+
+#[test]
+fn probes_stop_at_the_path_mtu() {
+    let mut cluster = LocalCluster::new(113).unwrap();
+    cluster.network().set_max_datagram_size(Some(1300));
+    cluster.host(|host| host.set_max_packet_size(MAX_DATAGRAM_SIZE as u16)).unwrap();
+    cluster.add_client("alice").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).packet_size_limit(1) == 1280).unwrap());
+    // The larger probes were dropped on the way, so nothing beyond 1280 is ever confirmed
+    assert!(!cluster.run_until(Duration::from_millis(200), |c| c.client(0).packet_size_limit(1) > 1280).unwrap());
+    assert_eq!(cluster.client(0).packet_size_limit(3), 1024, "other peers stay at the session default");
+}
@@ -0,0 +1,76 @@
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use project_neon::client::NeonClient;
+use project_neon::host::NeonHostMultiplexer;
+use project_neon::protocol::DisconnectReason;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn one_loop_hosts_several_sessions() {
+    let cluster = LocalCluster::new(220).unwrap();
+    let mut multiplexer = NeonHostMultiplexer::with_relay(cluster.relay_addr());
+    let (connect_tx, connect_rx) = mpsc::channel();
+    multiplexer.on_client_connect(move |session, client_id, name| connect_tx.send((session, client_id, name)).unwrap());
+    let (packet_tx, packet_rx) = mpsc::channel();
+    multiplexer.on_game_packet(move |session, packet_type, from, data| {
+        packet_tx.send((session, packet_type, from, data.to_vec())).unwrap()
+    });
+
+    let first = multiplexer.add_session_with_transport(221, cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    let second = multiplexer.add_session_with_transport(222, cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    let err = multiplexer.add_session_with_transport(221, cluster.network().bind("127.0.0.1:0").unwrap()).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(multiplexer.sessions(), vec![first, second]);
+    assert_eq!(second.session_id(), 222);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let host_thread = {
+        let running = running.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                multiplexer.process_packets().unwrap();
+                thread::sleep(Duration::from_millis(5));
+            }
+            multiplexer
+        })
+    };
+
+    let mut alice = NeonClient::with_transport("alice".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    alice.connect(221, cluster.relay_addr()).unwrap();
+    let mut bob = NeonClient::with_transport("bob".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    bob.connect(222, cluster.relay_addr()).unwrap();
+
+    let mut joins = vec![connect_rx.recv_timeout(TIMEOUT).unwrap(), connect_rx.recv_timeout(TIMEOUT).unwrap()];
+    joins.sort();
+    let (alice_id, bob_id) = (alice.client_id().unwrap(), bob.client_id().unwrap());
+    assert_eq!(joins, vec![(first, alice_id, "alice".to_string()), (second, bob_id, "bob".to_string())]);
+
+    bob.send_game_packet(0x10, b"from bob").unwrap();
+    assert_eq!(packet_rx.recv_timeout(TIMEOUT).unwrap(), (second, 0x10, bob_id, b"from bob".to_vec()));
+    alice.send_game_packet(0x11, b"from alice").unwrap();
+    assert_eq!(packet_rx.recv_timeout(TIMEOUT).unwrap(), (first, 0x11, alice_id, b"from alice".to_vec()));
+
+    running.store(false, Ordering::Relaxed);
+    let mut multiplexer = host_thread.join().unwrap();
+
+    let (closed_tx, closed_rx) = mpsc::channel();
+    bob.on_session_closed(move |reason| closed_tx.send(reason).unwrap());
+    assert!(multiplexer.remove_session(second).unwrap().is_some());
+    assert!(multiplexer.remove_session(second).unwrap().is_none());
+    assert_eq!(multiplexer.session_count(), 1);
+    for _ in 0..500 {
+        bob.process_packets().unwrap();
+        if let Ok(reason) = closed_rx.try_recv() {
+            assert_eq!(reason, DisconnectReason::HostClosed);
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("bob was never told session {} closed", second.session_id());
+}
@@ -0,0 +1,103 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::name;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Log<T> = Arc<Mutex<Vec<T>>>;
+
+#[test]
+fn clients_rename_to_names_not_in_use() {
+    let mut cluster = LocalCluster::with_clients(97, &["alice", "bob"]).unwrap();
+    let renames: Log<(u16, String, String)> = Log::default();
+    let sink = renames.clone();
+    cluster.host(|host| host.on_client_renamed(move |client_id, old, new| sink.lock().unwrap().push((client_id, old, new))));
+
+    let denied: Log<String> = Log::default();
+    let sink = denied.clone();
+    cluster.client(1).on_name_change_denied(move |reason| sink.lock().unwrap().push(reason));
+    let seen: Log<(u16, String, String)> = Log::default();
+    let sink = seen.clone();
+    cluster.client(0).on_peer_renamed(move |client_id, old, new| sink.lock().unwrap().push((client_id, old, new)));
+    assert!(cluster.run_until(TIMEOUT, |cluster| !cluster.client(0).peers().is_empty()).unwrap());
+
+    cluster.client(1).request_name_change("alice").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !denied.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*denied.lock().unwrap(), ["Name 'alice' is already in use"]);
+    assert_eq!(cluster.client(1).name(), "bob");
+
+    assert_eq!(cluster.client(1).request_name_change("").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(cluster.client(1).request_name_change(&"b".repeat(256)).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    cluster.client(1).request_name_change("robert").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |cluster| cluster.client(1).name() == "robert").unwrap());
+    assert!(cluster.run_until(TIMEOUT, |_| !seen.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*seen.lock().unwrap(), [(3, "bob".to_string(), "robert".to_string())]);
+    assert_eq!(*renames.lock().unwrap(), [(3, "bob".to_string(), "robert".to_string())]);
+    assert_eq!(cluster.client(0).peers()[0].name, "robert");
+
+    // The relay stamps chat with the new name
+    let chat: Log<String> = Log::default();
+    let sink = chat.clone();
+    cluster.client(0).on_chat_message(move |_, name, _| sink.lock().unwrap().push(name));
+    cluster.client(1).send_chat("hi", 0).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !chat.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*chat.lock().unwrap(), ["robert"]);
+
+    cluster.shutdown().unwrap();
+}
+
+#[test]
+fn hosts_can_number_duplicate_names() {
+    let mut cluster = LocalCluster::new(98).unwrap();
+    cluster.host(|host| host.set_auto_suffix_names(true));
+    let first = cluster.add_client("alice").unwrap();
+    let second = cluster.add_client("alice").unwrap();
+    let third = cluster.add_client("alice").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |cluster| cluster.client(third).name() == "alice (3)").unwrap());
+    assert_eq!(cluster.client(first).name(), "alice");
+    assert_eq!(cluster.client(second).name(), "alice (2)");
+
+    let chat: Log<String> = Log::default();
+    let sink = chat.clone();
+    cluster.client(first).on_chat_message(move |_, name, _| sink.lock().unwrap().push(name));
+    cluster.client(second).send_chat("hi", 0).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !chat.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*chat.lock().unwrap(), ["alice (2)"]);
+
+    // Renaming to a taken name numbers it too
+    cluster.client(first).request_name_change("alice (2)").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |cluster| cluster.client(first).name() != "alice").unwrap());
+    assert_eq!(cluster.client(first).name(), "alice (2) (2)");
+
+    cluster.shutdown().unwrap();
+}
+
+#[test]
+fn names_are_stripped_and_limited() {
+    assert_eq!(name::normalize("  al\u{202E}ice\n").unwrap(), "alice");
+    assert_eq!(name::normalize("Zoë 🦀").unwrap(), "Zoë 🦀");
+    // A family emoji or an accented letter is one character, however many code points it takes
+    assert!(name::normalize(&"👨\u{200D}👩\u{200D}👧".repeat(3)).is_ok());
+    assert!(name::normalize(&format!("{}{}", "e\u{0301}".repeat(12), "x".repeat(20))).is_ok());
+
+    for bad in ["", " \u{0007} ", &"ö".repeat(33), &"x".repeat(33), "Z\u{0300}\u{0301}\u{0302}\u{0303}\u{0304}algo"] {
+        assert_eq!(name::normalize(bad).unwrap_err().kind(), ErrorKind::InvalidInput, "{:?}", bad);
+    }
+
+    let mut cluster = LocalCluster::new(99).unwrap();
+    let alice = cluster.add_client("\u{2066}alice\r\n").unwrap();
+    assert_eq!(cluster.client(alice).name(), "alice");
+    let bob = cluster.add_client("bob").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |cluster| !cluster.client(bob).peers().is_empty()).unwrap());
+    assert_eq!(cluster.client(bob).peers()[0].name, "alice");
+
+    assert_eq!(cluster.client(bob).request_name_change("\u{0008}").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(cluster.add_client(&"a".repeat(100)).is_err());
+
+    cluster.shutdown().unwrap();
+}
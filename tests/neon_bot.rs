@@ -0,0 +1,70 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+/// The relay binary, killed when dropped
+struct Relay(Child);
+
+impl Drop for Relay {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Start a relay on a free port and return its address
+fn start_relay() -> (Relay, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_relay"))
+        .args(["--bind", "127.0.0.1:0", "--log-format", "json"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let listening = lines.next().unwrap().unwrap();
+    // Keep reading so the relay never writes to a closed pipe
+    thread::spawn(move || lines.for_each(drop));
+    let start = listening.find("\"addr\":\"").unwrap() + 8;
+    let addr = listening[start..start + listening[start..].find('"').unwrap()].to_string();
+    (Relay(child), addr)
+}
+
+fn bot(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_neon-bot")).args(args).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+    stdout
+}
+
+#[test]
+fn mesh_traffic_is_measured_end_to_end() {
+    let (_relay, addr) = start_relay();
+    let output = bot(&["--relay", &addr, "--host", "--clients", "3", "--rate", "50", "--duration", "1", "--pattern", "mesh"]);
+
+    assert!(output.contains("Connected 3/3 clients"), "{}", output);
+    let received = output.lines().find(|line| line.starts_with("Received")).unwrap();
+    assert!(received.contains("packets") && received.contains("loss"), "{}", output);
+    assert!(output.lines().any(|line| line.starts_with("Latency  p50")), "{}", output);
+    assert!(output.lines().any(|line| line.starts_with("RTT      p50")), "{}", output);
+}
+
+#[test]
+fn burst_traffic_to_a_hosted_session() {
+    let (_relay, addr) = start_relay();
+    let output = bot(&["--relay", &addr, "--host", "--clients", "2", "--rate", "20", "--duration", "1", "--pattern", "burst"]);
+
+    let sent = output.lines().find(|line| line.starts_with("Sent")).unwrap();
+    let count: u64 = sent.split_whitespace().nth(1).unwrap().parse().unwrap();
+    // One burst of 20 per bot at the start of each second
+    assert!(count >= 40, "{}", output);
+}
+
+#[test]
+fn a_session_is_required_without_a_host() {
+    let status = Command::new(env!("CARGO_BIN_EXE_neon-bot"))
+        .args(["--clients", "2"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(2));
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Lines};
+use std::net::UdpSocket;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::protocol::{self, NeonPacket, PacketPayload, Ping};
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A sniffer bound to a socket, killed when dropped
+struct Sniffer {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl Drop for Sniffer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Sniffer {
+    fn next_line(&mut self) -> String {
+        self.lines.next().expect("sniffer exited").unwrap()
+    }
+}
+
+fn ping(sequence: u16) -> Vec<u8> {
+    protocol::encode(&NeonPacket {
+        packet_type: 0x0B,
+        sequence,
+        client_id: 2,
+        destination_id: 1,
+        payload: PacketPayload::Ping(Ping { timestamp: 77 }),
+    })
+}
+
+#[test]
+fn live_traffic_is_decoded_with_sequence_gaps() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_neon-sniff"))
+        .args(["--bind", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut sniffer = Sniffer { child, lines };
+    let listening = sniffer.next_line();
+    let addr = listening.strip_prefix("Listening on ").unwrap();
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for sequence in [1, 2, 5, 2] {
+        socket.send_to(&ping(sequence), addr).unwrap();
+    }
+    socket.send_to(b"junk", addr).unwrap();
+
+    let first = sniffer.next_line();
+    assert!(first.contains("first") && first.contains("Ping(0x0B) seq=1 2->1"), "{}", first);
+    assert!(sniffer.next_line().contains("timestamp: 77"));
+    assert!(!sniffer.next_line().contains("gap"));
+    sniffer.next_line();
+    assert!(sniffer.next_line().contains("seq=5 2->1  [gap: 2 missing]"));
+    sniffer.next_line();
+    assert!(sniffer.next_line().contains("seq=2 2->1  [Duplicate]"));
+    sniffer.next_line();
+    assert!(sniffer.next_line().contains("undecodable (4 bytes)"));
+}
+
+#[test]
+fn captures_show_registered_packet_names() {
+    let path = std::env::temp_dir().join(format!("neon-sniff-{}.pcap", std::process::id()));
+    let mut cluster = LocalCluster::with_relay(192, |relay| relay.set_capture(&path, &[])).unwrap();
+    let received = Arc::new(Mutex::new(false));
+    let sink = received.clone();
+    cluster.host(|host| {
+        host.register_packet_type(0x10, "PlayerMove", "Position update").unwrap();
+        host.on_game_packet(move |_, _, _| *sink.lock().unwrap() = true);
+    });
+    let registry = Arc::new(Mutex::new(false));
+    let registry_sink = registry.clone();
+    let alice = cluster.add_client("alice").unwrap();
+    cluster.client(alice).on_packet_type_registry(move |_| *registry_sink.lock().unwrap() = true);
+    assert!(cluster.run_until(TIMEOUT, |_| *registry.lock().unwrap()).unwrap());
+    assert!(cluster
+        .run_until(TIMEOUT, |cluster| {
+            cluster.client(alice).send_game_packet(0x10, &[0xAB, 0xCD]).unwrap();
+            *received.lock().unwrap()
+        })
+        .unwrap());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_neon-sniff")).args(["--read"]).arg(&path).output().unwrap();
+    assert!(output.status.success());
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert!(output.contains("0x10 PlayerMove: Position update"), "{}", output);
+    assert!(output.contains("PlayerMove(0x10) seq="), "{}", output);
+    assert!(output.contains("2 bytes: ab cd"), "{}", output);
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use project_neon::compression::DEFAULT_COMPRESSION_THRESHOLD;
+use project_neon::protocol::{self, FLAG_COMPRESSED, PacketPayload};
+use project_neon::relay::read_capture;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn capture_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("neon-{}-{}.pcap", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// Send a game packet from the client until the host has seen it
+fn deliver(cluster: &mut LocalCluster, data: &'static [u8]) {
+    let received = Arc::new(Mutex::new(false));
+    let sink = received.clone();
+    cluster.host(|host| host.on_game_packet(move |_, _, got| *sink.lock().unwrap() |= got == data));
+    assert!(cluster
+        .run_until(TIMEOUT, |cluster| {
+            cluster.client(0).send_game_packet(0x10, data).unwrap();
+            *received.lock().unwrap()
+        })
+        .unwrap());
+}
+
+#[test]
+fn forwarded_packets_are_captured_with_their_addresses() {
+    let path = capture_path("forwarded");
+    let mut cluster = LocalCluster::with_relay(190, |relay| relay.set_capture(&path, &[190])).unwrap();
+    cluster.add_client("alice").unwrap();
+    deliver(&mut cluster, b"captured");
+
+    let packets = read_capture(&path).unwrap();
+    let packet = packets
+        .iter()
+        .find(|packet| packet.header().is_ok_and(|header| header.packet_type == 0x10))
+        .expect("game packet wasn't captured");
+    let header = packet.header().unwrap();
+    assert_eq!((header.client_id, header.destination_id), (2, 1));
+    // Both ends are the real peers, not the relay
+    assert_eq!(packet.from.ip().to_string(), "127.0.0.1");
+    assert_eq!(packet.to.ip().to_string(), "127.0.0.1");
+    assert!(packet.from != packet.to && packet.to != cluster.relay_addr());
+    assert!(packet.timestamp <= SystemTime::now());
+    let PacketPayload::GamePacket(data) = protocol::decode(&packet.data).unwrap().payload else {
+        panic!("expected a game payload");
+    };
+    assert_eq!(data, b"captured");
+
+    // The first record's IPv4 header checksum verifies, so packet tools accept it
+    let bytes = fs::read(&path).unwrap();
+    let ip_header = &bytes[24 + 16..24 + 16 + 20];
+    let sum: u32 = ip_header.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as u32).sum();
+    assert_eq!((sum & 0xFFFF) + (sum >> 16), 0xFFFF);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn game_packets_are_only_compressed_once_turned_on() {
+    let path = capture_path("compression");
+    let mut cluster = LocalCluster::with_relay(192, |relay| relay.set_capture(&path, &[192])).unwrap();
+    cluster.add_client("alice").unwrap();
+    deliver(&mut cluster, &[0; 600]);
+    cluster.client(0).set_compression_threshold(Some(DEFAULT_COMPRESSION_THRESHOLD));
+    deliver(&mut cluster, &[1; 600]);
+
+    // (fill byte, compressed flag) of each game packet
+    let sent: Vec<(u8, u8)> = read_capture(&path)
+        .unwrap()
+        .iter()
+        .filter(|packet| packet.header().is_ok_and(|header| header.packet_type == 0x10))
+        .map(|packet| {
+            let PacketPayload::GamePacket(data) = protocol::decode(&packet.data).unwrap().payload else {
+                panic!("expected a game payload");
+            };
+            (data[0], packet.header().unwrap().flags & FLAG_COMPRESSED)
+        })
+        .collect();
+    let compressed = if cfg!(feature = "lz4") { FLAG_COMPRESSED } else { 0 };
+    assert!(sent.iter().any(|&(fill, _)| fill == 1));
+    assert!(sent.iter().all(|&(fill, flags)| flags == if fill == 0 { 0 } else { compressed }));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn other_sessions_are_left_out() {
+    let path = capture_path("filtered");
+    let mut cluster = LocalCluster::with_relay(191, |relay| relay.set_capture(&path, &[999])).unwrap();
+    cluster.add_client("alice").unwrap();
+    deliver(&mut cluster, b"not captured");
+
+    assert!(read_capture(&path).unwrap().is_empty());
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use project_neon::host::HostConfig;
+use project_neon::testing::LocalCluster;
+use project_neon::timesync::RttEstimator;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn hosts_measure_round_trips_to_quiet_clients() {
+    let mut cluster = LocalCluster::new(120).unwrap();
+    cluster.host(|host| host.set_ping_interval(Some(Duration::from_millis(50))));
+    cluster.add_client("alice").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |cluster| {
+        cluster.host(|host| host.client_stats(2).is_some_and(|stats| stats.pongs_received >= 3))
+    }).unwrap());
+    let stats = cluster.host(|host| host.client_stats(2)).unwrap();
+    assert!(stats.rtt.is_some_and(|rtt| rtt < Duration::from_secs(1)), "{:?}", stats);
+    assert!(stats.smoothed_rtt.is_some_and(|rtt| rtt < Duration::from_secs(1)), "{:?}", stats);
+    assert!(stats.jitter < Duration::from_secs(1));
+    assert!(stats.pings_sent >= stats.pongs_received);
+    assert!(stats.since_heard < Duration::from_secs(1));
+    assert!(cluster.host(|host| host.client_stats(3)).is_none());
+
+    cluster.shutdown().unwrap();
+}
+
+#[test]
+fn quiet_clients_that_answer_pings_are_kept() {
+    let mut cluster = LocalCluster::new(121).unwrap();
+    cluster.host(|host| {
+        host.set_config(HostConfig { client_timeout: Some(Duration::from_millis(300)), ..HostConfig::default() }).unwrap();
+    });
+    cluster.add_client("alice").unwrap();
+
+    cluster.run_until(Duration::from_secs(1), |_| false).unwrap();
+    assert_eq!(cluster.host(|host| host.client_count()), 1);
+    assert!(cluster.host(|host| host.client_stats(2)).unwrap().pongs_received >= 3);
+
+    cluster.shutdown().unwrap();
+}
+
+#[test]
+fn clients_smooth_round_trips_to_the_host() {
+    let mut cluster = LocalCluster::new(122).unwrap();
+    let alice = cluster.add_client("alice").unwrap();
+    assert_eq!(cluster.client(alice).rtt_stats().smoothed(), None);
+
+    for _ in 0..3 {
+        let before = cluster.client(alice).rtt_stats().samples();
+        cluster.client(alice).send_ping().unwrap();
+        assert!(cluster.run_until(TIMEOUT, |cluster| cluster.client(alice).rtt_stats().samples() > before).unwrap());
+    }
+    let rtt = *cluster.client(alice).rtt_stats();
+    assert!(rtt.samples() >= 3);
+    assert!(rtt.smoothed().is_some_and(|smoothed| smoothed < Duration::from_secs(1)), "{:?}", rtt);
+    assert!(rtt.latest().is_some());
+
+    cluster.shutdown().unwrap();
+}
+
+#[test]
+fn smoothed_round_trips_follow_samples_slowly() {
+    let mut rtt = RttEstimator::new();
+    rtt.add_sample(Duration::from_millis(80));
+    assert_eq!(rtt.smoothed(), Some(Duration::from_millis(80)));
+    assert_eq!(rtt.jitter(), Duration::from_millis(40));
+
+    rtt.add_sample(Duration::from_millis(160));
+    assert_eq!(rtt.latest(), Some(Duration::from_millis(160)));
+    assert_eq!(rtt.smoothed(), Some(Duration::from_millis(90)));
+    assert_eq!(rtt.jitter(), Duration::from_millis(50));
+}
@@ -0,0 +1,334 @@
+use std::io::ErrorKind;
+
+use project_neon::protocol::{
+    self, Ack, Challenge, ConnectAccept, ConnectDeny, ConnectRequest, DisconnectNotice, DisconnectReason, FeatureSet, NeonPacket, PacketPayload, PacketType,
+    PacketTypeEntry, PacketTypeRegistry, PeerInfo, PeerList, PeerRole, Ping, Pong, RelayInfo, SessionConfig, SessionMetadata, SessionRedirect,
+    FLAG_ACKS, FLAG_COMPRESSED, FLAG_ENCRYPTED, FLAG_FRAGMENT, FLAG_RELIABLE, HEADER_SIZE, MAGIC, PROTOCOL_V1, PROTOCOL_V2, PROTOCOL_VERSION,
+    V1_HEADER_SIZE, V2_HEADER_SIZE,
+};
+
+fn packet(packet_type: u8, payload: PacketPayload) -> NeonPacket {
+    NeonPacket {
+        packet_type,
+        sequence: 0xBEEF,
+        client_id: 2,
+        destination_id: 1,
+        payload,
+    }
+}
+
+fn assert_round_trip(packet: NeonPacket) {
+    let bytes = protocol::encode(&packet);
+    assert_eq!(protocol::decode(&bytes).unwrap(), packet);
+}
+
+#[test]
+fn header_layout_matches_spec() {
+    let bytes = protocol::encode(&packet(PacketType::Ping as u8, PacketPayload::Ping(Ping { timestamp: 7 })));
+    assert_eq!(bytes.len(), HEADER_SIZE + 8);
+    assert_eq!(&bytes[..HEADER_SIZE], &[0x45, 0x4E, PROTOCOL_VERSION, 0, 0x0B, 0xEF, 0xBE, 2, 0, 1, 0]);
+
+    let header = protocol::decode_header(&bytes).unwrap();
+    assert_eq!(header.magic, MAGIC);
+    assert_eq!(header.flags, 0);
+    assert_eq!(header.packet_type, PacketType::Ping as u8);
+    assert_eq!(header.sequence, 0xBEEF);
+}
+
+#[test]
+fn flags_ride_in_their_own_byte_and_in_the_version_byte_before_v3() {
+    let original = packet(PacketType::Ping as u8, PacketPayload::Ping(Ping { timestamp: 7 }));
+    let mut header = original.header(PROTOCOL_VERSION);
+    header.flags = FLAG_RELIABLE | FLAG_COMPRESSED;
+    assert_eq!(header.to_bytes()[2..4], [PROTOCOL_VERSION, FLAG_RELIABLE | FLAG_COMPRESSED]);
+    assert_eq!(protocol::decode_header(&header.to_bytes()).unwrap(), header);
+
+    let mut header = original.header(PROTOCOL_V2);
+    header.flags = FLAG_RELIABLE | FLAG_COMPRESSED;
+    let bytes = header.to_bytes();
+    assert_eq!(bytes.len(), V2_HEADER_SIZE);
+    assert_eq!(bytes[2], PROTOCOL_V2 | 0x80 | 0x40);
+    assert_eq!(protocol::decode_header(&bytes).unwrap(), header);
+
+    // Version 1 has no room for the reliable flag
+    let mut header = original.header(PROTOCOL_V1);
+    header.flags = FLAG_RELIABLE;
+    assert_eq!(protocol::decode_header(&header.to_bytes()).unwrap().flags, 0);
+
+    for flag in [FLAG_ENCRYPTED, FLAG_FRAGMENT] {
+        let mut bytes = protocol::encode(&original);
+        bytes[3] = flag;
+        assert_eq!(protocol::decode(&bytes).unwrap_err().kind(), ErrorKind::Unsupported);
+    }
+}
+
+#[test]
+fn v1_headers_still_decode() {
+    let original = packet(PacketType::Ping as u8, PacketPayload::Ping(Ping { timestamp: 7 }));
+    let bytes = protocol::encode_versioned(&original, PROTOCOL_V1, None);
+    assert_eq!(bytes.len(), V1_HEADER_SIZE + 8);
+    assert_eq!(&bytes[..V1_HEADER_SIZE], &[0x45, 0x4E, PROTOCOL_V1, 0x0B, 0xEF, 0xBE, 2, 1]);
+    assert_eq!(protocol::decode(&bytes).unwrap(), original);
+
+    let accept = packet(
+        PacketType::ConnectAccept as u8,
+        PacketPayload::ConnectAccept(ConnectAccept { assigned_client_id: 9, session_id: 42, cookie: 7, nonce: 0, resume_token: 0, game_identifier: 0, join_code: String::new(), metadata: Default::default(), features: Default::default() }),
+    );
+    assert_eq!(protocol::decode(&protocol::encode_versioned(&accept, PROTOCOL_V1, None)).unwrap(), accept);
+
+    // Accepts from peers that predate cookies end after the session ID
+    let mut bytes = protocol::encode_versioned(&accept, PROTOCOL_V1, None);
+    bytes.truncate(bytes.len() - 20);
+    let PacketPayload::ConnectAccept(decoded) = protocol::decode(&bytes).unwrap().payload else { unreachable!() };
+    assert_eq!(decoded.cookie, 0);
+
+    let mut bytes = protocol::encode(&original);
+    bytes[2] = PROTOCOL_VERSION + 1;
+    assert_eq!(protocol::decode(&bytes).unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn v2_carries_client_ids_past_255() {
+    let mut original = packet(
+        PacketType::PeerList as u8,
+        PacketPayload::PeerList(PeerList {
+            peers: vec![
+                PeerInfo { client_id: 300, name: "alice".to_string(), role: PeerRole::Player },
+                PeerInfo { client_id: 65000, name: "bob".to_string(), role: PeerRole::Spectator },
+            ],
+        }),
+    );
+    original.client_id = 1;
+    original.destination_id = 4000;
+    assert_round_trip(original);
+    assert_round_trip(packet(
+        PacketType::ConnectAccept as u8,
+        PacketPayload::ConnectAccept(ConnectAccept { assigned_client_id: 1000, session_id: 42, cookie: 0, nonce: 0, resume_token: 0, game_identifier: 0, join_code: String::new(), metadata: Default::default(), features: Default::default() }),
+    ));
+}
+
+#[test]
+fn connection_payloads_round_trip() {
+    assert_round_trip(packet(
+        PacketType::ConnectRequest as u8,
+        PacketPayload::ConnectRequest(ConnectRequest {
+            client_version: 1,
+            desired_name: "Player \u{1F600}".to_string(),
+            target_session_id: 0xDEADBEEF,
+            game_identifier: 0x12345678,
+            nonce: 77,
+            resume_id: 300,
+            resume_token: u64::MAX,
+            auth_token: b"ticket\0with a NUL".to_vec(),
+            role: PeerRole::Spectator,
+        }),
+    ));
+    assert_round_trip(packet(
+        PacketType::ConnectAccept as u8,
+        PacketPayload::ConnectAccept(ConnectAccept { assigned_client_id: 9, session_id: 42, cookie: 0xDEAD_BEEF_CAFE, nonce: 77, resume_token: 5, game_identifier: 0x4E45_4F4E, join_code: "CRAB-7F2".to_string(), metadata: Default::default(), features: Default::default() }),
+    ));
+    assert_round_trip(packet(
+        PacketType::ConnectAccept as u8,
+        PacketPayload::ConnectAccept(ConnectAccept {
+            assigned_client_id: 1,
+            session_id: 42,
+            cookie: 0,
+            nonce: 0,
+            resume_token: 0,
+            game_identifier: 0,
+            join_code: String::new(),
+            metadata: SessionMetadata {
+                name: "Crab Cove \u{1F980}".to_string(),
+                max_players: 16,
+                properties: vec![("map".to_string(), "dust".to_string()), ("mode".to_string(), String::new())],
+            },
+            features: Default::default(),
+        }),
+    ));
+    // A client's confirmation carries its features after otherwise empty trailing fields
+    assert_round_trip(packet(
+        PacketType::ConnectAccept as u8,
+        PacketPayload::ConnectAccept(ConnectAccept {
+            assigned_client_id: 9,
+            session_id: 42,
+            cookie: 7,
+            nonce: 0,
+            resume_token: 0,
+            game_identifier: 0,
+            join_code: String::new(),
+            metadata: Default::default(),
+            features: FeatureSet::VOIP | FeatureSet::from_bits_retain(1 << 40),
+        }),
+    ));
+    assert_round_trip(packet(PacketType::Challenge as u8, PacketPayload::Challenge(Challenge { cookie: u64::MAX })));
+    assert_round_trip(packet(
+        PacketType::ConnectDeny as u8,
+        PacketPayload::ConnectDeny(ConnectDeny { reason: "Session full".to_string(), nonce: 77 }),
+    ));
+    assert_round_trip(packet(
+        PacketType::SessionRedirect as u8,
+        PacketPayload::SessionRedirect(SessionRedirect {
+            session_id: 42,
+            relays: vec![
+                RelayInfo { addr: "[2001:db8::1]:7777".to_string(), load: 12 },
+                RelayInfo { addr: "192.0.2.1:7777".to_string(), load: 0 },
+            ],
+        }),
+    ));
+}
+
+#[test]
+fn v1_handshakes_carry_no_nonce() {
+    let request = packet(
+        PacketType::ConnectRequest as u8,
+        PacketPayload::ConnectRequest(ConnectRequest {
+            client_version: PROTOCOL_VERSION,
+            desired_name: "legacy".to_string(),
+            target_session_id: 42,
+            game_identifier: 0,
+            nonce: 77,
+            resume_id: 300,
+            resume_token: 9,
+            auth_token: Vec::new(),
+            role: Default::default(),
+        }),
+    );
+    let PacketPayload::ConnectRequest(decoded) =
+        protocol::decode(&protocol::encode_versioned(&request, PROTOCOL_V1, None)).unwrap().payload
+    else {
+        unreachable!()
+    };
+    assert_eq!((decoded.nonce, decoded.resume_id, decoded.resume_token), (0, 0, 0));
+    assert_eq!(decoded.desired_name, "legacy");
+
+    // Without a token the name still runs to the end of the packet, as older peers expect
+    let bytes = protocol::encode_versioned(&request, PROTOCOL_V1, None);
+    assert!(bytes.ends_with(b"legacy"));
+
+    let deny = packet(
+        PacketType::ConnectDeny as u8,
+        PacketPayload::ConnectDeny(ConnectDeny { reason: "Session full".to_string(), nonce: 77 }),
+    );
+    let PacketPayload::ConnectDeny(decoded) =
+        protocol::decode(&protocol::encode_versioned(&deny, PROTOCOL_V1, None)).unwrap().payload
+    else {
+        unreachable!()
+    };
+    assert_eq!(decoded.reason, "Session full");
+    assert_eq!(decoded.nonce, 0);
+}
+
+#[test]
+fn session_payloads_round_trip() {
+    assert_round_trip(packet(
+        PacketType::SessionConfig as u8,
+        PacketPayload::SessionConfig(SessionConfig { version: 1, tick_rate: 60, max_packet_size: 1024, features: Default::default() }),
+    ));
+    assert_round_trip(packet(
+        PacketType::SessionConfig as u8,
+        PacketPayload::SessionConfig(SessionConfig { version: 3, tick_rate: 30, max_packet_size: 1400, features: FeatureSet::MOVEMENT | FeatureSet::RPC }),
+    ));
+    assert_round_trip(packet(
+        PacketType::PacketTypeRegistry as u8,
+        PacketPayload::PacketTypeRegistry(PacketTypeRegistry {
+            entries: vec![
+                PacketTypeEntry {
+                    packet_id: 0x10,
+                    name: "PlayerMovement".to_string(),
+                    description: "x, y, z as f32".to_string(),
+                },
+                PacketTypeEntry { packet_id: 0x11, name: "Chat".to_string(), description: String::new() },
+            ],
+        }),
+    ));
+    assert_round_trip(packet(
+        PacketType::Ack as u8,
+        PacketPayload::Ack(Ack { acknowledged_sequences: vec![1, 0xFFFF, 300] }),
+    ));
+    assert_round_trip(packet(
+        PacketType::DisconnectNotice as u8,
+        PacketPayload::DisconnectNotice(DisconnectNotice { client_id: 300, reason: DisconnectReason::TimedOut }),
+    ));
+}
+
+#[test]
+fn ping_pong_and_game_payloads_round_trip() {
+    assert_round_trip(packet(PacketType::Ping as u8, PacketPayload::Ping(Ping { timestamp: u64::MAX })));
+    assert_round_trip(packet(PacketType::Pong as u8, PacketPayload::Pong(Pong { original_timestamp: 12345 })));
+    assert_round_trip(packet(0x10, PacketPayload::GamePacket(vec![1, 2, 3])));
+    assert_round_trip(packet(0xFF, PacketPayload::GamePacket(Vec::new())));
+}
+
+#[test]
+fn batches_round_trip() {
+    assert_round_trip(packet(
+        PacketType::Batch as u8,
+        PacketPayload::Batch(vec![
+            packet(0x10, PacketPayload::GamePacket(vec![1; 40])),
+            packet(PacketType::Ping as u8, PacketPayload::Ping(Ping { timestamp: 1 })),
+        ]),
+    ));
+}
+
+#[test]
+fn compressed_encoding_decodes_to_the_same_packet() {
+    let original = packet(0x10, PacketPayload::GamePacket(vec![0xAB; 600]));
+    let bytes = protocol::encode_compressed(&original, Some(256));
+    if cfg!(feature = "lz4") {
+        assert!(bytes.len() < protocol::encode(&original).len());
+    } else {
+        assert_eq!(bytes, protocol::encode(&original));
+    }
+    assert_eq!(protocol::decode(&bytes).unwrap(), original);
+
+    // Core packets are never compressed
+    let ping = packet(PacketType::Ping as u8, PacketPayload::Ping(Ping { timestamp: 5 }));
+    assert_eq!(protocol::encode_compressed(&ping, Some(0)), protocol::encode(&ping));
+}
+
+fn acks(bytes: &[u8]) -> Vec<u16> {
+    let header = protocol::decode_header(bytes).unwrap();
+    protocol::split_acks(header.flags, &bytes[header.size()..]).unwrap().0
+}
+
+#[test]
+fn acks_ride_ahead_of_game_payloads() {
+    let original = packet(0x10, PacketPayload::GamePacket(vec![0xAB; 600]));
+    let bytes = protocol::encode_with_acks(&original, PROTOCOL_VERSION, Some(256), &[7, 9]);
+    assert_eq!(protocol::decode_header(&bytes).unwrap().flags & FLAG_ACKS, FLAG_ACKS);
+    assert_eq!(acks(&bytes), [7, 9]);
+    assert_eq!(protocol::decode(&bytes).unwrap(), original);
+
+    // Without a flags byte, or on core packets, the acks stay behind
+    assert!(!protocol::can_carry_acks(&original, PROTOCOL_V2));
+    assert_eq!(protocol::encode_with_acks(&original, PROTOCOL_V2, None, &[7]), protocol::encode_versioned(&original, PROTOCOL_V2, None));
+    let ping = packet(PacketType::Ping as u8, PacketPayload::Ping(Ping { timestamp: 5 }));
+    assert!(!protocol::can_carry_acks(&ping, PROTOCOL_VERSION));
+    assert_eq!(acks(&protocol::encode_with_acks(&ping, PROTOCOL_VERSION, None, &[7])), []);
+
+    let many: Vec<u16> = (0..100).collect();
+    let bytes = protocol::encode_with_acks(&original, PROTOCOL_VERSION, None, &many);
+    assert_eq!(acks(&bytes).len(), protocol::MAX_PIGGYBACKED_ACKS);
+}
+
+#[test]
+fn malformed_datagrams_are_rejected() {
+    assert_eq!(protocol::decode(&[0x45, 0x4E, 1]).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(protocol::decode(&[0; 8]).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let mut bytes = protocol::encode(&packet(
+        PacketType::SessionConfig as u8,
+        PacketPayload::SessionConfig(SessionConfig { version: 1, tick_rate: 60, max_packet_size: 1024, features: Default::default() }),
+    ));
+    bytes.truncate(bytes.len() - 1);
+    assert_eq!(protocol::decode(&bytes).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let mut bytes = protocol::encode(&packet(
+        PacketType::PacketTypeRegistry as u8,
+        PacketPayload::PacketTypeRegistry(PacketTypeRegistry {
+            entries: vec![PacketTypeEntry { packet_id: 0x10, name: "Move".to_string(), description: String::new() }],
+        }),
+    ));
+    bytes[HEADER_SIZE + 2] = 200; // name length past the end
+    assert_eq!(protocol::decode(&bytes).unwrap_err().kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,152 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use project_neon::NeonClient;
+use project_neon::host::NeonHost;
+use project_neon::protocol::{self, ConnectAccept, ConnectRequest, NeonPacket, PacketPayload, PacketType, PROTOCOL_VERSION};
+use project_neon::relay::{ConnectLimits, RelayCore};
+use project_neon::transport::{MemoryNetwork, Transport};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn registration(session_id: u32, cookie: u64) -> Vec<u8> {
+    let packet = NeonPacket {
+        packet_type: PacketType::ConnectAccept as u8,
+        sequence: 1,
+        client_id: 1,
+        destination_id: 1,
+        payload: PacketPayload::ConnectAccept(ConnectAccept { assigned_client_id: 1, session_id, cookie, nonce: 0, resume_token: 0, game_identifier: 0, join_code: String::new(), metadata: Default::default(), features: Default::default() }),
+    };
+    protocol::encode(&packet)
+}
+
+fn connect_request(session_id: u32) -> Vec<u8> {
+    protocol::encode(&NeonPacket {
+        packet_type: PacketType::ConnectRequest as u8,
+        sequence: 1,
+        client_id: 0,
+        destination_id: 1,
+        payload: PacketPayload::ConnectRequest(ConnectRequest {
+            client_version: PROTOCOL_VERSION,
+            desired_name: "alice".to_string(),
+            target_session_id: session_id,
+            game_identifier: 0,
+            nonce: 0,
+            resume_id: 0,
+            resume_token: 0,
+            auth_token: Vec::new(),
+            role: Default::default(),
+        }),
+    })
+}
+
+/// A relay core holding session 50, registered from `host`
+fn relay_with_session(host: SocketAddr) -> RelayCore {
+    let mut relay = RelayCore::new();
+    let outgoing = relay.handle_datagram(&registration(50, 0), host).unwrap();
+    let PacketPayload::Challenge(challenge) = protocol::decode(&outgoing[0].0).unwrap().payload else {
+        panic!("expected Challenge");
+    };
+    relay.handle_datagram(&registration(50, challenge.cookie), host).unwrap();
+    relay
+}
+
+#[test]
+fn registrations_are_challenged_without_a_socket() {
+    let mut relay = RelayCore::new();
+    let host: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+
+    let outgoing = relay.handle_datagram(&registration(50, 0), host).unwrap();
+    assert_eq!(outgoing.len(), 1);
+    assert_eq!(outgoing[0].1, host);
+    let PacketPayload::Challenge(challenge) = protocol::decode(&outgoing[0].0).unwrap().payload else {
+        panic!("expected Challenge");
+    };
+    assert_eq!(relay.session_count(), 0);
+
+    assert!(relay.handle_datagram(&registration(50, challenge.cookie), host).unwrap().is_empty());
+    assert_eq!(relay.session_count(), 1);
+    assert_eq!(relay.sessions().lookup_peer(host, 1), Some(50.into()));
+
+    assert!(relay.handle_datagram(b"not neon", host).is_err());
+}
+
+#[test]
+fn embedded_relay_serves_hosts_and_clients() {
+    let network = MemoryNetwork::new();
+    let socket = network.bind("127.0.0.1:7777").unwrap();
+    let relay_addr = socket.local_addr().unwrap();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let relay_thread = {
+        let running = running.clone();
+        thread::spawn(move || {
+            let mut relay = RelayCore::new();
+            let mut buf = [0; 1500];
+            socket.set_nonblocking(true).unwrap();
+            while running.load(Ordering::Relaxed) {
+                let mut outgoing = relay.poll();
+                if socket.wait_readable(Duration::from_millis(10)).unwrap() {
+                    while let Ok((len, from)) = socket.recv_from(&mut buf) {
+                        outgoing.extend(relay.handle_datagram(&buf[..len], from).unwrap());
+                    }
+                }
+                for (data, to) in outgoing {
+                    socket.send_to(&data, to).unwrap();
+                }
+            }
+            relay
+        })
+    };
+
+    let mut host = NeonHost::with_transport(51, relay_addr, network.bind("127.0.0.1:0").unwrap()).unwrap();
+    let (host_tx, host_rx) = mpsc::channel();
+    host.on_game_packet(move |packet_type, from, data| host_tx.send((packet_type, from, data.to_vec())).unwrap());
+    let worker = host.spawn().unwrap();
+
+    let mut client = NeonClient::with_transport("alice".to_string(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.connect(51, relay_addr).unwrap();
+    let client_id = client.client_id().unwrap();
+    client.send_game_packet(0x10, b"through the core").unwrap();
+    assert_eq!(host_rx.recv_timeout(TIMEOUT).unwrap(), (0x10, client_id, b"through the core".to_vec()));
+
+    worker.shutdown().unwrap();
+    running.store(false, Ordering::Relaxed);
+    let relay = relay_thread.join().unwrap();
+    assert!(relay.packets_relayed() >= 1);
+    assert_eq!(relay.total_client_count(), 2);
+}
+
+#[test]
+fn connection_request_floods_are_throttled() {
+    let host: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+    let mut relay = relay_with_session(host);
+    relay.set_connect_limits(ConnectLimits { requests_per_second: 0, burst: 3, ..ConnectLimits::default() });
+
+    // Forwarded to the host and acknowledged, until the address's burst is spent
+    let client: SocketAddr = "10.0.0.2:6000".parse().unwrap();
+    for _ in 0..3 {
+        assert_eq!(relay.handle_datagram(&connect_request(50), client).unwrap().len(), 2);
+    }
+    assert!(relay.handle_datagram(&connect_request(50), client).unwrap().is_empty());
+    assert_eq!(relay.throttled_request_count(), 1);
+
+    // Other addresses have their own allowance, but only so many requests may wait on the host;
+    // the first address's retries share one pending request
+    relay.set_connect_limits(ConnectLimits { max_pending: 3, ..ConnectLimits::default() });
+    for port in 7000..7002 {
+        let addr = SocketAddr::from(([10, 0, 0, 3], port));
+        assert_eq!(relay.handle_datagram(&connect_request(50), addr).unwrap().len(), 2);
+    }
+    let late: SocketAddr = "10.0.0.4:8000".parse().unwrap();
+    assert!(relay.handle_datagram(&connect_request(50), late).unwrap().is_empty());
+    assert_eq!(relay.throttled_request_count(), 2);
+
+    // Malformed requests fail before they are counted against anyone
+    assert!(relay.handle_datagram(&connect_request(50)[..6], late).is_err());
+    assert_eq!(relay.throttled_request_count(), 2);
+    assert_eq!(relay.malformed_packet_count(), 1);
+}
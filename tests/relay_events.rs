@@ -0,0 +1,86 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::protocol::{self, NeonPacket, PacketPayload};
+use project_neon::relay::{DropReason, NeonRelay};
+use project_neon::testing::{LocalCluster, MemoryTransport};
+use project_neon::transport::Transport;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn joins_are_reported_with_the_clients_address() {
+    let joined = Arc::new(Mutex::new(Vec::new()));
+    let mut cluster = {
+        let joined = joined.clone();
+        LocalCluster::with_relay(310, move |relay: &mut NeonRelay<MemoryTransport>| {
+            relay.on_client_joined(move |session_id, client_id, addr| {
+                joined.lock().unwrap().push((session_id, client_id, addr));
+            });
+        })
+        .unwrap()
+    };
+    cluster.add_client("alice").unwrap();
+    cluster.add_client("bob").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| joined.lock().unwrap().len() == 2).unwrap());
+    let joined = joined.lock().unwrap().clone();
+    assert_eq!(joined.iter().map(|&(session, id, _)| (session, id)).collect::<Vec<_>>(), vec![(310, 2), (310, 3)]);
+    assert_ne!(joined[0].2, joined[1].2);
+}
+
+#[test]
+fn silent_clients_are_reported_when_they_time_out() {
+    let timed_out = Arc::new(Mutex::new(Vec::new()));
+    let mut cluster = {
+        let timed_out = timed_out.clone();
+        LocalCluster::with_relay(311, move |relay: &mut NeonRelay<MemoryTransport>| {
+            relay.set_peer_timeout(Duration::from_millis(300));
+            relay.on_client_timed_out(move |session_id, client_id| {
+                timed_out.lock().unwrap().push((session_id, client_id));
+            });
+        })
+        .unwrap()
+    };
+    cluster.host(|host| host.set_relay_keepalive(Some(Duration::from_millis(50))));
+    cluster.add_client("alice").unwrap();
+    cluster.client(0).set_auto_ping(false);
+
+    assert!(cluster.run_until(TIMEOUT, |_| !timed_out.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(*timed_out.lock().unwrap(), vec![(311, 2)]);
+}
+
+#[test]
+fn dropped_datagrams_are_reported_and_the_relay_keeps_running() {
+    let dropped: Arc<Mutex<Vec<(SocketAddr, DropReason)>>> = Arc::new(Mutex::new(Vec::new()));
+    let mut cluster = {
+        let dropped = dropped.clone();
+        LocalCluster::with_relay(312, move |relay: &mut NeonRelay<MemoryTransport>| {
+            relay.on_packet_dropped(move |addr, reason| dropped.lock().unwrap().push((addr, reason)));
+        })
+        .unwrap()
+    };
+    let stranger = cluster.network().bind("127.0.0.1:0").unwrap();
+    let stranger_addr = stranger.local_addr().unwrap();
+
+    stranger.send_to(b"not a neon packet", cluster.relay_addr()).unwrap();
+    let packet = NeonPacket {
+        packet_type: 0x10,
+        sequence: 1,
+        client_id: 5,
+        destination_id: 1,
+        payload: PacketPayload::GamePacket(b"hello?".to_vec()),
+    };
+    stranger.send_to(&protocol::encode(&packet), cluster.relay_addr()).unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| dropped.lock().unwrap().len() == 2).unwrap());
+    assert_eq!(
+        *dropped.lock().unwrap(),
+        vec![(stranger_addr, DropReason::Malformed), (stranger_addr, DropReason::Unregistered)]
+    );
+
+    // The garbage didn't take the relay down
+    cluster.add_client("alice").unwrap();
+    assert_eq!(cluster.client(0).session_id(), Some(312));
+}
@@ -0,0 +1,99 @@
+use std::io::{BufRead, BufReader, Lines};
+use std::net::{SocketAddr, UdpSocket};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use project_neon::protocol::{self, ConnectRequest, NeonPacket, PacketPayload, PacketType, PROTOCOL_VERSION};
+
+/// The relay binary, killed when dropped
+struct Relay {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl Relay {
+    fn start(args: &[&str]) -> Relay {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_relay"))
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+        Relay { child, lines }
+    }
+
+    fn next_line(&mut self) -> String {
+        self.lines.next().expect("relay exited").unwrap()
+    }
+}
+
+impl Drop for Relay {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The string value of `field` in a flat JSON line
+fn field<'a>(line: &'a str, field: &str) -> Option<&'a str> {
+    let start = line.find(&format!("\"{}\":\"", field))? + field.len() + 4;
+    Some(&line[start..start + line[start..].find('"')?])
+}
+
+#[test]
+fn json_mode_logs_one_event_object_per_line() {
+    let mut relay = Relay::start(&["--bind", "127.0.0.1:0", "--log-format", "json"]);
+
+    let listening = relay.next_line();
+    assert!(listening.starts_with("{\"ts\":\"") && listening.ends_with('}'), "{}", listening);
+    assert_eq!(field(&listening, "event"), Some("listening"));
+    let ts = field(&listening, "ts").unwrap();
+    assert_eq!(ts.len(), "2026-01-01T00:00:00.000Z".len(), "{}", ts);
+    assert!(ts.starts_with("20") && ts.ends_with('Z') && &ts[10..11] == "T", "{}", ts);
+    let relay_addr: SocketAddr = field(&listening, "addr").unwrap().parse().unwrap();
+
+    let request = NeonPacket {
+        packet_type: PacketType::ConnectRequest as u8,
+        sequence: 1,
+        client_id: 0,
+        destination_id: 1,
+        payload: PacketPayload::ConnectRequest(ConnectRequest {
+            client_version: PROTOCOL_VERSION,
+            desired_name: "quote\"d".to_string(),
+            target_session_id: 4242,
+            game_identifier: 0,
+            nonce: 0,
+            resume_id: 0,
+            resume_token: 0,
+            auth_token: Vec::new(),
+            role: Default::default(),
+        }),
+    };
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.send_to(&protocol::encode(&request), relay_addr).unwrap();
+
+    let connect = relay.next_line();
+    assert_eq!(field(&connect, "event"), Some("connect"));
+    assert!(connect.contains("\"session_id\":4242,\"name\":\"quote\\\"d\","), "{}", connect);
+    assert_eq!(field(&connect, "addr"), Some(socket.local_addr().unwrap().to_string().as_str()));
+
+    let not_found = relay.next_line();
+    assert_eq!(field(&not_found, "event"), Some("session_not_found"));
+    assert!(not_found.contains("\"session_id\":4242"), "{}", not_found);
+}
+
+#[test]
+fn text_mode_keeps_the_banner() {
+    let mut relay = Relay::start(&["--bind", "127.0.0.1:0"]);
+    assert_eq!(relay.next_line(), "Project Neon Protocol v0.2 - Relay");
+}
+
+#[test]
+fn unknown_arguments_are_refused() {
+    let status = Command::new(env!("CARGO_BIN_EXE_relay"))
+        .args(["--log-format", "xml"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(2));
+}
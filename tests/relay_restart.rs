@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use project_neon::host::ConnectDecision;
+use project_neon::protocol::{
+    self, ConnectRequest, NeonPacket, PacketPayload, PacketType, PROTOCOL_VERSION, RESUME_REFUSED,
+};
+use project_neon::testing::LocalCluster;
+use project_neon::transport::Transport;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn state_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("neon-{}-{}.state", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// Game packets the host received, with the sender's ID
+type Received = Arc<Mutex<Vec<(u16, Vec<u8>)>>>;
+
+/// Host side of a cluster: game packets received and connect requests seen
+fn watch_host(cluster: &LocalCluster) -> (Received, Arc<Mutex<usize>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let requests = Arc::new(Mutex::new(0));
+    let (packet_sink, request_sink) = (received.clone(), requests.clone());
+    cluster.host(|host| {
+        host.set_relay_keepalive(Some(Duration::from_millis(50)));
+        host.on_game_packet(move |_, from, data| packet_sink.lock().unwrap().push((from, data.to_vec())));
+        host.on_connect_request(move |_, _, _| {
+            *request_sink.lock().unwrap() += 1;
+            ConnectDecision::Accept
+        });
+    });
+    (received, requests)
+}
+
+/// Keep sending game packets from every client until the host has heard from each of them
+fn until_host_hears_everyone(cluster: &mut LocalCluster, received: &Mutex<Vec<(u16, Vec<u8>)>>) -> bool {
+    let mut last_send = None::<Instant>;
+    cluster
+        .run_until(TIMEOUT, |cluster| {
+            if last_send.is_none_or(|at| at.elapsed() >= Duration::from_millis(100)) {
+                for index in 0..cluster.client_count() {
+                    cluster.client(index).send_game_packet(0x10, b"still here").unwrap();
+                }
+                last_send = Some(Instant::now());
+            }
+            let received = received.lock().unwrap();
+            [2, 3].iter().all(|id| received.iter().any(|(from, _)| from == id))
+        })
+        .unwrap()
+}
+
+#[test]
+fn restored_state_keeps_the_session_running() {
+    let path = state_path("restored");
+    let mut cluster = LocalCluster::with_relay(150, |relay| relay.set_state_file(&path)).unwrap();
+    cluster.add_client("alice").unwrap();
+    cluster.add_client("bob").unwrap();
+    let (received, requests) = watch_host(&cluster);
+
+    let saved = |needle: &[u8]| fs::read(&path).is_ok_and(|data| data.windows(needle.len()).any(|w| w == needle));
+    assert!(cluster.run_until(TIMEOUT, |_| saved(b"bob")).unwrap());
+
+    cluster.restart_relay(|relay| relay.set_state_file(&path)).unwrap();
+
+    // A relay that had forgotten them would drop these and ask the senders to resume
+    cluster.client(0).send_game_packet(0x10, b"first try").unwrap();
+    cluster.client(1).send_game_packet(0x10, b"first try").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| received.lock().unwrap().len() == 2).unwrap());
+
+    // Nobody had to join again
+    assert_eq!(*requests.lock().unwrap(), 0);
+    assert_eq!(cluster.client(0).client_id(), Some(2));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn clients_resume_their_ids_after_a_stateless_restart() {
+    let mut cluster = LocalCluster::with_clients(151, &["alice", "bob"]).unwrap();
+    let (received, requests) = watch_host(&cluster);
+    let chats = Arc::new(Mutex::new(Vec::new()));
+    let sink = chats.clone();
+    cluster.client(1).on_chat_message(move |from, name, text| sink.lock().unwrap().push((from, name, text)));
+
+    cluster.restart_relay(|_| {}).unwrap();
+    assert!(until_host_hears_everyone(&mut cluster, &received));
+
+    // Both came back under their old IDs without going through admission again,
+    // and the relay knows their names again
+    assert_eq!(*requests.lock().unwrap(), 0);
+    assert_eq!(cluster.client(0).client_id(), Some(2));
+    assert_eq!(cluster.client(1).client_id(), Some(3));
+    assert_eq!(cluster.host(|host| host.client_count()), 2);
+
+    cluster.client(0).send_chat("back again", 3).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !chats.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(chats.lock().unwrap()[0], (2, "alice".to_string(), "back again".to_string()));
+}
+
+#[test]
+fn resuming_needs_the_host_issued_token() {
+    let cluster = LocalCluster::with_clients(152, &["alice"]).unwrap();
+    let socket = cluster.network().bind("0.0.0.0:0").unwrap();
+    socket.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    let request = NeonPacket {
+        packet_type: PacketType::ConnectRequest as u8,
+        sequence: 1,
+        client_id: 0,
+        destination_id: 1,
+        payload: PacketPayload::ConnectRequest(ConnectRequest {
+            client_version: PROTOCOL_VERSION,
+            desired_name: "alice".to_string(),
+            target_session_id: 152,
+            game_identifier: 0,
+            nonce: 0,
+            resume_id: 2,
+            resume_token: 12345,
+            auth_token: Vec::new(),
+            role: Default::default(),
+        }),
+    };
+    socket.send_to(&protocol::encode(&request), cluster.relay_addr()).unwrap();
+
+    // The relay acknowledges the request before the host's answer arrives
+    let mut buf = [0; 1500];
+    let answer = loop {
+        let (len, _) = socket.recv_from(&mut buf).unwrap();
+        let packet = protocol::decode(&buf[..len]).unwrap();
+        if !matches!(packet.payload, PacketPayload::Ack(_)) {
+            break packet;
+        }
+    };
+    let PacketPayload::ConnectDeny(deny) = answer.payload else {
+        panic!("expected ConnectDeny");
+    };
+    assert_eq!(deny.reason, RESUME_REFUSED);
+}
@@ -0,0 +1,68 @@
+use std::net::UdpSocket;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use project_neon::NeonClient;
+use project_neon::host::NeonHost;
+use project_neon::relay::{NeonRelay, RelayHandle};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start a relay with `workers` threads on a real UDP socket, so datagrams come in through the
+/// batched receive path
+fn start_udp_relay(workers: usize) -> (String, RelayHandle, thread::JoinHandle<std::io::Result<()>>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap().to_string();
+    let mut relay = NeonRelay::builder().worker_threads(workers).build_with_transport(socket).unwrap();
+    let handle = relay.handle();
+    (addr, handle, thread::spawn(move || relay.start()))
+}
+
+#[test]
+fn sessions_on_a_multi_worker_relay_each_keep_their_order() {
+    const SESSIONS: u32 = 6;
+    const PACKETS: u16 = 50;
+    let (relay_addr, handle, relay_thread) = start_udp_relay(4);
+
+    let mut hosts = Vec::new();
+    let mut clients = Vec::new();
+    for session_id in 400..400 + SESSIONS {
+        let mut host = NeonHost::new(session_id, relay_addr.as_str()).unwrap();
+        let (tx, rx) = mpsc::channel();
+        host.on_game_packet(move |_, _, data| tx.send(u16::from_le_bytes([data[0], data[1]])).unwrap());
+        hosts.push((host.spawn().unwrap(), rx));
+
+        let mut client = NeonClient::new(format!("player{}", session_id)).unwrap();
+        client.connect(session_id, relay_addr.as_str()).unwrap();
+        clients.push(client);
+    }
+
+    // Interleaved, so each receive batch mixes sessions owned by different workers. Paced so the
+    // relay's socket buffer doesn't overflow.
+    for sequence in 0..PACKETS {
+        for client in &mut clients {
+            client.send_game_packet(0x10, &sequence.to_le_bytes()).unwrap();
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    for (session_id, (_, rx)) in (400..).zip(&hosts) {
+        let deadline = Instant::now() + TIMEOUT;
+        let received: Vec<u16> = (0..PACKETS).map_while(|_| rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok()).collect();
+        assert_eq!(received, (0..PACKETS).collect::<Vec<_>>(), "session {}", session_id);
+
+        // Stats come from the worker that owns the session, so it saw every packet
+        let stats = handle.session_stats(session_id).unwrap();
+        let client_id = clients[(session_id - 400) as usize].client_id().unwrap();
+        let sent = stats.peers.iter().find(|peer| peer.client_id == client_id).unwrap().sent.packets;
+        assert!(sent >= PACKETS as u64, "session {} forwarded {} packets", session_id, sent);
+    }
+    assert_eq!(handle.session_count(), SESSIONS as usize);
+
+    for (worker, _) in hosts {
+        worker.shutdown().unwrap();
+    }
+    handle.stop();
+    relay_thread.join().unwrap().unwrap();
+}
@@ -0,0 +1,58 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::sequence::{PeerSequences, SequenceStatus};
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn replayed_sequences_are_rejected_and_counted() {
+    let mut sequences = PeerSequences::new();
+    for sequence in 1..=3 {
+        assert_eq!(sequences.record(2, sequence), SequenceStatus::New);
+    }
+    assert_eq!(sequences.record(2, 2), SequenceStatus::Duplicate);
+    assert_eq!(sequences.record(2, 200), SequenceStatus::New);
+    assert_eq!(sequences.record(2, 3), SequenceStatus::Stale);
+    assert_eq!(sequences.record(3, 3), SequenceStatus::New);
+    assert_eq!(sequences.stats(2).unwrap().rejected(), 2);
+
+    // A reset peer starts a new window, but its counts stay in the totals
+    sequences.reset(2);
+    assert_eq!(sequences.record(2, 0), SequenceStatus::New);
+    let totals = sequences.totals();
+    assert_eq!((totals.received, totals.rejected()), (6, 2));
+    sequences.reset_all();
+    assert_eq!(sequences.totals(), totals);
+    assert_eq!(sequences.stats(3), None);
+}
+
+#[test]
+fn reused_ids_get_a_fresh_window() {
+    let mut cluster = LocalCluster::with_clients(123, &["alice", "bob"]).unwrap();
+    cluster.host(|host| host.set_id_reuse_delay(Duration::ZERO));
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.client(0).on_game_packet(move |_, from, data| sink.lock().unwrap().push((from, data.to_vec())));
+    assert!(cluster.run_until(TIMEOUT, |cluster| !cluster.client(0).peers().is_empty()).unwrap());
+
+    // Take bob's numbering well past the window
+    for _ in 0..80 {
+        cluster.client(1).send_game_packet_to(2, 0x20, b"bob").unwrap();
+    }
+    assert!(cluster.run_until(TIMEOUT, |_| received.lock().unwrap().len() == 80).unwrap());
+
+    assert!(cluster.host(|host| host.remove_client(3)).unwrap());
+    assert!(cluster.run_until(TIMEOUT, |cluster| cluster.client(0).peers().is_empty()).unwrap());
+    let carol = cluster.add_client("carol").unwrap();
+    assert_eq!(cluster.client(carol).client_id(), Some(3));
+    assert!(cluster.run_until(TIMEOUT, |cluster| !cluster.client(0).peers().is_empty()).unwrap());
+
+    cluster.client(carol).send_game_packet_to(2, 0x20, b"carol").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| received.lock().unwrap().len() == 81).unwrap());
+    assert_eq!(received.lock().unwrap()[80], (3, b"carol".to_vec()));
+    assert_eq!(cluster.client(0).sequence_totals().rejected(), 0);
+
+    cluster.shutdown().unwrap();
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::protocol::REPLICATION_SNAPSHOT;
+use project_neon::replication::{MAX_ENTITY_SIZE, ReplicationClient, ReplicationServer};
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn state(entries: &[(u32, &[u8])]) -> HashMap<u32, Vec<u8>> {
+    entries.iter().map(|(id, data)| (*id, data.to_vec())).collect()
+}
+
+#[test]
+fn deltas_apply_against_the_acknowledged_state() {
+    let mut server = ReplicationServer::new();
+    let mut client = ReplicationClient::new();
+
+    server.set(1, b"north").unwrap();
+    server.set(2, b"south").unwrap();
+    server.commit(10).unwrap();
+    let full = server.snapshot_for(2).unwrap();
+    assert_eq!(client.receive(&full[0]).unwrap(), Some(10));
+    server.ack(2, 10);
+
+    server.set(1, b"east").unwrap();
+    server.remove(2);
+    server.set(3, b"west").unwrap();
+    server.commit(11).unwrap();
+    let delta = server.snapshot_for(2).unwrap();
+    assert_eq!(client.receive(&delta[0]).unwrap(), Some(11));
+    assert_eq!(*client.entities(), state(&[(1, b"east"), (3, b"west")]));
+
+    // Stale snapshots are ignored
+    assert_eq!(client.receive(&full[0]).unwrap(), None);
+    assert_eq!(server.commit(11).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn large_snapshots_are_split_and_reassembled() {
+    let mut server = ReplicationServer::new();
+    let mut client = ReplicationClient::new();
+    let blob = vec![7; MAX_ENTITY_SIZE];
+    for id in 0..5 {
+        server.set(id, &blob).unwrap();
+    }
+    assert_eq!(server.set(9, &[0; MAX_ENTITY_SIZE + 1]).unwrap_err().kind(), ErrorKind::InvalidInput);
+    server.commit(1).unwrap();
+
+    let parts = server.snapshot_for(2).unwrap();
+    assert_eq!(parts.len(), 5);
+    // Parts may arrive in any order; nothing applies until all have
+    for part in parts[1..].iter().rev() {
+        assert_eq!(client.receive(part).unwrap(), None);
+    }
+    assert!(client.entities().is_empty());
+    assert_eq!(client.receive(&parts[0]).unwrap(), Some(1));
+    assert_eq!(client.entities().len(), 5);
+}
+
+#[test]
+fn clients_stay_in_sync_across_joins() {
+    let mut cluster = LocalCluster::with_clients(61, &["alice"]).unwrap();
+    let snapshots = Arc::new(Mutex::new(Vec::new()));
+    let sink = snapshots.clone();
+    cluster.client(0).on_snapshot(move |tick, entities| sink.lock().unwrap().push((tick, entities.clone())));
+
+    cluster.host(|host| {
+        host.set_entity(1, b"ship").unwrap();
+        host.set_entity(2, b"rock").unwrap();
+        host.replicate(1)
+    }).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).snapshot_tick() == Some(1)).unwrap());
+    assert_eq!(*cluster.client(0).entities(), state(&[(1, b"ship"), (2, b"rock")]));
+
+    cluster.host(|host| {
+        host.set_entity(1, b"ship moved").unwrap();
+        assert!(host.remove_entity(2));
+        host.replicate(2)
+    }).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).snapshot_tick() == Some(2)).unwrap());
+    assert_eq!(*cluster.client(0).entities(), state(&[(1, b"ship moved")]));
+    assert_eq!(snapshots.lock().unwrap().iter().map(|(tick, _)| *tick).collect::<Vec<_>>(), vec![1, 2]);
+
+    // A late joiner gets everything in its first snapshot
+    cluster.add_client("bob").unwrap();
+    cluster.host(|host| host.replicate(3)).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(1).snapshot_tick() == Some(3)).unwrap());
+    assert_eq!(*cluster.client(1).entities(), state(&[(1, b"ship moved")]));
+}
+
+#[test]
+fn reserved_packet_types_are_refused() {
+    let mut cluster = LocalCluster::with_clients(62, &["alice"]).unwrap();
+    let err = cluster.client(0).send_game_packet(REPLICATION_SNAPSHOT, b"spoof").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = cluster.host(|host| host.send_game_packet(2, REPLICATION_SNAPSHOT, b"spoof")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = cluster.host(|host| host.register_packet_type(0xF5, "Mine", "")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
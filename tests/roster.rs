@@ -0,0 +1,74 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::client::PeerInfo;
+use project_neon::protocol::DisconnectReason;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Events = Arc<Mutex<Vec<(&'static str, u16, String)>>>;
+
+fn record_roster_events(cluster: &mut LocalCluster, index: usize) -> Events {
+    let events = Events::default();
+    let joined = events.clone();
+    let left = events.clone();
+    let client = cluster.client(index);
+    client.on_peer_joined(move |id, name| joined.lock().unwrap().push(("joined", id, name)));
+    client.on_peer_left(move |id, name| left.lock().unwrap().push(("left", id, name)));
+    events
+}
+
+fn peer(client_id: u16, name: &str) -> PeerInfo {
+    PeerInfo { client_id, name: name.to_string(), role: Default::default() }
+}
+
+#[test]
+fn clients_learn_about_each_other() {
+    let mut cluster = LocalCluster::with_clients(41, &["alice"]).unwrap();
+    let alice_events = record_roster_events(&mut cluster, 0);
+
+    cluster.add_client("bob").unwrap();
+    let bob_events = record_roster_events(&mut cluster, 1);
+
+    assert!(cluster.run_until(TIMEOUT, |c| !c.client(0).peers().is_empty() && !c.client(1).peers().is_empty()).unwrap());
+    assert_eq!(cluster.client(0).peers(), &[peer(3, "bob")]);
+    assert_eq!(cluster.client(1).peers(), &[peer(2, "alice")]);
+    assert_eq!(*alice_events.lock().unwrap(), vec![("joined", 3, "bob".to_string())]);
+    // A late joiner hears about everyone already there
+    assert_eq!(*bob_events.lock().unwrap(), vec![("joined", 2, "alice".to_string())]);
+}
+
+#[test]
+fn removed_clients_leave_the_roster() {
+    let mut cluster = LocalCluster::with_clients(42, &["alice", "bob", "carol"]).unwrap();
+    let events = record_roster_events(&mut cluster, 0);
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).peers().len() == 2).unwrap());
+
+    assert!(cluster.host(|host| host.remove_client(3)).unwrap());
+    assert!(!cluster.host(|host| host.remove_client(3)).unwrap());
+
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).peers().len() == 1).unwrap());
+    assert_eq!(cluster.client(0).peers(), &[peer(4, "carol")]);
+    assert_eq!(events.lock().unwrap().last(), Some(&("left", 3, "bob".to_string())));
+    assert_eq!(cluster.host(|host| host.client_count()), 2);
+}
+
+#[test]
+fn closing_the_session_tells_its_clients() {
+    let mut cluster = LocalCluster::with_clients(43, &["alice"]).unwrap();
+    let closed = Arc::new(Mutex::new(Vec::new()));
+    let seen = closed.clone();
+    cluster.client(0).on_session_closed(move |reason| seen.lock().unwrap().push(reason));
+
+    cluster.host(|host| host.close_session()).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).client_id().is_none()).unwrap());
+    assert_eq!(*closed.lock().unwrap(), vec![DisconnectReason::HostClosed]);
+    assert!(cluster.client(0).peers().is_empty());
+    assert_eq!(cluster.host(|host| host.client_count()), 0);
+
+    // Registering again reopens it
+    cluster.host(|host| host.register()).unwrap();
+    cluster.add_client("bob").unwrap();
+    assert_eq!(cluster.host(|host| host.client_count()), 1);
+}
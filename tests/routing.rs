@@ -0,0 +1,156 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::NeonClient;
+use project_neon::protocol::{self, NeonPacket, PacketPayload};
+use project_neon::relay::{DropReason, NeonRelay};
+use project_neon::testing::{LocalCluster, MemoryTransport, Transport};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Received = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// Collect the payload of every game packet `client` receives
+fn record(client: &mut NeonClient<MemoryTransport>) -> Received {
+    let received = Received::default();
+    let sink = received.clone();
+    client.on_game_packet(move |_, _, data| sink.lock().unwrap().push(data.to_vec()));
+    received
+}
+
+fn payloads(received: &Received) -> Vec<Vec<u8>> {
+    received.lock().unwrap().clone()
+}
+
+/// Join the cluster's session with pings off, so the client goes quiet whenever it isn't pumped,
+/// along with a clone of its socket to send raw packets from its address
+fn join_quietly(cluster: &LocalCluster, name: &str) -> (NeonClient<MemoryTransport>, MemoryTransport) {
+    let transport = cluster.network().bind("127.0.0.1:0").unwrap();
+    let socket = transport.try_clone().unwrap();
+    let mut client = NeonClient::with_transport(name.to_string(), transport).unwrap();
+    client.set_auto_ping(false);
+    client.connect(cluster.session_id(), cluster.relay_addr()).unwrap();
+    (client, socket)
+}
+
+#[test]
+fn directed_packets_follow_the_index_as_clients_leave_and_ids_return() {
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let timed_out = Arc::new(Mutex::new(Vec::new()));
+    let mut cluster = {
+        let dropped = dropped.clone();
+        let timed_out = timed_out.clone();
+        LocalCluster::with_relay(430, move |relay: &mut NeonRelay<MemoryTransport>| {
+            relay.set_peer_timeout(Duration::from_millis(300));
+            relay.on_packet_dropped(move |_, reason| dropped.lock().unwrap().push(reason));
+            relay.on_client_timed_out(move |session_id, client_id| timed_out.lock().unwrap().push((session_id, client_id)));
+        })
+        .unwrap()
+    };
+    let host_received = Received::default();
+    {
+        let sink = host_received.clone();
+        cluster.host(|host| {
+            host.set_relay_keepalive(Some(Duration::from_millis(50)));
+            host.set_id_reuse_delay(Duration::ZERO);
+            host.on_game_packet(move |_, _, data| sink.lock().unwrap().push(data.to_vec()));
+        });
+    }
+    let (alice, carol) = (cluster.add_client("alice").unwrap(), cluster.add_client("carol").unwrap());
+    for index in [alice, carol] {
+        cluster.client(index).set_ping_interval(Duration::from_millis(50));
+    }
+    let received: Vec<Received> = (0..2).map(|index| record(cluster.client(index))).collect();
+
+    // Bob is pumped by hand, so he can go quiet while his socket stays open
+    let (mut bob, _) = join_quietly(&cluster, "bob");
+    let bob_received = record(&mut bob);
+    let bob_id = bob.client_id().unwrap();
+    let carol_id = cluster.client(carol).client_id().unwrap();
+
+    // Sent after each directed packet, so once they arrive the relay has routed it too
+    let barrier = |cluster: &mut LocalCluster| {
+        cluster.client(alice).send_game_packet_to(carol_id, 0x10, b"barrier").unwrap();
+        cluster.client(alice).send_game_packet(0x10, b"barrier").unwrap();
+    };
+    let barrier_count = |received: &Received| payloads(received).iter().filter(|data| data.as_slice() == b"barrier").count();
+
+    cluster.client(alice).send_game_packet_to(bob_id, 0x10, b"for bob").unwrap();
+    barrier(&mut cluster);
+    assert!(cluster.run_until(TIMEOUT, |_| barrier_count(&received[carol]) == 1 && barrier_count(&host_received) == 1).unwrap());
+    bob.process_packets().unwrap();
+    assert_eq!(payloads(&bob_received), [b"for bob".to_vec()]);
+    assert_eq!(payloads(&received[carol]), [b"barrier".to_vec()]);
+    assert_eq!(payloads(&host_received), [b"barrier".to_vec()]);
+
+    // Once bob times out, packets for his ID have nowhere to go
+    assert!(cluster.run_until(TIMEOUT, |cluster| !timed_out.lock().unwrap().is_empty() && cluster.host(|host| host.client_count()) == 2).unwrap());
+    assert_eq!(*timed_out.lock().unwrap(), [(430, bob_id)]);
+
+    cluster.client(alice).send_game_packet_to(bob_id, 0x10, b"too late").unwrap();
+    barrier(&mut cluster);
+    // Drops are reported from the relay's own thread, so wait for that too
+    assert!(cluster.run_until(TIMEOUT, |_| barrier_count(&received[carol]) == 2 && barrier_count(&host_received) == 2 && dropped.lock().unwrap().contains(&DropReason::UnknownDestination)).unwrap());
+
+    // A newcomer given bob's old ID gets what's sent to it, at its own address
+    let dave = cluster.add_client("dave").unwrap();
+    assert_eq!(cluster.client(dave).client_id(), Some(bob_id));
+    let dave_received = record(cluster.client(dave));
+    cluster.client(alice).send_game_packet_to(bob_id, 0x10, b"for dave").unwrap();
+    barrier(&mut cluster);
+    assert!(cluster.run_until(TIMEOUT, |_| barrier_count(&received[carol]) == 3 && barrier_count(&host_received) == 3).unwrap());
+    assert_eq!(payloads(&dave_received), [b"for dave".to_vec()]);
+    bob.process_packets().unwrap();
+    assert_eq!(payloads(&bob_received), [b"for bob".to_vec()]);
+    assert_eq!(payloads(&received[carol]), vec![b"barrier".to_vec(); 3]);
+}
+
+fn game_packet(client_id: u16, data: &[u8]) -> Vec<u8> {
+    protocol::encode(&NeonPacket { packet_type: 0x10, sequence: 900, client_id, destination_id: 1, payload: PacketPayload::GamePacket(data.to_vec()) })
+}
+
+#[test]
+fn packets_only_route_from_the_address_a_client_registered() {
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let timed_out = Arc::new(Mutex::new(Vec::new()));
+    let mut cluster = {
+        let dropped = dropped.clone();
+        let timed_out = timed_out.clone();
+        LocalCluster::with_relay(431, move |relay: &mut NeonRelay<MemoryTransport>| {
+            relay.set_peer_timeout(Duration::from_millis(300));
+            relay.on_packet_dropped(move |addr, reason| dropped.lock().unwrap().push((addr, reason)));
+            relay.on_client_timed_out(move |session_id, client_id| timed_out.lock().unwrap().push((session_id, client_id)));
+        })
+        .unwrap()
+    };
+    let host_received = Arc::new(Mutex::new(Vec::new()));
+    {
+        let sink = host_received.clone();
+        cluster.host(|host| {
+            host.set_relay_keepalive(Some(Duration::from_millis(50)));
+            host.on_game_packet(move |_, from, data| sink.lock().unwrap().push((from, data.to_vec())));
+        });
+    }
+    let (mut alice, alice_socket) = join_quietly(&cluster, "alice");
+    let (_carol, carol_socket) = join_quietly(&cluster, "carol");
+    let (alice_addr, carol_addr) = (alice_socket.local_addr().unwrap(), carol_socket.local_addr().unwrap());
+    let alice_id = alice.client_id().unwrap();
+    let stranger = cluster.network().bind("127.0.0.1:0").unwrap();
+    let stranger_addr = stranger.local_addr().unwrap();
+
+    // Alice's ID is only good from alice's address: carol's registration doesn't cover it, and a
+    // stranger has none
+    carol_socket.send_to(&game_packet(alice_id, b"from carol"), cluster.relay_addr()).unwrap();
+    stranger.send_to(&game_packet(alice_id, b"from a stranger"), cluster.relay_addr()).unwrap();
+    alice.send_game_packet(0x10, b"from alice").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| !host_received.lock().unwrap().is_empty() && dropped.lock().unwrap().len() == 2).unwrap());
+    assert_eq!(*dropped.lock().unwrap(), [(carol_addr, DropReason::Spoofed), (stranger_addr, DropReason::Unregistered)]);
+    assert_eq!(*host_received.lock().unwrap(), [(alice_id, b"from alice".to_vec())]);
+
+    // Once alice and carol time out, alice's address is no longer in the index and even her own
+    // packets stop routing
+    assert!(cluster.run_until(TIMEOUT, |_| timed_out.lock().unwrap().len() == 2).unwrap());
+    alice.send_game_packet(0x10, b"after leaving").unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| dropped.lock().unwrap().contains(&(alice_addr, DropReason::Unregistered))).unwrap());
+    assert_eq!(host_received.lock().unwrap().len(), 1);
+}
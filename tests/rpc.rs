@@ -0,0 +1,79 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Reply = Arc<Mutex<Option<Result<Vec<u8>, (ErrorKind, String)>>>>;
+
+fn reply_slot() -> (Reply, impl FnOnce(Result<Vec<u8>, std::io::Error>) + Send + 'static) {
+    let reply = Reply::default();
+    let sink = reply.clone();
+    (reply, move |result: Result<Vec<u8>, std::io::Error>| {
+        *sink.lock().unwrap() = Some(result.map_err(|e| (e.kind(), e.to_string())));
+    })
+}
+
+#[test]
+fn client_calls_host_and_gets_a_reply() {
+    let mut cluster = LocalCluster::with_clients(71, &["alice"]).unwrap();
+    let spawned = Arc::new(Mutex::new(Vec::new()));
+    let log = spawned.clone();
+    cluster.host(|host| {
+        host.register_rpc("spawn", move |from, args| {
+            log.lock().unwrap().push((from, args.to_vec()));
+            Ok(vec![42])
+        })
+    }).unwrap();
+
+    let (reply, callback) = reply_slot();
+    cluster.client(0).rpc_with_reply("spawn", b"orc", callback).unwrap();
+    cluster.client(0).rpc("spawn", b"elf").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| reply.lock().unwrap().is_some()).unwrap());
+    assert_eq!(*reply.lock().unwrap(), Some(Ok(vec![42])));
+    assert!(cluster.run_until(TIMEOUT, |_| spawned.lock().unwrap().len() == 2).unwrap());
+    assert_eq!(*spawned.lock().unwrap(), vec![(2, b"orc".to_vec()), (2, b"elf".to_vec())]);
+}
+
+#[test]
+fn errors_and_unknown_methods_reach_the_caller() {
+    let mut cluster = LocalCluster::with_clients(72, &["alice"]).unwrap();
+    cluster.host(|host| host.register_rpc("buy", |_, _| Err("Not enough gold".to_string()))).unwrap();
+
+    let (refused, callback) = reply_slot();
+    cluster.client(0).rpc_with_reply("buy", b"sword", callback).unwrap();
+    let (unknown, callback) = reply_slot();
+    cluster.client(0).rpc_with_reply("sell", b"sword", callback).unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| unknown.lock().unwrap().is_some()).unwrap());
+    assert_eq!(*refused.lock().unwrap(), Some(Err((ErrorKind::Other, "Not enough gold".to_string()))));
+    assert!(matches!(*unknown.lock().unwrap(), Some(Err((ErrorKind::Unsupported, _)))));
+    assert_eq!(cluster.client(0).rpc("", b"").unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn host_calls_client_and_unanswered_calls_time_out() {
+    let mut cluster = LocalCluster::with_clients(73, &["alice"]).unwrap();
+    cluster.client(0).register_rpc("ready", |from, _| Ok(vec![from as u8])).unwrap();
+
+    let (answered, callback) = reply_slot();
+    cluster.host(|host| host.rpc_with_reply(2, "ready", b"", callback)).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |_| answered.lock().unwrap().is_some()).unwrap());
+    assert_eq!(*answered.lock().unwrap(), Some(Ok(vec![1])));
+
+    // The client isn't pumped, so nothing answers
+    let (ignored, callback) = reply_slot();
+    cluster.host(|host| {
+        host.set_rpc_timeout(Duration::from_millis(20));
+        host.rpc_with_reply(2, "ready", b"", callback)
+    }).unwrap();
+    let started = Instant::now();
+    while ignored.lock().unwrap().is_none() && started.elapsed() < TIMEOUT {
+        sleep(Duration::from_millis(5));
+    }
+    assert!(matches!(*ignored.lock().unwrap(), Some(Err((ErrorKind::TimedOut, _)))));
+}
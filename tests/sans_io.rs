@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use project_neon::client::{ClientEvent, ClientProtocol};
+use project_neon::host::{HostEvent, HostProtocol};
+use project_neon::protocol::{self, DisconnectNotice, DisconnectReason, NeonPacket, PacketPayload, PacketType};
+use project_neon::relay::RelayCore;
+use project_neon::transport::Outgoing;
+
+/// A relay, host and client passing datagrams by hand, with no sockets or threads
+struct Wire {
+    relay: RelayCore,
+    host: HostProtocol,
+    client: ClientProtocol,
+    in_flight: VecDeque<(Vec<u8>, SocketAddr, SocketAddr)>,
+}
+
+const RELAY: &str = "10.0.0.1:7777";
+const HOST: &str = "10.0.0.2:5000";
+const CLIENT: &str = "10.0.0.3:5000";
+
+fn addr(addr: &str) -> SocketAddr {
+    addr.parse().unwrap()
+}
+
+impl Wire {
+    fn send(&mut self, from: &str, outgoing: Outgoing) {
+        self.in_flight.extend(outgoing.into_iter().map(|(data, to)| (data, addr(from), to)));
+    }
+
+    /// Deliver everything in flight, and everything sent in answer, then poll each side
+    fn settle(&mut self) {
+        for _ in 0..10 {
+            while let Some((data, from, to)) = self.in_flight.pop_front() {
+                if to == addr(RELAY) {
+                    let outgoing = self.relay.handle_datagram(&data, from).unwrap();
+                    self.send(RELAY, outgoing);
+                } else if to == addr(HOST) {
+                    let outgoing = self.host.handle_datagram(&data, from).unwrap();
+                    self.send(HOST, outgoing);
+                } else if to == addr(CLIENT) {
+                    let outgoing = self.client.handle_datagram(&data, from).unwrap();
+                    self.send(CLIENT, outgoing);
+                }
+            }
+            let outgoing = self.host.poll().unwrap();
+            self.send(HOST, outgoing);
+            let outgoing = self.client.poll().unwrap();
+            self.send(CLIENT, outgoing);
+        }
+    }
+}
+
+/// A wire with a host registered for `session_id` and a client joined to it, with the client's ID
+fn connected(session_id: u32) -> (Wire, u16) {
+    let mut wire = Wire {
+        relay: RelayCore::new(),
+        host: HostProtocol::new(session_id, addr(RELAY)).unwrap(),
+        client: ClientProtocol::new("alice".to_string()).unwrap(),
+        in_flight: VecDeque::new(),
+    };
+
+    let registration = wire.host.register().unwrap();
+    wire.send(HOST, registration);
+    wire.settle();
+    assert_eq!(wire.relay.sessions().lookup_peer(addr(HOST), 1), Some(session_id.into()));
+
+    let request = wire.client.connect(session_id, addr(RELAY)).unwrap();
+    wire.send(CLIENT, request);
+    wire.settle();
+
+    let Some(ClientEvent::Connected { client_id, session_id: joined }) = wire.client.poll_event() else {
+        panic!("client never connected");
+    };
+    assert_eq!(joined, session_id);
+    assert_eq!(wire.host.poll_event(), Some(HostEvent::ClientConnected { client_id, name: "alice".to_string() }));
+    (wire, client_id)
+}
+
+#[test]
+fn host_and_client_run_without_sockets() {
+    let (mut wire, client_id) = connected(60);
+
+    wire.client.client().send_game_packet(0x10, b"hi").unwrap();
+    wire.host.host().send_game_packet(client_id, 0x11, b"hello").unwrap();
+    wire.settle();
+
+    assert_eq!(wire.host.poll_event(), Some(HostEvent::GamePacket { packet_type: 0x10, from: client_id, data: b"hi".to_vec() }));
+    let mut packets = Vec::new();
+    while let Some(event) = wire.client.poll_event() {
+        if let ClientEvent::GamePacket { packet_type, from, data } = event {
+            packets.push((packet_type, from, data));
+        }
+    }
+    assert_eq!(packets, [(0x11, 1, b"hello".to_vec())]);
+}
+
+fn batch(client_id: u16, packets: Vec<NeonPacket>) -> Vec<u8> {
+    protocol::encode(&NeonPacket { packet_type: PacketType::Batch as u8, sequence: 100, client_id, destination_id: 1, payload: PacketPayload::Batch(packets) })
+}
+
+fn game_packet(client_id: u16, data: &[u8]) -> NeonPacket {
+    NeonPacket { packet_type: 0x10, sequence: 101, client_id, destination_id: 1, payload: PacketPayload::GamePacket(data.to_vec()) }
+}
+
+#[test]
+fn relay_drops_batches_carrying_another_senders_packets() {
+    let (mut wire, client_id) = connected(61);
+
+    // A relay notice that the client left, and a packet from another client, each in a batch
+    // that is honestly the client's own
+    let from_relay = NeonPacket {
+        packet_type: PacketType::DisconnectNotice as u8,
+        sequence: 102,
+        client_id: 0,
+        destination_id: 1,
+        payload: PacketPayload::DisconnectNotice(DisconnectNotice { client_id, reason: DisconnectReason::TimedOut }),
+    };
+    for forged in [from_relay, game_packet(client_id + 1, b"forged")] {
+        let outgoing = wire.relay.handle_datagram(&batch(client_id, vec![game_packet(client_id, b"cover"), forged]), addr(CLIENT)).unwrap();
+        assert!(outgoing.is_empty());
+    }
+    assert_eq!(wire.relay.malformed_packet_count(), 2);
+
+    let honest = batch(client_id, vec![game_packet(client_id, b"one"), game_packet(client_id, b"two")]);
+    let outgoing = wire.relay.handle_datagram(&honest, addr(CLIENT)).unwrap();
+    assert_eq!(outgoing, [(honest, addr(HOST))]);
+}
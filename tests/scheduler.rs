@@ -0,0 +1,76 @@
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+use project_neon::scheduler::{OutgoingScheduler, Priority};
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn higher_priorities_drain_first() {
+    let mut scheduler = OutgoingScheduler::new();
+    scheduler.push("bulk", Priority::Low);
+    scheduler.push("move", Priority::Normal);
+    scheduler.push("voice", Priority::High);
+    scheduler.push("shoot", Priority::Normal);
+
+    let mut sent = Vec::new();
+    assert_eq!(scheduler.drain(|packet| { sent.push(*packet); Ok(()) }).unwrap(), 4);
+    assert_eq!(sent, vec!["voice", "move", "shoot", "bulk"]);
+    assert!(scheduler.is_empty());
+}
+
+#[test]
+fn blocked_sends_stay_queued() {
+    let mut scheduler = OutgoingScheduler::new();
+    scheduler.push(1, Priority::Normal);
+    scheduler.push(2, Priority::Normal);
+
+    let mut budget = 1;
+    let sent = scheduler.drain(|_| {
+        if budget == 0 {
+            return Err(Error::new(ErrorKind::WouldBlock, "socket full"));
+        }
+        budget -= 1;
+        Ok(())
+    }).unwrap();
+    assert_eq!(sent, 1);
+    assert_eq!(scheduler.len(), 1);
+
+    scheduler.push(3, Priority::High);
+    let mut sent = Vec::new();
+    scheduler.drain(|packet| { sent.push(*packet); Ok(()) }).unwrap();
+    assert_eq!(sent, vec![3, 2]);
+}
+
+#[test]
+fn stale_low_priority_packets_are_dropped() {
+    let mut scheduler = OutgoingScheduler::new();
+    scheduler.set_stale_age(Some(Duration::from_millis(10)));
+    scheduler.push("old bulk", Priority::Low);
+    scheduler.push("old move", Priority::Normal);
+    sleep(Duration::from_millis(20));
+    scheduler.push("fresh bulk", Priority::Low);
+
+    let mut sent = Vec::new();
+    scheduler.drain(|packet| { sent.push(*packet); Ok(()) }).unwrap();
+    assert_eq!(sent, vec!["old move", "fresh bulk"]);
+    assert_eq!(scheduler.dropped_packets(), 1);
+}
+
+#[test]
+fn prioritized_game_packets_are_delivered() {
+    let mut cluster = LocalCluster::with_clients(91, &["alice"]).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.host(|host| host.on_game_packet(move |packet_type, _, data| sink.lock().unwrap().push((packet_type, data.to_vec()))));
+
+    cluster.client(0).send_game_packet_with_priority(1, 0x20, b"map chunk", Priority::Low).unwrap();
+    cluster.client(0).send_game_packet_with_priority(1, 0x21, b"input", Priority::High).unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| received.lock().unwrap().len() == 2).unwrap());
+    assert_eq!(cluster.client(0).queued_packets(), 0);
+    assert_eq!(cluster.client(0).dropped_packets(), 0);
+}
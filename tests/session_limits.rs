@@ -0,0 +1,132 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use project_neon::NeonHost;
+use project_neon::protocol::DisconnectReason;
+use project_neon::relay::{NeonRelay, SessionLimits};
+use project_neon::testing::{LocalCluster, MemoryTransport};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn cluster_with_limits(session_id: u32, limits: SessionLimits) -> LocalCluster {
+    LocalCluster::with_relay(session_id, |relay: &mut NeonRelay<MemoryTransport>| relay.set_session_limits(limits)).unwrap()
+}
+
+/// Record every on_session_closed from the cluster's host and its first client
+fn record_closes(cluster: &mut LocalCluster) -> Arc<Mutex<Vec<(&'static str, DisconnectReason)>>> {
+    let closes = Arc::new(Mutex::new(Vec::new()));
+    let seen = closes.clone();
+    cluster.host(|host| host.on_session_closed(move |reason| seen.lock().unwrap().push(("host", reason))));
+    let seen = closes.clone();
+    cluster.client(0).on_session_closed(move |reason| seen.lock().unwrap().push(("client", reason)));
+    closes
+}
+
+#[test]
+fn sessions_past_the_relay_cap_are_refused() {
+    let cluster = cluster_with_limits(171, SessionLimits { max_sessions: Some(1), ..SessionLimits::default() });
+
+    let transport = cluster.network().bind("127.0.0.1:0").unwrap();
+    let mut second = NeonHost::with_transport(172, cluster.relay_addr(), transport).unwrap();
+    let error = second.register().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(error.to_string(), "Relay is full");
+
+    // The session that's already there can still register again
+    cluster.host(|host| host.register()).unwrap();
+}
+
+#[test]
+fn joins_past_the_client_cap_are_refused() {
+    let mut cluster = cluster_with_limits(173, SessionLimits { max_clients_per_session: Some(1), ..SessionLimits::default() });
+    cluster.add_client("alice").unwrap();
+
+    let error = cluster.add_client("bob").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(error.to_string(), "Session is full");
+    assert_eq!(cluster.host(|host| host.client_count()), 1);
+}
+
+#[test]
+fn sessions_are_closed_when_they_expire() {
+    let limits = SessionLimits { max_lifetime: Some(Duration::from_millis(300)), ..SessionLimits::default() };
+    let mut cluster = cluster_with_limits(174, limits);
+    cluster.add_client("alice").unwrap();
+    let closes = record_closes(&mut cluster);
+
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).client_id().is_none()).unwrap());
+    let deadline = Instant::now() + TIMEOUT;
+    while closes.lock().unwrap().len() < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut closes = closes.lock().unwrap().clone();
+    closes.sort_by_key(|&(side, _)| side);
+    assert_eq!(closes, vec![("client", DisconnectReason::Expired), ("host", DisconnectReason::Expired)]);
+    assert_eq!(cluster.host(|host| host.client_count()), 0);
+}
+
+#[test]
+fn game_traffic_keeps_a_session_from_going_idle() {
+    let limits = SessionLimits { idle_timeout: Some(Duration::from_millis(300)), ..SessionLimits::default() };
+    let mut cluster = cluster_with_limits(175, limits);
+    cluster.add_client("alice").unwrap();
+    let closes = record_closes(&mut cluster);
+
+    let busy_until = Instant::now() + Duration::from_millis(600);
+    while Instant::now() < busy_until {
+        cluster.client(0).send_game_packet(0x10, b"still here").unwrap();
+        assert!(!cluster.run_until(Duration::from_millis(50), |_| false).unwrap());
+    }
+    assert!(closes.lock().unwrap().is_empty());
+
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).client_id().is_none()).unwrap());
+    assert!(closes.lock().unwrap().contains(&("client", DisconnectReason::Idle)));
+}
+
+/// Cluster whose relay drops peers after 300ms of silence, with one client that keeps talking but never pings
+fn cluster_with_short_timeout(session_id: u32, keepalive: Option<Duration>) -> LocalCluster {
+    let mut cluster = LocalCluster::with_relay(session_id, |relay: &mut NeonRelay<MemoryTransport>| {
+        relay.set_peer_timeout(Duration::from_millis(300))
+    })
+    .unwrap();
+    cluster.host(|host| host.set_relay_keepalive(keepalive));
+    cluster.add_client("alice").unwrap();
+    cluster.client(0).set_auto_ping(false);
+    cluster
+}
+
+#[test]
+fn silent_hosts_take_their_session_with_them() {
+    let mut cluster = cluster_with_short_timeout(176, None);
+    let closes = record_closes(&mut cluster);
+
+    let closed = cluster
+        .run_until(TIMEOUT, |c| {
+            let client = c.client(0);
+            client.client_id().is_none() || client.send_game_packet(0x10, b"anyone there?").is_err()
+        })
+        .unwrap();
+    assert!(closed);
+    let deadline = Instant::now() + TIMEOUT;
+    while closes.lock().unwrap().len() < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut closes = closes.lock().unwrap().clone();
+    closes.sort_by_key(|&(side, _)| side);
+    assert_eq!(closes, vec![("client", DisconnectReason::TimedOut), ("host", DisconnectReason::TimedOut)]);
+}
+
+#[test]
+fn checking_in_keeps_a_quiet_host_registered() {
+    let mut cluster = cluster_with_short_timeout(177, Some(Duration::from_millis(50)));
+    let closes = record_closes(&mut cluster);
+
+    let until = Instant::now() + Duration::from_millis(900);
+    while Instant::now() < until {
+        cluster.client(0).send_game_packet(0x10, b"still here").unwrap();
+        assert!(!cluster.run_until(Duration::from_millis(50), |_| false).unwrap());
+    }
+    assert!(closes.lock().unwrap().is_empty());
+    assert_eq!(cluster.host(|host| host.client_count()), 1);
+}
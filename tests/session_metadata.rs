@@ -0,0 +1,80 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+
+use project_neon::host::NeonHost;
+use project_neon::protocol::{self, ConnectAccept, NeonPacket, PacketPayload, PacketType, SessionMetadata};
+use project_neon::relay::RelayCore;
+use project_neon::transport::MemoryNetwork;
+
+fn registration(session_id: u32, cookie: u64, metadata: SessionMetadata) -> Vec<u8> {
+    let packet = NeonPacket {
+        packet_type: PacketType::ConnectAccept as u8,
+        sequence: 1,
+        client_id: 1,
+        destination_id: 1,
+        payload: PacketPayload::ConnectAccept(ConnectAccept {
+            assigned_client_id: 1,
+            session_id,
+            cookie,
+            nonce: 0,
+            resume_token: 0,
+            game_identifier: 0,
+            join_code: String::new(),
+            metadata,
+            features: Default::default(),
+        }),
+    };
+    protocol::encode(&packet)
+}
+
+/// Register a host for `session_id` with `relay`, answering its challenge
+fn register(relay: &mut RelayCore, host: SocketAddr, session_id: u32, metadata: SessionMetadata) {
+    let outgoing = relay.handle_datagram(&registration(session_id, 0, metadata.clone()), host).unwrap();
+    let PacketPayload::Challenge(challenge) = protocol::decode(&outgoing[0].0).unwrap().payload else {
+        panic!("expected Challenge");
+    };
+    relay.handle_datagram(&registration(session_id, challenge.cookie, metadata), host).unwrap();
+}
+
+fn lobby(name: &str, map: &str) -> SessionMetadata {
+    SessionMetadata { name: name.to_string(), max_players: 8, properties: vec![("map".to_string(), map.to_string())] }
+}
+
+#[test]
+fn relays_keep_what_hosts_advertise() {
+    let mut relay = RelayCore::new();
+    let host: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+
+    register(&mut relay, host, 70, lobby("Crab Cove", "dust"));
+    let metadata = relay.sessions().metadata(70.into()).unwrap();
+    assert_eq!(metadata.name, "Crab Cove");
+    assert_eq!(metadata.max_players, 8);
+    assert_eq!(metadata.property("map"), Some("dust"));
+    assert_eq!(metadata.property("mode"), None);
+
+    // Registering again replaces it
+    register(&mut relay, host, 70, lobby("Crab Cove", "harbour"));
+    assert_eq!(relay.sessions().metadata(70.into()).unwrap().property("map"), Some("harbour"));
+
+    register(&mut relay, "10.0.0.2:5000".parse().unwrap(), 71, SessionMetadata::default());
+    assert!(relay.sessions().metadata(71.into()).unwrap().is_empty());
+    assert!(relay.sessions().metadata(72.into()).is_none());
+}
+
+#[test]
+fn hosts_only_advertise_metadata_that_fits() {
+    let network = MemoryNetwork::new();
+    let mut host = NeonHost::with_transport(73, "127.0.0.1:7777".parse().unwrap(), network.bind("127.0.0.1:0").unwrap()).unwrap();
+
+    host.set_session_metadata(lobby("Crab Cove", "dust")).unwrap();
+    assert_eq!(host.session_metadata().name, "Crab Cove");
+
+    let long_name = SessionMetadata { name: "x".repeat(256), ..SessionMetadata::default() };
+    assert_eq!(host.set_session_metadata(long_name).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    let properties = (0..20).map(|i| (format!("key{}", i), "v".repeat(60))).collect();
+    let too_large = SessionMetadata { properties, ..SessionMetadata::default() };
+    assert_eq!(host.set_session_metadata(too_large).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    assert_eq!(host.session_metadata().name, "Crab Cove");
+}
@@ -0,0 +1,161 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use project_neon::testing::{
+    Latency, MemoryNetwork, MemoryTransport, NetworkConditions, SimulatedTransport, Transport,
+};
+
+fn link(conditions: NetworkConditions) -> (MemoryTransport, SimulatedTransport<MemoryTransport>, SocketAddr) {
+    let network = MemoryNetwork::new();
+    let sender = network.bind("127.0.0.1:0").unwrap();
+    let receiver = network.bind("127.0.0.1:0").unwrap();
+    let addr = receiver.local_addr().unwrap();
+    let receiver = SimulatedTransport::new(receiver, conditions).unwrap();
+    receiver.set_nonblocking(true).unwrap();
+    (sender, receiver, addr)
+}
+
+fn drain(receiver: &SimulatedTransport<MemoryTransport>, wait: Duration) -> Vec<u8> {
+    let mut received = Vec::new();
+    let mut buf = [0u8; 16];
+    while receiver.wait_readable(wait).unwrap() {
+        let (size, _) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(size, 1);
+        received.push(buf[0]);
+    }
+    received
+}
+
+#[test]
+fn latency_delays_delivery() {
+    let (sender, receiver, addr) = link(NetworkConditions {
+        latency: Latency::Fixed(Duration::from_millis(50)),
+        ..NetworkConditions::default()
+    });
+
+    let sent_at = Instant::now();
+    sender.send_to(&[1], addr).unwrap();
+
+    let err = receiver.recv_from(&mut [0u8; 16]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    assert!(receiver.wait_readable(Duration::from_secs(1)).unwrap());
+    assert!(sent_at.elapsed() >= Duration::from_millis(50));
+    assert_eq!(drain(&receiver, Duration::ZERO), vec![1]);
+}
+
+#[test]
+fn full_loss_drops_everything() {
+    let (sender, receiver, addr) = link(NetworkConditions {
+        loss_percent: 100.0,
+        ..NetworkConditions::default()
+    });
+
+    for i in 0..10 {
+        sender.send_to(&[i], addr).unwrap();
+    }
+
+    assert!(drain(&receiver, Duration::from_millis(20)).is_empty());
+    assert_eq!(receiver.stats().dropped, 10);
+}
+
+#[test]
+fn duplication_delivers_twice() {
+    let (sender, receiver, addr) = link(NetworkConditions {
+        duplicate_percent: 100.0,
+        ..NetworkConditions::default()
+    });
+
+    sender.send_to(&[7], addr).unwrap();
+
+    assert_eq!(drain(&receiver, Duration::from_millis(20)), vec![7, 7]);
+    assert_eq!(receiver.stats().duplicated, 1);
+}
+
+#[test]
+fn reordered_packet_is_overtaken() {
+    let (sender, receiver, addr) = link(NetworkConditions {
+        reorder_percent: 100.0,
+        reorder_delay: Duration::from_millis(30),
+        ..NetworkConditions::default()
+    });
+
+    sender.send_to(&[1], addr).unwrap();
+    // Pull the first datagram into the simulator while reordering is on
+    assert!(!receiver.wait_readable(Duration::ZERO).unwrap());
+    receiver.set_conditions(NetworkConditions::default());
+    sender.send_to(&[2], addr).unwrap();
+
+    assert_eq!(drain(&receiver, Duration::from_millis(100)), vec![2, 1]);
+}
+
+#[test]
+fn same_seed_gives_same_outcome() {
+    let conditions = NetworkConditions {
+        loss_percent: 30.0,
+        duplicate_percent: 10.0,
+        seed: Some(7),
+        ..NetworkConditions::default()
+    };
+
+    let runs: Vec<Vec<u8>> = (0..2)
+        .map(|_| {
+            let (sender, receiver, addr) = link(conditions.clone());
+            for i in 0..100 {
+                sender.send_to(&[i], addr).unwrap();
+            }
+            drain(&receiver, Duration::from_millis(20))
+        })
+        .collect();
+
+    assert_eq!(runs[0], runs[1]);
+    assert!(runs[0].len() < 100);
+}
+
+#[test]
+fn client_works_over_simulated_link() {
+    use project_neon::relay::RelayConfig;
+    use project_neon::{NeonClient, NeonHost, NeonRelay};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    let network = MemoryNetwork::new();
+    let relay_transport = network.bind("127.0.0.1:7777").unwrap();
+    let relay_addr = relay_transport.local_addr().unwrap();
+    let mut relay = NeonRelay::with_transport(relay_transport, RelayConfig { worker_threads: 1, ..RelayConfig::default() }).unwrap();
+    thread::spawn(move || relay.start());
+
+    let mut host = NeonHost::with_transport(9, relay_addr, network.bind("127.0.0.1:0").unwrap()).unwrap();
+    host.register().unwrap();
+    thread::spawn(move || -> std::io::Result<()> {
+        loop {
+            host.process_packets()?;
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    let slow_link = SimulatedTransport::new(
+        network.bind("127.0.0.1:0").unwrap(),
+        NetworkConditions {
+            latency: Latency::Fixed(Duration::from_millis(30)),
+            ..NetworkConditions::default()
+        },
+    )
+    .unwrap();
+    let mut client = NeonClient::with_transport("alice".to_string(), slow_link).unwrap();
+    let rtt = Arc::new(Mutex::new(None));
+    {
+        let rtt = rtt.clone();
+        client.on_pong(move |response_time, _| *rtt.lock().unwrap() = Some(response_time));
+    }
+
+    client.connect(9, relay_addr).unwrap();
+    client.send_ping().unwrap();
+    let started = Instant::now();
+    while rtt.lock().unwrap().is_none() && started.elapsed() < Duration::from_secs(5) {
+        client.process_packets().unwrap();
+        network.wait_for_delivery(network.delivered(), Duration::from_millis(10));
+    }
+
+    assert!(rtt.lock().unwrap().is_some_and(|rtt| rtt >= 30));
+}
@@ -0,0 +1,109 @@
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use project_neon::NeonClient;
+use project_neon::host::NeonHost;
+use project_neon::relay::{NeonRelay, RelayConfig};
+use project_neon::socket_options::{DSCP_EXPEDITED_FORWARDING, LocalPort, SocketOptions};
+use socket2::SockRef;
+
+#[test]
+fn options_are_applied_to_the_bound_socket() {
+    let options = SocketOptions {
+        recv_buffer_size: Some(64 * 1024),
+        send_buffer_size: Some(32 * 1024),
+        ttl: Some(32),
+        dscp: Some(DSCP_EXPEDITED_FORWARDING),
+        ..SocketOptions::default()
+    };
+    let socket = options.bind("127.0.0.1:0").unwrap();
+    let tuned = SockRef::from(&socket);
+    // Some systems round buffer sizes up (Linux doubles them for bookkeeping)
+    assert!(tuned.recv_buffer_size().unwrap() >= 64 * 1024);
+    assert!(tuned.send_buffer_size().unwrap() >= 32 * 1024);
+    assert_eq!(tuned.ttl_v4().unwrap(), 32);
+    assert_eq!(tuned.tos_v4().unwrap(), u32::from(DSCP_EXPEDITED_FORWARDING) << 2);
+}
+
+#[test]
+fn defaults_leave_the_socket_alone() {
+    let socket = SocketOptions::default().bind("127.0.0.1:0").unwrap();
+    let plain = UdpSocket::bind("127.0.0.1:0").unwrap();
+    assert_eq!(SockRef::from(&socket).ttl_v4().unwrap(), SockRef::from(&plain).ttl_v4().unwrap());
+    assert_eq!(SockRef::from(&socket).tos_v4().unwrap(), 0);
+}
+
+#[test]
+fn out_of_range_dscp_is_rejected() {
+    let options = SocketOptions { dscp: Some(64), ..SocketOptions::default() };
+    assert_eq!(options.bind("127.0.0.1:0").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(NeonClient::with_socket_options("alice".to_string(), options).err().unwrap().kind(), ErrorKind::InvalidInput);
+    let config = RelayConfig { socket: options, ..RelayConfig::default() };
+    assert_eq!(NeonRelay::with_config("127.0.0.1:0", config).err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn reuse_address_lets_two_sockets_share_a_port() {
+    let options = SocketOptions { reuse_address: true, ..SocketOptions::default() };
+    let first = options.bind("127.0.0.1:0").unwrap();
+    let addr = first.local_addr().unwrap();
+    assert!(options.bind(addr).is_ok());
+    assert_eq!(SocketOptions::default().bind(addr).unwrap_err().kind(), ErrorKind::AddrInUse);
+}
+
+/// A port nothing on 127.0.0.1 is bound to right now
+fn free_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+#[test]
+fn fixed_ports_and_ranges_pick_the_local_port() {
+    let remote: SocketAddr = "127.0.0.1:7777".parse().unwrap();
+    let port = free_port();
+    let options = SocketOptions { local_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)), local_port: LocalPort::Fixed(port), ..SocketOptions::default() };
+    let fixed = options.bind_local(&remote).unwrap();
+    assert_eq!(fixed.local_addr().unwrap(), SocketAddr::from(([127, 0, 0, 1], port)));
+    assert_eq!(options.bind_local(&remote).unwrap_err().kind(), ErrorKind::AddrInUse);
+
+    // A taken port in the range is passed over
+    let options = SocketOptions { local_port: LocalPort::Range(port, port.saturating_add(32)), ..options };
+    let ranged = options.bind_local(&remote).unwrap().local_addr().unwrap();
+    assert!(ranged.port() > port && ranged.port() <= port.saturating_add(32), "{}", ranged);
+
+    let options = SocketOptions { local_port: LocalPort::Range(port, port), ..options };
+    assert_eq!(options.bind_local(&remote).unwrap_err().kind(), ErrorKind::AddrInUse);
+    let options = SocketOptions { local_port: LocalPort::Range(port, port - 1), ..options };
+    assert_eq!(options.bind_local(&remote).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn local_addresses_must_match_the_remote_family() {
+    let options = SocketOptions { local_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)), ..SocketOptions::default() };
+    assert_eq!(options.bind_local(&"[::1]:7777".parse().unwrap()).unwrap_err().kind(), ErrorKind::AddrNotAvailable);
+    assert_eq!(NeonHost::with_socket_options(1, "[::1]:7777", options).err().unwrap().kind(), ErrorKind::AddrNotAvailable);
+}
+
+#[test]
+fn clients_and_hosts_send_from_the_chosen_address() {
+    let relay = UdpSocket::bind("127.0.0.1:0").unwrap();
+    relay.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let relay_addr = relay.local_addr().unwrap();
+    let mut buf = [0; 2048];
+
+    let client_port = free_port();
+    let options = SocketOptions { local_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)), local_port: LocalPort::Fixed(client_port), ..SocketOptions::default() };
+    let mut client = NeonClient::with_socket_options("alice".to_string(), options).unwrap();
+    client.begin_connect(1, relay_addr).unwrap();
+    let (_, from) = relay.recv_from(&mut buf).unwrap();
+    assert_eq!(from, SocketAddr::from(([127, 0, 0, 1], client_port)));
+
+    // Registration blocks waiting for a challenge this relay never sends, so it runs on its own thread
+    let host_port = free_port();
+    let options = SocketOptions { local_port: LocalPort::Fixed(host_port), ..options };
+    let mut host = NeonHost::with_socket_options(2, relay_addr, options).unwrap();
+    thread::spawn(move || host.register());
+    let (_, from) = relay.recv_from(&mut buf).unwrap();
+    assert_eq!(from, SocketAddr::from(([127, 0, 0, 1], host_port)));
+}
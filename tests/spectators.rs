@@ -0,0 +1,60 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::protocol::PeerRole;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type PacketLog = Arc<Mutex<Vec<(u8, u16, Vec<u8>)>>>;
+
+fn record(log: &PacketLog) -> impl FnMut(u8, u16, &[u8]) + Send + 'static {
+    let sink = log.clone();
+    move |packet_type, from, data| sink.lock().unwrap().push((packet_type, from, data.to_vec()))
+}
+
+#[test]
+fn spectators_watch_but_cannot_send() {
+    let mut cluster = LocalCluster::new(90).unwrap();
+    let alice = cluster.add_client("alice").unwrap();
+    let watcher = cluster.add_spectator("watcher").unwrap();
+    assert_eq!(cluster.client(alice).role(), PeerRole::Player);
+    assert_eq!(cluster.client(watcher).role(), PeerRole::Spectator);
+
+    let host_log = PacketLog::default();
+    let alice_log = PacketLog::default();
+    let watcher_log = PacketLog::default();
+    cluster.host(|host| {
+        host.on_game_packet(record(&host_log));
+        assert_eq!(host.client_role(2), Some(PeerRole::Player));
+        assert_eq!(host.client_role(3), Some(PeerRole::Spectator));
+        assert_eq!(host.client_role(4), None);
+    });
+    cluster.client(alice).on_game_packet(record(&alice_log));
+    cluster.client(watcher).on_game_packet(record(&watcher_log));
+
+    // Everyone learns who is watching
+    assert!(cluster.run_until(TIMEOUT, |cluster| !cluster.client(alice).peers().is_empty()).unwrap());
+    let roles: Vec<_> = cluster.client(alice).peers().iter().map(|peer| (peer.name.clone(), peer.role)).collect();
+    assert_eq!(roles, [("watcher".to_string(), PeerRole::Spectator)]);
+    assert!(cluster.run_until(TIMEOUT, |cluster| !cluster.client(watcher).peers().is_empty()).unwrap());
+    assert_eq!(cluster.client(watcher).peers()[0].role, PeerRole::Player);
+
+    cluster.host(|host| host.broadcast_game_packet(0x20, b"state").unwrap());
+    cluster.client(watcher).send_game_packet(0x21, b"move").unwrap();
+    cluster.client(watcher).send_game_packet_to(2, 0x22, b"psst").unwrap();
+    cluster.client(alice).send_game_packet(0x21, b"alice moves").unwrap();
+    cluster.client(alice).send_game_packet_to(3, 0x22, b"hello watcher").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| {
+        !host_log.lock().unwrap().is_empty() && watcher_log.lock().unwrap().len() == 2
+    }).unwrap());
+    // Give anything that slipped through time to arrive
+    cluster.run_until(Duration::from_millis(100), |_| false).unwrap();
+
+    assert_eq!(*host_log.lock().unwrap(), vec![(0x21, 2, b"alice moves".to_vec())]);
+    assert_eq!(*alice_log.lock().unwrap(), vec![(0x20, 1, b"state".to_vec())]);
+    assert_eq!(*watcher_log.lock().unwrap(), vec![(0x20, 1, b"state".to_vec()), (0x22, 2, b"hello watcher".to_vec())]);
+
+    cluster.shutdown().unwrap();
+}
@@ -0,0 +1,108 @@
+use std::ffi::CString;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use project_neon::client::{ClientSender, NeonClient};
+use project_neon::ffi::{self, NeonErrorCode};
+use project_neon::host::{HostSender, NeonHost};
+use project_neon::testing::LocalCluster;
+use project_neon::transport::MemoryTransport;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn assert_send<T: Send>() {}
+fn assert_send_sync_clone<T: Send + Sync + Clone>() {}
+
+#[test]
+fn handles_can_cross_threads() {
+    assert_send::<NeonClient>();
+    assert_send::<NeonHost>();
+    assert_send::<NeonClient<MemoryTransport>>();
+    assert_send::<NeonHost<MemoryTransport>>();
+    assert_send_sync_clone::<ClientSender>();
+    assert_send_sync_clone::<HostSender>();
+}
+
+#[test]
+fn client_sends_from_other_threads_reach_the_host() {
+    let mut cluster = LocalCluster::with_clients(200, &["alice"]).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.host(|host| host.on_game_packet(move |_, from, data| sink.lock().unwrap().push((from, data[0]))));
+
+    let sender = cluster.client(0).sender();
+    let threads: Vec<_> = (0..4u8)
+        .map(|thread| {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    sender.send_game_packet(0x10, &[thread]).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // Queued sends go out when the client is next pumped
+    assert!(cluster.run_until(TIMEOUT, |_| received.lock().unwrap().len() == 40).unwrap());
+    let received = received.lock().unwrap();
+    for thread in 0..4u8 {
+        assert_eq!(received.iter().filter(|&&(from, byte)| from == 2 && byte == thread).count(), 10);
+    }
+}
+
+#[test]
+fn host_sends_from_other_threads_reach_the_client() {
+    let mut cluster = LocalCluster::with_clients(201, &["alice"]).unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    cluster.client(0).on_game_packet(move |packet_type, from, data| sink.lock().unwrap().push((packet_type, from, data.to_vec())));
+
+    let sender = cluster.host(|host| host.sender());
+    thread::spawn(move || sender.send_game_packet(2, 0x22, b"from a worker").unwrap()).join().unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| !received.lock().unwrap().is_empty()).unwrap());
+    assert_eq!(received.lock().unwrap()[0], (0x22, 1, b"from a worker".to_vec()));
+}
+
+#[test]
+fn senders_reject_bad_packets_and_dropped_owners() {
+    let client = NeonClient::new("alice".to_string()).unwrap();
+    let sender = client.sender();
+    assert_eq!(sender.send_game_packet(0x05, b"core").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(sender.send_chat(&"x".repeat(1000), 0).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    drop(client);
+    assert_eq!(sender.send_ping().unwrap_err().kind(), ErrorKind::NotConnected);
+}
+
+#[test]
+fn ffi_handles_survive_concurrent_calls() {
+    let name = CString::new("alice").unwrap();
+    let handle = ffi::neon_client_new(name.as_ptr());
+    assert!(!handle.is_null());
+    let addr = handle as usize;
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn(move || {
+                let handle = addr as *mut ffi::NeonClientHandle;
+                for _ in 0..100 {
+                    assert_eq!(ffi::neon_client_get_id(handle), 0);
+                    ffi::neon_client_set_auto_ping(handle, true);
+                    // Not connected yet, so every send fails rather than racing
+                    assert_eq!(ffi::neon_client_send_game_packet(handle, 0x10, 1, b"hi".as_ptr(), 2), NeonErrorCode::NotConnected);
+                    assert_eq!(ffi::neon_client_process_packets(handle), NeonErrorCode::NotConnected);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    ffi::neon_client_free(handle);
+}
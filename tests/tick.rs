@@ -0,0 +1,60 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use project_neon::testing::LocalCluster;
+use project_neon::tick::TickDriver;
+
+#[test]
+fn client_driver_follows_host_tick_rate() {
+    let mut cluster = LocalCluster::new(61).unwrap();
+    cluster.host(|host| host.set_tick_rate(100)).unwrap();
+    cluster.add_client("alice").unwrap();
+
+    let mut driver = TickDriver::new(20);
+    let mut ticks = Vec::new();
+    let started = Instant::now();
+    while ticks.len() < 30 {
+        driver.step(cluster.client(0), |_, tick| {
+            ticks.push(tick);
+            Ok(())
+        }).unwrap();
+        sleep(driver.time_until_next_tick());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    assert_eq!(cluster.client(0).tick_rate(), Some(100));
+    assert_eq!(driver.tick_rate(), 100);
+    assert_eq!(driver.interval(), Duration::from_millis(10));
+    assert_eq!(ticks, (0..30).collect::<Vec<u64>>());
+}
+
+#[test]
+fn driver_catches_up_then_drops_backlog() {
+    // The session runs at the driver's rate, so the client's SessionConfig doesn't reset it mid-test
+    let mut cluster = LocalCluster::new(62).unwrap();
+    cluster.host(|host| host.set_tick_rate(100)).unwrap();
+    cluster.add_client("alice").unwrap();
+    let mut driver = TickDriver::new(100);
+
+    assert_eq!(driver.step(cluster.client(0), |_, _| Ok(())).unwrap(), 1);
+    sleep(Duration::from_millis(25));
+    // At least two ticks came due; all run
+    let ran = driver.step(cluster.client(0), |_, _| Ok(())).unwrap();
+    assert!(ran >= 2, "ran {}", ran);
+
+    sleep(Duration::from_millis(200));
+    let before = driver.tick();
+    let skipped_before = driver.skipped_ticks();
+    assert_eq!(driver.step(cluster.client(0), |_, _| Ok(())).unwrap(), 5);
+    assert!(driver.skipped_ticks() > skipped_before);
+    // Tick numbers still track wall time after dropping the backlog
+    assert!(driver.tick() - before >= 20);
+    assert!(driver.time_until_next_tick() <= driver.interval());
+}
+
+#[test]
+fn host_rejects_zero_tick_rate() {
+    let cluster = LocalCluster::new(63).unwrap();
+    assert!(cluster.host(|host| host.set_tick_rate(0)).is_err());
+    assert_eq!(cluster.host(|host| host.tick_rate()), 60);
+}
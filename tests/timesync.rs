@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use project_neon::testing::LocalCluster;
+use project_neon::timesync::{ClockEstimator, elapsed_between, timestamp_delta};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn symmetric_exchange_gives_exact_offset() {
+    let mut estimator = ClockEstimator::new();
+    assert!(!estimator.is_synced());
+    assert_eq!(estimator.remote_time_at(0), None);
+
+    // Remote is 5s ahead, 10ms each way
+    estimator.add_sample(1_000_000, 6_010_000, 1_020_000);
+
+    assert_eq!(estimator.offset_at(1_020_000), Some(5_000_000));
+    assert_eq!(estimator.remote_time_at(2_000_000), Some(7_000_000));
+    assert_eq!(estimator.round_trip(), Some(20_000));
+}
+
+#[test]
+fn lowest_latency_sample_wins() {
+    let mut estimator = ClockEstimator::new();
+    // A queued response: arrives late, skewing its midpoint
+    estimator.add_sample(0, 5_010_000, 200_000);
+    estimator.add_sample(300_000, 5_310_000, 320_000);
+    estimator.add_sample(600_000, 5_700_000, 800_000);
+
+    assert_eq!(estimator.round_trip(), Some(20_000));
+    assert_eq!(estimator.offset_at(310_000), Some(5_000_000));
+}
+
+#[test]
+fn drift_is_fitted_and_clamped() {
+    let mut estimator = ClockEstimator::new();
+    // Remote runs 100ppm fast: gains 100us per second
+    for second in 0..6u64 {
+        let sent = second * 1_000_000;
+        let remote = 5_000_000 + sent + 10_000 + second * 100;
+        estimator.add_sample(sent, remote, sent + 20_000);
+    }
+    assert!((estimator.skew() - 0.0001).abs() < 1e-9);
+
+    let mut runaway = ClockEstimator::new();
+    runaway.add_sample(0, 0, 0);
+    runaway.add_sample(3_000_000, 6_000_000, 3_000_000);
+    assert_eq!(runaway.skew(), 0.0005);
+}
+
+#[test]
+fn timestamps_compare_across_wraps_and_steps_back() {
+    assert_eq!(timestamp_delta(100, 250), 150);
+    assert_eq!(timestamp_delta(250, 100), -150);
+    assert_eq!(timestamp_delta(u64::MAX - 9, 10), 20);
+    assert_eq!(elapsed_between(u64::MAX, 4), 5);
+    assert_eq!(elapsed_between(250, 100), 0);
+
+    // An answer that seems to arrive before its request is ignored
+    let mut estimator = ClockEstimator::new();
+    estimator.add_sample(2_000_000, 9_000_000, 1_000_000);
+    assert!(!estimator.is_synced());
+}
+
+#[test]
+fn client_tracks_host_clock() {
+    let mut cluster = LocalCluster::with_clients(51, &["alice"]).unwrap();
+    let match_start = Instant::now();
+    // Game time, starting ten seconds in
+    let host_clock = move || 10_000_000 + match_start.elapsed().as_micros() as u64;
+    cluster.host(|host| host.set_clock_source(host_clock));
+    assert_eq!(cluster.client(0).server_time(), None);
+
+    cluster.client(0).sync_time().unwrap();
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).server_time().is_some()).unwrap());
+
+    let estimate = cluster.client(0).server_time().unwrap() as i64;
+    let actual = host_clock() as i64;
+    assert!((estimate - actual).abs() < 50_000, "estimate {} vs actual {}", estimate, actual);
+    assert_eq!(cluster.host(|host| host.host_time()) / 1_000_000, 10);
+}
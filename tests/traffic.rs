@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use project_neon::NeonClient;
+use project_neon::host::NeonHost;
+use project_neon::relay::{NeonRelay, RelayHandle, SessionStats};
+use project_neon::testing::{LocalCluster, MemoryNetwork, MemoryTransport, Transport};
+
+mod common;
+use common::{drive, join};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn cluster_with_handle(session_id: u32) -> (LocalCluster, RelayHandle) {
+    let mut handle = None;
+    let cluster = LocalCluster::with_relay(session_id, |relay: &mut NeonRelay<MemoryTransport>| {
+        handle = Some(relay.handle());
+    })
+    .unwrap();
+    (cluster, handle.unwrap())
+}
+
+fn sent_by(stats: &SessionStats, client_id: u16) -> u64 {
+    stats.peers.iter().find(|peer| peer.client_id == client_id).map_or(0, |peer| peer.sent.packets)
+}
+
+#[test]
+fn sessions_count_what_each_peer_sent_and_received() {
+    let (mut cluster, handle) = cluster_with_handle(320);
+    cluster.add_client("alice").unwrap();
+    for _ in 0..3 {
+        cluster.client(0).send_game_packet(0x10, &[7; 100]).unwrap();
+    }
+
+    let mut stats = None;
+    let counted = cluster
+        .run_until(TIMEOUT, |_| {
+            stats = handle.session_stats(320);
+            stats.as_ref().is_some_and(|stats| sent_by(stats, 2) >= 3)
+        })
+        .unwrap();
+    assert!(counted);
+    let stats = stats.unwrap();
+
+    assert_eq!(stats.session_id, 320);
+    assert_eq!(stats.peers.iter().map(|peer| peer.client_id).collect::<Vec<_>>(), vec![1, 2]);
+    let alice = stats.peers[1];
+    assert!(alice.sent.bytes >= 300);
+    assert!(stats.peers[0].received.packets >= 3);
+
+    // Every forwarded packet has one sender and one recipient
+    let sent: u64 = stats.peers.iter().map(|peer| peer.sent.bytes).sum();
+    let received: u64 = stats.peers.iter().map(|peer| peer.received.bytes).sum();
+    assert_eq!(sent, stats.forwarded.bytes);
+    assert_eq!(received, stats.forwarded.bytes);
+
+    assert_eq!(handle.session_stats(999), None);
+}
+
+#[test]
+fn forwarding_reuses_datagram_buffers() {
+    let network = MemoryNetwork::new();
+    let transport = network.bind("127.0.0.1:7777").unwrap();
+    let relay_addr = transport.local_addr().unwrap();
+    let mut relay = NeonRelay::builder().worker_threads(1).build_with_transport(transport).unwrap();
+    let handle = relay.handle();
+    let relay_thread = thread::spawn(move || {
+        relay.start().unwrap();
+        relay
+    });
+
+    let mut host = NeonHost::builder(321, relay_addr).ping_interval(None).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    let received = Arc::new(AtomicUsize::new(0));
+    let counter = received.clone();
+    host.on_game_packet(move |_, _, _| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+    host.register().unwrap();
+    let mut client = NeonClient::builder("alice").auto_ping(false).build_with_transport(network.bind("127.0.0.1:0").unwrap()).unwrap();
+    client.begin_connect(321, relay_addr).unwrap();
+    join(&network, &mut host, &mut client);
+
+    // Far more packets than the pool keeps (1024), in bursts the relay has to queue
+    const BURSTS: usize = 40;
+    const BURST: usize = 100;
+    for burst in 1..=BURSTS {
+        for _ in 0..BURST {
+            client.send_game_packet(0x10, &[burst as u8; 64]).unwrap();
+        }
+        drive(&network, &mut host, &mut client, |_| received.load(Ordering::Relaxed) >= burst * BURST).unwrap();
+    }
+    assert_eq!(received.load(Ordering::Relaxed), BURSTS * BURST);
+
+    handle.stop();
+    let relay = relay_thread.join().unwrap();
+    assert!(relay.packets_relayed() >= (BURSTS * BURST) as u64);
+    assert!(relay.buffer_allocations() <= 1024, "{} buffers allocated", relay.buffer_allocations());
+}
+
+#[test]
+fn stats_are_only_available_while_the_relay_runs() {
+    let relay = NeonRelay::new("127.0.0.1:0").unwrap();
+    assert_eq!(relay.session_stats(1), None);
+}
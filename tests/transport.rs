@@ -0,0 +1,51 @@
+use std::net::UdpSocket;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use project_neon::testing::{MemoryNetwork, Transport};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const SHORT: Duration = Duration::from_millis(50);
+
+/// Check that `receiver` waits out the timeout when nothing is sent, and wakes as soon as `send`,
+/// run on another thread after a moment, delivers a datagram, leaving it to be read
+fn check_wait_readable(receiver: &impl Transport, send: impl FnOnce() + Send + 'static) {
+    let start = Instant::now();
+    assert!(!receiver.wait_readable(SHORT).unwrap());
+    assert!(start.elapsed() >= SHORT - Duration::from_millis(10), "returned after {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let sender = thread::spawn(move || {
+        thread::sleep(SHORT);
+        send();
+    });
+    assert!(receiver.wait_readable(TIMEOUT).unwrap());
+    assert!(start.elapsed() < TIMEOUT / 2, "returned after {:?}", start.elapsed());
+    sender.join().unwrap();
+
+    let mut buf = [0; 16];
+    let (len, _) = receiver.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"wake");
+    assert!(!receiver.wait_readable(Duration::ZERO).unwrap());
+}
+
+#[test]
+fn udp_sockets_wake_for_a_datagram_or_time_out() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver.set_nonblocking(true).unwrap();
+    let addr = receiver.local_addr().unwrap();
+    check_wait_readable(&receiver, move || {
+        UdpSocket::bind("127.0.0.1:0").unwrap().send_to(b"wake", addr).unwrap();
+    });
+}
+
+#[test]
+fn memory_transports_wake_for_a_datagram_or_time_out() {
+    let network = MemoryNetwork::new();
+    let receiver = network.bind("127.0.0.1:0").unwrap();
+    let addr = receiver.local_addr().unwrap();
+    let sender = network.bind("127.0.0.1:0").unwrap();
+    check_wait_readable(&receiver, move || {
+        sender.send_to(b"wake", addr).unwrap();
+    });
+}
@@ -0,0 +1,37 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+/// Names of the form `neon_*(` in `source`
+fn functions(source: &str) -> BTreeSet<String> {
+    source
+        .match_indices("neon_")
+        .filter_map(|(start, _)| {
+            let rest = &source[start..];
+            let end = rest.find(|c: char| !c.is_alphanumeric() && c != '_')?;
+            rest[end..].starts_with('(').then(|| rest[..end].to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn csharp_bindings_cover_the_c_api() {
+    let header = fs::read_to_string("include/neon.h").unwrap();
+    let native = fs::read_to_string("bindings/unity/Runtime/NeonNative.cs").unwrap();
+    let (exported, bound) = (functions(&header), functions(&native));
+    assert!(exported.contains("neon_client_new"));
+
+    let missing: Vec<_> = exported.difference(&bound).collect();
+    assert!(missing.is_empty(), "NeonNative.cs has no P/Invoke for {:?}", missing);
+    let stale: Vec<_> = bound.difference(&exported).collect();
+    assert!(stale.is_empty(), "NeonNative.cs declares functions neon.h doesn't have: {:?}", stale);
+}
+
+#[test]
+fn csharp_bindings_declare_every_callback_type() {
+    let header = fs::read_to_string("include/neon.h").unwrap();
+    let native = fs::read_to_string("bindings/unity/Runtime/NeonNative.cs").unwrap();
+    for line in header.lines().filter(|line| line.starts_with("typedef void (*")) {
+        let name = line["typedef void (*".len()..].split(')').next().unwrap();
+        assert!(native.contains(&format!("public delegate void {}(", name)), "NeonNative.cs has no delegate for {}", name);
+    }
+}
@@ -0,0 +1,60 @@
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use project_neon::testing::LocalCluster;
+use project_neon::voice::{FixedJitterBuffer, JitterBuffer, MAX_VOICE_FRAME_SIZE, VoiceFrame};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn frame(sequence: u32) -> VoiceFrame {
+    VoiceFrame { sequence, data: vec![sequence as u8] }
+}
+
+fn played(buffer: &mut FixedJitterBuffer) -> Option<u32> {
+    buffer.pop().map(|frame| frame.sequence)
+}
+
+#[test]
+fn jitter_buffer_reorders_and_conceals() {
+    let mut buffer = FixedJitterBuffer::new(3);
+    buffer.push(frame(1));
+    buffer.push(frame(0));
+    assert_eq!(played(&mut buffer), None, "still filling");
+
+    buffer.push(frame(3));
+    assert_eq!(played(&mut buffer), Some(0));
+    assert_eq!(played(&mut buffer), Some(1));
+    assert_eq!(played(&mut buffer), None, "frame 2 is missing");
+    buffer.push(frame(2));
+    assert_eq!(played(&mut buffer), Some(3), "frame 2 arrived too late");
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn voice_reaches_every_peer() {
+    let mut cluster = LocalCluster::with_clients(81, &["alice", "bob"]).unwrap();
+    assert!(cluster.run_until(TIMEOUT, |c| c.client(0).peers().len() == 1).unwrap());
+
+    let host_frames = Arc::new(Mutex::new(Vec::new()));
+    let sink = host_frames.clone();
+    cluster.host(|host| host.on_voice_frame(move |from, sequence, data| sink.lock().unwrap().push((from, sequence, data.to_vec()))));
+    let bob_received = Arc::new(Mutex::new(0));
+    let counter = bob_received.clone();
+    cluster.client(1).on_voice_frame(move |_, _, _| *counter.lock().unwrap() += 1);
+    cluster.client(1).set_jitter_buffer(|_| Box::new(FixedJitterBuffer::new(2)));
+
+    cluster.client(0).send_voice(b"hello").unwrap();
+    cluster.client(0).send_voice(b"world").unwrap();
+
+    assert!(cluster.run_until(TIMEOUT, |_| host_frames.lock().unwrap().len() == 2).unwrap());
+    assert_eq!(host_frames.lock().unwrap()[1], (2, 1, b"world".to_vec()));
+
+    assert!(cluster.run_until(TIMEOUT, |_| *bob_received.lock().unwrap() == 2).unwrap());
+    let bob = cluster.client(1);
+    let heard: Vec<_> = (0..3).map(|_| bob.next_voice_frame(2).map(|frame| frame.data)).collect();
+    assert_eq!(heard, vec![Some(b"hello".to_vec()), Some(b"world".to_vec()), None]);
+
+    let oversized = vec![0; MAX_VOICE_FRAME_SIZE + 1];
+    assert_eq!(cluster.client(0).send_voice(&oversized).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
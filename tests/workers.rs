@@ -0,0 +1,77 @@
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use project_neon::client::NeonClient;
+use project_neon::host::NeonHost;
+use project_neon::testing::LocalCluster;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn client_worker_fires_callbacks_without_a_loop() {
+    let cluster = LocalCluster::new(210).unwrap();
+    let (host_tx, host_rx) = mpsc::channel();
+    cluster.host(|host| host.on_game_packet(move |_, from, data| host_tx.send((from, data.to_vec())).unwrap()));
+
+    let mut client = NeonClient::with_transport("alice".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    let (client_tx, client_rx) = mpsc::channel();
+    client.on_game_packet(move |packet_type, from, data| client_tx.send((packet_type, from, data.to_vec())).unwrap());
+    client.connect(cluster.session_id(), cluster.relay_addr()).unwrap();
+    let client_id = client.client_id().unwrap();
+
+    let worker = client.spawn().unwrap();
+    assert!(worker.is_running());
+    worker.send_game_packet(0x10, b"up").unwrap();
+    assert_eq!(host_rx.recv_timeout(TIMEOUT).unwrap(), (client_id, b"up".to_vec()));
+
+    cluster.host(|host| host.send_game_packet(client_id, 0x11, b"down")).unwrap();
+    assert_eq!(client_rx.recv_timeout(TIMEOUT).unwrap(), (0x11, 1, b"down".to_vec()));
+
+    let client = worker.disconnect().unwrap();
+    assert_eq!(client.client_id(), Some(client_id));
+}
+
+#[test]
+fn client_worker_stops_when_not_connected() {
+    let cluster = LocalCluster::new(211).unwrap();
+    let client = NeonClient::with_transport("bob".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+
+    let worker = client.spawn().unwrap();
+    let err = worker.disconnect().err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+}
+
+#[test]
+fn host_worker_registers_and_serves_clients() {
+    let cluster = LocalCluster::new(212).unwrap();
+    let transport = cluster.network().bind("127.0.0.1:0").unwrap();
+    let mut host = NeonHost::with_transport(213, cluster.relay_addr(), transport).unwrap();
+    let (host_tx, host_rx) = mpsc::channel();
+    host.on_game_packet(move |packet_type, from, data| host_tx.send((packet_type, from, data.to_vec())).unwrap());
+    let worker = host.spawn().unwrap();
+
+    let mut client = NeonClient::with_transport("carol".to_string(), cluster.network().bind("127.0.0.1:0").unwrap()).unwrap();
+    let (client_tx, client_rx) = mpsc::channel();
+    client.on_game_packet(move |_, _, data| client_tx.send(data.to_vec()).unwrap());
+    client.connect(213, cluster.relay_addr()).unwrap();
+    let client_id = client.client_id().unwrap();
+
+    client.send_game_packet(0x12, b"hello").unwrap();
+    assert_eq!(host_rx.recv_timeout(TIMEOUT).unwrap(), (0x12, client_id, b"hello".to_vec()));
+
+    worker.send_game_packet(client_id, 0x13, b"welcome").unwrap();
+    let deadline = std::time::Instant::now() + TIMEOUT;
+    let reply = loop {
+        client.process_packets().unwrap();
+        if let Ok(data) = client_rx.try_recv() {
+            break data;
+        }
+        assert!(std::time::Instant::now() < deadline, "reply never arrived");
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    assert_eq!(reply, b"welcome");
+
+    let host = worker.shutdown().unwrap();
+    assert_eq!(host.client_count(), 1);
+}